@@ -0,0 +1,116 @@
+//! Present latency benchmark
+//!
+//! Runs the scripted workloads on each available backend and prints a JSON
+//! report. Run with: cargo bench --bench present -- [options]
+//!
+//!   --workload <name>    resize-storm, window-churn or video-blit (default: all)
+//!   --backend <name>     headless or cocoa (default: all available)
+//!   --frames <n>         frames per workload (default: 300)
+//!   --output <file>      also write the report to a file
+//!   --baseline <file>    compare with an earlier report
+//!   --threshold <pct>    fail if the median or p95 got slower by more than this
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use wayoa::bench::{self, BackendKind, BenchOptions, Comparison, Report, Workload};
+
+/// The JSON written by a run
+#[derive(Serialize, Deserialize)]
+struct Output {
+    reports: Vec<Report>,
+    #[serde(default, skip_deserializing)]
+    comparisons: Vec<Comparison>,
+}
+
+struct Args {
+    workloads: Vec<Workload>,
+    backends: Vec<BackendKind>,
+    options: BenchOptions,
+    output: Option<PathBuf>,
+    baseline: Option<PathBuf>,
+    threshold: Option<f64>,
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let mut args = Args {
+        workloads: Workload::ALL.to_vec(),
+        backends: BackendKind::ALL
+            .into_iter()
+            .filter(|backend| backend.is_available())
+            .collect(),
+        options: BenchOptions::default(),
+        output: None,
+        baseline: None,
+        threshold: None,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))
+        };
+        match arg.as_str() {
+            "--workload" => {
+                let name = value()?;
+                let workload = Workload::parse(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown workload: {}", name))?;
+                args.workloads = vec![workload];
+            }
+            "--backend" => {
+                let name = value()?;
+                let backend = BackendKind::parse(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown backend: {}", name))?;
+                args.backends = vec![backend];
+            }
+            "--frames" => args.options.frames = value()?.parse()?,
+            "--output" => args.output = Some(value()?.into()),
+            "--baseline" => args.baseline = Some(value()?.into()),
+            "--threshold" => args.threshold = Some(value()?.parse()?),
+            // Passed by cargo bench
+            "--bench" => {}
+            _ => anyhow::bail!("Unknown argument: {}", arg),
+        }
+    }
+    Ok(args)
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args = parse_args()?;
+
+    let mut reports = Vec::new();
+    for &backend in &args.backends {
+        for &workload in &args.workloads {
+            eprintln!("Running {} on {}...", workload, backend);
+            reports.push(bench::run(workload, backend, &args.options)?);
+        }
+    }
+
+    let comparisons = match &args.baseline {
+        Some(path) => {
+            let baseline: Output = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            bench::compare(&baseline.reports, &reports)
+        }
+        None => Vec::new(),
+    };
+    let regressed: Vec<String> = comparisons
+        .iter()
+        .filter(|comparison| args.threshold.is_some_and(|t| comparison.regressed(t)))
+        .map(|comparison| format!("{} on {}", comparison.workload, comparison.backend))
+        .collect();
+
+    let output = Output {
+        reports,
+        comparisons,
+    };
+    let json = serde_json::to_string_pretty(&output)?;
+    println!("{}", json);
+    if let Some(path) = &args.output {
+        std::fs::write(path, format!("{}\n", json))?;
+    }
+    if !regressed.is_empty() {
+        anyhow::bail!("Latency regressed: {}", regressed.join(", "));
+    }
+    Ok(())
+}
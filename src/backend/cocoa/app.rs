@@ -1,19 +1,44 @@
 //! NSApplication delegate and event loop integration
 
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ptr::NonNull;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use log::{debug, error, info};
+use block2::RcBlock;
+use log::{debug, error, info, warn};
 use objc2::rc::Retained;
-use objc2::runtime::ProtocolObject;
-use objc2::{define_class, msg_send, MainThreadOnly};
+use objc2::runtime::{AnyObject, ProtocolObject};
+use objc2::{define_class, msg_send, DefinedClass, MainThreadOnly};
 use objc2_app_kit::{
-    NSApplication, NSApplicationActivationPolicy, NSApplicationDelegate, NSMenu, NSMenuItem,
+    NSApplication, NSApplicationActivationPolicy, NSApplicationDelegate, NSCursor,
+    NSCursorFrameResizeDirections, NSCursorFrameResizePosition, NSEvent, NSEventMask,
+    NSEventSubtype, NSEventType, NSMenu, NSMenuItem, NSPasteboard, NSPasteboardTypeString,
+    NSRequestUserAttentionType, NSScreen,
 };
-use objc2_foundation::{MainThreadMarker, NSNotification, NSObject, NSObjectProtocol, NSString};
+use objc2_foundation::{
+    MainThreadMarker, NSArray, NSNotification, NSNumber, NSObject, NSObjectProtocol, NSString,
+    NSUserDefaults,
+};
+use wayland_server::DisplayHandle;
 
+use super::input::key_is_down;
+use super::{GesturePhase, InputTranslator, LauncherPanel};
+use crate::compositor::{SurfaceId, WindowId};
+use crate::config::Config;
+use crate::input::{Corner, CursorShape, TabletToolId};
+use crate::protocol::seat::{ButtonState, GestureKind};
 use crate::server::{ServerState, WaylandServer};
+use crate::session::{Journal, JournalEvent};
+use crate::watchdog::Watchdog;
+
+/// Longest the run loop sleeps between polls for events
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// NSActivityUserInitiatedAllowingIdleSystemSleep: no App Nap, but the
+/// system may still sleep when idle
+const NS_ACTIVITY_USER_INITIATED_ALLOWING_IDLE_SYSTEM_SLEEP: u64 = 0x00FF_FFFF & !(1 << 20);
 
 /// Wayoa application wrapper
 pub struct WayoaApp {
@@ -25,13 +50,30 @@ pub struct WayoaApp {
     server: RefCell<WaylandServer>,
     /// Server state
     state: Rc<RefCell<ServerState>>,
+    /// Application delegate (NSApplication only holds it weakly)
+    _delegate: Retained<WayoaAppDelegate>,
+    /// Launcher overlay
+    launcher_panel: RefCell<LauncherPanel>,
+    /// Windows badged as needing attention
+    attention: RefCell<Vec<WindowId>>,
+    /// Window server number of the key window, as last seen
+    key_window: RefCell<Option<isize>>,
+    /// NSProcessInfo activity keeping App Nap away, while held
+    activity: RefCell<Option<Retained<AnyObject>>>,
+    /// Monitor of pointer motion over other apps, for hot corners
+    hot_corner_monitor: Option<Retained<AnyObject>>,
+    /// Main loop watchdog, stopped when the app is dropped
+    _watchdog: Option<Watchdog>,
     /// Running flag
     running: RefCell<bool>,
 }
 
 impl WayoaApp {
     /// Create a new Wayoa application
-    pub fn new() -> anyhow::Result<Self> {
+    ///
+    /// With `replace`, a running wayoa instance is asked to quit and its
+    /// display name is taken over.
+    pub fn new(replace: bool) -> anyhow::Result<Self> {
         info!("Initializing Wayoa application");
 
         // Ensure we're on the main thread
@@ -44,36 +86,98 @@ impl WayoaApp {
         // Set activation policy to regular (shows in dock)
         app.setActivationPolicy(NSApplicationActivationPolicy::Regular);
 
-        // Create and set the app delegate
-        let delegate = WayoaAppDelegate::new(mtm);
-        let delegate_obj: &ProtocolObject<dyn NSApplicationDelegate> =
-            ProtocolObject::from_ref(&*delegate);
-        app.setDelegate(Some(delegate_obj));
-
         // Set up the menu bar
         Self::setup_menu_bar(mtm, &app);
 
+        // Load configuration
+        let config = Config::load()?;
+
         // Create Wayland server
-        let mut server = WaylandServer::new()?;
+        let mut server = WaylandServer::with_replace(replace)?;
 
         // Set WAYLAND_DISPLAY environment variable
         let socket_name = server.socket_name().to_string();
         std::env::set_var("WAYLAND_DISPLAY", &socket_name);
         info!("WAYLAND_DISPLAY={}", socket_name);
+        if let Some(path) = server.ipc_socket_path() {
+            std::env::set_var("WAYOA_SOCKET", path);
+        }
 
         // Register protocol globals
         server.register_globals();
 
         // Create server state
-        let mut state = ServerState::new();
+        let mut state = ServerState::with_config(config);
         state.set_main_thread_marker(mtm);
+        state.hot_corners.set_system_corners(system_hot_corners());
+        follow_system_sticky_keys(&mut state);
+        let input_config = state.config.input.clone();
+        state
+            .compositor
+            .seat
+            .clicks_mut()
+            .set_system_double_click_interval(
+                Duration::from_secs_f64(NSEvent::doubleClickInterval()),
+                &input_config,
+            );
 
-        // Create a default output
-        let _output_id = state.compositor.outputs.create_output(
+        // Create a default output, keeping popups clear of the menu bar and
+        // the Dock
+        let output_id = state.compositor.outputs.create_output(
             "default".to_string(),
             "Wayoa".to_string(),
             "Virtual Display".to_string(),
         );
+        if let (Some(screen), Some(output)) = (
+            NSScreen::mainScreen(mtm),
+            state.compositor.outputs.get_mut(output_id),
+        ) {
+            output.reserved = crate::protocol::output::screen_insets(&screen);
+            output.fractional_scale = screen.backingScaleFactor();
+            let (refresh, min_refresh) = crate::protocol::output::screen_refresh(&screen);
+            if let Some(mode) = output.current_mode.and_then(|i| output.modes.get_mut(i)) {
+                mode.refresh = refresh;
+            }
+            output.min_refresh = min_refresh;
+            if let Some(min_refresh) = min_refresh {
+                info!(
+                    "Screen refreshes adaptively between {} and {} Hz",
+                    min_refresh / 1000,
+                    refresh / 1000
+                );
+            }
+        }
+        state.add_virtual_outputs(&server.display_handle());
+        server.start_remote(&state.config.remote);
+        let xwayland_config = state.config.xwayland.clone();
+        server.start_xwayland(&xwayland_config, &mut state);
+
+        // Journal the session next to the saved session
+        if let Some(path) = Journal::default_path() {
+            if let Err(e) = state.journal.open(&path) {
+                warn!("Failed to open session journal: {}", e);
+            }
+        }
+        state.journal.record(JournalEvent::Started {
+            pid: std::process::id(),
+        });
+
+        // Re-launch the last session and autostart clients
+        state.session.spawner.set_socket_name(socket_name);
+        let session_config = state.config.session.clone();
+        state.session.startup(&session_config);
+
+        let watchdog = Watchdog::spawn(state.watchdog.clone(), &state.config.watchdog);
+
+        let state = Rc::new(RefCell::new(state));
+        let hot_corner_monitor = Self::monitor_hot_corners(mtm, &state);
+
+        // Create and set the app delegate
+        let delegate = WayoaAppDelegate::new(mtm, state.clone(), server.display_handle());
+        let delegate_obj: &ProtocolObject<dyn NSApplicationDelegate> =
+            ProtocolObject::from_ref(&*delegate);
+        app.setDelegate(Some(delegate_obj));
+        Self::register_services(&app);
 
         debug!("Wayoa application initialized");
 
@@ -81,11 +185,55 @@ impl WayoaApp {
             mtm,
             app,
             server: RefCell::new(server),
-            state: Rc::new(RefCell::new(state)),
+            state,
+            _delegate: delegate,
+            launcher_panel: RefCell::new(LauncherPanel::new(mtm)),
+            attention: RefCell::new(Vec::new()),
+            key_window: RefCell::new(None),
+            activity: RefCell::new(None),
+            hot_corner_monitor,
+            _watchdog: watchdog,
             running: RefCell::new(true),
         })
     }
 
+    /// Watch pointer motion over other apps for hot corners
+    ///
+    /// A global monitor only sees events sent to other apps; the run loop
+    /// feeds those of wayoa's own windows.
+    fn monitor_hot_corners(
+        mtm: MainThreadMarker,
+        state: &Rc<RefCell<ServerState>>,
+    ) -> Option<Retained<AnyObject>> {
+        let state = Rc::downgrade(state);
+        let handler = RcBlock::new(move |_event: NonNull<NSEvent>| {
+            // Handlers run from the run loop, outside of any borrow
+            if let Some(state) = state.upgrade() {
+                if let Ok(mut state) = state.try_borrow_mut() {
+                    update_hot_corners(&mut state, mtm);
+                }
+            }
+        });
+        let mask = NSEventMask::MouseMoved
+            | NSEventMask::LeftMouseDragged
+            | NSEventMask::RightMouseDragged
+            | NSEventMask::OtherMouseDragged;
+        NSEvent::addGlobalMonitorForEventsMatchingMask_handler(mask, &handler)
+    }
+
+    /// Offer the selection of Wayland clients to the Services menu
+    ///
+    /// Services only get text; nothing is accepted back.
+    fn register_services(app: &NSApplication) {
+        // SAFETY: NSPasteboardTypeString is an immutable AppKit constant
+        let send_types = NSArray::from_slice(&[unsafe { NSPasteboardTypeString }]);
+        let return_types = NSArray::<NSString>::new();
+        // SAFETY: both arguments are arrays of pasteboard types
+        let _: () = unsafe {
+            msg_send![app, registerServicesMenuSendTypes: &*send_types, returnTypes: &*return_types]
+        };
+    }
+
     /// Set up the application menu bar
     fn setup_menu_bar(mtm: MainThreadMarker, app: &NSApplication) {
         unsafe {
@@ -138,16 +286,79 @@ impl WayoaApp {
             );
 
             if let Some(event) = event {
-                self.app.sendEvent(&event);
+                self.forward_hot_corner_motion(&event);
+                self.forward_relative_motion(&event);
+                self.forward_pointer(&event);
+                self.forward_gesture(&event);
+                self.forward_touches(&event);
+                self.forward_tablet(&event);
+                let bound = self.offer_to_bindings(&event);
+                if bound || !self.forward_key(&event) {
+                    self.forward_modifiers(&event);
+                }
+                if !bound && !self.send_past_menu(&event) {
+                    self.app.sendEvent(&event);
+                }
             }
 
+            // Move keyboard focus to the window the user made key
+            self.sync_key_window();
+
             // Dispatch Wayland events
             if let Err(e) = self.dispatch_wayland() {
                 error!("Wayland dispatch error: {}", e);
             }
 
-            // Small sleep to avoid busy-waiting when idle
-            std::thread::sleep(Duration::from_millis(1));
+            // Present windows whose output reached its frame deadline
+            self.state.borrow_mut().compose_due(Instant::now());
+
+            // Keep a confined cursor in its window
+            self.confine_cursor();
+
+            // Trigger a hot corner the pointer rested in
+            self.update_hot_corner_delay();
+
+            // Badge the Dock icons of windows needing attention
+            self.update_attention_badges();
+
+            // Follow windows the user clicked to the front
+            self.sync_stacking();
+
+            // Tell clients the sizes the user resized their windows to
+            self.sync_window_sizes();
+
+            // Ask clients to close the windows the user closed
+            self.sync_close_requests();
+
+            // Let macOS nap while only photos are shown
+            self.update_app_nap();
+
+            // Tell output power clients when the displays sleep or wake
+            self.sync_display_power();
+
+            // Show or hide the launcher overlay
+            self.launcher_panel
+                .borrow_mut()
+                .update(&self.state.borrow().launcher);
+
+            // Quit when asked over IPC, e.g. by a replacing instance
+            if self.state.borrow().quit_requested {
+                info!("Quitting on request");
+                self.state.borrow_mut().save_session();
+                self.stop();
+            }
+
+            // Sleep until the next frame deadline, but keep polling events
+            let now = Instant::now();
+            let sleep = self
+                .state
+                .borrow()
+                .scheduler
+                .next_deadline()
+                .map(|deadline| deadline.saturating_duration_since(now))
+                .unwrap_or(MAX_POLL_INTERVAL)
+                .min(MAX_POLL_INTERVAL);
+            std::thread::sleep(sleep);
 
             // Check if we should stop
             if !*self.running.borrow() {
@@ -156,6 +367,424 @@ impl WayoaApp {
         }
     }
 
+    /// Show the number of windows needing attention on the Dock icon, and
+    /// mark the Dock tiles of minimized ones
+    fn update_attention_badges(&self) {
+        let state = self.state.borrow();
+        let urgent: Vec<_> = state
+            .compositor
+            .windows
+            .needing_attention()
+            .iter()
+            .map(|window| window.id)
+            .collect();
+        if *self.attention.borrow() == urgent {
+            return;
+        }
+
+        let badge = (!urgent.is_empty()).then(|| NSString::from_str(&urgent.len().to_string()));
+        self.app.dockTile().setBadgeLabel(badge.as_deref());
+        for (id, native) in state.backend.native_windows() {
+            native.set_badge(urgent.contains(&id).then_some("!"));
+        }
+        *self.attention.borrow_mut() = urgent;
+    }
+
+    /// Hold an activity against App Nap unless every visible window shows
+    /// photos
+    fn update_app_nap(&self) {
+        let allowed = self.state.borrow().allows_app_nap();
+        let mut activity = self.activity.borrow_mut();
+        if allowed == activity.is_none() {
+            return;
+        }
+        let process_info: Retained<AnyObject> =
+            unsafe { msg_send![objc2::class!(NSProcessInfo), processInfo] };
+        match activity.take() {
+            Some(held) => {
+                debug!("Allowing App Nap");
+                let _: () = unsafe { msg_send![&*process_info, endActivity: &*held] };
+            }
+            None => {
+                debug!("Holding off App Nap");
+                let reason = NSString::from_str("Wayland clients are showing live content");
+                let held: Retained<AnyObject> = unsafe {
+                    msg_send![
+                        &*process_info,
+                        beginActivityWithOptions: NS_ACTIVITY_USER_INITIATED_ALLOWING_IDLE_SYSTEM_SLEEP,
+                        reason: &*reason
+                    ]
+                };
+                *activity = Some(held);
+            }
+        }
+    }
+
+    /// Report the displays going to sleep or waking up to output power
+    /// clients
+    fn sync_display_power(&self) {
+        let powered = !crate::util::power::displays_asleep();
+        self.state.borrow_mut().set_displays_powered(powered);
+    }
+
+    /// Mirror the native window order into the window stacking order
+    fn sync_stacking(&self) {
+        let ordered = self.app.orderedWindows();
+        let mut state = self.state.borrow_mut();
+        let front_to_back: Vec<WindowId> = ordered
+            .iter()
+            .filter_map(|window| native_window_id(&state, window.windowNumber()))
+            .collect();
+        if state.compositor.windows.restack(&front_to_back) {
+            debug!("Windows restacked to {:?}", front_to_back);
+        }
+    }
+
+    /// Send the content size of each native window to its toplevel when
+    /// the user resized it
+    fn sync_window_sizes(&self) {
+        let mut state = self.state.borrow_mut();
+        let sizes: Vec<_> = state
+            .backend
+            .native_windows()
+            .map(|(id, native)| (id, native.content_size(), native.in_live_resize()))
+            .collect();
+        for (window_id, size, resizing) in sizes {
+            state.native_window_resized(window_id, size, resizing);
+        }
+    }
+
+    /// Send a close to the toplevel of each native window whose close
+    /// button was clicked
+    fn sync_close_requests(&self) {
+        let mut state = self.state.borrow_mut();
+        let requested: Vec<_> = state
+            .backend
+            .native_windows()
+            .filter(|(_, native)| native.take_close_request())
+            .map(|(id, _)| id)
+            .collect();
+        for window_id in requested {
+            state.request_window_close(window_id);
+        }
+    }
+
+    /// Give keyboard focus to the window the user made key, e.g. by
+    /// clicking it or cycling windows
+    fn sync_key_window(&self) {
+        let number = self.app.keyWindow().map(|window| window.windowNumber());
+        if self.key_window.replace(number) == number {
+            return;
+        }
+        let mut state = self.state.borrow_mut();
+        let Some(window_id) = number.and_then(|number| native_window_id(&state, number)) else {
+            return;
+        };
+        let focused = state.compositor.windows.focused().map(|window| window.id);
+        let layer = state
+            .compositor
+            .windows
+            .get(window_id)
+            .is_some_and(|window| window.layer_surface);
+        if focused != Some(window_id) && !layer {
+            state.activate_window(window_id);
+        }
+    }
+
+    /// Let the launcher, preview and zoom bindings handle a key press before
+    /// the key window does
+    ///
+    /// Returns true if one of them took the event.
+    fn offer_to_bindings(&self, event: &NSEvent) -> bool {
+        if event.r#type() != NSEventType::KeyDown {
+            return false;
+        }
+        let translator = InputTranslator::new();
+        let key = translator.translate_keycode(event.keyCode());
+        let (modifiers, ..) = translator.translate_modifiers(event.modifierFlags().0 as u64);
+        self.state.borrow_mut().binding_key(key, modifiers)
+    }
+
+    /// Send a key event straight to the key window while its surface
+    /// inhibits shortcuts, so menu key equivalents like Cmd+Q reach the
+    /// client instead
+    ///
+    /// Returns true if the event was sent.
+    fn send_past_menu(&self, event: &NSEvent) -> bool {
+        let key = matches!(event.r#type(), NSEventType::KeyDown | NSEventType::KeyUp);
+        if !key || !self.state.borrow().compositor.seat.shortcuts_inhibited() {
+            return false;
+        }
+        match self.app.keyWindow() {
+            Some(window) => {
+                window.sendEvent(event);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Send a key typed into one of the compositor's windows to the client
+    /// with keyboard focus, followed by the modifiers
+    ///
+    /// Modifier keys arrive as flag changes, with the key that changed.
+    /// Clients repeat keys themselves, so repeats are dropped. Returns false
+    /// if the event isn't a key for a Wayland window.
+    fn forward_key(&self, event: &NSEvent) -> bool {
+        let pressed = match event.r#type() {
+            NSEventType::FlagsChanged => key_is_down(event.keyCode()),
+            NSEventType::KeyDown => true,
+            NSEventType::KeyUp => false,
+            _ => return false,
+        };
+        let mut state = self.state.borrow_mut();
+        if native_window_id(&state, event.windowNumber()).is_none() {
+            return false;
+        }
+        let translator = InputTranslator::new();
+        let time = translator.event_time(event.timestamp());
+        let key = translator.translate_keycode(event.keyCode());
+        let reported = translator.reported_modifiers(event.modifierFlags().0 as u64);
+        // AppKit doesn't send the release of a key pressed with Command
+        // held, so a fresh press of a key that's still down releases it
+        let fresh = event.r#type() == NSEventType::KeyDown && !event.isARepeat();
+        let down = state
+            .compositor
+            .seat
+            .keyboard()
+            .pressed_keys()
+            .contains(&key);
+        if fresh && down {
+            state.keyboard_key(time, key, false);
+        }
+        state.keyboard_key(time, key, pressed);
+        state.keyboard_modifiers(reported);
+        true
+    }
+
+    /// Follow keys and modifier changes for sticky modifiers, and send the
+    /// modifiers to the focused client when they change
+    fn forward_modifiers(&self, event: &NSEvent) {
+        let pressed = match event.r#type() {
+            NSEventType::FlagsChanged => key_is_down(event.keyCode()),
+            NSEventType::KeyDown if !event.isARepeat() => true,
+            NSEventType::KeyUp => false,
+            _ => return,
+        };
+        let translator = InputTranslator::new();
+        let key = translator.translate_keycode(event.keyCode());
+        let reported = translator.reported_modifiers(event.modifierFlags().0 as u64);
+        self.state
+            .borrow_mut()
+            .keyboard_system_key(key, pressed, reported);
+    }
+
+    /// Send mouse movement to relative pointers
+    fn forward_relative_motion(&self, event: &NSEvent) {
+        let moved = matches!(
+            event.r#type(),
+            NSEventType::MouseMoved
+                | NSEventType::LeftMouseDragged
+                | NSEventType::RightMouseDragged
+                | NSEventType::OtherMouseDragged
+        );
+        if !moved {
+            return;
+        }
+        let motion = InputTranslator::new().relative_motion(
+            event.deltaX(),
+            event.deltaY(),
+            event.timestamp(),
+        );
+        self.state.borrow_mut().pointer_relative_motion(motion);
+    }
+
+    /// Send mouse movement, buttons and scrolling to the surface under the
+    /// cursor
+    ///
+    /// Scrolling goes to the surface with pointer focus. Stylus points that
+    /// come as mouse events are left to [`Self::forward_tablet`].
+    fn forward_pointer(&self, event: &NSEvent) {
+        let kind = event.r#type();
+        let button = match kind {
+            NSEventType::LeftMouseDown
+            | NSEventType::RightMouseDown
+            | NSEventType::OtherMouseDown => Some(ButtonState::Pressed),
+            NSEventType::LeftMouseUp | NSEventType::RightMouseUp | NSEventType::OtherMouseUp => {
+                Some(ButtonState::Released)
+            }
+            NSEventType::MouseMoved
+            | NSEventType::LeftMouseDragged
+            | NSEventType::RightMouseDragged
+            | NSEventType::OtherMouseDragged => None,
+            NSEventType::ScrollWheel => {
+                let translator = InputTranslator::new();
+                let time = translator.event_time(event.timestamp());
+                let (dx, dy) = translator.scroll_motion(
+                    event.scrollingDeltaX(),
+                    event.scrollingDeltaY(),
+                    event.hasPreciseScrollingDeltas(),
+                );
+                if dx != 0.0 || dy != 0.0 {
+                    let events = translator.scroll_event(dx, dy, time);
+                    self.state.borrow_mut().pointer_axis(events);
+                }
+                return;
+            }
+            _ => return,
+        };
+        if event.subtype() == NSEventSubtype::TabletPoint {
+            return;
+        }
+
+        let translator = InputTranslator::new();
+        let time = translator.event_time(event.timestamp());
+        let (x, y) = self.cursor_position();
+        let mut state = self.state.borrow_mut();
+        let (under, located) = surfaces_at(&state, x, y);
+        let locate = |surface| located.get(&surface).copied().unwrap_or((x, y));
+        match button {
+            Some(button_state) => {
+                // Buttons go to the focused surface, so focus follows the
+                // cursor first if it got there without moving
+                if state.compositor.seat.pointer().focus() != under {
+                    state.pointer_motion(time, under, locate);
+                }
+                let button = translator.translate_button(event.buttonNumber() as i32);
+                state.pointer_button(time, button, button_state, under, locate);
+            }
+            None => state.pointer_motion(time, under, locate),
+        }
+    }
+
+    /// Send trackpad gestures to pointer gesture objects
+    ///
+    /// Magnify and rotate events make up one pinch. Fingers resting on the
+    /// trackpad, which AppKit reports as a scroll that may begin, are a
+    /// hold until they start scrolling or lift.
+    fn forward_gesture(&self, event: &NSEvent) {
+        let translator = InputTranslator::new();
+        let time = translator.event_time(event.timestamp());
+        let phase = translator.gesture_phase(event.phase().0);
+        let mut state = self.state.borrow_mut();
+        match (event.r#type(), phase) {
+            (NSEventType::Magnify | NSEventType::Rotate, Some(GesturePhase::Begin)) => {
+                state.gesture_begin(GestureKind::Pinch, time, 2);
+            }
+            (NSEventType::Magnify, Some(GesturePhase::Update)) => {
+                state.gesture_pinch_update(time, 0.0, 0.0, event.magnification(), 0.0);
+            }
+            (NSEventType::Rotate, Some(GesturePhase::Update)) => {
+                // AppKit rotates counterclockwise
+                let rotation = -f64::from(event.rotation());
+                state.gesture_pinch_update(time, 0.0, 0.0, 0.0, rotation);
+            }
+            (NSEventType::Magnify | NSEventType::Rotate, Some(GesturePhase::End)) => {
+                state.gesture_end(GestureKind::Pinch, time, false);
+            }
+            (NSEventType::Magnify | NSEventType::Rotate, Some(GesturePhase::Cancel)) => {
+                state.gesture_end(GestureKind::Pinch, time, true);
+            }
+            (NSEventType::Swipe, _) => {
+                let (dx, dy) = translator.swipe_motion(event.deltaX(), event.deltaY());
+                state.gesture_begin(GestureKind::Swipe, time, 3);
+                state.gesture_swipe_update(time, dx, dy);
+                state.gesture_end(GestureKind::Swipe, time, false);
+            }
+            (NSEventType::ScrollWheel, Some(GesturePhase::MayBegin)) => {
+                state.gesture_begin(GestureKind::Hold, time, 2);
+            }
+            (NSEventType::ScrollWheel, Some(GesturePhase::Begin)) => {
+                state.gesture_end(GestureKind::Hold, time, true);
+            }
+            (NSEventType::ScrollWheel, Some(GesturePhase::Cancel)) => {
+                state.gesture_end(GestureKind::Hold, time, false);
+            }
+            _ => {}
+        }
+    }
+
+    /// Send the fingers on the trackpad as touch points, in touchscreen
+    /// emulation mode
+    fn forward_touches(&self, event: &NSEvent) {
+        let touch_event = matches!(
+            event.r#type(),
+            NSEventType::Gesture | NSEventType::BeginGesture | NSEventType::EndGesture
+        );
+        if !touch_event || self.state.borrow().touch_emulation.is_none() {
+            return;
+        }
+        let translator = InputTranslator::new();
+        let time = translator.event_time(event.timestamp());
+        let contacts = translator.touch_contacts(event);
+        self.state.borrow_mut().touch_contacts(time, &contacts);
+    }
+
+    /// Send stylus proximity and motion to tablet tools
+    ///
+    /// Tablets report points as their own events while the stylus hovers,
+    /// and as mouse events of the tablet point subtype while the tip is
+    /// down; proximity events come either way too.
+    fn forward_tablet(&self, event: &NSEvent) {
+        let mouse = matches!(
+            event.r#type(),
+            NSEventType::LeftMouseDown
+                | NSEventType::LeftMouseUp
+                | NSEventType::LeftMouseDragged
+                | NSEventType::RightMouseDown
+                | NSEventType::RightMouseUp
+                | NSEventType::RightMouseDragged
+                | NSEventType::OtherMouseDown
+                | NSEventType::OtherMouseUp
+                | NSEventType::OtherMouseDragged
+                | NSEventType::MouseMoved
+        );
+        // Only mouse events have a subtype
+        let subtype = mouse.then(|| event.subtype());
+        let proximity = event.r#type() == NSEventType::TabletProximity
+            || subtype == Some(NSEventSubtype::TabletProximity);
+        let point = event.r#type() == NSEventType::TabletPoint
+            || subtype == Some(NSEventSubtype::TabletPoint);
+
+        let translator = InputTranslator::new();
+        let time = translator.event_time(event.timestamp());
+        let mut state = self.state.borrow_mut();
+        if proximity {
+            let tool = translator.tablet_tool(event);
+            if event.isEnteringProximity() {
+                state.tablet_tool_added(tool);
+            } else {
+                state.tablet_tool_proximity_out(time, tool.id);
+            }
+        } else if point {
+            let tool = TabletToolId(event.deviceID() as u64);
+            let sample = translator.tablet_sample(event);
+            let (x, y) = self.cursor_position();
+            let (under, located) = surfaces_at(&state, x, y);
+            let locate = |surface| located.get(&surface).copied().unwrap_or((x, y));
+            state.tablet_tool_motion(time, tool, sample, under, locate);
+        }
+    }
+
+    /// Get the cursor position with the origin at the top-left of the
+    /// primary screen, as outputs use
+    ///
+    /// Cocoa uses a bottom-left origin relative to the primary screen.
+    fn cursor_position(&self) -> (f64, f64) {
+        cursor_position(self.mtm)
+    }
+
+    /// Warp the cursor back into the window it is confined to
+    fn confine_cursor(&self) {
+        let state = self.state.borrow();
+        if let Some(window) = state
+            .confined_window()
+            .and_then(|window| state.backend.native_window(window))
+        {
+            window.confine_cursor();
+        }
+    }
+
     /// Dispatch pending Wayland events
     fn dispatch_wayland(&self) -> anyhow::Result<()> {
         let mut server = self.server.borrow_mut();
@@ -163,6 +792,35 @@ impl WayoaApp {
         server.dispatch(&mut state)
     }
 
+    /// Feed pointer motion over wayoa's own windows to the hot corners
+    fn forward_hot_corner_motion(&self, event: &NSEvent) {
+        let moved = matches!(
+            event.r#type(),
+            NSEventType::MouseMoved
+                | NSEventType::LeftMouseDragged
+                | NSEventType::RightMouseDragged
+                | NSEventType::OtherMouseDragged
+        );
+        if moved {
+            update_hot_corners(&mut self.state.borrow_mut(), self.mtm);
+        }
+    }
+
+    /// Update the hot corners once the pointer rested in one for its delay
+    ///
+    /// There is no motion to feed them while the pointer rests.
+    fn update_hot_corner_delay(&self) {
+        let due = self
+            .state
+            .borrow()
+            .hot_corners
+            .trigger_at()
+            .is_some_and(|at| at <= Instant::now());
+        if due {
+            update_hot_corners(&mut self.state.borrow_mut(), self.mtm);
+        }
+    }
+
     /// Stop the application
     pub fn stop(&self) {
         *self.running.borrow_mut() = false;
@@ -175,9 +833,258 @@ impl WayoaApp {
     }
 }
 
+impl Drop for WayoaApp {
+    fn drop(&mut self) {
+        if let Some(monitor) = self.hot_corner_monitor.take() {
+            // SAFETY: the monitor was returned by addGlobalMonitorForEvents
+            unsafe { NSEvent::removeMonitor(&monitor) };
+        }
+    }
+}
+
+/// Query the corners macOS already uses for its own hot corners
+fn system_hot_corners() -> Vec<Corner> {
+    [
+        ("wvous-tl-corner", Corner::TopLeft),
+        ("wvous-tr-corner", Corner::TopRight),
+        ("wvous-bl-corner", Corner::BottomLeft),
+        ("wvous-br-corner", Corner::BottomRight),
+    ]
+    .into_iter()
+    // 0 and 1 both mean "no action"
+    .filter(|(key, _)| domain_integer("com.apple.dock", key).is_some_and(|action| action > 1))
+    .map(|(_, corner)| corner)
+    .collect()
+}
+
+/// Read a number from the defaults of another domain, such as the Dock's
+fn domain_integer(domain: &str, key: &str) -> Option<isize> {
+    let defaults = NSUserDefaults::standardUserDefaults();
+    let domain = defaults.persistentDomainForName(&NSString::from_str(domain))?;
+    let value = domain.objectForKey(&NSString::from_str(key))?;
+    value
+        .downcast_ref::<NSNumber>()
+        .map(|number| number.integerValue())
+}
+
+/// Check if Sticky Keys is on in the macOS accessibility settings
+fn system_sticky_keys() -> bool {
+    domain_integer("com.apple.universalaccess", "stickyKey").is_some_and(|value| value != 0)
+}
+
+/// Hand sticky modifiers to macOS while its Sticky Keys is on
+///
+/// The setting is read again whenever wayoa becomes active, as macOS
+/// doesn't notify other apps when it changes.
+fn follow_system_sticky_keys(state: &mut ServerState) {
+    let input_config = state.config.input.clone();
+    state
+        .compositor
+        .seat
+        .keyboard_mut()
+        .sticky_mut()
+        .set_system_sticky_keys(system_sticky_keys(), &input_config);
+}
+
+/// Open Mission Control
+pub fn show_mission_control() {
+    if let Err(e) = std::process::Command::new("open")
+        .args(["-a", "Mission Control"])
+        .spawn()
+    {
+        error!("Failed to open Mission Control: {}", e);
+    }
+}
+
+/// Bounce the Dock icon once
+pub fn request_user_attention() {
+    let Some(mtm) = MainThreadMarker::new() else {
+        return;
+    };
+    NSApplication::sharedApplication(mtm)
+        .requestUserAttention(NSRequestUserAttentionType::InformationalRequest);
+}
+
+/// Show a named cursor
+///
+/// Shapes without an AppKit counterpart, such as busy cursors, fall back
+/// to the closest one or the arrow.
+pub fn set_cursor(shape: CursorShape) {
+    let frame_resize = |position| {
+        NSCursor::frameResizeCursorFromPosition_inDirections(
+            position,
+            NSCursorFrameResizeDirections::All,
+        )
+    };
+    let cursor = match shape {
+        CursorShape::Text => NSCursor::IBeamCursor(),
+        CursorShape::VerticalText => NSCursor::IBeamCursorForVerticalLayout(),
+        CursorShape::Pointer => NSCursor::pointingHandCursor(),
+        CursorShape::Crosshair | CursorShape::Cell => NSCursor::crosshairCursor(),
+        CursorShape::ContextMenu => NSCursor::contextualMenuCursor(),
+        CursorShape::Alias => NSCursor::dragLinkCursor(),
+        CursorShape::Copy => NSCursor::dragCopyCursor(),
+        CursorShape::NoDrop | CursorShape::NotAllowed => NSCursor::operationNotAllowedCursor(),
+        CursorShape::Grab | CursorShape::Move | CursorShape::AllScroll => {
+            NSCursor::openHandCursor()
+        }
+        CursorShape::Grabbing => NSCursor::closedHandCursor(),
+        CursorShape::NResize => frame_resize(NSCursorFrameResizePosition::Top),
+        CursorShape::SResize => frame_resize(NSCursorFrameResizePosition::Bottom),
+        CursorShape::EResize => frame_resize(NSCursorFrameResizePosition::Right),
+        CursorShape::WResize => frame_resize(NSCursorFrameResizePosition::Left),
+        CursorShape::NeResize | CursorShape::NeswResize => {
+            frame_resize(NSCursorFrameResizePosition::TopRight)
+        }
+        CursorShape::NwResize | CursorShape::NwseResize => {
+            frame_resize(NSCursorFrameResizePosition::TopLeft)
+        }
+        CursorShape::SeResize => frame_resize(NSCursorFrameResizePosition::BottomRight),
+        CursorShape::SwResize => frame_resize(NSCursorFrameResizePosition::BottomLeft),
+        CursorShape::EwResize | CursorShape::ColResize => NSCursor::columnResizeCursor(),
+        CursorShape::NsResize | CursorShape::RowResize => NSCursor::rowResizeCursor(),
+        _ => NSCursor::arrowCursor(),
+    };
+    cursor.set();
+}
+
+/// Get the cursor position with the origin at the top-left of the primary
+/// screen, as outputs use
+///
+/// Cocoa uses a bottom-left origin relative to the primary screen.
+fn cursor_position(mtm: MainThreadMarker) -> (f64, f64) {
+    let location = NSEvent::mouseLocation();
+    let primary_height = NSScreen::screens(mtm)
+        .firstObject()
+        .map(|screen| screen.frame().size.height)
+        .unwrap_or(0.0);
+    (location.x, primary_height - location.y)
+}
+
+/// Feed the global pointer position to the hot corner detector
+fn update_hot_corners(state: &mut ServerState, mtm: MainThreadMarker) {
+    if !state.hot_corners.is_enabled() {
+        return;
+    }
+
+    let (x, y) = cursor_position(mtm);
+    if let Some(action) = state
+        .hot_corners
+        .update(&state.compositor.outputs, x, y, Instant::now())
+    {
+        state.perform_action(action);
+    }
+}
+
+/// Find the window shown in the native window with a window server number
+fn native_window_id(state: &ServerState, number: isize) -> Option<WindowId> {
+    state
+        .backend
+        .native_windows()
+        .find(|(_, native)| native.number() == number)
+        .map(|(id, _)| id)
+}
+
+/// Find the surface under a point of the screen, and where the point is
+/// in the coordinates of each surface of the windows
+///
+/// The point has its origin at the top-left of the primary screen. Zoomed
+/// windows show a magnified part of their content, so the point is mapped
+/// to the content it shows.
+fn surfaces_at(
+    state: &ServerState,
+    x: f64,
+    y: f64,
+) -> (Option<SurfaceId>, HashMap<SurfaceId, (f64, f64)>) {
+    let surfaces = &state.compositor.surfaces;
+    let mut under = None;
+    let mut located = HashMap::new();
+    // Front to back
+    for window_id in state.compositor.windows.stacking_order().iter().rev() {
+        let (Some(window), Some(native)) = (
+            state.compositor.windows.get(*window_id),
+            state.backend.native_window(*window_id),
+        ) else {
+            continue;
+        };
+        let (ox, oy) = native.content_origin();
+        let (wx, wy) = (x - ox as f64, y - oy as f64);
+        let (wx, wy) = match surfaces.get(window.surface_id).and_then(|s| s.size()) {
+            Some((w, h)) => window.zoom.to_content((w as f64, h as f64), wx, wy),
+            None => (wx, wy),
+        };
+        for (surface, (sx, sy)) in surfaces.stacking_order(window.surface_id, (0, 0)) {
+            located
+                .entry(surface)
+                .or_insert((wx - sx as f64, wy - sy as f64));
+        }
+        if under.is_none() {
+            under = surfaces
+                .surface_at(window.surface_id, wx, wy)
+                .map(|(surface, _, _)| surface);
+        }
+    }
+    (under, located)
+}
+
+/// Lock the session, like Lock Screen in the Apple menu
+///
+/// That goes through SACLockScreenImmediate of the private login framework.
+/// Should it be missing, the displays are put to sleep instead, which only
+/// locks the session if a password is required after sleep.
+pub fn lock_session() {
+    if lock_screen_immediate() {
+        return;
+    }
+    warn!("SACLockScreenImmediate is unavailable, putting the displays to sleep");
+    if let Err(e) = std::process::Command::new("pmset")
+        .arg("displaysleepnow")
+        .spawn()
+    {
+        error!("Failed to lock session: {}", e);
+    }
+}
+
+/// Call SACLockScreenImmediate, returning true if it locked the screen
+fn lock_screen_immediate() -> bool {
+    const LOGIN: &std::ffi::CStr =
+        c"/System/Library/PrivateFrameworks/login.framework/Versions/Current/login";
+    // SAFETY: the framework stays loaded, and the symbol is a C function
+    // without arguments returning an error code
+    unsafe {
+        let handle = libc::dlopen(LOGIN.as_ptr(), libc::RTLD_LAZY);
+        if handle.is_null() {
+            return false;
+        }
+        let symbol = libc::dlsym(handle, c"SACLockScreenImmediate".as_ptr());
+        if symbol.is_null() {
+            return false;
+        }
+        let lock: extern "C" fn() -> libc::c_int = std::mem::transmute(symbol);
+        lock() == 0
+    }
+}
+
+/// Open a file in QuickLook
+pub fn quick_look(path: &std::path::Path) {
+    if let Err(e) = std::process::Command::new("qlmanage")
+        .arg("-p")
+        .arg(path)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+    {
+        error!("Failed to open QuickLook: {}", e);
+    }
+}
+
 /// Application delegate ivars
 struct WayoaAppDelegateIvars {
-    // Add any instance variables here
+    /// Server state, for saving the session on termination, reading the
+    /// selection for services and following system settings on activation
+    state: Rc<RefCell<ServerState>>,
+    /// Display, to flush requests for the selection to its owner
+    display: DisplayHandle,
 }
 
 define_class!(
@@ -195,9 +1102,20 @@ define_class!(
             info!("Application did finish launching");
         }
 
+        #[unsafe(method(applicationDidBecomeActive:))]
+        fn application_did_become_active(&self, _notification: &NSNotification) {
+            if let Ok(mut state) = self.ivars().state.try_borrow_mut() {
+                follow_system_sticky_keys(&mut state);
+            }
+        }
+
         #[unsafe(method(applicationWillTerminate:))]
         fn application_will_terminate(&self, _notification: &NSNotification) {
             info!("Application will terminate");
+            match self.ivars().state.try_borrow_mut() {
+                Ok(mut state) => state.save_session(),
+                Err(_) => error!("Server state busy, not saving session"),
+            }
         }
 
         #[unsafe(method(applicationShouldTerminateAfterLastWindowClosed:))]
@@ -209,11 +1127,76 @@ define_class!(
             false
         }
     }
+
+    impl WayoaAppDelegate {
+        /// Offer to provide text to a service while a client has a text
+        /// selection
+        ///
+        /// NSApplication asks its delegate once no responder in the chain
+        /// can provide the data.
+        #[unsafe(method(validRequestorForSendType:returnType:))]
+        fn valid_requestor(
+            &self,
+            send_type: Option<&NSString>,
+            return_type: Option<&NSString>,
+        ) -> *mut AnyObject {
+            // SAFETY: NSPasteboardTypeString is an immutable AppKit constant
+            let text = unsafe { NSPasteboardTypeString };
+            let has_text = self
+                .ivars()
+                .state
+                .try_borrow()
+                .is_ok_and(|state| state.has_selection_text());
+            if has_text && return_type.is_none() && send_type.is_some_and(|t| t == text) {
+                self as *const Self as *mut AnyObject
+            } else {
+                std::ptr::null_mut()
+            }
+        }
+
+        /// Read the selection from its client and put it on the pasteboard
+        /// of the chosen service
+        #[unsafe(method(writeSelectionToPasteboard:types:))]
+        fn write_selection_to_pasteboard(
+            &self,
+            pasteboard: &NSPasteboard,
+            _types: &NSArray<NSString>,
+        ) -> bool {
+            // The body has a single exit, as define_class! wraps the return
+            // value into a Bool
+            let ivars = self.ivars();
+            let mut display = ivars.display.clone();
+            let text = ivars
+                .state
+                .try_borrow()
+                .map(|state| state.read_selection_text(&mut display));
+            match text {
+                Ok(Ok(text)) => {
+                    pasteboard.clearContents();
+                    // SAFETY: NSPasteboardTypeString is an immutable AppKit constant
+                    let text_type = unsafe { NSPasteboardTypeString };
+                    pasteboard.setString_forType(&NSString::from_str(&text), text_type)
+                }
+                Ok(Err(e)) => {
+                    warn!("Failed to read selection for service: {}", e);
+                    false
+                }
+                // Busy dispatching a request, try again on the next one
+                Err(_) => false,
+            }
+        }
+    }
 );
 
 impl WayoaAppDelegate {
-    fn new(mtm: MainThreadMarker) -> Retained<Self> {
-        let this = mtm.alloc::<Self>().set_ivars(WayoaAppDelegateIvars {});
+    fn new(
+        mtm: MainThreadMarker,
+        state: Rc<RefCell<ServerState>>,
+        display: DisplayHandle,
+    ) -> Retained<Self> {
+        let this = mtm
+            .alloc::<Self>()
+            .set_ivars(WayoaAppDelegateIvars { state, display });
         let this: Option<Retained<Self>> = unsafe { msg_send![super(this), init] };
         this.expect("init failed")
     }
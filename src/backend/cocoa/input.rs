@@ -1,6 +1,85 @@
 //! NSEvent handling and translation to Wayland events
 
-use crate::protocol::seat::{AxisType, ButtonState, KeyState, KeyboardEvent, PointerEvent};
+use std::time::Duration;
+
+use objc2::msg_send;
+use objc2::rc::Retained;
+use objc2::runtime::AnyObject;
+use objc2_app_kit::{NSEvent, NSPointingDeviceType};
+use objc2_core_foundation::CGPoint;
+
+use crate::input::keyboard::ModifierState;
+use crate::input::{Contact, TabletSample, TabletTool, TabletToolId, TabletToolKind};
+use crate::protocol::seat::{
+    AxisType, ButtonState, KeyState, KeyboardEvent, PointerEvent, RelativeMotion,
+};
+use crate::util::clock;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGAssociateMouseAndMouseCursorPosition(connected: i32) -> i32;
+    fn CGWarpMouseCursorPosition(new_cursor_position: CGPoint) -> i32;
+    fn CGEventSourceKeyState(state_id: i32, key: u16) -> bool;
+}
+
+/// Tilt in degrees of a tablet tool at AppKit's largest tilt
+const MAX_TILT: f64 = 60.0;
+
+/// kCGEventSourceStateHIDSystemState, the state of the hardware
+const HID_SYSTEM_STATE: i32 = 1;
+
+/// Check if a key is physically down, which modifier flags don't tell
+/// while Sticky Keys is on
+pub fn key_is_down(keycode: u16) -> bool {
+    // SAFETY: plain CoreGraphics call without pointers
+    unsafe { CGEventSourceKeyState(HID_SYSTEM_STATE, keycode) }
+}
+
+/// Detach the cursor from the mouse, or attach it again
+///
+/// While detached the cursor stays put, but mouse events keep their deltas
+/// for relative pointers.
+pub fn set_cursor_locked(locked: bool) {
+    // SAFETY: plain CoreGraphics call without pointers
+    let error = unsafe { CGAssociateMouseAndMouseCursorPosition(i32::from(!locked)) };
+    if error != 0 {
+        log::warn!("Failed to set cursor lock: CGError {}", error);
+    }
+}
+
+/// Move the cursor, in global coordinates with the origin at the top-left
+/// of the primary screen
+pub fn warp_cursor(x: f64, y: f64) {
+    // SAFETY: plain CoreGraphics call without pointers
+    let error = unsafe { CGWarpMouseCursorPosition(CGPoint { x, y }) };
+    if error != 0 {
+        log::warn!("Failed to warp cursor: CGError {}", error);
+    }
+}
+
+/// NSTouchPhaseTouching: began, moved or stationary
+const NS_TOUCH_PHASE_TOUCHING: usize = 0x1 | 0x2 | 0x4;
+
+/// Swipe distance in logical pixels for one unit of NSEvent swipe delta
+const SWIPE_DISTANCE: f64 = 100.0;
+
+/// Scroll distance in logical pixels for one line of a scroll wheel
+const LINE_SCROLL_DISTANCE: f64 = 10.0;
+
+/// Phase of a gesture or scroll NSEvent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GesturePhase {
+    /// Fingers touched the trackpad but haven't moved yet
+    MayBegin,
+    /// The gesture started
+    Begin,
+    /// The gesture changed, or stayed put
+    Update,
+    /// The gesture ended
+    End,
+    /// The gesture was cancelled
+    Cancel,
+}
 
 /// Translates macOS NSEvent to Wayland input events
 pub struct InputTranslator;
@@ -123,6 +202,14 @@ impl InputTranslator {
         }
     }
 
+    /// Convert an NSEvent timestamp to an event time in milliseconds
+    ///
+    /// NSEvent timestamps are seconds on mach_absolute_time, the protocol
+    /// clock, so event times line up with presentation feedback.
+    pub fn event_time(&self, timestamp: f64) -> u32 {
+        clock::msec(Duration::from_secs_f64(timestamp.max(0.0)))
+    }
+
     /// Create a key event
     pub fn key_event(&self, keycode: u16, pressed: bool, time: u32) -> KeyboardEvent {
         let key = self.translate_keycode(keycode);
@@ -172,6 +259,118 @@ impl InputTranslator {
         }
     }
 
+    /// Create relative motion from an NSEvent's deltas and timestamp
+    ///
+    /// AppKit only reports accelerated deltas, which keep coming when the
+    /// cursor is stopped by a screen edge, so they are also sent as the
+    /// unaccelerated motion.
+    pub fn relative_motion(&self, delta_x: f64, delta_y: f64, timestamp: f64) -> RelativeMotion {
+        RelativeMotion {
+            utime: Duration::from_secs_f64(timestamp.max(0.0)).as_micros() as u64,
+            dx: delta_x,
+            dy: delta_y,
+            dx_unaccel: delta_x,
+            dy_unaccel: delta_y,
+        }
+    }
+
+    /// Translate NSEventPhase bits, None outside of a gesture
+    pub fn gesture_phase(&self, phase: usize) -> Option<GesturePhase> {
+        match phase {
+            0x1 => Some(GesturePhase::Begin),
+            0x2 | 0x4 => Some(GesturePhase::Update),
+            0x8 => Some(GesturePhase::End),
+            0x10 => Some(GesturePhase::Cancel),
+            0x20 => Some(GesturePhase::MayBegin),
+            _ => None,
+        }
+    }
+
+    /// Get the fingers touching the trackpad during an NSEvent
+    ///
+    /// Positions are flipped to have the origin at the top-left, and
+    /// fingers are told apart by the hash of their NSTouch identity.
+    pub fn touch_contacts(&self, event: &NSEvent) -> Vec<Contact> {
+        // SAFETY: touchesMatchingPhase:inView: returns an NSSet of NSTouch,
+        // whose allObjects is an NSArray of them; a nil view matches all
+        let touches: Vec<Retained<AnyObject>> = unsafe {
+            let set: Retained<AnyObject> = msg_send![
+                event,
+                touchesMatchingPhase: NS_TOUCH_PHASE_TOUCHING,
+                inView: None::<&AnyObject>
+            ];
+            let all: Retained<AnyObject> = msg_send![&*set, allObjects];
+            let count: usize = msg_send![&*all, count];
+            (0..count)
+                .map(|i| msg_send![&*all, objectAtIndex: i])
+                .collect()
+        };
+        touches
+            .iter()
+            .map(|touch| {
+                // SAFETY: NSTouch methods without arguments
+                let (position, identity): (CGPoint, usize) = unsafe {
+                    let position: CGPoint = msg_send![&**touch, normalizedPosition];
+                    let identity: Retained<AnyObject> = msg_send![&**touch, identity];
+                    (position, msg_send![&*identity, hash])
+                };
+                Contact {
+                    identity: identity as u64,
+                    x: position.x,
+                    y: 1.0 - position.y,
+                }
+            })
+            .collect()
+    }
+
+    /// Describe the tool of a tablet proximity NSEvent
+    pub fn tablet_tool(&self, event: &NSEvent) -> TabletTool {
+        let kind = match event.pointingDeviceType() {
+            NSPointingDeviceType::Eraser => TabletToolKind::Eraser,
+            NSPointingDeviceType::Cursor => TabletToolKind::Mouse,
+            _ => TabletToolKind::Pen,
+        };
+        TabletTool {
+            id: TabletToolId(event.deviceID() as u64),
+            kind,
+            serial: event.pointingDeviceSerialNumber() as u64,
+            hardware_id: event.uniqueID(),
+        }
+    }
+
+    /// Get the axes and buttons of a tablet point NSEvent
+    ///
+    /// AppKit reports tilt from -1 to 1, for the largest tilt the tablet
+    /// senses, taken as 60 degrees, with y positive away from the user.
+    pub fn tablet_sample(&self, event: &NSEvent) -> TabletSample {
+        let tilt = event.tilt();
+        TabletSample {
+            pressure: f64::from(event.pressure()),
+            tilt: (tilt.x * MAX_TILT, -tilt.y * MAX_TILT),
+            rotation: f64::from(event.rotation()),
+            buttons: event.buttonMask().0 as u32 & 0b111,
+        }
+    }
+
+    /// Convert the deltas of an NSEvent swipe to finger motion
+    ///
+    /// AppKit reports a swipe once it is recognized, with a delta of one
+    /// towards where the content would move, so it is sent as a whole
+    /// swipe of a fixed distance the other way.
+    pub fn swipe_motion(&self, delta_x: f64, delta_y: f64) -> (f64, f64) {
+        (-delta_x * SWIPE_DISTANCE, -delta_y * SWIPE_DISTANCE)
+    }
+
+    /// Convert NSEvent scrolling deltas to axis values
+    ///
+    /// Trackpads report precise deltas in points, mouse wheels in lines.
+    /// AppKit deltas are positive when the content moves down or right,
+    /// axis values when the view scrolls down or right.
+    pub fn scroll_motion(&self, delta_x: f64, delta_y: f64, precise: bool) -> (f64, f64) {
+        let scale = if precise { 1.0 } else { LINE_SCROLL_DISTANCE };
+        (-delta_x * scale, -delta_y * scale)
+    }
+
     /// Create a scroll/axis event
     pub fn scroll_event(&self, dx: f64, dy: f64, time: u32) -> Vec<PointerEvent> {
         let mut events = Vec::new();
@@ -196,6 +395,17 @@ impl InputTranslator {
         events
     }
 
+    /// Translate macOS modifier flags to the modifier state they report
+    pub fn reported_modifiers(&self, macos_flags: u64) -> ModifierState {
+        let (depressed, latched, locked, group) = self.translate_modifiers(macos_flags);
+        ModifierState {
+            depressed,
+            latched,
+            locked,
+            group,
+        }
+    }
+
     /// Translate macOS modifier flags to XKB modifier mask
     pub fn translate_modifiers(&self, macos_flags: u64) -> (u32, u32, u32, u32) {
         // macOS NSEventModifierFlags to XKB modifier state
@@ -260,6 +470,13 @@ mod tests {
         assert_eq!(translator.translate_button(2), 0x112); // Middle
     }
 
+    #[test]
+    fn test_scroll_motion() {
+        let translator = InputTranslator::new();
+        assert_eq!(translator.scroll_motion(0.0, 2.5, true), (-0.0, -2.5));
+        assert_eq!(translator.scroll_motion(1.0, -1.0, false), (-10.0, 10.0));
+    }
+
     #[test]
     fn test_key_event() {
         let translator = InputTranslator::new();
@@ -306,4 +523,24 @@ mod tests {
         let (_, _, locked, _) = translator.translate_modifiers(1 << 16);
         assert_eq!(locked, 2);
     }
+
+    #[test]
+    fn test_relative_motion() {
+        let translator = InputTranslator::new();
+        let motion = translator.relative_motion(-3.0, 2.5, 12.5);
+        assert_eq!(motion.utime, 12_500_000);
+        assert_eq!((motion.dx, motion.dy), (-3.0, 2.5));
+        assert_eq!((motion.dx_unaccel, motion.dy_unaccel), (-3.0, 2.5));
+        assert_eq!(translator.event_time(12.5), 12_500);
+    }
+
+    #[test]
+    fn test_gesture_phase() {
+        let translator = InputTranslator::new();
+        assert_eq!(translator.gesture_phase(0x1), Some(GesturePhase::Begin));
+        assert_eq!(translator.gesture_phase(0x2), Some(GesturePhase::Update));
+        assert_eq!(translator.gesture_phase(0x20), Some(GesturePhase::MayBegin));
+        assert_eq!(translator.gesture_phase(0), None);
+        assert_eq!(translator.swipe_motion(1.0, 0.0), (-SWIPE_DISTANCE, -0.0));
+    }
 }
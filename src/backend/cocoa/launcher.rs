@@ -0,0 +1,83 @@
+//! Launcher overlay panel
+//!
+//! A floating panel that shows the launcher's query and results. It never
+//! becomes key: the app's event loop feeds key presses to the launcher
+//! before they reach any window.
+
+use objc2::rc::Retained;
+use objc2_app_kit::{NSBackingStoreType, NSColor, NSFont, NSPanel, NSTextField, NSWindowStyleMask};
+use objc2_core_foundation::{CGPoint, CGRect, CGSize};
+use objc2_foundation::{MainThreadMarker, NSString};
+
+use crate::compositor::Launcher;
+
+/// Minimum panel width in points
+const MIN_WIDTH: f64 = 480.0;
+
+/// Results shown at most
+const MAX_RESULTS: usize = 12;
+
+/// Font size of the query and results
+const FONT_SIZE: f64 = 15.0;
+
+/// Native panel showing the launcher
+pub struct LauncherPanel {
+    panel: Retained<NSPanel>,
+    label: Retained<NSTextField>,
+    /// Lines currently shown, None while hidden
+    shown: Option<Vec<String>>,
+}
+
+impl LauncherPanel {
+    /// Create the hidden panel
+    pub fn new(mtm: MainThreadMarker) -> Self {
+        let frame = CGRect::new(CGPoint::new(0.0, 0.0), CGSize::new(MIN_WIDTH, 40.0));
+        let style = NSWindowStyleMask::Borderless | NSWindowStyleMask::NonactivatingPanel;
+        let panel = NSPanel::initWithContentRect_styleMask_backing_defer(
+            mtm.alloc(),
+            frame,
+            style,
+            NSBackingStoreType::Buffered,
+            false,
+        );
+        panel.setFloatingPanel(true);
+        panel.setHidesOnDeactivate(false);
+        panel.setBackgroundColor(Some(&NSColor::windowBackgroundColor()));
+
+        let label = NSTextField::labelWithString(&NSString::from_str(""), mtm);
+        if let Some(font) = NSFont::userFixedPitchFontOfSize(FONT_SIZE) {
+            label.setFont(Some(&font));
+        }
+        panel.setContentView(Some(&label));
+
+        Self {
+            panel,
+            label,
+            shown: None,
+        }
+    }
+
+    /// Show, refresh or hide the panel to match the launcher
+    pub fn update(&mut self, launcher: &Launcher) {
+        let lines: Option<Vec<String>> = launcher
+            .is_open()
+            .then(|| launcher.lines().into_iter().take(1 + MAX_RESULTS).collect());
+        if lines == self.shown {
+            return;
+        }
+
+        match &lines {
+            Some(lines) => {
+                self.label
+                    .setStringValue(&NSString::from_str(&lines.join("\n")));
+                let size = self.label.fittingSize();
+                self.panel
+                    .setContentSize(CGSize::new(size.width.max(MIN_WIDTH), size.height));
+                self.panel.center();
+                self.panel.orderFrontRegardless();
+            }
+            None => self.panel.orderOut(None),
+        }
+        self.shown = lines;
+    }
+}
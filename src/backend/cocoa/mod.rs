@@ -5,13 +5,16 @@
 //! - NSWindow for native windows (one per Wayland toplevel)
 //! - NSView with CAMetalLayer for Metal rendering
 //! - NSEvent handling for input translation
+//! - An NSPanel for the launcher overlay
 
 pub mod app;
 pub mod input;
+pub mod launcher;
 pub mod view;
 pub mod window;
 
 pub use app::WayoaApp;
-pub use input::InputTranslator;
+pub use input::{GesturePhase, InputTranslator};
+pub use launcher::LauncherPanel;
 pub use view::MetalView;
 pub use window::{NativeWindowHandle, WayoaWindow};
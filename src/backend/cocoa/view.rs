@@ -9,6 +9,7 @@ use objc2_foundation::{MainThreadMarker, NSObjectProtocol};
 use objc2_quartz_core::CAMetalLayer;
 
 use crate::compositor::SurfaceId;
+use crate::renderer::LayerOptions;
 
 /// A view with a Metal layer for rendering Wayland surface content
 pub struct MetalView {
@@ -78,6 +79,17 @@ impl MetalView {
         self.metal_layer.setDrawableSize(size);
     }
 
+    /// Set how the Metal layer presents, from the window's content hints
+    pub fn set_layer_options(&self, options: LayerOptions) {
+        let layer = &self.metal_layer;
+        if layer.maximumDrawableCount() != options.drawables as usize {
+            layer.setMaximumDrawableCount(options.drawables as usize);
+        }
+        if layer.displaySyncEnabled() != options.display_sync {
+            layer.setDisplaySyncEnabled(options.display_sync);
+        }
+    }
+
     /// Request a redraw
     pub fn set_needs_display(&self) {
         unsafe {
@@ -86,6 +98,9 @@ impl MetalView {
     }
 }
 
+/// NSTouchTypeMaskIndirect, touches on the trackpad
+const NS_TOUCH_TYPE_MASK_INDIRECT: usize = 1 << 1;
+
 /// View ivars - stores the surface ID for callback identification
 struct WayoaViewIvars {
     #[allow(dead_code)]
@@ -121,6 +136,8 @@ impl WayoaView {
         unsafe {
             let _: () = msg_send![&*this, setLayer: metal_layer];
             let _: () = msg_send![&*this, setWantsLayer: true];
+            // Trackpad touches, for touchscreen emulation
+            let _: () = msg_send![&*this, setAllowedTouchTypes: NS_TOUCH_TYPE_MASK_INDIRECT];
         }
 
         Ok(this)
@@ -1,16 +1,35 @@
 //! NSWindow wrapper for Wayland toplevels
 
+use std::cell::Cell;
+
 use log::debug;
 use objc2::rc::Retained;
 use objc2::runtime::ProtocolObject;
 use objc2::{define_class, msg_send, DefinedClass, MainThreadOnly};
 use objc2_app_kit::{
-    NSBackingStoreType, NSImageView, NSWindow, NSWindowDelegate, NSWindowStyleMask,
+    NSApplication, NSBackingStoreType, NSBitmapImageRep, NSEvent, NSImage, NSImageView,
+    NSResponder, NSScreen, NSWindow, NSWindowButton, NSWindowCollectionBehavior, NSWindowDelegate,
+    NSWindowOrderingMode, NSWindowStyleMask, NSWindowTitleVisibility,
 };
 use objc2_core_foundation::{CGPoint, CGRect, CGSize};
-use objc2_foundation::{MainThreadMarker, NSNotification, NSObject, NSObjectProtocol, NSString};
+use objc2_foundation::{
+    MainThreadMarker, NSNotification, NSObject, NSObjectProtocol, NSString, NSUserDefaults,
+};
 
-use crate::compositor::WindowId;
+use crate::compositor::icon::{find_icon, icon_dirs, ICON_SIZE};
+use crate::compositor::{WindowGeometry, WindowIcon, WindowId};
+use crate::protocol::layer_shell::Layer;
+
+/// Window level of a layer: the desktop, just below normal windows, the
+/// floating level and the status bar level
+fn layer_level(layer: Layer) -> isize {
+    match layer {
+        Layer::Background => -2,
+        Layer::Bottom => -1,
+        Layer::Top => 3,
+        Layer::Overlay => 25,
+    }
+}
 
 /// Native window handle
 #[derive(Debug)]
@@ -22,6 +41,11 @@ pub struct NativeWindowHandle {
 }
 
 impl NativeWindowHandle {
+    /// Get the backing scale factor of the screen the window is on
+    pub fn backing_scale(&self) -> f64 {
+        self.window.backingScaleFactor()
+    }
+
     /// Get the window ID
     pub fn id(&self) -> WindowId {
         self.window_id
@@ -43,6 +67,8 @@ pub struct WayoaWindow {
     window_id: WindowId,
     /// Image view for rendering buffer content
     image_view: Option<Retained<NSImageView>>,
+    /// Delegate, which the NSWindow doesn't keep alive
+    delegate: Retained<WayoaWindowDelegate>,
 }
 
 impl WayoaWindow {
@@ -64,6 +90,61 @@ impl WayoaWindow {
             | NSWindowStyleMask::Miniaturizable
             | NSWindowStyleMask::Resizable;
 
+        let window = Self::create(mtm, window_id, frame, style);
+
+        // Set title
+        let ns_title = NSString::from_str(title);
+        window.window.setTitle(&ns_title);
+
+        // Center on screen
+        window.window.center();
+
+        debug!(
+            "Created native window {:?}, {}x{}, title: {}",
+            window_id, width, height, title
+        );
+
+        Ok(window)
+    }
+
+    /// Create a borderless window for a layer surface
+    ///
+    /// The geometry is in global coordinates. The window sits at its layer's
+    /// window level on every Space, and isn't part of window cycling.
+    pub fn new_layer(
+        mtm: MainThreadMarker,
+        window_id: WindowId,
+        geometry: WindowGeometry,
+        layer: Layer,
+    ) -> Self {
+        let frame = CGRect::new(
+            CGPoint::new(0.0, 0.0),
+            CGSize::new(geometry.width as f64, geometry.height as f64),
+        );
+        let window = Self::create(mtm, window_id, frame, NSWindowStyleMask::Borderless);
+        window.window.setHasShadow(false);
+        window.window.setCollectionBehavior(
+            NSWindowCollectionBehavior::CanJoinAllSpaces
+                | NSWindowCollectionBehavior::Stationary
+                | NSWindowCollectionBehavior::IgnoresCycle,
+        );
+        window.set_layer(layer);
+        window.set_frame_global(geometry);
+
+        debug!(
+            "Created layer window {:?} at {:?} on {:?}",
+            window_id, geometry, layer
+        );
+        window
+    }
+
+    /// Create an NSWindow with a delegate and an image view for its content
+    fn create(
+        mtm: MainThreadMarker,
+        window_id: WindowId,
+        frame: CGRect,
+        style: NSWindowStyleMask,
+    ) -> Self {
         let window = unsafe {
             NSWindow::initWithContentRect_styleMask_backing_defer(
                 mtm.alloc(),
@@ -74,42 +155,33 @@ impl WayoaWindow {
             )
         };
 
-        // Set title
-        let ns_title = NSString::from_str(title);
-        window.setTitle(&ns_title);
-
-        // Center on screen
-        window.center();
-
         // Create and set delegate
         let delegate = WayoaWindowDelegate::new(mtm, window_id);
         let delegate_obj: &ProtocolObject<dyn NSWindowDelegate> =
             ProtocolObject::from_ref(&*delegate);
         window.setDelegate(Some(delegate_obj));
-
-        // Create an NSImageView for the content
-        let content_frame = CGRect::new(
-            CGPoint::new(0.0, 0.0),
-            CGSize::new(width as f64, height as f64),
-        );
-        let image_view = unsafe {
-            let view: Retained<NSImageView> =
-                msg_send![mtm.alloc::<NSImageView>(), initWithFrame: content_frame];
+        // Mouse movement without a button held is sent to clients too
+        window.setAcceptsMouseMovedEvents(true);
+
+        // Create an image view for the content, taking the window's keys
+        let content_frame = CGRect::new(CGPoint::new(0.0, 0.0), frame.size);
+        let content_view = unsafe {
+            let view: Retained<WayoaContentView> =
+                msg_send![mtm.alloc::<WayoaContentView>(), initWithFrame: content_frame];
             view
         };
+        let image_view = Retained::into_super(content_view);
         window.setContentView(Some(&image_view));
+        let responder: &NSResponder = &image_view;
+        window.makeFirstResponder(Some(responder));
 
-        debug!(
-            "Created native window {:?}, {}x{}, title: {}",
-            window_id, width, height, title
-        );
-
-        Ok(Self {
+        Self {
             mtm,
             window,
             window_id,
             image_view: Some(image_view),
-        })
+            delegate,
+        }
     }
 
     /// Show the window
@@ -123,10 +195,17 @@ impl WayoaWindow {
     }
 
     /// Close the window
+    ///
+    /// Unlike the close button, this doesn't ask the client.
     pub fn close(&self) {
         self.window.close();
     }
 
+    /// Check if the user clicked the close button since the last check
+    pub fn take_close_request(&self) -> bool {
+        self.delegate.ivars().close_requested.replace(false)
+    }
+
     /// Set the window title
     pub fn set_title(&self, title: &str) {
         let ns_title = NSString::from_str(title);
@@ -151,6 +230,98 @@ impl WayoaWindow {
         (frame.size.width as u32, frame.size.height as u32)
     }
 
+    /// Get the window placement
+    ///
+    /// The position is the frame's top-left point in Cocoa screen
+    /// coordinates, the size is the content size.
+    pub fn placement(&self) -> WindowGeometry {
+        let frame = self.window.frame();
+        let (width, height) = self.content_size();
+        WindowGeometry {
+            x: frame.origin.x as i32,
+            y: (frame.origin.y + frame.size.height) as i32,
+            width,
+            height,
+        }
+    }
+
+    /// Restore a placement previously returned by [`WayoaWindow::placement`]
+    pub fn set_placement(&self, placement: WindowGeometry) {
+        if placement.width > 0 && placement.height > 0 {
+            self.set_size(placement.width, placement.height);
+        }
+        self.set_position(placement.x, placement.y);
+    }
+
+    /// Move and resize the window to a rectangle in global coordinates
+    pub fn set_frame_global(&self, geometry: WindowGeometry) {
+        let primary_height = NSScreen::screens(self.mtm)
+            .firstObject()
+            .map(|screen| screen.frame().size.height)
+            .unwrap_or(0.0);
+        let frame = CGRect::new(
+            CGPoint::new(
+                geometry.x as f64,
+                primary_height - geometry.y as f64 - geometry.height as f64,
+            ),
+            CGSize::new(geometry.width as f64, geometry.height as f64),
+        );
+        self.window.setFrame_display(frame, true);
+    }
+
+    /// Put the window at the window level of a layer
+    pub fn set_layer(&self, layer: Layer) {
+        self.window.setLevel(layer_level(layer));
+    }
+
+    /// Show a layer window without activating the application
+    pub fn show_layer(&self) {
+        self.window.orderFrontRegardless();
+    }
+
+    /// Get the top-left corner of the content area
+    ///
+    /// Unlike [`WayoaWindow::placement`], this is in global coordinates with
+    /// the origin at the top-left of the primary screen, as outputs use.
+    pub fn content_origin(&self) -> (i32, i32) {
+        let content = self.window.contentRectForFrameRect(self.window.frame());
+        let primary_height = NSScreen::screens(self.mtm)
+            .firstObject()
+            .map(|screen| screen.frame().size.height)
+            .unwrap_or(0.0);
+        (
+            content.origin.x as i32,
+            (primary_height - (content.origin.y + content.size.height)) as i32,
+        )
+    }
+
+    /// Warp the cursor back into the content area if it left it
+    pub fn confine_cursor(&self) {
+        let content = self.window.contentRectForFrameRect(self.window.frame());
+        let location = NSEvent::mouseLocation();
+        let x = location.x.clamp(
+            content.origin.x,
+            content.origin.x + content.size.width - 1.0,
+        );
+        let y = location.y.clamp(
+            content.origin.y,
+            content.origin.y + content.size.height - 1.0,
+        );
+        if (x, y) == (location.x, location.y) {
+            return;
+        }
+        let primary_height = NSScreen::screens(self.mtm)
+            .firstObject()
+            .map(|screen| screen.frame().size.height)
+            .unwrap_or(0.0);
+        super::input::warp_cursor(x, primary_height - y);
+    }
+
+    /// Check if the user is dragging an edge of the window
+    pub fn in_live_resize(&self) -> bool {
+        self.window.inLiveResize()
+    }
+
     /// Get the content size (excluding title bar)
     pub fn content_size(&self) -> (u32, u32) {
         let content_rect = self.window.contentRectForFrameRect(self.window.frame());
@@ -160,6 +331,11 @@ impl WayoaWindow {
         )
     }
 
+    /// Get the backing scale factor of the screen the window is on
+    pub fn backing_scale(&self) -> f64 {
+        self.window.backingScaleFactor()
+    }
+
     /// Get the window ID
     pub fn id(&self) -> WindowId {
         self.window_id
@@ -192,6 +368,101 @@ impl WayoaWindow {
         }
     }
 
+    /// Show or hide the native titlebar
+    ///
+    /// Undecorated windows keep a transparent titlebar with the content
+    /// underneath it, rather than becoming borderless, so they can still be
+    /// made key and resized.
+    pub fn set_decorated(&self, decorated: bool) {
+        let mut style = self.window.styleMask();
+        if decorated {
+            style.remove(NSWindowStyleMask::FullSizeContentView);
+        } else {
+            style.insert(NSWindowStyleMask::FullSizeContentView);
+        }
+        self.window.setStyleMask(style);
+        self.window.setTitlebarAppearsTransparent(!decorated);
+        self.window.setTitleVisibility(if decorated {
+            NSWindowTitleVisibility::Visible
+        } else {
+            NSWindowTitleVisibility::Hidden
+        });
+        for button in [
+            NSWindowButton::CloseButton,
+            NSWindowButton::MiniaturizeButton,
+            NSWindowButton::ZoomButton,
+        ] {
+            if let Some(button) = self.window.standardWindowButton(button) {
+                button.setHidden(!decorated);
+            }
+        }
+    }
+
+    /// Set the opacity of the whole window, from 0.0 to 1.0
+    pub fn set_opacity(&self, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0) as f64;
+        if self.window.alphaValue() != opacity {
+            self.window.setAlphaValue(opacity);
+        }
+    }
+
+    /// Attach the window to a parent window, or detach it
+    ///
+    /// A sheet slides out of the parent's title bar and blocks the parent's
+    /// input until it's detached. Other windows become child windows, which
+    /// move with the parent and stay above it.
+    pub fn set_parent(&self, parent: Option<&WayoaWindow>, sheet: bool) {
+        let attached = match (self.window.sheetParent(), self.window.parentWindow()) {
+            (Some(current), _) => Some((current, true)),
+            (None, Some(current)) => Some((current, false)),
+            (None, None) => None,
+        };
+        let unchanged = match (&attached, parent) {
+            (Some((current, was_sheet)), Some(parent)) => {
+                std::ptr::eq(&**current, &*parent.window) && *was_sheet == sheet
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+
+        match attached {
+            Some((current, true)) => current.endSheet(&self.window),
+            Some((current, false)) => current.removeChildWindow(&self.window),
+            None => {}
+        }
+        match parent {
+            Some(parent) if sheet => parent
+                .window
+                .beginSheet_completionHandler(&self.window, None),
+            Some(parent) => {
+                // SAFETY: both windows are alive, and the parent keeps the
+                // child in its childWindows until it's removed above
+                unsafe {
+                    parent
+                        .window
+                        .addChildWindow_ordered(&self.window, NSWindowOrderingMode::Above)
+                };
+                self.window.orderFront(None);
+            }
+            None => self.window.orderFront(None),
+        }
+        debug!(
+            "Window {:?} attached to a parent: {} (sheet: {})",
+            self.window_id,
+            parent.is_some(),
+            sheet
+        );
+    }
+
+    /// Set the badge of the window's Dock tile, shown while minimized
+    pub fn set_badge(&self, label: Option<&str>) {
+        let label = label.map(NSString::from_str);
+        self.window.dockTile().setBadgeLabel(label.as_deref());
+    }
+
     /// Minimize the window
     pub fn minimize(&self) {
         self.window.miniaturize(None);
@@ -202,6 +473,47 @@ impl WayoaWindow {
         self.window.deminiaturize(None);
     }
 
+    /// Bring the window to the user and make it key
+    ///
+    /// Restores a minimized window. A window on another Space makes macOS
+    /// switch to that Space, unless "When switching to an application, switch
+    /// to a Space with open windows" is off; the window then joins the
+    /// current Space instead.
+    pub fn activate(&self) {
+        if self.window.isMiniaturized() {
+            self.window.deminiaturize(None);
+        }
+
+        let app = NSApplication::sharedApplication(self.mtm);
+        #[allow(deprecated)]
+        app.activateIgnoringOtherApps(true);
+
+        if !self.window.isOnActiveSpace() && !switches_space_on_activate() {
+            let behavior = self.window.collectionBehavior();
+            self.window
+                .setCollectionBehavior(behavior | NSWindowCollectionBehavior::MoveToActiveSpace);
+            self.window.makeKeyAndOrderFront(None);
+            self.window.setCollectionBehavior(behavior);
+        } else {
+            self.window.makeKeyAndOrderFront(None);
+        }
+    }
+
+    /// Put the window in front of the others without making it key
+    pub fn order_front(&self) {
+        self.window.orderFront(None);
+    }
+
+    /// Put the window behind the others
+    pub fn order_back(&self) {
+        self.window.orderBack(None);
+    }
+
+    /// The window server's number for the window
+    pub fn number(&self) -> isize {
+        self.window.windowNumber()
+    }
+
     /// Check if window is key (focused)
     pub fn is_key(&self) -> bool {
         self.window.isKeyWindow()
@@ -214,78 +526,171 @@ impl WayoaWindow {
 
     /// Update the window content from buffer data (ARGB8888 format)
     pub fn update_buffer(&self, data: &[u8], width: u32, height: u32, stride: u32) {
-        use objc2_app_kit::{NSBitmapImageRep, NSImage};
-
         let Some(image_view) = &self.image_view else {
             debug!("No image view for window {:?}", self.window_id);
             return;
         };
 
-        unsafe {
-            // Create bitmap rep that allocates its own storage (pass NULL for planes)
-            let bitmap_rep: Option<Retained<NSBitmapImageRep>> = msg_send![
-                self.mtm.alloc::<NSBitmapImageRep>(),
-                initWithBitmapDataPlanes: std::ptr::null_mut::<*mut u8>(),
-                pixelsWide: width as isize,
-                pixelsHigh: height as isize,
-                bitsPerSample: 8_isize,
-                samplesPerPixel: 4_isize,
-                hasAlpha: true,
-                isPlanar: false,
-                colorSpaceName: objc2_app_kit::NSCalibratedRGBColorSpace,
-                bytesPerRow: (width * 4) as isize,
-                bitsPerPixel: 32_isize
-            ];
-
-            if let Some(bitmap_rep) = bitmap_rep {
-                // Get the bitmap's internal buffer and copy data into it
-                let bitmap_data: *mut u8 = msg_send![&bitmap_rep, bitmapData];
-                if !bitmap_data.is_null() {
-                    // Convert BGRA (Wayland) to RGBA (macOS expects) directly into bitmap
-                    for y in 0..height {
-                        for x in 0..width {
-                            let src_offset = (y * stride + x * 4) as usize;
-                            let dst_offset = ((y * width + x) * 4) as usize;
-                            if src_offset + 3 < data.len() {
-                                // BGRA -> RGBA
-                                *bitmap_data.add(dst_offset) = data[src_offset + 2]; // R
-                                *bitmap_data.add(dst_offset + 1) = data[src_offset + 1]; // G
-                                *bitmap_data.add(dst_offset + 2) = data[src_offset]; // B
-                                *bitmap_data.add(dst_offset + 3) = data[src_offset + 3];
-                                // A
-                            }
-                        }
-                    }
-                }
-
-                // Create NSImage and add the bitmap rep
-                let size = CGSize::new(width as f64, height as f64);
-                let image: Retained<NSImage> =
-                    msg_send![self.mtm.alloc::<NSImage>(), initWithSize: size];
-                let _: () = msg_send![&image, addRepresentation: &*bitmap_rep];
-
-                // Set the image on the view
+        match bgra_image(self.mtm, data, width, height, stride) {
+            Some(image) => {
                 image_view.setImage(Some(&image));
-
                 debug!(
                     "Updated window {:?} buffer {}x{}",
                     self.window_id, width, height
                 );
-            } else {
-                debug!(
-                    "Failed to create bitmap rep for window {:?}",
-                    self.window_id
-                );
+            }
+            None => debug!(
+                "Failed to create bitmap rep for window {:?}",
+                self.window_id
+            ),
+        }
+    }
+
+    /// Draw any pending changes of the window now, instead of on the next
+    /// pass of the run loop
+    pub fn display_if_needed(&self) {
+        self.window.displayIfNeeded();
+    }
+
+    /// Set the image shown for the window when it's miniaturized
+    ///
+    /// The icon's own images are preferred, then its name is looked up in
+    /// the installed icon themes. The image is the window's miniaturized
+    /// image and its Dock tile; without one both go back to the default,
+    /// the window's content.
+    pub fn set_icon(&self, icon: Option<&WindowIcon>) {
+        let image = icon.and_then(|icon| {
+            let from_pixels = icon.image(ICON_SIZE).and_then(|image| {
+                bgra_image(
+                    self.mtm,
+                    &image.pixels,
+                    image.size,
+                    image.size,
+                    image.size * 4,
+                )
+            });
+            from_pixels.or_else(|| {
+                let path = find_icon(&icon_dirs(), icon.name.as_deref()?, ICON_SIZE)?;
+                let path = NSString::from_str(path.to_str()?);
+                NSImage::initWithContentsOfFile(self.mtm.alloc::<NSImage>(), &path)
+            })
+        });
+
+        self.window.setMiniwindowImage(image.as_deref());
+        let tile = self.window.dockTile();
+        match &image {
+            Some(image) => {
+                let view = NSImageView::imageViewWithImage(image, self.mtm);
+                tile.setContentView(Some(&view));
+            }
+            None => tile.setContentView(None),
+        }
+        tile.display();
+        debug!("Window {:?} icon set: {}", self.window_id, image.is_some());
+    }
+}
+
+/// Create an image from BGRA pixels
+fn bgra_image(
+    mtm: MainThreadMarker,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Option<Retained<NSImage>> {
+    unsafe {
+        // Create bitmap rep that allocates its own storage (pass NULL for planes)
+        let bitmap_rep: Option<Retained<NSBitmapImageRep>> = msg_send![
+            mtm.alloc::<NSBitmapImageRep>(),
+            initWithBitmapDataPlanes: std::ptr::null_mut::<*mut u8>(),
+            pixelsWide: width as isize,
+            pixelsHigh: height as isize,
+            bitsPerSample: 8_isize,
+            samplesPerPixel: 4_isize,
+            hasAlpha: true,
+            isPlanar: false,
+            colorSpaceName: objc2_app_kit::NSCalibratedRGBColorSpace,
+            bytesPerRow: (width * 4) as isize,
+            bitsPerPixel: 32_isize
+        ];
+        let bitmap_rep = bitmap_rep?;
+
+        // Get the bitmap's internal buffer and copy data into it
+        let bitmap_data: *mut u8 = msg_send![&bitmap_rep, bitmapData];
+        if !bitmap_data.is_null() {
+            // Convert BGRA (Wayland) to RGBA (macOS expects) directly into bitmap
+            for y in 0..height {
+                for x in 0..width {
+                    let src_offset = (y * stride + x * 4) as usize;
+                    let dst_offset = ((y * width + x) * 4) as usize;
+                    if src_offset + 3 < data.len() {
+                        // BGRA -> RGBA
+                        *bitmap_data.add(dst_offset) = data[src_offset + 2]; // R
+                        *bitmap_data.add(dst_offset + 1) = data[src_offset + 1]; // G
+                        *bitmap_data.add(dst_offset + 2) = data[src_offset]; // B
+                        *bitmap_data.add(dst_offset + 3) = data[src_offset + 3];
+                        // A
+                    }
+                }
             }
         }
+
+        // Create NSImage and add the bitmap rep
+        let size = CGSize::new(width as f64, height as f64);
+        let image: Retained<NSImage> = msg_send![mtm.alloc::<NSImage>(), initWithSize: size];
+        let _: () = msg_send![&image, addRepresentation: &*bitmap_rep];
+        Some(image)
     }
 }
 
+/// Check if activating an app switches to a Space with its windows
+///
+/// This is the "When switching to an application, switch to a Space with
+/// open windows for the application" setting, on unless turned off. The
+/// standard defaults search the global domain it lives in.
+fn switches_space_on_activate() -> bool {
+    let defaults = NSUserDefaults::standardUserDefaults();
+    let key = NSString::from_str("AppleSpacesSwitchOnActivate");
+    defaults.objectForKey(&key).is_none() || defaults.boolForKey(&key)
+}
+
+define_class!(
+    /// Content view of a window
+    ///
+    /// The app sends keys to the focused client before AppKit dispatches
+    /// them, so the view takes key events as first responder without
+    /// handling them. Otherwise NSWindow beeps for every key it gets.
+    #[unsafe(super(NSImageView))]
+    #[thread_kind = MainThreadOnly]
+    #[name = "WayoaContentView"]
+    struct WayoaContentView;
+
+    unsafe impl NSObjectProtocol for WayoaContentView {}
+
+    impl WayoaContentView {
+        #[unsafe(method(acceptsFirstResponder))]
+        fn accepts_first_responder(&self) -> bool {
+            true
+        }
+
+        #[unsafe(method(keyDown:))]
+        fn key_down(&self, _event: &NSEvent) {}
+
+        #[unsafe(method(keyUp:))]
+        fn key_up(&self, _event: &NSEvent) {}
+
+        #[unsafe(method(flagsChanged:))]
+        fn flags_changed(&self, _event: &NSEvent) {}
+    }
+);
+
 /// Window delegate ivars - stores the window ID for callback identification
 /// Note: In objc2, ivars are initialized via DeclaredClass::Ivars
 struct WayoaWindowDelegateIvars {
     // We store the raw u64 value since WindowId is Copy
     window_id_value: u64,
+    /// The close button was clicked, and the client wasn't asked yet
+    close_requested: Cell<bool>,
 }
 
 impl WayoaWindowDelegateIvars {
@@ -306,26 +711,33 @@ define_class!(
     unsafe impl NSWindowDelegate for WayoaWindowDelegate {
         #[unsafe(method(windowDidBecomeKey:))]
         fn window_did_become_key(&self, _notification: &NSNotification) {
+            // The app follows the key window and moves keyboard focus
             debug!("Window {:?} became key", self.ivars().window_id());
-            // TODO: Send keyboard enter event to Wayland client
         }
 
         #[unsafe(method(windowDidResignKey:))]
         fn window_did_resign_key(&self, _notification: &NSNotification) {
             debug!("Window {:?} resigned key", self.ivars().window_id());
-            // TODO: Send keyboard leave event to Wayland client
+        }
+
+        #[unsafe(method(windowShouldClose:))]
+        fn window_should_close(&self, _sender: &NSWindow) -> bool {
+            // The app asks the client, and the window closes once the
+            // client destroys its toplevel
+            debug!("Window {:?} close requested", self.ivars().window_id());
+            self.ivars().close_requested.set(true);
+            false
         }
 
         #[unsafe(method(windowWillClose:))]
         fn window_will_close(&self, _notification: &NSNotification) {
             debug!("Window {:?} will close", self.ivars().window_id());
-            // TODO: Send close request to Wayland client
         }
 
         #[unsafe(method(windowDidResize:))]
         fn window_did_resize(&self, _notification: &NSNotification) {
+            // The app follows content sizes and configures the toplevel
             debug!("Window {:?} did resize", self.ivars().window_id());
-            // TODO: Send configure event to Wayland client
         }
 
         #[unsafe(method(windowDidMove:))]
@@ -359,6 +771,7 @@ impl WayoaWindowDelegate {
     fn new(mtm: MainThreadMarker, window_id: WindowId) -> Retained<Self> {
         let this = mtm.alloc::<Self>().set_ivars(WayoaWindowDelegateIvars {
             window_id_value: window_id.0,
+            close_requested: Cell::new(false),
         });
         let this: Option<Retained<Self>> = unsafe { msg_send![super(this), init] };
         this.expect("init failed")
@@ -3,9 +3,13 @@
 //! This module contains platform-specific backends:
 //! - Cocoa backend for macOS (NSWindow, Metal rendering)
 //! - Event loop integration with calloop
+//!
+//! plus the state the server keeps for presenting on the platform.
 
 #[cfg(target_os = "macos")]
 pub mod cocoa;
 pub mod event_loop;
+pub mod state;
 
 pub use event_loop::EventLoop;
+pub use state::BackendState;
@@ -0,0 +1,72 @@
+//! Backend adapter state
+//!
+//! What the server keeps to show the compositor's state on the platform:
+//! native windows, content being uploaded for them and power assertions.
+//! Only the presentation code in the server touches it, so the protocol
+//! layer and the compositor core don't depend on a platform.
+//!
+//! Native windows are private to the adapter. The server looks them up,
+//! creates and closes them through the methods here, keyed by the
+//! compositor's window ids, and never holds on to the map itself.
+
+#[cfg(target_os = "macos")]
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+#[cfg(target_os = "macos")]
+use super::cocoa::window::WayoaWindow;
+use crate::compositor::WindowId;
+use crate::renderer::UploadPool;
+use crate::util::power::SleepAssertion;
+
+/// Native windows and presentation
+#[derive(Default)]
+pub struct BackendState {
+    /// Windows with committed content waiting for the next frame
+    pub pending_presents: HashSet<WindowId>,
+    /// Window content being copied and converted on worker threads
+    pub uploads: UploadPool,
+    /// Display sleep assertion, held while an idle inhibitor is active
+    pub sleep_assertion: Option<SleepAssertion>,
+    /// Draw diagnostic overlays over window content
+    pub diagnostics: bool,
+    /// Main thread marker (for creating native windows)
+    #[cfg(target_os = "macos")]
+    pub mtm: Option<objc2_foundation::MainThreadMarker>,
+    /// Native windows
+    #[cfg(target_os = "macos")]
+    native_windows: HashMap<WindowId, WayoaWindow>,
+}
+
+#[cfg(target_os = "macos")]
+impl BackendState {
+    /// Get the native window showing a window
+    pub fn native_window(&self, id: WindowId) -> Option<&WayoaWindow> {
+        self.native_windows.get(&id)
+    }
+
+    /// Check if a window is shown in a native window
+    pub fn has_native_window(&self, id: WindowId) -> bool {
+        self.native_windows.contains_key(&id)
+    }
+
+    /// Iterate over the native windows and the windows they show
+    pub fn native_windows(&self) -> impl Iterator<Item = (WindowId, &WayoaWindow)> {
+        self.native_windows.iter().map(|(&id, native)| (id, native))
+    }
+
+    /// Get the windows shown in native windows
+    pub fn native_window_ids(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.native_windows.keys().copied()
+    }
+
+    /// Show a window in a newly created native window
+    pub fn insert_native_window(&mut self, id: WindowId, native: WayoaWindow) {
+        self.native_windows.insert(id, native);
+    }
+
+    /// Take the native window of a window, for the caller to close
+    pub fn remove_native_window(&mut self, id: WindowId) -> Option<WayoaWindow> {
+        self.native_windows.remove(&id)
+    }
+}
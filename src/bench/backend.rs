@@ -0,0 +1,158 @@
+//! Backends the benchmark presents to
+//!
+//! Both backends get the same converted pixels from the upload pool. The
+//! headless backend drops them, so its numbers are the compositor's own
+//! cost, while the Cocoa backend draws them into a native window per
+//! client window, adding what AppKit takes to show them.
+
+use std::fmt;
+
+use super::BenchError;
+use crate::compositor::WindowId;
+use crate::renderer::Upload;
+
+/// Where benchmark frames are presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// No display, uploads are dropped
+    Headless,
+    /// Native windows, macOS only
+    Cocoa,
+}
+
+impl BackendKind {
+    /// Every backend
+    pub const ALL: [BackendKind; 2] = [BackendKind::Headless, BackendKind::Cocoa];
+
+    /// Name of the backend in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            BackendKind::Headless => "headless",
+            BackendKind::Cocoa => "cocoa",
+        }
+    }
+
+    /// Parse a backend name
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backend| backend.name() == name)
+    }
+
+    /// Check if the backend can run on this system
+    pub fn is_available(self) -> bool {
+        match self {
+            BackendKind::Headless => true,
+            BackendKind::Cocoa => cfg!(target_os = "macos"),
+        }
+    }
+
+    /// Start presenting to the backend
+    pub(super) fn presenter(self) -> Result<Box<dyn Presenter>, BenchError> {
+        match self {
+            BackendKind::Headless => Ok(Box::new(Headless)),
+            #[cfg(target_os = "macos")]
+            BackendKind::Cocoa => Ok(Box::new(cocoa::CocoaPresenter::new()?)),
+            #[cfg(not(target_os = "macos"))]
+            BackendKind::Cocoa => Err(BenchError::Unavailable(self)),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Shows the frames of scripted windows
+pub(super) trait Presenter {
+    /// A window was mapped
+    fn open(&mut self, window: WindowId, width: u32, height: u32) -> Result<(), BenchError>;
+
+    /// A window was unmapped
+    fn close(&mut self, window: WindowId);
+
+    /// Show a window's converted content
+    fn present(&mut self, upload: &Upload);
+}
+
+/// Presenter without a display
+struct Headless;
+
+impl Presenter for Headless {
+    fn open(&mut self, _window: WindowId, _width: u32, _height: u32) -> Result<(), BenchError> {
+        Ok(())
+    }
+
+    fn close(&mut self, _window: WindowId) {}
+
+    fn present(&mut self, upload: &Upload) {
+        std::hint::black_box(&upload.data);
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod cocoa {
+    use std::collections::HashMap;
+
+    use objc2_app_kit::NSApplication;
+    use objc2_foundation::MainThreadMarker;
+
+    use super::{BenchError, Presenter};
+    use crate::backend::cocoa::window::WayoaWindow;
+    use crate::compositor::WindowId;
+    use crate::renderer::Upload;
+
+    /// Presenter drawing into native windows
+    pub struct CocoaPresenter {
+        mtm: MainThreadMarker,
+        windows: HashMap<WindowId, WayoaWindow>,
+    }
+
+    impl CocoaPresenter {
+        /// Set up the application, which must happen on the main thread
+        pub fn new() -> Result<Self, BenchError> {
+            let mtm = MainThreadMarker::new().ok_or(BenchError::NotMainThread)?;
+            let _app = NSApplication::sharedApplication(mtm);
+            Ok(Self {
+                mtm,
+                windows: HashMap::new(),
+            })
+        }
+    }
+
+    impl Presenter for CocoaPresenter {
+        fn open(&mut self, window: WindowId, width: u32, height: u32) -> Result<(), BenchError> {
+            let native = WayoaWindow::new(self.mtm, window, width, height, "wayoa bench")
+                .map_err(|e| BenchError::Window(e.to_string()))?;
+            native.show();
+            self.windows.insert(window, native);
+            Ok(())
+        }
+
+        fn close(&mut self, window: WindowId) {
+            if let Some(native) = self.windows.remove(&window) {
+                native.close();
+            }
+        }
+
+        fn present(&mut self, upload: &Upload) {
+            let Some(native) = self.windows.get(&upload.window) else {
+                return;
+            };
+            if native.content_size() != (upload.width, upload.height) {
+                native.set_size(upload.width, upload.height);
+            }
+            native.update_buffer(&upload.data, upload.width, upload.height, upload.stride);
+            // Draw now, so the frame counts what AppKit takes to show it
+            native.display_if_needed();
+        }
+    }
+
+    impl Drop for CocoaPresenter {
+        fn drop(&mut self) {
+            for (_, native) in self.windows.drain() {
+                native.close();
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Present latency benchmarks
+//!
+//! Scripted workloads commit content to a server state paced by a virtual
+//! output, the way the main loop paces it by the primary display, and
+//! measure each commit until its converted pixels reach the backend. Run
+//! them with `cargo bench --bench present`, which writes one JSON report
+//! per workload and backend, and compares them with the reports of an
+//! earlier run if given one, so performance changes can show before and
+//! after numbers.
+
+pub mod backend;
+pub mod report;
+pub mod workload;
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::compositor::WindowId;
+use crate::renderer::UploadPool;
+use crate::server::ServerState;
+pub use backend::BackendKind;
+pub use report::{compare, Comparison, LatencySummary, Report};
+pub use workload::Workload;
+
+/// How a benchmark runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchOptions {
+    /// Frames committed by each workload
+    pub frames: u32,
+    /// Refresh rate of the virtual output (mHz)
+    pub refresh_mhz: u32,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            frames: 300,
+            refresh_mhz: 60_000,
+        }
+    }
+}
+
+/// Run a workload on a backend
+pub fn run(
+    workload: Workload,
+    backend: BackendKind,
+    options: &BenchOptions,
+) -> Result<Report, BenchError> {
+    let mut presenter = backend.presenter()?;
+    let mut state = ServerState::new();
+    let output = state.compositor.outputs.create_headless(
+        "bench-0".to_string(),
+        3840,
+        2160,
+        1,
+        options.refresh_mhz,
+    );
+    state.compositor.outputs.set_primary(output);
+    let mut uploads = UploadPool::new();
+    let mut script = workload::Script::start(workload, &mut state, presenter.as_mut())?;
+
+    let mut committed: HashMap<WindowId, Instant> = HashMap::new();
+    let mut samples = Vec::new();
+    let mut dropped = 0;
+    let started = Instant::now();
+    for frame in 0..options.frames {
+        for (job, at) in script.frame(frame, &mut state, presenter.as_mut())? {
+            if committed.insert(job.window, at).is_some() {
+                dropped += 1;
+            }
+            uploads.submit(job);
+        }
+
+        // Sleep until the frame deadline, as the main loop's timer would
+        if let Some(deadline) = state.scheduler.next_deadline() {
+            std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        }
+        state.compose_due(Instant::now());
+        for upload in uploads.finish() {
+            presenter.present(&upload);
+            if let Some(at) = committed.remove(&upload.window) {
+                samples.push(at.elapsed());
+            }
+        }
+    }
+
+    Ok(Report {
+        workload: workload.name().to_string(),
+        backend: backend.name().to_string(),
+        frames: options.frames as u64,
+        dropped,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        latency: LatencySummary::from_samples(&samples),
+    })
+}
+
+/// Benchmark errors
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    #[error("The {0} backend isn't available on this system")]
+    Unavailable(BackendKind),
+    #[error("The Cocoa backend must run on the main thread")]
+    NotMainThread,
+    #[error("Failed to create a window: {0}")]
+    Window(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_headless() {
+        let options = BenchOptions {
+            frames: 4,
+            refresh_mhz: 500_000,
+        };
+        for workload in [Workload::ResizeStorm, Workload::WindowChurn] {
+            let report = run(workload, BackendKind::Headless, &options).unwrap();
+            assert_eq!(report.workload, workload.name());
+            assert_eq!(report.frames, 4);
+            assert_eq!(report.latency.samples + report.dropped, 4);
+        }
+    }
+}
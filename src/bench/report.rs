@@ -0,0 +1,151 @@
+//! Benchmark reports
+//!
+//! A report summarizes the commit-to-present latency of one workload on one
+//! backend. Reports are written as JSON, and a run can be compared with the
+//! reports of an earlier one to show the change of each workload.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Percentiles of the commit-to-present latency, in microseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    /// Frames measured
+    pub samples: u64,
+    pub min_us: u64,
+    pub mean_us: u64,
+    pub p50_us: u64,
+    pub p95_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl LatencySummary {
+    /// Summarize measured latencies
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        let mut micros: Vec<u64> = samples.iter().map(|s| s.as_micros() as u64).collect();
+        micros.sort_unstable();
+        let Some(&max_us) = micros.last() else {
+            return Self::default();
+        };
+        // Nearest-rank percentile
+        let percentile = |p: usize| micros[(micros.len() * p).div_ceil(100).max(1) - 1];
+        Self {
+            samples: micros.len() as u64,
+            min_us: micros[0],
+            mean_us: micros.iter().sum::<u64>() / micros.len() as u64,
+            p50_us: percentile(50),
+            p95_us: percentile(95),
+            p99_us: percentile(99),
+            max_us,
+        }
+    }
+}
+
+/// Result of one workload on one backend
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// Name of the workload
+    pub workload: String,
+    /// Name of the backend
+    pub backend: String,
+    /// Frames the workload committed
+    pub frames: u64,
+    /// Commits replaced by a newer one before they were presented
+    pub dropped: u64,
+    /// Wall time of the run (milliseconds)
+    pub elapsed_ms: u64,
+    /// Latency of the presented commits
+    pub latency: LatencySummary,
+}
+
+/// Change of a workload's latency against a baseline, in percent
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Comparison {
+    pub workload: String,
+    pub backend: String,
+    pub mean_change: f64,
+    pub p50_change: f64,
+    pub p95_change: f64,
+    pub p99_change: f64,
+}
+
+impl Comparison {
+    /// Check if the median or the tail got slower by more than `threshold`
+    /// percent
+    pub fn regressed(&self, threshold: f64) -> bool {
+        self.p50_change > threshold || self.p95_change > threshold
+    }
+}
+
+/// Compare reports with the baseline of the same workload and backend
+///
+/// Reports without a baseline are left out.
+pub fn compare(baseline: &[Report], current: &[Report]) -> Vec<Comparison> {
+    let change = |before: u64, after: u64| match before {
+        0 => 0.0,
+        before => (after as f64 - before as f64) * 100.0 / before as f64,
+    };
+    current
+        .iter()
+        .filter_map(|report| {
+            let before = baseline
+                .iter()
+                .find(|b| b.workload == report.workload && b.backend == report.backend)?;
+            let (before, after) = (before.latency, report.latency);
+            Some(Comparison {
+                workload: report.workload.clone(),
+                backend: report.backend.clone(),
+                mean_change: change(before.mean_us, after.mean_us),
+                p50_change: change(before.p50_us, after.p50_us),
+                p95_change: change(before.p95_us, after.p95_us),
+                p99_change: change(before.p99_us, after.p99_us),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_summary() {
+        let samples: Vec<Duration> = (1..=100).map(Duration::from_micros).collect();
+        let summary = LatencySummary::from_samples(&samples);
+        assert_eq!(summary.samples, 100);
+        assert_eq!((summary.min_us, summary.max_us), (1, 100));
+        assert_eq!(summary.mean_us, 50);
+        assert_eq!(
+            (summary.p50_us, summary.p95_us, summary.p99_us),
+            (50, 95, 99)
+        );
+        assert_eq!(LatencySummary::from_samples(&[]), LatencySummary::default());
+    }
+
+    #[test]
+    fn test_compare() {
+        let report = |backend: &str, p50_us| Report {
+            workload: "video-blit".to_string(),
+            backend: backend.to_string(),
+            frames: 10,
+            dropped: 0,
+            elapsed_ms: 100,
+            latency: LatencySummary {
+                p50_us,
+                p95_us: 2000,
+                ..Default::default()
+            },
+        };
+        let comparisons = compare(
+            &[report("headless", 1000)],
+            &[report("headless", 1500), report("cocoa", 1000)],
+        );
+        assert_eq!(comparisons.len(), 1);
+        assert_eq!(comparisons[0].p50_change, 50.0);
+        assert_eq!(comparisons[0].p95_change, 0.0);
+        assert!(comparisons[0].regressed(10.0));
+        assert!(!comparisons[0].regressed(60.0));
+    }
+}
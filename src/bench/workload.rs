@@ -0,0 +1,238 @@
+//! Scripted client workloads
+//!
+//! A workload stands in for clients: it maps windows and commits new shm
+//! content to them each frame, the way a client would after its frame
+//! callback. Pixels are filled before the commit is timed, so the client's
+//! own drawing isn't counted.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::backend::Presenter;
+use super::BenchError;
+use crate::compositor::surface::BufferInfo;
+use crate::compositor::{SurfaceId, WindowId};
+use crate::protocol::shm::ShmFormat;
+use crate::renderer::{Pixels, UploadJob};
+use crate::server::ServerState;
+
+/// Windows kept mapped by the churn workload
+const CHURN_WINDOWS: usize = 100;
+
+/// A scripted client workload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// One window resized on every frame
+    ResizeStorm,
+    /// A window unmapped and a new one mapped on every frame, out of 100
+    WindowChurn,
+    /// One 4K window redrawn on every frame
+    VideoBlit,
+}
+
+impl Workload {
+    /// Every workload
+    pub const ALL: [Workload; 3] = [
+        Workload::ResizeStorm,
+        Workload::WindowChurn,
+        Workload::VideoBlit,
+    ];
+
+    /// Name of the workload in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Workload::ResizeStorm => "resize-storm",
+            Workload::WindowChurn => "window-churn",
+            Workload::VideoBlit => "video-blit",
+        }
+    }
+
+    /// Parse a workload name
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|workload| workload.name() == name)
+    }
+
+    /// Size of a window's content at a frame
+    fn size(self, frame: u32) -> (u32, u32) {
+        match self {
+            // Step through sizes between 640x480 and 1920x1080
+            Workload::ResizeStorm => (640 + frame * 37 % 1280, 480 + frame * 23 % 600),
+            Workload::WindowChurn => (256, 256),
+            Workload::VideoBlit => (3840, 2160),
+        }
+    }
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A mapped window of the scripted client
+struct ScriptedWindow {
+    surface: SurfaceId,
+    window: WindowId,
+    width: u32,
+    height: u32,
+    /// The window's shm buffer, XRGB8888
+    pixels: Arc<Vec<u8>>,
+}
+
+impl ScriptedWindow {
+    /// Draw a frame into the buffer, reallocating it if the size changed
+    fn draw(&mut self, width: u32, height: u32, frame: u32) {
+        let len = (width * height * 4) as usize;
+        match Arc::get_mut(&mut self.pixels) {
+            Some(pixels) if pixels.len() == len => pixels.fill(frame as u8),
+            _ => self.pixels = Arc::new(vec![frame as u8; len]),
+        }
+        self.width = width;
+        self.height = height;
+    }
+
+    /// The buffer's content as an upload
+    fn upload_job(&self) -> UploadJob {
+        UploadJob {
+            window: self.window,
+            pixels: Pixels::Shm {
+                memory: self.pixels.clone(),
+                range: 0..self.pixels.len(),
+                format: ShmFormat::Xrgb8888,
+                stride: self.width * 4,
+            },
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// A workload running against a server
+pub(super) struct Script {
+    workload: Workload,
+    windows: Vec<ScriptedWindow>,
+}
+
+impl Script {
+    /// Map the workload's first windows
+    pub fn start(
+        workload: Workload,
+        state: &mut ServerState,
+        presenter: &mut dyn Presenter,
+    ) -> Result<Self, BenchError> {
+        let mut script = Self {
+            workload,
+            windows: Vec::new(),
+        };
+        let count = match workload {
+            Workload::WindowChurn => CHURN_WINDOWS,
+            _ => 1,
+        };
+        for _ in 0..count {
+            script.map(state, presenter)?;
+        }
+        Ok(script)
+    }
+
+    /// Run a frame of the workload, returning the commits made and when
+    pub fn frame(
+        &mut self,
+        frame: u32,
+        state: &mut ServerState,
+        presenter: &mut dyn Presenter,
+    ) -> Result<Vec<(UploadJob, Instant)>, BenchError> {
+        if self.workload == Workload::WindowChurn {
+            let oldest = self.windows.remove(0);
+            self.unmap(oldest, state, presenter);
+            self.map(state, presenter)?;
+            // Only the new window has content to commit
+            let (width, height) = self.workload.size(frame);
+            let last = self.windows.len() - 1;
+            return Ok(vec![self.commit(last, width, height, frame, state)]);
+        }
+        let (width, height) = self.workload.size(frame);
+        Ok(vec![self.commit(0, width, height, frame, state)])
+    }
+
+    /// Map a new window
+    fn map(
+        &mut self,
+        state: &mut ServerState,
+        presenter: &mut dyn Presenter,
+    ) -> Result<(), BenchError> {
+        let (width, height) = self.workload.size(0);
+        let surface = state.compositor.surfaces.create_surface();
+        let window = state.compositor.windows.create_window(surface);
+        presenter.open(window, width, height)?;
+        self.windows.push(ScriptedWindow {
+            surface,
+            window,
+            width,
+            height,
+            pixels: Arc::new(Vec::new()),
+        });
+        Ok(())
+    }
+
+    /// Unmap a window and forget it
+    fn unmap(
+        &mut self,
+        window: ScriptedWindow,
+        state: &mut ServerState,
+        presenter: &mut dyn Presenter,
+    ) {
+        presenter.close(window.window);
+        state.backend.pending_presents.remove(&window.window);
+        state.scheduler.remove_window(window.window);
+        state.compositor.windows.remove(window.window);
+        state.compositor.surfaces.remove(window.surface);
+    }
+
+    /// Draw and commit a window's next frame, and queue it for presenting
+    fn commit(
+        &mut self,
+        index: usize,
+        width: u32,
+        height: u32,
+        frame: u32,
+        state: &mut ServerState,
+    ) -> (UploadJob, Instant) {
+        let window = &mut self.windows[index];
+        window.draw(width, height, frame);
+
+        let committed = Instant::now();
+        if let Some(surface) = state.compositor.surfaces.get_mut(window.surface) {
+            surface.attach(Some(BufferInfo {
+                width,
+                height,
+                stride: width * 4,
+                format: ShmFormat::Xrgb8888.to_wayland(),
+                offset: 0,
+                shm_buffer_id: None,
+                solid: None,
+                snapshot: None,
+            }));
+        }
+        state.compositor.surfaces.commit(window.surface);
+        state.schedule_present(window.window);
+        (window.upload_job(), committed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workload_names() {
+        for workload in Workload::ALL {
+            assert_eq!(Workload::parse(workload.name()), Some(workload));
+        }
+        assert_eq!(Workload::parse("idle"), None);
+        assert_eq!(Workload::ResizeStorm.size(0), (640, 480));
+        assert_ne!(Workload::ResizeStorm.size(1), Workload::ResizeStorm.size(0));
+    }
+}
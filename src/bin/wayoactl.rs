@@ -0,0 +1,112 @@
+//! wayoactl - control a running Wayoa compositor
+//!
+//! Sends its arguments as a single command to the compositor's IPC socket
+//! and prints the JSON result. A handshake first checks that the running
+//! compositor knows the command, and notes when it's a different version
+//! than this wayoactl, as after an update that wasn't followed by a restart.
+//!
+//! ```text
+//! wayoactl inhibitors
+//! wayoactl inhibitors ignore org.example.App
+//! wayoactl screenshot --window focused shot.png
+//! ```
+
+use std::process::ExitCode;
+
+use wayoa::ipc;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "--help" || args[0] == "-h" {
+        eprintln!("Usage: wayoactl <command> [args...]");
+        eprintln!();
+        eprintln!("Commands:");
+        eprintln!(
+            "  features                       List protocols, backends and experimental flags"
+        );
+        eprintln!("  inhibitors                     List idle inhibitors");
+        eprintln!("  inhibitors ignore <app_id>     Ignore idle inhibitors from app_id");
+        eprintln!("  inhibitors unignore <app_id>   Honor idle inhibitors from app_id");
+        eprintln!("  clients                        Show client limits and rejections");
+        eprintln!(
+            "  frames                         Show missed frames per output and window latency"
+        );
+        eprintln!("  resources                      Show surfaces and regions per client");
+        eprintln!("  activate <window-id>           Raise, restore and focus a window");
+        eprintln!("  attention                      List windows needing attention");
+        eprintln!("  journal                        Show uptime and the session journal");
+        eprintln!("  quit                           Save the session and quit");
+        eprintln!("  screenshot --window <id|focused> [--cursor] <path>");
+        eprintln!("  screenshot --output <name> [--cursor] <path>");
+        eprintln!("                                 Save a PNG of a window or headless output");
+        eprintln!("  preview                        Open the focused window in QuickLook");
+        eprintln!("  proxy-socket <tag>             Get the socket for clients tagged <tag>");
+        eprintln!("  zoom <in|out|reset|factor> [window-id]");
+        eprintln!("                                 Magnify a window's content, the focused one by default");
+        eprintln!(
+            "  diagnostics [on|off]           Toggle damage, geometry and input region overlays"
+        );
+        eprintln!("  touch [on|off]                 Toggle trackpad touchscreen emulation");
+        return ExitCode::FAILURE;
+    }
+
+    // The compositor writes screenshots itself, from its own working directory
+    let mut args = args;
+    if args[0] == "screenshot" {
+        if let Some(path) = args.last_mut().filter(|arg| !arg.starts_with("--")) {
+            if let Ok(absolute) = std::path::absolute(&*path) {
+                *path = absolute.to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    let socket = ipc::socket_from_env();
+    match ipc::handshake(&socket) {
+        Ok(Some(handshake)) => {
+            if handshake.is_outdated() {
+                eprintln!(
+                    "wayoactl: wayoa {} is running, restart it to use {}",
+                    handshake.version,
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            if !handshake.supports(&args[0]) {
+                eprintln!(
+                    "wayoactl: the running wayoa {} doesn't support {}",
+                    handshake.version, args[0]
+                );
+                return ExitCode::FAILURE;
+            }
+        }
+        // Compositors from before the handshake answer commands all the same
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("wayoactl: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match ipc::request(&socket, &args.join(" ")) {
+        Ok(response) if response.ok => {
+            let data = response.data.unwrap_or_default();
+            match serde_json::to_string_pretty(&data) {
+                Ok(text) => println!("{}", text),
+                Err(_) => println!("{}", data),
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(response) => {
+            eprintln!(
+                "wayoactl: {}",
+                response
+                    .error
+                    .unwrap_or_else(|| "command failed".to_string())
+            );
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("wayoactl: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Compositor actions
+//!
+//! Actions are things the compositor itself can do in response to user
+//! input that is not forwarded to clients (hot corners, keybindings, IPC).
+
+use serde::Deserialize;
+
+/// An action performed by the compositor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompositorAction {
+    /// Show the window overview
+    ShowOverview,
+    /// Lock the session
+    LockSession,
+    /// Open the window switcher and launcher
+    ShowLauncher,
+    /// Open the focused window's content in QuickLook
+    PreviewWindow,
+    /// Magnify the focused window's content by one step
+    ZoomIn,
+    /// Shrink the focused window's content by one step
+    ZoomOut,
+    /// Show the focused window's content at its size
+    ZoomReset,
+    /// Focus the window that was focused before the current one
+    FocusPrevious,
+    /// Show or hide the diagnostic overlays over window content
+    ToggleDiagnostics,
+    /// Turn trackpad touchscreen emulation on or off
+    ToggleTouchEmulation,
+}
+
+impl CompositorAction {
+    /// Get the action name as used in configuration and IPC
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompositorAction::ShowOverview => "show-overview",
+            CompositorAction::LockSession => "lock-session",
+            CompositorAction::ShowLauncher => "show-launcher",
+            CompositorAction::PreviewWindow => "preview-window",
+            CompositorAction::ZoomIn => "zoom-in",
+            CompositorAction::ZoomOut => "zoom-out",
+            CompositorAction::ZoomReset => "zoom-reset",
+            CompositorAction::FocusPrevious => "focus-previous",
+            CompositorAction::ToggleDiagnostics => "toggle-diagnostics",
+            CompositorAction::ToggleTouchEmulation => "toggle-touch-emulation",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_name() {
+        assert_eq!(CompositorAction::ShowOverview.name(), "show-overview");
+        assert_eq!(CompositorAction::LockSession.name(), "lock-session");
+    }
+}
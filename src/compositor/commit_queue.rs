@@ -0,0 +1,176 @@
+//! Commit queue
+//!
+//! Some commits aren't applied right away, but held on their surface until
+//! a display refresh allows it:
+//! - With wp_fifo_v1 a commit can set a barrier on its surface once it is
+//!   applied, and a later commit can wait for that barrier to clear. The
+//!   barrier clears at the next latching deadline, the refresh the content
+//!   is shown at, so each such commit stays on screen for at least one
+//!   refresh.
+//! - With wp_commit_timing_v1 a commit carries a target time, and is held
+//!   until the refresh it would be shown at is no earlier than that time.
+//!
+//! Commits after a held one are queued behind it and applied in order.
+//! Synchronized subsurfaces ignore both constraints, as their commits are
+//! already applied with their parent's.
+
+use std::time::Duration;
+
+use super::surface::{SurfaceId, SurfaceManager, SurfacePendingState};
+
+impl SurfacePendingState {
+    /// Check if the state can be applied for a refresh at `refresh`, with
+    /// the surface's fifo barrier set or not
+    fn is_ready(&self, barrier: bool, refresh: Duration) -> bool {
+        let fifo = !(self.fifo_wait && barrier);
+        let timing = self.target_time.is_none_or(|time| time <= refresh);
+        fifo && timing
+    }
+}
+
+impl SurfaceManager {
+    /// Queue the pending state of a surface if it waits on the surface's
+    /// fifo barrier or has a target time, or if earlier commits are queued
+    ///
+    /// Returns true if the state was queued rather than left to apply.
+    pub(super) fn queue_commit(&mut self, surface: SurfaceId) -> bool {
+        let Some(current) = self.get_mut(surface) else {
+            return false;
+        };
+        let blocked = current.pending.fifo_wait && current.fifo_barrier;
+        let timed = current.pending.target_time.is_some();
+        if !blocked && !timed && current.commit_queue.is_empty() {
+            return false;
+        }
+        let pending = std::mem::take(&mut current.pending);
+        current.commit_queue.push_back(pending);
+        true
+    }
+
+    /// Check if a surface has queued commits
+    pub fn has_commit_queue(&self, surface: SurfaceId) -> bool {
+        self.get(surface)
+            .is_some_and(|surface| !surface.commit_queue.is_empty())
+    }
+
+    /// Check if any queued commit waits for its target time
+    pub fn has_timed_commits(&self) -> bool {
+        self.iter().any(|(_, surface)| {
+            surface
+                .commit_queue
+                .iter()
+                .any(|state| state.target_time.is_some())
+        })
+    }
+
+    /// Clear every fifo barrier at a latching deadline, then apply the
+    /// queued commits that can be shown at the next refresh, at `refresh`
+    ///
+    /// A queued commit that sets a new barrier holds back the ones after it
+    /// that wait, until the next deadline. Returns the surfaces whose state
+    /// was applied.
+    pub fn release_commits(&mut self, refresh: Duration) -> Vec<SurfaceId> {
+        let blocked: Vec<SurfaceId> = self
+            .iter()
+            .filter(|(_, surface)| surface.fifo_barrier || !surface.commit_queue.is_empty())
+            .map(|(&id, _)| id)
+            .collect();
+        let mut applied = Vec::new();
+        for id in blocked {
+            if let Some(surface) = self.get_mut(id) {
+                surface.fifo_barrier = false;
+            }
+            while let Some(surface) = self.get_mut(id) {
+                match surface.commit_queue.front() {
+                    Some(next) if next.is_ready(surface.fifo_barrier, refresh) => {}
+                    _ => break,
+                }
+                // State the client hasn't committed yet is put back after
+                let queued = surface.commit_queue.pop_front().unwrap_or_default();
+                let uncommitted = std::mem::replace(&mut surface.pending, queued);
+                self.apply(id, &mut applied);
+                if let Some(surface) = self.get_mut(id) {
+                    surface.pending = uncommitted;
+                }
+            }
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commit a new scale with fifo requests
+    fn commit(surfaces: &mut SurfaceManager, id: SurfaceId, scale: i32, barrier: bool, wait: bool) {
+        let surface = surfaces.get_mut(id).unwrap();
+        surface.set_scale(scale);
+        surface.pending.fifo_barrier = barrier;
+        surface.pending.fifo_wait = wait;
+        surfaces.commit(id);
+    }
+
+    #[test]
+    fn test_commits_wait_for_barrier() {
+        let mut surfaces = SurfaceManager::new();
+        let id = surfaces.create_surface();
+
+        // The first commit sets the barrier, the next two wait on it
+        commit(&mut surfaces, id, 2, true, true);
+        commit(&mut surfaces, id, 3, true, true);
+        commit(&mut surfaces, id, 4, true, true);
+        assert_eq!(surfaces.get(id).unwrap().scale, 2);
+        assert!(surfaces.has_commit_queue(id));
+
+        // One commit per deadline
+        assert_eq!(surfaces.release_commits(Duration::ZERO), vec![id]);
+        assert_eq!(surfaces.get(id).unwrap().scale, 3);
+        assert_eq!(surfaces.release_commits(Duration::ZERO), vec![id]);
+        assert_eq!(surfaces.get(id).unwrap().scale, 4);
+        assert!(!surfaces.has_commit_queue(id));
+        assert!(surfaces.release_commits(Duration::ZERO).is_empty());
+
+        // Without waiting, commits apply right away
+        commit(&mut surfaces, id, 5, true, false);
+        assert_eq!(surfaces.get(id).unwrap().scale, 5);
+    }
+
+    #[test]
+    fn test_uncommitted_state_kept() {
+        let mut surfaces = SurfaceManager::new();
+        let id = surfaces.create_surface();
+        commit(&mut surfaces, id, 2, true, false);
+        commit(&mut surfaces, id, 3, false, true);
+
+        // A commit after a queued one queues too, even if it doesn't wait
+        commit(&mut surfaces, id, 4, false, false);
+        surfaces.get_mut(id).unwrap().set_transform(1);
+        assert_eq!(surfaces.release_commits(Duration::ZERO), vec![id, id]);
+        let surface = surfaces.get(id).unwrap();
+        assert_eq!((surface.scale, surface.transform), (4, 0));
+        assert_eq!(surface.pending.transform, Some(1));
+    }
+
+    #[test]
+    fn test_commits_wait_for_target_time() {
+        let mut surfaces = SurfaceManager::new();
+        let id = surfaces.create_surface();
+        let surface = surfaces.get_mut(id).unwrap();
+        surface.set_scale(2);
+        surface.pending.target_time = Some(Duration::from_millis(100));
+        assert!(surfaces.commit(id).is_empty());
+        assert!(surfaces.has_timed_commits());
+
+        // Held while the next refresh is too early
+        assert!(surfaces
+            .release_commits(Duration::from_millis(90))
+            .is_empty());
+        assert_eq!(
+            surfaces.release_commits(Duration::from_millis(100)),
+            vec![id]
+        );
+        assert_eq!(surfaces.get(id).unwrap().scale, 2);
+        assert!(!surfaces.has_timed_commits());
+    }
+}
@@ -0,0 +1,381 @@
+//! Damage coordinate transforms
+//!
+//! wl_surface.damage is given in surface-local coordinates, while
+//! wl_surface.damage_buffer is given in buffer coordinates. On commit,
+//! surface damage is converted to buffer coordinates by undoing the viewport,
+//! the buffer scale and the buffer transform, so texture uploads only ever
+//! deal with buffer rectangles. The same geometry gives the size of the
+//! surface and the part of the buffer to sample when drawing it.
+
+use super::output::OutputTransform;
+use super::surface::DamageRect;
+
+/// Source rectangle of a viewport, in buffer coordinates after the buffer
+/// transform and scale are applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportSource {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Cropping and scaling of a surface (wp_viewport)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Viewport {
+    /// Part of the buffer to show
+    pub source: Option<ViewportSource>,
+    /// Size of the surface
+    pub destination: Option<(i32, i32)>,
+}
+
+/// Viewport state that doesn't fit the attached buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ViewportError {
+    #[error("Source size is not integer and no destination size is set")]
+    BadSize,
+    #[error("Source rectangle extends outside of the buffer")]
+    OutOfBuffer,
+}
+
+/// How a buffer is mapped onto its surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferGeometry {
+    /// Buffer width in pixels
+    pub width: i32,
+    /// Buffer height in pixels
+    pub height: i32,
+    /// Buffer scale
+    pub scale: i32,
+    /// Buffer transform
+    pub transform: OutputTransform,
+    /// Viewport cropping and scaling
+    pub viewport: Viewport,
+}
+
+impl BufferGeometry {
+    /// Buffer size with the buffer transform applied
+    fn transformed_size(&self) -> (i32, i32) {
+        match self.transform.swaps_axes() {
+            true => (self.height, self.width),
+            false => (self.width, self.height),
+        }
+    }
+
+    /// Size of the viewport source, in transformed and scaled buffer units
+    fn source_size(&self) -> (f64, f64) {
+        match self.viewport.source {
+            Some(source) => (source.width, source.height),
+            None => {
+                let (width, height) = self.transformed_size();
+                let scale = self.scale.max(1) as f64;
+                (width as f64 / scale, height as f64 / scale)
+            }
+        }
+    }
+
+    /// Size of the surface: the viewport destination, else the viewport
+    /// source, else the buffer size with transform and scale applied
+    pub fn surface_size(&self) -> (i32, i32) {
+        if let Some(destination) = self.viewport.destination {
+            return destination;
+        }
+        let (width, height) = self.source_size();
+        (width as i32, height as i32)
+    }
+
+    /// Check the viewport against the buffer
+    pub fn check_viewport(&self) -> Result<(), ViewportError> {
+        let Some(source) = self.viewport.source else {
+            return Ok(());
+        };
+        let integer = source.width.fract() == 0.0 && source.height.fract() == 0.0;
+        if self.viewport.destination.is_none() && !integer {
+            return Err(ViewportError::BadSize);
+        }
+        let (width, height) = self.transformed_size();
+        let scale = self.scale.max(1) as f64;
+        if source.x + source.width > width as f64 / scale
+            || source.y + source.height > height as f64 / scale
+        {
+            return Err(ViewportError::OutOfBuffer);
+        }
+        Ok(())
+    }
+
+    /// Part of the buffer shown by the surface, as texture coordinates
+    /// (left, top, right, bottom)
+    pub fn source_crop(&self) -> [f32; 4] {
+        let Some(source) = self.viewport.source else {
+            return [0.0, 0.0, 1.0, 1.0];
+        };
+
+        // Work in 1/256 pixels, the precision of wl_fixed
+        const FIXED: f64 = 256.0;
+        let scale = self.scale.max(1) as f64 * FIXED;
+        let (width, height) = self.transformed_size();
+        let rect = DamageRect {
+            x: (source.x * scale).round() as i32,
+            y: (source.y * scale).round() as i32,
+            width: (source.width * scale).round() as i32,
+            height: (source.height * scale).round() as i32,
+        };
+        let rect = transform_rect(
+            rect,
+            self.transform.invert(),
+            width * FIXED as i32,
+            height * FIXED as i32,
+        );
+        let (buffer_width, buffer_height) = (
+            self.width.max(1) as f64 * FIXED,
+            self.height.max(1) as f64 * FIXED,
+        );
+        [
+            (rect.x as f64 / buffer_width) as f32,
+            (rect.y as f64 / buffer_height) as f32,
+            ((rect.x + rect.width) as f64 / buffer_width) as f32,
+            ((rect.y + rect.height) as f64 / buffer_height) as f32,
+        ]
+    }
+
+    /// Convert a rectangle in surface coordinates to buffer coordinates
+    ///
+    /// The result covers every buffer pixel the rectangle touches and is
+    /// clipped to the buffer.
+    pub fn surface_to_buffer(&self, rect: DamageRect) -> DamageRect {
+        let mut x0 = rect.x as f64;
+        let mut y0 = rect.y as f64;
+        let mut x1 = x0 + rect.width as f64;
+        let mut y1 = y0 + rect.height as f64;
+
+        // Undo the viewport scaling, then the crop
+        if let Some((dst_width, dst_height)) = self.viewport.destination {
+            let (src_width, src_height) = self.source_size();
+            let sx = src_width / dst_width.max(1) as f64;
+            let sy = src_height / dst_height.max(1) as f64;
+            (x0, x1, y0, y1) = (x0 * sx, x1 * sx, y0 * sy, y1 * sy);
+        }
+        if let Some(source) = self.viewport.source {
+            (x0, x1) = (x0 + source.x, x1 + source.x);
+            (y0, y1) = (y0 + source.y, y1 + source.y);
+        }
+
+        // Undo the buffer scale
+        let scale = self.scale.max(1) as f64;
+        let (x0, y0, x1, y1) = (x0 * scale, y0 * scale, x1 * scale, y1 * scale);
+
+        // Clip in transformed buffer space, then undo the buffer transform
+        let (width, height) = self.transformed_size();
+        let clip = |v: f64, max: i32| v.clamp(0.0, max as f64);
+        let x = clip(x0.floor(), width) as i32;
+        let y = clip(y0.floor(), height) as i32;
+        let rect = DamageRect {
+            x,
+            y,
+            width: clip(x1.ceil(), width) as i32 - x,
+            height: clip(y1.ceil(), height) as i32 - y,
+        };
+        transform_rect(rect, self.transform.invert(), width, height)
+    }
+
+    /// Convert a rectangle in buffer coordinates to surface coordinates
+    ///
+    /// The inverse of `surface_to_buffer`: the result covers every surface
+    /// pixel the rectangle touches and is clipped to the surface.
+    pub fn buffer_to_surface(&self, rect: DamageRect) -> DamageRect {
+        // Apply the buffer transform, then the buffer scale
+        let rect = transform_rect(rect, self.transform, self.width, self.height);
+        let scale = self.scale.max(1) as f64;
+        let mut x0 = rect.x as f64 / scale;
+        let mut y0 = rect.y as f64 / scale;
+        let mut x1 = (rect.x + rect.width) as f64 / scale;
+        let mut y1 = (rect.y + rect.height) as f64 / scale;
+
+        // Apply the crop, then the viewport scaling
+        if let Some(source) = self.viewport.source {
+            (x0, x1) = (x0 - source.x, x1 - source.x);
+            (y0, y1) = (y0 - source.y, y1 - source.y);
+        }
+        if let Some((dst_width, dst_height)) = self.viewport.destination {
+            let (src_width, src_height) = self.source_size();
+            let sx = dst_width as f64 / src_width.max(f64::EPSILON);
+            let sy = dst_height as f64 / src_height.max(f64::EPSILON);
+            (x0, x1, y0, y1) = (x0 * sx, x1 * sx, y0 * sy, y1 * sy);
+        }
+
+        let (width, height) = self.surface_size();
+        let clip = |v: f64, max: i32| v.clamp(0.0, max as f64);
+        let x = clip(x0.floor(), width) as i32;
+        let y = clip(y0.floor(), height) as i32;
+        DamageRect {
+            x,
+            y,
+            width: clip(x1.ceil(), width) as i32 - x,
+            height: clip(y1.ceil(), height) as i32 - y,
+        }
+    }
+}
+
+/// Apply a transform to a rectangle inside a `width` x `height` area
+pub fn transform_rect(
+    rect: DamageRect,
+    transform: OutputTransform,
+    width: i32,
+    height: i32,
+) -> DamageRect {
+    let DamageRect {
+        x,
+        y,
+        width: w,
+        height: h,
+    } = rect;
+    let (x, y) = match transform {
+        OutputTransform::Normal => (x, y),
+        OutputTransform::Rotate90 => (height - y - h, x),
+        OutputTransform::Rotate180 => (width - x - w, height - y - h),
+        OutputTransform::Rotate270 => (y, width - x - w),
+        OutputTransform::Flipped => (width - x - w, y),
+        OutputTransform::Flipped90 => (y, x),
+        OutputTransform::Flipped180 => (x, height - y - h),
+        OutputTransform::Flipped270 => (height - y - h, width - x - w),
+    };
+    let (width, height) = match transform.swaps_axes() {
+        true => (h, w),
+        false => (w, h),
+    };
+    DamageRect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> DamageRect {
+        DamageRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn geometry(width: i32, height: i32, scale: i32, transform: OutputTransform) -> BufferGeometry {
+        BufferGeometry {
+            width,
+            height,
+            scale,
+            transform,
+            viewport: Viewport::default(),
+        }
+    }
+
+    #[test]
+    fn test_scale_and_clip() {
+        let g = geometry(200, 100, 2, OutputTransform::Normal);
+        assert_eq!(
+            g.surface_to_buffer(rect(10, 10, 5, 5)),
+            rect(20, 20, 10, 10)
+        );
+
+        // Clients often damage everything with huge rectangles
+        let all = g.surface_to_buffer(rect(0, 0, i32::MAX, i32::MAX));
+        assert_eq!(all, rect(0, 0, 200, 100));
+        assert_eq!(
+            g.buffer_to_surface(rect(20, 20, 10, 10)),
+            rect(10, 10, 5, 5)
+        );
+        assert_eq!(g.buffer_to_surface(rect(1, 1, 1, 1)), rect(0, 0, 1, 1));
+    }
+
+    #[test]
+    fn test_transform() {
+        // A 100x200 buffer rotated by 90 degrees shows as a 200x100 surface
+        let g = geometry(100, 200, 1, OutputTransform::Rotate90);
+        assert_eq!(
+            g.surface_to_buffer(rect(0, 0, 10, 20)),
+            rect(0, 190, 20, 10)
+        );
+        assert_eq!(
+            g.buffer_to_surface(rect(0, 190, 20, 10)),
+            rect(0, 0, 10, 20)
+        );
+
+        let g = geometry(100, 50, 1, OutputTransform::Flipped);
+        assert_eq!(g.surface_to_buffer(rect(0, 0, 10, 5)), rect(90, 0, 10, 5));
+
+        for value in 0..8 {
+            let transform = OutputTransform::from_wayland(value).unwrap();
+            let r = rect(3, 5, 7, 11);
+            let there = transform_rect(r, transform, 40, 30);
+            let (w, h) = if transform.swaps_axes() {
+                (30, 40)
+            } else {
+                (40, 30)
+            };
+            assert_eq!(transform_rect(there, transform.invert(), w, h), r);
+        }
+    }
+
+    #[test]
+    fn test_viewport() {
+        // 100x100 buffer at scale 2, cropped to (10, 10, 20, 20) and shown at 40x40
+        let mut g = geometry(100, 100, 2, OutputTransform::Normal);
+        g.viewport = Viewport {
+            source: Some(ViewportSource {
+                x: 10.0,
+                y: 10.0,
+                width: 20.0,
+                height: 20.0,
+            }),
+            destination: Some((40, 40)),
+        };
+        assert_eq!(
+            g.surface_to_buffer(rect(0, 0, 40, 40)),
+            rect(20, 20, 40, 40)
+        );
+        assert_eq!(g.surface_to_buffer(rect(1, 1, 1, 1)), rect(21, 21, 1, 1));
+    }
+
+    #[test]
+    fn test_viewport_size_and_crop() {
+        let mut g = geometry(200, 100, 2, OutputTransform::Normal);
+        assert_eq!(g.surface_size(), (100, 50));
+        assert_eq!(g.source_crop(), [0.0, 0.0, 1.0, 1.0]);
+
+        g.viewport.source = Some(ViewportSource {
+            x: 50.0,
+            y: 0.0,
+            width: 50.0,
+            height: 25.0,
+        });
+        assert_eq!(g.surface_size(), (50, 25));
+        assert_eq!(g.source_crop(), [0.5, 0.0, 1.0, 0.5]);
+        assert_eq!(g.check_viewport(), Ok(()));
+
+        g.viewport.destination = Some((300, 150));
+        assert_eq!(g.surface_size(), (300, 150));
+
+        g.viewport.source = Some(ViewportSource {
+            x: 60.0,
+            y: 0.0,
+            width: 50.0,
+            height: 25.0,
+        });
+        assert_eq!(g.check_viewport(), Err(ViewportError::OutOfBuffer));
+        g.viewport = Viewport {
+            source: Some(ViewportSource {
+                x: 0.0,
+                y: 0.0,
+                width: 10.5,
+                height: 10.0,
+            }),
+            destination: None,
+        };
+        assert_eq!(g.check_viewport(), Err(ViewportError::BadSize));
+    }
+}
@@ -0,0 +1,155 @@
+//! Window icons
+//!
+//! A client gives a window an icon as square images, an icon theme name,
+//! or both. Images are preferred, since macOS has no icon themes; a name is
+//! looked up the way the XDG icon theme specification looks up the hicolor
+//! theme, among the PNG icons installed under the XDG data directories.
+
+use std::path::{Path, PathBuf};
+
+/// Edge size in pixels icons are shown at, which clients are asked for
+pub const ICON_SIZE: u32 = 128;
+
+/// A square icon image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IconImage {
+    /// Edge size in pixels
+    pub size: u32,
+    /// Scale the client drew the image for
+    pub scale: i32,
+    /// ARGB8888 pixels, `size * 4` bytes per row
+    pub pixels: Vec<u8>,
+}
+
+/// An icon set by a client for one of its windows
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowIcon {
+    /// Icon theme name
+    pub name: Option<String>,
+    /// Images of the icon at different sizes
+    pub images: Vec<IconImage>,
+}
+
+impl WindowIcon {
+    /// Check if the icon has neither a name nor images, which resets a
+    /// window to the default icon
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.images.is_empty()
+    }
+
+    /// The image best shown at an edge size in pixels
+    ///
+    /// This is the smallest image at least that large, or the largest one if
+    /// none is.
+    pub fn image(&self, size: u32) -> Option<&IconImage> {
+        self.images
+            .iter()
+            .filter(|image| image.size >= size)
+            .min_by_key(|image| image.size)
+            .or_else(|| self.images.iter().max_by_key(|image| image.size))
+    }
+}
+
+/// Directories icon themes are installed in
+///
+/// `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, plus the Homebrew prefixes.
+pub fn icon_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .chain([PathBuf::from("/opt/homebrew/share")])
+        .collect()
+}
+
+/// Find the PNG of a named icon in the hicolor theme, or among the pixmaps
+///
+/// The smallest size at least `size` pixels is preferred, then the largest.
+pub fn find_icon(dirs: &[PathBuf], name: &str, size: u32) -> Option<PathBuf> {
+    // Names are looked up, not paths
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let file = format!("{}.png", name);
+    let mut found: Vec<(u32, PathBuf)> = Vec::new();
+    for dir in dirs {
+        let theme = dir.join("icons/hicolor");
+        let Ok(entries) = std::fs::read_dir(&theme) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let edge = entry
+                .file_name()
+                .to_str()
+                .and_then(|sizes| sizes.split_once('x'))
+                .and_then(|(width, _)| width.parse::<u32>().ok());
+            let path = entry.path().join("apps").join(&file);
+            if let Some(edge) = edge.filter(|_| path.is_file()) {
+                found.push((edge, path));
+            }
+        }
+    }
+    let best = found
+        .iter()
+        .filter(|(edge, _)| *edge >= size)
+        .min_by_key(|(edge, _)| *edge)
+        .or_else(|| found.iter().max_by_key(|(edge, _)| *edge));
+    if let Some((_, path)) = best {
+        return Some(path.clone());
+    }
+    dirs.iter()
+        .map(|dir| dir.join("pixmaps").join(&file))
+        .find(|path| Path::is_file(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: u32) -> IconImage {
+        IconImage {
+            size,
+            scale: 1,
+            pixels: vec![0; (size * size * 4) as usize],
+        }
+    }
+
+    #[test]
+    fn test_best_image() {
+        let icon = WindowIcon {
+            name: None,
+            images: vec![image(16), image(256), image(64)],
+        };
+        assert_eq!(icon.image(48).map(|i| i.size), Some(64));
+        assert_eq!(icon.image(512).map(|i| i.size), Some(256));
+        assert!(WindowIcon::default().is_empty());
+        assert_eq!(WindowIcon::default().image(64), None);
+    }
+
+    #[test]
+    fn test_find_icon() {
+        let dir = tempfile::tempdir().unwrap();
+        for size in ["48x48", "256x256", "scalable"] {
+            let apps = dir.path().join("icons/hicolor").join(size).join("apps");
+            std::fs::create_dir_all(&apps).unwrap();
+            std::fs::write(apps.join("editor.png"), "").unwrap();
+        }
+        std::fs::create_dir_all(dir.path().join("pixmaps")).unwrap();
+        std::fs::write(dir.path().join("pixmaps/legacy.png"), "").unwrap();
+        let dirs = [dir.path().to_path_buf()];
+
+        let path = find_icon(&dirs, "editor", 128).unwrap();
+        assert!(path.ends_with("256x256/apps/editor.png"));
+        let path = find_icon(&dirs, "editor", 512).unwrap();
+        assert!(path.ends_with("256x256/apps/editor.png"));
+        assert!(find_icon(&dirs, "legacy", 128)
+            .unwrap()
+            .ends_with("pixmaps/legacy.png"));
+        assert_eq!(find_icon(&dirs, "missing", 128), None);
+        assert_eq!(find_icon(&dirs, "../editor", 128), None);
+    }
+}
@@ -0,0 +1,292 @@
+//! Window switcher and application launcher
+//!
+//! The launcher is an overlay drawn by the compositor. While it is open it
+//! takes all key presses: typing narrows the list of open windows and
+//! configured applications by fuzzy match, arrows move the selection,
+//! Return activates the selected window or launches the application and
+//! Escape closes it.
+
+use crate::compositor::{WindowId, WindowManager};
+use crate::config::LauncherApp;
+use crate::input::keybinding::key_char;
+
+/// Evdev keycodes the launcher handles
+mod keys {
+    pub const ESCAPE: u32 = 1;
+    pub const BACKSPACE: u32 = 14;
+    pub const TAB: u32 = 15;
+    pub const ENTER: u32 = 28;
+    pub const KP_ENTER: u32 = 96;
+    pub const UP: u32 = 103;
+    pub const DOWN: u32 = 108;
+}
+
+/// What a launcher entry does when chosen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LauncherTarget {
+    /// Bring an open window to the front
+    Window(WindowId),
+    /// Run a command (split on whitespace)
+    Launch(String),
+}
+
+/// An entry in the launcher list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LauncherEntry {
+    /// Text shown and searched
+    pub label: String,
+    /// What choosing the entry does
+    pub target: LauncherTarget,
+}
+
+/// Result of a key press in the open launcher
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LauncherOutcome {
+    /// The launcher stays open
+    Pending,
+    /// The launcher was dismissed
+    Dismissed,
+    /// An entry was chosen and the launcher closed
+    Chosen(LauncherTarget),
+}
+
+/// Launcher overlay state
+#[derive(Debug, Default)]
+pub struct Launcher {
+    /// Whether the overlay is shown
+    open: bool,
+    /// Search text typed so far
+    query: String,
+    /// All entries, windows first
+    entries: Vec<LauncherEntry>,
+    /// Index into the current results
+    selected: usize,
+}
+
+impl Launcher {
+    /// Create a closed launcher
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the launcher with the current windows and configured apps
+    ///
+    /// Windows are listed most recently focused first, and like Alt-Tab the
+    /// one focused before the current window is selected.
+    pub fn open(&mut self, windows: &WindowManager, apps: &[LauncherApp]) {
+        let focused = windows.focused().map(|window| window.id);
+        let recent: Vec<_> = windows.most_recent().collect();
+        let previous = recent.len() > 1 && recent.first().map(|window| window.id) == focused;
+
+        self.entries = recent
+            .into_iter()
+            .map(|window| LauncherEntry {
+                label: window
+                    .title
+                    .clone()
+                    .or_else(|| window.app_id.clone())
+                    .unwrap_or_else(|| format!("Window {}", window.id.0)),
+                target: LauncherTarget::Window(window.id),
+            })
+            .chain(apps.iter().map(|app| LauncherEntry {
+                label: app.name.clone(),
+                target: LauncherTarget::Launch(app.command.clone()),
+            }))
+            .collect();
+        self.query.clear();
+        self.selected = usize::from(previous);
+        self.open = true;
+    }
+
+    /// Close the launcher
+    pub fn close(&mut self) {
+        self.open = false;
+        self.query.clear();
+        self.entries.clear();
+        self.selected = 0;
+    }
+
+    /// Whether the overlay is shown
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// The search text
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Entries matching the query, best match first
+    pub fn results(&self) -> Vec<&LauncherEntry> {
+        let mut scored: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|entry| Some((fuzzy_score(&self.query, &entry.label)?, entry)))
+            .collect();
+        // Stable, so equal scores keep windows before apps
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Index of the selected result
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Handle a key press
+    pub fn handle_key(&mut self, key: u32) -> LauncherOutcome {
+        if !self.open {
+            return LauncherOutcome::Dismissed;
+        }
+        let count = self.results().len();
+        match key {
+            keys::ESCAPE => {
+                self.close();
+                return LauncherOutcome::Dismissed;
+            }
+            keys::ENTER | keys::KP_ENTER => {
+                let Some(target) = self
+                    .results()
+                    .get(self.selected)
+                    .map(|entry| entry.target.clone())
+                else {
+                    return LauncherOutcome::Pending;
+                };
+                self.close();
+                return LauncherOutcome::Chosen(target);
+            }
+            keys::DOWN | keys::TAB if count > 0 => self.selected = (self.selected + 1) % count,
+            keys::UP if count > 0 => self.selected = (self.selected + count - 1) % count,
+            keys::BACKSPACE => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            _ => {
+                if let Some(c) = key_char(key) {
+                    self.query.push(c);
+                    self.selected = 0;
+                }
+            }
+        }
+        LauncherOutcome::Pending
+    }
+
+    /// The overlay contents as text lines: the query, then the results with
+    /// the selection marked
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("> {}", self.query)];
+        lines.extend(self.results().iter().enumerate().map(|(i, entry)| {
+            let marker = if i == self.selected { '▸' } else { ' ' };
+            format!("{} {}", marker, entry.label)
+        }));
+        lines
+    }
+}
+
+/// Score how well a query matches a text, or None if it doesn't
+///
+/// Every query character must appear in order (case-insensitively).
+/// Consecutive characters and characters at word starts score higher.
+/// Whitespace in the query is ignored and an empty query matches anything.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous: Option<usize> = None;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = start + text[start..].iter().position(|&t| t == c)?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        start = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::SurfaceId;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("xyz", "Firefox").is_none());
+        assert!(fuzzy_score("ff", "Firefox").is_some());
+        // Consecutive and word-start matches win
+        assert!(fuzzy_score("term", "Terminal") > fuzzy_score("term", "the reminder"));
+        assert!(fuzzy_score("gc", "Google Chrome") > fuzzy_score("gc", "magic"));
+    }
+
+    #[test]
+    fn test_search_and_choose() {
+        let mut windows = WindowManager::new();
+        let editor = windows.create_window(SurfaceId(1));
+        windows.get_mut(editor).unwrap().title = Some("Text Editor".to_string());
+        let apps = [LauncherApp {
+            name: "Terminal".to_string(),
+            command: "foot --server".to_string(),
+        }];
+
+        let mut launcher = Launcher::new();
+        launcher.open(&windows, &apps);
+        assert_eq!(launcher.results().len(), 2);
+
+        // "te" matches both, Enter picks the window first
+        launcher.handle_key(20);
+        launcher.handle_key(18);
+        assert_eq!(launcher.query(), "te");
+        assert_eq!(launcher.lines()[1], "▸ Text Editor");
+
+        // "term" only matches the terminal
+        launcher.handle_key(19);
+        launcher.handle_key(50);
+        assert_eq!(launcher.results().len(), 1);
+        assert_eq!(
+            launcher.handle_key(keys::ENTER),
+            LauncherOutcome::Chosen(LauncherTarget::Launch("foot --server".to_string()))
+        );
+        assert!(!launcher.is_open());
+
+        launcher.open(&windows, &apps);
+        launcher.handle_key(keys::DOWN);
+        launcher.handle_key(keys::UP);
+        assert_eq!(
+            launcher.handle_key(keys::ENTER),
+            LauncherOutcome::Chosen(LauncherTarget::Window(editor))
+        );
+
+        launcher.open(&windows, &apps);
+        assert_eq!(
+            launcher.handle_key(keys::ESCAPE),
+            LauncherOutcome::Dismissed
+        );
+        assert!(!launcher.is_open());
+    }
+
+    #[test]
+    fn test_windows_in_recent_order() {
+        let mut windows = WindowManager::new();
+        let first = windows.create_window(SurfaceId(1));
+        let second = windows.create_window(SurfaceId(2));
+        let third = windows.create_window(SurfaceId(3));
+        windows.set_focused(Some(second));
+        windows.set_focused(Some(first));
+
+        let mut launcher = Launcher::new();
+        launcher.open(&windows, &[]);
+        let targets: Vec<_> = launcher
+            .results()
+            .iter()
+            .map(|e| e.target.clone())
+            .collect();
+        assert_eq!(targets, [first, second, third].map(LauncherTarget::Window));
+        // The previously focused window is selected
+        assert_eq!(launcher.selected(), 1);
+    }
+}
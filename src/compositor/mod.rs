@@ -3,15 +3,43 @@
 //! This module contains the core compositor logic including:
 //! - Global compositor state management
 //! - Surface management and damage tracking
+//! - Surface regions
+//! - Subsurface stacking and synchronized commits
+//! - Commits held for a fifo barrier or a target time
 //! - Window/toplevel management
+//! - Window icons
 //! - Output/display management
+//! - Compositor actions
+//! - The launcher overlay
+//! - Client compatibility quirks
+//! - Per-window content zoom
 
+pub mod actions;
+pub mod commit_queue;
+pub mod damage;
+pub mod icon;
+pub mod launcher;
 pub mod output;
+pub mod quirks;
+pub mod region;
 pub mod state;
+pub mod subsurface;
 pub mod surface;
 pub mod window;
+pub mod zoom;
 
-pub use output::{Output, OutputId, OutputManager, OutputMode};
+pub use actions::CompositorAction;
+pub use damage::{BufferGeometry, Viewport, ViewportError, ViewportSource};
+pub use icon::{IconImage, WindowIcon};
+pub use launcher::{Launcher, LauncherEntry, LauncherOutcome, LauncherTarget};
+pub use output::{Insets, Output, OutputId, OutputManager, OutputMode, WorkArea};
+pub use quirks::Quirks;
+pub use region::Region;
 pub use state::CompositorState;
+pub use subsurface::{Placement, SubsurfaceError, SubsurfaceState};
 pub use surface::{Surface, SurfaceId, SurfaceManager, SurfaceRole};
-pub use window::{Window, WindowId, WindowManager};
+pub use window::{
+    ColorFilter, DecorationMode, ResizeConfigure, Window, WindowChange, WindowGeometry, WindowId,
+    WindowManager,
+};
+pub use zoom::Zoom;
@@ -5,6 +5,8 @@
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use crate::config::DefaultOutputConfig;
+
 /// Unique identifier for outputs
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OutputId(pub u64);
@@ -44,6 +46,35 @@ impl OutputTransform {
             OutputTransform::Flipped270 => 7,
         }
     }
+
+    /// Convert from a Wayland wl_output::transform value
+    pub fn from_wayland(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(OutputTransform::Normal),
+            1 => Some(OutputTransform::Rotate90),
+            2 => Some(OutputTransform::Rotate180),
+            3 => Some(OutputTransform::Rotate270),
+            4 => Some(OutputTransform::Flipped),
+            5 => Some(OutputTransform::Flipped90),
+            6 => Some(OutputTransform::Flipped180),
+            7 => Some(OutputTransform::Flipped270),
+            _ => None,
+        }
+    }
+
+    /// Get the transform that undoes this one
+    pub fn invert(&self) -> Self {
+        match self {
+            OutputTransform::Rotate90 => OutputTransform::Rotate270,
+            OutputTransform::Rotate270 => OutputTransform::Rotate90,
+            other => *other,
+        }
+    }
+
+    /// Check if the transform swaps width and height
+    pub fn swaps_axes(&self) -> bool {
+        self.to_wayland() % 2 == 1
+    }
 }
 
 /// Output subpixel layout
@@ -87,6 +118,46 @@ pub struct OutputMode {
     pub preferred: bool,
 }
 
+/// Space taken from the edges of an output (logical pixels)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Insets {
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub left: i32,
+}
+
+/// Stacks two insets along the same edges
+impl std::ops::Add for Insets {
+    type Output = Insets;
+
+    fn add(self, other: Insets) -> Insets {
+        Insets {
+            top: self.top + other.top,
+            right: self.right + other.right,
+            bottom: self.bottom + other.bottom,
+            left: self.left + other.left,
+        }
+    }
+}
+
+/// Part of an output that windows and popups may cover, in global logical
+/// coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl WorkArea {
+    /// Check if a point lies inside the area
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+}
+
 /// A display output (monitor)
 #[derive(Debug)]
 pub struct Output {
@@ -116,6 +187,21 @@ pub struct Output {
     pub current_mode: Option<usize>,
     /// Scale factor
     pub scale: i32,
+    /// Unrounded scale factor, e.g. the screen's backing scale factor
+    pub fractional_scale: f64,
+    /// Edges taken by the system, e.g. the macOS menu bar and Dock
+    pub reserved: Insets,
+    /// Edges taken by layer-shell exclusive zones
+    pub exclusive: Insets,
+    /// Virtual output without a screen, only rendered offscreen
+    pub headless: bool,
+    /// Lowest refresh rate (mHz) of a display that refreshes adaptively,
+    /// like ProMotion displays, None if its refresh rate is fixed
+    pub min_refresh: Option<u32>,
+    /// Whether the output is in use; disabled outputs aren't advertised
+    pub enabled: bool,
+    /// Whether the output is powered on rather than asleep
+    pub powered: bool,
 }
 
 impl Output {
@@ -136,6 +222,13 @@ impl Output {
             modes: Vec::new(),
             current_mode: None,
             scale: 1,
+            fractional_scale: 1.0,
+            reserved: Insets::default(),
+            exclusive: Insets::default(),
+            headless: false,
+            min_refresh: None,
+            enabled: true,
+            powered: true,
         }
     }
 
@@ -154,6 +247,24 @@ impl Output {
         self.current_mode().map(|m| m.height).unwrap_or(0)
     }
 
+    /// Get the logical size of the current mode
+    pub fn logical_size(&self) -> (i32, i32) {
+        let scale = self.scale.max(1);
+        (self.width() as i32 / scale, self.height() as i32 / scale)
+    }
+
+    /// Get the area not taken by the system or exclusive zones
+    pub fn work_area(&self) -> WorkArea {
+        let (width, height) = self.logical_size();
+        let insets = self.reserved + self.exclusive;
+        WorkArea {
+            x: self.x + insets.left,
+            y: self.y + insets.top,
+            width: (width - insets.left - insets.right).max(0),
+            height: (height - insets.top - insets.bottom).max(0),
+        }
+    }
+
     /// Add a mode
     pub fn add_mode(&mut self, mode: OutputMode) {
         let is_current = mode.current;
@@ -170,6 +281,8 @@ pub struct OutputManager {
     outputs: HashMap<OutputId, Output>,
     /// Primary output
     primary: Option<OutputId>,
+    /// Mode of outputs created without a screen
+    default_mode: DefaultOutputConfig,
 }
 
 impl OutputManager {
@@ -178,22 +291,62 @@ impl OutputManager {
         Self {
             outputs: HashMap::new(),
             primary: None,
+            default_mode: DefaultOutputConfig::default(),
         }
     }
 
+    /// Apply the configured default output mode to outputs created later
+    pub fn configure(&mut self, config: &DefaultOutputConfig) {
+        self.default_mode = config.clone();
+    }
+
     /// Create and add a new output with basic info
     pub fn create_output(&mut self, name: String, make: String, model: String) -> OutputId {
         let mut output = Output::new(name);
         output.make = make;
         output.model = model;
-        output.physical_width = 527; // ~24" at 1920x1080
-        output.physical_height = 296;
-        output.scale = 1;
-        // Add a default 1920x1080@60Hz mode
+        let mode = &self.default_mode;
+        // ~92 DPI (24" at 1920x1080) per unit of scale
+        let mm_per_pixel = 527.0 / 1920.0 / mode.scale.max(1) as f64;
+        output.physical_width = (mode.width as f64 * mm_per_pixel).round() as u32;
+        output.physical_height = (mode.height as f64 * mm_per_pixel).round() as u32;
+        output.scale = mode.scale.max(1);
+        output.fractional_scale = output.scale as f64;
         output.add_mode(OutputMode {
-            width: 1920,
-            height: 1080,
-            refresh: 60000,
+            width: mode.width,
+            height: mode.height,
+            refresh: mode.refresh_mhz,
+            current: true,
+            preferred: true,
+        });
+        self.add(output)
+    }
+
+    /// Create and add a headless output to the right of all other outputs
+    pub fn create_headless(
+        &mut self,
+        name: String,
+        width: u32,
+        height: u32,
+        scale: i32,
+        refresh: u32,
+    ) -> OutputId {
+        let mut output = Output::new(name);
+        output.make = "Wayoa".to_string();
+        output.model = "Headless".to_string();
+        output.scale = scale.max(1);
+        output.fractional_scale = output.scale as f64;
+        output.headless = true;
+        output.x = self
+            .outputs
+            .values()
+            .map(|o| o.x + o.logical_size().0)
+            .max()
+            .unwrap_or(0);
+        output.add_mode(OutputMode {
+            width,
+            height,
+            refresh,
             current: true,
             preferred: true,
         });
@@ -230,11 +383,29 @@ impl OutputManager {
         output
     }
 
+    /// Get an output by name
+    pub fn by_name(&self, name: &str) -> Option<&Output> {
+        self.outputs.values().find(|output| output.name == name)
+    }
+
     /// Get the primary output
     pub fn primary(&self) -> Option<&Output> {
         self.primary.and_then(|id| self.outputs.get(&id))
     }
 
+    /// Get the enabled output containing a point in global logical
+    /// coordinates, falling back to the primary output
+    pub fn output_at(&self, x: i32, y: i32) -> Option<&Output> {
+        self.outputs
+            .values()
+            .filter(|output| output.enabled)
+            .find(|output| {
+                let (width, height) = output.logical_size();
+                x >= output.x && y >= output.y && x < output.x + width && y < output.y + height
+            })
+            .or_else(|| self.primary())
+    }
+
     /// Set the primary output
     pub fn set_primary(&mut self, id: OutputId) {
         if self.outputs.contains_key(&id) {
@@ -296,6 +467,38 @@ mod tests {
         assert_eq!(output.height(), 1080);
     }
 
+    #[test]
+    fn test_work_area() {
+        let mut manager = OutputManager::new();
+        let id = manager.create_output("a".to_string(), String::new(), String::new());
+        let output = manager.get_mut(id).unwrap();
+        output.x = 100;
+        output.scale = 2;
+        // Menu bar on top, a panel with an exclusive zone at the bottom
+        output.reserved = Insets {
+            top: 25,
+            ..Default::default()
+        };
+        output.exclusive = Insets {
+            bottom: 30,
+            ..Default::default()
+        };
+
+        let area = output.work_area();
+        assert_eq!(
+            area,
+            WorkArea {
+                x: 100,
+                y: 25,
+                width: 960,
+                height: 485,
+            }
+        );
+        assert!(area.contains(100, 25) && !area.contains(100, 10));
+        assert_eq!(manager.output_at(500, 300).map(|o| o.id), Some(id));
+        assert_eq!(manager.output_at(-50, 0).map(|o| o.id), Some(id));
+    }
+
     #[test]
     fn test_output_manager() {
         let mut manager = OutputManager::new();
@@ -306,4 +509,38 @@ mod tests {
         manager.remove(id);
         assert!(manager.get(id).is_none());
     }
+
+    #[test]
+    fn test_headless_output() {
+        let mut manager = OutputManager::new();
+        let primary = manager.create_output(
+            "default".to_string(),
+            "Wayoa".to_string(),
+            "Test".to_string(),
+        );
+        let id = manager.create_headless("virtual-0".to_string(), 2560, 1440, 2, 60_000);
+        let output = manager.by_name("virtual-0").unwrap();
+        assert_eq!(output.id, id);
+        assert!(output.headless);
+        assert_eq!((output.x, output.logical_size()), (1920, (1280, 720)));
+        assert_eq!(output.fractional_scale, 2.0);
+        assert_eq!(manager.primary().map(|o| o.id), Some(primary));
+    }
+
+    #[test]
+    fn test_configured_default_output() {
+        let mut manager = OutputManager::new();
+        manager.configure(&DefaultOutputConfig {
+            width: 2560,
+            height: 1600,
+            scale: 2,
+            refresh_mhz: 120_000,
+        });
+        let id = manager.create_output("default".to_string(), String::new(), String::new());
+        let output = manager.get(id).unwrap();
+        assert_eq!((output.width(), output.height()), (2560, 1600));
+        assert_eq!(output.logical_size(), (1280, 800));
+        assert_eq!(output.modes[0].refresh, 120_000);
+        assert_eq!(output.physical_width, 351);
+    }
 }
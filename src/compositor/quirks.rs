@@ -0,0 +1,141 @@
+//! Client compatibility quirks
+//!
+//! Quirks work around buggy clients. Rules from the configuration are
+//! matched against a client's executable and the app_id of its toplevels,
+//! and all matching rules are merged.
+//!
+//! Version caps can only be matched by executable, since globals are
+//! advertised before a client has created any toplevel.
+
+use std::collections::HashMap;
+
+use super::ColorFilter;
+use crate::config::QuirkRule;
+
+/// Resolved quirks for a client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// Maximum advertised version per global interface
+    pub max_versions: HashMap<String, u32>,
+    /// Always use server-side decorations
+    pub server_side_decorations: bool,
+    /// Scale reported in wl_output events instead of the real one
+    pub output_scale: Option<i32>,
+    /// Frames a window may have in flight
+    pub frames_in_flight: Option<u32>,
+    /// Color filter drawn over the client's windows
+    pub color_filter: Option<ColorFilter>,
+    /// Copy buffers when they're committed
+    pub snapshot_buffers: bool,
+}
+
+impl Quirks {
+    /// Merge all rules matching an executable and app_id
+    pub fn resolve(rules: &[QuirkRule], executable: Option<&str>, app_id: Option<&str>) -> Self {
+        let mut quirks = Quirks::default();
+        for rule in rules.iter().filter(|r| r.matches(executable, app_id)) {
+            quirks.merge(rule);
+        }
+        quirks
+    }
+
+    /// Merge a rule into these quirks
+    ///
+    /// Version caps keep the lowest version, later rules override the
+    /// output scale, frames in flight and color filter.
+    pub fn merge(&mut self, rule: &QuirkRule) {
+        for (interface, version) in &rule.max_versions {
+            self.max_versions
+                .entry(interface.clone())
+                .and_modify(|v| *v = (*v).min(*version))
+                .or_insert(*version);
+        }
+        self.server_side_decorations |= rule.server_side_decorations;
+        self.snapshot_buffers |= rule.snapshot_buffers;
+        if rule.output_scale.is_some() {
+            self.output_scale = rule.output_scale;
+        }
+        if rule.frames_in_flight.is_some() {
+            self.frames_in_flight = rule.frames_in_flight;
+        }
+        if rule.color_filter.is_some() {
+            self.color_filter = rule.color_filter;
+        }
+    }
+
+    /// Get the version cap for a global interface
+    pub fn max_version(&self, interface: &str) -> Option<u32> {
+        self.max_versions.get(interface).copied()
+    }
+
+    /// Check if no quirk applies
+    pub fn is_empty(&self) -> bool {
+        *self == Quirks::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(executable: Option<&str>, app_id: Option<&str>) -> QuirkRule {
+        QuirkRule {
+            executable: executable.map(String::from),
+            app_id: app_id.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_by_executable() {
+        let mut seat = rule(Some("firefox"), None);
+        seat.max_versions.insert("wl_seat".to_string(), 7);
+        let mut scale = rule(Some("/usr/bin/firefox"), None);
+        scale.output_scale = Some(1);
+
+        let quirks = Quirks::resolve(&[seat, scale], Some("/usr/bin/firefox"), None);
+        assert_eq!(quirks.max_version("wl_seat"), Some(7));
+        assert_eq!(quirks.max_version("wl_output"), None);
+        assert_eq!(quirks.output_scale, Some(1));
+
+        assert!(
+            Quirks::resolve(&[rule(Some("firefox"), None)], Some("/usr/bin/foot"), None).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_resolve_by_app_id() {
+        let mut ssd = rule(None, Some("org.example.App"));
+        ssd.server_side_decorations = true;
+        ssd.snapshot_buffers = true;
+        let mut latency = rule(None, Some("org.example.App"));
+        latency.frames_in_flight = Some(1);
+        latency.color_filter = Some(ColorFilter::Dark);
+        let rules = [ssd, latency];
+
+        let quirks = Quirks::resolve(&rules, None, Some("org.example.App"));
+        assert!(quirks.server_side_decorations);
+        assert!(quirks.snapshot_buffers);
+        assert_eq!(quirks.frames_in_flight, Some(1));
+        assert_eq!(quirks.color_filter, Some(ColorFilter::Dark));
+        assert!(Quirks::resolve(&rules, Some("app"), None).is_empty());
+    }
+
+    #[test]
+    fn test_merge_keeps_lowest_cap() {
+        let mut a = rule(Some("app"), None);
+        a.max_versions.insert("xdg_wm_base".to_string(), 5);
+        let mut b = rule(Some("app"), None);
+        b.max_versions.insert("xdg_wm_base".to_string(), 3);
+
+        let quirks = Quirks::resolve(&[a, b], Some("app"), None);
+        assert_eq!(quirks.max_version("xdg_wm_base"), Some(3));
+    }
+
+    #[test]
+    fn test_rule_without_matcher() {
+        let mut any = rule(None, None);
+        any.output_scale = Some(1);
+        assert!(Quirks::resolve(&[any], Some("app"), Some("app")).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Surface regions
+//!
+//! A wl_region is built by adding and subtracting rectangles, in surface
+//! coordinates. The operations are kept in order, so a point is in the
+//! region if the last rectangle containing it was added.
+
+use crate::compositor::surface::DamageRect;
+
+/// A region built from added and subtracted rectangles
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Region {
+    /// Rectangles in the order they were added (true) or subtracted
+    ops: Vec<(bool, DamageRect)>,
+}
+
+impl Region {
+    /// Add a rectangle to the region
+    pub fn add(&mut self, rect: DamageRect) {
+        if rect.width > 0 && rect.height > 0 {
+            self.ops.push((true, rect));
+        }
+    }
+
+    /// Subtract a rectangle from the region
+    pub fn subtract(&mut self, rect: DamageRect) {
+        if rect.width > 0 && rect.height > 0 {
+            self.ops.push((false, rect));
+        }
+    }
+
+    /// Check if a point is in the region
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.ops
+            .iter()
+            .rev()
+            .find(|(_, rect)| {
+                x >= rect.x as f64
+                    && y >= rect.y as f64
+                    && x < (rect.x + rect.width) as f64
+                    && y < (rect.y + rect.height) as f64
+            })
+            .is_some_and(|(added, _)| *added)
+    }
+
+    /// The region as rectangles that don't overlap
+    ///
+    /// The region is cut into bands at every rectangle edge, and the cells
+    /// of each band that are in the region merged into runs.
+    pub fn rects(&self) -> Vec<DamageRect> {
+        let edges = |mut values: Vec<i32>| {
+            values.sort_unstable();
+            values.dedup();
+            values
+        };
+        let xs = edges(
+            self.ops
+                .iter()
+                .flat_map(|(_, r)| [r.x, r.x + r.width])
+                .collect(),
+        );
+        let ys = edges(
+            self.ops
+                .iter()
+                .flat_map(|(_, r)| [r.y, r.y + r.height])
+                .collect(),
+        );
+
+        let mut rects = Vec::new();
+        for band in ys.windows(2) {
+            let (y, height) = (band[0], band[1] - band[0]);
+            let mut run: Option<DamageRect> = None;
+            for cell in xs.windows(2) {
+                // A cell is wholly in or out of the region, so its center decides
+                let center = (
+                    (cell[0] + cell[1]) as f64 / 2.0,
+                    (band[0] + band[1]) as f64 / 2.0,
+                );
+                match (self.contains(center.0, center.1), run.as_mut()) {
+                    (true, Some(run)) => run.width = cell[1] - run.x,
+                    (true, None) => {
+                        run = Some(DamageRect {
+                            x: cell[0],
+                            y,
+                            width: cell[1] - cell[0],
+                            height,
+                        })
+                    }
+                    (false, _) => rects.extend(run.take()),
+                }
+            }
+            rects.extend(run);
+        }
+        rects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> DamageRect {
+        DamageRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_region() {
+        let mut region = Region::default();
+        region.add(rect(0, 0, 100, 100));
+        region.subtract(rect(0, 0, 100, 20));
+        region.add(rect(40, 10, 20, 20));
+        assert!(!region.contains(10.0, 10.0));
+        assert!(region.contains(50.0, 10.0));
+        assert!(region.contains(10.0, 50.0));
+        assert!(!region.contains(100.0, 50.0));
+
+        let rects = region.rects();
+        let area: i32 = rects.iter().map(|r| r.width * r.height).sum();
+        assert_eq!(area, 100 * 80 + 20 * 10);
+        assert!(rects.contains(&rect(40, 10, 20, 10)));
+        assert!(rects.contains(&rect(0, 30, 100, 70)));
+        assert_eq!(Region::default().rects(), Vec::new());
+    }
+}
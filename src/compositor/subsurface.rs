@@ -0,0 +1,486 @@
+//! Subsurfaces
+//!
+//! A subsurface is drawn relative to a parent surface and stacked among its
+//! siblings, above or below the parent. Its position and stacking order are
+//! state of the parent and take effect when the parent commits. In
+//! synchronized mode (the default) the subsurface's own commits are cached
+//! and applied together with the parent's next commit; in desynchronized
+//! mode they apply right away. A subsurface with a synchronized ancestor is
+//! synchronized as well.
+
+use super::surface::{SurfaceId, SurfaceManager, SurfacePendingState, SurfaceRole};
+
+/// Where to restack a subsurface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// Directly above a sibling or the parent
+    Above(SurfaceId),
+    /// Directly below a sibling or the parent
+    Below(SurfaceId),
+}
+
+/// Subsurface state of a surface
+#[derive(Debug, Default)]
+pub struct SubsurfaceState {
+    /// Commits are applied together with the parent's
+    pub sync: bool,
+    /// Position to apply on the next parent commit
+    pub pending_position: Option<(i32, i32)>,
+    /// Committed state waiting for the parent to commit
+    pub cached: Option<SurfacePendingState>,
+}
+
+/// Subsurface errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum SubsurfaceError {
+    #[error("Surface not found")]
+    NotFound,
+    #[error("Surface already has a role")]
+    RoleAlreadySet,
+    #[error("Parent is the surface itself or one of its subsurfaces")]
+    BadParent,
+    #[error("Not the parent or a sibling")]
+    NotSibling,
+}
+
+impl SurfaceManager {
+    /// Make `surface` a subsurface of `parent`, on top of its siblings
+    pub fn create_subsurface(
+        &mut self,
+        surface: SurfaceId,
+        parent: SurfaceId,
+    ) -> Result<(), SubsurfaceError> {
+        if self.get(parent).is_none() {
+            return Err(SubsurfaceError::NotFound);
+        }
+        if self.is_ancestor(surface, parent) {
+            return Err(SubsurfaceError::BadParent);
+        }
+
+        let child = self.get_mut(surface).ok_or(SubsurfaceError::NotFound)?;
+        if child.subsurface.is_some() {
+            return Err(SubsurfaceError::RoleAlreadySet);
+        }
+        child
+            .set_role(SurfaceRole::Subsurface)
+            .map_err(|_| SubsurfaceError::RoleAlreadySet)?;
+        child.parent = Some(parent);
+        child.subsurface = Some(SubsurfaceState {
+            sync: true,
+            ..Default::default()
+        });
+
+        if let Some(parent) = self.get_mut(parent) {
+            parent.children.push(surface);
+        }
+        Ok(())
+    }
+
+    /// Check if `ancestor` is `surface` or one of its parents
+    fn is_ancestor(&self, ancestor: SurfaceId, mut surface: SurfaceId) -> bool {
+        loop {
+            if surface == ancestor {
+                return true;
+            }
+            match self.get(surface).and_then(|s| s.parent) {
+                Some(parent) => surface = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Unmap a subsurface and detach it from its parent
+    pub fn destroy_subsurface(&mut self, surface: SurfaceId) {
+        self.detach(surface);
+        if let Some(surface) = self.get_mut(surface) {
+            surface.parent = None;
+            surface.subsurface = None;
+        }
+    }
+
+    /// Remove a surface from its parent's stacking order
+    pub(super) fn detach(&mut self, surface: SurfaceId) {
+        let Some(parent) = self.get(surface).and_then(|s| s.parent) else {
+            return;
+        };
+        if let Some(parent) = self.get_mut(parent) {
+            parent.children.retain(|&child| child != surface);
+            parent.children_below.retain(|&child| child != surface);
+        }
+    }
+
+    /// Set the position of a subsurface, applied on the next parent commit
+    pub fn set_subsurface_position(&mut self, surface: SurfaceId, x: i32, y: i32) {
+        if let Some(state) = self.get_mut(surface).and_then(|s| s.subsurface.as_mut()) {
+            state.pending_position = Some((x, y));
+        }
+    }
+
+    /// Restack a subsurface, applied on the next parent commit
+    pub fn place_subsurface(
+        &mut self,
+        surface: SurfaceId,
+        placement: Placement,
+    ) -> Result<(), SubsurfaceError> {
+        let parent = self
+            .get(surface)
+            .and_then(|s| s.parent)
+            .ok_or(SubsurfaceError::NotFound)?;
+        let (Placement::Above(sibling) | Placement::Below(sibling)) = placement;
+        let is_sibling = sibling != surface
+            && self
+                .get(sibling)
+                .is_some_and(|s| s.subsurface.is_some() && s.parent == Some(parent));
+        if sibling != parent && !is_sibling {
+            return Err(SubsurfaceError::NotSibling);
+        }
+
+        if let Some(parent) = self.get_mut(parent) {
+            parent.pending.placements.push((surface, placement));
+        }
+        Ok(())
+    }
+
+    /// Switch a subsurface between synchronized and desynchronized mode
+    pub fn set_subsurface_sync(&mut self, surface: SurfaceId, sync: bool) {
+        if let Some(state) = self.get_mut(surface).and_then(|s| s.subsurface.as_mut()) {
+            state.sync = sync;
+        }
+    }
+
+    /// Check if a surface is a subsurface whose commits wait for its parent
+    pub fn is_synchronized(&self, mut surface: SurfaceId) -> bool {
+        while let Some(current) = self.get(surface) {
+            let Some(state) = &current.subsurface else {
+                return false;
+            };
+            if state.sync {
+                return true;
+            }
+            match current.parent {
+                Some(parent) => surface = parent,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Commit a surface
+    ///
+    /// Returns the surfaces whose state was applied: none if the surface is
+    /// synchronized or its commit is queued, otherwise
+    /// the surface and the synchronized subsurfaces below it.
+    pub fn commit(&mut self, surface: SurfaceId) -> Vec<SurfaceId> {
+        let mut applied = Vec::new();
+        if self.is_synchronized(surface) {
+            if let Some(current) = self.get_mut(surface) {
+                let pending = std::mem::take(&mut current.pending);
+                if let Some(state) = current.subsurface.as_mut() {
+                    match &mut state.cached {
+                        Some(cached) => cached.merge(pending),
+                        None => state.cached = Some(pending),
+                    }
+                }
+            }
+        } else if !self.queue_commit(surface) {
+            self.apply(surface, &mut applied);
+        }
+        applied
+    }
+
+    /// Apply the cached and pending state of a surface, then the parent
+    /// state of its subsurfaces
+    pub(super) fn apply(&mut self, surface: SurfaceId, applied: &mut Vec<SurfaceId>) {
+        let Some(current) = self.get_mut(surface) else {
+            return;
+        };
+        if let Some(mut cached) = current.subsurface.as_mut().and_then(|s| s.cached.take()) {
+            cached.merge(std::mem::take(&mut current.pending));
+            current.pending = cached;
+        }
+        let placements = std::mem::take(&mut current.pending.placements);
+        current.commit();
+        applied.push(surface);
+
+        for (child, placement) in placements {
+            self.restack(surface, child, placement);
+        }
+
+        let Some(current) = self.get(surface) else {
+            return;
+        };
+        let children: Vec<SurfaceId> = current
+            .children_below
+            .iter()
+            .chain(&current.children)
+            .copied()
+            .collect();
+        for child in children {
+            let Some(child_surface) = self.get_mut(child) else {
+                continue;
+            };
+            let Some(state) = child_surface.subsurface.as_mut() else {
+                continue;
+            };
+            if let Some(position) = state.pending_position.take() {
+                child_surface.position = position;
+            }
+            let has_cached = state.cached.is_some();
+            if has_cached && self.is_synchronized(child) {
+                self.apply(child, applied);
+            }
+        }
+    }
+
+    /// Move a subsurface within its parent's stacking order
+    fn restack(&mut self, parent: SurfaceId, child: SurfaceId, placement: Placement) {
+        let Some(parent_surface) = self.get_mut(parent) else {
+            return;
+        };
+        parent_surface.children.retain(|&c| c != child);
+        parent_surface.children_below.retain(|&c| c != child);
+
+        let find = |list: &[SurfaceId], sibling| list.iter().position(|&c| c == sibling);
+        match placement {
+            Placement::Above(sibling) if sibling == parent => {
+                parent_surface.children.insert(0, child);
+            }
+            Placement::Below(sibling) if sibling == parent => {
+                parent_surface.children_below.push(child);
+            }
+            Placement::Above(sibling) | Placement::Below(sibling) => {
+                let offset = usize::from(matches!(placement, Placement::Above(_)));
+                if let Some(i) = find(&parent_surface.children, sibling) {
+                    parent_surface.children.insert(i + offset, child);
+                } else if let Some(i) = find(&parent_surface.children_below, sibling) {
+                    parent_surface.children_below.insert(i + offset, child);
+                } else {
+                    // The sibling is gone, keep the subsurface on top
+                    parent_surface.children.push(child);
+                }
+            }
+        }
+    }
+
+    /// A surface and its subsurfaces from bottom to top, with their offset
+    /// from `origin`
+    pub fn stacking_order(
+        &self,
+        surface: SurfaceId,
+        origin: (i32, i32),
+    ) -> Vec<(SurfaceId, (i32, i32))> {
+        let mut order = Vec::new();
+        self.stack(surface, origin, &mut order);
+        order
+    }
+
+    /// Find the topmost mapped surface under a point relative to `surface`,
+    /// with the point in that surface's coordinates
+    ///
+    /// Surface sizes include viewport scaling, so the point lands where the
+    /// client drew it. Surfaces are passed through outside their input
+    /// region.
+    pub fn surface_at(&self, surface: SurfaceId, x: f64, y: f64) -> Option<(SurfaceId, f64, f64)> {
+        self.stacking_order(surface, (0, 0))
+            .into_iter()
+            .rev()
+            .find_map(|(id, (sx, sy))| {
+                let surface = self.get(id)?;
+                let (width, height) = surface.size()?;
+                let (lx, ly) = (x - sx as f64, y - sy as f64);
+                let inside = lx >= 0.0 && ly >= 0.0 && lx < width as f64 && ly < height as f64;
+                let accepts = surface
+                    .input_region
+                    .as_ref()
+                    .is_none_or(|region| region.contains(lx, ly));
+                (inside && accepts).then_some((id, lx, ly))
+            })
+    }
+
+    fn stack(
+        &self,
+        surface: SurfaceId,
+        origin: (i32, i32),
+        order: &mut Vec<(SurfaceId, (i32, i32))>,
+    ) {
+        let Some(current) = self.get(surface) else {
+            return;
+        };
+        let child_origin = |child: SurfaceId| {
+            let (x, y) = self.get(child).map(|c| c.position).unwrap_or_default();
+            (origin.0 + x, origin.1 + y)
+        };
+        for &child in &current.children_below {
+            self.stack(child, child_origin(child), order);
+        }
+        order.push((surface, origin));
+        for &child in &current.children {
+            self.stack(child, child_origin(child), order);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::surface::BufferInfo;
+
+    fn buffer(width: u32) -> Option<BufferInfo> {
+        Some(BufferInfo {
+            width,
+            height: 10,
+            stride: width * 4,
+            format: 0,
+            offset: 0,
+            shm_buffer_id: None,
+            solid: None,
+            snapshot: None,
+        })
+    }
+
+    #[test]
+    fn test_create_subsurface() {
+        let mut surfaces = SurfaceManager::new();
+        let parent = surfaces.create_surface();
+        let child = surfaces.create_surface();
+
+        assert_eq!(
+            surfaces.create_subsurface(parent, parent),
+            Err(SubsurfaceError::BadParent)
+        );
+        surfaces.create_subsurface(child, parent).unwrap();
+        assert_eq!(
+            surfaces.create_subsurface(child, parent),
+            Err(SubsurfaceError::RoleAlreadySet)
+        );
+        // A parent can't become a subsurface of its own subsurface
+        assert_eq!(
+            surfaces.create_subsurface(parent, child),
+            Err(SubsurfaceError::BadParent)
+        );
+        assert!(surfaces.is_synchronized(child));
+
+        surfaces.destroy_subsurface(child);
+        assert!(surfaces.get(parent).unwrap().children.is_empty());
+        assert!(!surfaces.is_synchronized(child));
+    }
+
+    #[test]
+    fn test_sync_commit_waits_for_parent() {
+        let mut surfaces = SurfaceManager::new();
+        let parent = surfaces.create_surface();
+        let child = surfaces.create_surface();
+        surfaces.create_subsurface(child, parent).unwrap();
+
+        surfaces.get_mut(child).unwrap().attach(buffer(10));
+        surfaces.set_subsurface_position(child, 5, 6);
+        assert!(surfaces.commit(child).is_empty());
+        assert!(surfaces.get(child).unwrap().buffer.is_none());
+
+        // The parent's commit applies the cached state and the position
+        assert_eq!(surfaces.commit(parent), vec![parent, child]);
+        let child_surface = surfaces.get(child).unwrap();
+        assert_eq!(child_surface.buffer.as_ref().map(|b| b.width), Some(10));
+        assert_eq!(child_surface.position, (5, 6));
+
+        // Desynchronized commits apply right away
+        surfaces.set_subsurface_sync(child, false);
+        surfaces.get_mut(child).unwrap().attach(buffer(20));
+        assert_eq!(surfaces.commit(child), vec![child]);
+        assert_eq!(
+            surfaces
+                .get(child)
+                .unwrap()
+                .buffer
+                .as_ref()
+                .map(|b| b.width),
+            Some(20)
+        );
+    }
+
+    #[test]
+    fn test_stacking_order() {
+        let mut surfaces = SurfaceManager::new();
+        let parent = surfaces.create_surface();
+        let a = surfaces.create_surface();
+        let b = surfaces.create_surface();
+        surfaces.create_subsurface(a, parent).unwrap();
+        surfaces.create_subsurface(b, parent).unwrap();
+        surfaces.set_subsurface_position(b, 3, 4);
+        surfaces.commit(parent);
+
+        let ids = |order: Vec<(SurfaceId, (i32, i32))>| -> Vec<SurfaceId> {
+            order.into_iter().map(|(id, _)| id).collect()
+        };
+        let order = surfaces.stacking_order(parent, (10, 10));
+        assert_eq!(order[2], (b, (13, 14)));
+        assert_eq!(ids(order), vec![parent, a, b]);
+
+        // Restacking waits for the parent's commit
+        surfaces
+            .place_subsurface(b, Placement::Below(parent))
+            .unwrap();
+        surfaces.place_subsurface(a, Placement::Above(b)).unwrap();
+        assert_eq!(
+            ids(surfaces.stacking_order(parent, (0, 0))),
+            vec![parent, a, b]
+        );
+        surfaces.commit(parent);
+        assert_eq!(
+            ids(surfaces.stacking_order(parent, (0, 0))),
+            vec![b, a, parent]
+        );
+
+        let other = surfaces.create_surface();
+        assert_eq!(
+            surfaces.place_subsurface(a, Placement::Above(other)),
+            Err(SubsurfaceError::NotSibling)
+        );
+    }
+
+    #[test]
+    fn test_surface_at_respects_viewport() {
+        let mut surfaces = SurfaceManager::new();
+        let parent = surfaces.create_surface();
+        let child = surfaces.create_surface();
+        surfaces.create_subsurface(child, parent).unwrap();
+        surfaces.set_subsurface_sync(child, false);
+        surfaces.set_subsurface_position(child, 5, 0);
+        surfaces.get_mut(parent).unwrap().attach(buffer(40));
+
+        // A 10x10 buffer scaled up to 20x20
+        let surface = surfaces.get_mut(child).unwrap();
+        surface.attach(buffer(10));
+        surface.update_viewport(|viewport| viewport.destination = Some((20, 20)));
+        surfaces.commit(child);
+        surfaces.commit(parent);
+
+        assert_eq!(
+            surfaces.surface_at(parent, 20.0, 15.0),
+            Some((child, 15.0, 15.0))
+        );
+        assert_eq!(
+            surfaces.surface_at(parent, 30.0, 5.0),
+            Some((parent, 30.0, 5.0))
+        );
+        assert_eq!(surfaces.surface_at(parent, 30.0, 15.0), None);
+
+        // Points outside the child's input region go to the parent
+        let mut region = crate::compositor::Region::default();
+        region.add(crate::compositor::surface::DamageRect {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 20,
+        });
+        surfaces.get_mut(child).unwrap().input_region = Some(region);
+        assert_eq!(
+            surfaces.surface_at(parent, 20.0, 5.0),
+            Some((parent, 20.0, 5.0))
+        );
+        assert_eq!(
+            surfaces.surface_at(parent, 10.0, 5.0),
+            Some((child, 5.0, 5.0))
+        );
+    }
+}
@@ -3,8 +3,15 @@
 //! This module handles Wayland surface tracking, damage regions,
 //! and buffer attachment.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::damage::{BufferGeometry, Viewport};
+use super::output::OutputTransform;
+use super::region::Region;
+use super::subsurface::{Placement, SubsurfaceState};
 
 /// Unique identifier for surfaces
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -18,7 +25,7 @@ impl SurfaceId {
 }
 
 /// A damage region on a surface
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DamageRect {
     pub x: i32,
     pub y: i32,
@@ -41,6 +48,102 @@ pub struct BufferInfo {
     pub offset: u32,
     /// SHM buffer ID (for accessing pixel data)
     pub shm_buffer_id: Option<u64>,
+    /// Color of a single-pixel buffer, premultiplied RGBA from 0 to 1
+    pub solid: Option<[f32; 4]>,
+    /// Copy of the pixels made at commit, read instead of the client's
+    /// memory
+    pub snapshot: Option<BufferSnapshot>,
+}
+
+/// Pixels of an shm buffer copied when it was committed
+///
+/// Holds `stride * height` bytes, laid out like the buffer.
+#[derive(Clone)]
+pub struct BufferSnapshot {
+    /// The shm buffer the pixels were copied from
+    pub shm_buffer_id: u64,
+    /// The copied pixels, shared with uploads in flight
+    pub data: Arc<Vec<u8>>,
+}
+
+impl BufferSnapshot {
+    /// Copy a committed buffer, reusing the previous snapshot
+    ///
+    /// Only the damaged rows of each damage rectangle are copied when the
+    /// previous snapshot is of the same buffer and isn't being uploaded;
+    /// otherwise the whole buffer is.
+    pub fn update(
+        previous: Option<BufferSnapshot>,
+        buffer: &BufferInfo,
+        bytes_per_pixel: u32,
+        source: &[u8],
+        damage: &[DamageRect],
+    ) -> Option<Self> {
+        let shm_buffer_id = buffer.shm_buffer_id?;
+        let len = (buffer.stride * buffer.height) as usize;
+        let source = source.get(..len)?;
+        let reusable = previous.filter(|previous| {
+            previous.shm_buffer_id == shm_buffer_id && previous.data.len() == len
+        });
+        let Some(mut data) = reusable.and_then(|previous| Arc::try_unwrap(previous.data).ok())
+        else {
+            return Some(Self {
+                shm_buffer_id,
+                data: Arc::new(source.to_vec()),
+            });
+        };
+
+        let bytes_per_pixel = bytes_per_pixel as i64;
+        let row_bytes = (buffer.width as i64 * bytes_per_pixel).min(buffer.stride as i64);
+        for rect in damage {
+            let x0 = (rect.x as i64 * bytes_per_pixel).clamp(0, row_bytes);
+            let x1 = ((rect.x as i64 + rect.width as i64) * bytes_per_pixel).clamp(0, row_bytes);
+            let y0 = (rect.y as i64).clamp(0, buffer.height as i64);
+            let y1 = (rect.y as i64 + rect.height as i64).clamp(0, buffer.height as i64);
+            for y in y0..y1 {
+                let row = (y * buffer.stride as i64) as usize;
+                let range = row + x0 as usize..row + x1 as usize;
+                data[range.clone()].copy_from_slice(&source[range]);
+            }
+        }
+        Some(Self {
+            shm_buffer_id,
+            data: Arc::new(data),
+        })
+    }
+}
+
+impl std::fmt::Debug for BufferSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferSnapshot")
+            .field("shm_buffer_id", &self.shm_buffer_id)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+impl BufferInfo {
+    /// A single-pixel buffer of one color, premultiplied RGBA from 0 to 1
+    pub fn single_pixel(color: [f32; 4]) -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            stride: 4,
+            format: 0, // ARGB8888
+            offset: 0,
+            shm_buffer_id: None,
+            solid: Some(color),
+            snapshot: None,
+        }
+    }
+
+    /// The pixel of a single-pixel buffer as ARGB8888 bytes
+    pub fn solid_pixel(&self) -> Option<[u8; 4]> {
+        let [r, g, b, a] = self
+            .solid?
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        Some([b, g, r, a])
+    }
 }
 
 /// Pending state for a surface (not yet committed)
@@ -48,14 +151,53 @@ pub struct BufferInfo {
 pub struct SurfacePendingState {
     /// Pending buffer attachment
     pub buffer: Option<BufferInfo>,
-    /// Accumulated damage regions
+    /// Accumulated damage regions, in surface coordinates
     pub damage: Vec<DamageRect>,
-    /// Buffer transform
-    pub transform: i32,
-    /// Buffer scale factor
-    pub scale: i32,
+    /// Accumulated damage regions, in buffer coordinates
+    pub buffer_damage: Vec<DamageRect>,
+    /// Buffer transform, if changed
+    pub transform: Option<i32>,
+    /// Buffer scale factor, if changed
+    pub scale: Option<i32>,
+    /// Viewport, if changed
+    pub viewport: Option<Viewport>,
     /// Frame callbacks to be fired
     pub frame_callbacks: Vec<u32>,
+    /// Subsurface restacking, applied with this surface's state
+    pub placements: Vec<(SurfaceId, Placement)>,
+    /// Set the fifo barrier once applied
+    pub fifo_barrier: bool,
+    /// Wait for the fifo barrier to clear before applying
+    pub fifo_wait: bool,
+    /// Earliest time to show the state, on the protocol clock
+    pub target_time: Option<Duration>,
+    /// Alpha multiplier, if changed
+    pub alpha_multiplier: Option<u32>,
+    /// Input region, if changed; None inside accepts input everywhere
+    pub input_region: Option<Option<Region>>,
+}
+
+impl SurfacePendingState {
+    /// Fold newer pending state into this one
+    pub fn merge(&mut self, newer: SurfacePendingState) {
+        if newer.buffer.is_some() {
+            self.buffer = newer.buffer;
+        }
+        self.damage.extend(newer.damage);
+        self.buffer_damage.extend(newer.buffer_damage);
+        self.transform = newer.transform.or(self.transform);
+        self.scale = newer.scale.or(self.scale);
+        self.viewport = newer.viewport.or(self.viewport);
+        self.frame_callbacks.extend(newer.frame_callbacks);
+        self.placements.extend(newer.placements);
+        self.fifo_barrier |= newer.fifo_barrier;
+        self.fifo_wait |= newer.fifo_wait;
+        self.target_time = newer.target_time.or(self.target_time);
+        self.alpha_multiplier = newer.alpha_multiplier.or(self.alpha_multiplier);
+        if newer.input_region.is_some() {
+            self.input_region = newer.input_region;
+        }
+    }
 }
 
 /// A Wayland surface
@@ -65,20 +207,39 @@ pub struct Surface {
     pub id: SurfaceId,
     /// Current buffer info
     pub buffer: Option<BufferInfo>,
-    /// Current damage regions
+    /// Damage of the last commit, in buffer coordinates
     pub damage: Vec<DamageRect>,
     /// Buffer transform
     pub transform: i32,
     /// Buffer scale factor (default 1)
     pub scale: i32,
+    /// Viewport cropping and scaling
+    pub viewport: Viewport,
+    /// Factor applied to the surface's alpha, from 0 (transparent) to
+    /// u32::MAX (opaque)
+    pub alpha_multiplier: u32,
+    /// Part of the surface accepting pointer and touch input, all of it if
+    /// None
+    pub input_region: Option<Region>,
     /// Pending state (not yet committed)
     pub pending: SurfacePendingState,
     /// Role-specific data (e.g., xdg_surface role)
     pub role: SurfaceRole,
     /// Parent surface (for subsurfaces)
     pub parent: Option<SurfaceId>,
-    /// Child subsurfaces
+    /// Position relative to the parent surface (for subsurfaces)
+    pub position: (i32, i32),
+    /// Child subsurfaces and popups stacked above, bottom to top
     pub children: Vec<SurfaceId>,
+    /// Child subsurfaces stacked below, bottom to top
+    pub children_below: Vec<SurfaceId>,
+    /// Subsurface state, if this is a subsurface
+    pub subsurface: Option<SubsurfaceState>,
+    /// A fifo barrier is set until the next latching deadline
+    pub fifo_barrier: bool,
+    /// Committed state waiting for the fifo barrier to clear or for its
+    /// target time, oldest first
+    pub commit_queue: VecDeque<SurfacePendingState>,
 }
 
 /// Surface role determines how the surface is used
@@ -97,6 +258,10 @@ pub enum SurfaceRole {
     Cursor,
     /// Layer shell surface
     LayerSurface,
+    /// Input method popup
+    InputPopup,
+    /// Surface of an X11 toplevel, drawn by Xwayland
+    Xwayland,
 }
 
 impl Surface {
@@ -108,10 +273,18 @@ impl Surface {
             damage: Vec::new(),
             transform: 0,
             scale: 1,
+            viewport: Viewport::default(),
+            alpha_multiplier: u32::MAX,
+            input_region: None,
             pending: SurfacePendingState::default(),
             role: SurfaceRole::None,
             parent: None,
+            position: (0, 0),
             children: Vec::new(),
+            children_below: Vec::new(),
+            subsurface: None,
+            fifo_barrier: false,
+            commit_queue: VecDeque::new(),
         }
     }
 
@@ -120,7 +293,7 @@ impl Surface {
         self.pending.buffer = buffer;
     }
 
-    /// Add damage to the pending state
+    /// Add damage in surface coordinates to the pending state
     pub fn damage(&mut self, x: i32, y: i32, width: i32, height: i32) {
         self.pending.damage.push(DamageRect {
             x,
@@ -130,6 +303,16 @@ impl Surface {
         });
     }
 
+    /// Add damage in buffer coordinates to the pending state
+    pub fn damage_buffer(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.pending.buffer_damage.push(DamageRect {
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+
     /// Add a frame callback
     pub fn frame(&mut self, callback_id: u32) {
         self.pending.frame_callbacks.push(callback_id);
@@ -137,12 +320,51 @@ impl Surface {
 
     /// Set the buffer scale
     pub fn set_scale(&mut self, scale: i32) {
-        self.pending.scale = scale;
+        self.pending.scale = Some(scale);
     }
 
     /// Set the buffer transform
     pub fn set_transform(&mut self, transform: i32) {
-        self.pending.transform = transform;
+        self.pending.transform = Some(transform);
+    }
+
+    /// Set the viewport
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.pending.viewport = Some(viewport);
+    }
+
+    /// Change the pending viewport, starting from the current one
+    pub fn update_viewport(&mut self, update: impl FnOnce(&mut Viewport)) {
+        let mut viewport = self.pending.viewport.unwrap_or(self.viewport);
+        update(&mut viewport);
+        self.set_viewport(viewport);
+    }
+
+    /// Opacity of the whole surface, from 0.0 to 1.0
+    pub fn opacity(&self) -> f32 {
+        (self.alpha_multiplier as f64 / u32::MAX as f64) as f32
+    }
+
+    /// Size of the mapped surface, None without a buffer
+    pub fn size(&self) -> Option<(i32, i32)> {
+        self.buffer.as_ref()?;
+        Some(self.buffer_geometry().surface_size())
+    }
+
+    /// How the current buffer maps onto the surface
+    pub fn buffer_geometry(&self) -> BufferGeometry {
+        let (width, height) = self
+            .buffer
+            .as_ref()
+            .map(|b| (b.width as i32, b.height as i32))
+            .unwrap_or((0, 0));
+        BufferGeometry {
+            width,
+            height,
+            scale: self.scale,
+            transform: OutputTransform::from_wayland(self.transform as u32).unwrap_or_default(),
+            viewport: self.viewport,
+        }
     }
 
     /// Commit pending state to current state
@@ -151,20 +373,46 @@ impl Surface {
             self.buffer = self.pending.buffer.take();
         }
 
-        if !self.pending.damage.is_empty() {
-            self.damage = std::mem::take(&mut self.pending.damage);
+        if let Some(scale) = self.pending.scale.take() {
+            self.scale = scale;
+        }
+
+        if let Some(transform) = self.pending.transform.take() {
+            self.transform = transform;
         }
 
-        if self.pending.scale != 0 {
-            self.scale = self.pending.scale;
-            self.pending.scale = 0;
+        if let Some(viewport) = self.pending.viewport.take() {
+            self.viewport = viewport;
         }
 
-        if self.pending.transform != 0 {
-            self.transform = self.pending.transform;
-            self.pending.transform = 0;
+        if let Some(multiplier) = self.pending.alpha_multiplier.take() {
+            self.alpha_multiplier = multiplier;
         }
 
+        if let Some(region) = self.pending.input_region.take() {
+            self.input_region = region;
+        }
+
+        // Surface damage is converted with the state being committed
+        self.damage = std::mem::take(&mut self.pending.buffer_damage);
+        let surface_damage = std::mem::take(&mut self.pending.damage);
+        match self.buffer.is_some() {
+            true => {
+                let geometry = self.buffer_geometry();
+                self.damage.extend(
+                    surface_damage
+                        .into_iter()
+                        .map(|rect| geometry.surface_to_buffer(rect)),
+                );
+            }
+            // Nothing to map onto yet
+            false => self.damage.extend(surface_damage),
+        }
+
+        self.fifo_barrier |= std::mem::take(&mut self.pending.fifo_barrier);
+        self.pending.fifo_wait = false;
+        self.pending.target_time = None;
+
         // Frame callbacks are handled separately by the caller
     }
 
@@ -216,9 +464,24 @@ impl SurfaceManager {
         self.surfaces.get_mut(&id)
     }
 
-    /// Remove a surface
+    /// Find the toplevel surface a subsurface or popup belongs to
+    pub fn root(&self, mut id: SurfaceId) -> SurfaceId {
+        while let Some(parent) = self.get(id).and_then(|surface| surface.parent) {
+            id = parent;
+        }
+        id
+    }
+
+    /// Remove a surface, detaching it from its parent and its children
     pub fn remove(&mut self, id: SurfaceId) -> Option<Surface> {
-        self.surfaces.remove(&id)
+        self.detach(id);
+        let surface = self.surfaces.remove(&id)?;
+        for child in surface.children.iter().chain(&surface.children_below) {
+            if let Some(child) = self.surfaces.get_mut(child) {
+                child.parent = None;
+            }
+        }
+        Some(surface)
     }
 
     /// Get all surfaces
@@ -271,6 +534,103 @@ mod tests {
         assert_eq!(surface.damage.len(), 1);
     }
 
+    #[test]
+    fn test_surface_alpha_multiplier() {
+        let mut surface = Surface::new();
+        assert_eq!(surface.opacity(), 1.0);
+        surface.pending.alpha_multiplier = Some(u32::MAX / 2);
+        assert_eq!(surface.opacity(), 1.0);
+        surface.commit();
+        assert!((surface.opacity() - 0.5).abs() < 1e-6);
+
+        // Unchanged by commits that don't set it
+        surface.commit();
+        assert!((surface.opacity() - 0.5).abs() < 1e-6);
+        surface.pending.alpha_multiplier = Some(0);
+        surface.commit();
+        assert_eq!(surface.opacity(), 0.0);
+    }
+
+    #[test]
+    fn test_buffer_snapshot() {
+        let buffer = BufferInfo {
+            width: 4,
+            height: 2,
+            stride: 16,
+            format: 0,
+            offset: 0,
+            shm_buffer_id: Some(1),
+            solid: None,
+            snapshot: None,
+        };
+        let first = BufferSnapshot::update(None, &buffer, 4, &[1; 32], &[]).unwrap();
+        assert_eq!(*first.data, vec![1; 32]);
+
+        // Only the damaged pixels are copied into an unshared snapshot
+        let damage = DamageRect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 5,
+        };
+        let second = BufferSnapshot::update(Some(first), &buffer, 4, &[2; 32], &[damage]).unwrap();
+        let expected: Vec<u8> = (0..32)
+            .map(|i| if (20..28).contains(&i) { 2 } else { 1 })
+            .collect();
+        assert_eq!(*second.data, expected);
+
+        // A snapshot still being uploaded is copied whole
+        let upload = second.clone();
+        let third = BufferSnapshot::update(Some(second), &buffer, 4, &[3; 32], &[damage]).unwrap();
+        assert_eq!(*third.data, vec![3; 32]);
+        assert_eq!(*upload.data, expected);
+        assert!(BufferSnapshot::update(None, &buffer, 4, &[0; 8], &[]).is_none());
+    }
+
+    #[test]
+    fn test_surface_and_buffer_damage() {
+        let mut surface = Surface::new();
+        surface.attach(Some(BufferInfo {
+            width: 200,
+            height: 100,
+            stride: 800,
+            format: 0,
+            offset: 0,
+            shm_buffer_id: None,
+            solid: None,
+            snapshot: None,
+        }));
+        surface.set_scale(2);
+        surface.damage(10, 10, 5, 5);
+        surface.damage_buffer(0, 0, 4, 4);
+        surface.commit();
+        assert_eq!(
+            surface.damage,
+            vec![
+                DamageRect {
+                    x: 0,
+                    y: 0,
+                    width: 4,
+                    height: 4
+                },
+                DamageRect {
+                    x: 20,
+                    y: 20,
+                    width: 10,
+                    height: 10
+                },
+            ]
+        );
+
+        // Damage doesn't carry over, and the transform can be reset
+        surface.set_transform(1);
+        surface.commit();
+        assert!(surface.damage.is_empty());
+        surface.set_transform(0);
+        surface.commit();
+        assert_eq!(surface.transform, 0);
+    }
+
     #[test]
     fn test_surface_manager() {
         let mut manager = SurfaceManager::new();
@@ -4,8 +4,15 @@
 
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::compositor::SurfaceId;
+use serde::{Deserialize, Serialize};
+
+use crate::compositor::{OutputId, SurfaceId, WindowIcon, Zoom};
+
+/// How long a client asked to close a window has to destroy it before its
+/// native window is closed, if the client stopped answering pings
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Unique identifier for windows
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -35,10 +42,49 @@ pub struct WindowState {
     pub resizing: bool,
     /// Window is being moved
     pub moving: bool,
+    /// The client asked for the user's attention
+    pub urgent: bool,
+}
+
+/// Who draws a window's titlebar and borders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecorationMode {
+    /// The client draws its own decorations in a borderless window
+    ClientSide,
+    /// The native titlebar decorates the window
+    #[default]
+    ServerSide,
+}
+
+impl DecorationMode {
+    /// Pick the mode for a toplevel
+    ///
+    /// Clients get the mode they ask for and the native titlebar otherwise,
+    /// so windows look at home on macOS. `force_server` (a quirk) overrides
+    /// the client's preference.
+    pub fn negotiate(requested: Option<DecorationMode>, force_server: bool) -> Self {
+        match requested {
+            _ if force_server => DecorationMode::ServerSide,
+            Some(mode) => mode,
+            None => DecorationMode::ServerSide,
+        }
+    }
+}
+
+/// Color filter drawn over a window's content, for light-only apps used in
+/// a dark environment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorFilter {
+    /// Invert every color
+    Invert,
+    /// Invert lightness but keep hues, so images and colored highlights
+    /// stay recognizable
+    Dark,
 }
 
 /// Window geometry
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WindowGeometry {
     /// X position
     pub x: i32,
@@ -50,6 +96,21 @@ pub struct WindowGeometry {
     pub height: u32,
 }
 
+/// A configure sent after the user resized a window's native window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeConfigure {
+    /// Serial of the configure
+    pub serial: u32,
+    /// Content size sent
+    pub size: (u32, u32),
+    /// Sent with the resizing state, during a live resize
+    pub resizing: bool,
+    /// The client acked the configure
+    pub acked: bool,
+    /// The client committed after acking it
+    pub committed: bool,
+}
+
 /// A native window representing a Wayland toplevel
 #[derive(Debug)]
 pub struct Window {
@@ -67,6 +128,8 @@ pub struct Window {
     pub fullscreen: bool,
     /// Window geometry
     pub geometry: WindowGeometry,
+    /// Screen placement to apply when the native window is created
+    pub placement: Option<WindowGeometry>,
     /// Minimum size (0 = no minimum)
     pub min_size: (u32, u32),
     /// Maximum size (0 = no maximum)
@@ -75,6 +138,26 @@ pub struct Window {
     pub state: WindowState,
     /// Parent window (for transient windows)
     pub parent: Option<WindowId>,
+    /// Marked as a modal dialog, blocking input to its parent
+    pub modal: bool,
+    /// Headless output the window is fullscreen on
+    pub output: Option<OutputId>,
+    /// Decoration mode, server-side unless negotiated otherwise
+    pub decoration: DecorationMode,
+    /// Magnification of the content, which the client isn't told about
+    pub zoom: Zoom,
+    /// When the client stopped answering pings, if it hasn't since
+    pub unresponsive_since: Option<std::time::Instant>,
+    /// Color filter set by a rule for the window's app_id
+    pub color_filter: Option<ColorFilter>,
+    /// Icon set by the client, if any
+    pub icon: Option<WindowIcon>,
+    /// Backs a layer-shell surface rather than a toplevel
+    pub layer_surface: bool,
+    /// Latest configure for the native window's size
+    pub resize: Option<ResizeConfigure>,
+    /// When the client was asked to close the window, if it hasn't decided
+    pub close_requested: Option<Instant>,
     /// Native window handle (platform-specific)
     #[cfg(target_os = "macos")]
     pub native_handle: Option<crate::backend::cocoa::window::NativeWindowHandle>,
@@ -93,10 +176,21 @@ impl Window {
             maximized: false,
             fullscreen: false,
             geometry: WindowGeometry::default(),
+            placement: None,
             min_size: (0, 0),
             max_size: (0, 0),
             state: WindowState::default(),
             parent: None,
+            modal: false,
+            output: None,
+            decoration: DecorationMode::default(),
+            zoom: Zoom::default(),
+            unresponsive_since: None,
+            color_filter: None,
+            icon: None,
+            layer_surface: false,
+            resize: None,
+            close_requested: None,
             native_handle: None,
         }
     }
@@ -155,6 +249,107 @@ impl Window {
     pub fn set_activated(&mut self, activated: bool) {
         self.state.activated = activated;
     }
+
+    /// Check if the native window's content size, or whether it is being
+    /// resized, should be sent to the client
+    ///
+    /// The first size seen is the one the window was created with, and is
+    /// only recorded. While a configure is in flight, newer sizes wait for
+    /// the client to commit, so a slow client isn't flooded.
+    pub fn wants_resize(&mut self, size: (u32, u32), resizing: bool) -> bool {
+        match self.resize {
+            None => {
+                self.resize = Some(ResizeConfigure {
+                    serial: 0,
+                    size,
+                    resizing,
+                    acked: true,
+                    committed: true,
+                });
+                false
+            }
+            Some(resize) => resize.committed && (resize.size, resize.resizing) != (size, resizing),
+        }
+    }
+
+    /// Record a configure sent for the native window's size
+    pub fn resize_configured(&mut self, serial: u32, size: (u32, u32), resizing: bool) {
+        self.resize = Some(ResizeConfigure {
+            serial,
+            size,
+            resizing,
+            acked: false,
+            committed: false,
+        });
+        self.state.resizing = resizing;
+    }
+
+    /// Record the client acking a configure
+    ///
+    /// Only an ack of the resize configure's own serial counts. Other
+    /// configures, e.g. for state changes, may not carry the new size.
+    pub fn ack_configure(&mut self, serial: u32) {
+        if let Some(resize) = self.resize.as_mut().filter(|r| r.serial == serial) {
+            resize.acked = true;
+        }
+    }
+
+    /// Record a commit of the window's surface, with its size
+    ///
+    /// Returns the size the native window should take when the client
+    /// acked the configure that ended a resize but chose another size,
+    /// e.g. because it only comes in steps.
+    pub fn resize_committed(&mut self, size: (u32, u32)) -> Option<(u32, u32)> {
+        let resize = self
+            .resize
+            .as_mut()
+            .filter(|resize| resize.acked && !resize.committed)?;
+        resize.committed = true;
+        if resize.resizing || resize.size == size {
+            return None;
+        }
+        resize.size = size;
+        Some(size)
+    }
+
+    /// Record asking the client to close the window
+    ///
+    /// Asking again doesn't extend the time the client has.
+    pub fn request_close(&mut self, now: Instant) {
+        self.close_requested.get_or_insert(now);
+    }
+
+    /// Check if the native window should be closed without waiting for the
+    /// client any longer
+    ///
+    /// That's once a client asked to close the window stayed unresponsive
+    /// for [`CLOSE_TIMEOUT`]. A responsive client that kept the window
+    /// open, e.g. to ask about unsaved changes, is taken to have declined,
+    /// and the request is forgotten.
+    pub fn close_expired(&mut self, now: Instant) -> bool {
+        let Some(requested) = self.close_requested else {
+            return false;
+        };
+        if now.duration_since(requested) < CLOSE_TIMEOUT {
+            return false;
+        }
+        if self.unresponsive_since.is_none() {
+            self.close_requested = None;
+            return false;
+        }
+        true
+    }
+}
+
+/// A window opening, closing or changing how it is identified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowChange {
+    /// The window was created
+    Opened(WindowId),
+    /// The window's title or app_id changed
+    Renamed(WindowId),
+    /// The window was removed
+    Closed(WindowId),
 }
 
 /// Manager for all windows
@@ -165,6 +360,12 @@ pub struct WindowManager {
     surface_to_window: HashMap<SurfaceId, WindowId>,
     /// Currently focused window
     focused_window: Option<WindowId>,
+    /// Changes not yet taken, oldest first
+    changes: Vec<WindowChange>,
+    /// Stacking order, bottom to top
+    stack: Vec<WindowId>,
+    /// Windows by when they were last focused, most recent first
+    recent: Vec<WindowId>,
 }
 
 impl WindowManager {
@@ -174,6 +375,9 @@ impl WindowManager {
             windows: HashMap::new(),
             surface_to_window: HashMap::new(),
             focused_window: None,
+            changes: Vec::new(),
+            stack: Vec::new(),
+            recent: Vec::new(),
         }
     }
 
@@ -183,6 +387,23 @@ impl WindowManager {
         let id = window.id;
         self.surface_to_window.insert(surface_id, id);
         self.windows.insert(id, window);
+        self.stack.push(id);
+        self.recent.push(id);
+        self.changes.push(WindowChange::Opened(id));
+        id
+    }
+
+    /// Create a window for a layer-shell surface
+    ///
+    /// Layer surfaces aren't toplevels, so the window isn't announced as
+    /// opened and stays out of the focus history.
+    pub fn create_layer_window(&mut self, surface_id: SurfaceId) -> WindowId {
+        let mut window = Window::new(surface_id);
+        window.layer_surface = true;
+        let id = window.id;
+        self.surface_to_window.insert(surface_id, id);
+        self.windows.insert(id, window);
+        self.stack.push(id);
         id
     }
 
@@ -217,18 +438,49 @@ impl WindowManager {
     }
 
     /// Remove a window
+    ///
+    /// Its children are given its parent.
     pub fn remove(&mut self, id: WindowId) -> Option<Window> {
         if let Some(window) = self.windows.remove(&id) {
             self.surface_to_window.remove(&window.surface_id);
+            for child in self.windows.values_mut() {
+                if child.parent == Some(id) {
+                    child.parent = window.parent;
+                }
+            }
+            self.stack.retain(|&other| other != id);
+            self.recent.retain(|&other| other != id);
             if self.focused_window == Some(id) {
                 self.focused_window = None;
             }
+            self.changes.push(WindowChange::Closed(id));
             Some(window)
         } else {
             None
         }
     }
 
+    /// Set a window's title
+    pub fn set_title(&mut self, id: WindowId, title: String) {
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.set_title(title);
+            self.changes.push(WindowChange::Renamed(id));
+        }
+    }
+
+    /// Set a window's app_id
+    pub fn set_app_id(&mut self, id: WindowId, app_id: String) {
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.set_app_id(app_id);
+            self.changes.push(WindowChange::Renamed(id));
+        }
+    }
+
+    /// Take the changes since they were last taken, oldest first
+    pub fn take_changes(&mut self) -> Vec<WindowChange> {
+        std::mem::take(&mut self.changes)
+    }
+
     /// Set the focused window
     pub fn set_focused(&mut self, id: Option<WindowId>) {
         // Unfocus previous window
@@ -241,25 +493,198 @@ impl WindowManager {
 
         self.focused_window = id;
 
-        // Focus new window
+        // Focus new window, which now has the user's attention and goes on
+        // top, like a key NSWindow
         if let Some(new_id) = id {
             if let Some(window) = self.windows.get_mut(&new_id) {
                 window.set_focused(true);
                 window.set_activated(true);
+                window.state.urgent = false;
+                self.raise(new_id);
+                self.recent.retain(|&other| other != new_id);
+                self.recent.insert(0, new_id);
             }
         }
     }
 
+    /// Put a window on top of the others
+    ///
+    /// Returns false if the window doesn't exist.
+    pub fn raise(&mut self, id: WindowId) -> bool {
+        if !self.windows.contains_key(&id) {
+            return false;
+        }
+        self.stack.retain(|&other| other != id);
+        self.stack.push(id);
+        true
+    }
+
+    /// Put a window below the others, keeping its focus
+    ///
+    /// Returns false if the window doesn't exist.
+    pub fn lower(&mut self, id: WindowId) -> bool {
+        if !self.windows.contains_key(&id) {
+            return false;
+        }
+        self.stack.retain(|&other| other != id);
+        self.stack.insert(0, id);
+        true
+    }
+
+    /// Follow the order of the native windows, front to back
+    ///
+    /// The listed windows are put in the places the stack has for them, in
+    /// the given order, and other windows keep their places. Returns true
+    /// if the order changed.
+    pub fn restack(&mut self, front_to_back: &[WindowId]) -> bool {
+        let mut listed = front_to_back
+            .iter()
+            .rev()
+            .filter(|id| self.windows.contains_key(id));
+        let before = self.stack.clone();
+        for slot in self.stack.iter_mut() {
+            if front_to_back.contains(slot) {
+                match listed.next() {
+                    Some(&id) => *slot = id,
+                    None => break,
+                }
+            }
+        }
+        self.stack != before
+    }
+
+    /// Window IDs in stacking order, bottom to top
+    pub fn stacking_order(&self) -> &[WindowId] {
+        &self.stack
+    }
+
+    /// The most recently focused window other than the focused one
+    pub fn previous(&self) -> Option<WindowId> {
+        self.recent
+            .iter()
+            .copied()
+            .find(|&id| Some(id) != self.focused_window)
+    }
+
+    /// Windows by when they were last focused, most recent first
+    ///
+    /// Windows never focused come last, oldest first.
+    pub fn most_recent(&self) -> impl Iterator<Item = &Window> {
+        self.recent.iter().filter_map(|id| self.windows.get(id))
+    }
+
+    /// Activate a window: restore it if minimized and focus it
+    ///
+    /// Returns false if the window doesn't exist.
+    pub fn activate(&mut self, id: WindowId) -> bool {
+        let Some(window) = self.windows.get_mut(&id) else {
+            return false;
+        };
+        window.set_minimized(false);
+        self.set_focused(Some(id));
+        true
+    }
+
+    /// Set or unset a window's parent
+    ///
+    /// Returns false if either window doesn't exist or the parent is the
+    /// window itself or one of its descendants.
+    pub fn set_parent(&mut self, id: WindowId, parent: Option<WindowId>) -> bool {
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(current) = ancestor {
+                if current == id {
+                    return false;
+                }
+                ancestor = self.windows.get(&current).and_then(|w| w.parent);
+            }
+            if !self.windows.contains_key(&parent) {
+                return false;
+            }
+        }
+        match self.windows.get_mut(&id) {
+            Some(window) => {
+                window.parent = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The modal dialog blocking input to a window, if any
+    ///
+    /// When dialogs are stacked, this is the innermost one, which is the
+    /// only window of the chain that takes input.
+    pub fn modal_dialog(&self, id: WindowId) -> Option<WindowId> {
+        let mut blocker = None;
+        let mut current = id;
+        while let Some(dialog) = self
+            .stack
+            .iter()
+            .rev()
+            .find(|&&other| {
+                self.windows
+                    .get(&other)
+                    .is_some_and(|w| w.modal && w.parent == Some(current))
+            })
+            .copied()
+        {
+            blocker = Some(dialog);
+            current = dialog;
+        }
+        blocker
+    }
+
+    /// Mark a window as needing attention
+    ///
+    /// Returns false if the window doesn't exist or is already focused,
+    /// since the user is looking at it.
+    pub fn request_attention(&mut self, id: WindowId) -> bool {
+        if self.focused_window == Some(id) {
+            return false;
+        }
+        let Some(window) = self.windows.get_mut(&id) else {
+            return false;
+        };
+        window.state.urgent = true;
+        true
+    }
+
+    /// Windows needing attention, oldest first
+    pub fn needing_attention(&self) -> Vec<&Window> {
+        let mut windows: Vec<_> = self
+            .windows
+            .values()
+            .filter(|window| window.state.urgent)
+            .collect();
+        windows.sort_by_key(|window| window.id.0);
+        windows
+    }
+
     /// Get the currently focused window
     pub fn focused(&self) -> Option<&Window> {
         self.focused_window.and_then(|id| self.windows.get(&id))
     }
 
+    /// Visible windows fullscreen on a headless output, bottom to top
+    pub fn on_output(&self, output: OutputId) -> Vec<&Window> {
+        self.stack
+            .iter()
+            .filter_map(|id| self.windows.get(id))
+            .filter(|window| window.output == Some(output) && !window.state.minimized)
+            .collect()
+    }
+
     /// Get all windows
     pub fn iter(&self) -> impl Iterator<Item = (&WindowId, &Window)> {
         self.windows.iter()
     }
 
+    /// Get all windows, mutably
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&WindowId, &mut Window)> {
+        self.windows.iter_mut()
+    }
+
     /// Get count of windows
     pub fn len(&self) -> usize {
         self.windows.len()
@@ -288,6 +713,51 @@ mod tests {
         assert_ne!(id1, id2);
     }
 
+    #[test]
+    fn test_resize_waits_for_commit() {
+        let mut window = Window::new(SurfaceId(1));
+        assert!(!window.wants_resize((640, 480), false));
+        assert!(window.wants_resize((700, 480), true));
+        window.resize_configured(10, (700, 480), true);
+        assert!(window.state.resizing);
+
+        // Newer sizes wait until the client acks and commits
+        assert!(!window.wants_resize((720, 480), true));
+        assert_eq!(window.resize_committed((640, 480)), None);
+        window.ack_configure(9);
+        assert_eq!(window.resize_committed((640, 480)), None);
+        window.ack_configure(12);
+        assert_eq!(window.resize_committed((640, 480)), None);
+        window.ack_configure(10);
+        assert_eq!(window.resize_committed((700, 480)), None);
+        assert!(window.wants_resize((720, 480), true));
+
+        // A client choosing another size at the end gets its way
+        window.resize_configured(11, (720, 480), false);
+        window.ack_configure(11);
+        assert_eq!(window.resize_committed((716, 480)), Some((716, 480)));
+        assert!(!window.state.resizing);
+        assert!(!window.wants_resize((716, 480), false));
+    }
+
+    #[test]
+    fn test_close_expires_while_unresponsive() {
+        let start = Instant::now();
+        let mut window = Window::new(SurfaceId(1));
+        assert!(!window.close_expired(start + CLOSE_TIMEOUT));
+
+        window.request_close(start);
+        window.request_close(start + Duration::from_secs(1));
+        window.unresponsive_since = Some(start);
+        assert!(!window.close_expired(start + Duration::from_secs(4)));
+        assert!(window.close_expired(start + CLOSE_TIMEOUT));
+
+        // A client that is back and kept the window declined
+        window.unresponsive_since = None;
+        assert!(!window.close_expired(start + CLOSE_TIMEOUT));
+        assert_eq!(window.close_requested, None);
+    }
+
     #[test]
     fn test_window_new() {
         let surface_id = SurfaceId(1);
@@ -307,6 +777,34 @@ mod tests {
         assert!(manager.get(id).is_none());
     }
 
+    #[test]
+    fn test_window_changes() {
+        let mut manager = WindowManager::new();
+        let id = manager.create_window(SurfaceId(1));
+        manager.set_title(id, "Terminal".to_string());
+        manager.set_app_id(WindowId(u64::MAX), "missing".to_string());
+        manager.remove(id);
+        assert_eq!(
+            manager.take_changes(),
+            vec![
+                WindowChange::Opened(id),
+                WindowChange::Renamed(id),
+                WindowChange::Closed(id)
+            ]
+        );
+        assert!(manager.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_layer_window() {
+        let mut manager = WindowManager::new();
+        let id = manager.create_layer_window(SurfaceId(1));
+        assert!(manager.get(id).unwrap().layer_surface);
+        assert_eq!(manager.window_for_surface(SurfaceId(1)), Some(id));
+        assert!(manager.take_changes().is_empty());
+        assert_eq!(manager.most_recent().count(), 0);
+    }
+
     #[test]
     fn test_window_focus() {
         let mut manager = WindowManager::new();
@@ -321,4 +819,104 @@ mod tests {
         assert!(!manager.get(id1).unwrap().state.focused);
         assert!(manager.get(id2).unwrap().state.focused);
     }
+
+    #[test]
+    fn test_attention_cleared_by_focus() {
+        let mut manager = WindowManager::new();
+        let id1 = manager.create_window(SurfaceId(1));
+        let id2 = manager.create_window(SurfaceId(2));
+        manager.set_focused(Some(id1));
+
+        assert!(!manager.request_attention(id1));
+        assert!(manager.request_attention(id2));
+        assert_eq!(manager.needing_attention().len(), 1);
+
+        manager.set_focused(Some(id2));
+        assert!(manager.needing_attention().is_empty());
+    }
+
+    #[test]
+    fn test_negotiate_decoration() {
+        use DecorationMode::*;
+        assert_eq!(DecorationMode::negotiate(None, false), ServerSide);
+        assert_eq!(
+            DecorationMode::negotiate(Some(ClientSide), false),
+            ClientSide
+        );
+        assert_eq!(
+            DecorationMode::negotiate(Some(ClientSide), true),
+            ServerSide
+        );
+        assert_eq!(Window::new(SurfaceId(1)).decoration, ServerSide);
+    }
+
+    #[test]
+    fn test_activate_restores_minimized() {
+        let mut manager = WindowManager::new();
+        let id1 = manager.create_window(SurfaceId(1));
+        let id2 = manager.create_window(SurfaceId(2));
+        manager.set_focused(Some(id1));
+        manager.get_mut(id2).unwrap().set_minimized(true);
+
+        assert!(manager.activate(id2));
+        let window = manager.get(id2).unwrap();
+        assert!(!window.state.minimized);
+        assert!(window.state.activated);
+        assert!(!manager.get(id1).unwrap().state.activated);
+        assert!(!manager.activate(WindowId(u64::MAX)));
+    }
+
+    #[test]
+    fn test_stacking_order() {
+        let mut manager = WindowManager::new();
+        let a = manager.create_window(SurfaceId(1));
+        let b = manager.create_window(SurfaceId(2));
+        let c = manager.create_window(SurfaceId(3));
+        assert_eq!(manager.stacking_order(), [a, b, c]);
+
+        // Focus raises, lowering keeps focus
+        manager.set_focused(Some(a));
+        manager.set_focused(Some(b));
+        assert_eq!(manager.stacking_order(), [c, a, b]);
+        assert!(manager.lower(b));
+        assert_eq!(manager.stacking_order(), [b, c, a]);
+        assert_eq!(manager.focused().map(|w| w.id), Some(b));
+        let recent: Vec<_> = manager.most_recent().map(|w| w.id).collect();
+        assert_eq!(recent, [b, a, c]);
+        assert_eq!(manager.previous(), Some(a));
+
+        // Native order only moves the windows it lists
+        assert!(manager.restack(&[b, c]));
+        assert_eq!(manager.stacking_order(), [c, b, a]);
+        assert!(!manager.restack(&[b, c]));
+        manager.remove(c);
+        assert_eq!(manager.stacking_order(), [b, a]);
+        manager.remove(a);
+        assert_eq!(manager.previous(), None);
+        assert!(!manager.raise(c));
+    }
+
+    #[test]
+    fn test_modal_dialog() {
+        let mut manager = WindowManager::new();
+        let parent = manager.create_window(SurfaceId(1));
+        let dialog = manager.create_window(SurfaceId(2));
+        let nested = manager.create_window(SurfaceId(3));
+        assert!(manager.set_parent(dialog, Some(parent)));
+        assert!(manager.set_parent(nested, Some(dialog)));
+        assert!(!manager.set_parent(parent, Some(nested)));
+        assert!(!manager.set_parent(parent, Some(parent)));
+        assert_eq!(manager.modal_dialog(parent), None);
+
+        manager.get_mut(dialog).unwrap().modal = true;
+        assert_eq!(manager.modal_dialog(parent), Some(dialog));
+        manager.get_mut(nested).unwrap().modal = true;
+        assert_eq!(manager.modal_dialog(parent), Some(nested));
+        assert_eq!(manager.modal_dialog(nested), None);
+
+        // Children of a removed window move to its parent
+        manager.remove(dialog);
+        assert_eq!(manager.get(nested).unwrap().parent, Some(parent));
+        assert_eq!(manager.modal_dialog(parent), Some(nested));
+    }
 }
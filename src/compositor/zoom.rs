@@ -0,0 +1,95 @@
+//! Per-window content zoom
+//!
+//! Zooming magnifies what a window shows without the client knowing: the
+//! window keeps its size, and only part of its content is stretched over
+//! it. The visible part is kept inside the content, centered on a point
+//! given as a fraction of the content size, so it applies the same to the
+//! buffer's pixels when presenting and to surface coordinates when mapping
+//! the pointer.
+
+/// Zoom state of a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zoom {
+    /// Magnification, 1 when not zoomed
+    factor: f64,
+    /// Point zoomed into, as a fraction of the content size
+    center: (f64, f64),
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            center: (0.5, 0.5),
+        }
+    }
+}
+
+impl Zoom {
+    /// Largest magnification
+    pub const MAX: f64 = 8.0;
+    /// Factor one zoom step in or out changes the magnification by
+    pub const STEP: f64 = 1.25;
+
+    /// Get the magnification
+    pub fn factor(&self) -> f64 {
+        self.factor
+    }
+
+    /// Check if the content is magnified
+    pub fn is_zoomed(&self) -> bool {
+        self.factor > 1.0
+    }
+
+    /// Set the magnification, clamped to `1..=MAX`
+    pub fn set_factor(&mut self, factor: f64) {
+        self.factor = if factor.is_finite() {
+            factor.clamp(1.0, Self::MAX)
+        } else {
+            1.0
+        };
+    }
+
+    /// Zoom into a point, as a fraction of the content size
+    pub fn set_center(&mut self, x: f64, y: f64) {
+        self.center = (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0));
+    }
+
+    /// Get the visible part of content of a size, as (x, y, width, height)
+    pub fn visible(&self, width: f64, height: f64) -> (f64, f64, f64, f64) {
+        let (w, h) = (width / self.factor, height / self.factor);
+        let x = (self.center.0 * width - w / 2.0).clamp(0.0, width - w);
+        let y = (self.center.1 * height - h / 2.0).clamp(0.0, height - h);
+        (x, y, w, h)
+    }
+
+    /// Map a point of the window to the content shown there
+    pub fn to_content(&self, size: (f64, f64), x: f64, y: f64) -> (f64, f64) {
+        let (vx, vy, ..) = self.visible(size.0, size.1);
+        (vx + x / self.factor, vy + y / self.factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zoom_mapping() {
+        let mut zoom = Zoom::default();
+        assert_eq!(zoom.to_content((200.0, 100.0), 50.0, 20.0), (50.0, 20.0));
+
+        zoom.set_factor(2.0);
+        assert_eq!(zoom.visible(200.0, 100.0), (50.0, 25.0, 100.0, 50.0));
+        assert_eq!(zoom.to_content((200.0, 100.0), 50.0, 20.0), (75.0, 35.0));
+
+        // The visible part stays inside the content
+        zoom.set_center(1.0, 0.0);
+        assert_eq!(zoom.visible(200.0, 100.0), (100.0, 0.0, 100.0, 50.0));
+
+        zoom.set_factor(100.0);
+        assert_eq!(zoom.factor(), Zoom::MAX);
+        zoom.set_factor(0.5);
+        assert!(!zoom.is_zoomed());
+    }
+}
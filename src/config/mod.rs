@@ -0,0 +1,704 @@
+//! Compositor configuration
+//!
+//! Wayoa reads an optional TOML file at startup. Every section has sensible
+//! defaults, so a missing file (or a missing section) behaves exactly like an
+//! empty configuration.
+//!
+//! The file is looked up in this order:
+//! - `$WAYOA_CONFIG`
+//! - `$XDG_CONFIG_HOME/wayoa/config.toml`
+//! - `$HOME/.config/wayoa/config.toml`
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info};
+use serde::Deserialize;
+
+use crate::compositor::{ColorFilter, CompositorAction};
+use crate::remote::Encoding;
+
+/// Top-level configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Screen edge hot corners
+    pub hot_corners: HotCornerConfig,
+    /// Session restore and autostart
+    pub session: SessionConfig,
+    /// Idle inhibitor rules
+    pub idle_inhibit: IdleInhibitConfig,
+    /// Client compatibility quirks
+    pub quirks: Vec<QuirkRule>,
+    /// Input thresholds
+    pub input: InputConfig,
+    /// Stall detection
+    pub watchdog: WatchdogConfig,
+    /// Client connection limits
+    pub clients: ClientLimitsConfig,
+    /// Per-client surface and region limits
+    pub resources: ResourceLimitsConfig,
+    /// Output used before a screen is known
+    pub default_output: DefaultOutputConfig,
+    /// Extra outputs without a screen
+    pub virtual_outputs: Vec<VirtualOutputConfig>,
+    /// Streaming of a headless output to remote viewers
+    pub remote: RemoteConfig,
+    /// Window switcher and launcher overlay
+    pub launcher: LauncherConfig,
+    /// QuickLook preview of the focused window
+    pub preview: PreviewConfig,
+    /// Zoom keybindings
+    pub zoom: ZoomConfig,
+    /// Trackpad contacts as touch points
+    pub touch_emulation: TouchEmulationConfig,
+    /// Named keybinding and hot corner profiles
+    pub bindings: BindingsConfig,
+    /// X11 clients through Xwayland
+    pub xwayland: XwaylandConfig,
+}
+
+/// Hot corner configuration
+///
+/// Each corner is optionally bound to a compositor action.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotCornerConfig {
+    /// Size of the trigger area in logical pixels
+    pub size: u32,
+    /// Time the pointer must rest in the corner before triggering (milliseconds)
+    pub delay_ms: u64,
+    /// Leave corners alone that macOS already uses for its own hot corners
+    pub respect_system: bool,
+    /// Action for the top-left corner
+    pub top_left: Option<CompositorAction>,
+    /// Action for the top-right corner
+    pub top_right: Option<CompositorAction>,
+    /// Action for the bottom-left corner
+    pub bottom_left: Option<CompositorAction>,
+    /// Action for the bottom-right corner
+    pub bottom_right: Option<CompositorAction>,
+}
+
+impl Default for HotCornerConfig {
+    fn default() -> Self {
+        Self {
+            size: 2,
+            delay_ms: 150,
+            respect_system: true,
+            top_left: None,
+            top_right: None,
+            bottom_left: None,
+            bottom_right: None,
+        }
+    }
+}
+
+/// Session configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// Re-launch the clients that were running at last shutdown
+    pub restore: bool,
+    /// Commands to launch at startup (split on whitespace)
+    pub autostart: Vec<String>,
+}
+
+/// Idle inhibitor configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleInhibitConfig {
+    /// App IDs whose idle inhibitors are ignored
+    pub ignore: Vec<String>,
+}
+
+/// Input configuration
+///
+/// Unset values follow the macOS system settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    /// Maximum time between the clicks of a double-click (milliseconds)
+    pub double_click_ms: Option<u64>,
+    /// Distance the pointer must move with a button held to start a drag
+    pub drag_threshold: Option<f64>,
+    /// Latch a modifier tapped on its own, and lock it when tapped twice,
+    /// while macOS Sticky Keys is off
+    pub sticky_keys: bool,
+}
+
+/// Client connection limits
+///
+/// Both limits are off unless set.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientLimitsConfig {
+    /// Maximum number of concurrently connected clients
+    pub max_clients: Option<usize>,
+    /// Maximum number of new connections per second
+    pub max_connections_per_sec: Option<u32>,
+}
+
+/// Per-client surface and region limits and leak detection
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceLimitsConfig {
+    /// Maximum number of surfaces per client
+    pub max_surfaces_per_client: Option<usize>,
+    /// Maximum number of regions per client
+    pub max_regions_per_client: Option<usize>,
+    /// Number of never-committed surfaces or live regions at which a client
+    /// is reported as leaking
+    pub leak_threshold: usize,
+    /// Time between leak checks (seconds)
+    pub check_interval_secs: u64,
+    /// Destroy role-less surfaces left behind by disconnected clients
+    pub collect_orphans: bool,
+}
+
+impl Default for ResourceLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_surfaces_per_client: None,
+            max_regions_per_client: None,
+            leak_threshold: 1000,
+            check_interval_secs: 60,
+            collect_orphans: false,
+        }
+    }
+}
+
+/// The default output's mode
+///
+/// Used for the output clients see when no screen is available yet, such
+/// as when wl_output is bound before the application has launched.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultOutputConfig {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Scale factor
+    pub scale: i32,
+    /// Refresh rate (mHz)
+    pub refresh_mhz: u32,
+}
+
+impl Default for DefaultOutputConfig {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            scale: 1,
+            refresh_mhz: 60_000,
+        }
+    }
+}
+
+/// A headless output
+///
+/// Virtual outputs are advertised to clients like any other output but are
+/// only rendered offscreen, for capture.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VirtualOutputConfig {
+    /// Output name, defaults to `virtual-<n>`
+    pub name: Option<String>,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Scale factor
+    pub scale: i32,
+    /// Refresh rate (mHz)
+    pub refresh_mhz: u32,
+}
+
+impl Default for VirtualOutputConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            width: 1920,
+            height: 1080,
+            scale: 1,
+            refresh_mhz: 60_000,
+        }
+    }
+}
+
+/// Remote access configuration
+///
+/// Off by default, and refused without a token. The stream isn't
+/// encrypted, so only listen on addresses reachable by trusted peers (e.g.
+/// through an SSH tunnel).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteConfig {
+    /// Accept remote viewers
+    pub enabled: bool,
+    /// Address to listen on
+    pub listen: String,
+    /// Token viewers must send before they are streamed to
+    pub token: Option<String>,
+    /// Headless output to stream, defaults to the first one
+    pub output: Option<String>,
+    /// Frame encoding, H.264 falls back to PNG without VideoToolbox
+    pub encoding: Encoding,
+    /// Maximum frames per second sent to viewers
+    pub max_fps: u32,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:5905".to_string(),
+            token: None,
+            output: None,
+            encoding: Encoding::H264,
+            max_fps: 30,
+        }
+    }
+}
+
+/// Xwayland configuration
+///
+/// Off by default. Once Xwayland is ready, clients spawned by wayoa get
+/// `DISPLAY` pointing at it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XwaylandConfig {
+    /// Run Xwayland for X11 clients
+    pub enabled: bool,
+    /// Xwayland executable, looked up in `PATH` if not absolute
+    pub path: String,
+}
+
+impl Default for XwaylandConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "Xwayland".to_string(),
+        }
+    }
+}
+
+/// Launcher overlay configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LauncherConfig {
+    /// Key that opens the launcher, e.g. `ctrl+alt+space`
+    pub keybinding: String,
+    /// Applications offered besides the open windows
+    pub apps: Vec<LauncherApp>,
+    /// Key that switches back to the previously focused window, e.g.
+    /// `alt+tab`
+    pub focus_previous: Option<String>,
+}
+
+impl Default for LauncherConfig {
+    fn default() -> Self {
+        Self {
+            keybinding: "ctrl+alt+space".to_string(),
+            apps: Vec::new(),
+            focus_previous: None,
+        }
+    }
+}
+
+/// QuickLook preview configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    /// Key that previews the focused window, e.g. `ctrl+alt+p`
+    pub keybinding: Option<String>,
+}
+
+/// Window zoom configuration
+///
+/// Each binding acts on the focused window and is unbound by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZoomConfig {
+    /// Key that magnifies the window by one step, e.g. `ctrl+alt+=`
+    pub zoom_in: Option<String>,
+    /// Key that shrinks the window by one step
+    pub zoom_out: Option<String>,
+    /// Key that shows the window's content at its size again
+    pub reset: Option<String>,
+}
+
+/// Touchscreen emulation configuration
+///
+/// When enabled, fingers on the trackpad become wl_touch points on the
+/// focused window, mapped to a region of it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TouchEmulationConfig {
+    /// Expose trackpad contacts as touch points
+    pub enabled: bool,
+    /// Part of the window the trackpad maps to, as `[x, y, width, height]`
+    /// fractions of its size
+    pub region: [f64; 4],
+    /// Most touch points at once
+    pub max_points: usize,
+}
+
+impl Default for TouchEmulationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            region: [0.0, 0.0, 1.0, 1.0],
+            max_points: 10,
+        }
+    }
+}
+
+/// Keybinding profiles
+///
+/// A profile binds keys and hot corners to actions on top of the bindings
+/// of the other sections, and can be switched while wayoa runs.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BindingsConfig {
+    /// Profile active at startup
+    pub profile: Option<String>,
+    /// Profiles by name, e.g. `mac-style` and `linux-style`
+    pub profiles: HashMap<String, BindingProfileConfig>,
+}
+
+/// A keybinding profile
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BindingProfileConfig {
+    /// Actions by key, e.g. `"cmd+space" = "show-launcher"`
+    pub keys: HashMap<String, CompositorAction>,
+    /// Action for the top-left corner, replacing the one in `[hot_corners]`
+    pub top_left: Option<CompositorAction>,
+    /// Action for the top-right corner
+    pub top_right: Option<CompositorAction>,
+    /// Action for the bottom-left corner
+    pub bottom_left: Option<CompositorAction>,
+    /// Action for the bottom-right corner
+    pub bottom_right: Option<CompositorAction>,
+}
+
+/// An application in the launcher
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LauncherApp {
+    /// Name shown and searched
+    pub name: String,
+    /// Command to run (split on whitespace)
+    pub command: String,
+}
+
+/// Watchdog configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// Watch the main loop for stalls
+    pub enabled: bool,
+    /// Time without progress before a stall is reported (seconds)
+    pub timeout_secs: u64,
+    /// Re-present all windows once the main loop recovers from a stall
+    pub restart_renderer: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_secs: 10,
+            restart_renderer: false,
+        }
+    }
+}
+
+/// A client compatibility quirk rule
+///
+/// A rule applies to clients whose executable (full path or file name) or
+/// toplevel app_id matches. A rule without either matcher applies to nothing.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkRule {
+    /// Executable to match
+    pub executable: Option<String>,
+    /// Application ID to match
+    pub app_id: Option<String>,
+    /// Maximum advertised version per global interface
+    pub max_versions: HashMap<String, u32>,
+    /// Always use server-side decorations
+    pub server_side_decorations: bool,
+    /// Scale to report in wl_output events
+    pub output_scale: Option<i32>,
+    /// Frames a window may have in flight, from 1 (lowest latency) to 3
+    pub frames_in_flight: Option<u32>,
+    /// Color filter drawn over the client's windows
+    pub color_filter: Option<ColorFilter>,
+    /// Copy buffers when they're committed, for clients that reuse them
+    /// before they're released
+    pub snapshot_buffers: bool,
+}
+
+impl QuirkRule {
+    /// Check if the rule matches a client
+    pub fn matches(&self, executable: Option<&str>, app_id: Option<&str>) -> bool {
+        let executable_matches = match (&self.executable, executable) {
+            (Some(pattern), Some(executable)) => {
+                pattern == executable
+                    || Path::new(executable)
+                        .file_name()
+                        .is_some_and(|name| name == pattern.as_str())
+            }
+            _ => false,
+        };
+        let app_id_matches = match (&self.app_id, app_id) {
+            (Some(pattern), Some(app_id)) => pattern == app_id,
+            _ => false,
+        };
+        executable_matches || app_id_matches
+    }
+}
+
+impl Config {
+    /// Parse a configuration from a TOML string
+    pub fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        toml::from_str(source).map_err(|e| ConfigError::Parse(e.to_string()))
+    }
+
+    /// Load the configuration from a file
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_path_buf(), e.to_string()))?;
+        let config = Self::from_toml(&source)?;
+        info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+
+    /// Load the configuration from the default location
+    ///
+    /// Returns the default configuration if no config file exists.
+    pub fn load() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            Some(path) => {
+                debug!("No config file at {}, using defaults", path.display());
+                Ok(Self::default())
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Get the default configuration file path
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("WAYOA_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        Self::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Get the wayoa configuration directory
+    pub fn config_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(base.join("wayoa"))
+    }
+}
+
+/// Configuration errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read {0}: {1}")]
+    Io(PathBuf, String),
+    #[error("Invalid configuration: {0}")]
+    Parse(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.hot_corners.size, 2);
+        assert!(config.hot_corners.top_left.is_none());
+    }
+
+    #[test]
+    fn test_hot_corner_config() {
+        let config = Config::from_toml(
+            r#"
+            [hot_corners]
+            delay_ms = 300
+            top_left = "show-overview"
+            bottom_right = "lock-session"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.hot_corners.delay_ms, 300);
+        assert_eq!(
+            config.hot_corners.top_left,
+            Some(CompositorAction::ShowOverview)
+        );
+        assert_eq!(
+            config.hot_corners.bottom_right,
+            Some(CompositorAction::LockSession)
+        );
+    }
+
+    #[test]
+    fn test_session_config() {
+        let config = Config::from_toml(
+            r#"
+            [session]
+            restore = true
+            autostart = ["foot", "weston-terminal"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.session.restore);
+        assert_eq!(config.session.autostart.len(), 2);
+        assert!(!Config::default().session.restore);
+    }
+
+    #[test]
+    fn test_quirk_rules() {
+        let config = Config::from_toml(
+            r#"
+            [[quirks]]
+            executable = "firefox"
+            max_versions = { wl_seat = 7 }
+            output_scale = 1
+            snapshot_buffers = true
+
+            [[quirks]]
+            app_id = "org.example.App"
+            server_side_decorations = true
+            color_filter = "invert"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.quirks.len(), 2);
+        assert_eq!(config.quirks[0].max_versions.get("wl_seat"), Some(&7));
+        assert!(config.quirks[0].snapshot_buffers);
+        assert!(config.quirks[0].matches(Some("/usr/lib/firefox/firefox"), None));
+        assert!(config.quirks[1].matches(None, Some("org.example.App")));
+        assert!(!config.quirks[1].matches(Some("org.example.App"), None));
+        assert_eq!(config.quirks[1].color_filter, Some(ColorFilter::Invert));
+    }
+
+    #[test]
+    fn test_virtual_outputs() {
+        let config = Config::from_toml(
+            r#"
+            [[virtual_outputs]]
+            name = "stream"
+            width = 2560
+            height = 1440
+            scale = 2
+
+            [[virtual_outputs]]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.virtual_outputs.len(), 2);
+        assert_eq!(config.virtual_outputs[0].name.as_deref(), Some("stream"));
+        assert_eq!(config.virtual_outputs[0].scale, 2);
+        assert_eq!(config.virtual_outputs[1].width, 1920);
+        assert_eq!(config.virtual_outputs[1].refresh_mhz, 60_000);
+    }
+
+    #[test]
+    fn test_default_output() {
+        let config =
+            Config::from_toml("[default_output]\nwidth = 1280\nheight = 800\nscale = 2").unwrap();
+        assert_eq!(config.default_output.width, 1280);
+        assert_eq!(config.default_output.scale, 2);
+        assert_eq!(config.default_output.refresh_mhz, 60_000);
+        assert_eq!(Config::default().default_output.height, 1080);
+    }
+
+    #[test]
+    fn test_remote() {
+        let config = Config::from_toml(
+            "[remote]\nenabled = true\noutput = \"stream\"\ntoken = \"secret\"\nencoding = \"png\"",
+        )
+        .unwrap();
+        assert!(config.remote.enabled);
+        assert_eq!(config.remote.output.as_deref(), Some("stream"));
+        assert_eq!(config.remote.token.as_deref(), Some("secret"));
+        assert_eq!(config.remote.encoding, Encoding::Png);
+        assert_eq!(config.remote.listen, "127.0.0.1:5905");
+        assert_eq!(Config::default().remote.encoding, Encoding::H264);
+        assert!(!Config::default().remote.enabled);
+    }
+
+    #[test]
+    fn test_xwayland() {
+        let config = Config::from_toml("[xwayland]\nenabled = true").unwrap();
+        assert!(config.xwayland.enabled);
+        assert_eq!(config.xwayland.path, "Xwayland");
+        assert!(!Config::default().xwayland.enabled);
+    }
+
+    #[test]
+    fn test_launcher() {
+        let config = Config::from_toml(
+            r#"
+            [launcher]
+            keybinding = "cmd+k"
+            apps = [{ name = "Terminal", command = "foot" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.launcher.keybinding, "cmd+k");
+        assert_eq!(config.launcher.apps[0].command, "foot");
+        assert_eq!(Config::default().launcher.keybinding, "ctrl+alt+space");
+        assert_eq!(Config::default().preview.keybinding, None);
+    }
+
+    #[test]
+    fn test_touch_emulation() {
+        let config =
+            Config::from_toml("[touch_emulation]\nenabled = true\nregion = [0, 0, 0.5, 0.5]")
+                .unwrap();
+        assert!(config.touch_emulation.enabled);
+        assert_eq!(config.touch_emulation.region, [0.0, 0.0, 0.5, 0.5]);
+        assert_eq!(config.touch_emulation.max_points, 10);
+    }
+
+    #[test]
+    fn test_binding_profiles() {
+        let config = Config::from_toml(
+            r#"
+            [bindings]
+            profile = "mac-style"
+
+            [bindings.profiles.mac-style]
+            keys = { "cmd+space" = "show-launcher" }
+            top_left = "show-overview"
+
+            [bindings.profiles.linux-style.keys]
+            "alt+tab" = "focus-previous"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.bindings.profile.as_deref(), Some("mac-style"));
+        let mac = &config.bindings.profiles["mac-style"];
+        assert_eq!(
+            mac.keys.get("cmd+space"),
+            Some(&CompositorAction::ShowLauncher)
+        );
+        assert_eq!(mac.top_left, Some(CompositorAction::ShowOverview));
+        assert_eq!(config.bindings.profiles["linux-style"].keys.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_field_rejected() {
+        assert!(Config::from_toml("[hot_corners]\nbogus = 1").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! Wayoa protocol extensions
+//!
+//! Bindings for the protocols in the `protocols/` directory, generated at
+//! build time by wayland-scanner. Each protocol module has a `server` side,
+//! used by the compositor, and a `client` side behind the `client` feature
+//! for third-party clients. The XML itself is in [`PROTOCOLS`], for clients
+//! that generate their own bindings.
+
+/// Generate the server and client modules of a protocol
+macro_rules! wayoa_protocol {
+    ($path:expr) => {
+        #[cfg(feature = "client")]
+        pub use self::generated::client;
+        pub use self::generated::server;
+
+        mod generated {
+            #![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+            #![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+            #![allow(missing_docs, clippy::all)]
+
+            #[cfg(feature = "client")]
+            pub mod client {
+                //! Client side of the protocol
+                use wayland_client;
+                use wayland_client::protocol::*;
+
+                pub mod __interfaces {
+                    use wayland_client::protocol::__interfaces::*;
+                    wayland_scanner::generate_interfaces!($path);
+                }
+                use self::__interfaces::*;
+
+                wayland_scanner::generate_client_code!($path);
+            }
+
+            pub mod server {
+                //! Server side of the protocol
+                use wayland_server;
+                use wayland_server::protocol::*;
+
+                pub mod __interfaces {
+                    use wayland_server::protocol::__interfaces::*;
+                    wayland_scanner::generate_interfaces!($path);
+                }
+                use self::__interfaces::*;
+
+                wayland_scanner::generate_server_code!($path);
+            }
+        }
+    };
+}
+
+pub mod info {
+    //! wayoa-info-v1: compositor identity and platform hints
+    wayoa_protocol!("protocols/wayoa-info-v1.xml");
+}
+
+pub mod permissions {
+    //! wayoa-permissions-v1: globals withheld from a tagged client
+    wayoa_protocol!("protocols/wayoa-permissions-v1.xml");
+}
+
+/// XML definition of an extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolXml {
+    /// Protocol name, as in the XML
+    pub name: &'static str,
+    /// File name in `protocols/`
+    pub file: &'static str,
+    /// Contents of the file
+    pub xml: &'static str,
+}
+
+/// All wayoa extensions
+pub const PROTOCOLS: &[ProtocolXml] = &[
+    ProtocolXml {
+        name: "wayoa_info_v1",
+        file: "wayoa-info-v1.xml",
+        xml: include_str!("../../protocols/wayoa-info-v1.xml"),
+    },
+    ProtocolXml {
+        name: "wayoa_permissions_v1",
+        file: "wayoa-permissions-v1.xml",
+        xml: include_str!("../../protocols/wayoa-permissions-v1.xml"),
+    },
+];
+
+/// Find an extension by protocol or file name
+pub fn find(name: &str) -> Option<&'static ProtocolXml> {
+    PROTOCOLS
+        .iter()
+        .find(|protocol| protocol.name == name || protocol.file == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayland_server::Resource;
+
+    #[test]
+    fn test_registry() {
+        for protocol in PROTOCOLS {
+            assert!(protocol
+                .xml
+                .contains(&format!("<protocol name=\"{}\">", protocol.name)));
+            assert_eq!(
+                protocol.file,
+                format!("{}.xml", protocol.name.replace('_', "-"))
+            );
+        }
+        assert_eq!(
+            find("wayoa-permissions-v1.xml"),
+            find("wayoa_permissions_v1")
+        );
+        assert!(find("wayland").is_none());
+        assert_eq!(
+            permissions::server::wayoa_permissions_v1::WayoaPermissionsV1::interface().name,
+            "wayoa_permissions_v1"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Click counting and drag detection
+//!
+//! Compositor-side gestures (titlebar double-click, starting an interactive
+//! move) should feel like the rest of macOS, so the double-click interval
+//! defaults to the system setting and both thresholds can be overridden in
+//! the configuration.
+
+use std::time::{Duration, Instant};
+
+use crate::config::InputConfig;
+
+/// Default double-click interval when the system value is unknown
+pub const DEFAULT_DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default distance the pointer must travel with a button held to start a drag
+pub const DEFAULT_DRAG_THRESHOLD: f64 = 3.0;
+
+/// Press that may turn into a multi-click or a drag
+#[derive(Debug, Clone, Copy)]
+struct LastPress {
+    button: u32,
+    position: (f64, f64),
+    time: Instant,
+    count: u32,
+}
+
+/// Click counter and drag detector
+#[derive(Debug)]
+pub struct ClickTracker {
+    /// Maximum time between clicks of a multi-click
+    double_click_interval: Duration,
+    /// Distance in logical pixels before a press becomes a drag
+    drag_threshold: f64,
+    /// Most recent press
+    last_press: Option<LastPress>,
+}
+
+impl ClickTracker {
+    /// Create a click tracker with default thresholds
+    pub fn new() -> Self {
+        Self {
+            double_click_interval: DEFAULT_DOUBLE_CLICK_INTERVAL,
+            drag_threshold: DEFAULT_DRAG_THRESHOLD,
+            last_press: None,
+        }
+    }
+
+    /// Apply configured overrides
+    pub fn configure(&mut self, config: &InputConfig) {
+        if let Some(ms) = config.double_click_ms {
+            self.double_click_interval = Duration::from_millis(ms);
+        }
+        if let Some(threshold) = config.drag_threshold {
+            self.drag_threshold = threshold.max(0.0);
+        }
+    }
+
+    /// Set the double-click interval reported by the system
+    ///
+    /// Ignored if the configuration overrides it.
+    pub fn set_system_double_click_interval(&mut self, interval: Duration, config: &InputConfig) {
+        if config.double_click_ms.is_none() {
+            self.double_click_interval = interval;
+        }
+    }
+
+    /// Get the double-click interval
+    pub fn double_click_interval(&self) -> Duration {
+        self.double_click_interval
+    }
+
+    /// Get the drag threshold
+    pub fn drag_threshold(&self) -> f64 {
+        self.drag_threshold
+    }
+
+    /// Record a button press and return its click count (1 = single click)
+    pub fn press(&mut self, button: u32, position: (f64, f64), now: Instant) -> u32 {
+        let count = match self.last_press {
+            Some(last)
+                if last.button == button
+                    && now.duration_since(last.time) <= self.double_click_interval
+                    && distance(last.position, position) <= self.drag_threshold =>
+            {
+                last.count + 1
+            }
+            _ => 1,
+        };
+        self.last_press = Some(LastPress {
+            button,
+            position,
+            time: now,
+            count,
+        });
+        count
+    }
+
+    /// Check if the pointer moved far enough from the last press to drag
+    pub fn is_drag(&self, position: (f64, f64)) -> bool {
+        self.last_press
+            .is_some_and(|last| distance(last.position, position) > self.drag_threshold)
+    }
+
+    /// Forget the last press, e.g. when focus moves elsewhere
+    pub fn reset(&mut self) {
+        self.last_press = None;
+    }
+}
+
+impl Default for ClickTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_double_click() {
+        let mut clicks = ClickTracker::new();
+        let start = Instant::now();
+        assert_eq!(clicks.press(0x110, (10.0, 10.0), start), 1);
+        assert_eq!(
+            clicks.press(0x110, (11.0, 10.0), start + Duration::from_millis(200)),
+            2
+        );
+        assert_eq!(
+            clicks.press(0x110, (11.0, 10.0), start + Duration::from_millis(400)),
+            3
+        );
+
+        // Too slow, different button, or too far away starts over
+        assert_eq!(
+            clicks.press(0x110, (11.0, 10.0), start + Duration::from_secs(2)),
+            1
+        );
+        let now = start + Duration::from_millis(2100);
+        assert_eq!(clicks.press(0x111, (11.0, 10.0), now), 1);
+        assert_eq!(clicks.press(0x111, (50.0, 10.0), now), 1);
+    }
+
+    #[test]
+    fn test_drag_threshold() {
+        let mut clicks = ClickTracker::new();
+        assert!(!clicks.is_drag((100.0, 100.0)));
+        clicks.press(0x110, (10.0, 10.0), Instant::now());
+        assert!(!clicks.is_drag((12.0, 11.0)));
+        assert!(clicks.is_drag((20.0, 10.0)));
+    }
+
+    #[test]
+    fn test_config_overrides_system() {
+        let config = InputConfig {
+            double_click_ms: Some(250),
+            drag_threshold: Some(8.0),
+            ..Default::default()
+        };
+        let mut clicks = ClickTracker::new();
+        clicks.configure(&config);
+        clicks.set_system_double_click_interval(Duration::from_millis(900), &config);
+        assert_eq!(clicks.double_click_interval(), Duration::from_millis(250));
+        assert_eq!(clicks.drag_threshold(), 8.0);
+
+        let mut clicks = ClickTracker::new();
+        clicks
+            .set_system_double_click_interval(Duration::from_millis(900), &InputConfig::default());
+        assert_eq!(clicks.double_click_interval(), Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,296 @@
+//! Screen edge hot corners
+//!
+//! Hot corners are detected from the global pointer position in compositor
+//! (top-left origin) coordinates. A corner triggers once the pointer has
+//! rested in it for the configured delay, and re-arms when the pointer leaves.
+//!
+//! The detector is fed on pointer motion, and once more when the delay of
+//! the corner the pointer rests in runs out.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::compositor::{CompositorAction, OutputManager};
+use crate::config::HotCornerConfig;
+
+/// A screen corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// All corners
+    pub const ALL: [Corner; 4] = [
+        Corner::TopLeft,
+        Corner::TopRight,
+        Corner::BottomLeft,
+        Corner::BottomRight,
+    ];
+}
+
+/// Hot corner detector
+#[derive(Debug)]
+pub struct HotCorners {
+    /// Size of the trigger area in logical pixels
+    size: f64,
+    /// Dwell time before triggering
+    delay: Duration,
+    /// Whether to skip corners used by macOS
+    respect_system: bool,
+    /// Action bound to each corner
+    bindings: HashMap<Corner, CompositorAction>,
+    /// Corners that macOS uses for its own hot corners
+    system_corners: Vec<Corner>,
+    /// Corner the pointer is currently in, and when it entered
+    current: Option<(Corner, Instant)>,
+    /// The current corner already triggered
+    triggered: bool,
+}
+
+impl HotCorners {
+    /// Create a hot corner detector from configuration
+    pub fn new(config: &HotCornerConfig) -> Self {
+        let mut corners = Self {
+            size: config.size.max(1) as f64,
+            delay: Duration::from_millis(config.delay_ms),
+            respect_system: config.respect_system,
+            bindings: HashMap::new(),
+            system_corners: Vec::new(),
+            current: None,
+            triggered: false,
+        };
+        corners.rebind(config, &[]);
+        corners
+    }
+
+    /// Bind the corners from configuration, with some of them replaced,
+    /// e.g. by a keybinding profile
+    pub fn rebind(&mut self, config: &HotCornerConfig, replaced: &[(Corner, CompositorAction)]) {
+        self.bindings = [
+            (Corner::TopLeft, config.top_left),
+            (Corner::TopRight, config.top_right),
+            (Corner::BottomLeft, config.bottom_left),
+            (Corner::BottomRight, config.bottom_right),
+        ]
+        .into_iter()
+        .filter_map(|(corner, action)| action.map(|a| (corner, a)))
+        .chain(replaced.iter().copied())
+        .collect();
+    }
+
+    /// Set the corners macOS uses for its own hot corners
+    pub fn set_system_corners(&mut self, corners: Vec<Corner>) {
+        self.system_corners = corners;
+    }
+
+    /// Get the action for a corner, if it is bound and not claimed by macOS
+    pub fn action(&self, corner: Corner) -> Option<CompositorAction> {
+        if self.respect_system && self.system_corners.contains(&corner) {
+            return None;
+        }
+        self.bindings.get(&corner).copied()
+    }
+
+    /// Check if any corner is active
+    pub fn is_enabled(&self) -> bool {
+        Corner::ALL.iter().any(|c| self.action(*c).is_some())
+    }
+
+    /// Find the corner of any output the point lies in
+    ///
+    /// Outputs span their logical size, like the pointer position does.
+    pub fn corner_at(&self, outputs: &OutputManager, x: f64, y: f64) -> Option<Corner> {
+        outputs.iter().find_map(|(_, output)| {
+            let (width, height) = output.logical_size();
+            let left = output.x as f64;
+            let top = output.y as f64;
+            let right = left + width as f64;
+            let bottom = top + height as f64;
+
+            if x < left || x >= right || y < top || y >= bottom {
+                return None;
+            }
+
+            let near_left = x < left + self.size;
+            let near_right = x >= right - self.size;
+            let near_top = y < top + self.size;
+            let near_bottom = y >= bottom - self.size;
+
+            match (near_left, near_right, near_top, near_bottom) {
+                (true, _, true, _) => Some(Corner::TopLeft),
+                (_, true, true, _) => Some(Corner::TopRight),
+                (true, _, _, true) => Some(Corner::BottomLeft),
+                (_, true, _, true) => Some(Corner::BottomRight),
+                _ => None,
+            }
+        })
+    }
+
+    /// Get when the corner the pointer rests in will trigger
+    ///
+    /// The pointer doesn't move while it rests, so the detector must be
+    /// updated again at that time.
+    pub fn trigger_at(&self) -> Option<Instant> {
+        let (_, entered) = self.current.filter(|_| !self.triggered)?;
+        Some(entered + self.delay)
+    }
+
+    /// Update with the current global pointer position
+    ///
+    /// Call on pointer motion and when [`HotCorners::trigger_at`] is
+    /// reached. Returns the action to perform when a corner triggers.
+    pub fn update(
+        &mut self,
+        outputs: &OutputManager,
+        x: f64,
+        y: f64,
+        now: Instant,
+    ) -> Option<CompositorAction> {
+        let corner = self
+            .corner_at(outputs, x, y)
+            .filter(|c| self.action(*c).is_some());
+
+        match (corner, self.current) {
+            (None, _) => {
+                self.current = None;
+                self.triggered = false;
+                None
+            }
+            (Some(corner), Some((current, _))) if current == corner => {
+                self.check_trigger(corner, now)
+            }
+            (Some(corner), _) => {
+                self.current = Some((corner, now));
+                self.triggered = false;
+                self.check_trigger(corner, now)
+            }
+        }
+    }
+
+    fn check_trigger(&mut self, corner: Corner, now: Instant) -> Option<CompositorAction> {
+        let (_, entered) = self.current?;
+        if self.triggered || now.duration_since(entered) < self.delay {
+            return None;
+        }
+        self.triggered = true;
+        let action = self.action(corner);
+        debug!("Hot corner {:?} triggered {:?}", corner, action);
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs() -> OutputManager {
+        let mut outputs = OutputManager::new();
+        outputs.create_output("test".to_string(), "Test".to_string(), "Test".to_string());
+        outputs
+    }
+
+    fn config() -> HotCornerConfig {
+        HotCornerConfig {
+            delay_ms: 100,
+            top_left: Some(CompositorAction::ShowOverview),
+            bottom_right: Some(CompositorAction::LockSession),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_corner_at() {
+        let corners = HotCorners::new(&config());
+        let outputs = outputs();
+        assert_eq!(corners.corner_at(&outputs, 0.0, 0.0), Some(Corner::TopLeft));
+        assert_eq!(
+            corners.corner_at(&outputs, 1919.0, 1079.0),
+            Some(Corner::BottomRight)
+        );
+        assert_eq!(corners.corner_at(&outputs, 500.0, 500.0), None);
+        assert_eq!(corners.corner_at(&outputs, 5000.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_corner_at_scaled_output() {
+        let corners = HotCorners::new(&config());
+        let mut outputs = OutputManager::new();
+        let id = outputs.create_output("test".to_string(), "Test".to_string(), "Test".to_string());
+        outputs.get_mut(id).unwrap().scale = 2;
+        assert_eq!(
+            corners.corner_at(&outputs, 959.0, 539.0),
+            Some(Corner::BottomRight)
+        );
+        assert_eq!(corners.corner_at(&outputs, 1919.0, 1079.0), None);
+    }
+
+    #[test]
+    fn test_trigger_after_delay() {
+        let mut corners = HotCorners::new(&config());
+        let outputs = outputs();
+        let start = Instant::now();
+
+        assert_eq!(corners.trigger_at(), None);
+        assert_eq!(corners.update(&outputs, 0.0, 0.0, start), None);
+        assert_eq!(
+            corners.trigger_at(),
+            Some(start + Duration::from_millis(100))
+        );
+        assert_eq!(
+            corners.update(&outputs, 0.0, 0.0, start + Duration::from_millis(150)),
+            Some(CompositorAction::ShowOverview)
+        );
+        assert_eq!(corners.trigger_at(), None);
+        // Only triggers once while resting in the corner
+        assert_eq!(
+            corners.update(&outputs, 0.0, 0.0, start + Duration::from_millis(300)),
+            None
+        );
+
+        // Leaving re-arms the corner
+        corners.update(&outputs, 500.0, 500.0, start + Duration::from_millis(400));
+        corners.update(&outputs, 0.0, 0.0, start + Duration::from_millis(500));
+        assert_eq!(
+            corners.update(&outputs, 0.0, 0.0, start + Duration::from_millis(700)),
+            Some(CompositorAction::ShowOverview)
+        );
+    }
+
+    #[test]
+    fn test_unbound_corner() {
+        let mut corners = HotCorners::new(&config());
+        let outputs = outputs();
+        let start = Instant::now();
+        corners.update(&outputs, 1919.0, 0.0, start);
+        assert_eq!(
+            corners.update(&outputs, 1919.0, 0.0, start + Duration::from_secs(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_respect_system_corners() {
+        let mut corners = HotCorners::new(&config());
+        corners.set_system_corners(vec![Corner::TopLeft]);
+        assert_eq!(corners.action(Corner::TopLeft), None);
+        assert_eq!(
+            corners.action(Corner::BottomRight),
+            Some(CompositorAction::LockSession)
+        );
+
+        let mut config = config();
+        config.respect_system = false;
+        let mut corners = HotCorners::new(&config);
+        corners.set_system_corners(vec![Corner::TopLeft]);
+        assert_eq!(
+            corners.action(Corner::TopLeft),
+            Some(CompositorAction::ShowOverview)
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! User idle tracking
+//!
+//! The user is idle for as long as no input has reached the compositor and,
+//! on macOS, no input has reached the system either, as reported by
+//! `CGEventSourceSecondsSinceLastEventType`. The system time covers input
+//! to native apps while the user isn't using any Wayland client.
+//!
+//! Clients register notifications with a timeout. Each one goes idle once
+//! the user has been idle that long, unless an idle inhibitor is active and
+//! the notification respects inhibitors, and resumes on the next input.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+/// Unique identifier for idle notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdleNotificationId(pub u64);
+
+impl IdleNotificationId {
+    fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(1);
+        IdleNotificationId(COUNTER.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A change of a notification's state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    /// The user has been idle for the notification's timeout
+    Idled,
+    /// The user is back
+    Resumed,
+}
+
+#[derive(Debug)]
+struct Notification {
+    timeout: Duration,
+    respects_inhibitors: bool,
+    idle: bool,
+}
+
+/// Idle time and client notifications
+#[derive(Debug)]
+pub struct IdleTracker {
+    /// Last input seen by the compositor
+    last_input: Instant,
+    /// Notifications by ID
+    notifications: HashMap<IdleNotificationId, Notification>,
+}
+
+impl IdleTracker {
+    /// Create a tracker counting idle time from now
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last_input: now,
+            notifications: HashMap::new(),
+        }
+    }
+
+    /// Add a notification
+    pub fn add(&mut self, timeout: Duration, respects_inhibitors: bool) -> IdleNotificationId {
+        let id = IdleNotificationId::new();
+        debug!("Idle notification {:?} after {:?}", id, timeout);
+        self.notifications.insert(
+            id,
+            Notification {
+                timeout,
+                respects_inhibitors,
+                idle: false,
+            },
+        );
+        id
+    }
+
+    /// Remove a notification
+    pub fn remove(&mut self, id: IdleNotificationId) {
+        self.notifications.remove(&id);
+    }
+
+    /// Record input, returning the notifications that resume
+    pub fn input(&mut self, now: Instant) -> Vec<IdleNotificationId> {
+        self.last_input = now;
+        self.notifications
+            .iter_mut()
+            .filter(|(_, notification)| notification.idle)
+            .map(|(&id, notification)| {
+                notification.idle = false;
+                id
+            })
+            .collect()
+    }
+
+    /// Get how long the user has been idle
+    ///
+    /// `system` is the time since the last input to the whole system, if
+    /// known.
+    pub fn idle_time(&self, now: Instant, system: Option<Duration>) -> Duration {
+        let compositor = now.saturating_duration_since(self.last_input);
+        system.map_or(compositor, |system| system.min(compositor))
+    }
+
+    /// Update the notifications for the current idle time
+    ///
+    /// Returns the notifications whose state changed.
+    pub fn update(
+        &mut self,
+        now: Instant,
+        system: Option<Duration>,
+        inhibited: bool,
+    ) -> Vec<(IdleNotificationId, IdleEvent)> {
+        let idle_time = self.idle_time(now, system);
+        let mut events = Vec::new();
+        for (&id, notification) in &mut self.notifications {
+            let elapsed = idle_time >= notification.timeout;
+            if notification.idle && !elapsed {
+                notification.idle = false;
+                events.push((id, IdleEvent::Resumed));
+            } else if !notification.idle
+                && elapsed
+                && !(inhibited && notification.respects_inhibitors)
+            {
+                notification.idle = true;
+                events.push((id, IdleEvent::Idled));
+            }
+        }
+        events
+    }
+
+    /// Get the number of notifications
+    pub fn len(&self) -> usize {
+        self.notifications.len()
+    }
+
+    /// Check if there are no notifications
+    pub fn is_empty(&self) -> bool {
+        self.notifications.is_empty()
+    }
+}
+
+/// Time since the last input to the system, from any source
+#[cfg(target_os = "macos")]
+pub fn system_idle_time() -> Option<Duration> {
+    /// kCGEventSourceStateHIDSystemState
+    const HID_SYSTEM_STATE: i32 = 1;
+    /// kCGAnyInputEventType
+    const ANY_INPUT_EVENT: u32 = u32::MAX;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(state: i32, event_type: u32) -> f64;
+    }
+
+    // SAFETY: a pure query without pointers
+    let seconds =
+        unsafe { CGEventSourceSecondsSinceLastEventType(HID_SYSTEM_STATE, ANY_INPUT_EVENT) };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Time since the last input to the system, from any source
+#[cfg(not(target_os = "macos"))]
+pub fn system_idle_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notifications() {
+        let start = Instant::now();
+        let mut tracker = IdleTracker::new(start);
+        let short = tracker.add(Duration::from_secs(10), true);
+        let input_only = tracker.add(Duration::from_secs(10), false);
+        let long = tracker.add(Duration::from_secs(60), true);
+
+        let at = |secs| start + Duration::from_secs(secs);
+        assert!(tracker.update(at(5), None, false).is_empty());
+
+        // Inhibitors hold back only the notifications respecting them
+        let events = tracker.update(at(20), None, true);
+        assert_eq!(events, vec![(input_only, IdleEvent::Idled)]);
+        let events = tracker.update(at(20), None, false);
+        assert_eq!(events, vec![(short, IdleEvent::Idled)]);
+
+        let mut resumed = tracker.input(at(30));
+        resumed.sort_by_key(|id| id.0);
+        assert_eq!(resumed, vec![short, input_only]);
+        assert!(tracker.update(at(35), None, false).is_empty());
+
+        // Input to the system counts too
+        tracker.update(at(45), None, false);
+        let events = tracker.update(at(46), Some(Duration::from_secs(1)), false);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|(_, event)| *event == IdleEvent::Resumed));
+
+        tracker.remove(long);
+        assert_eq!(tracker.len(), 2);
+    }
+}
@@ -0,0 +1,232 @@
+//! Compositor keybindings
+//!
+//! Bindings are written like `ctrl+alt+space`: any number of modifiers and
+//! one key, matched against Linux evdev keycodes and XKB modifier masks as
+//! produced by the input translator.
+
+use std::fmt;
+
+/// XKB modifier mask bits
+pub mod modifiers {
+    pub const SHIFT: u32 = 1;
+    pub const CTRL: u32 = 4;
+    pub const ALT: u32 = 8;
+    pub const LOGO: u32 = 64;
+}
+
+/// Keys that type a character, by evdev keycode
+const CHAR_KEYS: &[(u32, char)] = &[
+    (2, '1'),
+    (3, '2'),
+    (4, '3'),
+    (5, '4'),
+    (6, '5'),
+    (7, '6'),
+    (8, '7'),
+    (9, '8'),
+    (10, '9'),
+    (11, '0'),
+    (12, '-'),
+    (13, '='),
+    (16, 'q'),
+    (17, 'w'),
+    (18, 'e'),
+    (19, 'r'),
+    (20, 't'),
+    (21, 'y'),
+    (22, 'u'),
+    (23, 'i'),
+    (24, 'o'),
+    (25, 'p'),
+    (30, 'a'),
+    (31, 's'),
+    (32, 'd'),
+    (33, 'f'),
+    (34, 'g'),
+    (35, 'h'),
+    (36, 'j'),
+    (37, 'k'),
+    (38, 'l'),
+    (44, 'z'),
+    (45, 'x'),
+    (46, 'c'),
+    (47, 'v'),
+    (48, 'b'),
+    (49, 'n'),
+    (50, 'm'),
+    (52, '.'),
+    (53, '/'),
+    (57, ' '),
+];
+
+/// Named keys, by evdev keycode
+const NAMED_KEYS: &[(&str, u32)] = &[
+    ("escape", 1),
+    ("esc", 1),
+    ("backspace", 14),
+    ("tab", 15),
+    ("return", 28),
+    ("enter", 28),
+    ("space", 57),
+    ("f1", 59),
+    ("f2", 60),
+    ("f3", 61),
+    ("f4", 62),
+    ("f5", 63),
+    ("f6", 64),
+    ("f7", 65),
+    ("f8", 66),
+    ("f9", 67),
+    ("f10", 68),
+    ("f11", 87),
+    ("f12", 88),
+    ("up", 103),
+    ("left", 105),
+    ("right", 106),
+    ("down", 108),
+];
+
+/// The character a key types, ignoring modifiers
+pub fn key_char(key: u32) -> Option<char> {
+    CHAR_KEYS
+        .iter()
+        .find(|(code, _)| *code == key)
+        .map(|(_, c)| *c)
+}
+
+/// A key with modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    /// Required XKB modifier mask
+    pub modifiers: u32,
+    /// Evdev keycode
+    pub key: u32,
+}
+
+impl KeyBinding {
+    /// Parse a binding such as `ctrl+alt+space` or `cmd+k`
+    pub fn parse(binding: &str) -> Result<Self, KeyBindingError> {
+        let mut mods = 0;
+        let mut key = None;
+        for part in binding.split('+').map(|part| part.trim().to_lowercase()) {
+            let modifier = match part.as_str() {
+                "shift" => modifiers::SHIFT,
+                "ctrl" | "control" => modifiers::CTRL,
+                "alt" | "option" => modifiers::ALT,
+                "cmd" | "command" | "super" | "logo" => modifiers::LOGO,
+                _ => 0,
+            };
+            if modifier != 0 {
+                mods |= modifier;
+                continue;
+            }
+            if key.is_some() {
+                return Err(KeyBindingError::MultipleKeys(binding.to_string()));
+            }
+            let mut chars = part.chars();
+            let code = match (chars.next(), chars.next()) {
+                (Some(c), None) => CHAR_KEYS
+                    .iter()
+                    .find(|(_, key_char)| *key_char == c)
+                    .map(|(code, _)| *code),
+                _ => NAMED_KEYS
+                    .iter()
+                    .find(|(name, _)| *name == part)
+                    .map(|(_, code)| *code),
+            };
+            key = Some(code.ok_or(KeyBindingError::UnknownKey(part))?);
+        }
+
+        Ok(Self {
+            modifiers: mods,
+            key: key.ok_or(KeyBindingError::NoKey(binding.to_string()))?,
+        })
+    }
+
+    /// Check a key press against the binding
+    ///
+    /// Modifiers must match exactly, so `ctrl+space` doesn't fire on
+    /// `ctrl+shift+space`.
+    pub fn matches(&self, key: u32, modifiers: u32) -> bool {
+        let known = modifiers::SHIFT | modifiers::CTRL | modifiers::ALT | modifiers::LOGO;
+        self.key == key && self.modifiers == modifiers & known
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    /// Write the binding the way it is configured, e.g. `ctrl+alt+space`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = [
+            (modifiers::CTRL, "ctrl"),
+            (modifiers::ALT, "alt"),
+            (modifiers::SHIFT, "shift"),
+            (modifiers::LOGO, "cmd"),
+        ];
+        for (mask, name) in names {
+            if self.modifiers & mask != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match NAMED_KEYS.iter().find(|(_, code)| *code == self.key) {
+            Some((name, _)) => f.write_str(name),
+            None => match key_char(self.key) {
+                Some(c) => write!(f, "{}", c),
+                None => write!(f, "key{}", self.key),
+            },
+        }
+    }
+}
+
+/// Keybinding errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum KeyBindingError {
+    #[error("Unknown key: {0}")]
+    UnknownKey(String),
+    #[error("Binding has no key: {0}")]
+    NoKey(String),
+    #[error("Binding has more than one key: {0}")]
+    MultipleKeys(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_binding() {
+        let binding = KeyBinding::parse("ctrl+alt+space").unwrap();
+        assert_eq!(binding.modifiers, modifiers::CTRL | modifiers::ALT);
+        assert_eq!(binding.key, 57);
+        assert_eq!(KeyBinding::parse("Cmd+K").unwrap().key, 37);
+        assert_eq!(KeyBinding::parse("f12").unwrap().modifiers, 0);
+
+        assert!(matches!(
+            KeyBinding::parse("ctrl+bogus"),
+            Err(KeyBindingError::UnknownKey(_))
+        ));
+        assert!(matches!(
+            KeyBinding::parse("ctrl+alt"),
+            Err(KeyBindingError::NoKey(_))
+        ));
+        assert!(matches!(
+            KeyBinding::parse("a+b"),
+            Err(KeyBindingError::MultipleKeys(_))
+        ));
+    }
+
+    #[test]
+    fn test_binding_matches() {
+        let binding = KeyBinding::parse("ctrl+space").unwrap();
+        assert!(binding.matches(57, modifiers::CTRL));
+        // Caps Lock and other locks don't matter
+        assert!(binding.matches(57, modifiers::CTRL | 2));
+        assert!(!binding.matches(57, modifiers::CTRL | modifiers::SHIFT));
+        assert!(!binding.matches(30, modifiers::CTRL));
+        assert_eq!(key_char(30), Some('a'));
+
+        // Written back the way it is configured
+        assert_eq!(binding.to_string(), "ctrl+space");
+        let binding = KeyBinding::parse("Command+Shift+3").unwrap();
+        assert_eq!(binding.to_string(), "shift+cmd+3");
+    }
+}
@@ -2,7 +2,9 @@
 
 use log::debug;
 
+use super::sticky::StickyModifiers;
 use crate::compositor::SurfaceId;
+use crate::protocol::seat::KeyboardEvent;
 
 /// Keyboard state and XKB integration
 #[derive(Debug)]
@@ -11,18 +13,24 @@ pub struct Keyboard {
     focus: Option<SurfaceId>,
     /// Currently pressed keys (keycodes)
     pressed_keys: Vec<u32>,
-    /// Modifier state
+    /// Modifier state sent to clients
     modifiers: ModifierState,
+    /// Modifier state reported by the system
+    reported: ModifierState,
+    /// Latched and locked modifiers
+    sticky: StickyModifiers,
     /// Repeat rate (characters per second)
     repeat_rate: u32,
     /// Repeat delay (milliseconds)
     repeat_delay: u32,
     /// Keymap string (XKB format)
     keymap: Option<String>,
+    /// Bumped whenever the keymap is set
+    keymap_version: u64,
 }
 
 /// Keyboard modifier state
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct ModifierState {
     /// Depressed modifiers (currently held down)
     pub depressed: u32,
@@ -41,13 +49,19 @@ impl Keyboard {
             focus: None,
             pressed_keys: Vec::new(),
             modifiers: ModifierState::default(),
+            reported: ModifierState::default(),
+            sticky: StickyModifiers::new(),
             repeat_rate: 25,
             repeat_delay: 600,
             keymap: None,
+            keymap_version: 0,
         }
     }
 
     /// Set keyboard focus to a surface
+    ///
+    /// The returned change carries a snapshot of the pressed keys and
+    /// modifiers, which the newly focused surface must receive on enter.
     pub fn set_focus(&mut self, surface: Option<SurfaceId>) -> KeyboardFocusChange {
         let old_focus = self.focus;
         self.focus = surface;
@@ -56,6 +70,7 @@ impl Keyboard {
             old_focus,
             new_focus: surface,
             pressed_keys: self.pressed_keys.clone(),
+            modifiers: self.modifiers,
         }
     }
 
@@ -88,7 +103,7 @@ impl Keyboard {
 
     /// Update modifier state
     pub fn update_modifiers(&mut self, modifiers: ModifierState) {
-        self.modifiers = modifiers;
+        self.set_reported_modifiers(modifiers);
     }
 
     /// Get current modifier state
@@ -96,6 +111,31 @@ impl Keyboard {
         self.modifiers
     }
 
+    /// Update the modifier state reported by the system, adding the sticky
+    /// modifiers
+    ///
+    /// Returns true if the state sent to clients changed.
+    pub fn set_reported_modifiers(&mut self, reported: ModifierState) -> bool {
+        self.reported = reported;
+        let modifiers = self.sticky.apply(reported);
+        let changed = modifiers != self.modifiers;
+        self.modifiers = modifiers;
+        changed
+    }
+
+    /// Let sticky modifiers follow a key press or release
+    ///
+    /// Returns true if the state sent to clients changed.
+    pub fn sticky_key(&mut self, keycode: u32, pressed: bool) -> bool {
+        self.sticky.key(keycode, pressed, self.reported.depressed);
+        self.set_reported_modifiers(self.reported)
+    }
+
+    /// Get the sticky modifiers
+    pub fn sticky_mut(&mut self) -> &mut StickyModifiers {
+        &mut self.sticky
+    }
+
     /// Get currently pressed keys
     pub fn pressed_keys(&self) -> &[u32] {
         &self.pressed_keys
@@ -119,6 +159,12 @@ impl Keyboard {
     /// Set the keymap
     pub fn set_keymap(&mut self, keymap: String) {
         self.keymap = Some(keymap);
+        self.keymap_version += 1;
+    }
+
+    /// Get the keymap version, which changes whenever the keymap is set
+    pub fn keymap_version(&self) -> u64 {
+        self.keymap_version
     }
 
     /// Get the keymap
@@ -157,6 +203,49 @@ pub struct KeyboardFocusChange {
     pub new_focus: Option<SurfaceId>,
     /// Keys that were pressed when focus changed
     pub pressed_keys: Vec<u32>,
+    /// Modifiers that were active when focus changed
+    pub modifiers: ModifierState,
+}
+
+impl KeyboardFocusChange {
+    /// Check if focus actually moved
+    pub fn is_change(&self) -> bool {
+        self.old_focus != self.new_focus
+    }
+
+    /// Events to send for this focus change, in protocol order
+    ///
+    /// The old surface gets leave. The new surface gets enter with the keys
+    /// that are still held, followed by the current modifiers, so that it
+    /// doesn't miss a modifier pressed before it gained focus.
+    pub fn events(&self) -> Vec<KeyboardEvent> {
+        if !self.is_change() {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        if let Some(surface) = self.old_focus {
+            events.push(KeyboardEvent::Leave { surface });
+        }
+        if let Some(surface) = self.new_focus {
+            events.push(KeyboardEvent::Enter {
+                surface,
+                pressed_keys: self.pressed_keys.clone(),
+            });
+            events.push(KeyboardEvent::Modifiers {
+                depressed: self.modifiers.depressed,
+                latched: self.modifiers.latched,
+                locked: self.modifiers.locked,
+                group: self.modifiers.group,
+            });
+        }
+        events
+    }
+}
+
+/// Encode keycodes as the array argument of wl_keyboard.enter
+pub fn keys_to_array(keys: &[u32]) -> Vec<u8> {
+    keys.iter().flat_map(|key| key.to_ne_bytes()).collect()
 }
 
 #[cfg(test)]
@@ -207,6 +296,53 @@ mod tests {
         assert_eq!(change.new_focus, Some(surface2));
     }
 
+    #[test]
+    fn test_focus_change_snapshot() {
+        let mut keyboard = Keyboard::new();
+        keyboard.set_focus(Some(SurfaceId(1)));
+
+        // Shift held while focus moves
+        keyboard.key_press(42);
+        keyboard.update_modifiers(ModifierState {
+            depressed: 1,
+            ..Default::default()
+        });
+
+        let events = keyboard.set_focus(Some(SurfaceId(2))).events();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            events[0],
+            KeyboardEvent::Leave { surface } if surface == SurfaceId(1)
+        ));
+        assert!(matches!(
+            &events[1],
+            KeyboardEvent::Enter { surface, pressed_keys }
+                if *surface == SurfaceId(2) && pressed_keys == &[42]
+        ));
+        assert!(matches!(
+            events[2],
+            KeyboardEvent::Modifiers { depressed: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_focus_unchanged_has_no_events() {
+        let mut keyboard = Keyboard::new();
+        keyboard.set_focus(Some(SurfaceId(1)));
+        assert!(keyboard.set_focus(Some(SurfaceId(1))).events().is_empty());
+
+        let events = keyboard.set_focus(None).events();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], KeyboardEvent::Leave { .. }));
+    }
+
+    #[test]
+    fn test_keys_to_array() {
+        let array = keys_to_array(&[30, 42]);
+        assert_eq!(array.len(), 8);
+        assert_eq!(&array[..4], &30u32.to_ne_bytes());
+    }
+
     #[test]
     fn test_modifiers() {
         let mut keyboard = Keyboard::new();
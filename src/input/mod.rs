@@ -1,11 +1,30 @@
 //! Input handling module
 //!
-//! This module provides keyboard, pointer, and seat management.
+//! This module provides keyboard, pointer, and seat management, plus
+//! compositor-side input features such as hot corners, click counting,
+//! keybindings and their profiles, idle tracking, sticky modifiers,
+//! touchscreen emulation and graphics tablet tools.
 
+pub mod click;
+pub mod hot_corners;
+pub mod idle;
+pub mod keybinding;
 pub mod keyboard;
 pub mod pointer;
+pub mod profiles;
 pub mod seat;
+pub mod sticky;
+pub mod tablet;
+pub mod touch;
 
+pub use click::ClickTracker;
+pub use hot_corners::{Corner, HotCorners};
+pub use idle::{IdleEvent, IdleNotificationId, IdleTracker};
+pub use keybinding::{KeyBinding, KeyBindingError};
 pub use keyboard::Keyboard;
-pub use pointer::Pointer;
+pub use pointer::{CursorShape, Gesture, Pointer};
+pub use profiles::{BindingConflict, BindingProfile};
 pub use seat::Seat;
+pub use sticky::{StickyMode, StickyModifiers};
+pub use tablet::{Tablet, TabletEvent, TabletSample, TabletTool, TabletToolId, TabletToolKind};
+pub use touch::{Contact, TouchEmulator};
@@ -3,6 +3,7 @@
 use log::debug;
 
 use crate::compositor::SurfaceId;
+use crate::protocol::seat::{ButtonState, GestureEvent, GestureKind, PointerEvent};
 
 /// Pointer state
 #[derive(Debug)]
@@ -17,8 +18,27 @@ pub struct Pointer {
     cursor_surface: Option<SurfaceId>,
     /// Cursor hotspot
     cursor_hotspot: (i32, i32),
+    /// Named cursor, used instead of a cursor surface
+    cursor_shape: Option<CursorShape>,
     /// Grab state
     grab: Option<PointerGrab>,
+    /// Touchpad gesture in progress
+    gesture: Option<Gesture>,
+}
+
+/// A touchpad gesture in progress
+///
+/// Gestures stay with the surface they began on, like an implicit grab.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gesture {
+    /// Kind of gesture
+    pub kind: GestureKind,
+    /// Surface the gesture began on
+    pub surface: SurfaceId,
+    /// Fingers on the touchpad
+    pub fingers: u32,
+    /// Pinch scale relative to the start of the gesture
+    pub scale: f64,
 }
 
 /// Pointer grab state
@@ -45,6 +65,49 @@ pub enum GrabType {
     Resize(ResizeEdge),
 }
 
+/// A named cursor, from wp_cursor_shape_device_v1
+///
+/// The names follow the CSS cursor property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CursorShape {
+    Default,
+    ContextMenu,
+    Help,
+    Pointer,
+    Progress,
+    Wait,
+    Cell,
+    Crosshair,
+    Text,
+    VerticalText,
+    Alias,
+    Copy,
+    Move,
+    NoDrop,
+    NotAllowed,
+    Grab,
+    Grabbing,
+    EResize,
+    NResize,
+    NeResize,
+    NwResize,
+    SResize,
+    SeResize,
+    SwResize,
+    WResize,
+    EwResize,
+    NsResize,
+    NeswResize,
+    NwseResize,
+    ColResize,
+    RowResize,
+    AllScroll,
+    ZoomIn,
+    ZoomOut,
+    DndAsk,
+    AllResize,
+}
+
 /// Resize edge for resize grab
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResizeEdge {
@@ -68,7 +131,9 @@ impl Pointer {
             pressed_buttons: Vec::new(),
             cursor_surface: None,
             cursor_hotspot: (0, 0),
+            cursor_shape: None,
             grab: None,
+            gesture: None,
         }
     }
 
@@ -139,6 +204,19 @@ impl Pointer {
     pub fn set_cursor(&mut self, surface: Option<SurfaceId>, hotspot_x: i32, hotspot_y: i32) {
         self.cursor_surface = surface;
         self.cursor_hotspot = (hotspot_x, hotspot_y);
+        self.cursor_shape = None;
+    }
+
+    /// Set a named cursor, replacing the cursor surface
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_surface = None;
+        self.cursor_hotspot = (0, 0);
+        self.cursor_shape = Some(shape);
+    }
+
+    /// Get the named cursor, if the client asked for one
+    pub fn cursor_shape(&self) -> Option<CursorShape> {
+        self.cursor_shape
     }
 
     /// Get the cursor surface
@@ -174,6 +252,242 @@ impl Pointer {
     pub fn has_grab(&self) -> bool {
         self.grab.is_some()
     }
+
+    /// Get the touchpad gesture in progress
+    pub fn gesture(&self) -> Option<&Gesture> {
+        self.gesture.as_ref()
+    }
+
+    /// Start a touchpad gesture on the focused surface
+    ///
+    /// A gesture of another kind still in progress is cancelled first,
+    /// while beginning the gesture in progress again does nothing: AppKit
+    /// begins a pinch for both its magnify and its rotate events.
+    pub fn gesture_begin(
+        &mut self,
+        kind: GestureKind,
+        time: u32,
+        fingers: u32,
+    ) -> Vec<(SurfaceId, GestureEvent)> {
+        if self.gesture.is_some_and(|gesture| gesture.kind == kind) {
+            return Vec::new();
+        }
+        let mut events = self
+            .gesture
+            .map(|gesture| self.gesture_end(gesture.kind, time, true))
+            .unwrap_or_default();
+        if let Some(surface) = self.focus {
+            self.gesture = Some(Gesture {
+                kind,
+                surface,
+                fingers,
+                scale: 1.0,
+            });
+            events.push((
+                surface,
+                GestureEvent::Begin {
+                    kind,
+                    time,
+                    fingers,
+                },
+            ));
+        }
+        events
+    }
+
+    /// Update a swipe with the finger motion since the last update
+    pub fn gesture_swipe_update(
+        &mut self,
+        time: u32,
+        dx: f64,
+        dy: f64,
+    ) -> Option<(SurfaceId, GestureEvent)> {
+        let gesture = self.gesture.filter(|g| g.kind == GestureKind::Swipe)?;
+        Some((gesture.surface, GestureEvent::SwipeUpdate { time, dx, dy }))
+    }
+
+    /// Update a pinch
+    ///
+    /// `magnification` is the change in scale since the last update, as a
+    /// fraction, and `rotation` the clockwise rotation in degrees.
+    pub fn gesture_pinch_update(
+        &mut self,
+        time: u32,
+        dx: f64,
+        dy: f64,
+        magnification: f64,
+        rotation: f64,
+    ) -> Option<(SurfaceId, GestureEvent)> {
+        let gesture = self
+            .gesture
+            .as_mut()
+            .filter(|g| g.kind == GestureKind::Pinch)?;
+        gesture.scale = (gesture.scale * (1.0 + magnification)).max(0.0);
+        Some((
+            gesture.surface,
+            GestureEvent::PinchUpdate {
+                time,
+                dx,
+                dy,
+                scale: gesture.scale,
+                rotation,
+            },
+        ))
+    }
+
+    /// End a touchpad gesture, if one of that kind is in progress
+    pub fn gesture_end(
+        &mut self,
+        kind: GestureKind,
+        time: u32,
+        cancelled: bool,
+    ) -> Vec<(SurfaceId, GestureEvent)> {
+        match self.gesture.take_if(|gesture| gesture.kind == kind) {
+            Some(gesture) => vec![(
+                gesture.surface,
+                GestureEvent::End {
+                    kind,
+                    time,
+                    cancelled,
+                },
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    /// Surface that receives events regardless of what is under the cursor
+    fn grab_surface(&self) -> Option<SurfaceId> {
+        self.grab
+            .as_ref()
+            .filter(|grab| grab.grab_type == GrabType::Button)
+            .map(|grab| grab.surface)
+    }
+
+    /// Route pointer motion
+    ///
+    /// `under` is the surface below the cursor and `locate` maps the cursor
+    /// into the local coordinates of any surface. While a button grab is
+    /// active, motion goes to the grab surface even if the cursor left it,
+    /// and enter/leave are held back until the grab ends.
+    pub fn handle_motion(
+        &mut self,
+        time: u32,
+        under: Option<SurfaceId>,
+        locate: impl Fn(SurfaceId) -> (f64, f64),
+    ) -> Vec<(SurfaceId, PointerEvent)> {
+        let target = self.grab_surface().or(under);
+        let mut events = self.refocus(target, &locate);
+
+        if let Some(surface) = self.focus {
+            let (x, y) = locate(surface);
+            self.position = (x, y);
+            if events.is_empty() {
+                events.push((surface, PointerEvent::Motion { time, x, y }));
+            }
+            events.push((surface, PointerEvent::Frame));
+        }
+        events
+    }
+
+    /// Route a button press or release
+    ///
+    /// A press starts an implicit grab on the focused surface, which ends
+    /// when the last button is released. If the cursor ended up over another
+    /// surface in the meantime, focus moves there after the release.
+    pub fn handle_button(
+        &mut self,
+        time: u32,
+        serial: u32,
+        button: u32,
+        state: ButtonState,
+        under: Option<SurfaceId>,
+        locate: impl Fn(SurfaceId) -> (f64, f64),
+    ) -> Vec<(SurfaceId, PointerEvent)> {
+        let changed = match state {
+            ButtonState::Pressed => self.button_press(button),
+            ButtonState::Released => self.button_release(button),
+        };
+        let Some(surface) = self.focus else {
+            return Vec::new();
+        };
+        if !changed {
+            return Vec::new();
+        }
+
+        let mut events = vec![
+            (
+                surface,
+                PointerEvent::Button {
+                    time,
+                    button,
+                    state,
+                },
+            ),
+            (surface, PointerEvent::Frame),
+        ];
+
+        match state {
+            ButtonState::Pressed if !self.has_grab() => {
+                debug!("Implicit grab on {:?}", surface);
+                self.start_grab(surface, serial, GrabType::Button);
+            }
+            ButtonState::Released
+                if !self.has_button_pressed() && self.grab_surface().is_some() =>
+            {
+                debug!("Implicit grab on {:?} ended", surface);
+                self.end_grab();
+                let refocus = self.refocus(under, &locate);
+                if !refocus.is_empty() {
+                    events.extend(refocus);
+                    if let Some(surface) = self.focus {
+                        events.push((surface, PointerEvent::Frame));
+                    }
+                }
+            }
+            _ => {}
+        }
+        events
+    }
+
+    /// Route the axis events of one scroll to the focused surface
+    ///
+    /// Scrolling doesn't move focus, so nothing is sent without it.
+    pub fn handle_axis(&self, events: Vec<PointerEvent>) -> Vec<(SurfaceId, PointerEvent)> {
+        let Some(surface) = self.focus else {
+            return Vec::new();
+        };
+        events.into_iter().map(|event| (surface, event)).collect()
+    }
+
+    /// Move focus, returning leave/enter events if it changed
+    fn refocus(
+        &mut self,
+        target: Option<SurfaceId>,
+        locate: &impl Fn(SurfaceId) -> (f64, f64),
+    ) -> Vec<(SurfaceId, PointerEvent)> {
+        if target == self.focus {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        if let Some(old) = self.focus {
+            events.push((old, PointerEvent::Leave { surface: old }));
+            events.push((old, PointerEvent::Frame));
+        }
+        let (x, y) = target.map(locate).unwrap_or(self.position);
+        let change = self.set_focus(target, x, y);
+        if let Some(new) = change.new_focus {
+            events.push((
+                new,
+                PointerEvent::Enter {
+                    surface: new,
+                    x: change.x,
+                    y: change.y,
+                },
+            ));
+        }
+        events
+    }
 }
 
 impl Default for Pointer {
@@ -250,6 +564,114 @@ mod tests {
 
         assert_eq!(pointer.cursor(), Some(cursor_surface));
         assert_eq!(pointer.cursor_hotspot(), (10, 5));
+
+        // A named cursor replaces the surface, and the other way around
+        pointer.set_cursor_shape(CursorShape::Text);
+        assert_eq!(pointer.cursor(), None);
+        assert_eq!(pointer.cursor_shape(), Some(CursorShape::Text));
+        pointer.set_cursor(Some(cursor_surface), 0, 0);
+        assert_eq!(pointer.cursor_shape(), None);
+    }
+
+    fn locate(surface: SurfaceId) -> (f64, f64) {
+        (surface.0 as f64, 0.0)
+    }
+
+    #[test]
+    fn test_motion_changes_focus() {
+        let mut pointer = Pointer::new();
+        let events = pointer.handle_motion(0, Some(SurfaceId(1)), locate);
+        assert!(matches!(events[0].1, PointerEvent::Enter { .. }));
+        assert!(matches!(events.last().unwrap().1, PointerEvent::Frame));
+
+        let events = pointer.handle_motion(1, Some(SurfaceId(1)), locate);
+        assert!(matches!(
+            events[0],
+            (SurfaceId(1), PointerEvent::Motion { .. })
+        ));
+
+        let events = pointer.handle_motion(2, Some(SurfaceId(2)), locate);
+        assert!(matches!(
+            events[0],
+            (SurfaceId(1), PointerEvent::Leave { .. })
+        ));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, (SurfaceId(2), PointerEvent::Enter { .. }))));
+    }
+
+    #[test]
+    fn test_implicit_grab() {
+        let mut pointer = Pointer::new();
+        pointer.handle_motion(0, Some(SurfaceId(1)), locate);
+        pointer.handle_button(
+            1,
+            10,
+            0x110,
+            ButtonState::Pressed,
+            Some(SurfaceId(1)),
+            locate,
+        );
+        assert_eq!(pointer.grab().unwrap().grab_type, GrabType::Button);
+
+        // Dragging over another surface keeps delivering to the grab surface
+        let events = pointer.handle_motion(2, Some(SurfaceId(2)), locate);
+        assert!(events.iter().all(|(surface, _)| *surface == SurfaceId(1)));
+        assert!(matches!(events[0].1, PointerEvent::Motion { x, .. } if x == 1.0));
+        let events = pointer.handle_motion(3, None, locate);
+        assert!(events.iter().all(|(surface, _)| *surface == SurfaceId(1)));
+
+        // Releasing ends the grab and moves focus to what is under the cursor
+        let events = pointer.handle_button(
+            4,
+            11,
+            0x110,
+            ButtonState::Released,
+            Some(SurfaceId(2)),
+            locate,
+        );
+        assert!(matches!(
+            events[0],
+            (
+                SurfaceId(1),
+                PointerEvent::Button {
+                    state: ButtonState::Released,
+                    ..
+                }
+            )
+        ));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, (SurfaceId(2), PointerEvent::Enter { .. }))));
+        assert!(!pointer.has_grab());
+        assert_eq!(pointer.focus(), Some(SurfaceId(2)));
+    }
+
+    #[test]
+    fn test_grab_held_until_last_button() {
+        let mut pointer = Pointer::new();
+        pointer.handle_motion(0, Some(SurfaceId(1)), locate);
+        pointer.handle_button(
+            1,
+            10,
+            0x110,
+            ButtonState::Pressed,
+            Some(SurfaceId(1)),
+            locate,
+        );
+        pointer.handle_button(
+            2,
+            11,
+            0x111,
+            ButtonState::Pressed,
+            Some(SurfaceId(1)),
+            locate,
+        );
+        pointer.handle_button(3, 12, 0x110, ButtonState::Released, None, locate);
+        assert!(pointer.has_grab());
+        pointer.handle_button(4, 13, 0x111, ButtonState::Released, None, locate);
+        assert!(!pointer.has_grab());
+        assert_eq!(pointer.focus(), None);
     }
 
     #[test]
@@ -265,4 +687,64 @@ mod tests {
         pointer.end_grab();
         assert!(!pointer.has_grab());
     }
+
+    #[test]
+    fn test_gestures() {
+        let mut pointer = Pointer::new();
+        assert!(pointer.gesture_begin(GestureKind::Hold, 1, 2).is_empty());
+
+        pointer.set_focus(Some(SurfaceId(1)), 0.0, 0.0);
+        let events = pointer.gesture_begin(GestureKind::Hold, 1, 2);
+        assert_eq!(events.len(), 1);
+
+        // A pinch cancels the hold, and beginning it again does nothing
+        let events = pointer.gesture_begin(GestureKind::Pinch, 2, 2);
+        assert_eq!(
+            events[0].1,
+            GestureEvent::End {
+                kind: GestureKind::Hold,
+                time: 2,
+                cancelled: true
+            }
+        );
+        assert_eq!(events.len(), 2);
+        assert!(pointer.gesture_begin(GestureKind::Pinch, 3, 2).is_empty());
+
+        // The pinch stays on its surface and accumulates its scale
+        pointer.set_focus(Some(SurfaceId(2)), 0.0, 0.0);
+        pointer.gesture_pinch_update(4, 0.0, 0.0, 1.0, 0.0);
+        let (surface, update) = pointer
+            .gesture_pinch_update(5, 0.0, 0.0, -0.25, 5.0)
+            .unwrap();
+        assert_eq!(surface, SurfaceId(1));
+        assert!(matches!(update, GestureEvent::PinchUpdate { scale, .. } if scale == 1.5));
+        assert!(pointer.gesture_swipe_update(5, 1.0, 0.0).is_none());
+
+        assert!(pointer.gesture_end(GestureKind::Swipe, 6, false).is_empty());
+        assert_eq!(pointer.gesture_end(GestureKind::Pinch, 6, false).len(), 1);
+        assert!(pointer.gesture().is_none());
+    }
+
+    #[test]
+    fn test_axis_goes_to_focus() {
+        use crate::protocol::seat::AxisType;
+
+        let mut pointer = Pointer::new();
+        let scroll = || {
+            vec![
+                PointerEvent::Axis {
+                    time: 5,
+                    axis: AxisType::VerticalScroll,
+                    value: 10.0,
+                },
+                PointerEvent::Frame,
+            ]
+        };
+        assert!(pointer.handle_axis(scroll()).is_empty());
+
+        pointer.set_focus(Some(SurfaceId(1)), 0.0, 0.0);
+        let events = pointer.handle_axis(scroll());
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|(surface, _)| *surface == SurfaceId(1)));
+    }
 }
@@ -0,0 +1,218 @@
+//! Keybinding profiles
+//!
+//! A profile is a named set of keys and hot corners bound to compositor
+//! actions, such as a "mac-style" and a "linux-style" set, and one of them
+//! is active at a time. Profile keys are tried after the launcher,
+//! focus-previous, preview and zoom keys, and profile corners replace the
+//! configured ones.
+//!
+//! Conflicts are found between the keys the compositor binds, and with the
+//! shortcuts macOS takes for itself by default: those never reach wayoa
+//! unless they are turned off in the Keyboard settings.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use super::hot_corners::Corner;
+use super::keybinding::{KeyBinding, KeyBindingError};
+use crate::compositor::CompositorAction;
+use crate::config::BindingProfileConfig;
+
+/// Default macOS shortcuts and what they do
+const SYSTEM_SHORTCUTS: &[(&str, &str)] = &[
+    ("cmd+space", "Spotlight"),
+    ("cmd+alt+space", "Finder search window"),
+    ("cmd+tab", "App Switcher"),
+    ("ctrl+space", "Select the previous input source"),
+    ("ctrl+alt+space", "Select next source in Input menu"),
+    ("ctrl+up", "Mission Control"),
+    ("ctrl+down", "Application windows"),
+    ("ctrl+left", "Move left a space"),
+    ("ctrl+right", "Move right a space"),
+    ("shift+cmd+3", "Save picture of screen as a file"),
+    ("shift+cmd+4", "Save picture of selected area as a file"),
+    ("shift+cmd+5", "Screenshot and recording options"),
+    ("ctrl+cmd+q", "Lock Screen"),
+    ("alt+cmd+escape", "Force Quit"),
+    ("ctrl+f2", "Move focus to the menu bar"),
+    ("ctrl+f3", "Move focus to the Dock"),
+];
+
+/// An active keybinding profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingProfile {
+    /// Name of the profile
+    pub name: String,
+    /// Actions by key, in key order
+    pub keys: Vec<(KeyBinding, CompositorAction)>,
+    /// Actions by hot corner
+    pub corners: Vec<(Corner, CompositorAction)>,
+}
+
+impl BindingProfile {
+    /// Parse a configured profile
+    pub fn from_config(name: &str, config: &BindingProfileConfig) -> Result<Self, KeyBindingError> {
+        let mut keys = config
+            .keys
+            .iter()
+            .map(|(key, &action)| Ok((KeyBinding::parse(key)?, action)))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Configured keys come in no particular order
+        keys.sort_by_key(|(binding, _)| binding.to_string());
+        let corners = [
+            (Corner::TopLeft, config.top_left),
+            (Corner::TopRight, config.top_right),
+            (Corner::BottomLeft, config.bottom_left),
+            (Corner::BottomRight, config.bottom_right),
+        ]
+        .into_iter()
+        .filter_map(|(corner, action)| Some((corner, action?)))
+        .collect();
+        Ok(Self {
+            name: name.to_string(),
+            keys,
+            corners,
+        })
+    }
+
+    /// Get the action bound to a key press
+    pub fn key_action(&self, key: u32, modifiers: u32) -> Option<CompositorAction> {
+        self.keys
+            .iter()
+            .find(|(binding, _)| binding.matches(key, modifiers))
+            .map(|(_, action)| *action)
+    }
+}
+
+/// A problem with the active bindings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum BindingConflict {
+    /// A key bound to more than one action, of which the first wins
+    Duplicate {
+        key: String,
+        actions: Vec<&'static str>,
+    },
+    /// A key macOS uses by default, so wayoa doesn't see it
+    System {
+        key: String,
+        action: &'static str,
+        shortcut: &'static str,
+    },
+    /// The focused client inhibits compositor shortcuts
+    Inhibited { app_id: Option<String> },
+}
+
+impl fmt::Display for BindingConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingConflict::Duplicate { key, actions } => {
+                write!(f, "{} is bound to {}", key, actions.join(" and "))
+            }
+            BindingConflict::System {
+                key,
+                action,
+                shortcut,
+            } => write!(
+                f,
+                "{} ({}) is the macOS shortcut for {}",
+                key, action, shortcut
+            ),
+            BindingConflict::Inhibited { app_id } => write!(
+                f,
+                "{} inhibits compositor shortcuts",
+                app_id.as_deref().unwrap_or("the focused client")
+            ),
+        }
+    }
+}
+
+/// Find the keys bound twice or taken by macOS, in the order the bindings
+/// are tried
+pub fn find_conflicts(bindings: &[(KeyBinding, CompositorAction)]) -> Vec<BindingConflict> {
+    let mut conflicts = Vec::new();
+    for (i, (binding, action)) in bindings.iter().enumerate() {
+        let earlier = bindings[..i].iter().any(|(other, _)| other == binding);
+        if earlier {
+            continue;
+        }
+        let mut actions = vec![action.name()];
+        for (_, other) in bindings[i + 1..].iter().filter(|(b, _)| b == binding) {
+            if !actions.contains(&other.name()) {
+                actions.push(other.name());
+            }
+        }
+        let key = binding.to_string();
+        if let Some(&(_, shortcut)) = SYSTEM_SHORTCUTS
+            .iter()
+            .find(|(system, _)| KeyBinding::parse(system).ok() == Some(*binding))
+        {
+            conflicts.push(BindingConflict::System {
+                key: key.clone(),
+                action: action.name(),
+                shortcut,
+            });
+        }
+        if actions.len() > 1 {
+            conflicts.push(BindingConflict::Duplicate { key, actions });
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::keybinding::modifiers;
+
+    #[test]
+    fn test_profile_from_config() {
+        let mut config = BindingProfileConfig::default();
+        config
+            .keys
+            .insert("cmd+k".to_string(), CompositorAction::ShowLauncher);
+        config.top_left = Some(CompositorAction::ShowOverview);
+        let profile = BindingProfile::from_config("mac-style", &config).unwrap();
+        assert_eq!(
+            profile.key_action(37, modifiers::LOGO),
+            Some(CompositorAction::ShowLauncher)
+        );
+        assert_eq!(profile.key_action(37, modifiers::CTRL), None);
+        assert_eq!(
+            profile.corners,
+            vec![(Corner::TopLeft, CompositorAction::ShowOverview)]
+        );
+
+        config
+            .keys
+            .insert("cmd+bogus".to_string(), CompositorAction::ZoomIn);
+        assert!(BindingProfile::from_config("mac-style", &config).is_err());
+    }
+
+    #[test]
+    fn test_find_conflicts() {
+        let key = |binding: &str| KeyBinding::parse(binding).unwrap();
+        let bindings = [
+            (key("ctrl+alt+p"), CompositorAction::PreviewWindow),
+            (key("ctrl+alt+p"), CompositorAction::ZoomIn),
+            (key("ctrl+alt+p"), CompositorAction::ZoomIn),
+            (key("Command+Space"), CompositorAction::ShowLauncher),
+            (key("alt+tab"), CompositorAction::FocusPrevious),
+        ];
+        assert_eq!(
+            find_conflicts(&bindings),
+            vec![
+                BindingConflict::Duplicate {
+                    key: "ctrl+alt+p".to_string(),
+                    actions: vec!["preview-window", "zoom-in"],
+                },
+                BindingConflict::System {
+                    key: "cmd+space".to_string(),
+                    action: "show-launcher",
+                    shortcut: "Spotlight",
+                },
+            ]
+        );
+    }
+}
@@ -1,8 +1,10 @@
 //! Input seat coordination
 
+use std::collections::{HashSet, VecDeque};
+
 use log::debug;
 
-use super::{Keyboard, Pointer};
+use super::{ClickTracker, Keyboard, Pointer};
 use crate::compositor::SurfaceId;
 
 /// Input seat that coordinates keyboard and pointer
@@ -14,10 +16,23 @@ pub struct Seat {
     keyboard: Keyboard,
     /// Pointer device
     pointer: Pointer,
+    /// Click counting and drag detection
+    clicks: ClickTracker,
     /// Capabilities
     capabilities: SeatCapabilities,
+    /// Surfaces that asked for compositor shortcuts to be suspended while
+    /// they have keyboard focus
+    shortcut_inhibitors: HashSet<SurfaceId>,
+    /// Serials of the latest key and button presses, with the surface each
+    /// went to, newest last
+    recent_inputs: VecDeque<(u32, SurfaceId)>,
+    /// Serial of the latest pointer enter
+    pointer_enter: Option<u32>,
 }
 
+/// Number of input serials kept for validating activation tokens
+const RECENT_INPUTS: usize = 16;
+
 /// Seat capabilities
 #[derive(Debug, Clone, Copy, Default)]
 pub struct SeatCapabilities {
@@ -50,11 +65,15 @@ impl Seat {
             name: "seat0".to_string(),
             keyboard: Keyboard::new(),
             pointer: Pointer::new(),
+            clicks: ClickTracker::new(),
             capabilities: SeatCapabilities {
                 keyboard: true,
                 pointer: true,
                 touch: false,
             },
+            shortcut_inhibitors: HashSet::new(),
+            recent_inputs: VecDeque::new(),
+            pointer_enter: None,
         }
     }
 
@@ -101,6 +120,16 @@ impl Seat {
         &mut self.pointer
     }
 
+    /// Get click tracker reference
+    pub fn clicks(&self) -> &ClickTracker {
+        &self.clicks
+    }
+
+    /// Get mutable click tracker reference
+    pub fn clicks_mut(&mut self) -> &mut ClickTracker {
+        &mut self.clicks
+    }
+
     /// Focus a surface for both keyboard and pointer
     pub fn focus_surface(&mut self, surface: Option<SurfaceId>, x: f64, y: f64) {
         self.keyboard.set_focus(surface);
@@ -117,6 +146,55 @@ impl Seat {
     pub fn pointer_focus(&self) -> Option<SurfaceId> {
         self.pointer.focus()
     }
+
+    /// Remember the serial of a key or button press sent to a surface
+    pub fn record_input(&mut self, serial: u32, surface: SurfaceId) {
+        if self.recent_inputs.len() == RECENT_INPUTS {
+            self.recent_inputs.pop_front();
+        }
+        self.recent_inputs.push_back((serial, surface));
+    }
+
+    /// Get the surface a recent key or button press went to
+    pub fn input_surface(&self, serial: u32) -> Option<SurfaceId> {
+        self.recent_inputs
+            .iter()
+            .find(|&&(recent, _)| recent == serial)
+            .map(|&(_, surface)| surface)
+    }
+
+    /// Remember the serial of a pointer enter
+    pub fn record_pointer_enter(&mut self, serial: u32) {
+        self.pointer_enter = Some(serial);
+    }
+
+    /// Get the serial of the latest pointer enter
+    pub fn pointer_enter_serial(&self) -> Option<u32> {
+        self.pointer_enter
+    }
+
+    /// Suspend compositor shortcuts while a surface has keyboard focus
+    ///
+    /// Returns false if the surface already has an inhibitor.
+    pub fn inhibit_shortcuts(&mut self, surface: SurfaceId) -> bool {
+        self.shortcut_inhibitors.insert(surface)
+    }
+
+    /// Stop suspending compositor shortcuts for a surface
+    pub fn restore_shortcuts(&mut self, surface: SurfaceId) {
+        self.shortcut_inhibitors.remove(&surface);
+    }
+
+    /// Check if a surface has an inhibitor, whether or not it has focus
+    pub fn has_shortcut_inhibitor(&self, surface: SurfaceId) -> bool {
+        self.shortcut_inhibitors.contains(&surface)
+    }
+
+    /// Check if compositor shortcuts are suspended for the focused surface
+    pub fn shortcuts_inhibited(&self) -> bool {
+        self.keyboard_focus()
+            .is_some_and(|focus| self.shortcut_inhibitors.contains(&focus))
+    }
 }
 
 impl Default for Seat {
@@ -158,6 +236,45 @@ mod tests {
         assert_eq!(seat.pointer_focus(), Some(surface));
     }
 
+    #[test]
+    fn test_shortcut_inhibitors() {
+        let mut seat = Seat::new();
+        let (inhibiting, other) = (SurfaceId(1), SurfaceId(2));
+        assert!(seat.inhibit_shortcuts(inhibiting));
+        assert!(!seat.inhibit_shortcuts(inhibiting));
+
+        // Only in effect while the surface has focus
+        assert!(!seat.shortcuts_inhibited());
+        seat.focus_surface(Some(inhibiting), 0.0, 0.0);
+        assert!(seat.shortcuts_inhibited());
+        seat.focus_surface(Some(other), 0.0, 0.0);
+        assert!(!seat.shortcuts_inhibited());
+
+        seat.focus_surface(Some(inhibiting), 0.0, 0.0);
+        seat.restore_shortcuts(inhibiting);
+        assert!(!seat.shortcuts_inhibited());
+        assert!(!seat.has_shortcut_inhibitor(inhibiting));
+    }
+
+    #[test]
+    fn test_recent_inputs() {
+        let mut seat = Seat::new();
+        for serial in 0..=RECENT_INPUTS as u32 {
+            seat.record_input(serial, SurfaceId(serial as u64));
+        }
+        assert_eq!(seat.input_surface(0), None);
+        assert_eq!(seat.input_surface(3), Some(SurfaceId(3)));
+    }
+
+    #[test]
+    fn test_pointer_enter_serial() {
+        let mut seat = Seat::new();
+        assert_eq!(seat.pointer_enter_serial(), None);
+        seat.record_pointer_enter(4);
+        seat.record_pointer_enter(9);
+        assert_eq!(seat.pointer_enter_serial(), Some(9));
+    }
+
     #[test]
     fn test_keyboard_access() {
         let mut seat = Seat::new();
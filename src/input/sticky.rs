@@ -0,0 +1,238 @@
+//! Sticky modifiers
+//!
+//! With Sticky Keys turned on in the macOS accessibility settings, a
+//! modifier pressed and released on its own stays in the modifier flags
+//! until the next key, and pressed twice it stays until pressed again.
+//! AppKit reports such a modifier like one that is held, so it is told
+//! apart from held modifiers by its key not being down, and sent to
+//! clients as XKB latched, or locked once it outlives a key press.
+//!
+//! When the macOS feature is off, the same behavior can be emulated for
+//! Wayland clients with `input.sticky_keys`. Slow Keys needs nothing here:
+//! macOS only delivers a key once it has been held long enough.
+
+use super::keyboard::ModifierState;
+use crate::config::InputConfig;
+
+/// Shift in the XKB modifier mask
+pub const MOD_SHIFT: u32 = 1;
+/// Control in the XKB modifier mask
+pub const MOD_CTRL: u32 = 4;
+/// Alt in the XKB modifier mask
+pub const MOD_ALT: u32 = 8;
+/// Logo (Command) in the XKB modifier mask
+pub const MOD_LOGO: u32 = 64;
+
+/// Modifiers that can be sticky
+const STICKY_MASK: u32 = MOD_SHIFT | MOD_CTRL | MOD_ALT | MOD_LOGO;
+
+/// Get the modifier an evdev keycode stands for
+pub fn modifier_mask(key: u32) -> Option<u32> {
+    match key {
+        42 | 54 => Some(MOD_SHIFT),
+        29 | 97 => Some(MOD_CTRL),
+        56 | 100 => Some(MOD_ALT),
+        125 | 126 => Some(MOD_LOGO),
+        _ => None,
+    }
+}
+
+/// Who makes modifiers sticky
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StickyMode {
+    /// Modifiers only apply while held
+    #[default]
+    Off,
+    /// macOS Sticky Keys is on; its modifiers are translated
+    System,
+    /// Wayoa latches and locks modifiers itself
+    Emulated,
+}
+
+/// Latched and locked modifiers of the seat
+#[derive(Debug, Default)]
+pub struct StickyModifiers {
+    mode: StickyMode,
+    /// Modifiers whose keys are down
+    held: u32,
+    /// Held modifiers that were used with another key
+    used: u32,
+    /// Modifiers applying to the next key only
+    latched: u32,
+    /// Modifiers applying until pressed again
+    locked: u32,
+    /// Modifiers latched when the key that is down was pressed
+    pressed_with: u32,
+}
+
+impl StickyModifiers {
+    /// Create a tracker with sticky modifiers off
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply configured emulation
+    pub fn configure(&mut self, config: &InputConfig) {
+        if config.sticky_keys {
+            self.set_mode(StickyMode::Emulated);
+        }
+    }
+
+    /// Follow whether macOS Sticky Keys is on, which takes over from
+    /// emulation while it is
+    pub fn set_system_sticky_keys(&mut self, enabled: bool, config: &InputConfig) {
+        match (enabled, config.sticky_keys) {
+            (true, _) => self.set_mode(StickyMode::System),
+            (false, true) => self.set_mode(StickyMode::Emulated),
+            (false, false) => self.set_mode(StickyMode::Off),
+        }
+    }
+
+    /// Set who makes modifiers sticky, dropping any stuck modifiers
+    pub fn set_mode(&mut self, mode: StickyMode) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.latched = 0;
+            self.locked = 0;
+        }
+    }
+
+    /// Get who makes modifiers sticky
+    pub fn mode(&self) -> StickyMode {
+        self.mode
+    }
+
+    /// Record a key press or release, with the modifiers the system reports
+    ///
+    /// Returns true if the latched or locked modifiers changed. Emulated
+    /// latches go away when another key is pressed, after the key itself
+    /// was sent with them.
+    pub fn key(&mut self, key: u32, pressed: bool, reported: u32) -> bool {
+        let before = (self.latched, self.locked);
+        match (modifier_mask(key), pressed) {
+            (Some(mask), true) => {
+                self.held |= mask;
+                self.used &= !mask;
+            }
+            (Some(mask), false) => {
+                self.held &= !mask;
+                let tapped = self.used & mask == 0;
+                if self.mode == StickyMode::Emulated && tapped {
+                    if self.locked & mask != 0 {
+                        self.locked &= !mask;
+                    } else if self.latched & mask != 0 {
+                        self.latched &= !mask;
+                        self.locked |= mask;
+                    } else {
+                        self.latched |= mask;
+                    }
+                }
+            }
+            (None, true) => {
+                self.used |= self.held;
+                self.pressed_with = self.latched;
+                if self.mode == StickyMode::Emulated {
+                    self.latched = 0;
+                }
+            }
+            // Latches macOS still reports after their key are locked
+            (None, false) => {
+                if self.mode == StickyMode::System {
+                    self.locked |= self.pressed_with & reported & !self.held;
+                    self.latched &= !self.locked;
+                }
+                self.pressed_with = 0;
+            }
+        }
+        before != (self.latched, self.locked)
+    }
+
+    /// Combine the modifiers reported by the system with the sticky ones
+    ///
+    /// `reported` has every modifier the system considers active as
+    /// depressed, with Caps Lock locked.
+    pub fn apply(&mut self, reported: ModifierState) -> ModifierState {
+        let mut state = reported;
+        match self.mode {
+            StickyMode::Off => {}
+            StickyMode::System => {
+                let sticky = reported.depressed & !self.held & STICKY_MASK;
+                self.locked &= sticky;
+                self.latched = sticky & !self.locked;
+                state.depressed &= !sticky;
+            }
+            StickyMode::Emulated => {
+                state.depressed = (reported.depressed & !STICKY_MASK) | self.held;
+            }
+        }
+        state.latched |= self.latched;
+        state.locked |= self.locked;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depressed(mask: u32) -> ModifierState {
+        ModifierState {
+            depressed: mask,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_emulated_latch_and_lock() {
+        let mut sticky = StickyModifiers::new();
+        sticky.set_mode(StickyMode::Emulated);
+
+        // Shift tapped latches it for the next key only
+        sticky.key(42, true, MOD_SHIFT);
+        assert!(sticky.key(42, false, 0));
+        assert_eq!(sticky.apply(depressed(0)).latched, MOD_SHIFT);
+        assert!(sticky.key(30, true, 0));
+        assert_eq!(sticky.apply(depressed(0)).latched, 0);
+
+        // Tapped twice locks it, a third tap unlocks it
+        for _ in 0..2 {
+            sticky.key(29, true, MOD_CTRL);
+            sticky.key(29, false, 0);
+        }
+        assert_eq!(sticky.apply(depressed(0)).locked, MOD_CTRL);
+        sticky.key(30, true, 0);
+        assert_eq!(sticky.apply(depressed(0)).locked, MOD_CTRL);
+        sticky.key(29, true, MOD_CTRL);
+        sticky.key(29, false, 0);
+        assert_eq!(sticky.apply(depressed(0)).locked, 0);
+
+        // A modifier held with another key is just held
+        sticky.key(42, true, MOD_SHIFT);
+        sticky.key(30, true, MOD_SHIFT);
+        assert_eq!(sticky.apply(depressed(MOD_SHIFT)).depressed, MOD_SHIFT);
+        sticky.key(42, false, 0);
+        assert_eq!(sticky.apply(depressed(0)), depressed(0));
+    }
+
+    #[test]
+    fn test_system_sticky_keys() {
+        let mut sticky = StickyModifiers::new();
+        sticky.set_mode(StickyMode::System);
+
+        // macOS keeps reporting Shift after it is released
+        sticky.key(42, true, MOD_SHIFT);
+        assert_eq!(sticky.apply(depressed(MOD_SHIFT)).depressed, MOD_SHIFT);
+        sticky.key(42, false, MOD_SHIFT);
+        let state = sticky.apply(depressed(MOD_SHIFT));
+        assert_eq!((state.depressed, state.latched), (0, MOD_SHIFT));
+
+        // Still reported after a key, so it is locked
+        sticky.key(30, true, MOD_SHIFT);
+        sticky.key(30, false, MOD_SHIFT);
+        let state = sticky.apply(depressed(MOD_SHIFT));
+        assert_eq!((state.latched, state.locked), (0, MOD_SHIFT));
+
+        // Until macOS stops reporting it
+        assert_eq!(sticky.apply(depressed(0)), depressed(0));
+    }
+}
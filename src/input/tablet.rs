@@ -0,0 +1,390 @@
+//! Graphics tablet tools
+//!
+//! Tools (pens, erasers, pucks) become known when they first come into
+//! proximity of the tablet, and stay known. A tool in proximity is over one
+//! surface at a time: moving to another surface leaves the first one, and
+//! while the tip is down the tool stays on the surface it touched, like an
+//! implicit pointer grab. Axis events are only sent for axes that changed
+//! since the last frame.
+
+use std::collections::HashMap;
+
+use crate::compositor::SurfaceId;
+
+/// Button code of the lower side button of a pen (BTN_STYLUS)
+pub const BTN_STYLUS: u32 = 0x14b;
+/// Button code of the upper side button of a pen (BTN_STYLUS2)
+pub const BTN_STYLUS2: u32 = 0x14c;
+
+/// Largest pressure value sent to clients
+const MAX_PRESSURE: f64 = 65535.0;
+
+/// A tablet tool, identified by the tablet driver's device ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TabletToolId(pub u64);
+
+/// Physical kind of a tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabletToolKind {
+    Pen,
+    Eraser,
+    Mouse,
+}
+
+/// A tool that came into proximity of the tablet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TabletTool {
+    pub id: TabletToolId,
+    pub kind: TabletToolKind,
+    /// Serial number of the tool, 0 if unknown
+    pub serial: u64,
+    /// Wacom hardware ID of the tool, 0 if unknown
+    pub hardware_id: u64,
+}
+
+/// The state of a tool's axes and buttons
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TabletSample {
+    /// Pressure from 0 to 1
+    pub pressure: f64,
+    /// Tilt in degrees, positive to the right and towards the user
+    pub tilt: (f64, f64),
+    /// Rotation around the tool's axis in degrees
+    pub rotation: f64,
+    /// Tip in bit 0, the lower and upper side buttons in bits 1 and 2
+    pub buttons: u32,
+}
+
+/// An event for the tool objects of a surface's client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TabletEvent {
+    ProximityIn {
+        tool: TabletToolId,
+        surface: SurfaceId,
+    },
+    ProximityOut {
+        tool: TabletToolId,
+    },
+    Down {
+        tool: TabletToolId,
+    },
+    Up {
+        tool: TabletToolId,
+    },
+    Motion {
+        tool: TabletToolId,
+        x: f64,
+        y: f64,
+    },
+    Pressure {
+        tool: TabletToolId,
+        pressure: u32,
+    },
+    Tilt {
+        tool: TabletToolId,
+        x: f64,
+        y: f64,
+    },
+    Rotation {
+        tool: TabletToolId,
+        degrees: f64,
+    },
+    Button {
+        tool: TabletToolId,
+        button: u32,
+        pressed: bool,
+    },
+    Frame {
+        tool: TabletToolId,
+    },
+}
+
+impl TabletEvent {
+    /// Get the tool the event is for
+    pub fn tool(&self) -> TabletToolId {
+        match *self {
+            TabletEvent::ProximityIn { tool, .. }
+            | TabletEvent::ProximityOut { tool }
+            | TabletEvent::Down { tool }
+            | TabletEvent::Up { tool }
+            | TabletEvent::Motion { tool, .. }
+            | TabletEvent::Pressure { tool, .. }
+            | TabletEvent::Tilt { tool, .. }
+            | TabletEvent::Rotation { tool, .. }
+            | TabletEvent::Button { tool, .. }
+            | TabletEvent::Frame { tool } => tool,
+        }
+    }
+}
+
+/// Where a tool is and what was last sent for it
+#[derive(Debug, Default)]
+struct ToolState {
+    focus: Option<SurfaceId>,
+    position: Option<(f64, f64)>,
+    sent: Option<TabletSample>,
+}
+
+/// Known tools and their focus
+#[derive(Debug, Default)]
+pub struct Tablet {
+    tools: Vec<TabletTool>,
+    states: HashMap<TabletToolId, ToolState>,
+}
+
+impl Tablet {
+    /// Create a tablet without tools
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember a tool, returning true if it wasn't known
+    pub fn add_tool(&mut self, tool: TabletTool) -> bool {
+        if self.tools.iter().any(|known| known.id == tool.id) {
+            return false;
+        }
+        self.tools.push(tool);
+        true
+    }
+
+    /// Get the known tools
+    pub fn tools(&self) -> &[TabletTool] {
+        &self.tools
+    }
+
+    /// Get the surface a tool is over
+    pub fn focus(&self, tool: TabletToolId) -> Option<SurfaceId> {
+        self.states.get(&tool).and_then(|state| state.focus)
+    }
+
+    /// Turn a sample of a tool into events
+    ///
+    /// `under` is the surface under the tool, and `locate` gives the tool's
+    /// position in any surface's coordinates. Samples of unknown tools are
+    /// ignored.
+    pub fn update(
+        &mut self,
+        tool: TabletToolId,
+        sample: TabletSample,
+        under: Option<SurfaceId>,
+        locate: impl Fn(SurfaceId) -> (f64, f64),
+    ) -> Vec<(SurfaceId, TabletEvent)> {
+        if !self.tools.iter().any(|known| known.id == tool) {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        let state = self.states.entry(tool).or_default();
+        let down = state.sent.is_some_and(|s| s.buttons & 1 != 0);
+        let target = if down { state.focus } else { under };
+        if target != state.focus {
+            if let Some(old) = state.focus {
+                events.push((old, TabletEvent::ProximityOut { tool }));
+                events.push((old, TabletEvent::Frame { tool }));
+            }
+            *state = ToolState {
+                focus: target,
+                ..Default::default()
+            };
+            if let Some(surface) = target {
+                events.push((surface, TabletEvent::ProximityIn { tool, surface }));
+            }
+        }
+        let Some(surface) = state.focus else {
+            return events;
+        };
+        let before = events.len();
+
+        let position = locate(surface);
+        if state.position != Some(position) {
+            state.position = Some(position);
+            let (x, y) = position;
+            events.push((surface, TabletEvent::Motion { tool, x, y }));
+        }
+        let sent = state.sent.unwrap_or(TabletSample {
+            pressure: -1.0,
+            tilt: (f64::NAN, f64::NAN),
+            rotation: f64::NAN,
+            buttons: 0,
+        });
+        if sample.pressure != sent.pressure {
+            let pressure = (sample.pressure.clamp(0.0, 1.0) * MAX_PRESSURE).round() as u32;
+            events.push((surface, TabletEvent::Pressure { tool, pressure }));
+        }
+        if sample.tilt != sent.tilt {
+            let (x, y) = sample.tilt;
+            events.push((surface, TabletEvent::Tilt { tool, x, y }));
+        }
+        if sample.rotation != sent.rotation {
+            let degrees = sample.rotation;
+            events.push((surface, TabletEvent::Rotation { tool, degrees }));
+        }
+        let changed = sample.buttons ^ sent.buttons;
+        for (bit, button) in [(1 << 1, BTN_STYLUS), (1 << 2, BTN_STYLUS2)] {
+            if changed & bit != 0 {
+                let pressed = sample.buttons & bit != 0;
+                events.push((
+                    surface,
+                    TabletEvent::Button {
+                        tool,
+                        button,
+                        pressed,
+                    },
+                ));
+            }
+        }
+        if changed & 1 != 0 {
+            let event = match sample.buttons & 1 != 0 {
+                true => TabletEvent::Down { tool },
+                false => TabletEvent::Up { tool },
+            };
+            events.push((surface, event));
+        }
+        state.sent = Some(sample);
+
+        if events.len() > before {
+            events.push((surface, TabletEvent::Frame { tool }));
+        }
+        events
+    }
+
+    /// Take a tool out of proximity, lifting it if it was down
+    pub fn proximity_out(&mut self, tool: TabletToolId) -> Vec<(SurfaceId, TabletEvent)> {
+        let Some(state) = self.states.remove(&tool) else {
+            return Vec::new();
+        };
+        let Some(surface) = state.focus else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        if state.sent.is_some_and(|s| s.buttons & 1 != 0) {
+            events.push((surface, TabletEvent::Up { tool }));
+        }
+        events.push((surface, TabletEvent::ProximityOut { tool }));
+        events.push((surface, TabletEvent::Frame { tool }));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEN: TabletToolId = TabletToolId(3);
+
+    fn tablet() -> Tablet {
+        let mut tablet = Tablet::new();
+        assert!(tablet.add_tool(TabletTool {
+            id: PEN,
+            kind: TabletToolKind::Pen,
+            serial: 42,
+            hardware_id: 0x802,
+        }));
+        tablet
+    }
+
+    #[test]
+    fn test_stroke() {
+        let mut tablet = tablet();
+        let (a, b) = (SurfaceId(1), SurfaceId(2));
+        let hover = TabletSample::default();
+        let events = tablet.update(PEN, hover, Some(a), |_| (10.0, 20.0));
+        assert_eq!(
+            events[0],
+            (
+                a,
+                TabletEvent::ProximityIn {
+                    tool: PEN,
+                    surface: a
+                }
+            )
+        );
+        assert_eq!(
+            events[1],
+            (
+                a,
+                TabletEvent::Motion {
+                    tool: PEN,
+                    x: 10.0,
+                    y: 20.0
+                }
+            )
+        );
+        // All axes are sent on entering
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[5], (a, TabletEvent::Frame { tool: PEN }));
+
+        // The tip goes down, and the stroke stays on its surface
+        let touching = TabletSample {
+            pressure: 0.5,
+            buttons: 1,
+            ..hover
+        };
+        let events = tablet.update(PEN, touching, Some(a), |_| (10.0, 20.0));
+        assert_eq!(
+            events,
+            vec![
+                (
+                    a,
+                    TabletEvent::Pressure {
+                        tool: PEN,
+                        pressure: 32768
+                    }
+                ),
+                (a, TabletEvent::Down { tool: PEN }),
+                (a, TabletEvent::Frame { tool: PEN }),
+            ]
+        );
+        let events = tablet.update(PEN, touching, Some(b), |_| (30.0, 20.0));
+        assert_eq!(events[0].0, a);
+        assert_eq!(tablet.focus(PEN), Some(a));
+
+        // Once lifted, the pen moves over to the other surface
+        tablet.update(PEN, hover, Some(b), |_| (30.0, 20.0));
+        let events = tablet.update(PEN, hover, Some(b), |_| (30.0, 20.0));
+        assert_eq!(events[0], (a, TabletEvent::ProximityOut { tool: PEN }));
+        assert_eq!(
+            events[2],
+            (
+                b,
+                TabletEvent::ProximityIn {
+                    tool: PEN,
+                    surface: b
+                }
+            )
+        );
+        assert_eq!(tablet.focus(PEN), Some(b));
+    }
+
+    #[test]
+    fn test_proximity_out() {
+        let mut tablet = tablet();
+        let surface = SurfaceId(1);
+        let touching = TabletSample {
+            pressure: 1.0,
+            buttons: 1 | 1 << 1,
+            ..Default::default()
+        };
+        let events = tablet.update(PEN, touching, Some(surface), |_| (0.0, 0.0));
+        assert!(events.contains(&(
+            surface,
+            TabletEvent::Button {
+                tool: PEN,
+                button: BTN_STYLUS,
+                pressed: true
+            }
+        )));
+        let events = tablet.proximity_out(PEN);
+        assert_eq!(events[0], (surface, TabletEvent::Up { tool: PEN }));
+        assert_eq!(
+            events[1],
+            (surface, TabletEvent::ProximityOut { tool: PEN })
+        );
+        assert_eq!(tablet.focus(PEN), None);
+
+        // Unknown tools are ignored
+        let unknown = TabletToolId(9);
+        assert!(tablet
+            .update(unknown, touching, Some(surface), |_| (0.0, 0.0))
+            .is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Touchscreen emulation
+//!
+//! In touchscreen emulation mode the fingers on the trackpad become touch
+//! points on the focused window, for testing touch UIs of Wayland apps on a
+//! Mac. The whole trackpad maps to a configurable region of the window, so
+//! a small region gives finer control. A touch sequence stays on the
+//! surface it started on until the last finger lifts.
+
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::compositor::SurfaceId;
+use crate::config::TouchEmulationConfig;
+use crate::protocol::seat::TouchEvent;
+
+/// A finger on the trackpad
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    /// Identity of the finger, stable while it touches
+    pub identity: u64,
+    /// Position on the trackpad from 0 to 1, with the origin at the
+    /// top-left
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A touch point of the current sequence
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    id: i32,
+    x: f64,
+    y: f64,
+}
+
+/// Turns trackpad contacts into touch points
+#[derive(Debug)]
+pub struct TouchEmulator {
+    /// Part of the surface the trackpad maps to: x, y, width and height as
+    /// fractions of its size
+    region: [f64; 4],
+    /// Most touch points at once
+    max_points: usize,
+    /// Surface of the current sequence
+    target: Option<SurfaceId>,
+    /// Touch points by finger identity
+    points: HashMap<u64, Point>,
+    /// ID of the next touch point
+    next_id: i32,
+}
+
+impl TouchEmulator {
+    /// Create an emulator, if the mode is enabled
+    pub fn new(config: &TouchEmulationConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let [x, y, width, height] = config.region.map(|v| v.clamp(0.0, 1.0));
+        Some(Self {
+            region: [x, y, width.min(1.0 - x), height.min(1.0 - y)],
+            max_points: config.max_points.max(1),
+            target: None,
+            points: HashMap::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Get the surface of the current touch sequence
+    pub fn target(&self) -> Option<SurfaceId> {
+        self.target
+    }
+
+    /// Turn the fingers now on the trackpad into touch events
+    ///
+    /// Fingers missing from `contacts` were lifted. A new sequence starts
+    /// on `focus`, and `size_of` gives the size of a surface, or None once
+    /// it is gone, which cancels the sequence.
+    pub fn update(
+        &mut self,
+        contacts: &[Contact],
+        focus: Option<SurfaceId>,
+        size_of: impl Fn(SurfaceId) -> Option<(f64, f64)>,
+    ) -> Vec<(SurfaceId, TouchEvent)> {
+        if self.points.is_empty() {
+            self.target = focus;
+        }
+        let Some(target) = self.target else {
+            return Vec::new();
+        };
+        let Some((width, height)) = size_of(target) else {
+            return self.cancel();
+        };
+
+        let mut events = Vec::new();
+        let lifted: Vec<u64> = self
+            .points
+            .keys()
+            .filter(|identity| !contacts.iter().any(|c| c.identity == **identity))
+            .copied()
+            .collect();
+        for identity in lifted {
+            if let Some(point) = self.points.remove(&identity) {
+                events.push((target, TouchEvent::Up { id: point.id }));
+            }
+        }
+
+        let [rx, ry, rw, rh] = self.region;
+        for contact in contacts {
+            let x = (rx + contact.x.clamp(0.0, 1.0) * rw) * width;
+            let y = (ry + contact.y.clamp(0.0, 1.0) * rh) * height;
+            let full = self.points.len() >= self.max_points;
+            match self.points.get_mut(&contact.identity) {
+                Some(point) if (point.x, point.y) != (x, y) => {
+                    (point.x, point.y) = (x, y);
+                    events.push((target, TouchEvent::Motion { id: point.id, x, y }));
+                }
+                Some(_) => {}
+                None if !full => {
+                    let id = self.next_id;
+                    self.next_id = self.next_id.wrapping_add(1);
+                    self.points.insert(contact.identity, Point { id, x, y });
+                    events.push((
+                        target,
+                        TouchEvent::Down {
+                            surface: target,
+                            id,
+                            x,
+                            y,
+                        },
+                    ));
+                }
+                None => debug!("Ignoring touch beyond {} points", self.max_points),
+            }
+        }
+
+        if !events.is_empty() {
+            events.push((target, TouchEvent::Frame));
+        }
+        if self.points.is_empty() {
+            self.target = None;
+        }
+        events
+    }
+
+    /// Cancel the current touch sequence
+    pub fn cancel(&mut self) -> Vec<(SurfaceId, TouchEvent)> {
+        self.points.clear();
+        match self.target.take() {
+            Some(target) => vec![(target, TouchEvent::Cancel)],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(identity: u64, x: f64, y: f64) -> Contact {
+        Contact { identity, x, y }
+    }
+
+    #[test]
+    fn test_touch_sequence() {
+        let config = TouchEmulationConfig {
+            enabled: true,
+            region: [0.5, 0.0, 0.5, 1.0],
+            max_points: 2,
+        };
+        let mut touch = TouchEmulator::new(&config).unwrap();
+        let surface = SurfaceId(1);
+        let size = |_| Some((200.0, 100.0));
+
+        let events = touch.update(&[contact(7, 0.0, 0.5)], Some(surface), size);
+        assert_eq!(
+            events,
+            vec![
+                (
+                    surface,
+                    TouchEvent::Down {
+                        surface,
+                        id: 0,
+                        x: 100.0,
+                        y: 50.0
+                    }
+                ),
+                (surface, TouchEvent::Frame),
+            ]
+        );
+
+        // The sequence stays on its surface, up to two fingers
+        let fingers = [
+            contact(7, 1.0, 0.5),
+            contact(8, 0.0, 0.0),
+            contact(9, 0.0, 0.0),
+        ];
+        let events = touch.update(&fingers, Some(SurfaceId(2)), size);
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            (
+                surface,
+                TouchEvent::Motion {
+                    id: 0,
+                    x: 200.0,
+                    y: 50.0
+                }
+            )
+        );
+        assert!(matches!(events[1].1, TouchEvent::Down { id: 1, .. }));
+
+        let events = touch.update(&[contact(8, 0.0, 0.0)], None, size);
+        assert_eq!(events[0], (surface, TouchEvent::Up { id: 0 }));
+        assert_eq!(touch.update(&[], None, size).len(), 2);
+        assert_eq!(touch.target(), None);
+
+        assert!(TouchEmulator::new(&TouchEmulationConfig::default()).is_none());
+    }
+}
@@ -0,0 +1,878 @@
+//! IPC commands
+
+use std::path::{Path, PathBuf};
+
+use log::debug;
+use serde_json::{json, Value};
+
+use super::{IpcError, IpcResponse, IPC_VERSION};
+use crate::compositor::WindowId;
+use crate::renderer::Capture;
+use crate::server::{ServerState, ZoomChange, GLOBALS};
+
+/// Names of the commands the compositor understands
+pub const COMMANDS: &[&str] = &[
+    "hello",
+    "features",
+    "inhibitors",
+    "clients",
+    "frames",
+    "resources",
+    "activate",
+    "focus-previous",
+    "raise",
+    "lower",
+    "stack",
+    "attention",
+    "bindings",
+    "journal",
+    "quit",
+    "screenshot",
+    "preview",
+    "proxy-socket",
+    "zoom",
+    "diagnostics",
+    "touch",
+];
+
+/// A parsed IPC command
+#[derive(Debug, Clone, PartialEq)]
+pub enum IpcCommand {
+    /// Exchange IPC versions and list the supported commands
+    Hello(u32),
+    /// List the enabled protocols, backends and experimental flags
+    Features,
+    /// List idle inhibitors and ignored app_ids
+    Inhibitors,
+    /// Ignore idle inhibitors from an app_id
+    IgnoreInhibitors(String),
+    /// Honor idle inhibitors from an app_id again
+    UnignoreInhibitors(String),
+    /// Show client counts, limits and rejected connections
+    Clients,
+    /// Show missed-frame statistics per output and latency per window
+    Frames,
+    /// Show surfaces and regions per client and the clients leaking them
+    Resources,
+    /// Bring a window to the front, restoring it and switching Spaces
+    Activate(u64),
+    /// Focus the window that was focused before the current one
+    FocusPrevious,
+    /// Put a window on top of the others without focusing it
+    Raise(u64),
+    /// Put a window below the others
+    Lower(u64),
+    /// List windows from top to bottom
+    Stack,
+    /// List windows that asked for the user's attention
+    Attention,
+    /// Show the keybinding profiles and conflicts between bindings
+    Bindings,
+    /// Switch to a keybinding profile
+    UseBindings(String),
+    /// Read the keybindings from the configuration file again
+    ReloadBindings,
+    /// Show the uptime and session journal
+    Journal,
+    /// Save the session and quit
+    Quit,
+    /// Save a PNG of a single window (the focused one if no id is given)
+    Screenshot {
+        window: Option<u64>,
+        cursor: bool,
+        path: PathBuf,
+    },
+    /// Open the focused window's current frame in QuickLook
+    Preview,
+    /// Get the socket for clients tagged with a name, creating it
+    ProxySocket(String),
+    /// Zoom a window's content (the focused window if no id is given)
+    Zoom {
+        change: ZoomChange,
+        window: Option<u64>,
+    },
+    /// Save a PNG of the Wayland windows of an output ("focused" for the
+    /// focused window's)
+    ScreenshotOutput {
+        output: String,
+        cursor: bool,
+        path: PathBuf,
+    },
+    /// Show or hide the diagnostic overlays, toggling them if not told which
+    Diagnostics(Option<bool>),
+    /// Turn touchscreen emulation on or off, toggling it if not told which
+    TouchEmulation(Option<bool>),
+}
+
+const ZOOM_USAGE: &str = "zoom <in|out|reset|factor> [window-id]";
+
+const SCREENSHOT_USAGE: &str =
+    "screenshot (--window <id|focused> | --output <name|focused>) [--cursor] <path>";
+
+impl IpcCommand {
+    /// Parse a command line
+    pub fn parse(line: &str) -> Result<Self, IpcError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return Err(IpcError::EmptyCommand);
+        };
+
+        match name {
+            "hello" => match args {
+                [version] => version
+                    .parse()
+                    .map(IpcCommand::Hello)
+                    .map_err(|_| IpcError::Usage("hello <ipc-version>")),
+                _ => Err(IpcError::Usage("hello <ipc-version>")),
+            },
+            "features" => match args {
+                [] => Ok(IpcCommand::Features),
+                _ => Err(IpcError::Usage("features")),
+            },
+            "inhibitors" => match args {
+                [] => Ok(IpcCommand::Inhibitors),
+                ["ignore", app_id] => Ok(IpcCommand::IgnoreInhibitors(app_id.to_string())),
+                ["unignore", app_id] => Ok(IpcCommand::UnignoreInhibitors(app_id.to_string())),
+                _ => Err(IpcError::Usage("inhibitors [ignore|unignore <app_id>]")),
+            },
+            "clients" => match args {
+                [] => Ok(IpcCommand::Clients),
+                _ => Err(IpcError::Usage("clients")),
+            },
+            "frames" => match args {
+                [] => Ok(IpcCommand::Frames),
+                _ => Err(IpcError::Usage("frames")),
+            },
+            "resources" => match args {
+                [] => Ok(IpcCommand::Resources),
+                _ => Err(IpcError::Usage("resources")),
+            },
+            "activate" => match args {
+                [id] => id
+                    .parse()
+                    .map(IpcCommand::Activate)
+                    .map_err(|_| IpcError::Usage("activate <window-id>")),
+                _ => Err(IpcError::Usage("activate <window-id>")),
+            },
+            "focus-previous" => match args {
+                [] => Ok(IpcCommand::FocusPrevious),
+                _ => Err(IpcError::Usage("focus-previous")),
+            },
+            "raise" => match args {
+                [id] => id
+                    .parse()
+                    .map(IpcCommand::Raise)
+                    .map_err(|_| IpcError::Usage("raise <window-id>")),
+                _ => Err(IpcError::Usage("raise <window-id>")),
+            },
+            "lower" => match args {
+                [id] => id
+                    .parse()
+                    .map(IpcCommand::Lower)
+                    .map_err(|_| IpcError::Usage("lower <window-id>")),
+                _ => Err(IpcError::Usage("lower <window-id>")),
+            },
+            "stack" => match args {
+                [] => Ok(IpcCommand::Stack),
+                _ => Err(IpcError::Usage("stack")),
+            },
+            "attention" => match args {
+                [] => Ok(IpcCommand::Attention),
+                _ => Err(IpcError::Usage("attention")),
+            },
+            "bindings" => match args {
+                [] => Ok(IpcCommand::Bindings),
+                ["use", profile] => Ok(IpcCommand::UseBindings(profile.to_string())),
+                ["reload"] => Ok(IpcCommand::ReloadBindings),
+                _ => Err(IpcError::Usage("bindings [use <profile>|reload]")),
+            },
+            "journal" => match args {
+                [] => Ok(IpcCommand::Journal),
+                _ => Err(IpcError::Usage("journal")),
+            },
+            "quit" => match args {
+                [] => Ok(IpcCommand::Quit),
+                _ => Err(IpcError::Usage("quit")),
+            },
+            "screenshot" => Self::parse_screenshot(args),
+            "preview" => match args {
+                [] => Ok(IpcCommand::Preview),
+                _ => Err(IpcError::Usage("preview")),
+            },
+            "proxy-socket" => match args {
+                [tag] => Ok(IpcCommand::ProxySocket(tag.to_string())),
+                _ => Err(IpcError::Usage("proxy-socket <tag>")),
+            },
+            "zoom" => {
+                let (change, window) = match args {
+                    [change] => (ZoomChange::parse(change), Some(None)),
+                    [change, id] => (ZoomChange::parse(change), id.parse().ok().map(Some)),
+                    _ => (None, None),
+                };
+                match (change, window) {
+                    (Some(change), Some(window)) => Ok(IpcCommand::Zoom { change, window }),
+                    _ => Err(IpcError::Usage(ZOOM_USAGE)),
+                }
+            }
+            "diagnostics" => match args {
+                [] => Ok(IpcCommand::Diagnostics(None)),
+                ["on"] => Ok(IpcCommand::Diagnostics(Some(true))),
+                ["off"] => Ok(IpcCommand::Diagnostics(Some(false))),
+                _ => Err(IpcError::Usage("diagnostics [on|off]")),
+            },
+            "touch" => match args {
+                [] => Ok(IpcCommand::TouchEmulation(None)),
+                ["on"] => Ok(IpcCommand::TouchEmulation(Some(true))),
+                ["off"] => Ok(IpcCommand::TouchEmulation(Some(false))),
+                _ => Err(IpcError::Usage("touch [on|off]")),
+            },
+            _ => Err(IpcError::UnknownCommand(name.to_string())),
+        }
+    }
+
+    fn parse_screenshot(args: &[&str]) -> Result<Self, IpcError> {
+        let mut window = None;
+        let mut output = None;
+        let mut cursor = false;
+        let mut path = None;
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            match arg {
+                "--window" => {
+                    window = match args.next() {
+                        Some(&"focused") => Some(None),
+                        Some(id) => Some(Some(
+                            id.parse().map_err(|_| IpcError::Usage(SCREENSHOT_USAGE))?,
+                        )),
+                        None => return Err(IpcError::Usage(SCREENSHOT_USAGE)),
+                    };
+                }
+                "--output" => match args.next() {
+                    Some(name) => output = Some(name.to_string()),
+                    None => return Err(IpcError::Usage(SCREENSHOT_USAGE)),
+                },
+                "--cursor" => cursor = true,
+                _ if path.is_none() && !arg.starts_with("--") => path = Some(PathBuf::from(arg)),
+                _ => return Err(IpcError::Usage(SCREENSHOT_USAGE)),
+            }
+        }
+
+        match (window, output, path) {
+            (Some(window), None, Some(path)) => Ok(IpcCommand::Screenshot {
+                window,
+                cursor,
+                path,
+            }),
+            (None, Some(output), Some(path)) => Ok(IpcCommand::ScreenshotOutput {
+                output,
+                cursor,
+                path,
+            }),
+            _ => Err(IpcError::Usage(SCREENSHOT_USAGE)),
+        }
+    }
+
+    /// Execute the command against the compositor state
+    pub fn execute(self, state: &mut ServerState) -> IpcResponse {
+        match self {
+            IpcCommand::Hello(version) => {
+                if version > IPC_VERSION {
+                    debug!("IPC peer speaks version {}, newer than ours", version);
+                }
+                IpcResponse::success(json!({
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "ipc": IPC_VERSION,
+                    "commands": COMMANDS,
+                }))
+            }
+            IpcCommand::Features => {
+                let protocols: Vec<_> = GLOBALS
+                    .iter()
+                    .map(|(interface, version)| json!({ "interface": interface, "version": version }))
+                    .collect();
+                let mut backends = vec!["headless"];
+                if cfg!(target_os = "macos") {
+                    backends.extend(["cocoa", "metal"]);
+                }
+                IpcResponse::success(json!({
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "ipc": IPC_VERSION,
+                    "protocols": protocols,
+                    "backends": backends,
+                    "experimental": {
+                        "strict": cfg!(feature = "strict"),
+                        "touch_emulation": state.config.touch_emulation.enabled,
+                        "remote": state.config.remote.enabled,
+                    },
+                }))
+            }
+            IpcCommand::Inhibitors => IpcResponse::success(json!({
+                "inhibitors": state.idle_inhibit.list(&state.compositor),
+                "ignored": state.idle_inhibit.ignored(),
+                "inhibited": state.idle_inhibit.is_inhibited(&state.compositor),
+            })),
+            IpcCommand::IgnoreInhibitors(app_id) => {
+                let changed = state.idle_inhibit.ignore(&app_id);
+                IpcResponse::success(json!({ "changed": changed }))
+            }
+            IpcCommand::UnignoreInhibitors(app_id) => {
+                let changed = state.idle_inhibit.unignore(&app_id);
+                IpcResponse::success(json!({ "changed": changed }))
+            }
+            IpcCommand::Clients => {
+                let limits = &state.client_limits;
+                IpcResponse::success(json!({
+                    "connected": limits.connected(),
+                    "max_clients": limits.max_clients(),
+                    "max_connections_per_sec": limits.max_rate(),
+                    "rejected_total": limits.rejected_total(),
+                    "rejected": limits.rejected().collect::<Vec<_>>(),
+                }))
+            }
+            IpcCommand::Frames => {
+                let outputs: Vec<_> = state
+                    .scheduler
+                    .all_stats()
+                    .map(|(id, stats)| {
+                        json!({
+                            "output": id.0,
+                            "name": state.compositor.outputs.get(id).map(|o| o.name.clone()),
+                            "refresh_us": state.scheduler.refresh(id).map(|r| r.as_micros() as u64),
+                            "adaptive_sync": state.scheduler.adaptive_sync(id),
+                            "policy": state.scheduler.policy(id),
+                            "stats": stats,
+                        })
+                    })
+                    .collect();
+                let windows: Vec<_> = state
+                    .scheduler
+                    .window_stats()
+                    .map(|(id, latency)| {
+                        json!({
+                            "window": id.0,
+                            "app_id": state.compositor.windows.get(id).and_then(|w| w.app_id.clone()),
+                            "frames_in_flight": state.scheduler.frames_in_flight(id),
+                            "display_sync": state.scheduler.layer_options(id).display_sync,
+                            "latency": latency,
+                        })
+                    })
+                    .collect();
+                IpcResponse::success(json!({ "outputs": outputs, "windows": windows }))
+            }
+            IpcCommand::Resources => {
+                let leaks = &state.leaks;
+                IpcResponse::success(json!({
+                    "max_surfaces_per_client": leaks.max_surfaces(),
+                    "max_regions_per_client": leaks.max_regions(),
+                    "leak_threshold": leaks.threshold(),
+                    "collected_total": leaks.collected_total(),
+                    "clients": leaks.clients(),
+                }))
+            }
+            IpcCommand::Activate(id) => match state.activate_window(WindowId(id)) {
+                true => IpcResponse::success(json!({ "window": id })),
+                false => IpcResponse::failure(format!("Unknown window {}", id)),
+            },
+            IpcCommand::FocusPrevious => match state.focus_previous_window() {
+                Some(id) => IpcResponse::success(json!({ "window": id.0 })),
+                None => IpcResponse::failure("No previous window"),
+            },
+            IpcCommand::Raise(id) => match state.raise_window(WindowId(id)) {
+                true => IpcResponse::success(json!({ "window": id })),
+                false => IpcResponse::failure(format!("Unknown window {}", id)),
+            },
+            IpcCommand::Lower(id) => match state.lower_window(WindowId(id)) {
+                true => IpcResponse::success(json!({ "window": id })),
+                false => IpcResponse::failure(format!("Unknown window {}", id)),
+            },
+            IpcCommand::Stack => {
+                let windows = &state.compositor.windows;
+                let focused = windows.focused().map(|window| window.id);
+                let stack: Vec<_> = windows
+                    .stacking_order()
+                    .iter()
+                    .rev()
+                    .filter_map(|&id| windows.get(id))
+                    .filter(|window| !window.layer_surface)
+                    .map(|window| {
+                        json!({
+                            "id": window.id.0,
+                            "title": window.title,
+                            "app_id": window.app_id,
+                            "focused": Some(window.id) == focused,
+                            "minimized": window.state.minimized,
+                        })
+                    })
+                    .collect();
+                IpcResponse::success(json!({ "windows": stack }))
+            }
+            IpcCommand::Attention => {
+                let windows: Vec<_> = state
+                    .compositor
+                    .windows
+                    .needing_attention()
+                    .iter()
+                    .map(|window| {
+                        json!({
+                            "id": window.id.0,
+                            "title": window.title,
+                            "app_id": window.app_id,
+                        })
+                    })
+                    .collect();
+                IpcResponse::success(json!({ "windows": windows }))
+            }
+            IpcCommand::Bindings => {
+                let mut profiles: Vec<&String> = state.config.bindings.profiles.keys().collect();
+                profiles.sort();
+                let keys: Vec<_> = state
+                    .key_bindings()
+                    .into_iter()
+                    .map(|(binding, action)| {
+                        json!({ "key": binding.to_string(), "action": action.name() })
+                    })
+                    .collect();
+                IpcResponse::success(json!({
+                    "profile": state.binding_profile.as_ref().map(|p| &p.name),
+                    "profiles": profiles,
+                    "keys": keys,
+                    "conflicts": state.binding_conflicts(),
+                }))
+            }
+            IpcCommand::UseBindings(profile) => match state.use_binding_profile(Some(&profile)) {
+                Ok(()) => IpcResponse::success(json!({
+                    "profile": profile,
+                    "conflicts": state.binding_conflicts(),
+                })),
+                Err(e) => IpcResponse::failure(e.to_string()),
+            },
+            IpcCommand::ReloadBindings => match state.reload_bindings() {
+                Ok(()) => IpcResponse::success(json!({
+                    "profile": state.binding_profile.as_ref().map(|p| &p.name),
+                    "conflicts": state.binding_conflicts(),
+                })),
+                Err(e) => IpcResponse::failure(e.to_string()),
+            },
+            IpcCommand::Journal => IpcResponse::success(json!({
+                "started": state.journal.started_at(),
+                "uptime": state.journal.uptime().as_secs_f64(),
+                "entries": state.journal.entries(),
+            })),
+            IpcCommand::Quit => {
+                state.quit_requested = true;
+                IpcResponse::success(json!({}))
+            }
+            IpcCommand::Screenshot {
+                window,
+                cursor,
+                path,
+            } => {
+                let window = match window {
+                    Some(id) => WindowId(id),
+                    None => match state.compositor.windows.focused() {
+                        Some(window) => window.id,
+                        None => return IpcResponse::failure("No focused window"),
+                    },
+                };
+                let capture = match state.capture_window(window, cursor) {
+                    Ok(capture) => capture,
+                    Err(e) => return IpcResponse::failure(e.to_string()),
+                };
+                save_capture(&capture, &path, json!({ "window": window.0 }))
+            }
+            IpcCommand::Preview => match state.preview_focused_window() {
+                Ok(path) => IpcResponse::success(json!({ "path": path })),
+                Err(e) => IpcResponse::failure(e.to_string()),
+            },
+            IpcCommand::ProxySocket(tag) => match state.context_sockets.socket(&tag) {
+                Ok(path) => IpcResponse::success(json!({ "tag": tag, "path": path })),
+                Err(e) => IpcResponse::failure(e.to_string()),
+            },
+            IpcCommand::Zoom { change, window } => {
+                match state.zoom_window(window.map(WindowId), change) {
+                    Ok((window, factor)) => {
+                        IpcResponse::success(json!({ "window": window.0, "zoom": factor }))
+                    }
+                    Err(e) => IpcResponse::failure(e.to_string()),
+                }
+            }
+            IpcCommand::ScreenshotOutput {
+                output,
+                cursor,
+                path,
+            } => {
+                let outputs = &state.compositor.outputs;
+                let found = match output.as_str() {
+                    // The output of the focused window, or the first one
+                    "focused" => state
+                        .compositor
+                        .windows
+                        .focused()
+                        .and_then(|window| window.output)
+                        .and_then(|id| outputs.get(id))
+                        .or_else(|| outputs.iter().map(|(_, o)| o).min_by_key(|o| o.id.0)),
+                    name => outputs.by_name(name),
+                };
+                let Some((id, output)) = found.map(|o| (o.id, o.name.clone())) else {
+                    return IpcResponse::failure(format!("Unknown output {}", output));
+                };
+                let capture = match state.capture_output(id, cursor) {
+                    Ok(capture) => capture,
+                    Err(e) => return IpcResponse::failure(e.to_string()),
+                };
+                save_capture(&capture, &path, json!({ "output": output }))
+            }
+            IpcCommand::Diagnostics(enabled) => {
+                let enabled = enabled.unwrap_or(!state.backend.diagnostics);
+                state.set_diagnostics(enabled);
+                IpcResponse::success(json!({ "enabled": enabled }))
+            }
+            IpcCommand::TouchEmulation(enabled) => {
+                let enabled = enabled.unwrap_or(state.touch_emulation.is_none());
+                state.set_touch_emulation(enabled);
+                IpcResponse::success(json!({ "enabled": enabled }))
+            }
+        }
+    }
+}
+
+/// Write a capture as PNG and describe it, along with `source`
+fn save_capture(capture: &Capture, path: &Path, mut source: Value) -> IpcResponse {
+    let written = capture
+        .to_png()
+        .map_err(|e| e.to_string())
+        .and_then(|png| std::fs::write(path, png).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => {
+            source["path"] = json!(path);
+            source["width"] = json!(capture.width);
+            source["height"] = json!(capture.height);
+            IpcResponse::success(source)
+        }
+        Err(e) => IpcResponse::failure(format!("{}: {}", path.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::SurfaceId;
+    use crate::session::JournalEvent;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            IpcCommand::parse("inhibitors\n").unwrap(),
+            IpcCommand::Inhibitors
+        );
+        assert_eq!(
+            IpcCommand::parse("inhibitors ignore org.example.App").unwrap(),
+            IpcCommand::IgnoreInhibitors("org.example.App".to_string())
+        );
+        assert_eq!(IpcCommand::parse("   "), Err(IpcError::EmptyCommand));
+        assert!(matches!(
+            IpcCommand::parse("inhibitors ignore"),
+            Err(IpcError::Usage(_))
+        ));
+        assert_eq!(
+            IpcCommand::parse("screenshot --window 3 --cursor /tmp/shot.png").unwrap(),
+            IpcCommand::Screenshot {
+                window: Some(3),
+                cursor: true,
+                path: PathBuf::from("/tmp/shot.png"),
+            }
+        );
+        assert!(matches!(
+            IpcCommand::parse("screenshot /tmp/shot.png"),
+            Err(IpcError::Usage(_))
+        ));
+        assert_eq!(IpcCommand::parse("quit").unwrap(), IpcCommand::Quit);
+        assert_eq!(
+            IpcCommand::parse("focus-previous").unwrap(),
+            IpcCommand::FocusPrevious
+        );
+        assert_eq!(IpcCommand::parse("preview").unwrap(), IpcCommand::Preview);
+        assert_eq!(
+            IpcCommand::parse("proxy-socket lima").unwrap(),
+            IpcCommand::ProxySocket("lima".to_string())
+        );
+        assert_eq!(
+            IpcCommand::parse("preview 3"),
+            Err(IpcError::Usage("preview"))
+        );
+        assert_eq!(
+            IpcCommand::parse("zoom 2 7").unwrap(),
+            IpcCommand::Zoom {
+                change: ZoomChange::To(2.0),
+                window: Some(7),
+            }
+        );
+        assert_eq!(
+            IpcCommand::parse("bindings use mac-style").unwrap(),
+            IpcCommand::UseBindings("mac-style".to_string())
+        );
+        assert_eq!(
+            IpcCommand::parse("zoom sideways"),
+            Err(IpcError::Usage(ZOOM_USAGE))
+        );
+        assert_eq!(
+            IpcCommand::parse("diagnostics on").unwrap(),
+            IpcCommand::Diagnostics(Some(true))
+        );
+        assert_eq!(
+            IpcCommand::parse("diagnostics").unwrap(),
+            IpcCommand::Diagnostics(None)
+        );
+        assert_eq!(
+            IpcCommand::parse("touch off").unwrap(),
+            IpcCommand::TouchEmulation(Some(false))
+        );
+        assert!(matches!(
+            IpcCommand::parse("bogus"),
+            Err(IpcError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_hello_and_features() {
+        let mut state = ServerState::new();
+        let response = IpcCommand::parse("hello 1").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["ipc"], json!(IPC_VERSION));
+        assert_eq!(data["commands"], json!(COMMANDS));
+        assert!(matches!(
+            IpcCommand::parse("hello"),
+            Err(IpcError::Usage(_))
+        ));
+
+        let response = IpcCommand::parse("features").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(
+            data["protocols"][0],
+            json!({ "interface": "wl_compositor", "version": 6 })
+        );
+        assert_eq!(data["backends"][0], json!("headless"));
+        assert_eq!(data["experimental"]["remote"], json!(false));
+
+        // Every command has a name in the list
+        for line in ["clients", "stack", "preview", "quit", "journal"] {
+            assert!(IpcCommand::parse(line).is_ok());
+            assert!(COMMANDS.contains(&line));
+        }
+    }
+
+    #[test]
+    fn test_ignore_inhibitors() {
+        let mut state = ServerState::new();
+        let response = IpcCommand::IgnoreInhibitors("app".to_string()).execute(&mut state);
+        assert_eq!(response.data.unwrap()["changed"], json!(true));
+        assert!(state.idle_inhibit.is_ignored("app"));
+
+        let response = IpcCommand::Inhibitors.execute(&mut state);
+        assert_eq!(response.data.unwrap()["ignored"], json!(["app"]));
+
+        IpcCommand::UnignoreInhibitors("app".to_string()).execute(&mut state);
+        assert!(!state.idle_inhibit.is_ignored("app"));
+    }
+
+    #[test]
+    fn test_clients() {
+        let mut state = ServerState::new();
+        let response = IpcCommand::parse("clients").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["connected"], json!(0));
+        assert_eq!(data["max_clients"], json!(null));
+        assert_eq!(data["rejected"], json!([]));
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut state = ServerState::new();
+        let id = state.compositor.outputs.create_output(
+            "test".to_string(),
+            "Wayoa".to_string(),
+            "Test".to_string(),
+        );
+        let output = state.compositor.outputs.get(id).unwrap();
+        state.scheduler.sync_output(output);
+
+        let response = IpcCommand::parse("frames").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["outputs"][0]["name"], json!("test"));
+        assert_eq!(data["outputs"][0]["refresh_us"], json!(16_666));
+        assert_eq!(data["outputs"][0]["stats"]["missed"], json!(0));
+    }
+
+    #[test]
+    fn test_resources() {
+        let mut state = ServerState::new();
+        let client = crate::compositor::state::ClientId(1);
+        let surface = state.compositor.surfaces.create_surface();
+        state.leaks.surface_created(client, surface);
+        state.leaks.region_created(client).unwrap();
+
+        let response = IpcCommand::parse("resources").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["leak_threshold"], json!(1000));
+        assert_eq!(data["clients"][0]["uncommitted"], json!(1));
+        assert_eq!(data["clients"][0]["regions"], json!(1));
+        assert_eq!(data["clients"][0]["leaking"], json!(false));
+    }
+
+    #[test]
+    fn test_activate() {
+        let mut state = ServerState::new();
+        let surface = state.compositor.surfaces.create_surface();
+        let window = state.compositor.windows.create_window(surface);
+        state
+            .compositor
+            .windows
+            .get_mut(window)
+            .unwrap()
+            .set_minimized(true);
+
+        let response = IpcCommand::parse(&format!("activate {}", window.0))
+            .unwrap()
+            .execute(&mut state);
+        assert!(response.ok);
+        assert_eq!(
+            state.compositor.windows.focused().map(|w| w.id),
+            Some(window)
+        );
+        assert_eq!(state.compositor.seat.keyboard_focus(), Some(surface));
+
+        let response = IpcCommand::Activate(u64::MAX).execute(&mut state);
+        assert!(!response.ok);
+        assert!(matches!(
+            IpcCommand::parse("activate focused"),
+            Err(IpcError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_raise_and_lower() {
+        let mut state = ServerState::new();
+        let bottom = state.compositor.windows.create_window(SurfaceId(1));
+        let top = state.compositor.windows.create_window(SurfaceId(2));
+        state.compositor.windows.set_focused(Some(top));
+
+        let response = IpcCommand::parse(&format!("raise {}", bottom.0))
+            .unwrap()
+            .execute(&mut state);
+        assert!(response.ok);
+        let data = IpcCommand::Stack.execute(&mut state).data.unwrap();
+        assert_eq!(data["windows"][0]["id"], json!(bottom.0));
+        assert_eq!(data["windows"][1]["focused"], json!(true));
+
+        IpcCommand::Lower(bottom.0).execute(&mut state);
+        assert_eq!(state.compositor.windows.stacking_order(), [bottom, top]);
+        assert!(!IpcCommand::Raise(u64::MAX).execute(&mut state).ok);
+        assert!(matches!(
+            IpcCommand::parse("lower"),
+            Err(IpcError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn test_attention() {
+        let mut state = ServerState::new();
+        let focused = state.compositor.windows.create_window(SurfaceId(1));
+        let other = state.compositor.windows.create_window(SurfaceId(2));
+        state.compositor.windows.get_mut(other).unwrap().title = Some("Chat".to_string());
+        state.compositor.windows.set_focused(Some(focused));
+
+        assert!(!state.request_attention(focused));
+        assert!(state.request_attention(other));
+        let response = IpcCommand::parse("attention").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["windows"][0]["id"], json!(other.0));
+        assert_eq!(data["windows"][0]["title"], json!("Chat"));
+
+        state.activate_window(other);
+        let response = IpcCommand::Attention.execute(&mut state);
+        assert_eq!(response.data.unwrap()["windows"], json!([]));
+    }
+
+    #[test]
+    fn test_bindings() {
+        let mut state = ServerState::new();
+        let response = IpcCommand::parse("bindings").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["profile"], json!(null));
+        assert_eq!(
+            data["keys"][0],
+            json!({ "key": "ctrl+alt+space", "action": "show-launcher" })
+        );
+        assert_eq!(data["conflicts"][0]["kind"], json!("system"));
+
+        let response = IpcCommand::UseBindings("mac-style".to_string()).execute(&mut state);
+        assert!(!response.ok);
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut state = ServerState::new();
+        state.journal.record(JournalEvent::Started { pid: 42 });
+        let response = IpcCommand::parse("journal").unwrap().execute(&mut state);
+        let data = response.data.unwrap();
+        assert_eq!(data["entries"][0]["event"], json!("started"));
+        assert_eq!(data["entries"][0]["pid"], json!(42));
+        assert!(data["uptime"].as_f64().unwrap() >= 0.0);
+        assert!(IpcCommand::parse("journal all").is_err());
+    }
+
+    #[test]
+    fn test_screenshot_unknown_window() {
+        let mut state = ServerState::new();
+        let response = IpcCommand::Screenshot {
+            window: Some(u64::MAX),
+            cursor: false,
+            path: PathBuf::from("unused.png"),
+        }
+        .execute(&mut state);
+        assert!(!response.ok);
+
+        let response = IpcCommand::parse("screenshot --window focused unused.png")
+            .unwrap()
+            .execute(&mut state);
+        assert_eq!(response.error.as_deref(), Some("No focused window"));
+    }
+
+    #[test]
+    fn test_screenshot_output() {
+        let mut state = ServerState::new();
+        state
+            .compositor
+            .outputs
+            .create_headless("virtual-0".to_string(), 8, 6, 1, 60_000);
+        let path = std::env::temp_dir().join(format!("wayoa-output-{}.png", std::process::id()));
+
+        let command =
+            IpcCommand::parse(&format!("screenshot --output virtual-0 {}", path.display()))
+                .unwrap();
+        let response = command.execute(&mut state);
+        let _ = std::fs::remove_file(&path);
+        let data = response.data.unwrap();
+        assert_eq!(data["output"], json!("virtual-0"));
+        assert_eq!(
+            (data["width"].clone(), data["height"].clone()),
+            (json!(8), json!(6))
+        );
+
+        let response =
+            IpcCommand::parse(&format!("screenshot --output focused {}", path.display()))
+                .unwrap()
+                .execute(&mut state);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(response.data.unwrap()["output"], json!("virtual-0"));
+
+        let response = IpcCommand::parse("screenshot --output bogus unused.png")
+            .unwrap()
+            .execute(&mut state);
+        assert_eq!(response.error.as_deref(), Some("Unknown output bogus"));
+        assert!(matches!(
+            IpcCommand::parse("screenshot --window 1 --output virtual-0 unused.png"),
+            Err(IpcError::Usage(_))
+        ));
+    }
+}
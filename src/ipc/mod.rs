@@ -0,0 +1,540 @@
+//! IPC control socket
+//!
+//! Wayoa listens on a Unix socket for control commands from `wayoactl` and
+//! scripts. Each connection sends a single command line (words separated by
+//! whitespace) and receives a single JSON response line.
+//!
+//! The socket path is exported to clients as `$WAYOA_SOCKET`.
+//!
+//! Connections are non-blocking: a peer's command is read as it arrives
+//! over later loop iterations, so a slow or idle peer never stalls the
+//! compositor. Peers that send no command in time are disconnected.
+//!
+//! Tools can start with a `hello <ipc-version>` command, which answers with
+//! the compositor's version, its IPC version and the commands it knows, so
+//! commands a running compositor is too old for can be reported instead of
+//! failing. Compositors from before the handshake answer it with an
+//! unknown command error.
+
+pub mod command;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use calloop::generic::Generic;
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{Interest, LoopHandle, Mode, PostAction};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::server::ServerState;
+
+pub use command::{IpcCommand, COMMANDS};
+
+/// Version of the IPC protocol, raised when commands or responses change
+/// incompatibly
+pub const IPC_VERSION: u32 = 1;
+
+/// How long a connected peer has to send its command and take the reply
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest command line accepted
+const MAX_COMMAND: usize = 64 * 1024;
+
+/// Response to an IPC command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpcResponse {
+    /// Whether the command succeeded
+    pub ok: bool,
+    /// Command result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    /// Error message if the command failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl IpcResponse {
+    /// Create a successful response
+    pub fn success(data: serde_json::Value) -> Self {
+        Self {
+            ok: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    /// Create an error response
+    pub fn failure(error: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            data: None,
+            error: Some(error.into()),
+        }
+    }
+}
+
+/// IPC socket server
+#[derive(Debug)]
+pub struct IpcServer {
+    /// Listening socket
+    listener: UnixListener,
+    /// Socket path (removed on drop)
+    path: PathBuf,
+    /// Device and inode of the socket file, to tell it from one a newer
+    /// instance bound at the same path
+    file: (u64, u64),
+    /// Connected peers not answered yet
+    connections: Vec<Connection>,
+}
+
+impl IpcServer {
+    /// Bind the IPC socket
+    ///
+    /// A leftover socket file at the path is replaced.
+    pub fn bind(path: &Path) -> Result<Self, IpcError> {
+        if path.exists() {
+            debug!("Removing stale IPC socket {}", path.display());
+            std::fs::remove_file(path).map_err(|e| IpcError::Io(e.to_string()))?;
+        }
+        let listener = UnixListener::bind(path).map_err(|e| IpcError::Io(e.to_string()))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| IpcError::Io(e.to_string()))?;
+        let metadata = std::fs::metadata(path).map_err(|e| IpcError::Io(e.to_string()))?;
+        info!("IPC socket: {}", path.display());
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            file: (metadata.dev(), metadata.ino()),
+            connections: Vec::new(),
+        })
+    }
+
+    /// Get the socket path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the default socket path for a Wayland display name
+    pub fn default_path(socket_name: &str) -> PathBuf {
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        dir.join(format!("wayoa-{}.sock", socket_name))
+    }
+
+    /// Accept connections and answer the commands that arrived
+    pub fn dispatch(&mut self, state: &mut ServerState) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match Connection::new(stream) {
+                    Ok(connection) => self.connections.push(connection),
+                    Err(e) => warn!("IPC connection failed: {}", e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to accept IPC connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let now = Instant::now();
+        self.connections
+            .retain_mut(|connection| match connection.poll(state) {
+                Ok(true) => false,
+                Ok(false) if now.duration_since(connection.connected) >= TIMEOUT => {
+                    debug!("Dropping idle IPC connection");
+                    false
+                }
+                Ok(false) => true,
+                Err(e) => {
+                    warn!("IPC connection failed: {}", e);
+                    false
+                }
+            });
+    }
+
+    /// Insert the socket into a calloop event loop
+    ///
+    /// Every connection becomes a source of its own, removed once answered
+    /// or after the timeout.
+    pub fn insert_into_loop(
+        self,
+        handle: LoopHandle<'static, Arc<Mutex<ServerState>>>,
+    ) -> anyhow::Result<()> {
+        let fd = self.listener.as_fd().try_clone_to_owned()?;
+        let loop_handle = handle.clone();
+        handle.insert_source(
+            Generic::new(fd, Interest::READ, Mode::Level),
+            move |_, _, _| {
+                loop {
+                    match self.listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(e) = insert_connection(&loop_handle, stream) {
+                                warn!("IPC connection failed: {}", e);
+                            }
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => {
+                            warn!("Failed to accept IPC connection: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Ok(PostAction::Continue)
+            },
+        )?;
+        Ok(())
+    }
+}
+
+/// Register a connection with calloop, along with its timeout
+fn insert_connection(
+    handle: &LoopHandle<'static, Arc<Mutex<ServerState>>>,
+    stream: UnixStream,
+) -> anyhow::Result<()> {
+    let fd = stream.as_fd().try_clone_to_owned()?;
+    let mut connection = Connection::new(stream)?;
+    // Edge triggered, since the socket stays writable the whole time
+    let token = handle.insert_source(
+        Generic::new(fd, Interest::BOTH, Mode::Edge),
+        move |_, _, state| match connection.poll(&mut state.lock().unwrap()) {
+            Ok(false) => Ok(PostAction::Continue),
+            Ok(true) => Ok(PostAction::Remove),
+            Err(e) => {
+                warn!("IPC connection failed: {}", e);
+                Ok(PostAction::Remove)
+            }
+        },
+    )?;
+    let timeout_handle = handle.clone();
+    handle
+        .insert_source(Timer::from_duration(TIMEOUT), move |_, _, _| {
+            // Does nothing if the connection was answered already
+            timeout_handle.remove(token);
+            TimeoutAction::Drop
+        })
+        .map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// A connected peer, read from and answered without blocking
+#[derive(Debug)]
+struct Connection {
+    stream: UnixStream,
+    /// Bytes of the command received so far
+    received: Vec<u8>,
+    /// Bytes of the response not written yet, once the command ran
+    reply: Option<Vec<u8>>,
+    /// When the peer connected
+    connected: Instant,
+}
+
+impl Connection {
+    fn new(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            received: Vec::new(),
+            reply: None,
+            connected: Instant::now(),
+        })
+    }
+
+    /// Read the command, run it and write the response as far as the
+    /// socket allows
+    ///
+    /// Returns whether the connection is done with.
+    fn poll(&mut self, state: &mut ServerState) -> io::Result<bool> {
+        if self.reply.is_none() {
+            let Some(line) = self.read_command()? else {
+                return Ok(false);
+            };
+            self.reply = Some(respond(&line, state)?);
+        }
+        self.write_reply()
+    }
+
+    /// Read until the end of the command line or of the stream
+    fn read_command(&mut self) -> io::Result<Option<String>> {
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    self.received.extend_from_slice(&buf[..n]);
+                    if self.received.contains(&b'\n') {
+                        break;
+                    }
+                    if self.received.len() > MAX_COMMAND {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "command too long",
+                        ));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let end = self
+            .received
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(self.received.len());
+        Ok(Some(
+            String::from_utf8_lossy(&self.received[..end]).into_owned(),
+        ))
+    }
+
+    /// Write what's left of the response
+    ///
+    /// Returns whether all of it was written.
+    fn write_reply(&mut self) -> io::Result<bool> {
+        let Some(reply) = &mut self.reply else {
+            return Ok(false);
+        };
+        while !reply.is_empty() {
+            match self.stream.write(reply) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    reply.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Run a command line and serialize the response
+fn respond(line: &str, state: &mut ServerState) -> io::Result<Vec<u8>> {
+    debug!("IPC command: {}", line.trim());
+
+    let response = match IpcCommand::parse(line) {
+        Ok(command) => command.execute(state),
+        Err(e) => IpcResponse::failure(e.to_string()),
+    };
+
+    let mut reply = serde_json::to_string(&response).map_err(io::Error::other)?;
+    reply.push('\n');
+    Ok(reply.into_bytes())
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        // With --replace, the new instance may have bound the path already
+        let ours = std::fs::metadata(&self.path)
+            .is_ok_and(|metadata| (metadata.dev(), metadata.ino()) == self.file);
+        if ours {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Find the IPC socket of the compositor to talk to
+///
+/// `$WAYOA_SOCKET` if set, otherwise the socket next to `$WAYLAND_DISPLAY`.
+pub fn socket_from_env() -> PathBuf {
+    if let Some(path) = std::env::var_os("WAYOA_SOCKET") {
+        return PathBuf::from(path);
+    }
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+    IpcServer::default_path(&display)
+}
+
+/// Send a command to a running compositor and wait for the response
+pub fn request(path: &Path, command: &str) -> Result<IpcResponse, IpcError> {
+    let mut stream = UnixStream::connect(path)
+        .map_err(|e| IpcError::Connect(path.to_path_buf(), e.to_string()))?;
+    writeln!(stream, "{}", command).map_err(|e| IpcError::Io(e.to_string()))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|e| IpcError::Io(e.to_string()))?;
+    serde_json::from_str(&line).map_err(|e| IpcError::InvalidResponse(e.to_string()))
+}
+
+/// What a running compositor told about itself in the handshake
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Handshake {
+    /// Version of the running compositor
+    pub version: String,
+    /// Its IPC version
+    pub ipc: u32,
+    /// Commands it understands
+    pub commands: Vec<String>,
+}
+
+impl Handshake {
+    /// Check if the compositor understands a command
+    pub fn supports(&self, command: &str) -> bool {
+        self.commands.iter().any(|c| c == command)
+    }
+
+    /// Check if the compositor is a different version than this build
+    pub fn is_outdated(&self) -> bool {
+        self.version != env!("CARGO_PKG_VERSION")
+    }
+}
+
+/// Ask a running compositor for its versions and commands
+///
+/// Returns `None` for a compositor from before the handshake.
+pub fn handshake(path: &Path) -> Result<Option<Handshake>, IpcError> {
+    let response = request(path, &format!("hello {}", IPC_VERSION))?;
+    if !response.ok {
+        return Ok(None);
+    }
+    let data = response.data.unwrap_or_default();
+    serde_json::from_value(data)
+        .map(Some)
+        .map_err(|e| IpcError::InvalidResponse(e.to_string()))
+}
+
+/// IPC errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IpcError {
+    #[error("Failed to connect to {0}: {1}")]
+    Connect(PathBuf, String),
+    #[error("IPC I/O error: {0}")]
+    Io(String),
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("Empty command")]
+    EmptyCommand,
+    #[error("Unknown command: {0}")]
+    UnknownCommand(String),
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa-test.sock");
+        let mut server = IpcServer::bind(&path).unwrap();
+        let mut state = ServerState::new();
+
+        let client_path = path.clone();
+        let client = std::thread::spawn(move || request(&client_path, "inhibitors").unwrap());
+        while !client.is_finished() {
+            server.dispatch(&mut state);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let response = client.join().unwrap();
+        assert!(response.ok);
+        assert_eq!(response.data.unwrap()["inhibitors"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_handshake() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa-test.sock");
+        let mut server = IpcServer::bind(&path).unwrap();
+        let mut state = ServerState::new();
+
+        let client_path = path.clone();
+        let client = std::thread::spawn(move || handshake(&client_path).unwrap());
+        while !client.is_finished() {
+            server.dispatch(&mut state);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let handshake = client.join().unwrap().unwrap();
+        assert_eq!(handshake.ipc, IPC_VERSION);
+        assert!(!handshake.is_outdated());
+        assert!(handshake.supports("features"));
+        assert!(!handshake.supports("bogus"));
+    }
+
+    #[test]
+    fn test_slow_peer_does_not_block() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa-test.sock");
+        let mut server = IpcServer::bind(&path).unwrap();
+        let mut state = ServerState::new();
+
+        // Half a command: dispatching returns without an answer
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"inhi").unwrap();
+        let started = Instant::now();
+        server.dispatch(&mut state);
+        assert!(started.elapsed() < Duration::from_millis(50));
+        assert_eq!(server.connections.len(), 1);
+
+        stream.write_all(b"bitors\n").unwrap();
+        server.dispatch(&mut state);
+        assert!(server.connections.is_empty());
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        let response: IpcResponse = serde_json::from_str(&line).unwrap();
+        assert!(response.ok);
+    }
+
+    #[test]
+    fn test_idle_peer_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa-test.sock");
+        let mut server = IpcServer::bind(&path).unwrap();
+        let mut state = ServerState::new();
+
+        let _stream = UnixStream::connect(&path).unwrap();
+        server.dispatch(&mut state);
+        assert_eq!(server.connections.len(), 1);
+
+        server.connections[0].connected -= TIMEOUT;
+        server.dispatch(&mut state);
+        assert!(server.connections.is_empty());
+    }
+
+    #[test]
+    fn test_stale_socket_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa-test.sock");
+        std::fs::write(&path, "").unwrap();
+        let server = IpcServer::bind(&path).unwrap();
+        assert!(path.exists());
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_drop_keeps_replacement_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa-test.sock");
+        let old = IpcServer::bind(&path).unwrap();
+        let new = IpcServer::bind(&path).unwrap();
+
+        drop(old);
+        assert!(path.exists());
+        drop(new);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_connect_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.sock");
+        assert!(matches!(
+            request(&path, "inhibitors"),
+            Err(IpcError::Connect(..))
+        ));
+    }
+}
@@ -6,8 +6,10 @@
 //!
 //! # Architecture
 //!
-//! - **Protocol Layer**: Implements Wayland protocols using wayland-server-rs
+//! - **Protocol Layer**: Implements Wayland protocols using wayland-server-rs,
+//!   keeping the Wayland objects of clients apart from compositor state
 //! - **Compositor Core**: Manages surfaces, windows, and input routing
+//! - **Backend Adapter**: Native windows and uploads, used only to present
 //! - **Cocoa Backend**: NSApplication event loop, NSWindow per toplevel
 //! - **Metal Renderer**: GPU-accelerated surface composition
 //!
@@ -21,8 +23,19 @@
 //! ```
 
 pub mod backend;
+pub mod bench;
 pub mod compositor;
+pub mod config;
+pub mod extensions;
 pub mod input;
+pub mod ipc;
+pub mod portal;
 pub mod protocol;
+pub mod proxy;
+pub mod remote;
 pub mod renderer;
 pub mod server;
+pub mod session;
+pub mod util;
+pub mod watchdog;
+pub mod xwayland;
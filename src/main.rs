@@ -8,10 +8,10 @@ mod macos_main {
     use log::info;
     use wayoa::backend::cocoa::app::WayoaApp;
 
-    pub fn run() -> anyhow::Result<()> {
+    pub fn run(replace: bool) -> anyhow::Result<()> {
         info!("Starting Wayoa compositor");
 
-        let app = WayoaApp::new()?;
+        let app = WayoaApp::new(replace)?;
         app.run();
 
         Ok(())
@@ -22,23 +22,72 @@ mod macos_main {
 mod stub_main {
     use log::error;
 
-    pub fn run() -> anyhow::Result<()> {
+    pub fn run(_replace: bool) -> anyhow::Result<()> {
         error!("Wayoa only runs on macOS");
         anyhow::bail!("Wayoa requires macOS to run")
     }
 }
 
+/// List the protocol extensions, or print the XML of one
+fn print_protocols(name: Option<&str>) -> anyhow::Result<()> {
+    match name {
+        Some(name) => match wayoa::extensions::find(name) {
+            Some(protocol) => print!("{}", protocol.xml),
+            None => anyhow::bail!("Unknown protocol: {}", name),
+        },
+        None => {
+            for protocol in wayoa::extensions::PROTOCOLS {
+                println!("{}", protocol.name);
+            }
+        }
+    }
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     // Initialize logging
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "proxy") {
+        let options = wayoa::proxy::ProxyOptions::parse(&args[1..])?;
+        return wayoa::proxy::run(&options);
+    }
+    if args.first().is_some_and(|arg| arg == "portal") {
+        let options = wayoa::portal::PortalOptions::parse(&args[1..])?;
+        return wayoa::portal::run(&options);
+    }
+    if args.first().is_some_and(|arg| arg == "protocols") {
+        return print_protocols(args.get(1).map(String::as_str));
+    }
+
+    let mut replace = false;
+    for arg in args {
+        match arg.as_str() {
+            "--replace" => replace = true,
+            "-h" | "--help" => {
+                println!("Usage: wayoa [--replace]");
+                println!("       wayoa {}", wayoa::proxy::USAGE);
+                println!("       wayoa {}", wayoa::portal::USAGE);
+                println!("       wayoa protocols [name]");
+                println!();
+                println!("  --replace   Take over the display of a running wayoa instance");
+                println!("  proxy       Relay TCP or vsock connections to the Wayland socket");
+                println!("  portal      Serve the screenshot portal on a session bus of its own");
+                println!("  protocols   List wayoa's protocol extensions, or print one's XML");
+                return Ok(());
+            }
+            _ => anyhow::bail!("Unknown argument: {}", arg),
+        }
+    }
+
     #[cfg(target_os = "macos")]
     {
-        macos_main::run()
+        macos_main::run(replace)
     }
 
     #[cfg(not(target_os = "macos"))]
     {
-        stub_main::run()
+        stub_main::run(replace)
     }
 }
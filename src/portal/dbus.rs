@@ -0,0 +1,646 @@
+//! D-Bus wire format
+//!
+//! Just enough of the D-Bus specification for the portal shim: whole
+//! messages are read and written, with bodies of any basic or container
+//! type. Values are read in either byte order and written little-endian.
+//! Unix file descriptors aren't passed.
+
+use std::io::{self, Read, Write};
+
+/// Largest message accepted, well below the protocol's 128 MiB
+const MAX_MESSAGE: usize = 1 << 20;
+
+/// Deepest nesting of containers accepted
+const MAX_DEPTH: usize = 32;
+
+/// Header field codes
+mod field {
+    pub const PATH: u8 = 1;
+    pub const INTERFACE: u8 = 2;
+    pub const MEMBER: u8 = 3;
+    pub const ERROR_NAME: u8 = 4;
+    pub const REPLY_SERIAL: u8 = 5;
+    pub const DESTINATION: u8 = 6;
+    pub const SENDER: u8 = 7;
+    pub const SIGNATURE: u8 = 8;
+}
+
+/// Flag of a method call whose caller doesn't want a reply
+pub const NO_REPLY_EXPECTED: u8 = 1;
+
+/// Kind of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+impl MessageType {
+    fn code(self) -> u8 {
+        match self {
+            MessageType::MethodCall => 1,
+            MessageType::MethodReturn => 2,
+            MessageType::Error => 3,
+            MessageType::Signal => 4,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(MessageType::MethodCall),
+            2 => Some(MessageType::MethodReturn),
+            3 => Some(MessageType::Error),
+            4 => Some(MessageType::Signal),
+            _ => None,
+        }
+    }
+}
+
+/// A D-Bus value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    Uint16(u16),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    Double(f64),
+    String(String),
+    ObjectPath(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// Elements of the given signature
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    /// Build an `a{sv}` dictionary
+    pub fn dict(entries: Vec<(&str, Value)>) -> Self {
+        Value::Array(
+            "{sv}".to_string(),
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    Value::DictEntry(
+                        Box::new(Value::String(key.to_string())),
+                        Box::new(Value::Variant(Box::new(value))),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// The signature of the value
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::Int16(_) => "n".to_string(),
+            Value::Uint16(_) => "q".to_string(),
+            Value::Int32(_) => "i".to_string(),
+            Value::Uint32(_) => "u".to_string(),
+            Value::Int64(_) => "x".to_string(),
+            Value::Uint64(_) => "t".to_string(),
+            Value::Double(_) => "d".to_string(),
+            Value::String(_) => "s".to_string(),
+            Value::ObjectPath(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::Variant(_) => "v".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                let fields: String = fields.iter().map(Value::signature).collect();
+                format!("({})", fields)
+            }
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+        }
+    }
+
+    /// The text of a string, object path or signature
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Look up a key of a string-keyed dictionary, unwrapping variants
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let Value::Array(_, entries) = self else {
+            return None;
+        };
+        entries.iter().find_map(|entry| match entry {
+            Value::DictEntry(k, v) if k.as_str() == Some(key) => match &**v {
+                Value::Variant(inner) => Some(&**inner),
+                value => Some(value),
+            },
+            _ => None,
+        })
+    }
+}
+
+/// A D-Bus message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub kind: MessageType,
+    pub flags: u8,
+    /// Set by the sender's connection as the message is written
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    fn new(kind: MessageType) -> Self {
+        Self {
+            kind,
+            flags: 0,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body: Vec::new(),
+        }
+    }
+
+    /// A method call
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str) -> Self {
+        Self {
+            destination: Some(destination.to_string()),
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            ..Self::new(MessageType::MethodCall)
+        }
+    }
+
+    /// The reply to a method call
+    pub fn method_return(call: &Message, body: Vec<Value>) -> Self {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            body,
+            ..Self::new(MessageType::MethodReturn)
+        }
+    }
+
+    /// An error reply to a method call
+    pub fn error(call: &Message, name: &str, text: &str) -> Self {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            error_name: Some(name.to_string()),
+            body: vec![Value::String(text.to_string())],
+            ..Self::new(MessageType::Error)
+        }
+    }
+
+    /// A signal
+    pub fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Self::new(MessageType::Signal)
+        }
+    }
+
+    /// Check if the message calls a method of an interface
+    pub fn is_call(&self, interface: &str, member: &str) -> bool {
+        self.kind == MessageType::MethodCall
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    /// Read a message
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut fixed = [0u8; 16];
+        reader.read_exact(&mut fixed)?;
+        let big_endian = match fixed[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid("bad byte order")),
+        };
+        let word = |at: usize| {
+            let bytes = [fixed[at], fixed[at + 1], fixed[at + 2], fixed[at + 3]];
+            match big_endian {
+                true => u32::from_be_bytes(bytes),
+                false => u32::from_le_bytes(bytes),
+            }
+        };
+        let header = (16 + word(12) as usize).next_multiple_of(8);
+        let total = header + word(4) as usize;
+        if total > MAX_MESSAGE {
+            return Err(invalid("message too large"));
+        }
+        let mut buf = fixed.to_vec();
+        buf.resize(total, 0);
+        reader.read_exact(&mut buf[16..])?;
+
+        let kind = MessageType::from_code(buf[1]).ok_or_else(|| invalid("bad message type"))?;
+        let mut message = Self {
+            flags: buf[2],
+            serial: word(8),
+            ..Self::new(kind)
+        };
+        let mut r = Reader {
+            buf: &buf,
+            pos: 12,
+            big_endian,
+        };
+        let Value::Array(_, fields) = r.value("a(yv)", 0)? else {
+            return Err(invalid("bad header"));
+        };
+        let mut signature = String::new();
+        for field in fields {
+            let Value::Struct(parts) = field else {
+                continue;
+            };
+            let (Some(Value::Byte(code)), Some(Value::Variant(value))) =
+                (parts.first(), parts.get(1))
+            else {
+                continue;
+            };
+            let text = value.as_str().map(str::to_string);
+            match *code {
+                field::PATH => message.path = text,
+                field::INTERFACE => message.interface = text,
+                field::MEMBER => message.member = text,
+                field::ERROR_NAME => message.error_name = text,
+                field::DESTINATION => message.destination = text,
+                field::SENDER => message.sender = text,
+                field::SIGNATURE => signature = text.unwrap_or_default(),
+                field::REPLY_SERIAL => {
+                    if let Value::Uint32(serial) = **value {
+                        message.reply_serial = Some(serial);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        r.align(8)?;
+        let mut rest = signature.as_str();
+        while !rest.is_empty() {
+            let (single, tail) = split_type(rest)?;
+            message.body.push(r.value(single, 0)?);
+            rest = tail;
+        }
+        Ok(message)
+    }
+
+    /// Encode the message
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Writer::default();
+        for value in &self.body {
+            body.value(value);
+        }
+        let signature: String = self.body.iter().map(Value::signature).collect();
+
+        let text = |code, value: &Option<String>, wrap: fn(String) -> Value| {
+            value.clone().map(|value| (code, wrap(value)))
+        };
+        let fields: Vec<Value> = [
+            text(field::PATH, &self.path, Value::ObjectPath),
+            text(field::INTERFACE, &self.interface, Value::String),
+            text(field::MEMBER, &self.member, Value::String),
+            text(field::ERROR_NAME, &self.error_name, Value::String),
+            self.reply_serial
+                .map(|serial| (field::REPLY_SERIAL, Value::Uint32(serial))),
+            text(field::DESTINATION, &self.destination, Value::String),
+            text(field::SENDER, &self.sender, Value::String),
+            (!signature.is_empty()).then_some((field::SIGNATURE, Value::Signature(signature))),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|(code, value)| {
+            Value::Struct(vec![Value::Byte(code), Value::Variant(Box::new(value))])
+        })
+        .collect();
+
+        let mut message = Writer::default();
+        message
+            .buf
+            .extend_from_slice(&[b'l', self.kind.code(), self.flags, 1]);
+        message.u32(body.buf.len() as u32);
+        message.u32(self.serial);
+        message.value(&Value::Array("(yv)".to_string(), fields));
+        message.align(8);
+        message.buf.extend_from_slice(&body.buf);
+        message.buf
+    }
+
+    /// Write the message
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Split the first complete type off a signature
+fn split_type(signature: &str) -> io::Result<(&str, &str)> {
+    let bytes = signature.as_bytes();
+    let end = match bytes.first() {
+        None => return Err(invalid("empty signature")),
+        Some(b'a') => 1 + split_type(&signature[1..])?.0.len(),
+        Some(&open @ (b'(' | b'{')) => {
+            let close = if open == b'(' { b')' } else { b'}' };
+            let mut depth = 0;
+            let end = bytes.iter().position(|&c| {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                }
+                depth == 0
+            });
+            end.ok_or_else(|| invalid("unbalanced signature"))? + 1
+        }
+        Some(_) => 1,
+    };
+    Ok(signature.split_at(end))
+}
+
+/// Alignment of the values of a type
+fn alignment(signature: &str) -> usize {
+    match signature.as_bytes().first() {
+        Some(b'n' | b'q') => 2,
+        Some(b'b' | b'i' | b'u' | b's' | b'o' | b'a') => 4,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 1,
+    }
+}
+
+/// Reads values from a message, aligned from its start
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn align(&mut self, n: usize) -> io::Result<()> {
+        let pos = self.pos.next_multiple_of(n);
+        if pos > self.buf.len() {
+            return Err(invalid("truncated message"));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        self.align(N)?;
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += N;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        if self.big_endian {
+            array.reverse();
+        }
+        Ok(array)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    fn text(&mut self, len: usize) -> io::Result<String> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len + 1)
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += len + 1;
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| invalid("invalid UTF-8"))
+    }
+
+    /// Read a value of a single complete type
+    fn value(&mut self, signature: &str, depth: usize) -> io::Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deeply"));
+        }
+        let value = match signature.as_bytes().first() {
+            Some(b'y') => Value::Byte(self.take::<1>()?[0]),
+            Some(b'b') => Value::Bool(self.u32()? != 0),
+            Some(b'n') => Value::Int16(i16::from_le_bytes(self.take()?)),
+            Some(b'q') => Value::Uint16(u16::from_le_bytes(self.take()?)),
+            Some(b'i') => Value::Int32(i32::from_le_bytes(self.take()?)),
+            Some(b'u') => Value::Uint32(self.u32()?),
+            Some(b'x') => Value::Int64(i64::from_le_bytes(self.take()?)),
+            Some(b't') => Value::Uint64(u64::from_le_bytes(self.take()?)),
+            Some(b'd') => Value::Double(f64::from_le_bytes(self.take()?)),
+            Some(b's') => {
+                let len = self.u32()? as usize;
+                Value::String(self.text(len)?)
+            }
+            Some(b'o') => {
+                let len = self.u32()? as usize;
+                Value::ObjectPath(self.text(len)?)
+            }
+            Some(b'g') => {
+                let len = self.take::<1>()?[0] as usize;
+                Value::Signature(self.text(len)?)
+            }
+            Some(b'v') => {
+                let len = self.take::<1>()?[0] as usize;
+                let inner = self.text(len)?;
+                let (single, rest) = split_type(&inner)?;
+                if !rest.is_empty() {
+                    return Err(invalid("variant of several types"));
+                }
+                Value::Variant(Box::new(self.value(single, depth + 1)?))
+            }
+            Some(b'a') => {
+                let len = self.u32()? as usize;
+                let element = split_type(&signature[1..])?.0;
+                self.align(alignment(element))?;
+                let end = self.pos + len;
+                if end > self.buf.len() {
+                    return Err(invalid("truncated message"));
+                }
+                let mut elements = Vec::new();
+                while self.pos < end {
+                    elements.push(self.value(element, depth + 1)?);
+                }
+                Value::Array(element.to_string(), elements)
+            }
+            Some(b'(') => {
+                self.align(8)?;
+                let mut rest = &signature[1..signature.len() - 1];
+                let mut fields = Vec::new();
+                while !rest.is_empty() {
+                    let (single, tail) = split_type(rest)?;
+                    fields.push(self.value(single, depth + 1)?);
+                    rest = tail;
+                }
+                Value::Struct(fields)
+            }
+            Some(b'{') => {
+                self.align(8)?;
+                let (key, rest) = split_type(&signature[1..signature.len() - 1])?;
+                let key = self.value(key, depth + 1)?;
+                let value = self.value(rest, depth + 1)?;
+                Value::DictEntry(Box::new(key), Box::new(value))
+            }
+            _ => return Err(invalid("unsupported type")),
+        };
+        Ok(value)
+    }
+}
+
+/// Writes values little-endian, aligned from the start of the buffer
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, n: usize) {
+        let len = self.buf.len().next_multiple_of(n);
+        self.buf.resize(len, 0);
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.align(bytes.len());
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.put(&value.to_le_bytes());
+    }
+
+    fn text(&mut self, text: &str) {
+        self.buf.extend_from_slice(text.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Byte(v) => self.buf.push(*v),
+            Value::Bool(v) => self.u32(*v as u32),
+            Value::Int16(v) => self.put(&v.to_le_bytes()),
+            Value::Uint16(v) => self.put(&v.to_le_bytes()),
+            Value::Int32(v) => self.put(&v.to_le_bytes()),
+            Value::Uint32(v) => self.u32(*v),
+            Value::Int64(v) => self.put(&v.to_le_bytes()),
+            Value::Uint64(v) => self.put(&v.to_le_bytes()),
+            Value::Double(v) => self.put(&v.to_le_bytes()),
+            Value::String(s) | Value::ObjectPath(s) => {
+                self.u32(s.len() as u32);
+                self.text(s);
+            }
+            Value::Signature(s) => {
+                self.buf.push(s.len() as u8);
+                self.text(s);
+            }
+            Value::Variant(inner) => {
+                let signature = inner.signature();
+                self.buf.push(signature.len() as u8);
+                self.text(&signature);
+                self.value(inner);
+            }
+            Value::Array(element, elements) => {
+                self.u32(0);
+                let len_at = self.buf.len() - 4;
+                self.align(alignment(element));
+                let start = self.buf.len();
+                for element in elements {
+                    self.value(element);
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.align(8);
+                for field in fields {
+                    self.value(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.align(8);
+                self.value(key);
+                self.value(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut call = Message::method_call(
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Screenshot",
+            "Screenshot",
+        );
+        call.serial = 7;
+        call.body = vec![
+            Value::String(String::new()),
+            Value::dict(vec![
+                ("handle_token", Value::String("t1".to_string())),
+                ("interactive", Value::Bool(false)),
+                ("types", Value::Uint32(3)),
+            ]),
+        ];
+
+        let bytes = call.to_bytes();
+        let read = Message::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read, call);
+        assert_eq!(read.body[1].signature(), "a{sv}");
+        assert_eq!(read.body[1].get("types"), Some(&Value::Uint32(3)));
+        assert!(read.is_call("org.freedesktop.portal.Screenshot", "Screenshot"));
+
+        // A truncated message is an error, not a panic
+        assert!(Message::read(&mut &bytes[..bytes.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_read_big_endian() {
+        // Hello from a big-endian peer, as sent by libdbus
+        let mut bytes = vec![b'B', 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1];
+        let path = b"/org/freedesktop/DBus";
+        let mut fields = Vec::new();
+        fields.extend_from_slice(&[field::PATH, 1, b'o', 0]);
+        fields.extend_from_slice(&(path.len() as u32).to_be_bytes());
+        fields.extend_from_slice(path);
+        fields.push(0);
+        fields.resize(fields.len().next_multiple_of(8), 0);
+        fields.extend_from_slice(&[field::MEMBER, 1, b's', 0]);
+        fields.extend_from_slice(&5u32.to_be_bytes());
+        fields.extend_from_slice(b"Hello\0");
+        bytes.extend_from_slice(&(fields.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&fields);
+        bytes.resize(bytes.len().next_multiple_of(8), 0);
+
+        let message = Message::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(message.serial, 1);
+        assert_eq!(message.path.as_deref(), Some("/org/freedesktop/DBus"));
+        assert_eq!(message.member.as_deref(), Some("Hello"));
+    }
+}
@@ -0,0 +1,344 @@
+//! Screenshot and ScreenCast portal interfaces
+//!
+//! Portal methods return the path of a request object at once, and the
+//! outcome follows as its Response signal: 0 on success with the results,
+//! 1 if the user cancelled, 2 on any other failure.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::{info, warn};
+
+use super::dbus::{Message, Value};
+
+/// Well-known name of the portal
+pub const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+
+/// Object path of the portal
+pub const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+
+const SCREENSHOT: &str = "org.freedesktop.portal.Screenshot";
+const SCREEN_CAST: &str = "org.freedesktop.portal.ScreenCast";
+const REQUEST: &str = "org.freedesktop.portal.Request";
+const SESSION: &str = "org.freedesktop.portal.Session";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+
+/// Response codes of the Response signal
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_FAILED: u32 = 2;
+
+/// ScreenCast source types: monitors and windows
+const SOURCE_TYPES: u32 = 1 | 2;
+
+/// ScreenCast cursor modes: hidden and embedded
+const CURSOR_MODES: u32 = 1 | 2;
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.freedesktop.portal.Screenshot">
+    <method name="Screenshot">
+      <arg type="s" name="parent_window" direction="in"/>
+      <arg type="a{sv}" name="options" direction="in"/>
+      <arg type="o" name="handle" direction="out"/>
+    </method>
+    <property name="version" type="u" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.portal.ScreenCast">
+    <method name="CreateSession">
+      <arg type="a{sv}" name="options" direction="in"/>
+      <arg type="o" name="handle" direction="out"/>
+    </method>
+    <method name="SelectSources">
+      <arg type="o" name="session_handle" direction="in"/>
+      <arg type="a{sv}" name="options" direction="in"/>
+      <arg type="o" name="handle" direction="out"/>
+    </method>
+    <method name="Start">
+      <arg type="o" name="session_handle" direction="in"/>
+      <arg type="s" name="parent_window" direction="in"/>
+      <arg type="a{sv}" name="options" direction="in"/>
+      <arg type="o" name="handle" direction="out"/>
+    </method>
+    <property name="AvailableSourceTypes" type="u" access="read"/>
+    <property name="AvailableCursorModes" type="u" access="read"/>
+    <property name="version" type="u" access="read"/>
+  </interface>
+</node>
+"#;
+
+/// Takes a screenshot into a PNG file
+pub type Capture = dyn Fn(&Path) -> Result<(), String> + Send + Sync;
+
+/// The portal object and the requests and sessions under it
+pub struct Desktop {
+    /// Directory screenshots are saved in
+    dir: PathBuf,
+    capture: Box<Capture>,
+    /// Number of the next screenshot and default token
+    counter: AtomicU64,
+}
+
+impl Desktop {
+    /// Create the portal, saving screenshots in `dir` through `capture`
+    pub fn new(dir: PathBuf, capture: Box<Capture>) -> Self {
+        Self {
+            dir,
+            capture,
+            counter: AtomicU64::new(1),
+        }
+    }
+
+    /// Check if a path is the portal or one of its requests or sessions
+    pub fn owns(path: &str) -> bool {
+        path == PORTAL_PATH || path.starts_with(&format!("{}/", PORTAL_PATH))
+    }
+
+    /// Handle a method call from `sender`, returning the reply and any
+    /// signals that follow it
+    pub fn handle(&self, call: &Message, sender: &str) -> Vec<Message> {
+        let interface = call.interface.as_deref().unwrap_or_default();
+        let member = call.member.as_deref().unwrap_or_default();
+        let arg = |n: usize| call.body.get(n);
+        match (interface, member) {
+            (PROPERTIES, "Get") => {
+                let (Some(iface), Some(name)) = (
+                    arg(0).and_then(Value::as_str),
+                    arg(1).and_then(Value::as_str),
+                ) else {
+                    return vec![invalid_args(call)];
+                };
+                match properties(iface).into_iter().find(|(key, _)| *key == name) {
+                    Some((_, value)) => {
+                        vec![Message::method_return(
+                            call,
+                            vec![Value::Variant(Box::new(value))],
+                        )]
+                    }
+                    None => vec![Message::error(
+                        call,
+                        "org.freedesktop.DBus.Error.UnknownProperty",
+                        &format!("No property {} on {}", name, iface),
+                    )],
+                }
+            }
+            (PROPERTIES, "GetAll") => {
+                let iface = arg(0).and_then(Value::as_str).unwrap_or_default();
+                vec![Message::method_return(
+                    call,
+                    vec![Value::dict(properties(iface))],
+                )]
+            }
+            (INTROSPECTABLE, "Introspect") => vec![Message::method_return(
+                call,
+                vec![Value::String(INTROSPECTION.to_string())],
+            )],
+            (SCREENSHOT, "Screenshot") => {
+                let options = arg(1);
+                let handle = self.request_path(sender, options);
+                let (response, results) = match self.screenshot() {
+                    Ok(uri) => {
+                        info!("Screenshot for {} saved as {}", sender, uri);
+                        (RESPONSE_SUCCESS, vec![("uri", Value::String(uri))])
+                    }
+                    Err(e) => {
+                        warn!("Screenshot for {} failed: {}", sender, e);
+                        (RESPONSE_FAILED, Vec::new())
+                    }
+                };
+                respond(call, sender, handle, response, results)
+            }
+            (SCREEN_CAST, "CreateSession") => {
+                let options = arg(0);
+                let handle = self.request_path(sender, options);
+                let session = format!(
+                    "{}/session/{}/{}",
+                    PORTAL_PATH,
+                    escape_sender(sender),
+                    self.token(options, "session_handle_token")
+                );
+                let results = vec![("session_handle", Value::String(session))];
+                respond(call, sender, handle, RESPONSE_SUCCESS, results)
+            }
+            (SCREEN_CAST, "SelectSources") => {
+                let handle = self.request_path(sender, arg(1));
+                respond(call, sender, handle, RESPONSE_SUCCESS, Vec::new())
+            }
+            (SCREEN_CAST, "Start") => {
+                // Streams are PipeWire nodes, and there is no PipeWire here
+                warn!("Screen cast for {} can't start without PipeWire", sender);
+                let handle = self.request_path(sender, arg(2));
+                respond(call, sender, handle, RESPONSE_FAILED, Vec::new())
+            }
+            (REQUEST, "Close") | (SESSION, "Close") => vec![Message::method_return(call, vec![])],
+            _ => vec![Message::error(
+                call,
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                &format!("No method {}.{}", interface, member),
+            )],
+        }
+    }
+
+    /// Take a screenshot, returning its file URI
+    fn screenshot(&self) -> Result<String, String> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let number = self.counter.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("screenshot-{}.png", number));
+        (self.capture)(&path)?;
+        Ok(file_uri(&path))
+    }
+
+    /// The token an option asks for, or a new one
+    fn token(&self, options: Option<&Value>, key: &str) -> String {
+        match options
+            .and_then(|options| options.get(key))
+            .and_then(Value::as_str)
+        {
+            Some(token) => token
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect(),
+            None => format!("wayoa{}", self.counter.fetch_add(1, Ordering::Relaxed)),
+        }
+    }
+
+    /// Path of the request object of a call, from its handle_token option
+    fn request_path(&self, sender: &str, options: Option<&Value>) -> String {
+        format!(
+            "{}/request/{}/{}",
+            PORTAL_PATH,
+            escape_sender(sender),
+            self.token(options, "handle_token")
+        )
+    }
+}
+
+/// Properties of a portal interface
+fn properties(interface: &str) -> Vec<(&'static str, Value)> {
+    match interface {
+        SCREENSHOT => vec![("version", Value::Uint32(2))],
+        SCREEN_CAST => vec![
+            ("AvailableSourceTypes", Value::Uint32(SOURCE_TYPES)),
+            ("AvailableCursorModes", Value::Uint32(CURSOR_MODES)),
+            ("version", Value::Uint32(4)),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+/// Reply with a request handle, then send its Response
+fn respond(
+    call: &Message,
+    sender: &str,
+    handle: String,
+    response: u32,
+    results: Vec<(&str, Value)>,
+) -> Vec<Message> {
+    let reply = Message::method_return(call, vec![Value::ObjectPath(handle.clone())]);
+    let mut signal = Message::signal(
+        &handle,
+        REQUEST,
+        "Response",
+        vec![Value::Uint32(response), Value::dict(results)],
+    );
+    signal.destination = Some(sender.to_string());
+    vec![reply, signal]
+}
+
+fn invalid_args(call: &Message) -> Message {
+    Message::error(
+        call,
+        "org.freedesktop.DBus.Error.InvalidArgs",
+        "Invalid arguments",
+    )
+}
+
+/// A unique name as used in request paths: `:1.5` becomes `1_5`
+fn escape_sender(sender: &str) -> String {
+    sender.trim_start_matches(':').replace('.', "_")
+}
+
+/// A file URI, percent-encoding anything but unreserved characters
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screenshot_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let desktop = Desktop::new(
+            dir.path().join("shots dir"),
+            Box::new(|path| std::fs::write(path, b"png").map_err(|e| e.to_string())),
+        );
+        let mut call = Message::method_call(PORTAL_NAME, PORTAL_PATH, SCREENSHOT, "Screenshot");
+        call.serial = 3;
+        call.sender = Some(":1.5".to_string());
+        call.body = vec![
+            Value::String(String::new()),
+            Value::dict(vec![("handle_token", Value::String("t-1".to_string()))]),
+        ];
+
+        let messages = desktop.handle(&call, ":1.5");
+        let handle = "/org/freedesktop/portal/desktop/request/1_5/t_1";
+        assert_eq!(messages[0].reply_serial, Some(3));
+        assert_eq!(messages[0].body, [Value::ObjectPath(handle.to_string())]);
+
+        let response = &messages[1];
+        assert_eq!(response.path.as_deref(), Some(handle));
+        assert_eq!(response.body[0], Value::Uint32(RESPONSE_SUCCESS));
+        let uri = response.body[1].get("uri").and_then(Value::as_str).unwrap();
+        assert!(uri.starts_with("file://") && uri.contains("shots%20dir/screenshot-"));
+        let path = dir.path().join("shots dir").join("screenshot-1.png");
+        assert_eq!(std::fs::read(path).unwrap(), b"png");
+    }
+
+    #[test]
+    fn test_screen_cast_cannot_start() {
+        let desktop = Desktop::new(PathBuf::new(), Box::new(|_| Err("unused".to_string())));
+        let mut call = Message::method_call(PORTAL_NAME, PORTAL_PATH, SCREEN_CAST, "CreateSession");
+        call.body = vec![Value::dict(vec![(
+            "session_handle_token",
+            Value::String("s1".to_string()),
+        )])];
+        let messages = desktop.handle(&call, ":1.2");
+        let session = messages[1].body[1].get("session_handle").unwrap();
+        assert_eq!(
+            session.as_str(),
+            Some("/org/freedesktop/portal/desktop/session/1_2/s1")
+        );
+
+        let mut call = Message::method_call(PORTAL_NAME, PORTAL_PATH, SCREEN_CAST, "Start");
+        call.body = vec![
+            Value::ObjectPath(session.as_str().unwrap().to_string()),
+            Value::String(String::new()),
+            Value::dict(vec![]),
+        ];
+        let messages = desktop.handle(&call, ":1.2");
+        assert_eq!(messages[1].body[0], Value::Uint32(RESPONSE_FAILED));
+
+        let mut call = Message::method_call(PORTAL_NAME, PORTAL_PATH, PROPERTIES, "Get");
+        call.body = vec![
+            Value::String(SCREEN_CAST.to_string()),
+            Value::String("version".to_string()),
+        ];
+        let messages = desktop.handle(&call, ":1.2");
+        assert_eq!(
+            messages[0].body,
+            [Value::Variant(Box::new(Value::Uint32(4)))]
+        );
+    }
+}
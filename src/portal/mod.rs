@@ -0,0 +1,331 @@
+//! Desktop portal shim
+//!
+//! Linux apps take screenshots and share the screen through
+//! xdg-desktop-portal, which they reach over the D-Bus session bus. macOS
+//! has neither, so `wayoa portal` stands in for both: it listens on a
+//! socket of its own as a session bus that only knows the portal, and
+//! answers the portal's Screenshot and ScreenCast interfaces itself. Apps
+//! are pointed at it with
+//! `DBUS_SESSION_BUS_ADDRESS=unix:path=$XDG_RUNTIME_DIR/wayoa-portal-bus`.
+//!
+//! Screenshots are taken by the running compositor over IPC, of the
+//! Wayland windows on the focused window's output, and saved next to the
+//! bus socket. ScreenCast sessions
+//! can be created, but starting one fails: streams are shared as PipeWire
+//! nodes, and there is no PipeWire on macOS.
+//!
+//! The bus doesn't route messages between its clients, and doesn't pass
+//! file descriptors.
+
+mod dbus;
+mod desktop;
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use log::{debug, info, warn};
+
+use crate::ipc;
+
+pub use dbus::{Message, MessageType, Value};
+pub use desktop::{Desktop, PORTAL_NAME, PORTAL_PATH};
+
+/// Usage of the portal subcommand
+pub const USAGE: &str = "portal [--socket <path>]";
+
+/// Name of the bus itself
+const BUS_NAME: &str = "org.freedesktop.DBus";
+
+/// Unique name the portal answers as
+const PORTAL_UNIQUE: &str = ":1.0";
+
+/// GUID of the bus, sent when authenticating
+const BUS_GUID: &str = "7761796f61706f7274616c0000000001";
+
+/// Number of the next client's unique name
+static CLIENTS: AtomicU64 = AtomicU64::new(1);
+
+/// Options of the portal subcommand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortalOptions {
+    /// Socket of the bus, in the runtime directory if unset
+    pub socket: Option<PathBuf>,
+}
+
+impl PortalOptions {
+    /// Parse the arguments following `portal`
+    pub fn parse(args: &[String]) -> Result<Self, PortalError> {
+        match args {
+            [] => Ok(Self { socket: None }),
+            [flag, path] if flag == "--socket" => Ok(Self {
+                socket: Some(PathBuf::from(path)),
+            }),
+            _ => Err(PortalError::Usage),
+        }
+    }
+
+    /// The socket of the bus
+    fn socket(&self) -> PathBuf {
+        self.socket.clone().unwrap_or_else(|| {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join("wayoa-portal-bus")
+        })
+    }
+}
+
+/// Take a screenshot of the focused output through the compositor
+fn capture_output(path: &Path) -> Result<(), String> {
+    let command = format!("screenshot --output focused {}", path.display());
+    let response = ipc::request(&ipc::socket_from_env(), &command).map_err(|e| e.to_string())?;
+    match response.ok {
+        true => Ok(()),
+        false => Err(response.error.unwrap_or_default()),
+    }
+}
+
+/// Run the bus until it fails to accept connections
+pub fn run(options: &PortalOptions) -> anyhow::Result<()> {
+    let socket = options.socket();
+    if socket.exists() {
+        std::fs::remove_file(&socket)?;
+    }
+    let listener = UnixListener::bind(&socket)?;
+    let dir = socket.with_file_name("wayoa-portal");
+    let desktop = Arc::new(Desktop::new(dir, Box::new(capture_output)));
+
+    info!("Portal bus listening on {}", socket.display());
+    println!("DBUS_SESSION_BUS_ADDRESS=unix:path={}", socket.display());
+
+    loop {
+        let (stream, _) = listener.accept()?;
+        let desktop = desktop.clone();
+        std::thread::spawn(move || {
+            let name = format!(":1.{}", CLIENTS.fetch_add(1, Ordering::Relaxed));
+            match serve(stream, &name, &desktop) {
+                Ok(()) => debug!("Bus client {} disconnected", name),
+                Err(e) => debug!("Bus client {} failed: {}", name, e),
+            }
+        });
+    }
+}
+
+/// Authenticate a client, then answer its messages until it disconnects
+fn serve(stream: UnixStream, name: &str, desktop: &Desktop) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    authenticate(&mut reader, &mut writer)?;
+
+    let mut bus = BusClient::new(name);
+    loop {
+        let message = match Message::read(&mut reader) {
+            Ok(message) => message,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for reply in bus.handle(message, desktop) {
+            reply.write(&mut writer)?;
+        }
+    }
+}
+
+/// Run the SASL exchange that opens a connection
+///
+/// EXTERNAL is the only mechanism; the socket is only reachable by its
+/// owner, so the identity the client claims isn't checked.
+fn authenticate(reader: &mut impl BufRead, writer: &mut impl Write) -> std::io::Result<()> {
+    let mut nul = [0u8];
+    reader.read_exact(&mut nul)?;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut words = line.split_whitespace();
+        let reply = match (words.next(), words.next()) {
+            (Some("AUTH"), Some("EXTERNAL")) => match words.next() {
+                Some(_) => format!("OK {}", BUS_GUID),
+                None => "DATA".to_string(),
+            },
+            (Some("DATA"), _) => format!("OK {}", BUS_GUID),
+            (Some("AUTH" | "CANCEL"), _) => "REJECTED EXTERNAL".to_string(),
+            (Some("BEGIN"), _) => return Ok(()),
+            _ => "ERROR".to_string(),
+        };
+        writer.write_all(format!("{}\r\n", reply).as_bytes())?;
+    }
+}
+
+/// A client connection to the bus
+struct BusClient {
+    /// Unique name of the client
+    name: String,
+    /// Serial of the next message sent to it
+    serial: u32,
+}
+
+impl BusClient {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            serial: 0,
+        }
+    }
+
+    /// Handle a message from the client, returning what is sent back
+    fn handle(&mut self, mut message: Message, desktop: &Desktop) -> Vec<Message> {
+        if message.kind != MessageType::MethodCall {
+            return Vec::new();
+        }
+        message.sender = Some(self.name.clone());
+        let destination = message.destination.as_deref().unwrap_or_default();
+        let (sender, mut replies) = match destination {
+            BUS_NAME => (BUS_NAME, self.bus_method(&message)),
+            PORTAL_NAME | PORTAL_UNIQUE if message.path.as_deref().is_some_and(Desktop::owns) => {
+                (PORTAL_UNIQUE, desktop.handle(&message, &self.name))
+            }
+            _ => (
+                BUS_NAME,
+                vec![Message::error(
+                    &message,
+                    "org.freedesktop.DBus.Error.ServiceUnknown",
+                    &format!("The name {} is not provided by this bus", destination),
+                )],
+            ),
+        };
+        if message.flags & dbus::NO_REPLY_EXPECTED != 0 {
+            replies.retain(|reply| reply.reply_serial.is_none());
+        }
+        for reply in &mut replies {
+            self.serial += 1;
+            reply.serial = self.serial;
+            reply.sender = Some(sender.to_string());
+        }
+        replies
+    }
+
+    /// Answer a method of the bus itself
+    fn bus_method(&self, call: &Message) -> Vec<Message> {
+        let name = call
+            .body
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let owner = match name {
+            BUS_NAME => Some(BUS_NAME),
+            PORTAL_NAME | PORTAL_UNIQUE => Some(PORTAL_UNIQUE),
+            _ if name == self.name => Some(self.name.as_str()),
+            _ => None,
+        };
+        let strings = |names: &[&str]| {
+            Value::Array(
+                "s".to_string(),
+                names.iter().map(|n| Value::String(n.to_string())).collect(),
+            )
+        };
+        let body = match call.member.as_deref().unwrap_or_default() {
+            "Hello" => vec![Value::String(self.name.clone())],
+            // Primary owner, or exists for the portal's name
+            "RequestName" if name == PORTAL_NAME => vec![Value::Uint32(3)],
+            "RequestName" | "ReleaseName" => vec![Value::Uint32(1)],
+            "AddMatch" | "RemoveMatch" | "Ping" => Vec::new(),
+            "GetId" => vec![Value::String(BUS_GUID.to_string())],
+            "NameHasOwner" => vec![Value::Bool(owner.is_some())],
+            "ListNames" => vec![strings(&[BUS_NAME, PORTAL_NAME, PORTAL_UNIQUE, &self.name])],
+            "ListActivatableNames" => vec![strings(&[BUS_NAME, PORTAL_NAME])],
+            // Already running
+            "StartServiceByName" if name == PORTAL_NAME => vec![Value::Uint32(2)],
+            "GetNameOwner" if owner.is_some() => {
+                vec![Value::String(owner.unwrap_or_default().to_string())]
+            }
+            "GetNameOwner" => {
+                return vec![Message::error(
+                    call,
+                    "org.freedesktop.DBus.Error.NameHasNoOwner",
+                    &format!("No owner for {}", name),
+                )]
+            }
+            member => {
+                warn!("Unsupported bus method {} from {}", member, self.name);
+                return vec![Message::error(
+                    call,
+                    "org.freedesktop.DBus.Error.UnknownMethod",
+                    &format!("Unsupported method {}", member),
+                )];
+            }
+        };
+        vec![Message::method_return(call, body)]
+    }
+}
+
+/// Portal errors
+#[derive(Debug, thiserror::Error)]
+pub enum PortalError {
+    #[error("Usage: wayoa {USAGE}")]
+    Usage,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(PortalOptions::parse(&[]).unwrap().socket, None);
+        let args = ["--socket".to_string(), "/tmp/bus".to_string()];
+        assert_eq!(
+            PortalOptions::parse(&args).unwrap().socket,
+            Some(PathBuf::from("/tmp/bus"))
+        );
+        assert!(matches!(
+            PortalOptions::parse(&args[..1]),
+            Err(PortalError::Usage)
+        ));
+    }
+
+    #[test]
+    fn test_connect_and_hello() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let desktop = Desktop::new(PathBuf::new(), Box::new(|_| Err("unused".to_string())));
+        let bus = std::thread::spawn(move || serve(server, ":1.9", &desktop));
+
+        let mut writer = client.try_clone().unwrap();
+        let mut reader = BufReader::new(client);
+        writer.write_all(b"\0AUTH EXTERNAL 31303030\r\n").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, format!("OK {}\r\n", BUS_GUID));
+        writer.write_all(b"BEGIN\r\n").unwrap();
+
+        let mut hello = Message::method_call(BUS_NAME, "/org/freedesktop/DBus", BUS_NAME, "Hello");
+        hello.serial = 1;
+        hello.write(&mut writer).unwrap();
+        let reply = Message::read(&mut reader).unwrap();
+        assert_eq!(reply.reply_serial, Some(1));
+        assert_eq!(reply.body, [Value::String(":1.9".to_string())]);
+
+        let mut owner =
+            Message::method_call(BUS_NAME, "/org/freedesktop/DBus", BUS_NAME, "GetNameOwner");
+        owner.serial = 2;
+        owner.body = vec![Value::String(PORTAL_NAME.to_string())];
+        owner.write(&mut writer).unwrap();
+        let reply = Message::read(&mut reader).unwrap();
+        assert_eq!(reply.body, [Value::String(PORTAL_UNIQUE.to_string())]);
+
+        // Nothing else lives on this bus
+        let mut other = Message::method_call("org.example.App", "/", "org.example.App", "Go");
+        other.serial = 3;
+        other.write(&mut writer).unwrap();
+        let reply = Message::read(&mut reader).unwrap();
+        assert_eq!(reply.kind, MessageType::Error);
+
+        drop(writer);
+        drop(reader);
+        bus.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! xdg_activation_v1 tokens
+//!
+//! A client passes focus to another toplevel, possibly of another client,
+//! by getting a token and handing it over. The compositor only grants focus
+//! for tokens that stem from the user: ones requested with the serial of a
+//! recent key or button press the requesting client received, and ones the
+//! compositor issues itself to the apps it launches. Other activations only
+//! make the window ask for attention. Tokens can be used once and expire.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::compositor::SurfaceId;
+
+/// Time a token stays usable
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Unique identifier for token requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActivationTokenId(pub u64);
+
+impl ActivationTokenId {
+    fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(1);
+        ActivationTokenId(COUNTER.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// What a client told about a token before committing it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenRequest {
+    /// Serial of the input event the request stems from
+    pub serial: Option<u32>,
+    /// Application ID of the toplevel to activate
+    pub app_id: Option<String>,
+    /// Surface of the requesting client
+    pub surface: Option<SurfaceId>,
+}
+
+/// An issued token
+#[derive(Debug)]
+struct IssuedToken {
+    /// When the token was issued
+    issued: Instant,
+    /// Whether the token grants focus
+    granted: bool,
+}
+
+/// Token requests and issued tokens
+#[derive(Debug)]
+pub struct ActivationHandler {
+    /// Requests not committed yet
+    requests: HashMap<ActivationTokenId, TokenRequest>,
+    /// Issued tokens by their string
+    tokens: HashMap<String, IssuedToken>,
+    /// Randomness for token strings
+    random: RandomState,
+    /// Number of tokens issued
+    issued: u64,
+}
+
+impl Default for ActivationHandler {
+    fn default() -> Self {
+        Self {
+            requests: HashMap::new(),
+            tokens: HashMap::new(),
+            random: RandomState::new(),
+            issued: 0,
+        }
+    }
+}
+
+impl ActivationHandler {
+    /// Create a handler without tokens
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a token request
+    pub fn create_request(&mut self) -> ActivationTokenId {
+        let id = ActivationTokenId::new();
+        self.requests.insert(id, TokenRequest::default());
+        id
+    }
+
+    /// Get a request that isn't committed yet
+    pub fn request_mut(&mut self, id: ActivationTokenId) -> Option<&mut TokenRequest> {
+        self.requests.get_mut(&id)
+    }
+
+    /// Take a request to commit it
+    pub fn take_request(&mut self, id: ActivationTokenId) -> Option<TokenRequest> {
+        self.requests.remove(&id)
+    }
+
+    /// Forget a request that was destroyed before being committed
+    pub fn destroy_request(&mut self, id: ActivationTokenId) {
+        self.requests.remove(&id);
+    }
+
+    /// Issue a token, which grants focus if it stems from the user
+    pub fn issue(&mut self, granted: bool, now: Instant) -> String {
+        self.tokens
+            .retain(|_, token| now.duration_since(token.issued) < TOKEN_LIFETIME);
+        self.issued += 1;
+        let token = format!(
+            "wayoa-{}-{:016x}",
+            self.issued,
+            self.random.hash_one((self.issued, now))
+        );
+        debug!("Issued activation token {} (granted: {})", token, granted);
+        self.tokens.insert(
+            token.clone(),
+            IssuedToken {
+                issued: now,
+                granted,
+            },
+        );
+        token
+    }
+
+    /// Use a token
+    pub fn activate(&mut self, token: &str, now: Instant) -> Result<(), ActivationError> {
+        let issued = self
+            .tokens
+            .remove(token)
+            .ok_or(ActivationError::UnknownToken)?;
+        if now.duration_since(issued.issued) >= TOKEN_LIFETIME {
+            return Err(ActivationError::Expired);
+        }
+        if !issued.granted {
+            return Err(ActivationError::NoUserInput);
+        }
+        Ok(())
+    }
+}
+
+/// Reasons focus isn't granted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum ActivationError {
+    #[error("Unknown or already used activation token")]
+    UnknownToken,
+    #[error("Activation token expired")]
+    Expired,
+    #[error("Activation token wasn't requested in response to user input")]
+    NoUserInput,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let now = Instant::now();
+        let mut activation = ActivationHandler::new();
+        let id = activation.create_request();
+        activation.request_mut(id).unwrap().serial = Some(7);
+        assert_eq!(activation.take_request(id).unwrap().serial, Some(7));
+        assert!(activation.request_mut(id).is_none());
+
+        let granted = activation.issue(true, now);
+        let denied = activation.issue(false, now);
+        assert_ne!(granted, denied);
+        assert_eq!(activation.activate(&granted, now), Ok(()));
+        assert_eq!(
+            activation.activate(&granted, now),
+            Err(ActivationError::UnknownToken)
+        );
+        assert_eq!(
+            activation.activate(&denied, now),
+            Err(ActivationError::NoUserInput)
+        );
+
+        let old = activation.issue(true, now);
+        assert_eq!(
+            activation.activate(&old, now + TOKEN_LIFETIME),
+            Err(ActivationError::Expired)
+        );
+    }
+}
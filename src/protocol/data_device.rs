@@ -1,11 +1,19 @@
 //! wl_data_device protocol implementation
 //!
-//! Implements clipboard and drag-and-drop functionality.
+//! Implements clipboard and drag-and-drop functionality, and the primary
+//! selection pasted with the middle button. Both selections are sources
+//! tracked here; the primary selection is offered to the client with
+//! pointer focus.
 
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::OwnedFd;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use log::debug;
+use rustix::event::{PollFd, PollFlags, Timespec};
 
 use crate::compositor::SurfaceId;
 
@@ -31,6 +39,66 @@ impl DataOfferId {
     }
 }
 
+/// Text MIME types, most preferred first
+pub const TEXT_MIME_TYPES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "TEXT",
+    "STRING",
+];
+
+/// Largest transfer read from a data source
+pub const MAX_TRANSFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Errors reading data from a data source
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("No selection")]
+    NoSelection,
+    #[error("Selection has no text type")]
+    NoText,
+    #[error("Source did not finish writing in time")]
+    Timeout,
+    #[error("Transfer larger than {MAX_TRANSFER_SIZE} bytes")]
+    TooLarge,
+    #[error("Text is not UTF-8")]
+    NotUtf8,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Pick the text type to read from the MIME types a source offers
+pub fn text_mime_type(mime_types: &[String]) -> Option<&'static str> {
+    TEXT_MIME_TYPES
+        .iter()
+        .find(|text| mime_types.iter().any(|mime| mime == *text))
+        .copied()
+}
+
+/// Read what a data source writes to a pipe until it closes it
+///
+/// Gives up if the source hasn't closed the pipe within `timeout`.
+pub fn read_transfer(fd: OwnedFd, timeout: Duration) -> Result<Vec<u8>, TransferError> {
+    let deadline = Instant::now() + timeout;
+    let mut file = File::from(fd);
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 8192];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timespec = Timespec::try_from(remaining).map_err(|_| TransferError::Timeout)?;
+        let mut fds = [PollFd::new(&file, PollFlags::IN)];
+        if rustix::event::poll(&mut fds, Some(&timespec)).map_err(std::io::Error::from)? == 0 {
+            return Err(TransferError::Timeout);
+        }
+        match file.read(&mut chunk)? {
+            0 => return Ok(data),
+            n if data.len() + n > MAX_TRANSFER_SIZE => return Err(TransferError::TooLarge),
+            n => data.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
 /// A data source (clipboard or drag source)
 #[derive(Debug)]
 pub struct DataSource {
@@ -159,6 +227,8 @@ pub struct DataDeviceHandler {
     offers: HashMap<DataOfferId, DataOffer>,
     /// Current clipboard selection source
     selection: Option<DataSourceId>,
+    /// Current primary selection source
+    primary_selection: Option<DataSourceId>,
     /// Current DnD source
     dnd_source: Option<DataSourceId>,
     /// Surface being dragged over (will be used for full DnD implementation)
@@ -173,6 +243,7 @@ impl DataDeviceHandler {
             sources: HashMap::new(),
             offers: HashMap::new(),
             selection: None,
+            primary_selection: None,
             dnd_source: None,
             dnd_focus: None,
         }
@@ -203,6 +274,9 @@ impl DataDeviceHandler {
         if self.selection == Some(id) {
             self.selection = None;
         }
+        if self.primary_selection == Some(id) {
+            self.primary_selection = None;
+        }
         if self.dnd_source == Some(id) {
             self.dnd_source = None;
         }
@@ -214,11 +288,34 @@ impl DataDeviceHandler {
         debug!("Selection set to {:?}", source_id);
     }
 
+    /// Get the text type the selection can be read in
+    pub fn selection_text_type(&self) -> Option<&'static str> {
+        text_mime_type(&self.selection()?.mime_types)
+    }
+
     /// Get the current selection
     pub fn selection(&self) -> Option<&DataSource> {
         self.selection.and_then(|id| self.sources.get(&id))
     }
 
+    /// Set the primary selection
+    ///
+    /// Returns the source it replaces, which should be cancelled.
+    pub fn set_primary_selection(
+        &mut self,
+        source_id: Option<DataSourceId>,
+        _serial: u32,
+    ) -> Option<DataSourceId> {
+        debug!("Primary selection set to {:?}", source_id);
+        std::mem::replace(&mut self.primary_selection, source_id)
+            .filter(|old| Some(*old) != source_id)
+    }
+
+    /// Get the current primary selection
+    pub fn primary_selection(&self) -> Option<&DataSource> {
+        self.primary_selection.and_then(|id| self.sources.get(&id))
+    }
+
     /// Start a drag operation
     pub fn start_drag(
         &mut self,
@@ -291,6 +388,50 @@ mod tests {
         assert!(handler.get_offer(offer_id).is_some());
     }
 
+    #[test]
+    fn test_primary_selection() {
+        let mut handler = DataDeviceHandler::new();
+        let first = handler.create_data_source();
+        let second = handler.create_data_source();
+
+        assert_eq!(handler.set_primary_selection(Some(first), 1), None);
+        assert_eq!(handler.set_primary_selection(Some(first), 2), None);
+        assert_eq!(handler.set_primary_selection(Some(second), 3), Some(first));
+        // The clipboard is separate
+        assert!(handler.selection().is_none());
+
+        let offer = handler.create_offer(second).unwrap();
+        assert_eq!(handler.get_offer(offer).unwrap().source_id, second);
+        handler.destroy_source(second);
+        assert!(handler.primary_selection().is_none());
+    }
+
+    #[test]
+    fn test_read_text_selection() {
+        let mut handler = DataDeviceHandler::new();
+        let source_id = handler.create_data_source();
+        let source = handler.get_source_mut(source_id).unwrap();
+        source.offer("text/html".to_string());
+        source.offer("STRING".to_string());
+        source.offer("text/plain".to_string());
+        handler.set_selection(Some(source_id), 1);
+        assert_eq!(handler.selection_text_type(), Some("text/plain"));
+
+        let (read, write) = rustix::pipe::pipe().unwrap();
+        let writer = std::thread::spawn(move || {
+            use std::io::Write;
+            File::from(write).write_all(b"hello").unwrap();
+        });
+        let data = read_transfer(read, Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+        assert_eq!(data, b"hello");
+
+        // A source that never closes the pipe times out
+        let (read, _write) = rustix::pipe::pipe().unwrap();
+        let result = read_transfer(read, Duration::from_millis(10));
+        assert!(matches!(result, Err(TransferError::Timeout)));
+    }
+
     #[test]
     fn test_dnd_action_negotiation() {
         let mut source = DataSource::new();
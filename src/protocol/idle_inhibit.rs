@@ -0,0 +1,261 @@
+//! Idle inhibitor tracking
+//!
+//! Clients inhibit idle behavior (display sleep, screen savers) for as long
+//! as an inhibiting surface is visible. Users can tell the compositor to
+//! ignore the inhibitors of specific app_ids, for clients that keep the
+//! display awake when they shouldn't.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::{debug, info};
+use serde::Serialize;
+
+use crate::compositor::{CompositorState, SurfaceId};
+
+/// Unique identifier for idle inhibitors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdleInhibitorId(pub u64);
+
+impl IdleInhibitorId {
+    fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(1);
+        IdleInhibitorId(COUNTER.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// An idle inhibitor
+#[derive(Debug)]
+pub struct IdleInhibitor {
+    /// Unique identifier
+    pub id: IdleInhibitorId,
+    /// Surface the inhibitor was created for
+    pub surface_id: SurfaceId,
+}
+
+/// Inhibitor state as reported to users
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InhibitorInfo {
+    /// Inhibitor ID
+    pub id: u64,
+    /// Surface ID
+    pub surface: u64,
+    /// Application ID of the window owning the surface
+    pub app_id: Option<String>,
+    /// Whether the surface is currently visible
+    pub visible: bool,
+    /// Whether the app_id is on the ignore list
+    pub ignored: bool,
+    /// Whether the inhibitor currently prevents idle
+    pub active: bool,
+}
+
+/// Idle inhibitor registry
+#[derive(Debug, Default)]
+pub struct IdleInhibitHandler {
+    /// Inhibitors by ID
+    inhibitors: HashMap<IdleInhibitorId, IdleInhibitor>,
+    /// App IDs whose inhibitors are ignored
+    ignored: HashSet<String>,
+}
+
+impl IdleInhibitHandler {
+    /// Create a new idle inhibit handler
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an inhibitor for a surface
+    pub fn create_inhibitor(&mut self, surface_id: SurfaceId) -> IdleInhibitorId {
+        let inhibitor = IdleInhibitor {
+            id: IdleInhibitorId::new(),
+            surface_id,
+        };
+        let id = inhibitor.id;
+        debug!("Created idle inhibitor {:?} for {:?}", id, surface_id);
+        self.inhibitors.insert(id, inhibitor);
+        id
+    }
+
+    /// Destroy an inhibitor
+    pub fn destroy_inhibitor(&mut self, id: IdleInhibitorId) -> Option<IdleInhibitor> {
+        debug!("Destroyed idle inhibitor {:?}", id);
+        self.inhibitors.remove(&id)
+    }
+
+    /// Remove all inhibitors of a surface
+    pub fn remove_surface(&mut self, surface_id: SurfaceId) {
+        self.inhibitors.retain(|_, i| i.surface_id != surface_id);
+    }
+
+    /// Get an inhibitor by ID
+    pub fn get(&self, id: IdleInhibitorId) -> Option<&IdleInhibitor> {
+        self.inhibitors.get(&id)
+    }
+
+    /// Ignore inhibitors from an app_id
+    ///
+    /// Returns false if the app_id was already ignored.
+    pub fn ignore(&mut self, app_id: &str) -> bool {
+        info!("Ignoring idle inhibitors from {}", app_id);
+        self.ignored.insert(app_id.to_string())
+    }
+
+    /// Stop ignoring inhibitors from an app_id
+    ///
+    /// Returns false if the app_id was not ignored.
+    pub fn unignore(&mut self, app_id: &str) -> bool {
+        info!("Honoring idle inhibitors from {}", app_id);
+        self.ignored.remove(app_id)
+    }
+
+    /// Check if an app_id is ignored
+    pub fn is_ignored(&self, app_id: &str) -> bool {
+        self.ignored.contains(app_id)
+    }
+
+    /// Get the ignored app_ids, sorted
+    pub fn ignored(&self) -> Vec<String> {
+        let mut ignored: Vec<String> = self.ignored.iter().cloned().collect();
+        ignored.sort();
+        ignored
+    }
+
+    /// Describe all inhibitors, sorted by ID
+    pub fn list(&self, compositor: &CompositorState) -> Vec<InhibitorInfo> {
+        let mut list: Vec<InhibitorInfo> = self
+            .inhibitors
+            .values()
+            .map(|inhibitor| {
+                let app_id = app_id_for_surface(compositor, inhibitor.surface_id);
+                let visible = is_surface_visible(compositor, inhibitor.surface_id);
+                let ignored = app_id.as_deref().is_some_and(|a| self.is_ignored(a));
+                InhibitorInfo {
+                    id: inhibitor.id.0,
+                    surface: inhibitor.surface_id.0,
+                    app_id,
+                    visible,
+                    ignored,
+                    active: visible && !ignored,
+                }
+            })
+            .collect();
+        list.sort_by_key(|info| info.id);
+        list
+    }
+
+    /// Check if any inhibitor currently prevents idle
+    pub fn is_inhibited(&self, compositor: &CompositorState) -> bool {
+        self.inhibitors.values().any(|inhibitor| {
+            is_surface_visible(compositor, inhibitor.surface_id)
+                && !app_id_for_surface(compositor, inhibitor.surface_id)
+                    .is_some_and(|a| self.is_ignored(&a))
+        })
+    }
+
+    /// Get the number of inhibitors
+    pub fn len(&self) -> usize {
+        self.inhibitors.len()
+    }
+
+    /// Check if empty
+    pub fn is_empty(&self) -> bool {
+        self.inhibitors.is_empty()
+    }
+}
+
+fn app_id_for_surface(compositor: &CompositorState, surface_id: SurfaceId) -> Option<String> {
+    let root = compositor.surfaces.root(surface_id);
+    compositor.windows.get_by_surface(root)?.app_id.clone()
+}
+
+/// A surface is visible when it has content and its window is not minimized
+fn is_surface_visible(compositor: &CompositorState, surface_id: SurfaceId) -> bool {
+    let has_buffer = compositor
+        .surfaces
+        .get(surface_id)
+        .is_some_and(|surface| surface.buffer.is_some());
+    let minimized = compositor
+        .windows
+        .get_by_surface(compositor.surfaces.root(surface_id))
+        .is_some_and(|window| window.state.minimized);
+    has_buffer && !minimized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::surface::BufferInfo;
+
+    fn mapped_window(compositor: &mut CompositorState, app_id: &str) -> SurfaceId {
+        let surface_id = compositor.surfaces.create_surface();
+        compositor.surfaces.get_mut(surface_id).unwrap().buffer = Some(BufferInfo {
+            width: 100,
+            height: 100,
+            stride: 400,
+            format: 0,
+            offset: 0,
+            shm_buffer_id: None,
+            solid: None,
+            snapshot: None,
+        });
+        let window_id = compositor.windows.create_window(surface_id);
+        compositor.windows.get_mut(window_id).unwrap().app_id = Some(app_id.to_string());
+        surface_id
+    }
+
+    #[test]
+    fn test_visible_inhibitor_is_active() {
+        let mut compositor = CompositorState::new();
+        let mut handler = IdleInhibitHandler::new();
+        let surface_id = mapped_window(&mut compositor, "mpv");
+
+        let id = handler.create_inhibitor(surface_id);
+        assert!(handler.is_inhibited(&compositor));
+
+        handler.destroy_inhibitor(id);
+        assert!(!handler.is_inhibited(&compositor));
+    }
+
+    #[test]
+    fn test_hidden_surface_does_not_inhibit() {
+        let mut compositor = CompositorState::new();
+        let mut handler = IdleInhibitHandler::new();
+
+        // Unmapped surface
+        let unmapped = compositor.surfaces.create_surface();
+        handler.create_inhibitor(unmapped);
+        assert!(!handler.is_inhibited(&compositor));
+
+        // Minimized window
+        let surface_id = mapped_window(&mut compositor, "mpv");
+        handler.create_inhibitor(surface_id);
+        let window_id = compositor.windows.window_for_surface(surface_id).unwrap();
+        compositor
+            .windows
+            .get_mut(window_id)
+            .unwrap()
+            .set_minimized(true);
+        assert!(!handler.is_inhibited(&compositor));
+    }
+
+    #[test]
+    fn test_ignored_app_id() {
+        let mut compositor = CompositorState::new();
+        let mut handler = IdleInhibitHandler::new();
+        let surface_id = mapped_window(&mut compositor, "chatty");
+        handler.create_inhibitor(surface_id);
+
+        assert!(handler.ignore("chatty"));
+        assert!(!handler.ignore("chatty"));
+        assert!(!handler.is_inhibited(&compositor));
+
+        let list = handler.list(&compositor);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].app_id.as_deref(), Some("chatty"));
+        assert!(list[0].visible && list[0].ignored && !list[0].active);
+
+        assert!(handler.unignore("chatty"));
+        assert!(handler.is_inhibited(&compositor));
+    }
+}
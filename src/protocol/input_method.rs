@@ -0,0 +1,153 @@
+//! zwp_input_method_v2 state tracking
+//!
+//! An input method is a client composing text for other clients, such as
+//! an IME for CJK languages. There is at most one per seat. It is active
+//! while a text input with keyboard focus is enabled and is told about that
+//! text input's state; the text it commits goes to that text input. With a
+//! keyboard grab it receives the hardware keys instead of the focused
+//! client while it is active.
+
+use crate::protocol::text_input::{
+    ChangeCause, ContentHint, ContentPurpose, Preedit, SurroundingText, TextInputId, TextInputState,
+};
+
+/// Event to send to the input method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMethodEvent {
+    /// A text input wants the input method
+    Activate,
+    /// No text input wants the input method any more
+    Deactivate,
+    /// Text around the cursor of the text input
+    SurroundingText(SurroundingText),
+    /// What caused the last change of the surrounding text
+    TextChangeCause(ChangeCause),
+    /// Hints and purpose of the text field
+    ContentType {
+        hint: ContentHint,
+        purpose: ContentPurpose,
+    },
+    /// Apply the events sent since the last done
+    Done,
+}
+
+/// Text set by the input method, applied to the text input on commit
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputMethodText {
+    /// Text being composed
+    pub preedit: Option<Preedit>,
+    /// Text to insert at the cursor
+    pub commit: Option<String>,
+    /// Bytes to delete before and after the cursor
+    pub delete: Option<(u32, u32)>,
+}
+
+/// The input method of the seat
+#[derive(Debug, Clone, Default)]
+pub struct InputMethod {
+    /// Text input it was last told about, None while inactive
+    sent: Option<(TextInputId, TextInputState)>,
+    /// Done events sent, which the client echoes on commit
+    pub done_count: u32,
+    /// Text set since the last commit
+    pub pending: InputMethodText,
+}
+
+impl InputMethod {
+    /// Create an inactive input method
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if a text input is using the input method
+    pub fn is_active(&self) -> bool {
+        self.sent.is_some()
+    }
+
+    /// Follow the focused and enabled text input
+    ///
+    /// Returns the events telling the input method about the change, none
+    /// if nothing changed. Moving to another text input deactivates the
+    /// input method before activating it again.
+    pub fn update(
+        &mut self,
+        active: Option<(TextInputId, &TextInputState)>,
+    ) -> Vec<InputMethodEvent> {
+        let mut events = Vec::new();
+        match (&self.sent, active) {
+            (None, None) => return events,
+            (Some(sent), Some((id, state))) if sent.0 == id && sent.1 == *state => return events,
+            (Some(sent), Some((id, _))) if sent.0 != id => {
+                events = self.update(None);
+                events.extend(self.update(active));
+                return events;
+            }
+            (Some(_), None) => events.push(InputMethodEvent::Deactivate),
+            (sent, Some((_, state))) => {
+                if sent.is_none() {
+                    events.push(InputMethodEvent::Activate);
+                    self.pending = InputMethodText::default();
+                }
+                if let Some(surrounding) = &state.surrounding {
+                    events.push(InputMethodEvent::SurroundingText(surrounding.clone()));
+                }
+                events.push(InputMethodEvent::TextChangeCause(state.change_cause));
+                events.push(InputMethodEvent::ContentType {
+                    hint: state.hint,
+                    purpose: state.purpose,
+                });
+            }
+        }
+        self.sent = active.map(|(id, state)| (id, state.clone()));
+        self.done_count = self.done_count.wrapping_add(1);
+        events.push(InputMethodEvent::Done);
+        events
+    }
+
+    /// Take the text set since the last commit
+    ///
+    /// Returns None while inactive, dropping the text.
+    pub fn commit(&mut self) -> Option<InputMethodText> {
+        let text = std::mem::take(&mut self.pending);
+        self.is_active().then_some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_activation() {
+        let mut method = InputMethod::new();
+        assert!(method.update(None).is_empty());
+
+        let state = TextInputState {
+            enabled: true,
+            purpose: ContentPurpose::Email,
+            ..Default::default()
+        };
+        let events = method.update(Some((TextInputId(1), &state)));
+        assert_eq!(events.first(), Some(&InputMethodEvent::Activate));
+        assert_eq!(events.last(), Some(&InputMethodEvent::Done));
+        assert!(method.update(Some((TextInputId(1), &state))).is_empty());
+
+        // Another text input deactivates before activating again
+        let events = method.update(Some((TextInputId(2), &state)));
+        assert_eq!(
+            events[..3],
+            [
+                InputMethodEvent::Deactivate,
+                InputMethodEvent::Done,
+                InputMethodEvent::Activate
+            ]
+        );
+        assert_eq!(method.done_count, 3);
+
+        method.pending.commit = Some("é".to_string());
+        assert_eq!(method.commit().unwrap().commit.as_deref(), Some("é"));
+        method.update(None);
+        method.pending.commit = Some("x".to_string());
+        assert_eq!(method.commit(), None);
+    }
+}
@@ -7,7 +7,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 
 use log::debug;
 
-use crate::compositor::{OutputId, SurfaceId};
+use crate::compositor::{Insets, OutputId, SurfaceId};
 
 /// Unique identifier for layer surfaces
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -119,6 +119,8 @@ pub struct LayerSurface {
     pub configured: bool,
     /// Configure serial
     pub configure_serial: u32,
+    /// Size sent in the last configure
+    pub configured_size: (u32, u32),
 }
 
 impl LayerSurface {
@@ -142,6 +144,7 @@ impl LayerSurface {
             keyboard_interactivity: KeyboardInteractivity::None,
             configured: false,
             configure_serial: 0,
+            configured_size: (0, 0),
         }
     }
 
@@ -175,6 +178,36 @@ impl LayerSurface {
         self.keyboard_interactivity = mode;
     }
 
+    /// Get the edge this surface reserves space along, if any
+    ///
+    /// Only surfaces anchored to one edge, or to one edge and both edges
+    /// perpendicular to it, reserve space.
+    pub fn exclusive_edge(&self) -> Option<Anchor> {
+        if self.exclusive_zone <= 0 {
+            return None;
+        }
+        let horizontal = Anchor::LEFT | Anchor::RIGHT;
+        let vertical = Anchor::TOP | Anchor::BOTTOM;
+        [Anchor::TOP, Anchor::BOTTOM, Anchor::LEFT, Anchor::RIGHT]
+            .into_iter()
+            .find(|&edge| {
+                let across = if vertical.contains(edge) {
+                    horizontal
+                } else {
+                    vertical
+                };
+                self.anchor == edge || self.anchor == edge | across
+            })
+    }
+
+    /// Check that a size of 0 is only used along an axis anchored to both
+    /// edges, where the surface is stretched
+    pub fn has_valid_size(&self) -> bool {
+        let (width, height) = self.size;
+        (width != 0 || self.anchor.contains(Anchor::LEFT | Anchor::RIGHT))
+            && (height != 0 || self.anchor.contains(Anchor::TOP | Anchor::BOTTOM))
+    }
+
     /// Calculate the geometry based on output and anchoring
     pub fn calculate_geometry(
         &self,
@@ -186,10 +219,10 @@ impl LayerSurface {
 
         // If anchored to opposite edges and size is 0, stretch to fill
         if self.anchor.contains(Anchor::LEFT | Anchor::RIGHT) && width == 0 {
-            width = output_width - (margin_left + margin_right) as u32;
+            width = (output_width as i32 - margin_left - margin_right).max(0) as u32;
         }
         if self.anchor.contains(Anchor::TOP | Anchor::BOTTOM) && height == 0 {
-            height = output_height - (margin_top + margin_bottom) as u32;
+            height = (output_height as i32 - margin_top - margin_bottom).max(0) as u32;
         }
 
         // Calculate position based on anchoring
@@ -273,6 +306,30 @@ impl LayerShellHandler {
         }
     }
 
+    /// Get the space reserved by exclusive zones on an output
+    ///
+    /// Surfaces without an output count for every output. Zones along the
+    /// same edge stack.
+    pub fn exclusive_insets(&self, output: OutputId) -> Insets {
+        let mut insets = Insets::default();
+        for surface in self
+            .surfaces
+            .values()
+            .filter(|s| s.output.is_none_or(|o| o == output))
+        {
+            let (top, right, bottom, left) = surface.margin;
+            let zone = surface.exclusive_zone;
+            match surface.exclusive_edge() {
+                Some(Anchor::TOP) => insets.top += zone + top,
+                Some(Anchor::BOTTOM) => insets.bottom += zone + bottom,
+                Some(Anchor::LEFT) => insets.left += zone + left,
+                Some(Anchor::RIGHT) => insets.right += zone + right,
+                _ => {}
+            }
+        }
+        insets
+    }
+
     /// Get all layer surfaces on a specific layer
     pub fn surfaces_on_layer(&self, layer: Layer) -> impl Iterator<Item = &LayerSurface> {
         self.surfaces.values().filter(move |s| s.layer == layer)
@@ -318,6 +375,11 @@ mod tests {
         assert_eq!(y, 0);
         assert_eq!(w, 1920);
         assert_eq!(h, 50);
+        assert!(surface.has_valid_size());
+
+        // Nothing to stretch the height to
+        surface.set_size(0, 0);
+        assert!(!surface.has_valid_size());
     }
 
     #[test]
@@ -332,4 +394,38 @@ mod tests {
         handler.destroy(id);
         assert!(handler.get(id).is_none());
     }
+
+    #[test]
+    fn test_exclusive_insets() {
+        let mut handler = LayerShellHandler::new();
+        let output = OutputId(1);
+
+        let top = handler.get_layer_surface(SurfaceId(1), None, Layer::Top, "bar".to_string());
+        let bar = handler.get_mut(top).unwrap();
+        bar.set_anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT);
+        bar.set_exclusive_zone(30);
+        bar.set_margin(4, 0, 0, 0);
+
+        // A dock on the left of another output doesn't count
+        let dock =
+            handler.get_layer_surface(SurfaceId(2), Some(OutputId(2)), Layer::Top, "dock".into());
+        let dock = handler.get_mut(dock).unwrap();
+        dock.set_anchor(Anchor::LEFT);
+        dock.set_exclusive_zone(60);
+
+        // Anchored to opposite edges, so it reserves nothing
+        let overlay = handler.get_layer_surface(SurfaceId(3), None, Layer::Top, "osd".into());
+        let overlay = handler.get_mut(overlay).unwrap();
+        overlay.set_anchor(Anchor::TOP | Anchor::BOTTOM);
+        overlay.set_exclusive_zone(10);
+
+        assert_eq!(
+            handler.exclusive_insets(output),
+            Insets {
+                top: 34,
+                ..Default::default()
+            }
+        );
+        assert_eq!(handler.exclusive_insets(OutputId(2)).left, 60);
+    }
 }
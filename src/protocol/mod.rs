@@ -6,24 +6,38 @@
 //! - wl_seat, wl_keyboard, wl_pointer, wl_touch
 //! - wl_shm, wl_buffer
 //! - wl_output
-//! - wl_data_device (clipboard/drag-and-drop)
+//! - wl_data_device (clipboard/drag-and-drop) and zwp_primary_selection_v1
+//! - idle inhibitors
 //! - wlr-layer-shell
 //! - wlr-screencopy
+//! - zwp_text_input_v3
+//! - zwp_input_method_v2
+//! - xdg_wm_base pings
+//! - xdg_activation_v1 tokens
 
+pub mod activation;
 pub mod compositor;
 pub mod data_device;
+pub mod idle_inhibit;
+pub mod input_method;
 pub mod layer_shell;
 pub mod output;
+pub mod ping;
 pub mod screencopy;
 pub mod seat;
 pub mod shell;
 pub mod shm;
+pub mod text_input;
 
+pub use activation::ActivationHandler;
 pub use compositor::WlCompositorHandler;
 pub use data_device::DataDeviceHandler;
+pub use idle_inhibit::IdleInhibitHandler;
+pub use input_method::InputMethod;
 pub use layer_shell::LayerShellHandler;
 pub use output::WlOutputHandler;
 pub use screencopy::ScreencopyHandler;
 pub use seat::WlSeatHandler;
 pub use shell::XdgShellHandler;
 pub use shm::WlShmHandler;
+pub use text_input::TextInputHandler;
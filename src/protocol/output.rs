@@ -109,7 +109,6 @@ pub fn enumerate_outputs() -> Vec<Output> {
         let screens = NSScreen::screens(mtm);
         for (i, screen) in screens.iter().enumerate() {
             let frame = screen.frame();
-            let _visible_frame = screen.visibleFrame();
 
             let mut output = Output::new(format!("screen-{}", i));
             output.make = "Apple".to_string();
@@ -119,12 +118,16 @@ pub fn enumerate_outputs() -> Vec<Output> {
 
             // Get backing scale factor for Retina displays
             output.scale = screen.backingScaleFactor().round() as i32;
+            output.fractional_scale = screen.backingScaleFactor();
+            output.reserved = screen_insets(&screen);
+            let (refresh, min_refresh) = screen_refresh(&screen);
+            output.min_refresh = min_refresh;
 
             // Add current mode
             output.add_mode(OutputMode {
                 width: frame.size.width as u32,
                 height: frame.size.height as u32,
-                refresh: 60000, // Assume 60Hz
+                refresh,
                 current: true,
                 preferred: true,
             });
@@ -136,6 +139,42 @@ pub fn enumerate_outputs() -> Vec<Output> {
     outputs
 }
 
+/// Get the space the menu bar and the Dock take from a screen
+#[cfg(target_os = "macos")]
+pub fn screen_insets(screen: &objc2_app_kit::NSScreen) -> crate::compositor::Insets {
+    let frame = screen.frame();
+    let visible = screen.visibleFrame();
+    crate::compositor::Insets {
+        top: (frame.origin.y + frame.size.height - visible.origin.y - visible.size.height) as i32,
+        right: (frame.origin.x + frame.size.width - visible.origin.x - visible.size.width) as i32,
+        bottom: (visible.origin.y - frame.origin.y) as i32,
+        left: (visible.origin.x - frame.origin.x) as i32,
+    }
+}
+
+/// Get the highest refresh rate of a screen and, if it refreshes
+/// adaptively like ProMotion displays, its lowest (mHz)
+#[cfg(target_os = "macos")]
+pub fn screen_refresh(screen: &objc2_app_kit::NSScreen) -> (u32, Option<u32>) {
+    use objc2::msg_send;
+
+    // SAFETY: plain getters of NSScreen, available since macOS 12
+    let (fps, min_interval, max_interval): (isize, f64, f64) = unsafe {
+        (
+            msg_send![screen, maximumFramesPerSecond],
+            msg_send![screen, minimumRefreshInterval],
+            msg_send![screen, maximumRefreshInterval],
+        )
+    };
+    let refresh = match fps {
+        fps if fps > 0 => fps as u32 * 1000,
+        _ => 60_000,
+    };
+    let min_refresh =
+        (max_interval > min_interval && max_interval > 0.0).then(|| (1000.0 / max_interval) as u32);
+    (refresh, min_refresh)
+}
+
 #[cfg(not(target_os = "macos"))]
 pub fn enumerate_outputs() -> Vec<Output> {
     // Return a dummy output for non-macOS platforms
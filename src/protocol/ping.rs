@@ -0,0 +1,153 @@
+//! Client liveness pings
+//!
+//! Every client with an xdg_wm_base is pinged a few seconds after it last
+//! answered. A client that doesn't answer a ping in time is unresponsive
+//! until its pong arrives, which is how hung clients are told apart from
+//! idle ones.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+
+/// Time between a pong and the next ping
+pub const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time a client has to answer a ping
+pub const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+struct ClientPings {
+    /// Serial and send time of the unanswered ping
+    pending: Option<(u32, Instant)>,
+    /// When the last ping was answered, or the client was added
+    answered: Instant,
+    /// When the pending ping timed out
+    unresponsive_since: Option<Instant>,
+}
+
+/// Ping state of every client
+#[derive(Debug)]
+pub struct PingTracker<K> {
+    clients: HashMap<K, ClientPings>,
+}
+
+impl<K> Default for PingTracker<K> {
+    fn default() -> Self {
+        Self {
+            clients: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + std::fmt::Debug> PingTracker<K> {
+    /// Create a tracker without clients
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start pinging a client
+    pub fn add(&mut self, client: K, now: Instant) {
+        self.clients.entry(client).or_insert(ClientPings {
+            pending: None,
+            answered: now,
+            unresponsive_since: None,
+        });
+    }
+
+    /// Stop pinging a client
+    pub fn remove(&mut self, client: &K) {
+        self.clients.remove(client);
+    }
+
+    /// Get the clients due for a ping, with the serial to send each
+    pub fn due(&mut self, now: Instant, mut next_serial: impl FnMut() -> u32) -> Vec<(K, u32)> {
+        let mut due = Vec::new();
+        for (client, pings) in &mut self.clients {
+            if pings.pending.is_none() && now.duration_since(pings.answered) >= PING_INTERVAL {
+                let serial = next_serial();
+                pings.pending = Some((serial, now));
+                due.push((client.clone(), serial));
+            }
+        }
+        due
+    }
+
+    /// Handle a pong
+    ///
+    /// Returns true if the client was unresponsive until now.
+    pub fn pong(&mut self, client: &K, serial: u32, now: Instant) -> bool {
+        let Some(pings) = self.clients.get_mut(client) else {
+            return false;
+        };
+        if pings.pending.is_none_or(|(pending, _)| pending != serial) {
+            debug!("Ignoring pong {} from {:?}", serial, client);
+            return false;
+        }
+        pings.pending = None;
+        pings.answered = now;
+        let recovered = pings.unresponsive_since.take().is_some();
+        if recovered {
+            info!("Client {:?} is responsive again", client);
+        }
+        recovered
+    }
+
+    /// Mark the clients whose ping timed out as unresponsive
+    ///
+    /// Returns the clients that just became unresponsive.
+    pub fn check(&mut self, now: Instant) -> Vec<K> {
+        let mut timed_out = Vec::new();
+        for (client, pings) in &mut self.clients {
+            let Some((_, sent)) = pings.pending else {
+                continue;
+            };
+            if pings.unresponsive_since.is_none() && now.duration_since(sent) >= PING_TIMEOUT {
+                info!("Client {:?} didn't answer a ping", client);
+                pings.unresponsive_since = Some(now);
+                timed_out.push(client.clone());
+            }
+        }
+        timed_out
+    }
+
+    /// Get when a client became unresponsive, if it is
+    pub fn unresponsive_since(&self, client: &K) -> Option<Instant> {
+        self.clients.get(client)?.unresponsive_since
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_timeout_and_pong() {
+        let start = Instant::now();
+        let mut pings = PingTracker::new();
+        pings.add("client", start);
+        let mut serial = 0;
+        let mut next_serial = || {
+            serial += 1;
+            serial
+        };
+
+        assert!(pings.due(start, &mut next_serial).is_empty());
+        let due = pings.due(start + PING_INTERVAL, &mut next_serial);
+        assert_eq!(due, vec![("client", 1)]);
+        // Only one ping is outstanding at a time
+        assert!(pings
+            .due(start + PING_INTERVAL * 2, &mut next_serial)
+            .is_empty());
+
+        let late = start + PING_INTERVAL + PING_TIMEOUT;
+        assert_eq!(pings.check(late), vec!["client"]);
+        assert!(pings.check(late).is_empty());
+        assert_eq!(pings.unresponsive_since(&"client"), Some(late));
+
+        assert!(!pings.pong(&"client", 7, late));
+        assert!(pings.pong(&"client", 1, late));
+        assert_eq!(pings.unresponsive_since(&"client"), None);
+    }
+}
@@ -180,6 +180,80 @@ pub enum PointerEvent {
     Frame,
 }
 
+/// Touch events to send to clients
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchEvent {
+    /// A touch point started on a surface
+    Down {
+        surface: SurfaceId,
+        id: i32,
+        x: f64,
+        y: f64,
+    },
+    /// A touch point moved
+    Motion { id: i32, x: f64, y: f64 },
+    /// A touch point ended
+    Up { id: i32 },
+    /// Frame delimiter
+    Frame,
+    /// The touch sequence was cancelled
+    Cancel,
+}
+
+/// Relative pointer motion, for zwp_relative_pointer_v1
+///
+/// Unlike motion events, this isn't bounded by the screen edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativeMotion {
+    /// Timestamp in microseconds on the protocol clock
+    pub utime: u64,
+    /// Motion with pointer acceleration applied
+    pub dx: f64,
+    pub dy: f64,
+    /// Motion before pointer acceleration
+    pub dx_unaccel: f64,
+    pub dy_unaccel: f64,
+}
+
+/// Kind of touchpad gesture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureKind {
+    /// Fingers moving together
+    Swipe,
+    /// Fingers moving apart or together, or rotating
+    Pinch,
+    /// Fingers resting on the touchpad
+    Hold,
+}
+
+/// Touchpad gesture event, for zwp_pointer_gestures_v1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GestureEvent {
+    /// A gesture started
+    Begin {
+        kind: GestureKind,
+        time: u32,
+        fingers: u32,
+    },
+    /// Swipe motion since the last event
+    SwipeUpdate { time: u32, dx: f64, dy: f64 },
+    /// Pinch motion, with the scale relative to the start of the gesture
+    /// and the clockwise rotation in degrees since the last event
+    PinchUpdate {
+        time: u32,
+        dx: f64,
+        dy: f64,
+        scale: f64,
+        rotation: f64,
+    },
+    /// A gesture ended, or was cancelled
+    End {
+        kind: GestureKind,
+        time: u32,
+        cancelled: bool,
+    },
+}
+
 /// Button state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ButtonState {
@@ -72,6 +72,10 @@ impl XdgShellHandler {
         parent: SurfaceId,
         positioner: &XdgPositioner,
     ) -> Result<XdgPopup, XdgShellError> {
+        if !positioner.is_complete() {
+            return Err(XdgShellError::InvalidPositioner);
+        }
+
         // Set the surface role to popup
         let surface = state
             .surfaces
@@ -84,7 +88,7 @@ impl XdgShellHandler {
 
         surface.parent = Some(parent);
 
-        let geometry = positioner.calculate_geometry();
+        let geometry = self.place_popup(state, xdg_surface.surface_id, positioner, None)?;
 
         debug!(
             "Created xdg_popup for surface {:?}, parent {:?}",
@@ -98,6 +102,34 @@ impl XdgShellHandler {
         })
     }
 
+    /// Place a popup relative to its parent, as on creation and on
+    /// xdg_popup.reposition
+    ///
+    /// `bounds` is the area the popup may cover, in parent surface
+    /// coordinates; the popup is kept inside it as far as the positioner's constraint adjustment allows.
+    pub fn place_popup(
+        &self,
+        state: &mut CompositorState,
+        surface_id: SurfaceId,
+        positioner: &XdgPositioner,
+        bounds: Option<PopupGeometry>,
+    ) -> Result<PopupGeometry, XdgShellError> {
+        if !positioner.is_complete() {
+            return Err(XdgShellError::InvalidPositioner);
+        }
+        let surface = state
+            .surfaces
+            .get_mut(surface_id)
+            .ok_or(XdgShellError::InvalidSurface)?;
+
+        let geometry = match bounds {
+            Some(bounds) => positioner.calculate_constrained_geometry(bounds),
+            None => positioner.calculate_geometry(),
+        };
+        surface.position = (geometry.x, geometry.y);
+        Ok(geometry)
+    }
+
     /// Handle xdg_wm_base::pong (response to ping)
     pub fn pong(&self, _serial: u32) {
         // Client responded to ping, they're alive
@@ -169,7 +201,7 @@ pub struct PopupGeometry {
 }
 
 /// XDG positioner for popup placement
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct XdgPositioner {
     /// Size of the popup
     pub size: (i32, i32),
@@ -221,6 +253,11 @@ impl XdgPositioner {
         self.offset = (x, y);
     }
 
+    /// Check if the positioner has the non-empty size the protocol requires
+    pub fn is_complete(&self) -> bool {
+        self.size.0 > 0 && self.size.1 > 0
+    }
+
     /// Calculate the popup geometry
     pub fn calculate_geometry(&self) -> PopupGeometry {
         let (ax, ay, aw, ah) = self.anchor_rect;
@@ -263,6 +300,88 @@ impl XdgPositioner {
             height: popup_h,
         }
     }
+
+    /// Calculate the popup geometry, kept inside `bounds`
+    ///
+    /// Per axis, a popup that doesn't fit is first flipped to the other side
+    /// of its anchor, then slid, then resized, as far as the constraint
+    /// adjustment allows. A flip is only kept if the flipped popup fits.
+    pub fn calculate_constrained_geometry(&self, bounds: PopupGeometry) -> PopupGeometry {
+        let adjustment = ConstraintAdjustment::from_bits_truncate(self.constraint_adjustment);
+        let mut geometry = self.calculate_geometry();
+
+        let x_fits =
+            |g: &PopupGeometry| g.x >= bounds.x && g.x + g.width <= bounds.x + bounds.width;
+        let y_fits =
+            |g: &PopupGeometry| g.y >= bounds.y && g.y + g.height <= bounds.y + bounds.height;
+
+        if adjustment.contains(ConstraintAdjustment::FLIP_X) && !x_fits(&geometry) {
+            let flipped = XdgPositioner {
+                anchor: self.anchor.flip_x(),
+                gravity: self.gravity.flip_x(),
+                offset: (-self.offset.0, self.offset.1),
+                ..*self
+            }
+            .calculate_geometry();
+            if x_fits(&flipped) {
+                geometry.x = flipped.x;
+            }
+        }
+        if adjustment.contains(ConstraintAdjustment::FLIP_Y) && !y_fits(&geometry) {
+            let flipped = XdgPositioner {
+                anchor: self.anchor.flip_y(),
+                gravity: self.gravity.flip_y(),
+                offset: (self.offset.0, -self.offset.1),
+                ..*self
+            }
+            .calculate_geometry();
+            if y_fits(&flipped) {
+                geometry.y = flipped.y;
+            }
+        }
+
+        // Slide, keeping the top-left corner visible if the popup is too big
+        if adjustment.contains(ConstraintAdjustment::SLIDE_X) {
+            geometry.x = geometry.x.min(bounds.x + bounds.width - geometry.width);
+            geometry.x = geometry.x.max(bounds.x);
+        }
+        if adjustment.contains(ConstraintAdjustment::SLIDE_Y) {
+            geometry.y = geometry.y.min(bounds.y + bounds.height - geometry.height);
+            geometry.y = geometry.y.max(bounds.y);
+        }
+
+        if adjustment.contains(ConstraintAdjustment::RESIZE_X) {
+            let left = geometry.x.max(bounds.x);
+            let right = (geometry.x + geometry.width).min(bounds.x + bounds.width);
+            if right > left {
+                geometry.x = left;
+                geometry.width = right - left;
+            }
+        }
+        if adjustment.contains(ConstraintAdjustment::RESIZE_Y) {
+            let top = geometry.y.max(bounds.y);
+            let bottom = (geometry.y + geometry.height).min(bounds.y + bounds.height);
+            if bottom > top {
+                geometry.y = top;
+                geometry.height = bottom - top;
+            }
+        }
+
+        geometry
+    }
+}
+
+bitflags::bitflags! {
+    /// How a popup may be adjusted to stay on screen
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct ConstraintAdjustment: u32 {
+        const SLIDE_X = 1;
+        const SLIDE_Y = 2;
+        const FLIP_X = 4;
+        const FLIP_Y = 8;
+        const RESIZE_X = 16;
+        const RESIZE_Y = 32;
+    }
 }
 
 /// Anchor edge for popup positioning
@@ -280,6 +399,50 @@ pub enum Anchor {
     BottomRight,
 }
 
+impl Anchor {
+    /// Convert from the xdg_positioner.anchor value
+    pub fn from_wayland(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Anchor::None),
+            1 => Some(Anchor::Top),
+            2 => Some(Anchor::Bottom),
+            3 => Some(Anchor::Left),
+            4 => Some(Anchor::Right),
+            5 => Some(Anchor::TopLeft),
+            6 => Some(Anchor::BottomLeft),
+            7 => Some(Anchor::TopRight),
+            8 => Some(Anchor::BottomRight),
+            _ => None,
+        }
+    }
+
+    /// Mirror horizontally
+    pub fn flip_x(self) -> Self {
+        match self {
+            Anchor::Left => Anchor::Right,
+            Anchor::Right => Anchor::Left,
+            Anchor::TopLeft => Anchor::TopRight,
+            Anchor::TopRight => Anchor::TopLeft,
+            Anchor::BottomLeft => Anchor::BottomRight,
+            Anchor::BottomRight => Anchor::BottomLeft,
+            other => other,
+        }
+    }
+
+    /// Mirror vertically
+    pub fn flip_y(self) -> Self {
+        match self {
+            Anchor::Top => Anchor::Bottom,
+            Anchor::Bottom => Anchor::Top,
+            Anchor::TopLeft => Anchor::BottomLeft,
+            Anchor::BottomLeft => Anchor::TopLeft,
+            Anchor::TopRight => Anchor::BottomRight,
+            Anchor::BottomRight => Anchor::TopRight,
+            other => other,
+        }
+    }
+}
+
 /// Gravity for popup positioning
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Gravity {
@@ -295,6 +458,50 @@ pub enum Gravity {
     BottomRight,
 }
 
+impl Gravity {
+    /// Convert from the xdg_positioner.gravity value
+    pub fn from_wayland(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Gravity::None),
+            1 => Some(Gravity::Top),
+            2 => Some(Gravity::Bottom),
+            3 => Some(Gravity::Left),
+            4 => Some(Gravity::Right),
+            5 => Some(Gravity::TopLeft),
+            6 => Some(Gravity::BottomLeft),
+            7 => Some(Gravity::TopRight),
+            8 => Some(Gravity::BottomRight),
+            _ => None,
+        }
+    }
+
+    /// Mirror horizontally
+    pub fn flip_x(self) -> Self {
+        match self {
+            Gravity::Left => Gravity::Right,
+            Gravity::Right => Gravity::Left,
+            Gravity::TopLeft => Gravity::TopRight,
+            Gravity::TopRight => Gravity::TopLeft,
+            Gravity::BottomLeft => Gravity::BottomRight,
+            Gravity::BottomRight => Gravity::BottomLeft,
+            other => other,
+        }
+    }
+
+    /// Mirror vertically
+    pub fn flip_y(self) -> Self {
+        match self {
+            Gravity::Top => Gravity::Bottom,
+            Gravity::Bottom => Gravity::Top,
+            Gravity::TopLeft => Gravity::BottomLeft,
+            Gravity::BottomLeft => Gravity::TopLeft,
+            Gravity::TopRight => Gravity::BottomRight,
+            Gravity::BottomRight => Gravity::TopRight,
+            other => other,
+        }
+    }
+}
+
 /// XDG shell errors
 #[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
 pub enum XdgShellError {
@@ -338,4 +545,77 @@ mod tests {
         assert_eq!(geometry.width, 200);
         assert_eq!(geometry.height, 100);
     }
+
+    #[test]
+    fn test_reposition_popup() {
+        let handler = XdgShellHandler::new();
+        let mut state = CompositorState::new();
+        let parent = state.surfaces.create_surface();
+        let surface_id = state.surfaces.create_surface();
+        let mut xdg_surface = handler.get_xdg_surface(&mut state, surface_id).unwrap();
+
+        let mut positioner = XdgPositioner::new();
+        assert_eq!(
+            handler
+                .get_popup(&mut state, &mut xdg_surface, parent, &positioner)
+                .unwrap_err(),
+            XdgShellError::InvalidPositioner
+        );
+
+        positioner.set_size(50, 20);
+        positioner.set_anchor_rect(10, 10, 30, 10);
+        positioner.set_anchor(Anchor::from_wayland(6).unwrap());
+        positioner.set_gravity(Gravity::from_wayland(8).unwrap());
+        let popup = handler
+            .get_popup(&mut state, &mut xdg_surface, parent, &positioner)
+            .unwrap();
+        assert_eq!((popup.geometry.x, popup.geometry.y), (10, 20));
+
+        // Menus scrolled by the client move with a new positioner
+        positioner.set_offset(0, -15);
+        let geometry = handler
+            .place_popup(&mut state, surface_id, &positioner, None)
+            .unwrap();
+        assert_eq!((geometry.x, geometry.y), (10, 5));
+        assert_eq!(state.surfaces.get(surface_id).unwrap().position, (10, 5));
+    }
+
+    #[test]
+    fn test_constrained_geometry() {
+        // A menu opening below a button near the bottom right of the work area
+        let mut positioner = XdgPositioner::new();
+        positioner.set_size(100, 200);
+        positioner.set_anchor_rect(850, 400, 50, 20);
+        positioner.set_anchor(Anchor::BottomLeft);
+        positioner.set_gravity(Gravity::BottomRight);
+        let bounds = PopupGeometry {
+            x: 0,
+            y: 25,
+            width: 900,
+            height: 500,
+        };
+
+        // Without adjustments the popup is left where it is
+        let geometry = positioner.calculate_constrained_geometry(bounds);
+        assert_eq!((geometry.x, geometry.y), (850, 420));
+
+        // Flips above the button, slides left
+        positioner.set_constraint_adjustment(
+            (ConstraintAdjustment::FLIP_Y | ConstraintAdjustment::SLIDE_X).bits(),
+        );
+        let geometry = positioner.calculate_constrained_geometry(bounds);
+        assert_eq!((geometry.x, geometry.y), (800, 200));
+
+        // Too tall to flip, so it slides up and then gets cut to the area,
+        // never going under the menu bar
+        positioner.set_size(100, 600);
+        positioner.set_constraint_adjustment(
+            (ConstraintAdjustment::FLIP_Y
+                | ConstraintAdjustment::SLIDE_Y
+                | ConstraintAdjustment::RESIZE_Y)
+                .bits(),
+        );
+        let geometry = positioner.calculate_constrained_geometry(bounds);
+        assert_eq!((geometry.y, geometry.height), (25, 500));
+    }
 }
@@ -37,6 +37,30 @@ pub enum ShmFormat {
     Argb8888,
     /// 32-bit XRGB (X in high byte, alpha ignored)
     Xrgb8888,
+    /// 16-bit RGB (5-6-5, R in high bits)
+    Rgb565,
+    /// 24-bit packed RGB, stored R, G, B in memory
+    Bgr888,
+    /// 30-bit XRGB (2-10-10-10, padding in the top bits)
+    Xrgb2101010,
+    /// 30-bit ARGB (2-10-10-10, A in the top bits)
+    Argb2101010,
+    /// 30-bit XBGR (2-10-10-10, padding in the top bits)
+    Xbgr2101010,
+    /// 30-bit ABGR (2-10-10-10, A in the top bits)
+    Abgr2101010,
+    /// 32-bit ABGR (A in high byte), stored R, G, B, A in memory
+    Abgr8888,
+    /// 32-bit XBGR (X in high byte, alpha ignored)
+    Xbgr8888,
+    /// 64-bit half-float ABGR, stored R, G, B, A in memory
+    Abgr16161616f,
+    /// 64-bit half-float XBGR (alpha ignored)
+    Xbgr16161616f,
+    /// 64-bit half-float ARGB, stored B, G, R, A in memory
+    Argb16161616f,
+    /// 64-bit half-float XRGB (alpha ignored)
+    Xrgb16161616f,
     /// Other format with raw value
     Other(u32),
 }
@@ -47,6 +71,18 @@ impl ShmFormat {
         match format {
             0 => ShmFormat::Argb8888,
             1 => ShmFormat::Xrgb8888,
+            0x3631_4752 => ShmFormat::Rgb565,
+            0x3432_4742 => ShmFormat::Bgr888,
+            0x3033_5258 => ShmFormat::Xrgb2101010,
+            0x3033_5241 => ShmFormat::Argb2101010,
+            0x3033_4258 => ShmFormat::Xbgr2101010,
+            0x3033_4241 => ShmFormat::Abgr2101010,
+            0x3432_4241 => ShmFormat::Abgr8888,
+            0x3432_4258 => ShmFormat::Xbgr8888,
+            0x4834_4241 => ShmFormat::Abgr16161616f,
+            0x4834_4258 => ShmFormat::Xbgr16161616f,
+            0x4834_5241 => ShmFormat::Argb16161616f,
+            0x4834_5258 => ShmFormat::Xrgb16161616f,
             other => ShmFormat::Other(other),
         }
     }
@@ -56,6 +92,18 @@ impl ShmFormat {
         match self {
             ShmFormat::Argb8888 => 0,
             ShmFormat::Xrgb8888 => 1,
+            ShmFormat::Rgb565 => 0x3631_4752,
+            ShmFormat::Bgr888 => 0x3432_4742,
+            ShmFormat::Xrgb2101010 => 0x3033_5258,
+            ShmFormat::Argb2101010 => 0x3033_5241,
+            ShmFormat::Xbgr2101010 => 0x3033_4258,
+            ShmFormat::Abgr2101010 => 0x3033_4241,
+            ShmFormat::Abgr8888 => 0x3432_4241,
+            ShmFormat::Xbgr8888 => 0x3432_4258,
+            ShmFormat::Abgr16161616f => 0x4834_4241,
+            ShmFormat::Xbgr16161616f => 0x4834_4258,
+            ShmFormat::Argb16161616f => 0x4834_5241,
+            ShmFormat::Xrgb16161616f => 0x4834_5258,
             ShmFormat::Other(v) => *v,
         }
     }
@@ -63,10 +111,36 @@ impl ShmFormat {
     /// Get bytes per pixel
     pub fn bytes_per_pixel(&self) -> u32 {
         match self {
-            ShmFormat::Argb8888 | ShmFormat::Xrgb8888 => 4,
+            ShmFormat::Rgb565 => 2,
+            ShmFormat::Bgr888 => 3,
+            ShmFormat::Argb8888
+            | ShmFormat::Xrgb8888
+            | ShmFormat::Xrgb2101010
+            | ShmFormat::Argb2101010
+            | ShmFormat::Xbgr2101010
+            | ShmFormat::Abgr2101010
+            | ShmFormat::Abgr8888
+            | ShmFormat::Xbgr8888 => 4,
+            ShmFormat::Abgr16161616f
+            | ShmFormat::Xbgr16161616f
+            | ShmFormat::Argb16161616f
+            | ShmFormat::Xrgb16161616f => 8,
             ShmFormat::Other(_) => 4, // Assume 4 for unknown formats
         }
     }
+
+    /// Check if the alpha channel should be ignored
+    pub fn is_opaque(&self) -> bool {
+        !matches!(
+            self,
+            ShmFormat::Argb8888
+                | ShmFormat::Argb2101010
+                | ShmFormat::Abgr2101010
+                | ShmFormat::Abgr8888
+                | ShmFormat::Abgr16161616f
+                | ShmFormat::Argb16161616f
+        )
+    }
 }
 
 /// A shared memory pool
@@ -75,23 +149,15 @@ pub struct ShmPool {
     /// Unique identifier
     pub id: ShmPoolId,
     /// File for the shared memory (keeps fd alive)
-    #[cfg(target_os = "macos")]
     pub file: Option<std::fs::File>,
-    /// Raw fd for non-macos
-    #[cfg(not(target_os = "macos"))]
-    pub fd: RawFd,
     /// Size of the pool in bytes
     pub size: usize,
-    /// Memory-mapped data (when mapped)
-    #[cfg(target_os = "macos")]
-    pub data: Option<memmap2::Mmap>,
-    #[cfg(not(target_os = "macos"))]
-    pub data: Option<()>,
+    /// Memory-mapped data (when mapped), shared with uploads in flight
+    pub data: Option<std::sync::Arc<memmap2::Mmap>>,
 }
 
 impl ShmPool {
     /// Create a new shm pool
-    #[cfg(target_os = "macos")]
     pub fn new(fd: RawFd, size: usize) -> Self {
         use std::os::unix::io::FromRawFd;
         // Duplicate the fd by creating a File and using try_clone
@@ -107,16 +173,6 @@ impl ShmPool {
         }
     }
 
-    #[cfg(not(target_os = "macos"))]
-    pub fn new(fd: RawFd, size: usize) -> Self {
-        Self {
-            id: ShmPoolId::new(),
-            fd,
-            size,
-            data: None,
-        }
-    }
-
     /// Resize the pool
     pub fn resize(&mut self, new_size: usize) {
         if new_size > self.size {
@@ -190,7 +246,22 @@ impl WlShmHandler {
 
     /// Get supported formats
     pub fn formats(&self) -> Vec<ShmFormat> {
-        vec![ShmFormat::Argb8888, ShmFormat::Xrgb8888]
+        vec![
+            ShmFormat::Argb8888,
+            ShmFormat::Xrgb8888,
+            ShmFormat::Rgb565,
+            ShmFormat::Bgr888,
+            ShmFormat::Xrgb2101010,
+            ShmFormat::Argb2101010,
+            ShmFormat::Xbgr2101010,
+            ShmFormat::Abgr2101010,
+            ShmFormat::Abgr8888,
+            ShmFormat::Xbgr8888,
+            ShmFormat::Abgr16161616f,
+            ShmFormat::Xbgr16161616f,
+            ShmFormat::Argb16161616f,
+            ShmFormat::Xrgb16161616f,
+        ]
     }
 
     /// Create a new shm pool
@@ -278,8 +349,25 @@ impl WlShmHandler {
 
     /// Read buffer pixel data
     /// Returns a copy of the buffer contents
-    #[cfg(target_os = "macos")]
     pub fn read_buffer_data(&mut self, buffer_id: ShmBufferId) -> Result<Vec<u8>, ShmError> {
+        let (memory, range) = self.buffer_memory(buffer_id)?;
+        Ok((*memory).as_ref()[range].to_vec())
+    }
+
+    /// Get the mapped pool memory of a buffer and the buffer's range in it
+    ///
+    /// The mapping stays valid after the pool is resized or destroyed, so
+    /// it can be read on another thread.
+    pub fn buffer_memory(
+        &mut self,
+        buffer_id: ShmBufferId,
+    ) -> Result<
+        (
+            crate::renderer::upload::SharedMemory,
+            std::ops::Range<usize>,
+        ),
+        ShmError,
+    > {
         let buffer = self.buffers.get(&buffer_id).ok_or(ShmError::InvalidPool)?;
         let pool_id = buffer.pool_id;
         let offset = buffer.offset as usize;
@@ -292,7 +380,7 @@ impl WlShmHandler {
             let file = pool.file.as_ref().ok_or(ShmError::InvalidPool)?;
             match unsafe { memmap2::Mmap::map(file) } {
                 Ok(mmap) => {
-                    pool.data = Some(mmap);
+                    pool.data = Some(std::sync::Arc::new(mmap));
                 }
                 Err(e) => {
                     debug!("Failed to mmap pool: {}", e);
@@ -301,16 +389,32 @@ impl WlShmHandler {
             }
         }
 
-        // Read the data
-        if let Some(ref mmap) = pool.data {
-            if offset + size <= mmap.len() {
-                Ok(mmap[offset..offset + size].to_vec())
-            } else {
-                Err(ShmError::BufferTooLarge)
-            }
-        } else {
-            Err(ShmError::InvalidPool)
+        let mmap = pool.data.clone().ok_or(ShmError::InvalidPool)?;
+        if offset + size > mmap.len() {
+            return Err(ShmError::BufferTooLarge);
         }
+        let memory: crate::renderer::upload::SharedMemory = mmap;
+        Ok((memory, offset..offset + size))
+    }
+
+    /// Write pixel data into a buffer, such as a captured frame
+    ///
+    /// The data is written through the pool's file, so the pool doesn't
+    /// need a writable mapping.
+    pub fn write_buffer_data(&self, buffer_id: ShmBufferId, data: &[u8]) -> Result<(), ShmError> {
+        use std::os::unix::fs::FileExt;
+
+        let buffer = self.buffers.get(&buffer_id).ok_or(ShmError::InvalidPool)?;
+        if data.len() > buffer.data_size() {
+            return Err(ShmError::BufferTooLarge);
+        }
+        let pool = self
+            .pools
+            .get(&buffer.pool_id)
+            .ok_or(ShmError::InvalidPool)?;
+        let file = pool.file.as_ref().ok_or(ShmError::InvalidPool)?;
+        file.write_all_at(data, buffer.offset as u64)
+            .map_err(|_| ShmError::InvalidPool)
     }
 }
 
@@ -342,6 +446,19 @@ mod tests {
         assert_eq!(ShmFormat::from_wayland(0), ShmFormat::Argb8888);
         assert_eq!(ShmFormat::Argb8888.to_wayland(), 0);
         assert_eq!(ShmFormat::Argb8888.bytes_per_pixel(), 4);
+        assert_eq!(ShmFormat::from_wayland(0x3631_4752), ShmFormat::Rgb565);
+        assert_eq!(ShmFormat::Rgb565.bytes_per_pixel(), 2);
+        assert_eq!(ShmFormat::Bgr888.bytes_per_pixel(), 3);
+        assert_eq!(ShmFormat::Xrgb2101010.to_wayland(), 0x3033_5258);
+        assert_eq!(ShmFormat::from_wayland(0x3432_4241), ShmFormat::Abgr8888);
+        assert_eq!(ShmFormat::Xbgr8888.bytes_per_pixel(), 4);
+        assert_eq!(
+            ShmFormat::from_wayland(0x4834_4241),
+            ShmFormat::Abgr16161616f
+        );
+        assert_eq!(ShmFormat::Xrgb16161616f.bytes_per_pixel(), 8);
+        assert!(ShmFormat::Xbgr16161616f.is_opaque());
+        assert!(!ShmFormat::Argb16161616f.is_opaque());
     }
 
     #[test]
@@ -369,5 +486,10 @@ mod tests {
         // Create buffer
         let buffer_id = handler.create_buffer(pool_id, 0, 100, 100, 400, 0).unwrap();
         assert!(handler.get_buffer(buffer_id).is_some());
+
+        // Written pixels are read back through the pool's mapping
+        let pixels = vec![0x7f; 40000];
+        handler.write_buffer_data(buffer_id, &pixels).unwrap();
+        assert_eq!(handler.read_buffer_data(buffer_id).unwrap(), pixels);
     }
 }
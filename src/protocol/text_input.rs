@@ -0,0 +1,375 @@
+//! zwp_text_input_v3 state tracking
+//!
+//! A text input tells the compositor about the text field a client is
+//! editing: whether one is focused, the text around the cursor, what kind
+//! of content it takes and where the cursor is on screen. An input method
+//! uses that to compose text, which comes back as preedit and commit
+//! strings.
+//!
+//! Client state is double-buffered and applied on commit. Enabling a text
+//! input resets its state. Text inputs of the client with keyboard focus
+//! are entered on the focused surface; at most one of them is expected to
+//! be enabled at a time, and it receives the input method's text.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::debug;
+
+use crate::compositor::SurfaceId;
+
+/// Unique identifier for text inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextInputId(pub u64);
+
+impl TextInputId {
+    fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(1);
+        TextInputId(COUNTER.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Text around the cursor, with byte offsets into it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SurroundingText {
+    /// The text, usually a paragraph around the cursor
+    pub text: String,
+    /// Byte offset of the cursor
+    pub cursor: i32,
+    /// Byte offset of the other end of the selection, the cursor if none
+    pub anchor: i32,
+}
+
+/// What caused a change of the surrounding text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChangeCause {
+    /// The input method's own text
+    #[default]
+    InputMethod,
+    /// Something else, such as the user moving the cursor
+    Other,
+}
+
+bitflags::bitflags! {
+    /// Hints on how text should be entered
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct ContentHint: u32 {
+        const COMPLETION = 0x1;
+        const SPELLCHECK = 0x2;
+        const AUTO_CAPITALIZATION = 0x4;
+        const LOWERCASE = 0x8;
+        const UPPERCASE = 0x10;
+        const TITLECASE = 0x20;
+        const HIDDEN_TEXT = 0x40;
+        const SENSITIVE_DATA = 0x80;
+        const LATIN = 0x100;
+        const MULTILINE = 0x200;
+    }
+}
+
+/// What a text field is for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentPurpose {
+    #[default]
+    Normal,
+    Alpha,
+    Digits,
+    Number,
+    Phone,
+    Url,
+    Email,
+    Name,
+    Password,
+    Pin,
+    Date,
+    Time,
+    Datetime,
+    Terminal,
+}
+
+impl ContentPurpose {
+    /// Convert the protocol value, None if it is unknown
+    pub fn from_u32(value: u32) -> Option<Self> {
+        Some(match value {
+            0 => ContentPurpose::Normal,
+            1 => ContentPurpose::Alpha,
+            2 => ContentPurpose::Digits,
+            3 => ContentPurpose::Number,
+            4 => ContentPurpose::Phone,
+            5 => ContentPurpose::Url,
+            6 => ContentPurpose::Email,
+            7 => ContentPurpose::Name,
+            8 => ContentPurpose::Password,
+            9 => ContentPurpose::Pin,
+            10 => ContentPurpose::Date,
+            11 => ContentPurpose::Time,
+            12 => ContentPurpose::Datetime,
+            13 => ContentPurpose::Terminal,
+            _ => return None,
+        })
+    }
+}
+
+/// Cursor position in surface-local coordinates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CursorRectangle {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// State of a text input, as set by its client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextInputState {
+    /// Whether the client wants text input
+    pub enabled: bool,
+    /// Text around the cursor, if the client reported it
+    pub surrounding: Option<SurroundingText>,
+    /// What caused the last change of the surrounding text
+    pub change_cause: ChangeCause,
+    /// Hints on how text should be entered
+    pub hint: ContentHint,
+    /// What the text field is for
+    pub purpose: ContentPurpose,
+    /// Where the cursor is, if the client reported it
+    pub cursor_rectangle: Option<CursorRectangle>,
+}
+
+/// Text being composed by the input method
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preedit {
+    /// The text, shown in place of nothing at the cursor
+    pub text: String,
+    /// Byte offsets of the cursor in the text, or -1 to hide it
+    pub cursor_begin: i32,
+    pub cursor_end: i32,
+}
+
+/// Event to send to a text input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextInputEvent {
+    /// The text input's client got keyboard focus on a surface
+    Enter(SurfaceId),
+    /// The surface lost keyboard focus
+    Leave(SurfaceId),
+    /// Text being composed, replacing any earlier preedit
+    Preedit(Option<Preedit>),
+    /// Text to insert at the cursor
+    CommitString(String),
+    /// Bytes to delete before and after the cursor
+    DeleteSurroundingText { before: u32, after: u32 },
+    /// Apply the events sent since the last done, with the number of
+    /// commits the client made
+    Done(u32),
+}
+
+/// A text input object
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+    /// Surface it was entered on
+    pub focus: Option<SurfaceId>,
+    /// State of the last commit
+    pub current: TextInputState,
+    /// State set since the last commit
+    pub pending: TextInputState,
+    /// Commits made, sent back with done
+    pub commits: u32,
+}
+
+impl TextInput {
+    /// Enable text input, resetting the pending state
+    pub fn enable(&mut self) {
+        self.pending = TextInputState {
+            enabled: true,
+            ..Default::default()
+        };
+    }
+
+    /// Disable text input
+    pub fn disable(&mut self) {
+        self.pending.enabled = false;
+    }
+
+    /// Apply the pending state
+    pub fn commit(&mut self) {
+        self.current = self.pending.clone();
+        self.commits = self.commits.wrapping_add(1);
+    }
+
+    /// Check if the text input is focused and enabled
+    pub fn is_active(&self) -> bool {
+        self.focus.is_some() && self.current.enabled
+    }
+}
+
+/// Text input registry
+#[derive(Debug, Default)]
+pub struct TextInputHandler {
+    /// Text inputs by ID
+    inputs: HashMap<TextInputId, TextInput>,
+}
+
+impl TextInputHandler {
+    /// Create a new text input handler
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a text input
+    pub fn create(&mut self) -> TextInputId {
+        let id = TextInputId::new();
+        self.inputs.insert(id, TextInput::default());
+        debug!("Created text input {:?}", id);
+        id
+    }
+
+    /// Destroy a text input
+    pub fn destroy(&mut self, id: TextInputId) {
+        self.inputs.remove(&id);
+    }
+
+    /// Get a text input
+    pub fn get(&self, id: TextInputId) -> Option<&TextInput> {
+        self.inputs.get(&id)
+    }
+
+    /// Get a mutable text input
+    pub fn get_mut(&mut self, id: TextInputId) -> Option<&mut TextInput> {
+        self.inputs.get_mut(&id)
+    }
+
+    /// Number of text inputs
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Check if there are no text inputs
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Move keyboard focus to a surface
+    ///
+    /// `focused` are the text inputs of the client owning the surface. Text
+    /// inputs entered on another surface leave it and have their state
+    /// reset, and those of the focused client enter the surface.
+    pub fn set_focus(
+        &mut self,
+        surface: Option<SurfaceId>,
+        focused: &[TextInputId],
+    ) -> Vec<(TextInputId, TextInputEvent)> {
+        let mut events = Vec::new();
+        for (id, input) in &mut self.inputs {
+            let entered = surface.filter(|_| focused.contains(id));
+            if input.focus == entered {
+                continue;
+            }
+            if let Some(old) = input.focus.take() {
+                events.push((*id, TextInputEvent::Leave(old)));
+                input.current = TextInputState::default();
+                input.pending = TextInputState::default();
+            }
+            if let Some(new) = entered {
+                input.focus = Some(new);
+                events.push((*id, TextInputEvent::Enter(new)));
+            }
+        }
+        events
+    }
+
+    /// Get the focused and enabled text input
+    pub fn active(&self) -> Option<(TextInputId, &TextInput)> {
+        self.inputs
+            .iter()
+            .find(|(_, input)| input.is_active())
+            .map(|(id, input)| (*id, input))
+    }
+
+    /// Compose the events delivering input method text to the active text
+    /// input
+    ///
+    /// Surrounding text is deleted first, then the commit string inserted
+    /// and the preedit replaced, as the client applies them on done.
+    pub fn input_method_text(
+        &self,
+        preedit: Option<Preedit>,
+        commit: Option<String>,
+        delete: Option<(u32, u32)>,
+    ) -> Vec<(TextInputId, TextInputEvent)> {
+        let Some((id, input)) = self.active() else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        if let Some((before, after)) = delete {
+            events.push((id, TextInputEvent::DeleteSurroundingText { before, after }));
+        }
+        if let Some(text) = commit {
+            events.push((id, TextInputEvent::CommitString(text)));
+        }
+        events.push((id, TextInputEvent::Preedit(preedit)));
+        events.push((id, TextInputEvent::Done(input.commits)));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_double_buffered_state() {
+        let mut input = TextInput::default();
+        input.enable();
+        input.pending.purpose = ContentPurpose::Email;
+        assert!(!input.current.enabled);
+
+        input.commit();
+        assert!(input.current.enabled);
+        assert_eq!(input.current.purpose, ContentPurpose::Email);
+        assert_eq!(input.commits, 1);
+
+        // Enabling again resets the state
+        input.enable();
+        input.commit();
+        assert_eq!(input.current.purpose, ContentPurpose::Normal);
+        assert_eq!(ContentPurpose::from_u32(13), Some(ContentPurpose::Terminal));
+        assert_eq!(ContentPurpose::from_u32(14), None);
+    }
+
+    #[test]
+    fn test_focus_and_input_method_text() {
+        let mut handler = TextInputHandler::new();
+        let a = handler.create();
+        let b = handler.create();
+        let surface = SurfaceId(1);
+
+        let events = handler.set_focus(Some(surface), &[a]);
+        assert_eq!(events, vec![(a, TextInputEvent::Enter(surface))]);
+        assert!(handler.set_focus(Some(surface), &[a]).is_empty());
+        assert!(handler.input_method_text(None, None, None).is_empty());
+
+        let input = handler.get_mut(a).unwrap();
+        input.enable();
+        input.commit();
+        let events = handler.input_method_text(None, Some("é".to_string()), Some((1, 0)));
+        assert_eq!(
+            events.iter().map(|(_, e)| e.clone()).collect::<Vec<_>>(),
+            vec![
+                TextInputEvent::DeleteSurroundingText {
+                    before: 1,
+                    after: 0
+                },
+                TextInputEvent::CommitString("é".to_string()),
+                TextInputEvent::Preedit(None),
+                TextInputEvent::Done(1),
+            ]
+        );
+
+        // Leaving resets the state
+        let events = handler.set_focus(Some(SurfaceId(2)), &[b]);
+        assert_eq!(events.len(), 2);
+        assert!(!handler.get(a).unwrap().current.enabled);
+        assert!(handler.active().is_none());
+    }
+}
@@ -0,0 +1,316 @@
+//! Client socket forwarding
+//!
+//! `wayoa proxy` lets clients in containers and VMs reach the compositor.
+//! It listens on a TCP address or vsock port and relays every connection
+//! to a local Unix socket, by default the compositor's Wayland socket,
+//! logging each connection with the bytes relayed both ways.
+//!
+//! With `--tag`, the proxy asks the running compositor over IPC for a
+//! socket of its own. Clients connecting through it are tagged with that
+//! name in the journal and don't see privileged globals, like clients of a
+//! security context.
+//!
+//! Only bytes are relayed: file descriptors can't cross TCP or vsock, so
+//! clients that share buffers need waypipe on both ends, with `--socket`
+//! pointing at the socket of `waypipe client`.
+
+mod vsock;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use log::{info, warn};
+
+use crate::ipc;
+
+pub use vsock::VsockListener;
+
+/// Usage of the proxy subcommand
+pub const USAGE: &str =
+    "proxy --listen <tcp:HOST:PORT|vsock:PORT> [--socket <path> | --tag <name>]";
+
+/// Where the proxy accepts connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// A TCP address, e.g. `127.0.0.1:6000`
+    Tcp(String),
+    /// A vsock port, on any context ID
+    Vsock(u32),
+}
+
+impl Endpoint {
+    /// Parse `tcp:HOST:PORT` or `vsock:PORT`
+    pub fn parse(endpoint: &str) -> Result<Self, ProxyError> {
+        match endpoint.split_once(':') {
+            Some(("tcp", address)) if address.contains(':') => {
+                Ok(Endpoint::Tcp(address.to_string()))
+            }
+            Some(("vsock", port)) => port
+                .parse()
+                .map(Endpoint::Vsock)
+                .map_err(|_| ProxyError::InvalidEndpoint(endpoint.to_string())),
+            _ => Err(ProxyError::InvalidEndpoint(endpoint.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "tcp:{}", address),
+            Endpoint::Vsock(port) => write!(f, "vsock:{}", port),
+        }
+    }
+}
+
+/// Options of the proxy subcommand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyOptions {
+    /// Endpoint to listen on
+    pub listen: Endpoint,
+    /// Unix socket to relay to, the Wayland socket if unset
+    pub socket: Option<PathBuf>,
+    /// Ask the compositor for a socket tagged with this name
+    pub tag: Option<String>,
+}
+
+impl ProxyOptions {
+    /// Parse the arguments following `proxy`
+    pub fn parse(args: &[String]) -> Result<Self, ProxyError> {
+        let mut listen = None;
+        let mut socket = None;
+        let mut tag = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or(ProxyError::Usage);
+            match arg.as_str() {
+                "--listen" => listen = Some(Endpoint::parse(value?)?),
+                "--socket" => socket = Some(PathBuf::from(value?)),
+                "--tag" => tag = Some(value?.clone()),
+                _ => return Err(ProxyError::Usage),
+            }
+        }
+        match (listen, &socket, &tag) {
+            (Some(listen), None, _) | (Some(listen), _, None) => Ok(Self {
+                listen,
+                socket,
+                tag,
+            }),
+            _ => Err(ProxyError::Usage),
+        }
+    }
+
+    /// Find the Unix socket to relay to
+    ///
+    /// A tagged socket is created by the compositor on request.
+    fn target(&self) -> Result<PathBuf, ProxyError> {
+        if let Some(socket) = &self.socket {
+            return Ok(socket.clone());
+        }
+        if let Some(tag) = &self.tag {
+            let command = format!("proxy-socket {}", tag);
+            let response = ipc::request(&ipc::socket_from_env(), &command)?;
+            let path = response
+                .data
+                .as_ref()
+                .and_then(|data| data["path"].as_str())
+                .filter(|_| response.ok);
+            return match path {
+                Some(path) => Ok(PathBuf::from(path)),
+                None => Err(ProxyError::Refused(response.error.unwrap_or_default())),
+            };
+        }
+        let dir = std::env::var_os("XDG_RUNTIME_DIR").ok_or(ProxyError::NoRuntimeDir)?;
+        let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+        Ok(PathBuf::from(dir).join(display))
+    }
+}
+
+/// A stream that can be split into a reading and a writing half
+pub trait Duplex: Read + Write + Send + Sized + 'static {
+    /// Get another handle to the stream
+    fn try_clone(&self) -> std::io::Result<Self>;
+    /// Tell the peer nothing more will be written
+    fn shutdown_write(&self);
+}
+
+impl Duplex for TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn shutdown_write(&self) {
+        let _ = self.shutdown(Shutdown::Write);
+    }
+}
+
+impl Duplex for UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn shutdown_write(&self) {
+        let _ = self.shutdown(Shutdown::Write);
+    }
+}
+
+/// Bytes relayed over a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Relayed {
+    /// From the client to the compositor
+    pub up: u64,
+    /// From the compositor to the client
+    pub down: u64,
+}
+
+/// Relay bytes both ways until both sides have closed
+pub fn relay<A: Duplex, B: Duplex>(client: A, server: B) -> std::io::Result<Relayed> {
+    let (mut client_reader, mut server_writer) = (client.try_clone()?, server.try_clone()?);
+    let upstream = std::thread::spawn(move || {
+        let copied = std::io::copy(&mut client_reader, &mut server_writer).unwrap_or(0);
+        server_writer.shutdown_write();
+        copied
+    });
+    let (mut server_reader, mut client_writer) = (server, client);
+    let down = std::io::copy(&mut server_reader, &mut client_writer).unwrap_or(0);
+    client_writer.shutdown_write();
+    let up = upstream.join().unwrap_or(0);
+    Ok(Relayed { up, down })
+}
+
+/// Listening socket of the proxy
+enum Listener {
+    Tcp(TcpListener),
+    Vsock(VsockListener),
+}
+
+/// Number of the next connection, for the log
+static CONNECTIONS: AtomicU64 = AtomicU64::new(1);
+
+/// Run the proxy until it fails to accept connections
+pub fn run(options: &ProxyOptions) -> anyhow::Result<()> {
+    let target = options.target()?;
+    let listener = match &options.listen {
+        Endpoint::Tcp(address) => Listener::Tcp(TcpListener::bind(address)?),
+        Endpoint::Vsock(port) => Listener::Vsock(VsockListener::bind(*port)?),
+    };
+    info!("Relaying {} to {}", options.listen, target.display());
+
+    loop {
+        match &listener {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept()?;
+                spawn_connection(stream, peer.to_string(), target.clone());
+            }
+            Listener::Vsock(listener) => {
+                let (stream, cid) = listener.accept()?;
+                spawn_connection(stream, format!("vsock cid {}", cid), target.clone());
+            }
+        }
+    }
+}
+
+/// Relay a connection on its own thread, logging when it opens and closes
+fn spawn_connection<S: Duplex>(stream: S, peer: String, target: PathBuf) {
+    let number = CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    std::thread::spawn(move || {
+        let server = match UnixStream::connect(&target) {
+            Ok(server) => server,
+            Err(e) => {
+                warn!(
+                    "Connection {} from {}: {}: {}",
+                    number,
+                    peer,
+                    target.display(),
+                    e
+                );
+                return;
+            }
+        };
+        info!("Connection {} from {}", number, peer);
+        let start = Instant::now();
+        match relay(stream, server) {
+            Ok(relayed) => info!(
+                "Connection {} from {} closed after {:.1}s: {} bytes up, {} down",
+                number,
+                peer,
+                start.elapsed().as_secs_f64(),
+                relayed.up,
+                relayed.down
+            ),
+            Err(e) => warn!("Connection {} from {} failed: {}", number, peer, e),
+        }
+    });
+}
+
+/// Proxy errors
+#[derive(Debug, thiserror::Error)]
+pub enum ProxyError {
+    #[error("Usage: wayoa {USAGE}")]
+    Usage,
+    #[error("Invalid endpoint {0}, expected tcp:HOST:PORT or vsock:PORT")]
+    InvalidEndpoint(String),
+    #[error("XDG_RUNTIME_DIR is not set")]
+    NoRuntimeDir,
+    #[error(transparent)]
+    Ipc(#[from] ipc::IpcError),
+    #[error("The compositor refused the tagged socket: {0}")]
+    Refused(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = ProxyOptions::parse(&args("--listen tcp:127.0.0.1:6000 --tag lima")).unwrap();
+        assert_eq!(options.listen, Endpoint::Tcp("127.0.0.1:6000".to_string()));
+        assert_eq!(options.tag.as_deref(), Some("lima"));
+        assert_eq!(
+            ProxyOptions::parse(&args("--listen vsock:6000"))
+                .unwrap()
+                .listen,
+            Endpoint::Vsock(6000)
+        );
+
+        assert!(matches!(
+            ProxyOptions::parse(&args("--listen tcp:6000")),
+            Err(ProxyError::InvalidEndpoint(_))
+        ));
+        for line in ["", "--listen", "--listen vsock:1 --socket a --tag b"] {
+            assert!(matches!(
+                ProxyOptions::parse(&args(line)),
+                Err(ProxyError::Usage)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_relay() {
+        let (client, mut client_peer) = UnixStream::pair().unwrap();
+        let (server, mut server_peer) = UnixStream::pair().unwrap();
+        let relay = std::thread::spawn(move || relay(client, server).unwrap());
+
+        client_peer.write_all(b"hello").unwrap();
+        client_peer.shutdown(Shutdown::Write).unwrap();
+        let mut received = Vec::new();
+        server_peer.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"hello");
+
+        server_peer.write_all(b"hi").unwrap();
+        drop(server_peer);
+        let mut received = Vec::new();
+        client_peer.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"hi");
+        assert_eq!(relay.join().unwrap(), Relayed { up: 5, down: 2 });
+    }
+}
@@ -0,0 +1,70 @@
+//! vsock listening sockets
+//!
+//! std has no vsock type, so sockets are created with libc. Accepted
+//! connections are driven through `UnixStream`, whose reads, writes and
+//! shutdown are plain socket calls that work the same on vsock.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+
+/// Backlog of connections waiting to be accepted
+const BACKLOG: i32 = 16;
+
+/// A vsock socket listening on a port of any context ID
+#[derive(Debug)]
+pub struct VsockListener {
+    fd: OwnedFd,
+}
+
+impl VsockListener {
+    /// Listen on a port
+    pub fn bind(port: u32) -> std::io::Result<Self> {
+        // SAFETY: socket() returns a new descriptor we take ownership of
+        let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_vm is plain data, valid when zeroed
+        let mut address: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+        #[cfg(target_os = "macos")]
+        {
+            address.svm_len = std::mem::size_of::<libc::sockaddr_vm>() as u8;
+        }
+        address.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+        address.svm_port = port;
+        address.svm_cid = libc::VMADDR_CID_ANY;
+        // SAFETY: the address outlives the call and its length is given
+        let bound = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_vm as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if bound < 0 || unsafe { libc::listen(fd.as_raw_fd(), BACKLOG) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    /// Wait for a connection, returning it with the peer's context ID
+    pub fn accept(&self) -> std::io::Result<(UnixStream, u32)> {
+        // SAFETY: as in bind, accept() fills in at most `length` bytes
+        let mut address: libc::sockaddr_vm = unsafe { std::mem::zeroed() };
+        let mut length = std::mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+        let fd = unsafe {
+            libc::accept(
+                self.fd.as_raw_fd(),
+                &mut address as *mut libc::sockaddr_vm as *mut libc::sockaddr,
+                &mut length,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok((UnixStream::from(fd), address.svm_cid))
+    }
+}
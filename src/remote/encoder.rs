@@ -0,0 +1,184 @@
+//! Frame encoding on a worker thread
+//!
+//! Encoding a frame takes far longer than capturing it, so captures are
+//! handed to a worker thread and the main loop picks up the encoded frames
+//! on a later iteration. One frame is encoded at a time: the server doesn't
+//! capture while the worker is busy.
+//!
+//! On macOS frames are encoded to H.264 with VideoToolbox. Elsewhere, or
+//! when PNG is configured, every frame is a PNG image.
+
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+
+use log::warn;
+
+use super::protocol::Encoding;
+use super::RemoteError;
+use crate::renderer::Capture;
+
+/// An encoded frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoded {
+    /// Frame data in the stream's encoding
+    pub data: Vec<u8>,
+    /// The frame doesn't depend on earlier ones
+    pub keyframe: bool,
+}
+
+/// Encoder of one stream, living on the worker thread
+pub(super) trait FrameEncoder {
+    /// Encode a frame, as a keyframe if asked to
+    fn encode(&mut self, capture: &Capture, keyframe: bool) -> Result<Encoded, String>;
+}
+
+/// Encodes every frame as a PNG image
+struct PngEncoder;
+
+impl FrameEncoder for PngEncoder {
+    fn encode(&mut self, capture: &Capture, _keyframe: bool) -> Result<Encoded, String> {
+        let data = capture.to_png().map_err(|e| e.to_string())?;
+        Ok(Encoded {
+            data,
+            keyframe: true,
+        })
+    }
+}
+
+/// Get the encoding used for a configured one
+///
+/// H.264 needs VideoToolbox, so other platforms fall back to PNG.
+pub fn supported(encoding: Encoding) -> Encoding {
+    match encoding {
+        Encoding::H264 if !cfg!(target_os = "macos") => {
+            warn!("H.264 remote streams need VideoToolbox, sending PNG frames");
+            Encoding::Png
+        }
+        encoding => encoding,
+    }
+}
+
+/// Create the encoder of a supported encoding
+fn new_encoder(encoding: Encoding) -> Box<dyn FrameEncoder> {
+    match encoding {
+        #[cfg(target_os = "macos")]
+        Encoding::H264 => Box::new(super::videotoolbox::H264Encoder::default()),
+        // Not supported, see supported()
+        #[cfg(not(target_os = "macos"))]
+        Encoding::H264 => Box::new(PngEncoder),
+        Encoding::Png => Box::new(PngEncoder),
+    }
+}
+
+/// A worker thread encoding the frames of a stream
+#[derive(Debug)]
+pub struct EncoderThread {
+    /// Encoding of the frames
+    encoding: Encoding,
+    /// Captures to encode, closed when dropped to stop the thread
+    jobs: Option<Sender<(Capture, bool)>>,
+    /// Encoded frames
+    results: Receiver<Result<Encoded, String>>,
+    thread: Option<JoinHandle<()>>,
+    /// A capture was submitted and its frame not received yet
+    busy: bool,
+}
+
+impl EncoderThread {
+    /// Start encoding frames in a supported encoding
+    pub fn spawn(encoding: Encoding) -> Result<Self, RemoteError> {
+        let (jobs, job_queue) = mpsc::channel::<(Capture, bool)>();
+        let (done, results) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("wayoa-remote-encoder".to_string())
+            .spawn(move || {
+                let mut encoder = new_encoder(encoding);
+                for (capture, keyframe) in job_queue {
+                    if done.send(encoder.encode(&capture, keyframe)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| RemoteError::Io(e.to_string()))?;
+
+        Ok(Self {
+            encoding,
+            jobs: Some(jobs),
+            results,
+            thread: Some(thread),
+            busy: false,
+        })
+    }
+
+    /// Encoding of the frames
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// A frame is being encoded
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Queue a capture for encoding
+    pub fn submit(&mut self, capture: Capture, keyframe: bool) {
+        let sent = self
+            .jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send((capture, keyframe)).is_ok());
+        self.busy = sent;
+    }
+
+    /// Take the encoded frame if it's ready
+    pub fn poll(&mut self) -> Option<Result<Encoded, String>> {
+        if !self.busy {
+            return None;
+        }
+        match self.results.try_recv() {
+            Ok(result) => {
+                self.busy = false;
+                Some(result)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.busy = false;
+                Some(Err("the encoder thread exited".to_string()))
+            }
+        }
+    }
+}
+
+impl Drop for EncoderThread {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_encode_off_thread() {
+        let mut encoder = EncoderThread::spawn(Encoding::Png).unwrap();
+        assert!(encoder.poll().is_none());
+        encoder.submit(Capture::new(2, 2), false);
+        assert!(encoder.is_busy());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let encoded = loop {
+            if let Some(result) = encoder.poll() {
+                break result.unwrap();
+            }
+            assert!(Instant::now() < deadline, "frame wasn't encoded");
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        assert!(!encoder.is_busy());
+        assert!(encoded.keyframe);
+        assert_eq!(&encoded.data[1..4], b"PNG");
+    }
+}
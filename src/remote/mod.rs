@@ -0,0 +1,609 @@
+//! Remote access to a headless output
+//!
+//! With `[remote] enabled = true`, wayoa listens on a TCP address and
+//! streams a headless output to every connected viewer, which can send
+//! pointer and keyboard input back. The protocol is kept simple so that
+//! viewers are easy to write:
+//!
+//! - A viewer first sends a [`Hello`] line with the configured token, and
+//!   is disconnected if it doesn't match.
+//! - The server then sends one [`StreamInfo`] line.
+//! - Each frame is a [`FrameHeader`] line followed by `length` bytes of
+//!   H.264 or PNG. Frames are only sent when the output changed, and
+//!   viewers that fall behind skip frames instead of queueing them. An
+//!   H.264 viewer that skipped a frame waits for the next keyframe, which
+//!   is then forced.
+//! - Viewers send [`RemoteInput`] lines, e.g.
+//!   `{"type":"motion","x":10,"y":20}`.
+//!
+//! Frames are encoded on a worker thread, see [`encoder`].
+
+pub mod encoder;
+pub mod protocol;
+#[cfg(target_os = "macos")]
+mod videotoolbox;
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use crate::compositor::{OutputId, SurfaceId};
+use crate::config::RemoteConfig;
+use crate::protocol::seat::ButtonState;
+use crate::renderer::Capture;
+use crate::server::ServerState;
+use crate::util::clock;
+
+pub use encoder::{Encoded, EncoderThread};
+pub use protocol::{Encoding, FrameHeader, Hello, RemoteInput, StreamInfo};
+
+/// Longest input line accepted from a viewer
+const MAX_LINE: usize = 4096;
+
+/// How long a viewer has to send its token
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Remote viewer server
+#[derive(Debug)]
+pub struct RemoteServer {
+    /// Listening socket
+    listener: TcpListener,
+    /// Name of the output to stream, or the first headless one
+    output: Option<String>,
+    /// Token viewers authenticate with
+    token: String,
+    /// Minimum time between frames
+    interval: Duration,
+    /// Encoder of the frames
+    encoder: EncoderThread,
+    /// Connected viewers
+    peers: Vec<Peer>,
+    /// When the last frame was captured
+    last_frame: Option<Instant>,
+    /// Last captured frame, to skip unchanged ones
+    last_capture: Option<Capture>,
+    /// Frames sent so far
+    frames: u64,
+    /// Pointer position in output pixels
+    pointer: (f64, f64),
+}
+
+/// A connected viewer
+#[derive(Debug)]
+struct Peer {
+    stream: TcpStream,
+    addr: SocketAddr,
+    /// Partial input line
+    input: Vec<u8>,
+    /// Data not yet written
+    output: Vec<u8>,
+    /// When the peer connected
+    connected: Instant,
+    /// The peer sent the right token
+    authenticated: bool,
+    /// The peer hasn't received the current frame
+    stale: bool,
+    /// The peer missed a frame the next ones depend on
+    needs_keyframe: bool,
+    /// The connection failed or was closed
+    closed: bool,
+}
+
+impl Peer {
+    /// Read pending input lines
+    fn read(&mut self) -> Vec<String> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    debug!("Remote viewer {} failed: {}", self.addr, e);
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.input.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        if self.input.len() > MAX_LINE {
+            warn!("Remote viewer {} sent an overlong line", self.addr);
+            self.closed = true;
+        }
+        lines
+    }
+
+    /// Write as much pending output as the socket takes
+    fn flush(&mut self) {
+        while !self.output.is_empty() && !self.closed {
+            match self.stream.write(&self.output) {
+                Ok(0) => self.closed = true,
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    debug!("Remote viewer {} failed: {}", self.addr, e);
+                    self.closed = true;
+                }
+            }
+        }
+    }
+
+    /// The peer is watching and can take a new frame
+    fn ready(&self) -> bool {
+        self.authenticated && self.stale && self.output.is_empty()
+    }
+
+    /// Check the first line of a peer against the token, and describe the
+    /// stream to it if it matches
+    ///
+    /// Peers are refused while there is no output to stream.
+    fn authenticate(&mut self, line: &str, token: &str, info: Option<&StreamInfo>) {
+        let hello: Option<Hello> = serde_json::from_str(line).ok();
+        if !hello.is_some_and(|hello| token_matches(token, &hello.token)) {
+            warn!("Refusing remote viewer {}: wrong token", self.addr);
+            self.closed = true;
+            return;
+        }
+        let Some(info) = info else {
+            warn!("Refusing remote viewer {}: no headless output", self.addr);
+            self.closed = true;
+            return;
+        };
+        debug!("Remote viewer {} authenticated", self.addr);
+        self.authenticated = true;
+        self.send_line(info);
+    }
+
+    /// Queue a JSON line
+    fn send_line(&mut self, message: &impl serde::Serialize) {
+        match serde_json::to_vec(message) {
+            Ok(mut line) => {
+                line.push(b'\n');
+                self.output.extend_from_slice(&line);
+            }
+            Err(e) => warn!("Failed to encode remote message: {}", e),
+        }
+    }
+}
+
+impl RemoteServer {
+    /// Listen for viewers
+    ///
+    /// A token is required, as anyone reaching the address could otherwise
+    /// watch and control the session.
+    pub fn bind(config: &RemoteConfig) -> Result<Self, RemoteError> {
+        let token = config
+            .token
+            .clone()
+            .filter(|token| !token.is_empty())
+            .ok_or(RemoteError::NoToken)?;
+        let encoder = EncoderThread::spawn(encoder::supported(config.encoding))?;
+        let listener = TcpListener::bind(&config.listen)
+            .map_err(|e| RemoteError::Bind(config.listen.clone(), e.to_string()))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| RemoteError::Io(e.to_string()))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| RemoteError::Io(e.to_string()))?;
+        info!("Remote access on {}", addr);
+
+        Ok(Self {
+            listener,
+            output: config.output.clone(),
+            token,
+            interval: Duration::from_secs(1) / config.max_fps.max(1),
+            encoder,
+            peers: Vec::new(),
+            last_frame: None,
+            last_capture: None,
+            frames: 0,
+            pointer: (0.0, 0.0),
+        })
+    }
+
+    /// Get the address viewers connect to
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener.local_addr().ok()
+    }
+
+    /// Number of connected viewers
+    pub fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// The streamed output
+    fn output_id(&self, state: &ServerState) -> Option<OutputId> {
+        let outputs = &state.compositor.outputs;
+        match &self.output {
+            Some(name) => outputs.by_name(name).filter(|o| o.headless).map(|o| o.id),
+            None => outputs
+                .iter()
+                .filter(|(_, output)| output.headless)
+                .map(|(id, _)| *id)
+                .min_by_key(|id| id.0),
+        }
+    }
+
+    /// Accept viewers, apply their input and send them new frames
+    pub fn dispatch(&mut self, state: &mut ServerState, now: Instant) {
+        let output = self.output_id(state);
+        self.accept(now);
+
+        let info = output
+            .and_then(|id| state.compositor.outputs.get(id))
+            .map(|output| StreamInfo {
+                output: output.name.clone(),
+                width: output.width(),
+                height: output.height(),
+                encoding: self.encoder.encoding(),
+            });
+        let mut inputs = Vec::new();
+        for peer in &mut self.peers {
+            for line in peer.read() {
+                if line.is_empty() {
+                    continue;
+                }
+                if !peer.authenticated {
+                    peer.authenticate(&line, &self.token, info.as_ref());
+                    continue;
+                }
+                match RemoteInput::parse(&line) {
+                    Ok(input) => inputs.push(input),
+                    Err(e) => debug!("Ignoring remote input from {}: {}", peer.addr, e),
+                }
+            }
+            if !peer.authenticated && now.duration_since(peer.connected) >= AUTH_TIMEOUT {
+                info!("Remote viewer {} didn't authenticate", peer.addr);
+                peer.closed = true;
+            }
+        }
+        if let Some(output) = output {
+            for input in inputs {
+                self.inject(state, output, input);
+            }
+        }
+
+        self.receive_frame();
+        if let Some(output) = output {
+            let due = self
+                .last_frame
+                .is_none_or(|last| now.duration_since(last) >= self.interval);
+            let watched = self.peers.iter().any(|peer| peer.authenticated);
+            if watched && due && !self.encoder.is_busy() {
+                self.last_frame = Some(now);
+                self.capture_frame(state, output);
+            }
+        }
+
+        for peer in &mut self.peers {
+            peer.flush();
+        }
+        self.peers.retain(|peer| {
+            if peer.closed {
+                info!("Remote viewer {} disconnected", peer.addr);
+            }
+            !peer.closed
+        });
+        if !self.peers.iter().any(|peer| peer.authenticated) {
+            self.last_capture = None;
+        }
+    }
+
+    fn accept(&mut self, now: Instant) {
+        loop {
+            let (stream, addr) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to accept remote viewer: {}", e);
+                    break;
+                }
+            };
+            if let Err(e) = stream.set_nonblocking(true) {
+                warn!("Failed to set up remote viewer {}: {}", addr, e);
+                continue;
+            }
+            let _ = stream.set_nodelay(true);
+            info!("Remote viewer {} connected", addr);
+
+            self.peers.push(Peer {
+                stream,
+                addr,
+                input: Vec::new(),
+                output: Vec::new(),
+                connected: now,
+                authenticated: false,
+                stale: true,
+                needs_keyframe: true,
+                closed: false,
+            });
+        }
+    }
+
+    /// Capture the output and encode it if a viewer that is keeping up
+    /// hasn't seen it
+    fn capture_frame(&mut self, state: &mut ServerState, output: OutputId) {
+        let capture = match state.capture_output(output, true) {
+            Ok(capture) => capture,
+            Err(e) => {
+                warn!("Failed to capture remote output: {}", e);
+                return;
+            }
+        };
+        if self.last_capture.as_ref() != Some(&capture) {
+            for peer in &mut self.peers {
+                peer.stale = true;
+            }
+        }
+
+        // Peers still sending the previous frame get the next one instead
+        let waiting: Vec<&Peer> = self.peers.iter().filter(|peer| peer.ready()).collect();
+        if !waiting.is_empty() {
+            let keyframe = waiting.iter().any(|peer| peer.needs_keyframe);
+            self.encoder.submit(capture.clone(), keyframe);
+        }
+        self.last_capture = Some(capture);
+    }
+
+    /// Queue an encoded frame for every viewer that is keeping up
+    ///
+    /// Viewers that don't get a frame the next ones depend on wait for a
+    /// keyframe.
+    fn receive_frame(&mut self) {
+        let encoded = match self.encoder.poll() {
+            Some(Ok(encoded)) => encoded,
+            Some(Err(e)) => {
+                warn!("Failed to encode remote frame: {}", e);
+                return;
+            }
+            None => return,
+        };
+        self.frames += 1;
+
+        let header = FrameHeader {
+            frame: self.frames,
+            length: encoded.data.len(),
+            keyframe: encoded.keyframe,
+        };
+        for peer in self.peers.iter_mut().filter(|peer| peer.authenticated) {
+            if peer.ready() && (encoded.keyframe || !peer.needs_keyframe) {
+                peer.send_line(&header);
+                peer.output.extend_from_slice(&encoded.data);
+                peer.stale = false;
+                peer.needs_keyframe = false;
+            } else if !encoded.keyframe {
+                peer.needs_keyframe = true;
+            }
+        }
+    }
+
+    /// Route viewer input to the surfaces on the output
+    fn inject(&mut self, state: &mut ServerState, output: OutputId, input: RemoteInput) {
+        let time = clock::now_msec();
+        match input {
+            RemoteInput::Motion { x, y } => {
+                self.pointer = (x, y);
+                let (under, locate) = Self::hit_test(state, output, x, y);
+                state.pointer_motion(time, under, locate);
+            }
+            RemoteInput::Button { button, pressed } => {
+                let (x, y) = self.pointer;
+                let (under, locate) = Self::hit_test(state, output, x, y);
+                if pressed {
+                    if let Some(surface) = under {
+                        let root = state.compositor.surfaces.root(surface);
+                        let window = state.compositor.windows.window_for_surface(root);
+                        if window.is_some() {
+                            state.compositor.windows.set_focused(window);
+                            state.set_keyboard_focus(Some(root));
+                        }
+                    }
+                }
+                let button_state = match pressed {
+                    true => ButtonState::Pressed,
+                    false => ButtonState::Released,
+                };
+                state.pointer_button(time, button, button_state, under, locate);
+            }
+            RemoteInput::Key { key, pressed } => state.keyboard_key(time, key, pressed),
+        }
+    }
+
+    /// Find the surface under a point of the output, and a function giving
+    /// the point in any surface's coordinates
+    ///
+    /// Zoomed windows show a magnified part of their content, so the point
+    /// is first mapped to the content it shows.
+    fn hit_test(
+        state: &ServerState,
+        output: OutputId,
+        x: f64,
+        y: f64,
+    ) -> (Option<SurfaceId>, impl Fn(SurfaceId) -> (f64, f64)) {
+        let surfaces = &state.compositor.surfaces;
+        let windows = state.compositor.windows.on_output(output);
+        let points: Vec<(f64, f64)> = windows
+            .iter()
+            .map(|window| {
+                let size = surfaces.get(window.surface_id).and_then(|s| s.size());
+                match size {
+                    Some((w, h)) => window.zoom.to_content((w as f64, h as f64), x, y),
+                    None => (x, y),
+                }
+            })
+            .collect();
+
+        // Windows on a headless output are all at its origin
+        let located: HashMap<SurfaceId, (f64, f64)> = windows
+            .iter()
+            .zip(&points)
+            .flat_map(|(window, &(x, y))| {
+                surfaces
+                    .stacking_order(window.surface_id, (0, 0))
+                    .into_iter()
+                    .map(move |(surface, (ox, oy))| (surface, (x - ox as f64, y - oy as f64)))
+            })
+            .collect();
+        let under = windows
+            .iter()
+            .zip(&points)
+            .rev()
+            .find_map(|(window, &(x, y))| surfaces.surface_at(window.surface_id, x, y))
+            .map(|(surface, _, _)| surface);
+
+        let locate = move |surface| located.get(&surface).copied().unwrap_or((x, y));
+        (under, locate)
+    }
+}
+
+/// Compare a token in time independent of where it differs
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Remote access errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RemoteError {
+    #[error("No token is configured for remote viewers")]
+    NoToken,
+    #[error("Failed to listen on {0}: {1}")]
+    Bind(String, String),
+    #[error("Remote I/O error: {0}")]
+    Io(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    fn config() -> RemoteConfig {
+        RemoteConfig {
+            enabled: true,
+            listen: "127.0.0.1:0".to_string(),
+            token: Some("secret".to_string()),
+            encoding: Encoding::Png,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(
+            RemoteInput::parse(r#"{"type":"motion","x":1.5,"y":2}"#).unwrap(),
+            RemoteInput::Motion { x: 1.5, y: 2.0 }
+        );
+        assert_eq!(
+            RemoteInput::parse(r#"{"type":"key","key":30,"pressed":true}"#).unwrap(),
+            RemoteInput::Key {
+                key: 30,
+                pressed: true
+            }
+        );
+        assert!(RemoteInput::parse(r#"{"type":"scroll"}"#).is_err());
+    }
+
+    #[test]
+    fn test_stream_frames_and_input() {
+        let mut state = ServerState::new();
+        let output =
+            state
+                .compositor
+                .outputs
+                .create_headless("virtual-0".to_string(), 4, 3, 1, 60000);
+        let root = state.compositor.surfaces.create_surface();
+        let window = state.compositor.windows.create_window(root);
+        state.compositor.windows.get_mut(window).unwrap().output = Some(output);
+        state.set_keyboard_focus(Some(root));
+
+        let mut server = RemoteServer::bind(&config()).unwrap();
+        let addr = server.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            writeln!(stream, r#"{{"token":"secret"}}"#).unwrap();
+            writeln!(stream, r#"{{"type":"key","key":30,"pressed":true}}"#).unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let info: StreamInfo = serde_json::from_str(&line).unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let header: FrameHeader = serde_json::from_str(&line).unwrap();
+            let mut png = vec![0; header.length];
+            reader.read_exact(&mut png).unwrap();
+            (info, header, png)
+        });
+        while !client.is_finished() {
+            server.dispatch(&mut state, Instant::now());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let (info, header, png) = client.join().unwrap();
+        server.dispatch(&mut state, Instant::now());
+        assert_eq!((info.width, info.height), (4, 3));
+        assert_eq!(info.output, "virtual-0");
+        assert_eq!(info.encoding, Encoding::Png);
+        assert_eq!(header.frame, 1);
+        assert!(header.keyframe);
+        assert_eq!(&png[1..4], b"PNG");
+        assert_eq!(
+            state.compositor.seat.keyboard().pressed_keys(),
+            &[30],
+            "key input reaches the seat"
+        );
+    }
+
+    #[test]
+    fn test_token_required() {
+        let no_token = RemoteConfig {
+            token: None,
+            ..config()
+        };
+        assert_eq!(
+            RemoteServer::bind(&no_token).unwrap_err(),
+            RemoteError::NoToken
+        );
+
+        let mut state = ServerState::new();
+        state
+            .compositor
+            .outputs
+            .create_headless("virtual-0".to_string(), 4, 3, 1, 60000);
+        let mut server = RemoteServer::bind(&config()).unwrap();
+        let addr = server.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            writeln!(stream, r#"{{"token":"guess"}}"#).unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+        while !client.is_finished() {
+            server.dispatch(&mut state, Instant::now());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(client.join().unwrap().is_empty(), "nothing is streamed");
+        assert_eq!(server.peer_count(), 0);
+    }
+}
@@ -0,0 +1,67 @@
+//! Remote viewer wire protocol
+//!
+//! All messages are JSON lines, except for frame data which follows its
+//! header as raw bytes.
+
+use serde::{Deserialize, Serialize};
+
+/// Frame encoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// H.264 Annex B, with the parameter sets before every keyframe
+    #[default]
+    H264,
+    /// One PNG image per frame
+    Png,
+}
+
+/// Sent by a viewer as its first line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    /// Token from the `[remote]` configuration
+    pub token: String,
+}
+
+/// Sent once the viewer is authenticated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamInfo {
+    /// Name of the streamed output
+    pub output: String,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Frame encoding
+    pub encoding: Encoding,
+}
+
+/// Precedes every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameHeader {
+    /// Frame sequence number, counting all frames of the stream
+    pub frame: u64,
+    /// Number of bytes of frame data following the header line
+    pub length: usize,
+    /// The frame doesn't depend on earlier ones, as every PNG frame
+    pub keyframe: bool,
+}
+
+/// Input sent by a viewer, in output pixels and Linux input event codes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum RemoteInput {
+    /// Pointer moved to a position
+    Motion { x: f64, y: f64 },
+    /// Pointer button pressed or released
+    Button { button: u32, pressed: bool },
+    /// Key pressed or released
+    Key { key: u32, pressed: bool },
+}
+
+impl RemoteInput {
+    /// Parse one input line
+    pub fn parse(line: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(line)
+    }
+}
@@ -0,0 +1,447 @@
+//! H.264 encoding with VideoToolbox
+//!
+//! Frames are encoded in real time without reordering, so every frame comes
+//! out of the session before `encode` returns. The output is converted from
+//! the length-prefixed NAL units VideoToolbox produces to an Annex B byte
+//! stream, with the SPS and PPS in front of every keyframe so viewers can
+//! start decoding at any of them.
+
+#![allow(non_upper_case_globals)]
+
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::Mutex;
+
+use super::encoder::{Encoded, FrameEncoder};
+use crate::renderer::Capture;
+
+type CFTypeRef = *const c_void;
+type CFStringRef = *const c_void;
+type CFDictionaryRef = *const c_void;
+type CFArrayRef = *const c_void;
+type CVPixelBufferRef = *mut c_void;
+type CMSampleBufferRef = *mut c_void;
+type CMBlockBufferRef = *mut c_void;
+type CMFormatDescriptionRef = *mut c_void;
+type VTCompressionSessionRef = *mut c_void;
+type OSStatus = i32;
+
+/// kCMVideoCodecType_H264, 'avc1'
+const CODEC_H264: u32 = 0x6176_6331;
+/// kCVPixelFormatType_32BGRA, 'BGRA', the layout of captures
+const PIXEL_FORMAT_BGRA: u32 = 0x4247_5241;
+/// kCMTimeFlags_Valid
+const TIME_VALID: u32 = 1;
+
+/// Annex B start code put before every NAL unit
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CMTime {
+    value: i64,
+    timescale: i32,
+    flags: u32,
+    epoch: i64,
+}
+
+/// kCMTimeInvalid
+const TIME_INVALID: CMTime = CMTime {
+    value: 0,
+    timescale: 0,
+    flags: 0,
+    epoch: 0,
+};
+
+/// Opaque CFDictionary callback tables
+#[repr(C)]
+struct CallBacks {
+    _private: [u8; 0],
+}
+
+type OutputCallback = extern "C" fn(
+    output_refcon: *mut c_void,
+    frame_refcon: *mut c_void,
+    status: OSStatus,
+    info_flags: u32,
+    sample: CMSampleBufferRef,
+);
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFBooleanTrue: CFTypeRef;
+    static kCFBooleanFalse: CFTypeRef;
+    static kCFTypeDictionaryKeyCallBacks: CallBacks;
+    static kCFTypeDictionaryValueCallBacks: CallBacks;
+
+    fn CFDictionaryCreate(
+        allocator: *const c_void,
+        keys: *const CFTypeRef,
+        values: *const CFTypeRef,
+        count: isize,
+        key_callbacks: *const CallBacks,
+        value_callbacks: *const CallBacks,
+    ) -> CFDictionaryRef;
+    fn CFDictionaryContainsKey(dictionary: CFDictionaryRef, key: CFTypeRef) -> u8;
+    fn CFArrayGetCount(array: CFArrayRef) -> isize;
+    fn CFArrayGetValueAtIndex(array: CFArrayRef, index: isize) -> CFTypeRef;
+    fn CFRelease(cf: CFTypeRef);
+}
+
+#[link(name = "CoreVideo", kind = "framework")]
+extern "C" {
+    fn CVPixelBufferCreate(
+        allocator: *const c_void,
+        width: usize,
+        height: usize,
+        pixel_format: u32,
+        attributes: CFDictionaryRef,
+        buffer_out: *mut CVPixelBufferRef,
+    ) -> i32;
+    fn CVPixelBufferLockBaseAddress(buffer: CVPixelBufferRef, flags: u64) -> i32;
+    fn CVPixelBufferUnlockBaseAddress(buffer: CVPixelBufferRef, flags: u64) -> i32;
+    fn CVPixelBufferGetBaseAddress(buffer: CVPixelBufferRef) -> *mut c_void;
+    fn CVPixelBufferGetBytesPerRow(buffer: CVPixelBufferRef) -> usize;
+}
+
+#[link(name = "CoreMedia", kind = "framework")]
+extern "C" {
+    static kCMSampleAttachmentKey_NotSync: CFStringRef;
+
+    fn CMSampleBufferGetDataBuffer(sample: CMSampleBufferRef) -> CMBlockBufferRef;
+    fn CMSampleBufferGetFormatDescription(sample: CMSampleBufferRef) -> CMFormatDescriptionRef;
+    fn CMSampleBufferGetSampleAttachmentsArray(sample: CMSampleBufferRef, create: u8)
+        -> CFArrayRef;
+    fn CMBlockBufferGetDataLength(buffer: CMBlockBufferRef) -> usize;
+    fn CMBlockBufferCopyDataBytes(
+        buffer: CMBlockBufferRef,
+        offset: usize,
+        length: usize,
+        destination: *mut c_void,
+    ) -> OSStatus;
+    fn CMVideoFormatDescriptionGetH264ParameterSetAtIndex(
+        description: CMFormatDescriptionRef,
+        index: usize,
+        parameter_set: *mut *const u8,
+        size: *mut usize,
+        count: *mut usize,
+        nal_header_length: *mut i32,
+    ) -> OSStatus;
+}
+
+#[link(name = "VideoToolbox", kind = "framework")]
+extern "C" {
+    static kVTCompressionPropertyKey_RealTime: CFStringRef;
+    static kVTCompressionPropertyKey_AllowFrameReordering: CFStringRef;
+    static kVTCompressionPropertyKey_ProfileLevel: CFStringRef;
+    static kVTProfileLevel_H264_Baseline_AutoLevel: CFStringRef;
+    static kVTEncodeFrameOptionKey_ForceKeyFrame: CFStringRef;
+
+    fn VTCompressionSessionCreate(
+        allocator: *const c_void,
+        width: i32,
+        height: i32,
+        codec: u32,
+        encoder_specification: CFDictionaryRef,
+        source_attributes: CFDictionaryRef,
+        compressed_allocator: *const c_void,
+        callback: Option<OutputCallback>,
+        callback_refcon: *mut c_void,
+        session_out: *mut VTCompressionSessionRef,
+    ) -> OSStatus;
+    fn VTSessionSetProperty(session: CFTypeRef, key: CFStringRef, value: CFTypeRef) -> OSStatus;
+    fn VTCompressionSessionEncodeFrame(
+        session: VTCompressionSessionRef,
+        image: CVPixelBufferRef,
+        presentation_time: CMTime,
+        duration: CMTime,
+        frame_properties: CFDictionaryRef,
+        frame_refcon: *mut c_void,
+        info_flags_out: *mut u32,
+    ) -> OSStatus;
+    fn VTCompressionSessionCompleteFrames(
+        session: VTCompressionSessionRef,
+        until: CMTime,
+    ) -> OSStatus;
+    fn VTCompressionSessionInvalidate(session: VTCompressionSessionRef);
+}
+
+/// Where the output callback leaves an encoded frame
+type Output = Mutex<Option<Result<Encoded, String>>>;
+
+/// Receive an encoded frame from the session
+extern "C" fn on_output(
+    output_refcon: *mut c_void,
+    _frame_refcon: *mut c_void,
+    status: OSStatus,
+    _info_flags: u32,
+    sample: CMSampleBufferRef,
+) {
+    // SAFETY: the refcon is the session's boxed output, which outlives the
+    // session
+    let output = unsafe { &*(output_refcon as *const Output) };
+    let result = match status {
+        0 if !sample.is_null() => unsafe { annex_b(sample) },
+        0 => Err("the frame was dropped".to_string()),
+        status => Err(format!("encoding failed with status {}", status)),
+    };
+    if let Ok(mut slot) = output.lock() {
+        *slot = Some(result);
+    }
+}
+
+/// Check whether a sample can be decoded on its own
+unsafe fn is_keyframe(sample: CMSampleBufferRef) -> bool {
+    let attachments = CMSampleBufferGetSampleAttachmentsArray(sample, 0);
+    if attachments.is_null() || CFArrayGetCount(attachments) == 0 {
+        return true;
+    }
+    let attachment = CFArrayGetValueAtIndex(attachments, 0);
+    CFDictionaryContainsKey(attachment, kCMSampleAttachmentKey_NotSync) == 0
+}
+
+/// Convert an encoded sample to Annex B
+unsafe fn annex_b(sample: CMSampleBufferRef) -> Result<Encoded, String> {
+    let keyframe = is_keyframe(sample);
+    let mut data = Vec::new();
+
+    // The length of the NAL unit size prefixes, 4 unless the format says
+    let mut prefix = 4;
+    let description = CMSampleBufferGetFormatDescription(sample);
+    let mut count = 0;
+    let mut header_length = 0;
+    let status = CMVideoFormatDescriptionGetH264ParameterSetAtIndex(
+        description,
+        0,
+        ptr::null_mut(),
+        ptr::null_mut(),
+        &mut count,
+        &mut header_length,
+    );
+    if status == 0 && header_length > 0 {
+        prefix = header_length as usize;
+    }
+    if keyframe {
+        for index in 0..count {
+            let mut set = ptr::null();
+            let mut size = 0;
+            let status = CMVideoFormatDescriptionGetH264ParameterSetAtIndex(
+                description,
+                index,
+                &mut set,
+                &mut size,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if status != 0 || set.is_null() {
+                return Err(format!("no H.264 parameter set {}", index));
+            }
+            data.extend_from_slice(&START_CODE);
+            data.extend_from_slice(std::slice::from_raw_parts(set, size));
+        }
+    }
+
+    let block = CMSampleBufferGetDataBuffer(sample);
+    if block.is_null() {
+        return Err("the frame has no data".to_string());
+    }
+    let length = CMBlockBufferGetDataLength(block);
+    let mut units = vec![0u8; length];
+    let status = CMBlockBufferCopyDataBytes(block, 0, length, units.as_mut_ptr().cast());
+    if status != 0 {
+        return Err(format!("reading the frame failed with status {}", status));
+    }
+
+    let mut rest = units.as_slice();
+    while rest.len() > prefix {
+        let size = rest[..prefix]
+            .iter()
+            .fold(0usize, |size, &byte| (size << 8) | byte as usize);
+        let unit = rest
+            .get(prefix..prefix + size)
+            .ok_or("truncated NAL unit")?;
+        data.extend_from_slice(&START_CODE);
+        data.extend_from_slice(unit);
+        rest = &rest[prefix + size..];
+    }
+    Ok(Encoded { data, keyframe })
+}
+
+/// A compression session for one frame size
+struct Session {
+    raw: VTCompressionSessionRef,
+    width: u32,
+    height: u32,
+    /// Frames encoded, for presentation times
+    frames: i64,
+    /// Filled by the output callback
+    output: Box<Output>,
+}
+
+impl Session {
+    fn new(width: u32, height: u32) -> Result<Self, String> {
+        let output: Box<Output> = Box::new(Mutex::new(None));
+        let mut raw = ptr::null_mut();
+        // SAFETY: the output is boxed, so its address is stable for the
+        // session's life
+        let status = unsafe {
+            VTCompressionSessionCreate(
+                ptr::null(),
+                width as i32,
+                height as i32,
+                CODEC_H264,
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                Some(on_output),
+                &*output as *const Output as *mut c_void,
+                &mut raw,
+            )
+        };
+        if status != 0 || raw.is_null() {
+            return Err(format!(
+                "creating a {}x{} H.264 session failed with status {}",
+                width, height, status
+            ));
+        }
+
+        // Frames come out in order as soon as they are encoded
+        unsafe {
+            VTSessionSetProperty(raw, kVTCompressionPropertyKey_RealTime, kCFBooleanTrue);
+            VTSessionSetProperty(
+                raw,
+                kVTCompressionPropertyKey_AllowFrameReordering,
+                kCFBooleanFalse,
+            );
+            VTSessionSetProperty(
+                raw,
+                kVTCompressionPropertyKey_ProfileLevel,
+                kVTProfileLevel_H264_Baseline_AutoLevel,
+            );
+        }
+
+        Ok(Self {
+            raw,
+            width,
+            height,
+            frames: 0,
+            output,
+        })
+    }
+
+    fn encode(&mut self, capture: &Capture, keyframe: bool) -> Result<Encoded, String> {
+        let pixels = pixel_buffer(capture)?;
+        let properties = match keyframe {
+            true => unsafe {
+                let keys = [kVTEncodeFrameOptionKey_ForceKeyFrame];
+                let values = [kCFBooleanTrue];
+                CFDictionaryCreate(
+                    ptr::null(),
+                    keys.as_ptr(),
+                    values.as_ptr(),
+                    1,
+                    &kCFTypeDictionaryKeyCallBacks,
+                    &kCFTypeDictionaryValueCallBacks,
+                )
+            },
+            false => ptr::null(),
+        };
+        let time = CMTime {
+            value: self.frames,
+            timescale: 30,
+            flags: TIME_VALID,
+            epoch: 0,
+        };
+        self.frames += 1;
+
+        // SAFETY: the pixel buffer and properties are valid until released
+        // below, after the session has finished with the frame
+        let status = unsafe {
+            let status = VTCompressionSessionEncodeFrame(
+                self.raw,
+                pixels,
+                time,
+                TIME_INVALID,
+                properties,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if status == 0 {
+                VTCompressionSessionCompleteFrames(self.raw, TIME_INVALID);
+            }
+            CFRelease(pixels);
+            if !properties.is_null() {
+                CFRelease(properties);
+            }
+            status
+        };
+        if status != 0 {
+            return Err(format!("encoding failed with status {}", status));
+        }
+        self.output
+            .lock()
+            .ok()
+            .and_then(|mut slot| slot.take())
+            .unwrap_or_else(|| Err("the encoder produced no frame".to_string()))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // SAFETY: invalidating stops the callbacks before the output is freed
+        unsafe {
+            VTCompressionSessionInvalidate(self.raw);
+            CFRelease(self.raw);
+        }
+    }
+}
+
+/// Copy a capture into a new BGRA pixel buffer, released by the caller
+fn pixel_buffer(capture: &Capture) -> Result<CVPixelBufferRef, String> {
+    let mut buffer = ptr::null_mut();
+    // SAFETY: the buffer is locked while its rows are written, and each row
+    // copied is no longer than both strides
+    unsafe {
+        let status = CVPixelBufferCreate(
+            ptr::null(),
+            capture.width as usize,
+            capture.height as usize,
+            PIXEL_FORMAT_BGRA,
+            ptr::null(),
+            &mut buffer,
+        );
+        if status != 0 || buffer.is_null() {
+            return Err(format!(
+                "creating a pixel buffer failed with status {}",
+                status
+            ));
+        }
+        CVPixelBufferLockBaseAddress(buffer, 0);
+        let base = CVPixelBufferGetBaseAddress(buffer) as *mut u8;
+        let stride = CVPixelBufferGetBytesPerRow(buffer);
+        let row = capture.stride() as usize;
+        for (y, line) in capture.data.chunks_exact(row).enumerate() {
+            ptr::copy_nonoverlapping(line.as_ptr(), base.add(y * stride), row.min(stride));
+        }
+        CVPixelBufferUnlockBaseAddress(buffer, 0);
+    }
+    Ok(buffer)
+}
+
+/// Encodes frames to H.264, starting a new session when the size changes
+#[derive(Default)]
+pub struct H264Encoder {
+    session: Option<Session>,
+}
+
+impl FrameEncoder for H264Encoder {
+    fn encode(&mut self, capture: &Capture, keyframe: bool) -> Result<Encoded, String> {
+        let size = (capture.width, capture.height);
+        let session = match self.session.take() {
+            Some(session) if (session.width, session.height) == size => session,
+            _ => Session::new(capture.width, capture.height)?,
+        };
+        let session = self.session.insert(session);
+        session.encode(capture, keyframe)
+    }
+}
@@ -0,0 +1,186 @@
+//! Pixel format conversion for shm buffers
+//!
+//! Windows are presented and captured as ARGB8888 images, so buffers in
+//! other wl_shm formats (16-bit RGB565, packed 24-bit BGR888, the 30-bit
+//! 2-10-10-10 formats, ABGR/XBGR8888 and the half-float ones) are expanded
+//! to it on the CPU. Showing their bytes as ARGB8888 would show garbage.
+
+use crate::protocol::shm::ShmFormat;
+
+/// Convert a half-float to an 8-bit channel, clamped to 0..=1
+fn half_to_u8(bits: u16) -> u8 {
+    let mantissa = (bits & 0x3ff) as f32;
+    let value = match (bits >> 10) & 0x1f {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f => {
+            return if mantissa == 0.0 && bits & 0x8000 == 0 {
+                0xff
+            } else {
+                0
+            }
+        }
+        exponent => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    };
+    if bits & 0x8000 != 0 {
+        return 0;
+    }
+    (value.min(1.0) * 255.0).round() as u8
+}
+
+/// Decode one pixel into ARGB8888 byte order (B, G, R, A)
+fn decode_pixel(format: ShmFormat, bytes: &[u8]) -> [u8; 4] {
+    let expand_10 = |v: u32| (v >> 2) as u8;
+    let expand_2 = |v: u32| (v * 85) as u8;
+    let word = || u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let half = |i: usize| half_to_u8(u16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]));
+
+    match format {
+        ShmFormat::Argb8888 => [bytes[0], bytes[1], bytes[2], bytes[3]],
+        ShmFormat::Xrgb8888 => [bytes[0], bytes[1], bytes[2], 0xff],
+        ShmFormat::Rgb565 => {
+            let v = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let r = ((v >> 11) & 0x1f) as u8;
+            let g = ((v >> 5) & 0x3f) as u8;
+            let b = (v & 0x1f) as u8;
+            [
+                (b << 3) | (b >> 2),
+                (g << 2) | (g >> 4),
+                (r << 3) | (r >> 2),
+                0xff,
+            ]
+        }
+        // Stored R, G, B in memory
+        ShmFormat::Bgr888 => [bytes[2], bytes[1], bytes[0], 0xff],
+        ShmFormat::Xrgb2101010 | ShmFormat::Argb2101010 => {
+            let v = word();
+            let alpha = match format {
+                ShmFormat::Argb2101010 => expand_2(v >> 30),
+                _ => 0xff,
+            };
+            [
+                expand_10(v & 0x3ff),
+                expand_10((v >> 10) & 0x3ff),
+                expand_10((v >> 20) & 0x3ff),
+                alpha,
+            ]
+        }
+        ShmFormat::Xbgr2101010 | ShmFormat::Abgr2101010 => {
+            let v = word();
+            let alpha = match format {
+                ShmFormat::Abgr2101010 => expand_2(v >> 30),
+                _ => 0xff,
+            };
+            [
+                expand_10((v >> 20) & 0x3ff),
+                expand_10((v >> 10) & 0x3ff),
+                expand_10(v & 0x3ff),
+                alpha,
+            ]
+        }
+        // Stored R, G, B, A in memory
+        ShmFormat::Abgr8888 => [bytes[2], bytes[1], bytes[0], bytes[3]],
+        ShmFormat::Xbgr8888 => [bytes[2], bytes[1], bytes[0], 0xff],
+        ShmFormat::Abgr16161616f => [half(2), half(1), half(0), half(3)],
+        ShmFormat::Xbgr16161616f => [half(2), half(1), half(0), 0xff],
+        ShmFormat::Argb16161616f => [half(0), half(1), half(2), half(3)],
+        ShmFormat::Xrgb16161616f => [half(0), half(1), half(2), 0xff],
+        ShmFormat::Other(_) => [0, 0, 0, 0],
+    }
+}
+
+/// Convert a buffer to ARGB8888 with a tight stride of `width * 4`
+///
+/// Returns None for formats that can't be displayed or when `data` is
+/// shorter than `stride * height`.
+pub fn to_argb8888(
+    format: ShmFormat,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Option<Vec<u8>> {
+    if let ShmFormat::Other(_) = format {
+        return None;
+    }
+    let bpp = format.bytes_per_pixel() as usize;
+    let (width, height, stride) = (width as usize, height as usize, stride as usize);
+    if stride < width * bpp || data.len() < stride * height {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(width * height * 4);
+    for row in data.chunks(stride).take(height) {
+        for pixel in row[..width * bpp].chunks_exact(bpp) {
+            out.extend_from_slice(&decode_pixel(format, pixel));
+        }
+    }
+    Some(out)
+}
+
+/// Get buffer data in ARGB8888, along with its stride
+///
+/// 32-bit ARGB/XRGB data is passed through untouched, other formats are
+/// converted with [`to_argb8888`].
+pub fn into_argb8888(
+    format: ShmFormat,
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Option<(Vec<u8>, u32)> {
+    match format {
+        ShmFormat::Argb8888 | ShmFormat::Xrgb8888 => Some((data, stride)),
+        _ => to_argb8888(format, &data, width, height, stride).map(|data| (data, width * 4)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgb565_and_bgr888() {
+        // Pure red, pure green with padding at the end of the row
+        let data = [0x00, 0xf8, 0xe0, 0x07, 0xaa, 0xbb];
+        let out = to_argb8888(ShmFormat::Rgb565, &data, 2, 1, 6).unwrap();
+        assert_eq!(out, vec![0, 0, 0xff, 0xff, 0, 0xff, 0, 0xff]);
+
+        let data = [0x10, 0x20, 0x30];
+        let out = to_argb8888(ShmFormat::Bgr888, &data, 1, 1, 3).unwrap();
+        assert_eq!(out, vec![0x30, 0x20, 0x10, 0xff]);
+
+        assert_eq!(to_argb8888(ShmFormat::Bgr888, &data, 2, 1, 6), None);
+    }
+
+    #[test]
+    fn test_2101010() {
+        // A=3, R=0x3ff, G=0, B=0x200
+        let v: u32 = (3 << 30) | (0x3ff << 20) | 0x200;
+        let out = to_argb8888(ShmFormat::Argb2101010, &v.to_le_bytes(), 1, 1, 4).unwrap();
+        assert_eq!(out, vec![0x80, 0, 0xff, 0xff]);
+
+        // Same word as ABGR swaps red and blue, XBGR ignores alpha
+        let out = to_argb8888(ShmFormat::Abgr2101010, &v.to_le_bytes(), 1, 1, 4).unwrap();
+        assert_eq!(out, vec![0xff, 0, 0x80, 0xff]);
+        let v: u32 = 0x3ff;
+        let out = to_argb8888(ShmFormat::Xbgr2101010, &v.to_le_bytes(), 1, 1, 4).unwrap();
+        assert_eq!(out, vec![0, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_abgr8888_and_f16() {
+        let data = [0x10, 0x20, 0x30, 0x40];
+        let out = to_argb8888(ShmFormat::Abgr8888, &data, 1, 1, 4).unwrap();
+        assert_eq!(out, vec![0x30, 0x20, 0x10, 0x40]);
+        let out = to_argb8888(ShmFormat::Xbgr8888, &data, 1, 1, 4).unwrap();
+        assert_eq!(out, vec![0x30, 0x20, 0x10, 0xff]);
+
+        // R=1.0, G=0.5, B=-1.0, A=2.0, out of range values are clamped
+        let halves: [u16; 4] = [0x3c00, 0x3800, 0xbc00, 0x4000];
+        let data: Vec<u8> = halves.iter().flat_map(|h| h.to_le_bytes()).collect();
+        let out = to_argb8888(ShmFormat::Abgr16161616f, &data, 1, 1, 8).unwrap();
+        assert_eq!(out, vec![0, 0x80, 0xff, 0xff]);
+        let out = to_argb8888(ShmFormat::Argb16161616f, &data, 1, 1, 8).unwrap();
+        assert_eq!(out, vec![0xff, 0x80, 0, 0xff]);
+    }
+}
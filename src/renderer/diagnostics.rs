@@ -0,0 +1,280 @@
+//! Diagnostic overlays over window content
+//!
+//! With diagnostics on, each window is drawn with its surface tree made
+//! visible: the damage of each surface's last commit is tinted, surfaces
+//! are outlined (the toplevel and popups in blue, subsurfaces in cyan),
+//! the window geometry in green and input regions in yellow, and every
+//! surface is labelled with its ID. The overlay is a list of solid
+//! rectangles in window coordinates, painted over the window's composed
+//! content.
+
+use crate::compositor::surface::DamageRect;
+use crate::compositor::{CompositorState, SurfaceRole, WindowId};
+
+/// Damage of the last commit
+const DAMAGE: [f32; 4] = [0.45, 0.0, 0.0, 0.45];
+/// Outline of the toplevel surface and popups
+const SURFACE: [f32; 4] = [0.2, 0.4, 1.0, 1.0];
+/// Outline of subsurfaces
+const SUBSURFACE: [f32; 4] = [0.0, 0.9, 0.9, 1.0];
+/// Outline of the window geometry
+const GEOMETRY: [f32; 4] = [0.0, 0.9, 0.2, 1.0];
+/// Outline of input regions
+const INPUT_REGION: [f32; 4] = [0.9, 0.8, 0.0, 1.0];
+/// Background of surface labels
+const LABEL_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.75];
+/// Digits of surface labels
+const LABEL_TEXT: [f32; 4] = [1.0; 4];
+
+/// Size of a pixel of the label font, in window coordinates
+const FONT_PIXEL: f32 = 2.0;
+
+/// Digits 0-9 as 3x5 bitmaps, a row per byte from the top
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// A solid rectangle of the overlay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayRect {
+    /// Position and size (x, y, width, height), in window coordinates
+    pub rect: [f32; 4],
+    /// Premultiplied RGBA color
+    pub color: [f32; 4],
+}
+
+/// The diagnostic overlay of one window, in paint order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiagnosticOverlay {
+    pub rects: Vec<OverlayRect>,
+}
+
+impl DiagnosticOverlay {
+    /// Build the overlay of a window from its committed surface state
+    pub fn for_window(compositor: &CompositorState, window_id: WindowId) -> Self {
+        let mut overlay = Self::default();
+        let Some(window) = compositor.windows.get(window_id) else {
+            return overlay;
+        };
+        let surfaces = &compositor.surfaces;
+        let order = surfaces.stacking_order(window.surface_id, (0, 0));
+        let placed = || {
+            order.iter().filter_map(|&(id, origin)| {
+                let surface = surfaces.get(id)?;
+                Some((surface, origin, surface.size()?))
+            })
+        };
+
+        for (surface, (x, y), _) in placed() {
+            let geometry = surface.buffer_geometry();
+            for &damage in &surface.damage {
+                let rect = geometry.buffer_to_surface(damage);
+                overlay.fill(offset(rect, x, y), DAMAGE);
+            }
+        }
+        for (surface, (x, y), (width, height)) in placed() {
+            let color = match surface.role {
+                SurfaceRole::Subsurface => SUBSURFACE,
+                _ => SURFACE,
+            };
+            overlay.outline(rect(x, y, width, height), color);
+            for region in surface.input_region.iter().flat_map(|r| r.rects()) {
+                overlay.outline(offset(region, x, y), INPUT_REGION);
+            }
+        }
+        let geometry = window.geometry;
+        if geometry.width > 0 && geometry.height > 0 {
+            let (width, height) = (geometry.width as i32, geometry.height as i32);
+            overlay.outline(rect(geometry.x, geometry.y, width, height), GEOMETRY);
+        }
+        for (surface, (x, y), _) in placed() {
+            overlay.label(x as f32 + 2.0, y as f32 + 2.0, surface.id.0);
+        }
+        overlay
+    }
+
+    fn fill(&mut self, rect: DamageRect, color: [f32; 4]) {
+        if rect.width <= 0 || rect.height <= 0 {
+            return;
+        }
+        let [x, y, width, height] = [rect.x, rect.y, rect.width, rect.height].map(|v| v as f32);
+        self.rects.push(OverlayRect {
+            rect: [x, y, width, height],
+            color,
+        });
+    }
+
+    /// Outline a rectangle with a line inside its edges
+    fn outline(&mut self, r: DamageRect, color: [f32; 4]) {
+        let (x, y, w, h) = (r.x, r.y, r.width, r.height);
+        self.fill(rect(x, y, w, 1), color);
+        self.fill(rect(x, y + h - 1, w, 1), color);
+        self.fill(rect(x, y + 1, 1, h - 2), color);
+        self.fill(rect(x + w - 1, y + 1, 1, h - 2), color);
+    }
+
+    /// Write a number on a dark background
+    fn label(&mut self, x: f32, y: f32, number: u64) {
+        let digits = number.to_string();
+        let advance = 4.0 * FONT_PIXEL;
+        let width = advance * digits.len() as f32 + FONT_PIXEL;
+        self.rects.push(OverlayRect {
+            rect: [x, y, width, 7.0 * FONT_PIXEL],
+            color: LABEL_BACKGROUND,
+        });
+        for (i, digit) in digits.bytes().enumerate() {
+            let glyph = DIGITS[(digit - b'0') as usize];
+            let left = x + FONT_PIXEL + advance * i as f32;
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) == 0 {
+                        continue;
+                    }
+                    self.rects.push(OverlayRect {
+                        rect: [
+                            left + column as f32 * FONT_PIXEL,
+                            y + (row + 1) as f32 * FONT_PIXEL,
+                            FONT_PIXEL,
+                            FONT_PIXEL,
+                        ],
+                        color: LABEL_TEXT,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Paint the overlay over premultiplied ARGB8888 pixels
+    ///
+    /// `scale` is the number of pixels per unit of window coordinates.
+    pub fn paint(&self, data: &mut [u8], width: u32, height: u32, stride: u32, scale: f32) {
+        for overlay in &self.rects {
+            let [x, y, w, h] = overlay.rect.map(|v| v * scale);
+            let columns = (x.round().max(0.0) as u32).min(width)
+                ..((x + w).round().max(0.0) as u32).min(width);
+            let rows = (y.round().max(0.0) as u32).min(height)
+                ..((y + h).round().max(0.0) as u32).min(height);
+            let [r, g, b, a] = overlay.color;
+            let source = [b, g, r, a].map(|c| (c * 255.0).round() as u32);
+            let keep = 256 - (a * 256.0) as u32;
+            for py in rows {
+                let start = py as usize * stride as usize;
+                let Some(row) = data.get_mut(start..start + width as usize * 4) else {
+                    break;
+                };
+                for px in columns.clone() {
+                    let pixel = &mut row[px as usize * 4..px as usize * 4 + 4];
+                    // Source over, premultiplied
+                    for (channel, source) in pixel.iter_mut().zip(source) {
+                        *channel = (source + ((*channel as u32 * keep) >> 8)).min(255) as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn rect(x: i32, y: i32, width: i32, height: i32) -> DamageRect {
+    DamageRect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+fn offset(r: DamageRect, x: i32, y: i32) -> DamageRect {
+    rect(r.x + x, r.y + y, r.width, r.height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::surface::BufferInfo;
+    use crate::compositor::Region;
+
+    fn buffer(width: u32, height: u32) -> Option<BufferInfo> {
+        Some(BufferInfo {
+            width,
+            height,
+            stride: width * 4,
+            format: 0,
+            offset: 0,
+            shm_buffer_id: None,
+            solid: None,
+            snapshot: None,
+        })
+    }
+
+    #[test]
+    fn test_window_overlay() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let child = state.surfaces.create_surface();
+        state.surfaces.create_subsurface(child, root).unwrap();
+        state.surfaces.set_subsurface_sync(child, false);
+        state.surfaces.set_subsurface_position(child, 10, 10);
+        let window = state.windows.create_window(root);
+
+        let surface = state.surfaces.get_mut(root).unwrap();
+        surface.set_scale(2);
+        surface.attach(buffer(200, 100));
+        surface.damage_buffer(0, 0, 20, 20);
+        state.surfaces.commit(root);
+        let surface = state.surfaces.get_mut(child).unwrap();
+        surface.attach(buffer(30, 30));
+        let mut region = Region::default();
+        region.add(rect(0, 0, 15, 30));
+        surface.pending.input_region = Some(Some(region));
+        state.surfaces.commit(child);
+
+        let overlay = DiagnosticOverlay::for_window(&state, window);
+        let with = |color| -> Vec<[f32; 4]> {
+            overlay
+                .rects
+                .iter()
+                .filter(|r| r.color == color)
+                .map(|r| r.rect)
+                .collect()
+        };
+        // Buffer damage shows in surface coordinates
+        assert!(with(DAMAGE).contains(&[0.0, 0.0, 10.0, 10.0]));
+        // Both surfaces, the input region and no geometry are outlined
+        assert!(with(SURFACE).contains(&[0.0, 0.0, 100.0, 1.0]));
+        assert!(with(SUBSURFACE).contains(&[10.0, 10.0, 30.0, 1.0]));
+        assert!(with(INPUT_REGION).contains(&[10.0, 10.0, 15.0, 1.0]));
+        assert!(with(GEOMETRY).is_empty());
+        assert_eq!(with(LABEL_BACKGROUND).len(), 2);
+        assert_eq!(overlay.rects.last().unwrap().color, LABEL_TEXT);
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut overlay = DiagnosticOverlay::default();
+        overlay.fill(rect(1, 0, 1, 1), [1.0, 0.0, 0.0, 1.0]);
+        overlay.fill(rect(0, 0, 1, 1), [0.0, 0.0, 0.5, 0.5]);
+
+        // At 2x each unit is two pixels wide
+        let (width, height) = (4, 2);
+        let mut data = vec![0u8; width * height * 4];
+        overlay.paint(
+            &mut data,
+            width as u32,
+            height as u32,
+            width as u32 * 4,
+            2.0,
+        );
+        assert_eq!(&data[8..12], [0, 0, 255, 255]);
+        assert_eq!(&data[16 + 12..16 + 16], [0, 0, 255, 255]);
+        assert_eq!(&data[0..4], [128, 0, 0, 128]);
+    }
+}
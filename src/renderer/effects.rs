@@ -0,0 +1,224 @@
+//! Visual effects on composed window content
+//!
+//! Effects are applied to a window's premultiplied 32-bit pixels after
+//! zoom, so native windows and headless outputs show them the same way.
+//! An unresponsive window fades to a lower opacity and shows a spinner in
+//! its center until its client answers a ping.
+//!
+//! Color filters are applied before the other effects, to each surface of
+//! the windows a rule sets them for.
+
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+use crate::compositor::ColorFilter;
+
+/// Opacity an unresponsive window fades to
+pub const UNRESPONSIVE_OPACITY: f64 = 0.6;
+
+/// Time the fade takes
+pub const FADE_DURATION: Duration = Duration::from_millis(400);
+
+/// Time between frames of the fade
+const FADE_FRAME: Duration = Duration::from_millis(16);
+
+/// Dots around the spinner, one of which is lit at a time
+const SPINNER_DOTS: u32 = 12;
+
+/// Time the spinner takes for a turn
+const SPINNER_PERIOD: Duration = Duration::from_secs(1);
+
+/// Dim a window with a spinner, for clients that missed a ping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unresponsive {
+    /// Time since the client became unresponsive
+    pub elapsed: Duration,
+}
+
+impl Unresponsive {
+    /// Create the effect at a point of its animation
+    pub fn new(elapsed: Duration) -> Self {
+        Self { elapsed }
+    }
+
+    /// Opacity of the content, easing out from opaque
+    pub fn opacity(&self) -> f64 {
+        let t = (self.elapsed.as_secs_f64() / FADE_DURATION.as_secs_f64()).min(1.0);
+        let eased = 1.0 - (1.0 - t) * (1.0 - t);
+        1.0 - (1.0 - UNRESPONSIVE_OPACITY) * eased
+    }
+
+    /// Number of the animation frame, which changes whenever the picture
+    /// does
+    pub fn frame(&self) -> u64 {
+        let fade_frames = (FADE_DURATION.as_millis() / FADE_FRAME.as_millis()) as u64;
+        match self.elapsed.checked_sub(FADE_DURATION) {
+            None => (self.elapsed.as_millis() / FADE_FRAME.as_millis()) as u64,
+            Some(spinning) => {
+                let step = SPINNER_PERIOD.as_millis() / SPINNER_DOTS as u128;
+                fade_frames + 1 + (spinning.as_millis() / step) as u64
+            }
+        }
+    }
+
+    /// Dot of the spinner that is lit
+    fn head(&self) -> u32 {
+        let turns = self.elapsed.as_millis() * SPINNER_DOTS as u128 / SPINNER_PERIOD.as_millis();
+        (turns % SPINNER_DOTS as u128) as u32
+    }
+
+    /// Apply the effect to premultiplied 32-bit pixels
+    pub fn apply(&self, data: &mut [u8], width: u32, height: u32, stride: u32) {
+        let scale = (self.opacity() * 256.0) as u32;
+        for y in 0..height as usize {
+            let start = y * stride as usize;
+            let Some(row) = data.get_mut(start..start + width as usize * 4) else {
+                break;
+            };
+            for byte in row {
+                *byte = ((*byte as u32 * scale) >> 8) as u8;
+            }
+        }
+        self.draw_spinner(data, width, height, stride);
+    }
+
+    /// Draw the spinner in the center, the dots behind the lit one fading
+    fn draw_spinner(&self, data: &mut [u8], width: u32, height: u32, stride: u32) {
+        let radius = (width.min(height) as f64 / 16.0).clamp(6.0, 24.0);
+        let dot = (radius / 4.0).max(1.5);
+        if (width as f64) < 2.0 * (radius + dot) || (height as f64) < 2.0 * (radius + dot) {
+            return;
+        }
+        let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+        let head = self.head();
+
+        for i in 0..SPINNER_DOTS {
+            let age = (head + SPINNER_DOTS - i) % SPINNER_DOTS;
+            let alpha = 0.9 * (1.0 - age as f64 / SPINNER_DOTS as f64);
+            let angle = i as f64 * TAU / SPINNER_DOTS as f64 - TAU / 4.0;
+            let (x, y) = (cx + radius * angle.cos(), cy + radius * angle.sin());
+
+            let rows = (y - dot).floor().max(0.0) as u32..(y + dot).ceil() as u32;
+            for py in rows {
+                for px in (x - dot).floor().max(0.0) as u32..(x + dot).ceil() as u32 {
+                    let (dx, dy) = (px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                    if dx * dx + dy * dy > dot * dot {
+                        continue;
+                    }
+                    let offset = py as usize * stride as usize + px as usize * 4;
+                    if let Some(pixel) = data.get_mut(offset..offset + 4) {
+                        // White source over, premultiplied
+                        let source = (255.0 * alpha) as u32;
+                        let keep = 256 - (alpha * 256.0) as u32;
+                        for channel in pixel {
+                            *channel = (source + ((*channel as u32 * keep) >> 8)).min(255) as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Apply a color filter to one premultiplied ARGB8888 pixel
+///
+/// The alpha byte of `opaque` pixels is undefined, and counts as opaque.
+pub fn filter_pixel(filter: ColorFilter, pixel: &mut [u8], opaque: bool) {
+    let alpha = if opaque { 255 } else { pixel[3] as i32 };
+    let [b, g, r] = [pixel[0], pixel[1], pixel[2]].map(|c| (c as i32).min(alpha));
+    let filtered = match filter {
+        ColorFilter::Invert => [alpha - b, alpha - g, alpha - r],
+        ColorFilter::Dark => {
+            // Moving every channel by the same amount keeps the hue, and
+            // mirrors the lightness around the middle
+            let shift = alpha - 2 * (b + g + r) / 3;
+            [b + shift, g + shift, r + shift]
+        }
+    };
+    for (channel, value) in pixel.iter_mut().zip(filtered) {
+        *channel = value.clamp(0, alpha) as u8;
+    }
+}
+
+/// Apply a color filter to premultiplied ARGB8888 pixels
+pub fn apply_color_filter(
+    filter: ColorFilter,
+    data: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    opaque: bool,
+) {
+    for y in 0..height as usize {
+        let start = y * stride as usize;
+        let Some(row) = data.get_mut(start..start + width as usize * 4) else {
+            break;
+        };
+        for pixel in row.chunks_exact_mut(4) {
+            filter_pixel(filter, pixel, opaque);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unresponsive_fades_and_spins() {
+        let start = Unresponsive::new(Duration::ZERO);
+        let faded = Unresponsive::new(FADE_DURATION * 2);
+        assert_eq!(start.opacity(), 1.0);
+        assert!((faded.opacity() - UNRESPONSIVE_OPACITY).abs() < 1e-9);
+        assert!(start.frame() < faded.frame());
+        assert_eq!(faded.frame(), Unresponsive::new(FADE_DURATION * 2).frame());
+        assert_ne!(
+            faded.frame(),
+            Unresponsive::new(FADE_DURATION * 2 + SPINNER_PERIOD / 2).frame()
+        );
+
+        // Content is dimmed, with the spinner drawn over the center
+        let (width, height) = (64, 64);
+        let mut data = vec![200u8; (width * height * 4) as usize];
+        faded.apply(&mut data, width, height, width * 4);
+        assert_eq!(data[0] as u32, (200 * 153) >> 8);
+        let lit = data.iter().step_by(4).filter(|&&b| b > 200).count();
+        assert!(lit > 0);
+    }
+
+    #[test]
+    fn test_color_filters() {
+        let filtered = |filter, pixel: [u8; 4], opaque| {
+            let mut pixel = pixel;
+            filter_pixel(filter, &mut pixel, opaque);
+            pixel
+        };
+        // White turns black and black white, for both filters
+        for filter in [ColorFilter::Invert, ColorFilter::Dark] {
+            assert_eq!(filtered(filter, [255; 4], false), [0, 0, 0, 255]);
+            assert_eq!(
+                filtered(filter, [0, 0, 0, 0xff], true),
+                [255, 255, 255, 0xff]
+            );
+        }
+        // Half-transparent black stays premultiplied
+        assert_eq!(
+            filtered(ColorFilter::Invert, [0, 0, 0, 128], false),
+            [128, 128, 128, 128]
+        );
+
+        // Inverting turns red cyan, the dark filter keeps it red
+        assert_eq!(
+            filtered(ColorFilter::Invert, [0, 0, 255, 255], false),
+            [255, 255, 0, 255]
+        );
+        assert_eq!(
+            filtered(ColorFilter::Dark, [0, 0, 255, 255], false),
+            [85, 85, 255, 255]
+        );
+
+        let mut data = vec![255u8; 4 * 4];
+        apply_color_filter(ColorFilter::Invert, &mut data, 2, 2, 8, false);
+        assert!(data.chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+    }
+}
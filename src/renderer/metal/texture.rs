@@ -8,6 +8,7 @@ use objc2::rc::Retained;
 use objc2::runtime::ProtocolObject;
 use objc2_metal::{MTLDevice, MTLPixelFormat, MTLTexture, MTLTextureDescriptor, MTLTextureUsage};
 
+use crate::compositor::surface::DamageRect;
 use crate::compositor::SurfaceId;
 use crate::protocol::shm::ShmFormat;
 
@@ -114,6 +115,76 @@ impl TextureManager {
         Ok(())
     }
 
+    /// Update only the damaged parts of a surface's texture
+    ///
+    /// `damage` is in buffer coordinates. Falls back to a full upload when
+    /// the texture has to be recreated.
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_damage(
+        &mut self,
+        device: &MetalDevice,
+        surface_id: SurfaceId,
+        width: u32,
+        height: u32,
+        stride: u32,
+        format: ShmFormat,
+        data: &[u8],
+        damage: &[DamageRect],
+    ) -> anyhow::Result<()> {
+        let texture = match self.textures.get(&surface_id) {
+            Some(entry)
+                if entry.width == width
+                    && entry.height == height
+                    && entry.format == format
+                    && !damage.is_empty() =>
+            {
+                entry.texture.clone()
+            }
+            _ => {
+                return self.upload_texture(device, surface_id, width, height, stride, format, data)
+            }
+        };
+
+        let bpp = format.bytes_per_pixel() as usize;
+        for rect in damage {
+            // Damage is clipped to the buffer on commit, but don't trust it here
+            let x = rect.x.clamp(0, width as i32) as u32;
+            let y = rect.y.clamp(0, height as i32) as u32;
+            let right = (rect.x.saturating_add(rect.width)).clamp(0, width as i32) as u32;
+            let bottom = (rect.y.saturating_add(rect.height)).clamp(0, height as i32) as u32;
+            if right <= x || bottom <= y {
+                continue;
+            }
+            let offset = y as usize * stride as usize + x as usize * bpp;
+            let Some(pixels) = data.get(offset..) else {
+                continue;
+            };
+            let region = objc2_metal::MTLRegion {
+                origin: objc2_metal::MTLOrigin {
+                    x: x as usize,
+                    y: y as usize,
+                    z: 0,
+                },
+                size: objc2_metal::MTLSize {
+                    width: (right - x) as usize,
+                    height: (bottom - y) as usize,
+                    depth: 1,
+                },
+            };
+            let bytes_ptr = NonNull::new(pixels.as_ptr() as *mut std::ffi::c_void)
+                .expect("data pointer should not be null");
+            unsafe {
+                texture.replaceRegion_mipmapLevel_withBytes_bytesPerRow(
+                    region,
+                    0,
+                    bytes_ptr,
+                    stride as usize,
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Get a texture for a surface
     pub fn get(&self, surface_id: SurfaceId) -> Option<&ProtocolObject<dyn MTLTexture>> {
         self.textures.get(&surface_id).map(|e| e.texture.as_ref())
@@ -129,7 +200,7 @@ impl TextureManager {
         match format {
             ShmFormat::Argb8888 => MTLPixelFormat::BGRA8Unorm,
             ShmFormat::Xrgb8888 => MTLPixelFormat::BGRA8Unorm,
-            ShmFormat::Other(_) => MTLPixelFormat::BGRA8Unorm, // Default
+            _ => MTLPixelFormat::BGRA8Unorm, // Default
         }
     }
 
@@ -1,7 +1,27 @@
 //! Rendering module
 //!
 //! This module handles rendering using Metal on macOS.
-//! It includes texture management, shader pipelines, and surface composition.
+//! It includes texture management, shader pipelines, and surface composition,
+//! plus platform-independent scene rendering for window captures, frame
+//! scheduling, buffer uploads on worker threads, window zoom and effects,
+//! and diagnostic overlays.
+
+pub mod convert;
+pub mod diagnostics;
+pub mod effects;
+pub mod scene;
+pub mod scheduler;
+pub mod upload;
+pub mod zoom;
+
+pub use diagnostics::{DiagnosticOverlay, OverlayRect};
+pub use effects::{apply_color_filter, Unresponsive};
+pub use scene::{place_tree, Capture, CaptureError, Placement, Scene};
+pub use scheduler::{
+    ContentType, FrameScheduler, FrameStats, LatencyStats, LayerOptions, PresentFeedback,
+    RefreshPolicy,
+};
+pub use upload::{Layer, Pixels, Upload, UploadJob, UploadPool};
 
 #[cfg(target_os = "macos")]
 pub mod metal;
@@ -0,0 +1,904 @@
+//! Scene-level rendering of a single window or an output
+//!
+//! Builds the surface tree of one window (toplevel, subsurfaces and
+//! optionally the cursor) and composes it into an offscreen image. Unlike
+//! grabbing the screen, this never picks up windows that overlap the one
+//! being captured. Outputs are composed the same way from the Wayland
+//! windows on them, at their positions on the output, with zoomed
+//! windows magnified and unresponsive ones dimmed as they would be on
+//! screen. Color filters set by window rules are applied per node while
+//! painting.
+//!
+//! The content of native windows is composed the same way, from the
+//! [`Placement`]s of their surface trees, on the upload workers.
+
+use std::collections::HashMap;
+
+use crate::compositor::surface::BufferInfo;
+use crate::compositor::{
+    ColorFilter, CompositorState, OutputId, SurfaceId, SurfaceManager, Window, WindowId,
+};
+use crate::protocol::shm::ShmFormat;
+
+use super::effects::filter_pixel;
+use super::{convert, zoom, Unresponsive};
+
+/// The whole buffer, as a crop
+pub const FULL_CROP: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Where a surface of a tree is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// Surface drawn
+    pub surface: SurfaceId,
+    /// Position relative to the tree's origin, in pixels
+    pub x: i32,
+    pub y: i32,
+    /// Size drawn, in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Part of the buffer shown (left, top, right, bottom), from the
+    /// viewport source
+    pub crop: [f32; 4],
+}
+
+/// Place the surfaces of a tree that have a buffer, bottom first
+///
+/// Subsurfaces below their parent come before it and the ones above, and
+/// popups, after it. Surface coordinates are multiplied by `scale` to get
+/// pixels, and sizes follow the surfaces' viewports.
+pub fn place_tree(
+    surfaces: &SurfaceManager,
+    root: SurfaceId,
+    origin: (i32, i32),
+    scale: i32,
+) -> Vec<Placement> {
+    let scale = scale.max(1);
+    surfaces
+        .stacking_order(root, origin)
+        .into_iter()
+        .filter_map(|(surface, (x, y))| {
+            let current = surfaces.get(surface)?;
+            let (width, height) = current.size()?;
+            Some(Placement {
+                surface,
+                x: x * scale,
+                y: y * scale,
+                width: (width * scale).max(0) as u32,
+                height: (height * scale).max(0) as u32,
+                crop: current.buffer_geometry().source_crop(),
+            })
+        })
+        .collect()
+}
+
+/// A surface placed in the scene
+#[derive(Debug, Clone)]
+pub struct SceneNode {
+    /// Surface this node was built from
+    pub surface: SurfaceId,
+    /// Position relative to the window origin
+    pub x: i32,
+    pub y: i32,
+    /// Size in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub stride: u32,
+    /// Whether the alpha channel should be ignored
+    pub opaque: bool,
+    /// Color filter applied while painting
+    pub filter: Option<ColorFilter>,
+    /// Premultiplied ARGB8888 pixel data
+    pub data: Vec<u8>,
+}
+
+impl SceneNode {
+    /// A node of a buffer's pixels, at the origin until placed
+    fn from_buffer(surface: SurfaceId, info: &BufferInfo, data: Vec<u8>) -> Option<Self> {
+        // Stored stride is validated when the buffer is created, but the
+        // data may have been read from a pool that shrank since.
+        if data.len() < (info.stride * info.height) as usize {
+            return None;
+        }
+        let format = ShmFormat::from_wayland(info.format);
+        let (data, stride) =
+            convert::into_argb8888(format, data, info.width, info.height, info.stride)?;
+        Some(Self {
+            surface,
+            x: 0,
+            y: 0,
+            width: info.width,
+            height: info.height,
+            stride,
+            opaque: format.is_opaque(),
+            filter: None,
+            data,
+        })
+    }
+
+    /// Move the node to its placement, stretching the cropped part of its
+    /// pixels over the placement's size as a viewport does
+    ///
+    /// Pixels are sampled at their nearest neighbour.
+    pub fn place(self, placement: &Placement) -> Self {
+        let (width, height) = (placement.width, placement.height);
+        let unchanged = placement.crop == FULL_CROP && (width, height) == (self.width, self.height);
+        if unchanged || self.width == 0 || self.height == 0 {
+            return Self {
+                x: placement.x,
+                y: placement.y,
+                ..self
+            };
+        }
+
+        let [left, top, right, bottom] = placement.crop;
+        let sample = |start: f32, end: f32, size: u32, count: u32, i: u32| {
+            let position = (start + (end - start) * (i as f32 + 0.5) / count as f32) * size as f32;
+            (position as u32).min(size - 1) as usize
+        };
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            let row = sample(top, bottom, self.height, height, y) * self.stride as usize;
+            for x in 0..width {
+                let src = row + sample(left, right, self.width, width, x) * 4;
+                data.extend_from_slice(&self.data[src..src + 4]);
+            }
+        }
+        Self {
+            x: placement.x,
+            y: placement.y,
+            width,
+            height,
+            stride: width * 4,
+            data,
+            ..self
+        }
+    }
+
+    /// A node of one premultiplied ARGB8888 pixel, stretched once placed
+    fn solid(surface: SurfaceId, pixel: [u8; 4]) -> Self {
+        Self {
+            surface,
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+            stride: 4,
+            opaque: false,
+            filter: None,
+            data: pixel.to_vec(),
+        }
+    }
+}
+
+/// Surfaces of one window in paint order (bottom first)
+#[derive(Debug, Clone)]
+pub struct Scene {
+    /// Width of the captured area
+    pub width: u32,
+    /// Height of the captured area
+    pub height: u32,
+    /// Nodes to paint
+    pub nodes: Vec<SceneNode>,
+}
+
+impl Scene {
+    /// Build the scene of a window
+    ///
+    /// `read` returns the pixel data of a committed buffer. The cursor is
+    /// only included when requested and the pointer is over the window.
+    pub fn for_window(
+        compositor: &CompositorState,
+        window_id: WindowId,
+        include_cursor: bool,
+        mut read: impl FnMut(&BufferInfo) -> Option<Vec<u8>>,
+    ) -> Result<Self, CaptureError> {
+        let window = compositor
+            .windows
+            .get(window_id)
+            .ok_or(CaptureError::NoSuchWindow(window_id.0))?;
+        let root = compositor
+            .surfaces
+            .get(window.surface_id)
+            .filter(|s| s.buffer.is_some())
+            .ok_or(CaptureError::NoBuffer(window_id.0))?;
+        // The window is drawn in the pixels of its root surface's buffer,
+        // and is as large as the root's viewport makes it
+        let scale = root.scale.max(1);
+        let (width, height) = root
+            .size()
+            .map(|(w, h)| ((w * scale).max(1) as u32, (h * scale).max(1) as u32))
+            .unwrap_or((1, 1));
+
+        let mut scene = Self {
+            width,
+            height,
+            nodes: Vec::new(),
+        };
+        let mut origins = HashMap::new();
+        scene.add_tree(
+            compositor,
+            window.surface_id,
+            (0, 0),
+            scale,
+            window.color_filter,
+            &mut origins,
+            &mut read,
+        );
+        if scene.nodes.is_empty() {
+            return Err(CaptureError::NoBuffer(window_id.0));
+        }
+
+        if include_cursor {
+            scene.add_cursor(compositor, scale, &origins, &mut read);
+        }
+
+        Ok(scene)
+    }
+
+    /// Build the scene of an output
+    ///
+    /// Windows on the output are stacked in the window stacking order, at
+    /// their origin in `positions` (relative to the output, in surface
+    /// coordinates), or at the output's origin if they have none, as on
+    /// headless outputs. Windows below one that covers the output with
+    /// opaque content are left out. Areas no window covers stay
+    /// transparent.
+    pub fn for_output(
+        compositor: &CompositorState,
+        output_id: OutputId,
+        positions: &HashMap<WindowId, (i32, i32)>,
+        include_cursor: bool,
+        mut read: impl FnMut(&BufferInfo) -> Option<Vec<u8>>,
+    ) -> Result<Self, CaptureError> {
+        let output = compositor
+            .outputs
+            .get(output_id)
+            .ok_or(CaptureError::NoSuchOutput(output_id.0))?;
+
+        let mut scene = Self {
+            width: output.width(),
+            height: output.height(),
+            nodes: Vec::new(),
+        };
+        let scale = output.scale.max(1);
+        let mut origins = HashMap::new();
+        let windows = compositor.windows.on_output(output_id);
+        let position = |window: &Window| positions.get(&window.id).copied().unwrap_or((0, 0));
+        let visible = windows
+            .iter()
+            .rposition(|window| {
+                position(window) == (0, 0) && scene.is_covered_by(compositor, window)
+            })
+            .unwrap_or(0);
+        for window in &windows[visible..] {
+            let origin = position(window);
+            if window.zoom.is_zoomed() || window.unresponsive_since.is_some() {
+                scene.add_composed(compositor, window, origin, scale, include_cursor, &mut read);
+                continue;
+            }
+            scene.add_tree(
+                compositor,
+                window.surface_id,
+                origin,
+                scale,
+                window.color_filter,
+                &mut origins,
+                &mut read,
+            );
+        }
+
+        if include_cursor {
+            scene.add_cursor(compositor, scale, &origins, &mut read);
+        }
+
+        Ok(scene)
+    }
+
+    /// Check if a window hides everything below it: its root surface is
+    /// opaque and at least as large as the scene
+    fn is_covered_by(&self, compositor: &CompositorState, window: &Window) -> bool {
+        if window.zoom.is_zoomed() || window.unresponsive_since.is_some() {
+            return false;
+        }
+        let Some(surface) = compositor.surfaces.get(window.surface_id) else {
+            return false;
+        };
+        let Some(info) = surface.buffer.as_ref() else {
+            return false;
+        };
+        let (width, height, opaque) = match (info.solid_pixel(), surface.size()) {
+            (Some(pixel), Some((w, h))) => (w.max(0) as u32, h.max(0) as u32, pixel[3] == 255),
+            _ => (
+                info.width,
+                info.height,
+                ShmFormat::from_wayland(info.format).is_opaque(),
+            ),
+        };
+        opaque && width >= self.width && height >= self.height
+    }
+
+    /// Add a zoomed or unresponsive window as one node
+    ///
+    /// The window is composed on its own, with the cursor if requested, its
+    /// visible part stretched over its size and its effect applied.
+    fn add_composed(
+        &mut self,
+        compositor: &CompositorState,
+        window: &Window,
+        origin: (i32, i32),
+        scale: i32,
+        include_cursor: bool,
+        read: &mut impl FnMut(&BufferInfo) -> Option<Vec<u8>>,
+    ) {
+        let Some((width, height)) = compositor
+            .surfaces
+            .get(window.surface_id)
+            .and_then(|surface| surface.size())
+            .map(|(w, h)| ((w * scale).max(1) as u32, (h * scale).max(1) as u32))
+        else {
+            return;
+        };
+
+        let mut zoomed = Self {
+            width,
+            height,
+            nodes: Vec::new(),
+        };
+        let mut origins = HashMap::new();
+        zoomed.add_tree(
+            compositor,
+            window.surface_id,
+            (0, 0),
+            scale,
+            window.color_filter,
+            &mut origins,
+            read,
+        );
+        if include_cursor {
+            zoomed.add_cursor(compositor, scale, &origins, read);
+        }
+        let capture = zoomed.render();
+        let mut data = if window.zoom.is_zoomed() {
+            zoom::magnify(&capture.data, width, height, capture.stride(), &window.zoom)
+        } else {
+            capture.data
+        };
+        if let Some(since) = window.unresponsive_since {
+            Unresponsive::new(since.elapsed()).apply(&mut data, width, height, width * 4);
+        }
+        self.nodes.push(SceneNode {
+            surface: window.surface_id,
+            x: origin.0 * scale,
+            y: origin.1 * scale,
+            width,
+            height,
+            stride: width * 4,
+            opaque: false,
+            filter: None,
+            data,
+        });
+    }
+
+    /// Add the cursor if the pointer is over one of the scene's surfaces
+    ///
+    /// `origins` are in surface coordinates, which `scale` turns into the
+    /// scene's pixels.
+    fn add_cursor(
+        &mut self,
+        compositor: &CompositorState,
+        scale: i32,
+        origins: &HashMap<SurfaceId, (i32, i32)>,
+        read: &mut impl FnMut(&BufferInfo) -> Option<Vec<u8>>,
+    ) {
+        let pointer = compositor.seat.pointer();
+        let cursor = pointer.cursor().and_then(|cursor| {
+            let focus = pointer.focus()?;
+            let origin = origins.get(&focus)?;
+            let info = compositor.surfaces.get(cursor)?.buffer.as_ref()?;
+            let (px, py) = pointer.position();
+            let (hx, hy) = pointer.cursor_hotspot();
+            let x = (origin.0 + px as i32 - hx) * scale;
+            let y = (origin.1 + py as i32 - hy) * scale;
+            let node = SceneNode::from_buffer(cursor, info, read(info)?)?;
+            Some(SceneNode { x, y, ..node })
+        });
+        self.nodes.extend(cursor);
+    }
+
+    /// Add a surface and its subsurfaces in stacking order, with the color
+    /// filter of their window
+    ///
+    /// Surfaces are scaled by `scale` and cropped and stretched by their
+    /// viewports. Their origins are recorded in surface coordinates.
+    #[allow(clippy::too_many_arguments)]
+    fn add_tree(
+        &mut self,
+        compositor: &CompositorState,
+        surface_id: SurfaceId,
+        origin: (i32, i32),
+        scale: i32,
+        filter: Option<ColorFilter>,
+        origins: &mut HashMap<SurfaceId, (i32, i32)>,
+        read: &mut impl FnMut(&BufferInfo) -> Option<Vec<u8>>,
+    ) {
+        origins.extend(compositor.surfaces.stacking_order(surface_id, origin));
+        for placement in place_tree(&compositor.surfaces, surface_id, origin, scale) {
+            let id = placement.surface;
+            let Some(info) = compositor
+                .surfaces
+                .get(id)
+                .and_then(|surface| surface.buffer.as_ref())
+            else {
+                continue;
+            };
+            // Single-pixel buffers are stretched over the whole surface
+            let node = match info.solid_pixel() {
+                Some(pixel) => Some(SceneNode::solid(id, pixel)),
+                None => read(info).and_then(|data| SceneNode::from_buffer(id, info, data)),
+            };
+            if let Some(node) = node {
+                self.nodes.push(SceneNode {
+                    filter,
+                    ..node.place(&placement)
+                });
+            }
+        }
+    }
+
+    /// Compose the scene into an offscreen image
+    pub fn render(&self) -> Capture {
+        let mut capture = Capture::new(self.width, self.height);
+        for node in &self.nodes {
+            capture.blend(node);
+        }
+        capture
+    }
+}
+
+/// An offscreen image in premultiplied ARGB8888 with a tight stride
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Capture {
+    /// Create a transparent image
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    /// Bytes per row
+    pub fn stride(&self) -> u32 {
+        self.width * 4
+    }
+
+    /// Paint a node over the image, clipped to its bounds
+    fn blend(&mut self, node: &SceneNode) {
+        let x0 = node.x.max(0);
+        let y0 = node.y.max(0);
+        let x1 = (node.x + node.width as i32).min(self.width as i32);
+        let y1 = (node.y + node.height as i32).min(self.height as i32);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        let stride = self.stride() as usize;
+        for y in y0..y1 {
+            let src_row = (y - node.y) as usize * node.stride as usize;
+            for x in x0..x1 {
+                let src = src_row + (x - node.x) as usize * 4;
+                let dst = y as usize * stride + x as usize * 4;
+                let mut pixel = [0; 4];
+                pixel.copy_from_slice(&node.data[src..src + 4]);
+                if let Some(filter) = node.filter {
+                    filter_pixel(filter, &mut pixel, node.opaque);
+                }
+                let alpha = if node.opaque { 255 } else { pixel[3] as u32 };
+
+                // Source over with premultiplied alpha
+                for (c, &value) in pixel[..3].iter().enumerate() {
+                    let below = self.data[dst + c] as u32 * (255 - alpha) / 255;
+                    self.data[dst + c] = (value as u32 + below).min(255) as u8;
+                }
+                let below = self.data[dst + 3] as u32 * (255 - alpha) / 255;
+                self.data[dst + 3] = (alpha + below).min(255) as u8;
+            }
+        }
+    }
+
+    /// Encode the image as PNG
+    pub fn to_png(&self) -> Result<Vec<u8>, CaptureError> {
+        // ARGB8888 is little-endian BGRA in memory; PNG wants straight RGBA
+        let mut rgba = Vec::with_capacity(self.data.len());
+        for pixel in self.data.chunks_exact(4) {
+            let alpha = pixel[3] as u32;
+            let unpremultiply = |c: u8| match alpha {
+                0 => 0,
+                _ => (c as u32 * 255 / alpha).min(255) as u8,
+            };
+            rgba.extend_from_slice(&[
+                unpremultiply(pixel[2]),
+                unpremultiply(pixel[1]),
+                unpremultiply(pixel[0]),
+                pixel[3],
+            ]);
+        }
+
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| CaptureError::Encode(e.to_string()))?;
+        writer
+            .write_image_data(&rgba)
+            .map_err(|e| CaptureError::Encode(e.to_string()))?;
+        writer
+            .finish()
+            .map_err(|e| CaptureError::Encode(e.to_string()))?;
+        Ok(png)
+    }
+}
+
+/// Capture errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CaptureError {
+    #[error("No window with id {0}")]
+    NoSuchWindow(u64),
+    #[error("Window {0} has no buffer to capture")]
+    NoBuffer(u64),
+    #[error("No headless output with id {0}")]
+    NoSuchOutput(u64),
+    #[error("Failed to encode image: {0}")]
+    Encode(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn buffer(width: u32, height: u32, format: u32, id: u64) -> BufferInfo {
+        BufferInfo {
+            width,
+            height,
+            stride: width * 4,
+            format,
+            offset: 0,
+            shm_buffer_id: Some(id),
+            solid: None,
+            snapshot: None,
+        }
+    }
+
+    /// Fill a buffer with one premultiplied BGRA pixel value
+    fn solid(info: &BufferInfo, pixel: [u8; 4]) -> Vec<u8> {
+        pixel.repeat((info.width * info.height) as usize)
+    }
+
+    fn attach(state: &mut CompositorState, surface: SurfaceId, info: BufferInfo) {
+        let surface = state.surfaces.get_mut(surface).unwrap();
+        surface.attach(Some(info));
+        surface.commit();
+    }
+
+    #[test]
+    fn test_window_scene_excludes_other_windows() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let child = state.surfaces.create_surface();
+        let other = state.surfaces.create_surface();
+        attach(
+            &mut state,
+            root,
+            buffer(4, 4, ShmFormat::Xrgb8888.to_wayland(), 1),
+        );
+        attach(&mut state, child, buffer(2, 2, 0, 2));
+        attach(&mut state, other, buffer(4, 4, 0, 3));
+        state.surfaces.get_mut(root).unwrap().children.push(child);
+        state.surfaces.get_mut(child).unwrap().position = (2, 2);
+        let window = state.windows.create_window(root);
+        state.windows.create_window(other);
+
+        let scene = Scene::for_window(&state, window, false, |info| {
+            let pixel = match info.shm_buffer_id {
+                Some(1) => [0, 0, 255, 0],   // red, alpha ignored (XRGB)
+                Some(2) => [255, 0, 0, 255], // blue
+                _ => [0, 255, 0, 255],       // green, must not appear
+            };
+            Some(solid(info, pixel))
+        })
+        .unwrap();
+        assert_eq!(scene.nodes.len(), 2);
+
+        let capture = scene.render();
+        assert_eq!((capture.width, capture.height), (4, 4));
+        assert_eq!(&capture.data[0..4], &[0, 0, 255, 255]);
+        let inside_child = (3 * 4 + 3) * 4;
+        assert_eq!(
+            &capture.data[inside_child..inside_child + 4],
+            &[255, 0, 0, 255]
+        );
+        assert!(capture.data.chunks(4).all(|p| p[1] == 0));
+    }
+
+    #[test]
+    fn test_window_scene_from_shm_pool() {
+        use crate::protocol::shm::{ShmBufferId, WlShmHandler};
+        use std::os::unix::io::AsRawFd;
+
+        // An opaque red XRGB8888 root with an RGB565 blue child, whose rows
+        // are half as wide in bytes
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(72).unwrap();
+        let mut shm = WlShmHandler::new();
+        let pool = shm.create_pool(file.as_raw_fd(), 72);
+        let xrgb = ShmFormat::Xrgb8888.to_wayland();
+        let rgb565 = ShmFormat::Rgb565.to_wayland();
+        let root_buffer = shm.create_buffer(pool, 0, 4, 4, 16, xrgb).unwrap();
+        let child_buffer = shm.create_buffer(pool, 64, 2, 2, 4, rgb565).unwrap();
+        shm.write_buffer_data(root_buffer, &[0, 0, 255, 0].repeat(16))
+            .unwrap();
+        shm.write_buffer_data(child_buffer, &[0x1f, 0x00].repeat(4))
+            .unwrap();
+
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let child = state.surfaces.create_surface();
+        let mut root_info = buffer(4, 4, xrgb, root_buffer.0);
+        root_info.stride = 16;
+        let mut child_info = buffer(2, 2, rgb565, child_buffer.0);
+        child_info.stride = 4;
+        attach(&mut state, root, root_info);
+        attach(&mut state, child, child_info);
+        state.surfaces.get_mut(root).unwrap().children.push(child);
+        state.surfaces.get_mut(child).unwrap().position = (1, 1);
+        let window = state.windows.create_window(root);
+
+        let capture = Scene::for_window(&state, window, false, |info| {
+            shm.read_buffer_data(ShmBufferId(info.shm_buffer_id?)).ok()
+        })
+        .unwrap()
+        .render();
+        let pixel = |x: usize, y: usize| &capture.data[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), &[0, 0, 255, 255]);
+        assert_eq!(pixel(1, 1), &[255, 0, 0, 255]);
+        assert_eq!(pixel(2, 2), &[255, 0, 0, 255]);
+        assert_eq!(pixel(3, 3), &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_cursor_only_when_requested() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let cursor = state.surfaces.create_surface();
+        attach(
+            &mut state,
+            root,
+            buffer(8, 8, ShmFormat::Xrgb8888.to_wayland(), 1),
+        );
+        attach(&mut state, cursor, buffer(2, 2, 0, 2));
+        let window = state.windows.create_window(root);
+        let pointer = state.seat.pointer_mut();
+        pointer.set_focus(Some(root), 4.0, 4.0);
+        pointer.set_cursor(Some(cursor), 1, 1);
+
+        let read = |info: &BufferInfo| Some(solid(info, [255, 255, 255, 255]));
+        let scene = Scene::for_window(&state, window, false, read).unwrap();
+        assert_eq!(scene.nodes.len(), 1);
+
+        let scene = Scene::for_window(&state, window, true, read).unwrap();
+        let cursor_node = scene.nodes.last().unwrap();
+        assert_eq!(cursor_node.surface, cursor);
+        assert_eq!((cursor_node.x, cursor_node.y), (3, 3));
+    }
+
+    #[test]
+    fn test_output_scene() {
+        let mut state = CompositorState::new();
+        let screen = state.outputs.create_output(
+            "default".to_string(),
+            "Wayoa".to_string(),
+            "Test".to_string(),
+        );
+        let output = state
+            .outputs
+            .create_headless("virtual-0".to_string(), 8, 6, 1, 60_000);
+        let (a, b, elsewhere) = (
+            state.surfaces.create_surface(),
+            state.surfaces.create_surface(),
+            state.surfaces.create_surface(),
+        );
+        attach(&mut state, a, buffer(4, 4, 0, 1));
+        attach(&mut state, b, buffer(2, 2, 0, 2));
+        attach(&mut state, elsewhere, buffer(2, 2, 0, 3));
+        let window_a = state.windows.create_window(a);
+        let window_b = state.windows.create_window(b);
+        state.windows.create_window(elsewhere);
+        for window in [window_a, window_b] {
+            state.windows.get_mut(window).unwrap().output = Some(output);
+        }
+        state.windows.set_focused(Some(window_a));
+
+        let read = |info: &BufferInfo| Some(solid(info, [255, 255, 255, 255]));
+        let scene = Scene::for_output(&state, output, &HashMap::new(), false, read).unwrap();
+        assert_eq!((scene.width, scene.height), (8, 6));
+        let surfaces: Vec<_> = scene.nodes.iter().map(|n| n.surface).collect();
+        assert_eq!(surfaces, vec![b, a]);
+
+        // A zoomed window is one magnified node of its size
+        state
+            .windows
+            .get_mut(window_a)
+            .unwrap()
+            .zoom
+            .set_factor(2.0);
+        let scene = Scene::for_output(&state, output, &HashMap::new(), false, read).unwrap();
+        let zoomed = scene.nodes.last().unwrap();
+        assert_eq!((zoomed.surface, zoomed.width, zoomed.height), (a, 4, 4));
+
+        // So is an unresponsive one, dimmed
+        let window = state.windows.get_mut(window_b).unwrap();
+        window.unresponsive_since = Some(std::time::Instant::now() - Duration::from_secs(1));
+        let scene = Scene::for_output(&state, output, &HashMap::new(), false, read).unwrap();
+        let dimmed = &scene.nodes[0];
+        assert_eq!((dimmed.surface, dimmed.width, dimmed.height), (b, 2, 2));
+        assert!(dimmed.data[3] < 255);
+
+        // Windows on a screen are placed where they are on it
+        let window = state.windows.window_for_surface(elsewhere).unwrap();
+        state.windows.get_mut(window).unwrap().output = Some(screen);
+        let positions = HashMap::from([(window, (3, 2))]);
+        let scene = Scene::for_output(&state, screen, &positions, false, read).unwrap();
+        let scale = state.outputs.get(screen).unwrap().scale;
+        let placed = &scene.nodes[0];
+        assert_eq!(
+            (placed.surface, placed.x, placed.y),
+            (elsewhere, 3 * scale, 2 * scale)
+        );
+
+        assert!(matches!(
+            Scene::for_output(&state, OutputId(u64::MAX), &HashMap::new(), false, read),
+            Err(CaptureError::NoSuchOutput(_))
+        ));
+    }
+
+    #[test]
+    fn test_opaque_window_hides_windows_below() {
+        let mut state = CompositorState::new();
+        let output = state
+            .outputs
+            .create_headless("virtual-0".to_string(), 8, 6, 1, 60_000);
+        let (below, opaque, above) = (
+            state.surfaces.create_surface(),
+            state.surfaces.create_surface(),
+            state.surfaces.create_surface(),
+        );
+        attach(&mut state, below, buffer(8, 6, 0, 1));
+        // XRGB8888 covering the whole output
+        attach(&mut state, opaque, buffer(8, 6, 1, 2));
+        attach(&mut state, above, buffer(2, 2, 0, 3));
+        let windows = [below, opaque, above].map(|surface| state.windows.create_window(surface));
+        for window in windows {
+            state.windows.get_mut(window).unwrap().output = Some(output);
+        }
+
+        let read = |info: &BufferInfo| Some(solid(info, [255, 255, 255, 255]));
+        let scene = Scene::for_output(&state, output, &HashMap::new(), false, read).unwrap();
+        let surfaces: Vec<_> = scene.nodes.iter().map(|n| n.surface).collect();
+        assert_eq!(surfaces, vec![opaque, above]);
+
+        // Once lowered it hides nothing
+        state.windows.lower(windows[1]);
+        let scene = Scene::for_output(&state, output, &HashMap::new(), false, read).unwrap();
+        let surfaces: Vec<_> = scene.nodes.iter().map(|n| n.surface).collect();
+        assert_eq!(surfaces, vec![opaque, below, above]);
+    }
+
+    #[test]
+    fn test_capture_errors_and_png() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let window = state.windows.create_window(root);
+        assert_eq!(
+            Scene::for_window(&state, window, false, |_| None).unwrap_err(),
+            CaptureError::NoBuffer(window.0)
+        );
+        assert!(matches!(
+            Scene::for_window(&state, WindowId(u64::MAX), false, |_| None),
+            Err(CaptureError::NoSuchWindow(_))
+        ));
+
+        let png = Capture::new(2, 2).to_png().unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn test_viewport_crops_and_scales() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let surface = state.surfaces.get_mut(root).unwrap();
+        surface.attach(Some(buffer(4, 2, 0, 1)));
+        // The right half of the buffer, stretched to 4x4
+        surface.set_viewport(crate::compositor::Viewport {
+            source: Some(crate::compositor::ViewportSource {
+                x: 2.0,
+                y: 0.0,
+                width: 2.0,
+                height: 2.0,
+            }),
+            destination: Some((4, 4)),
+        });
+        surface.commit();
+        let window = state.windows.create_window(root);
+
+        // Columns are blue, green, red and white
+        let row: Vec<u8> = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255; 4],
+        ]
+        .concat();
+        let capture = Scene::for_window(&state, window, false, |_| Some(row.repeat(2)))
+            .unwrap()
+            .render();
+        assert_eq!((capture.width, capture.height), (4, 4));
+        for row in capture.data.chunks(16) {
+            assert_eq!(&row[..8], [0, 0, 255, 255].repeat(2));
+            assert_eq!(&row[8..], [255; 8]);
+        }
+    }
+
+    #[test]
+    fn test_single_pixel_buffer_fills_viewport() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        let surface = state.surfaces.get_mut(root).unwrap();
+        surface.attach(Some(BufferInfo::single_pixel([0.0, 0.0, 0.5, 0.5])));
+        surface.set_viewport(crate::compositor::Viewport {
+            source: None,
+            destination: Some((3, 2)),
+        });
+        surface.commit();
+        let window = state.windows.create_window(root);
+
+        // No pixel storage is read
+        let capture = Scene::for_window(&state, window, false, |_| None)
+            .unwrap()
+            .render();
+        assert_eq!((capture.width, capture.height), (3, 2));
+        assert!(capture.data.chunks(4).all(|p| p == [128, 0, 0, 128]));
+    }
+
+    #[test]
+    fn test_window_color_filter() {
+        let mut state = CompositorState::new();
+        let root = state.surfaces.create_surface();
+        attach(
+            &mut state,
+            root,
+            buffer(2, 2, ShmFormat::Xrgb8888.to_wayland(), 1),
+        );
+        let window = state.windows.create_window(root);
+        state.windows.get_mut(window).unwrap().color_filter = Some(ColorFilter::Invert);
+
+        // Red with an undefined alpha byte turns opaque cyan
+        let scene = Scene::for_window(&state, window, false, |info| {
+            Some(solid(info, [0, 0, 255, 0]))
+        })
+        .unwrap();
+        assert_eq!(scene.nodes[0].filter, Some(ColorFilter::Invert));
+        let capture = scene.render();
+        assert!(capture.data.chunks(4).all(|p| p == [255, 255, 0, 255]));
+    }
+}
@@ -0,0 +1,707 @@
+//! Frame scheduling
+//!
+//! Tracks the refresh cycle of each output and decides when frames are
+//! composed. A commit schedules its output for the next refresh; the frame
+//! is composed shortly before that refresh and its present is recorded,
+//! counting presents that came after their refresh as late and the refresh
+//! cycles they skipped as missed. Each present yields the timing needed for
+//! presentation-time feedback.
+//!
+//! The scheduler also bounds how many frames each window has in flight,
+//! committed and not yet presented. With one, a window's frame callbacks
+//! wait for its present and the client draws each frame against the latest
+//! input. With two or three, callbacks are answered right away while there
+//! is room, so throughput-bound clients can draw ahead at the cost of a
+//! frame or two of latency. The depth comes from window rules, else from
+//! the window's content type, and the commit-to-screen latency each window
+//! actually gets is measured.
+//!
+//! Outputs that refresh adaptively, like ProMotion displays, can drop the
+//! refresh grid. Under the adaptive policy a frame is shown as soon as it
+//! is composed, at most once per refresh period of the highest rate, which
+//! is what fullscreen games and clients accepting tearing ask for.
+//!
+//! The same hints pick how a window's CAMetalLayer presents: games and
+//! clients accepting tearing present without waiting for the display
+//! refresh, and windows drawing ahead get a third drawable.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::compositor::{Output, OutputId, WindowId};
+
+/// Refresh rate assumed when an output has no current mode (mHz)
+pub const DEFAULT_REFRESH_MHZ: u32 = 60_000;
+
+/// Frames a window may have in flight unless a rule or its content type
+/// says otherwise
+pub const DEFAULT_FRAMES_IN_FLIGHT: u32 = 1;
+
+/// Most frames a window may have in flight
+pub const MAX_FRAMES_IN_FLIGHT: u32 = 3;
+
+/// How long before a refresh composition starts
+const COMPOSE_MARGIN: Duration = Duration::from_millis(2);
+
+/// Get the refresh period of a refresh rate in mHz
+pub fn refresh_period(refresh_mhz: u32) -> Duration {
+    let refresh_mhz = match refresh_mhz {
+        0 => DEFAULT_REFRESH_MHZ,
+        refresh => refresh,
+    };
+    Duration::from_nanos(1_000_000_000_000 / refresh_mhz as u64)
+}
+
+/// Present statistics of an output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FrameStats {
+    /// Frames presented
+    pub presented: u64,
+    /// Frames presented after the refresh they were scheduled for
+    pub late: u64,
+    /// Refresh cycles skipped by late frames
+    pub missed: u64,
+    /// Longest delay past the scheduled refresh (microseconds)
+    pub worst_delay_us: u64,
+}
+
+/// What a window shows, as hinted by its client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentType {
+    /// No hint
+    #[default]
+    None,
+    /// Still images
+    Photo,
+    /// Video playback
+    Video,
+    /// Games and other interactive content
+    Game,
+}
+
+impl ContentType {
+    /// Frames in flight suited to the content, None to use the default
+    pub fn frames_in_flight(self) -> Option<u32> {
+        match self {
+            ContentType::Game => Some(1),
+            ContentType::Video => Some(3),
+            ContentType::None | ContentType::Photo => None,
+        }
+    }
+}
+
+/// How a window's CAMetalLayer presents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerOptions {
+    /// Drawables the layer keeps, 2 or 3
+    pub drawables: u32,
+    /// Whether presents wait for the display refresh
+    pub display_sync: bool,
+}
+
+/// How the refreshes of an output are timed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefreshPolicy {
+    /// Frames are shown on the fixed refresh grid
+    #[default]
+    Fixed,
+    /// Frames are shown when they are ready, at most once per refresh
+    /// period
+    Adaptive,
+}
+
+/// Latency statistics of a window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencyStats {
+    /// Frames presented
+    pub presented: u64,
+    /// Frames replaced by a newer commit before they were presented
+    pub dropped: u64,
+    /// Commit-to-screen latency of the last presented frame (microseconds)
+    pub last_us: u64,
+    /// Moving average of the latency (microseconds)
+    pub average_us: u64,
+    /// Highest latency seen (microseconds)
+    pub worst_us: u64,
+}
+
+impl LatencyStats {
+    /// Record the latency of a presented frame
+    fn record(&mut self, latency: Duration) {
+        let latency = latency.as_micros() as u64;
+        self.average_us = match self.presented {
+            0 => latency,
+            // Weigh the new frame by 1/8
+            _ => (self.average_us * 7 + latency) / 8,
+        };
+        self.presented += 1;
+        self.last_us = latency;
+        self.worst_us = self.worst_us.max(latency);
+    }
+}
+
+/// Frames in flight of one window
+#[derive(Debug, Clone, Default)]
+struct WindowQueue {
+    /// Depth set by a window rule
+    rule: Option<u32>,
+    /// Content type hinted by the client
+    content: ContentType,
+    /// The client accepts tearing for lower latency
+    tearing: bool,
+    /// Commit times of the frames in flight, oldest first
+    in_flight: VecDeque<Instant>,
+    /// Latency statistics
+    stats: LatencyStats,
+}
+
+impl WindowQueue {
+    /// Frames the window may have in flight
+    fn depth(&self) -> u32 {
+        self.rule
+            .or(self.content.frames_in_flight())
+            .unwrap_or(DEFAULT_FRAMES_IN_FLIGHT)
+            .clamp(1, MAX_FRAMES_IN_FLIGHT)
+    }
+
+    /// Check if the window's frames should be shown as soon as they are
+    /// ready
+    fn wants_adaptive(&self) -> bool {
+        self.content == ContentType::Game || self.tearing
+    }
+}
+
+/// Timing of a presented frame, for presentation-time feedback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentFeedback {
+    /// Output the frame was shown on
+    pub output: OutputId,
+    /// Refresh at which the frame became visible
+    pub time: Instant,
+    /// Refresh period of the output
+    pub refresh: Duration,
+    /// Refresh counter of the output
+    pub sequence: u64,
+    /// The frame missed the refresh it was scheduled for
+    pub late: bool,
+    /// The output refreshed when the frame was ready, off the fixed grid
+    pub adaptive: bool,
+}
+
+/// Refresh cycle of one output
+#[derive(Debug, Clone)]
+struct OutputTimeline {
+    /// Refresh period
+    refresh: Duration,
+    /// The display can refresh adaptively
+    adaptive_sync: bool,
+    /// How refreshes are timed
+    policy: RefreshPolicy,
+    /// A past refresh, anchoring the refresh grid
+    last_refresh: Option<Instant>,
+    /// Refresh counter at `last_refresh`
+    sequence: u64,
+    /// Refresh the scheduled frame is for
+    target: Option<Instant>,
+    /// Present statistics
+    stats: FrameStats,
+}
+
+impl OutputTimeline {
+    fn new(refresh: Duration) -> Self {
+        Self {
+            refresh,
+            adaptive_sync: false,
+            policy: RefreshPolicy::Fixed,
+            last_refresh: None,
+            sequence: 0,
+            target: None,
+            stats: FrameStats::default(),
+        }
+    }
+
+    /// First refresh at or after `time`, and the refresh count to reach it
+    fn refresh_after(&self, time: Instant) -> (Instant, u64) {
+        let Some(anchor) = self.last_refresh else {
+            return (time, 0);
+        };
+        if time <= anchor {
+            return (anchor, 0);
+        }
+        let elapsed = time.duration_since(anchor).as_nanos();
+        let period = self.refresh.as_nanos().max(1);
+        let cycles = elapsed.div_ceil(period);
+        (
+            anchor + Duration::from_nanos((cycles * period) as u64),
+            cycles as u64,
+        )
+    }
+
+    /// Earliest time the next frame can be shown under the adaptive
+    /// policy, one refresh period after the last
+    fn earliest_refresh(&self, time: Instant) -> Instant {
+        self.last_refresh
+            .map(|last| last + self.refresh)
+            .map_or(time, |earliest| earliest.max(time))
+    }
+
+    /// Refresh to aim a frame scheduled at `now` for
+    fn next_target(&self, now: Instant) -> Instant {
+        match self.policy {
+            // Show the frame as soon as it can be composed
+            RefreshPolicy::Adaptive => {
+                let composed = now + COMPOSE_MARGIN.min(self.refresh / 2);
+                self.earliest_refresh(composed)
+            }
+            // Aim for the first refresh that leaves time to compose
+            RefreshPolicy::Fixed => {
+                let (mut target, _) = self.refresh_after(now);
+                while self.deadline(target) < now {
+                    target += self.refresh;
+                }
+                target
+            }
+        }
+    }
+
+    /// Time to start composing for a refresh
+    fn deadline(&self, target: Instant) -> Instant {
+        let margin = COMPOSE_MARGIN.min(self.refresh / 2);
+        target.checked_sub(margin).unwrap_or(target)
+    }
+}
+
+/// Per-output frame scheduler
+#[derive(Debug, Default)]
+pub struct FrameScheduler {
+    outputs: HashMap<OutputId, OutputTimeline>,
+    /// Frames in flight by window
+    windows: HashMap<WindowId, WindowQueue>,
+}
+
+impl FrameScheduler {
+    /// Create a scheduler without outputs
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track an output, or pick up a change of its refresh rate
+    pub fn sync_output(&mut self, output: &Output) {
+        let refresh = refresh_period(
+            output
+                .current_mode()
+                .map(|mode| mode.refresh)
+                .unwrap_or(DEFAULT_REFRESH_MHZ),
+        );
+        let timeline = self
+            .outputs
+            .entry(output.id)
+            .or_insert_with(|| OutputTimeline::new(refresh));
+        if timeline.refresh != refresh {
+            // The old refresh grid doesn't apply to the new rate
+            timeline.refresh = refresh;
+            timeline.last_refresh = None;
+            timeline.target = None;
+        }
+        timeline.adaptive_sync = output.min_refresh.is_some();
+        if !timeline.adaptive_sync {
+            timeline.policy = RefreshPolicy::Fixed;
+        }
+    }
+
+    /// Stop tracking an output
+    pub fn remove_output(&mut self, output: OutputId) {
+        self.outputs.remove(&output);
+    }
+
+    /// Get the refresh period of an output
+    pub fn refresh(&self, output: OutputId) -> Option<Duration> {
+        self.outputs.get(&output).map(|t| t.refresh)
+    }
+
+    /// Check if an output can refresh adaptively
+    pub fn adaptive_sync(&self, output: OutputId) -> bool {
+        self.outputs.get(&output).is_some_and(|t| t.adaptive_sync)
+    }
+
+    /// Get the refresh policy of an output
+    pub fn policy(&self, output: OutputId) -> Option<RefreshPolicy> {
+        self.outputs.get(&output).map(|t| t.policy)
+    }
+
+    /// Set the refresh policy of an output
+    ///
+    /// Outputs that can't refresh adaptively stay fixed. Returns true if
+    /// the policy changed.
+    pub fn set_policy(&mut self, output: OutputId, policy: RefreshPolicy) -> bool {
+        let Some(timeline) = self.outputs.get_mut(&output) else {
+            return false;
+        };
+        let policy = match timeline.adaptive_sync {
+            true => policy,
+            false => RefreshPolicy::Fixed,
+        };
+        if timeline.policy == policy {
+            return false;
+        }
+        timeline.policy = policy;
+        true
+    }
+
+    /// Schedule a frame for an output, returning when to compose it
+    ///
+    /// Scheduling an output that already has a frame pending keeps the
+    /// earlier deadline.
+    pub fn schedule(&mut self, output: OutputId, now: Instant) -> Option<Instant> {
+        let timeline = self.outputs.get_mut(&output)?;
+        if let Some(target) = timeline.target {
+            return Some(timeline.deadline(target));
+        }
+
+        let target = timeline.next_target(now);
+        timeline.target = Some(target);
+        Some(timeline.deadline(target))
+    }
+
+    /// Refresh of an output that content committed at `now` would be shown
+    /// at
+    pub fn next_refresh(&self, output: OutputId, now: Instant) -> Option<Instant> {
+        let timeline = self.outputs.get(&output)?;
+        Some(timeline.target.unwrap_or_else(|| timeline.next_target(now)))
+    }
+
+    /// Check if an output has a frame pending
+    pub fn is_scheduled(&self, output: OutputId) -> bool {
+        self.outputs
+            .get(&output)
+            .is_some_and(|t| t.target.is_some())
+    }
+
+    /// Earliest composition deadline of all outputs
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.outputs
+            .values()
+            .filter_map(|t| t.target.map(|target| t.deadline(target)))
+            .min()
+    }
+
+    /// Outputs whose frame should be composed now
+    pub fn due(&self, now: Instant) -> Vec<OutputId> {
+        self.outputs
+            .iter()
+            .filter(|(_, t)| t.target.is_some_and(|target| t.deadline(target) <= now))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Record that a frame was presented on an output at `time`
+    ///
+    /// The frame becomes visible at the first refresh after `time`, or
+    /// right away under the adaptive policy unless the last refresh was less
+    /// than a period ago. If that is later than the refresh it was scheduled
+    /// for, the frame is late.
+    pub fn presented(&mut self, output: OutputId, time: Instant) -> Option<PresentFeedback> {
+        let timeline = self.outputs.get_mut(&output)?;
+        let adaptive = timeline.policy == RefreshPolicy::Adaptive;
+        let (shown, cycles) = match adaptive {
+            true => (timeline.earliest_refresh(time), 1),
+            false => timeline.refresh_after(time),
+        };
+        let target = timeline.target.take().unwrap_or(shown);
+
+        let late = shown > target;
+        if late {
+            let delay = shown.duration_since(target);
+            let skipped = delay.as_nanos() / timeline.refresh.as_nanos().max(1);
+            timeline.stats.late += 1;
+            timeline.stats.missed += skipped as u64;
+            timeline.stats.worst_delay_us = timeline
+                .stats
+                .worst_delay_us
+                .max(time.saturating_duration_since(target).as_micros() as u64);
+        }
+        timeline.stats.presented += 1;
+        timeline.sequence += cycles.max(1);
+        timeline.last_refresh = Some(shown);
+
+        Some(PresentFeedback {
+            output,
+            time: shown,
+            refresh: timeline.refresh,
+            sequence: timeline.sequence,
+            late,
+            adaptive,
+        })
+    }
+
+    /// Get the present statistics of an output
+    pub fn stats(&self, output: OutputId) -> Option<FrameStats> {
+        self.outputs.get(&output).map(|t| t.stats)
+    }
+
+    /// Present statistics of all outputs
+    pub fn all_stats(&self) -> impl Iterator<Item = (OutputId, FrameStats)> + '_ {
+        self.outputs.iter().map(|(id, t)| (*id, t.stats))
+    }
+
+    /// Set the frames in flight a window rule allows, None to drop the rule
+    pub fn set_frames_in_flight(&mut self, window: WindowId, frames: Option<u32>) {
+        self.windows.entry(window).or_default().rule = frames;
+    }
+
+    /// Set the content type hinted for a window
+    pub fn set_content_type(&mut self, window: WindowId, content: ContentType) {
+        self.windows.entry(window).or_default().content = content;
+    }
+
+    /// Set whether a window's client accepts tearing
+    pub fn set_tearing(&mut self, window: WindowId, tearing: bool) {
+        self.windows.entry(window).or_default().tearing = tearing;
+    }
+
+    /// Check if a window asked for its frames to be shown as soon as they
+    /// are ready, being a game or accepting tearing
+    pub fn wants_adaptive(&self, window: WindowId) -> bool {
+        self.windows
+            .get(&window)
+            .is_some_and(WindowQueue::wants_adaptive)
+    }
+
+    /// Frames a window may have in flight
+    pub fn frames_in_flight(&self, window: WindowId) -> u32 {
+        self.windows
+            .get(&window)
+            .map(WindowQueue::depth)
+            .unwrap_or(DEFAULT_FRAMES_IN_FLIGHT)
+    }
+
+    /// Presentation options of a window's layer
+    ///
+    /// A layer needs one drawable more than the window's frames in flight,
+    /// and waits for the display unless the window wants its frames shown
+    /// as soon as they are ready.
+    pub fn layer_options(&self, window: WindowId) -> LayerOptions {
+        LayerOptions {
+            drawables: (self.frames_in_flight(window) + 1).clamp(2, 3),
+            display_sync: !self.wants_adaptive(window),
+        }
+    }
+
+    /// Record a commit of a window's content at `now`
+    ///
+    /// Returns true if the window still has room for another frame, so its
+    /// frame callbacks can be answered before this one is presented.
+    pub fn window_committed(&mut self, window: WindowId, now: Instant) -> bool {
+        let queue = self.windows.entry(window).or_default();
+        queue.in_flight.push_back(now);
+        (queue.in_flight.len() as u32) < queue.depth()
+    }
+
+    /// Record that a window's latest content became visible at `shown`
+    ///
+    /// Older frames still in flight were replaced without being shown.
+    pub fn window_presented(&mut self, window: WindowId, shown: Instant) {
+        let Some(queue) = self.windows.get_mut(&window) else {
+            return;
+        };
+        let Some(committed) = queue.in_flight.pop_back() else {
+            return;
+        };
+        queue.stats.dropped += queue.in_flight.len() as u64;
+        queue.in_flight.clear();
+        queue
+            .stats
+            .record(shown.saturating_duration_since(committed));
+    }
+
+    /// Stop tracking a window
+    pub fn remove_window(&mut self, window: WindowId) {
+        self.windows.remove(&window);
+    }
+
+    /// Latency statistics of all windows
+    pub fn window_stats(&self) -> impl Iterator<Item = (WindowId, LatencyStats)> + '_ {
+        self.windows.iter().map(|(id, q)| (*id, q.stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler_with_output(refresh_mhz: u32) -> (FrameScheduler, OutputId) {
+        let mut output = Output::new("test".to_string());
+        output.add_mode(crate::compositor::OutputMode {
+            width: 1920,
+            height: 1080,
+            refresh: refresh_mhz,
+            current: true,
+            preferred: true,
+        });
+        let mut scheduler = FrameScheduler::new();
+        scheduler.sync_output(&output);
+        (scheduler, output.id)
+    }
+
+    #[test]
+    fn test_refresh_period() {
+        assert_eq!(refresh_period(60_000), Duration::from_nanos(16_666_666));
+        assert_eq!(refresh_period(120_000), Duration::from_nanos(8_333_333));
+        assert_eq!(refresh_period(0), refresh_period(DEFAULT_REFRESH_MHZ));
+    }
+
+    #[test]
+    fn test_schedule_and_present_on_time() {
+        let (mut scheduler, id) = scheduler_with_output(100_000);
+        let start = Instant::now();
+        let period = Duration::from_millis(10);
+
+        // The first present anchors the refresh grid
+        scheduler.schedule(id, start).unwrap();
+        let feedback = scheduler.presented(id, start).unwrap();
+        assert_eq!(feedback.time, start);
+        assert!(!feedback.late);
+        assert!(!scheduler.is_scheduled(id));
+
+        let deadline = scheduler
+            .schedule(id, start + Duration::from_millis(3))
+            .unwrap();
+        assert_eq!(deadline, start + period - COMPOSE_MARGIN);
+        assert_eq!(scheduler.next_deadline(), Some(deadline));
+        assert!(scheduler
+            .due(deadline - Duration::from_millis(1))
+            .is_empty());
+        assert_eq!(scheduler.due(deadline), vec![id]);
+
+        let feedback = scheduler.presented(id, deadline).unwrap();
+        assert_eq!(feedback.time, start + period);
+        assert_eq!(feedback.sequence, 2);
+        assert!(!feedback.late);
+        assert_eq!(scheduler.stats(id).unwrap().presented, 2);
+    }
+
+    #[test]
+    fn test_late_present_counts_missed_frames() {
+        let (mut scheduler, id) = scheduler_with_output(100_000);
+        let start = Instant::now();
+        scheduler.presented(id, start);
+
+        // Scheduled for the refresh at 10ms, presented after the one at 30ms
+        scheduler.schedule(id, start + Duration::from_millis(1));
+        let feedback = scheduler
+            .presented(id, start + Duration::from_millis(35))
+            .unwrap();
+        assert!(feedback.late);
+        assert_eq!(feedback.time, start + Duration::from_millis(40));
+        assert_eq!(feedback.sequence, 5);
+
+        let stats = scheduler.stats(id).unwrap();
+        assert_eq!(stats.late, 1);
+        assert_eq!(stats.missed, 3);
+        assert_eq!(stats.worst_delay_us, 25_000);
+    }
+
+    #[test]
+    fn test_frames_in_flight() {
+        let mut scheduler = FrameScheduler::new();
+        let window = WindowId(1);
+        let start = Instant::now();
+        assert_eq!(scheduler.frames_in_flight(window), DEFAULT_FRAMES_IN_FLIGHT);
+
+        // Video draws ahead, a window rule takes precedence and is clamped
+        scheduler.set_content_type(window, ContentType::Video);
+        assert_eq!(scheduler.frames_in_flight(window), 3);
+        scheduler.set_frames_in_flight(window, Some(8));
+        assert_eq!(scheduler.frames_in_flight(window), MAX_FRAMES_IN_FLIGHT);
+        scheduler.set_frames_in_flight(window, Some(2));
+
+        assert!(scheduler.window_committed(window, start));
+        assert!(!scheduler.window_committed(window, start + Duration::from_millis(4)));
+        scheduler.window_presented(window, start + Duration::from_millis(10));
+
+        let (_, stats) = scheduler.window_stats().next().unwrap();
+        assert_eq!(stats.presented, 1);
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.last_us, 6_000);
+        assert_eq!(stats.average_us, 6_000);
+
+        // Once presented the window has room again
+        assert!(scheduler.window_committed(window, start + Duration::from_millis(12)));
+        scheduler.remove_window(window);
+        assert_eq!(scheduler.window_stats().count(), 0);
+    }
+
+    #[test]
+    fn test_layer_options() {
+        let mut scheduler = FrameScheduler::new();
+        let window = WindowId(1);
+        let synced = |drawables| LayerOptions {
+            drawables,
+            display_sync: true,
+        };
+        assert_eq!(scheduler.layer_options(window), synced(2));
+
+        scheduler.set_content_type(window, ContentType::Video);
+        assert_eq!(scheduler.layer_options(window), synced(3));
+        scheduler.set_content_type(window, ContentType::Photo);
+        assert_eq!(scheduler.layer_options(window), synced(2));
+
+        // Games aren't held to the display refresh
+        scheduler.set_content_type(window, ContentType::Game);
+        assert_eq!(
+            scheduler.layer_options(window),
+            LayerOptions {
+                drawables: 2,
+                display_sync: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_adaptive_policy() {
+        let (mut scheduler, id) = scheduler_with_output(120_000);
+        assert!(!scheduler.set_policy(id, RefreshPolicy::Adaptive));
+
+        let mut output = Output::new("promotion".to_string());
+        output.add_mode(crate::compositor::OutputMode {
+            width: 1512,
+            height: 982,
+            refresh: 120_000,
+            current: true,
+            preferred: true,
+        });
+        output.min_refresh = Some(24_000);
+        scheduler.sync_output(&output);
+        let id = output.id;
+        assert!(scheduler.adaptive_sync(id));
+        assert!(scheduler.set_policy(id, RefreshPolicy::Adaptive));
+
+        // Off the grid, but no sooner than a period after the last refresh
+        let start = Instant::now();
+        scheduler.presented(id, start);
+        let period = refresh_period(120_000);
+        let deadline = scheduler
+            .schedule(id, start + Duration::from_millis(20))
+            .unwrap();
+        assert_eq!(deadline, start + Duration::from_millis(20));
+        let feedback = scheduler.presented(id, deadline).unwrap();
+        assert!(feedback.adaptive && !feedback.late);
+        assert_eq!(feedback.time, deadline);
+        assert_eq!(feedback.sequence, 2);
+
+        scheduler.schedule(id, deadline + Duration::from_millis(1));
+        let feedback = scheduler
+            .presented(id, deadline + Duration::from_millis(1))
+            .unwrap();
+        assert_eq!(feedback.time, deadline + period);
+
+        let window = WindowId(1);
+        assert!(!scheduler.wants_adaptive(window));
+        scheduler.set_tearing(window, true);
+        assert!(scheduler.wants_adaptive(window));
+    }
+}
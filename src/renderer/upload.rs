@@ -0,0 +1,426 @@
+//! Buffer uploads on worker threads
+//!
+//! Copying a 4K shm buffer out of client memory and converting it to
+//! ARGB8888 takes long enough to stall client dispatch when done on the main
+//! thread. Windows instead queue an upload when they commit, and a small
+//! pool of workers copies and converts the pixels while the main loop keeps
+//! dispatching. Before presenting, the main loop waits on the pool's fence
+//! for the uploads still in flight, which have usually finished by the
+//! frame deadline, and hands the pixels to the windows.
+//!
+//! A window with subsurfaces or popups is uploaded as the layers of its
+//! surface tree, which the worker composes into one image.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use log::{debug, warn};
+
+use super::convert::into_argb8888;
+use super::scene::{Placement, Scene, SceneNode};
+use crate::compositor::WindowId;
+use crate::protocol::shm::ShmFormat;
+
+/// Most worker threads started
+const MAX_WORKERS: usize = 4;
+
+/// Client memory that outlives the pool it was mapped from
+pub type SharedMemory = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
+/// Pixels to upload
+#[derive(Clone)]
+pub enum Pixels {
+    /// Part of a mapped shm pool, in its wl_shm format
+    Shm {
+        memory: SharedMemory,
+        range: Range<usize>,
+        format: ShmFormat,
+        stride: u32,
+    },
+    /// One ARGB8888 pixel covering the whole image
+    Solid([u8; 4]),
+    /// Surfaces composed into one image, bottom first
+    Layers(Vec<Layer>),
+}
+
+/// The pixels of one surface in a composed upload
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// Where the pixels come from
+    pub pixels: Pixels,
+    /// Size in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Where the surface is drawn in the image, and the part of the pixels
+    /// shown
+    pub placement: Placement,
+}
+
+impl Layer {
+    /// Convert the pixels into a node to paint
+    fn into_node(self) -> Option<SceneNode> {
+        let opaque = matches!(self.pixels, Pixels::Shm { format, .. } if format.is_opaque());
+        let (data, stride) = self.pixels.convert(self.width, self.height)?;
+        let node = SceneNode {
+            surface: self.placement.surface,
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+            stride,
+            opaque,
+            filter: None,
+            data,
+        };
+        Some(node.place(&self.placement))
+    }
+}
+
+impl std::fmt::Debug for Pixels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pixels::Shm {
+                range,
+                format,
+                stride,
+                ..
+            } => f
+                .debug_struct("Shm")
+                .field("range", range)
+                .field("format", format)
+                .field("stride", stride)
+                .finish(),
+            Pixels::Solid(pixel) => f.debug_tuple("Solid").field(pixel).finish(),
+            Pixels::Layers(layers) => f.debug_tuple("Layers").field(layers).finish(),
+        }
+    }
+}
+
+/// A window's committed content to copy and convert
+#[derive(Debug, Clone)]
+pub struct UploadJob {
+    /// Window the content is for
+    pub window: WindowId,
+    /// Where the pixels come from
+    pub pixels: Pixels,
+    /// Size in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Pixels ready to present, in ARGB8888
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upload {
+    /// Window the content is for
+    pub window: WindowId,
+    /// Pixel data
+    pub data: Vec<u8>,
+    /// Size in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub stride: u32,
+}
+
+impl Pixels {
+    /// Copy and convert the pixels of an image of the given size to
+    /// ARGB8888, returning them with their stride
+    fn convert(self, width: u32, height: u32) -> Option<(Vec<u8>, u32)> {
+        match self {
+            Pixels::Shm {
+                memory,
+                range,
+                format,
+                stride,
+            } => {
+                let data = (*memory).as_ref().get(range)?.to_vec();
+                into_argb8888(format, data, width, height, stride)
+            }
+            Pixels::Solid(pixel) => {
+                let data = pixel.repeat((width * height) as usize);
+                Some((data, width * 4))
+            }
+            Pixels::Layers(layers) => {
+                let nodes = layers.into_iter().filter_map(Layer::into_node).collect();
+                let capture = Scene {
+                    width,
+                    height,
+                    nodes,
+                }
+                .render();
+                let stride = capture.stride();
+                Some((capture.data, stride))
+            }
+        }
+    }
+}
+
+impl UploadJob {
+    /// Copy and convert the pixels, None if they can't be displayed
+    pub fn run(self) -> Option<Upload> {
+        let (data, stride) = self.pixels.convert(self.width, self.height)?;
+        Some(Upload {
+            window: self.window,
+            data,
+            width: self.width,
+            height: self.height,
+            stride,
+        })
+    }
+}
+
+/// Worker threads and the uploads in flight
+#[derive(Debug)]
+struct Workers {
+    /// Queue the workers take jobs from
+    jobs: Sender<(u64, UploadJob)>,
+    /// Finished uploads, with the sequence number of their job
+    results: Receiver<(u64, WindowId, Option<Upload>)>,
+    /// Worker threads, joined on drop
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// A pool of threads uploading window content
+///
+/// Threads are started on the first upload.
+#[derive(Debug, Default)]
+pub struct UploadPool {
+    /// Workers, once started
+    workers: Option<Workers>,
+    /// Jobs submitted and not collected yet
+    in_flight: usize,
+    /// Sequence number of the next job
+    next_sequence: u64,
+    /// Latest finished upload of each window, with its sequence number
+    finished: HashMap<WindowId, (u64, Upload)>,
+}
+
+impl UploadPool {
+    /// Create a pool without starting its threads
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of uploads submitted and not collected yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Queue a window's content for upload
+    ///
+    /// Runs the job right away if the worker threads can't be started.
+    pub fn submit(&mut self, job: UploadJob) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        let Some(workers) = self.workers() else {
+            let window = job.window;
+            self.finish_job(sequence, window, job.run());
+            return;
+        };
+        if workers.jobs.send((sequence, job)).is_ok() {
+            self.in_flight += 1;
+        }
+    }
+
+    /// Wait for the uploads in flight and take the latest of each window
+    ///
+    /// This is the fence before presenting: content committed before it is
+    /// ready once it returns.
+    pub fn finish(&mut self) -> Vec<Upload> {
+        while self.in_flight > 0 {
+            let Some(result) = self.workers.as_ref().map(|w| w.results.recv()) else {
+                break;
+            };
+            let Ok((sequence, window, upload)) = result else {
+                warn!(
+                    "Upload workers exited with {} uploads in flight",
+                    self.in_flight
+                );
+                self.in_flight = 0;
+                break;
+            };
+            self.in_flight -= 1;
+            self.finish_job(sequence, window, upload);
+        }
+        self.finished
+            .drain()
+            .map(|(_, (_, upload))| upload)
+            .collect()
+    }
+
+    /// Keep a finished upload unless a newer one of its window finished first
+    fn finish_job(&mut self, sequence: u64, window: WindowId, upload: Option<Upload>) {
+        let Some(upload) = upload else {
+            debug!("Can't upload content of window {:?}", window);
+            return;
+        };
+        match self.finished.get(&window) {
+            Some((newer, _)) if *newer > sequence => {}
+            _ => {
+                self.finished.insert(window, (sequence, upload));
+            }
+        }
+    }
+
+    /// Get the workers, starting them if needed
+    fn workers(&mut self) -> Option<&Workers> {
+        if self.workers.is_none() {
+            self.workers = Workers::start();
+        }
+        self.workers.as_ref()
+    }
+}
+
+impl Workers {
+    /// Start one worker per core, up to `MAX_WORKERS`
+    fn start() -> Option<Self> {
+        let count = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .clamp(1, MAX_WORKERS);
+        let (jobs, job_queue) = mpsc::channel::<(u64, UploadJob)>();
+        let (done, results) = mpsc::channel();
+        let job_queue = Arc::new(Mutex::new(job_queue));
+
+        let mut threads = Vec::new();
+        for index in 0..count {
+            let job_queue = job_queue.clone();
+            let done = done.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("wayoa-upload-{}", index))
+                .spawn(move || loop {
+                    let job = match job_queue.lock() {
+                        Ok(queue) => queue.recv(),
+                        Err(_) => return,
+                    };
+                    let Ok((sequence, job)) = job else {
+                        return;
+                    };
+                    let window = job.window;
+                    if done.send((sequence, window, job.run())).is_err() {
+                        return;
+                    }
+                });
+            match thread {
+                Ok(thread) => threads.push(thread),
+                Err(e) => warn!("Failed to start upload worker: {}", e),
+            }
+        }
+        if threads.is_empty() {
+            return None;
+        }
+        debug!("Started {} upload workers", threads.len());
+        Some(Self {
+            jobs,
+            results,
+            threads,
+        })
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        // Closing the queue stops the workers once they finish their job
+        let (closed, _) = mpsc::channel();
+        self.jobs = closed;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::scene::FULL_CROP;
+
+    fn shm_job(window: u64, memory: SharedMemory, format: ShmFormat, stride: u32) -> UploadJob {
+        let len = (*memory).as_ref().len();
+        UploadJob {
+            window: WindowId(window),
+            pixels: Pixels::Shm {
+                memory,
+                range: 0..len,
+                format,
+                stride,
+            },
+            width: 1,
+            height: 1,
+        }
+    }
+
+    #[test]
+    fn test_upload_job() {
+        let solid = UploadJob {
+            window: WindowId(1),
+            pixels: Pixels::Solid([1, 2, 3, 4]),
+            width: 2,
+            height: 1,
+        };
+        let upload = solid.run().unwrap();
+        assert_eq!(upload.data, vec![1, 2, 3, 4, 1, 2, 3, 4]);
+        assert_eq!(upload.stride, 8);
+
+        // RGB565 red converts to BGRA, and ranges past the memory fail
+        let memory: SharedMemory = Arc::new(vec![0x00, 0xf8]);
+        let upload = shm_job(1, memory.clone(), ShmFormat::Rgb565, 2).run();
+        assert_eq!(upload.unwrap().data, vec![0, 0, 0xff, 0xff]);
+        let mut job = shm_job(1, memory, ShmFormat::Rgb565, 2);
+        if let Pixels::Shm { range, .. } = &mut job.pixels {
+            *range = 0..4;
+        }
+        assert_eq!(job.run(), None);
+    }
+
+    #[test]
+    fn test_layers_are_composed() {
+        let surface = crate::compositor::SurfaceId(1);
+        let layer = |pixel, size, x, y| Layer {
+            pixels: Pixels::Solid(pixel),
+            width: 1,
+            height: 1,
+            placement: Placement {
+                surface,
+                x,
+                y,
+                width: size,
+                height: size,
+                crop: FULL_CROP,
+            },
+        };
+        let job = UploadJob {
+            window: WindowId(1),
+            pixels: Pixels::Layers(vec![
+                layer([0, 0, 255, 255], 2, 0, 0),
+                layer([255, 0, 0, 255], 1, 1, 1),
+            ]),
+            width: 2,
+            height: 2,
+        };
+        let upload = job.run().unwrap();
+        assert_eq!(upload.stride, 8);
+        assert_eq!(&upload.data[..4], &[0, 0, 255, 255]);
+        assert_eq!(&upload.data[12..], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_pool_keeps_latest_per_window() {
+        let mut pool = UploadPool::new();
+        assert!(pool.finish().is_empty());
+
+        for (window, pixel) in [(1, 1u8), (2, 2), (1, 3)] {
+            let memory: SharedMemory = Arc::new(vec![pixel; 4]);
+            pool.submit(shm_job(window, memory, ShmFormat::Argb8888, 4));
+        }
+        let mut uploads = pool.finish();
+        assert_eq!(pool.in_flight(), 0);
+        uploads.sort_by_key(|upload| upload.window.0);
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(uploads[0].data, vec![3; 4]);
+        assert_eq!(uploads[1].data, vec![2; 4]);
+    }
+}
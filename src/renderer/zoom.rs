@@ -0,0 +1,56 @@
+//! Magnification of zoomed window content
+//!
+//! The visible part of the content is stretched back over the full size
+//! with nearest-neighbor sampling, which keeps text sharp at whole-number
+//! factors.
+
+use crate::compositor::Zoom;
+
+/// Magnify 32-bit pixels, returning an image of the same size with a tight
+/// stride
+pub fn magnify(data: &[u8], width: u32, height: u32, stride: u32, zoom: &Zoom) -> Vec<u8> {
+    let (vx, vy, vw, vh) = zoom.visible(width as f64, height as f64);
+    let columns: Vec<usize> = (0..width)
+        .map(|x| {
+            let src = vx + (x as f64 + 0.5) * vw / width as f64;
+            (src as u32).min(width.saturating_sub(1)) as usize * 4
+        })
+        .collect();
+
+    let mut out = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        let src_y = (vy + (y as f64 + 0.5) * vh / height as f64) as u32;
+        let row = src_y.min(height.saturating_sub(1)) as usize * stride as usize;
+        for &column in &columns {
+            match data.get(row + column..row + column + 4) {
+                Some(pixel) => out.extend_from_slice(pixel),
+                None => out.extend_from_slice(&[0; 4]),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magnify() {
+        // 4x2 image whose pixels are their index, with a padded stride
+        let mut data = Vec::new();
+        for y in 0..2u8 {
+            for x in 0..4u8 {
+                data.extend_from_slice(&[y * 4 + x; 4]);
+            }
+            data.extend_from_slice(&[0xff; 4]);
+        }
+
+        let mut zoom = Zoom::default();
+        zoom.set_factor(2.0);
+        zoom.set_center(0.0, 0.0);
+        let out = magnify(&data, 4, 2, 20, &zoom);
+        let pixels: Vec<u8> = out.chunks(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(pixels, [0, 0, 1, 1, 0, 0, 1, 1]);
+    }
+}
@@ -0,0 +1,179 @@
+//! xdg_activation_v1 objects
+//!
+//! Tokens are tracked by the [`ActivationHandler`]. A committed token
+//! grants focus if its serial is that of a recent key or button press the
+//! seat sent to one of the requesting client's surfaces. Using a token that
+//! grants focus activates the window, raising its native window; any other
+//! activation marks the window as needing attention instead.
+//!
+//! [`ActivationHandler`]: crate::protocol::ActivationHandler
+
+use std::time::Instant;
+
+use log::{debug, info};
+use wayland_protocols::xdg::activation::v1::server::{xdg_activation_token_v1, xdg_activation_v1};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::protocol::activation::{ActivationTokenId, TokenRequest};
+
+impl GlobalDispatch<xdg_activation_v1::XdgActivationV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<xdg_activation_v1::XdgActivationV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound xdg_activation_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<xdg_activation_v1::XdgActivationV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &xdg_activation_v1::XdgActivationV1,
+        request: xdg_activation_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_activation_v1::Request::GetActivationToken { id } => {
+                let token_id = state.activation.create_request();
+                data_init.init(id, token_id);
+            }
+            xdg_activation_v1::Request::Activate { token, surface } => {
+                if let Some(&surface_id) = surface.data::<SurfaceId>() {
+                    state.activate_with_token(&token, surface_id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<xdg_activation_token_v1::XdgActivationTokenV1, ActivationTokenId> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &xdg_activation_token_v1::XdgActivationTokenV1,
+        request: xdg_activation_token_v1::Request,
+        data: &ActivationTokenId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let xdg_activation_token_v1::Request::Commit = request {
+            let Some(token_request) = state.activation.take_request(*data) else {
+                resource.post_error(
+                    xdg_activation_token_v1::Error::AlreadyUsed,
+                    "The token was already committed",
+                );
+                return;
+            };
+            let granted = state.stems_from_input(client, &token_request);
+            let token = state.activation.issue(granted, Instant::now());
+            resource.done(token);
+            return;
+        }
+
+        let Some(token_request) = state.activation.request_mut(*data) else {
+            resource.post_error(
+                xdg_activation_token_v1::Error::AlreadyUsed,
+                "The token was already committed",
+            );
+            return;
+        };
+        match request {
+            xdg_activation_token_v1::Request::SetSerial { serial, seat: _ } => {
+                token_request.serial = Some(serial);
+            }
+            xdg_activation_token_v1::Request::SetAppId { app_id } => {
+                token_request.app_id = Some(app_id);
+            }
+            xdg_activation_token_v1::Request::SetSurface { surface } => {
+                token_request.surface = surface.data::<SurfaceId>().copied();
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &xdg_activation_token_v1::XdgActivationTokenV1,
+        data: &ActivationTokenId,
+    ) {
+        state.activation.destroy_request(*data);
+    }
+}
+
+impl ServerState {
+    /// Check if a token request carries the serial of a recent key or
+    /// button press sent to the requesting client
+    fn stems_from_input(&self, client: &Client, request: &TokenRequest) -> bool {
+        request
+            .serial
+            .and_then(|serial| self.compositor.seat.input_surface(serial))
+            .and_then(|surface| self.resources.surfaces.get(&surface))
+            .is_some_and(|surface| surface.client().is_some_and(|c| c.id() == client.id()))
+    }
+
+    /// Activate the window of a surface with a token, or mark it as
+    /// needing attention if the token doesn't grant focus
+    pub fn activate_with_token(&mut self, token: &str, surface_id: SurfaceId) {
+        let Some(window_id) = self.compositor.windows.window_for_surface(surface_id) else {
+            debug!(
+                "Ignoring activation of surface {:?} without a window",
+                surface_id
+            );
+            return;
+        };
+        match self.activation.activate(token, Instant::now()) {
+            Ok(()) => {
+                self.activate_window(window_id);
+            }
+            Err(e) => {
+                info!("Not focusing window {:?}: {}", window_id, e);
+                self.compositor.windows.request_attention(window_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::activation::ActivationError;
+
+    #[test]
+    fn test_activate_with_token() {
+        let mut state = ServerState::new();
+        let focused = state.compositor.windows.create_window(SurfaceId(1));
+        let other = state.compositor.windows.create_window(SurfaceId(2));
+        state.compositor.windows.set_focused(Some(focused));
+
+        let denied = state.activation.issue(false, Instant::now());
+        state.activate_with_token(&denied, SurfaceId(2));
+        let window = state.compositor.windows.get(other).unwrap();
+        assert!(window.state.urgent && !window.state.focused);
+
+        let granted = state.activation.issue(true, Instant::now());
+        state.activate_with_token(&granted, SurfaceId(2));
+        let window = state.compositor.windows.get(other).unwrap();
+        assert!(window.state.focused && !window.state.urgent);
+        assert_eq!(
+            state.activation.activate(&granted, Instant::now()),
+            Err(ActivationError::UnknownToken)
+        );
+    }
+}
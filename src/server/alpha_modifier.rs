@@ -0,0 +1,98 @@
+//! wp_alpha_modifier_v1 objects
+//!
+//! A client sets a factor for the alpha of a whole surface, from 0 for
+//! transparent to u32::MAX for opaque. The factor is double-buffered
+//! surface state, applied with the surface's commit, and destroying the
+//! object resets it to opaque from the next commit. Native windows take
+//! the factor of their toplevel surface as their opacity.
+
+use log::debug;
+use wayland_protocols::wp::alpha_modifier::v1::server::{
+    wp_alpha_modifier_surface_v1, wp_alpha_modifier_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+
+impl GlobalDispatch<wp_alpha_modifier_v1::WpAlphaModifierV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_alpha_modifier_v1::WpAlphaModifierV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_alpha_modifier_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_alpha_modifier_v1::WpAlphaModifierV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_alpha_modifier_v1::WpAlphaModifierV1,
+        request: wp_alpha_modifier_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_alpha_modifier_v1::Request::GetSurface { id, surface } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if !state.resources.alpha_modifiers.insert(surface_id) {
+                resource.post_error(
+                    wp_alpha_modifier_v1::Error::AlreadyConstructed,
+                    "surface already has an alpha modifier object",
+                );
+                return;
+            }
+            data_init.init(id, surface_id);
+        }
+    }
+}
+
+impl Dispatch<wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1,
+        request: wp_alpha_modifier_surface_v1::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_alpha_modifier_surface_v1::Request::SetMultiplier { factor } = request {
+            let Some(surface) = state.compositor.surfaces.get_mut(*surface_id) else {
+                resource.post_error(
+                    wp_alpha_modifier_surface_v1::Error::NoSurface,
+                    "the surface no longer exists",
+                );
+                return;
+            };
+            debug!("Surface {:?} alpha multiplier {}", surface_id, factor);
+            surface.pending.alpha_multiplier = Some(factor);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wp_alpha_modifier_surface_v1::WpAlphaModifierSurfaceV1,
+        surface_id: &SurfaceId,
+    ) {
+        state.resources.alpha_modifiers.remove(surface_id);
+        if let Some(surface) = state.compositor.surfaces.get_mut(*surface_id) {
+            surface.pending.alpha_multiplier = Some(u32::MAX);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Keybinding configuration and profiles
+//!
+//! The launcher, focus-previous, preview and zoom keys come from their own
+//! configuration sections, and the active profile adds its keys and hot
+//! corners on top. Reloading reads the configuration file again and
+//! rebinds all of them without a restart, keeping the active profile if it
+//! still exists. Conflicts are logged whenever the bindings change, and the
+//! focused client inhibiting shortcuts is reported along with them.
+
+use log::{info, warn};
+
+use super::{zoom, ServerState};
+use crate::compositor::CompositorAction;
+use crate::config::{Config, ConfigError};
+use crate::input::profiles::{self, BindingConflict, BindingProfile};
+use crate::input::{KeyBinding, KeyBindingError};
+
+/// Parse a configured key, warning if it is invalid
+fn parse_binding(name: &str, binding: Option<&str>) -> Option<KeyBinding> {
+    match KeyBinding::parse(binding?) {
+        Ok(binding) => Some(binding),
+        Err(e) => {
+            warn!("Invalid {} keybinding: {}", name, e);
+            None
+        }
+    }
+}
+
+impl ServerState {
+    /// Parse the keys of the launcher, preview and zoom sections
+    pub(super) fn parse_key_bindings(&mut self) {
+        let config = &self.config;
+        self.launcher_binding = parse_binding("launcher", Some(&config.launcher.keybinding));
+        self.focus_previous_binding =
+            parse_binding("focus-previous", config.launcher.focus_previous.as_deref());
+        self.preview_binding = parse_binding("preview", config.preview.keybinding.as_deref());
+        self.zoom_bindings = zoom::zoom_bindings(&config.zoom);
+    }
+
+    /// Switch to a configured profile, or to none
+    pub fn use_binding_profile(&mut self, name: Option<&str>) -> Result<(), BindingsError> {
+        let profile = match name {
+            Some(name) => {
+                let config = self
+                    .config
+                    .bindings
+                    .profiles
+                    .get(name)
+                    .ok_or_else(|| BindingsError::UnknownProfile(name.to_string()))?;
+                let profile = BindingProfile::from_config(name, config)
+                    .map_err(|e| BindingsError::InvalidKey(name.to_string(), e))?;
+                Some(profile)
+            }
+            None => None,
+        };
+        let corners = profile
+            .as_ref()
+            .map(|profile| profile.corners.as_slice())
+            .unwrap_or_default();
+        self.hot_corners.rebind(&self.config.hot_corners, corners);
+        self.binding_profile = profile;
+        info!("Keybinding profile: {}", name.unwrap_or("none"));
+        self.log_binding_conflicts();
+        Ok(())
+    }
+
+    /// Read the configuration file again and rebind every key and corner
+    pub fn reload_bindings(&mut self) -> Result<(), BindingsError> {
+        let config = Config::load()?;
+        self.config.launcher = config.launcher;
+        self.config.preview = config.preview;
+        self.config.zoom = config.zoom;
+        self.config.hot_corners = config.hot_corners;
+        self.config.bindings = config.bindings;
+        self.parse_key_bindings();
+
+        let active = self
+            .binding_profile
+            .as_ref()
+            .map(|profile| profile.name.clone())
+            .filter(|name| self.config.bindings.profiles.contains_key(name))
+            .or_else(|| self.config.bindings.profile.clone());
+        self.use_binding_profile(active.as_deref())
+    }
+
+    /// Every key the compositor binds, in the order they are tried
+    pub fn key_bindings(&self) -> Vec<(KeyBinding, CompositorAction)> {
+        let single = [
+            (self.launcher_binding, CompositorAction::ShowLauncher),
+            (self.focus_previous_binding, CompositorAction::FocusPrevious),
+            (self.preview_binding, CompositorAction::PreviewWindow),
+        ];
+        let profile = self.binding_profile.iter().flat_map(|p| &p.keys);
+        single
+            .into_iter()
+            .filter_map(|(binding, action)| Some((binding?, action)))
+            .chain(self.zoom_bindings.iter().copied())
+            .chain(profile.copied())
+            .collect()
+    }
+
+    /// Find the keys bound twice or taken by macOS, and whether the focused
+    /// client keeps every binding from working
+    pub fn binding_conflicts(&self) -> Vec<BindingConflict> {
+        let mut conflicts = profiles::find_conflicts(&self.key_bindings());
+        if self.compositor.seat.shortcuts_inhibited() {
+            let focused = self.compositor.windows.focused();
+            conflicts.push(BindingConflict::Inhibited {
+                app_id: focused.and_then(|window| window.app_id.clone()),
+            });
+        }
+        conflicts
+    }
+
+    fn log_binding_conflicts(&self) {
+        for conflict in profiles::find_conflicts(&self.key_bindings()) {
+            warn!("Keybinding conflict: {}", conflict);
+        }
+    }
+
+    /// Perform the active profile's action if a key press is bound in it
+    ///
+    /// Returns true if the key was taken, in which case it must not reach
+    /// clients.
+    pub fn profile_key(&mut self, key: u32, modifiers: u32) -> bool {
+        let action = self
+            .binding_profile
+            .as_ref()
+            .and_then(|profile| profile.key_action(key, modifiers));
+        if let Some(action) = action {
+            self.perform_action(action);
+        }
+        action.is_some()
+    }
+}
+
+/// Keybinding profile errors
+#[derive(Debug, thiserror::Error)]
+pub enum BindingsError {
+    #[error("Unknown keybinding profile: {0}")]
+    UnknownProfile(String),
+    #[error("Invalid key in profile {0}: {1}")]
+    InvalidKey(String, KeyBindingError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BindingProfileConfig;
+    use crate::input::keybinding::modifiers;
+    use crate::input::Corner;
+
+    #[test]
+    fn test_switch_profiles() {
+        let mut config = Config::default();
+        let mut linux = BindingProfileConfig::default();
+        linux
+            .keys
+            .insert("alt+tab".to_string(), CompositorAction::FocusPrevious);
+        linux.top_left = Some(CompositorAction::ShowLauncher);
+        config
+            .bindings
+            .profiles
+            .insert("linux-style".to_string(), linux);
+        config.bindings.profile = Some("linux-style".to_string());
+        let mut state = ServerState::with_config(config);
+
+        assert_eq!(
+            state.binding_profile.as_ref().map(|p| p.name.as_str()),
+            Some("linux-style")
+        );
+        assert_eq!(
+            state.hot_corners.action(Corner::TopLeft),
+            Some(CompositorAction::ShowLauncher)
+        );
+        assert!(state.profile_key(15, modifiers::ALT));
+
+        assert!(matches!(
+            state.use_binding_profile(Some("mac-style")),
+            Err(BindingsError::UnknownProfile(_))
+        ));
+        state.use_binding_profile(None).unwrap();
+        assert!(!state.profile_key(15, modifiers::ALT));
+        assert_eq!(state.hot_corners.action(Corner::TopLeft), None);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut config = Config::default();
+        config.preview.keybinding = Some("ctrl+alt+space".to_string());
+        let state = ServerState::with_config(config);
+        let conflicts = state.binding_conflicts();
+
+        // The launcher's default key is also the macOS input source switcher
+        assert!(conflicts.contains(&BindingConflict::Duplicate {
+            key: "ctrl+alt+space".to_string(),
+            actions: vec!["show-launcher", "preview-window"],
+        }));
+        assert!(conflicts
+            .iter()
+            .any(|conflict| matches!(conflict, BindingConflict::System { .. })));
+    }
+}
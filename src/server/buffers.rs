@@ -0,0 +1,195 @@
+//! wl_buffer release events and commit-time snapshots
+//!
+//! A committed shm buffer is read from the client's memory when its window
+//! is presented, so it's only released once a newer buffer replaces it on
+//! every surface showing it. Some clients draw into a buffer before it's
+//! released anyway, which tears the presented content. For clients matched
+//! by the `snapshot_buffers` quirk, the damaged parts of a committed buffer
+//! are copied into a staging buffer kept for the surface, which is read
+//! instead, and the buffer is released right after the copy.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use log::debug;
+use wayland_server::backend::ClientId;
+use wayland_server::protocol::wl_buffer;
+use wayland_server::Resource;
+
+use super::client::client_info;
+use super::ServerState;
+use crate::compositor::surface::BufferSnapshot;
+use crate::compositor::SurfaceId;
+use crate::protocol::shm::{ShmBufferId, ShmFormat, WlShmHandler};
+use crate::renderer::upload::SharedMemory;
+
+/// Live shm buffers and the surfaces reading them
+#[derive(Debug, Default)]
+pub struct BufferReleases {
+    /// wl_buffer objects by shm buffer
+    buffers: HashMap<ShmBufferId, wl_buffer::WlBuffer>,
+    /// The buffer each surface reads from the client's memory
+    shown: HashMap<SurfaceId, ShmBufferId>,
+    /// Staging buffers of snapshotted surfaces
+    snapshots: HashMap<SurfaceId, BufferSnapshot>,
+    /// Clients snapshotted because of a rule for one of their app_ids
+    snapshot_clients: HashSet<ClientId>,
+}
+
+impl BufferReleases {
+    /// Track a new wl_buffer
+    pub fn add(&mut self, id: ShmBufferId, buffer: wl_buffer::WlBuffer) {
+        self.buffers.insert(id, buffer);
+    }
+
+    /// Stop tracking a destroyed wl_buffer
+    pub fn remove(&mut self, id: ShmBufferId) {
+        self.buffers.remove(&id);
+    }
+
+    /// Snapshot a client's buffers from now on
+    pub fn snapshot_client(&mut self, client: ClientId) {
+        self.snapshot_clients.insert(client);
+    }
+
+    /// Record the buffer a surface reads, if any
+    ///
+    /// Returns the buffer the surface read before if no surface reads it
+    /// anymore, which can then be released.
+    fn show(&mut self, surface: SurfaceId, buffer: Option<ShmBufferId>) -> Option<ShmBufferId> {
+        let previous = match buffer {
+            Some(buffer) => self.shown.insert(surface, buffer),
+            None => self.shown.remove(&surface),
+        };
+        previous.filter(|&previous| Some(previous) != buffer && !self.is_shown(previous))
+    }
+
+    /// Check if a surface reads a buffer
+    fn is_shown(&self, buffer: ShmBufferId) -> bool {
+        self.shown.values().any(|&shown| shown == buffer)
+    }
+
+    /// Send a release event for a buffer no surface reads
+    fn release(&self, buffer: ShmBufferId) {
+        if self.is_shown(buffer) {
+            return;
+        }
+        if let Some(resource) = self.buffers.get(&buffer).filter(|b| b.is_alive()) {
+            debug!("Releasing buffer {:?}", buffer);
+            resource.release();
+        }
+    }
+}
+
+/// Mapped memory of an shm buffer, and the buffer's range in it
+#[cfg(target_os = "macos")]
+fn buffer_memory(shm: &mut WlShmHandler, id: ShmBufferId) -> Option<(SharedMemory, Range<usize>)> {
+    shm.buffer_memory(id).ok()
+}
+
+/// Pools are only mapped on macOS
+#[cfg(not(target_os = "macos"))]
+fn buffer_memory(
+    _shm: &mut WlShmHandler,
+    _id: ShmBufferId,
+) -> Option<(SharedMemory, Range<usize>)> {
+    None
+}
+
+impl ServerState {
+    /// Check if a surface's client has its buffers snapshotted
+    fn snapshots_buffers(&self, surface: SurfaceId) -> bool {
+        let Some(client) = self
+            .resources
+            .surfaces
+            .get(&surface)
+            .and_then(|surface| surface.client())
+        else {
+            return false;
+        };
+        client_info(&client).is_some_and(|info| info.quirks.snapshot_buffers)
+            || self
+                .resources
+                .buffers
+                .snapshot_clients
+                .contains(&client.id())
+    }
+
+    /// Snapshot and release the buffers of surfaces whose state was applied
+    pub fn buffers_applied(&mut self, applied: &[SurfaceId]) {
+        for &surface_id in applied {
+            let snapshot = self.snapshots_buffers(surface_id);
+            let Some(surface) = self.compositor.surfaces.get_mut(surface_id) else {
+                continue;
+            };
+            let Some(buffer) = surface.buffer.as_mut() else {
+                continue;
+            };
+            let shown = buffer.shm_buffer_id.map(ShmBufferId);
+
+            // Drop the surface's references first, so the staging buffer can
+            // be written in place if no upload holds it
+            let previous = self.resources.buffers.snapshots.remove(&surface_id);
+            buffer.snapshot = None;
+            let copy = match (snapshot, shown) {
+                (true, Some(id)) => buffer_memory(&mut self.shm, id).and_then(|(memory, range)| {
+                    let bytes_per_pixel = ShmFormat::from_wayland(buffer.format).bytes_per_pixel();
+                    let source = &(*memory).as_ref()[range];
+                    BufferSnapshot::update(
+                        previous,
+                        buffer,
+                        bytes_per_pixel,
+                        source,
+                        &surface.damage,
+                    )
+                }),
+                _ => None,
+            };
+            buffer.snapshot = copy.clone();
+
+            let releases = self
+                .resources
+                .buffers
+                .show(surface_id, shown.filter(|_| copy.is_none()));
+            if let Some(copy) = copy {
+                self.resources.buffers.snapshots.insert(surface_id, copy);
+                if let Some(shown) = shown {
+                    self.resources.buffers.release(shown);
+                }
+            }
+            if let Some(previous) = releases {
+                self.resources.buffers.release(previous);
+            }
+        }
+    }
+
+    /// Release the buffer of a destroyed surface
+    pub fn buffers_surface_destroyed(&mut self, surface: SurfaceId) {
+        self.resources.buffers.snapshots.remove(&surface);
+        if let Some(previous) = self.resources.buffers.show(surface, None) {
+            self.resources.buffers.release(previous);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_show_releases_replaced_buffer() {
+        let mut releases = BufferReleases::default();
+        let (a, b) = (SurfaceId(1), SurfaceId(2));
+        let (first, second) = (ShmBufferId(1), ShmBufferId(2));
+        assert_eq!(releases.show(a, Some(first)), None);
+        assert_eq!(releases.show(a, Some(first)), None);
+        assert_eq!(releases.show(b, Some(first)), None);
+
+        // Still read by the other surface
+        assert_eq!(releases.show(a, Some(second)), None);
+        assert!(releases.is_shown(first));
+        assert_eq!(releases.show(b, None), Some(first));
+        assert_eq!(releases.show(a, None), Some(second));
+        assert!(!releases.is_shown(second));
+    }
+}
@@ -0,0 +1,534 @@
+//! Per-client state and quirk-aware globals
+//!
+//! Every Wayland client carries a [`ClientState`] with its process
+//! information and resolved quirks. Quirks that cap a global's version are
+//! implemented by registering an extra copy of that global at the capped
+//! version, which only the affected clients can see.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use log::{debug, info};
+use wayland_protocols::ext::data_control::v1::server::ext_data_control_manager_v1;
+use wayland_protocols::ext::foreign_toplevel_list::v1::server::ext_foreign_toplevel_list_v1;
+use wayland_protocols::ext::idle_notify::v1::server::ext_idle_notifier_v1;
+use wayland_protocols::wp::alpha_modifier::v1::server::wp_alpha_modifier_v1;
+use wayland_protocols::wp::commit_timing::v1::server::wp_commit_timing_manager_v1;
+use wayland_protocols::wp::content_type::v1::server::wp_content_type_manager_v1;
+use wayland_protocols::wp::cursor_shape::v1::server::wp_cursor_shape_manager_v1;
+use wayland_protocols::wp::fifo::v1::server::wp_fifo_manager_v1;
+use wayland_protocols::wp::fractional_scale::v1::server::wp_fractional_scale_manager_v1;
+use wayland_protocols::wp::idle_inhibit::zv1::server::zwp_idle_inhibit_manager_v1;
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::zwp_keyboard_shortcuts_inhibit_manager_v1;
+use wayland_protocols::wp::pointer_constraints::zv1::server::zwp_pointer_constraints_v1;
+use wayland_protocols::wp::pointer_gestures::zv1::server::zwp_pointer_gestures_v1;
+use wayland_protocols::wp::pointer_warp::v1::server::wp_pointer_warp_v1;
+use wayland_protocols::wp::presentation_time::server::wp_presentation;
+use wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_device_manager_v1;
+use wayland_protocols::wp::relative_pointer::zv1::server::zwp_relative_pointer_manager_v1;
+use wayland_protocols::wp::security_context::v1::server::wp_security_context_manager_v1;
+use wayland_protocols::wp::single_pixel_buffer::v1::server::wp_single_pixel_buffer_manager_v1;
+use wayland_protocols::wp::tablet::zv2::server::zwp_tablet_manager_v2;
+use wayland_protocols::wp::tearing_control::v1::server::wp_tearing_control_manager_v1;
+use wayland_protocols::wp::text_input::zv3::server::zwp_text_input_manager_v3;
+use wayland_protocols::wp::viewporter::server::wp_viewporter;
+use wayland_protocols::xdg::activation::v1::server::xdg_activation_v1;
+use wayland_protocols::xdg::decoration::zv1::server::zxdg_decoration_manager_v1;
+use wayland_protocols::xdg::dialog::v1::server::xdg_wm_dialog_v1;
+use wayland_protocols::xdg::shell::server::xdg_wm_base;
+use wayland_protocols::xdg::toplevel_icon::v1::server::xdg_toplevel_icon_manager_v1;
+use wayland_protocols_misc::zwp_input_method_v2::server::zwp_input_method_manager_v2;
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::server::zwp_virtual_keyboard_manager_v1;
+use wayland_protocols_wlr::data_control::v1::server::zwlr_data_control_manager_v1;
+use wayland_protocols_wlr::foreign_toplevel::v1::server::zwlr_foreign_toplevel_manager_v1;
+use wayland_protocols_wlr::layer_shell::v1::server::zwlr_layer_shell_v1;
+use wayland_protocols_wlr::output_management::v1::server::zwlr_output_manager_v1;
+use wayland_protocols_wlr::output_power_management::v1::server::zwlr_output_power_manager_v1;
+use wayland_protocols_wlr::screencopy::v1::server::zwlr_screencopy_manager_v1;
+use wayland_server::backend::{
+    ClientData, ClientId as BackendClientId, DisconnectReason, GlobalId,
+};
+use wayland_server::protocol::{
+    wl_compositor, wl_data_device_manager, wl_fixes, wl_output, wl_seat, wl_shm, wl_subcompositor,
+};
+use wayland_server::{Client, DisplayHandle};
+
+use super::contexts::{SecurityContext, PRIVILEGED_GLOBALS};
+use super::limits::ConnectionGuard;
+use super::ServerState;
+use crate::compositor::state::ClientId;
+use crate::compositor::Quirks;
+use crate::extensions::info::server::wayoa_info_v1;
+use crate::extensions::permissions::server::wayoa_permissions_v1;
+use crate::session::{Journal, JournalEvent};
+
+/// Globals advertised by the compositor, with their default versions
+pub const GLOBALS: &[(&str, u32)] = &[
+    ("wl_compositor", 6),
+    ("wl_subcompositor", 1),
+    ("wl_shm", 1),
+    ("wl_seat", 9),
+    ("wl_output", 4),
+    ("wl_data_device_manager", 3),
+    ("wl_fixes", 1),
+    ("xdg_wm_base", 6),
+    ("xdg_wm_dialog_v1", 1),
+    ("xdg_toplevel_icon_manager_v1", 1),
+    ("wp_viewporter", 1),
+    ("zxdg_decoration_manager_v1", 1),
+    ("wp_fractional_scale_manager_v1", 1),
+    ("wp_presentation", 1),
+    ("wp_content_type_manager_v1", 1),
+    ("wp_tearing_control_manager_v1", 1),
+    ("wp_fifo_manager_v1", 1),
+    ("wp_commit_timing_manager_v1", 1),
+    ("wp_alpha_modifier_v1", 1),
+    ("wp_single_pixel_buffer_manager_v1", 1),
+    ("wp_cursor_shape_manager_v1", 2),
+    ("zwp_relative_pointer_manager_v1", 1),
+    ("wp_pointer_warp_v1", 1),
+    ("zwp_pointer_constraints_v1", 1),
+    ("zwp_pointer_gestures_v1", 3),
+    ("zwp_text_input_manager_v3", 1),
+    ("zwp_input_method_manager_v2", 1),
+    ("zwp_virtual_keyboard_manager_v1", 1),
+    ("zwp_tablet_manager_v2", 1),
+    ("zwp_primary_selection_device_manager_v1", 1),
+    ("zwp_idle_inhibit_manager_v1", 1),
+    ("ext_idle_notifier_v1", 2),
+    ("zwp_keyboard_shortcuts_inhibit_manager_v1", 1),
+    ("xdg_activation_v1", 1),
+    ("zwlr_foreign_toplevel_manager_v1", 3),
+    ("ext_foreign_toplevel_list_v1", 1),
+    ("zwlr_output_manager_v1", 4),
+    ("zwlr_output_power_manager_v1", 1),
+    ("zwlr_screencopy_manager_v1", 3),
+    ("zwlr_layer_shell_v1", 4),
+    ("zwlr_data_control_manager_v1", 2),
+    ("ext_data_control_manager_v1", 1),
+    ("wp_security_context_manager_v1", 1),
+    ("wayoa_info_v1", 1),
+    ("wayoa_permissions_v1", 1),
+];
+
+/// Process information about a connected client
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    /// Compositor-side client ID
+    pub id: ClientId,
+    /// Process ID
+    pub pid: Option<u32>,
+    /// Path of the client executable
+    pub executable: Option<String>,
+    /// Quirks matched by executable
+    pub quirks: Quirks,
+    /// Tag of the socket the client connected through, or the label of its
+    /// security context, if any
+    pub context: Option<String>,
+    /// Metadata of the security context the client connected through
+    pub security_context: Option<SecurityContext>,
+}
+
+/// Data attached to every Wayland client
+#[derive(Debug, Default)]
+pub struct ClientState {
+    /// Set right after the client is inserted into the display
+    info: OnceLock<ClientInfo>,
+    /// Keeps the client counted against the connection limit
+    _connection: Option<ConnectionGuard>,
+    /// Journal the disconnect is recorded in
+    journal: Option<Journal>,
+}
+
+impl ClientState {
+    /// Get the client information
+    pub fn info(&self) -> Option<&ClientInfo> {
+        self.info.get()
+    }
+}
+
+impl ClientData for ClientState {
+    fn disconnected(&self, _client_id: BackendClientId, reason: DisconnectReason) {
+        debug!(
+            "Client {:?} disconnected: {:?}",
+            self.info().map(|info| info.id),
+            reason
+        );
+        if let (Some(journal), Some(info)) = (&self.journal, self.info()) {
+            journal.record(JournalEvent::ClientDisconnected {
+                client: info.id.0,
+                executable: info.executable.clone(),
+                reason: match reason {
+                    DisconnectReason::ConnectionClosed => "connection closed".to_string(),
+                    DisconnectReason::ProtocolError(e) => format!("protocol error: {}", e),
+                },
+            });
+        }
+    }
+}
+
+/// Get the information of a Wayland client
+pub fn client_info(client: &Client) -> Option<&ClientInfo> {
+    client.get_data::<ClientState>()?.info()
+}
+
+/// Data attached to every global
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalData {
+    /// Interface name
+    pub interface: &'static str,
+    /// Version cap this copy of the global was created for
+    pub version_cap: Option<u32>,
+}
+
+impl GlobalData {
+    /// Decide whether a client can see this global
+    ///
+    /// Clients with a version cap for the interface see only the capped
+    /// copy, every other client sees only the default global. Clients of a
+    /// tagged socket or a security context don't see privileged globals at
+    /// all.
+    pub fn can_view(&self, client: &Client) -> bool {
+        let info = client_info(client);
+        if info.is_some_and(|info| info.context.is_some())
+            && PRIVILEGED_GLOBALS.contains(&self.interface)
+        {
+            return false;
+        }
+        let cap = info.and_then(|info| info.quirks.max_version(self.interface));
+        self.version_cap == cap
+    }
+}
+
+/// Create a global by interface name
+pub(super) fn create_global(
+    dh: &DisplayHandle,
+    interface: &'static str,
+    version: u32,
+    version_cap: Option<u32>,
+) -> Option<GlobalId> {
+    let data = GlobalData {
+        interface,
+        version_cap,
+    };
+    let id = match interface {
+        "wl_compositor" => {
+            dh.create_global::<ServerState, wl_compositor::WlCompositor, _>(version, data)
+        }
+        "wl_subcompositor" => {
+            dh.create_global::<ServerState, wl_subcompositor::WlSubcompositor, _>(version, data)
+        }
+        "wl_shm" => dh.create_global::<ServerState, wl_shm::WlShm, _>(version, data),
+        "wl_seat" => dh.create_global::<ServerState, wl_seat::WlSeat, _>(version, data),
+        "wl_output" => dh.create_global::<ServerState, wl_output::WlOutput, _>(version, data),
+        "wl_data_device_manager" => dh.create_global::<
+            ServerState,
+            wl_data_device_manager::WlDataDeviceManager,
+            _,
+        >(version, data),
+        "wl_fixes" => dh.create_global::<ServerState, wl_fixes::WlFixes, _>(version, data),
+        "xdg_wm_base" => dh.create_global::<ServerState, xdg_wm_base::XdgWmBase, _>(version, data),
+        "wp_viewporter" => {
+            dh.create_global::<ServerState, wp_viewporter::WpViewporter, _>(version, data)
+        }
+        "zxdg_decoration_manager_v1" => dh
+            .create_global::<ServerState, zxdg_decoration_manager_v1::ZxdgDecorationManagerV1, _>(
+                version, data,
+            ),
+        "wp_fractional_scale_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+            _,
+        >(version, data),
+        "wp_presentation" => {
+            dh.create_global::<ServerState, wp_presentation::WpPresentation, _>(version, data)
+        }
+        "wp_content_type_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_content_type_manager_v1::WpContentTypeManagerV1,
+            _,
+        >(version, data),
+        "wp_tearing_control_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_tearing_control_manager_v1::WpTearingControlManagerV1,
+            _,
+        >(version, data),
+        "wp_fifo_manager_v1" => {
+            dh.create_global::<ServerState, wp_fifo_manager_v1::WpFifoManagerV1, _>(version, data)
+        }
+        "wp_commit_timing_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_commit_timing_manager_v1::WpCommitTimingManagerV1,
+            _,
+        >(version, data),
+        "wp_alpha_modifier_v1" => dh.create_global::<
+            ServerState,
+            wp_alpha_modifier_v1::WpAlphaModifierV1,
+            _,
+        >(version, data),
+        "wp_single_pixel_buffer_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1,
+            _,
+        >(version, data),
+        "wp_cursor_shape_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+            _,
+        >(version, data),
+        "zwp_relative_pointer_manager_v1" => dh.create_global::<
+            ServerState,
+            zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1,
+            _,
+        >(version, data),
+        "wp_pointer_warp_v1" => {
+            dh.create_global::<ServerState, wp_pointer_warp_v1::WpPointerWarpV1, _>(version, data)
+        }
+        "zwp_pointer_constraints_v1" => dh.create_global::<
+            ServerState,
+            zwp_pointer_constraints_v1::ZwpPointerConstraintsV1,
+            _,
+        >(version, data),
+        "zwp_pointer_gestures_v1" => dh.create_global::<
+            ServerState,
+            zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+            _,
+        >(version, data),
+        "zwp_text_input_manager_v3" => dh.create_global::<
+            ServerState,
+            zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+            _,
+        >(version, data),
+        "zwp_input_method_manager_v2" => dh.create_global::<
+            ServerState,
+            zwp_input_method_manager_v2::ZwpInputMethodManagerV2,
+            _,
+        >(version, data),
+        "zwp_virtual_keyboard_manager_v1" => dh.create_global::<
+            ServerState,
+            zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+            _,
+        >(version, data),
+        "zwp_primary_selection_device_manager_v1" => dh.create_global::<
+            ServerState,
+            zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+            _,
+        >(version, data),
+        "zwp_idle_inhibit_manager_v1" => dh.create_global::<
+            ServerState,
+            zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1,
+            _,
+        >(version, data),
+        "ext_idle_notifier_v1" => {
+            dh.create_global::<ServerState, ext_idle_notifier_v1::ExtIdleNotifierV1, _>(
+                version, data,
+            )
+        }
+        "zwp_keyboard_shortcuts_inhibit_manager_v1" => dh.create_global::<
+            ServerState,
+            zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+            _,
+        >(version, data),
+        "xdg_wm_dialog_v1" => {
+            dh.create_global::<ServerState, xdg_wm_dialog_v1::XdgWmDialogV1, _>(version, data)
+        }
+        "xdg_toplevel_icon_manager_v1" => dh.create_global::<
+            ServerState,
+            xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1,
+            _,
+        >(version, data),
+        "xdg_activation_v1" => {
+            dh.create_global::<ServerState, xdg_activation_v1::XdgActivationV1, _>(version, data)
+        }
+        "zwlr_foreign_toplevel_manager_v1" => dh.create_global::<
+            ServerState,
+            zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+            _,
+        >(version, data),
+        "ext_foreign_toplevel_list_v1" => dh.create_global::<
+            ServerState,
+            ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+            _,
+        >(version, data),
+        "zwlr_output_manager_v1" => dh
+            .create_global::<ServerState, zwlr_output_manager_v1::ZwlrOutputManagerV1, _>(
+                version, data,
+            ),
+        "zwp_tablet_manager_v2" => {
+            dh.create_global::<ServerState, zwp_tablet_manager_v2::ZwpTabletManagerV2, _>(
+                version, data,
+            )
+        }
+        "zwlr_output_power_manager_v1" => dh.create_global::<
+            ServerState,
+            zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+            _,
+        >(version, data),
+        "zwlr_screencopy_manager_v1" => dh.create_global::<
+            ServerState,
+            zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+            _,
+        >(version, data),
+        "zwlr_layer_shell_v1" => dh.create_global::<
+            ServerState,
+            zwlr_layer_shell_v1::ZwlrLayerShellV1,
+            _,
+        >(version, data),
+        "zwlr_data_control_manager_v1" => dh.create_global::<
+            ServerState,
+            zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+            _,
+        >(version, data),
+        "ext_data_control_manager_v1" => dh.create_global::<
+            ServerState,
+            ext_data_control_manager_v1::ExtDataControlManagerV1,
+            _,
+        >(version, data),
+        "wp_security_context_manager_v1" => dh.create_global::<
+            ServerState,
+            wp_security_context_manager_v1::WpSecurityContextManagerV1,
+            _,
+        >(version, data),
+        "wayoa_info_v1" => {
+            dh.create_global::<ServerState, wayoa_info_v1::WayoaInfoV1, _>(version, data)
+        }
+        "wayoa_permissions_v1" => {
+            dh.create_global::<ServerState, wayoa_permissions_v1::WayoaPermissionsV1, _>(
+                version, data,
+            )
+        }
+        _ => return None,
+    };
+    Some(id)
+}
+
+/// Drop version caps that are unknown or not below the default version
+fn effective_caps(max_versions: &mut HashMap<String, u32>) {
+    max_versions.retain(|interface, cap| {
+        GLOBALS
+            .iter()
+            .any(|(name, version)| name == interface && *cap >= 1 && *cap < *version)
+    });
+}
+
+/// Look up the executable of a process
+#[cfg(target_os = "macos")]
+fn executable_for_pid(pid: u32) -> Option<String> {
+    let mut buffer = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+    // SAFETY: proc_pidpath writes at most buffer.len() bytes
+    let len = unsafe {
+        libc::proc_pidpath(
+            pid as libc::c_int,
+            buffer.as_mut_ptr().cast(),
+            buffer.len() as u32,
+        )
+    };
+    if len <= 0 {
+        return None;
+    }
+    buffer.truncate(len as usize);
+    Some(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// Look up the executable of a process
+#[cfg(not(target_os = "macos"))]
+fn executable_for_pid(pid: u32) -> Option<String> {
+    std::fs::read_link(format!("/proc/{}/exe", pid))
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Insert a newly connected client into the display
+///
+/// Connections over the client limits are refused, returning None.
+/// `context` is the tag of the socket the client connected through, and
+/// `security_context` the metadata of a sandbox engine's socket. Quirks
+/// match the app_id of the security context.
+pub(super) fn insert_client(
+    dh: &mut DisplayHandle,
+    stream: UnixStream,
+    context: Option<String>,
+    security_context: Option<SecurityContext>,
+    state: &mut ServerState,
+) -> std::io::Result<Option<Client>> {
+    // The limiter reports rejections itself; dropping the stream closes it
+    let Ok(connection) = state.client_limits.admit(Instant::now()) else {
+        return Ok(None);
+    };
+    let client_state = Arc::new(ClientState {
+        info: OnceLock::new(),
+        _connection: Some(connection),
+        journal: Some(state.journal.clone()),
+    });
+    let client = dh.insert_client(stream, client_state.clone())?;
+    let id = state.compositor.add_client();
+
+    let pid = client
+        .get_credentials(dh)
+        .ok()
+        .and_then(|credentials| u32::try_from(credentials.pid).ok())
+        .filter(|pid| *pid > 0);
+    let executable = pid.and_then(executable_for_pid);
+
+    let app_id = security_context.as_ref().and_then(|c| c.app_id.as_deref());
+    let mut quirks = Quirks::resolve(&state.config.quirks, executable.as_deref(), app_id);
+    let context = context.or_else(|| security_context.as_ref().map(SecurityContext::label));
+    effective_caps(&mut quirks.max_versions);
+    if !quirks.is_empty() {
+        info!("Applying quirks to {:?}: {:?}", executable, quirks);
+    }
+
+    for (interface, cap) in &quirks.max_versions {
+        let Some(&(interface, _)) = GLOBALS.iter().find(|(name, _)| name == interface) else {
+            continue;
+        };
+        if let Entry::Vacant(entry) = state.resources.capped_globals.entry((interface, *cap)) {
+            if let Some(global) = create_global(dh, interface, *cap, Some(*cap)) {
+                debug!("Registered {} capped at version {}", interface, cap);
+                entry.insert(global);
+            }
+        }
+    }
+
+    state.journal.record(JournalEvent::ClientConnected {
+        client: id.0,
+        pid,
+        executable: executable.clone(),
+        context: context.clone(),
+    });
+    let _ = client_state.info.set(ClientInfo {
+        id,
+        pid,
+        executable,
+        quirks,
+        context,
+        security_context,
+    });
+    Ok(Some(client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_caps() {
+        let mut caps = HashMap::from([
+            ("wl_seat".to_string(), 5),
+            ("wl_shm".to_string(), 1),
+            ("xdg_wm_base".to_string(), 0),
+            ("wl_bogus".to_string(), 1),
+        ]);
+        effective_caps(&mut caps);
+        assert_eq!(caps, HashMap::from([("wl_seat".to_string(), 5)]));
+    }
+
+    #[test]
+    fn test_executable_for_pid() {
+        let executable = executable_for_pid(std::process::id()).unwrap();
+        assert!(!executable.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+//! wp_fifo_manager_v1 and wp_commit_timing_manager_v1 objects
+//!
+//! Fifo barriers and target times are set with a surface's commits, see
+//! [`crate::compositor::commit_queue`]. The latching deadline that releases
+//! queued commits is each frame composed for the primary output, so they
+//! are applied in step with its refresh. A commit that gets queued
+//! schedules that frame itself, and without an output to pace against
+//! queued commits are applied at once, so a surface that isn't shown never
+//! stalls.
+//!
+//! A target time the next refresh already meets is dropped before the
+//! commit, so the commit isn't held back a frame for nothing. While timed
+//! commits remain, a frame is scheduled every refresh to check them.
+//!
+//! Frame callbacks and presentation feedback are queued when the commit
+//! is made, not when its state is applied.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use wayland_protocols::wp::commit_timing::v1::server::{
+    wp_commit_timer_v1, wp_commit_timing_manager_v1,
+};
+use wayland_protocols::wp::fifo::v1::server::{wp_fifo_manager_v1, wp_fifo_v1};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::util::clock;
+
+impl GlobalDispatch<wp_fifo_manager_v1::WpFifoManagerV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_fifo_manager_v1::WpFifoManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_fifo_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_fifo_manager_v1::WpFifoManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_fifo_manager_v1::WpFifoManagerV1,
+        request: wp_fifo_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_fifo_manager_v1::Request::GetFifo { id, surface } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if !state.resources.fifos.insert(surface_id) {
+                resource.post_error(
+                    wp_fifo_manager_v1::Error::AlreadyExists,
+                    "surface already has a fifo object",
+                );
+                return;
+            }
+            data_init.init(id, surface_id);
+        }
+    }
+}
+
+impl Dispatch<wp_fifo_v1::WpFifoV1, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_fifo_v1::WpFifoV1,
+        request: wp_fifo_v1::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let barrier = match request {
+            wp_fifo_v1::Request::SetBarrier => true,
+            wp_fifo_v1::Request::WaitBarrier => false,
+            _ => return,
+        };
+        let Some(surface) = state.compositor.surfaces.get_mut(*surface_id) else {
+            resource.post_error(
+                wp_fifo_v1::Error::SurfaceDestroyed,
+                "the surface no longer exists",
+            );
+            return;
+        };
+        match barrier {
+            true => surface.pending.fifo_barrier = true,
+            false => surface.pending.fifo_wait = true,
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wp_fifo_v1::WpFifoV1,
+        surface_id: &SurfaceId,
+    ) {
+        state.resources.fifos.remove(surface_id);
+    }
+}
+
+impl GlobalDispatch<wp_commit_timing_manager_v1::WpCommitTimingManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_commit_timing_manager_v1::WpCommitTimingManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_commit_timing_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_commit_timing_manager_v1::WpCommitTimingManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_commit_timing_manager_v1::WpCommitTimingManagerV1,
+        request: wp_commit_timing_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_commit_timing_manager_v1::Request::GetTimer { id, surface } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if !state.resources.commit_timers.insert(surface_id) {
+                resource.post_error(
+                    wp_commit_timing_manager_v1::Error::CommitTimerExists,
+                    "surface already has a commit timer",
+                );
+                return;
+            }
+            data_init.init(id, surface_id);
+        }
+    }
+}
+
+impl Dispatch<wp_commit_timer_v1::WpCommitTimerV1, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_commit_timer_v1::WpCommitTimerV1,
+        request: wp_commit_timer_v1::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_commit_timer_v1::Request::SetTimestamp {
+            tv_sec_hi,
+            tv_sec_lo,
+            tv_nsec,
+        } = request
+        {
+            if tv_nsec >= 1_000_000_000 {
+                resource.post_error(
+                    wp_commit_timer_v1::Error::InvalidTimestamp,
+                    "tv_nsec is out of range",
+                );
+                return;
+            }
+            let Some(surface) = state.compositor.surfaces.get_mut(*surface_id) else {
+                resource.post_error(
+                    wp_commit_timer_v1::Error::SurfaceDestroyed,
+                    "the surface no longer exists",
+                );
+                return;
+            };
+            if surface.pending.target_time.is_some() {
+                resource.post_error(
+                    wp_commit_timer_v1::Error::TimestampExists,
+                    "the commit already has a timestamp",
+                );
+                return;
+            }
+            let secs = (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo);
+            surface.pending.target_time = Some(Duration::new(secs, tv_nsec));
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wp_commit_timer_v1::WpCommitTimerV1,
+        surface_id: &SurfaceId,
+    ) {
+        state.resources.commit_timers.remove(surface_id);
+    }
+}
+
+impl ServerState {
+    /// Drop the target time of a surface's pending state if the next
+    /// refresh of the primary output already meets it
+    pub fn check_target_time(&mut self, surface: SurfaceId) {
+        let refresh = self.compositor.outputs.primary().and_then(|output| {
+            self.scheduler
+                .next_refresh(output.id, Instant::now())
+                .map(clock::at)
+        });
+        let Some(surface) = self.compositor.surfaces.get_mut(surface) else {
+            return;
+        };
+        let due = surface
+            .pending
+            .target_time
+            .is_some_and(|time| refresh.is_none_or(|refresh| time <= refresh));
+        if due {
+            surface.pending.target_time = None;
+        }
+    }
+
+    /// Make sure a latching deadline comes for a queued commit
+    pub fn schedule_commit_release(&mut self) {
+        match self.compositor.outputs.primary() {
+            Some(output) => {
+                if self.scheduler.refresh(output.id).is_none() {
+                    self.scheduler.sync_output(output);
+                }
+                self.scheduler.schedule(output.id, Instant::now());
+            }
+            None => self.release_queued_commits(Duration::MAX),
+        }
+    }
+
+    /// Apply the queued commits that can be shown at the refresh at
+    /// `refresh` and present them
+    pub fn release_queued_commits(&mut self, refresh: Duration) {
+        let applied = self.compositor.surfaces.release_commits(refresh);
+        if applied.is_empty() {
+            return;
+        }
+        self.buffers_applied(&applied);
+        self.check_viewports(&applied);
+        let windows: HashSet<_> = applied
+            .iter()
+            .filter_map(|&surface| {
+                let root = self.compositor.surfaces.root(surface);
+                self.compositor.windows.window_for_surface(root)
+            })
+            .collect();
+        for window in windows {
+            self.schedule_present(window);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_released_without_output() {
+        let mut state = ServerState::new();
+        let surface = state.compositor.surfaces.create_surface();
+        let window = state.compositor.windows.create_window(surface);
+
+        let pending = &mut state.compositor.surfaces.get_mut(surface).unwrap().pending;
+        pending.fifo_barrier = true;
+        state.compositor.surfaces.commit(surface);
+        let pending = &mut state.compositor.surfaces.get_mut(surface).unwrap().pending;
+        pending.fifo_wait = true;
+        pending.scale = Some(2);
+        assert!(state.compositor.surfaces.commit(surface).is_empty());
+        assert!(state.compositor.surfaces.has_commit_queue(surface));
+
+        // Nothing paces the surface, so the barrier clears right away
+        state.schedule_commit_release();
+        assert!(!state.compositor.surfaces.has_commit_queue(surface));
+        assert_eq!(state.compositor.surfaces.get(surface).unwrap().scale, 2);
+        assert!(state.backend.pending_presents.contains(&window));
+    }
+
+    #[test]
+    fn test_due_target_time_dropped() {
+        let mut state = ServerState::new();
+        let surface = state.compositor.surfaces.create_surface();
+
+        // Without an output any target time is due
+        let pending = &mut state.compositor.surfaces.get_mut(surface).unwrap().pending;
+        pending.target_time = Some(clock::now() + Duration::from_secs(60));
+        state.check_target_time(surface);
+        let pending = &state.compositor.surfaces.get(surface).unwrap().pending;
+        assert_eq!(pending.target_time, None);
+    }
+}
@@ -0,0 +1,292 @@
+//! Tagged client sockets and security contexts
+//!
+//! `wayoa proxy --tag` asks for a listening socket of its own over IPC.
+//! Clients connecting through it are tagged with the proxy's name, which
+//! is journaled with the connection, and don't see privileged globals such
+//! as the input method manager, the keyboard shortcuts inhibitor or the
+//! foreign toplevel lists. A tag keeps its socket until the compositor
+//! exits.
+//!
+//! Sandbox engines hand over listening sockets of their own through
+//! wp_security_context_v1, along with the metadata of the sandboxed
+//! application. Their clients are restricted the same way, and their
+//! sockets are closed once the engine hangs up the close FD.
+
+use std::os::fd::OwnedFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+use log::{info, warn};
+use rustix::event::{poll, PollFd, PollFlags, Timespec};
+
+use super::socket;
+
+/// Longest tag accepted
+const MAX_TAG_LEN: usize = 64;
+
+/// Globals hidden from tagged clients
+pub const PRIVILEGED_GLOBALS: &[&str] = &[
+    "zwp_input_method_manager_v2",
+    "zwp_virtual_keyboard_manager_v1",
+    "zwp_keyboard_shortcuts_inhibit_manager_v1",
+    "zwlr_foreign_toplevel_manager_v1",
+    "ext_foreign_toplevel_list_v1",
+    "zwlr_output_manager_v1",
+    "zwlr_output_power_manager_v1",
+    "zwlr_screencopy_manager_v1",
+    "zwlr_data_control_manager_v1",
+    "ext_data_control_manager_v1",
+    "wp_security_context_manager_v1",
+];
+
+/// Metadata a sandbox engine attached to a security context
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecurityContext {
+    /// Reverse-DNS name of the sandbox engine, such as org.flatpak
+    pub sandbox_engine: Option<String>,
+    /// Sandbox-specific application ID
+    pub app_id: Option<String>,
+    /// Sandbox-specific ID of the running instance
+    pub instance_id: Option<String>,
+}
+
+impl SecurityContext {
+    /// Describe the context for logs and the journal, such as
+    /// `org.flatpak/org.gnome.Maps`
+    pub fn label(&self) -> String {
+        let engine = self.sandbox_engine.as_deref().unwrap_or("sandbox");
+        match &self.app_id {
+            Some(app_id) => format!("{}/{}", engine, app_id),
+            None => engine.to_string(),
+        }
+    }
+}
+
+/// A listening socket handed over by a sandbox engine
+#[derive(Debug)]
+struct SecurityListener {
+    context: SecurityContext,
+    listener: UnixListener,
+    /// Signals hangup when the socket should be closed
+    close_fd: OwnedFd,
+}
+
+impl SecurityListener {
+    /// Check whether the engine asked to stop accepting connections
+    fn closed(&self) -> bool {
+        let mut fds = [PollFd::new(&self.close_fd, PollFlags::IN)];
+        let timeout = Timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        match poll(&mut fds, Some(&timeout)) {
+            Ok(0) => false,
+            Ok(_) => fds[0]
+                .revents()
+                .intersects(PollFlags::IN | PollFlags::HUP | PollFlags::ERR),
+            Err(_) => true,
+        }
+    }
+}
+
+/// A listening socket for tagged clients
+#[derive(Debug)]
+struct ContextSocket {
+    tag: String,
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+/// Listening sockets by tag, and those of security contexts
+#[derive(Debug, Default)]
+pub struct ContextSockets {
+    sockets: Vec<ContextSocket>,
+    listeners: Vec<SecurityListener>,
+}
+
+impl ContextSockets {
+    /// Create an empty set of sockets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the socket of a tag, creating it in the runtime directory
+    pub fn socket(&mut self, tag: &str) -> Result<PathBuf, ContextError> {
+        let dir = socket::runtime_dir().map_err(|e| ContextError::Bind(e.to_string()))?;
+        self.socket_in(tag, dir)
+    }
+
+    fn socket_in(&mut self, tag: &str, dir: PathBuf) -> Result<PathBuf, ContextError> {
+        let valid = tag
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if tag.is_empty() || tag.len() > MAX_TAG_LEN || !valid {
+            return Err(ContextError::InvalidTag(tag.to_string()));
+        }
+        if let Some(existing) = self.sockets.iter().find(|s| s.tag == tag) {
+            return Ok(existing.path.clone());
+        }
+
+        let path = dir.join(format!("wayoa-{}-{}", std::process::id(), tag));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+            .map_err(|e| ContextError::Bind(format!("{}: {}", path.display(), e)))?;
+        info!("Listening for clients tagged {} on {}", tag, path.display());
+        self.sockets.push(ContextSocket {
+            tag: tag.to_string(),
+            path: path.clone(),
+            listener,
+        });
+        Ok(path)
+    }
+
+    /// Accept the pending connections, with the tag of their socket
+    pub fn accept(&self) -> Vec<(String, UnixStream)> {
+        let mut accepted = Vec::new();
+        for socket in &self.sockets {
+            loop {
+                match socket.listener.accept() {
+                    Ok((stream, _)) => accepted.push((socket.tag.clone(), stream)),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!("Failed to accept a client tagged {}: {}", socket.tag, e);
+                        break;
+                    }
+                }
+            }
+        }
+        accepted
+    }
+
+    /// Accept connections on a socket of a security context until
+    /// `close_fd` hangs up
+    pub fn add_listener(
+        &mut self,
+        context: SecurityContext,
+        listener: UnixListener,
+        close_fd: OwnedFd,
+    ) {
+        info!("Accepting clients of security context {}", context.label());
+        self.listeners.push(SecurityListener {
+            context,
+            listener,
+            close_fd,
+        });
+    }
+
+    /// Accept the pending connections of security contexts, with their
+    /// metadata, dropping the sockets whose engine hung up
+    pub fn accept_secure(&mut self) -> Vec<(SecurityContext, UnixStream)> {
+        self.listeners.retain(|listener| {
+            let closed = listener.closed();
+            if closed {
+                info!("Security context {} closed", listener.context.label());
+            }
+            !closed
+        });
+        let mut accepted = Vec::new();
+        for listener in &self.listeners {
+            loop {
+                match listener.listener.accept() {
+                    Ok((stream, _)) => accepted.push((listener.context.clone(), stream)),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!(
+                            "Failed to accept a client of security context {}: {}",
+                            listener.context.label(),
+                            e
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+        accepted
+    }
+}
+
+/// Take a listening socket handed over by a sandbox engine
+pub fn security_listener(listen_fd: OwnedFd) -> Result<UnixListener, ContextError> {
+    let listener = UnixListener::from(listen_fd);
+    listener
+        .set_nonblocking(true)
+        .and_then(|()| listener.local_addr())
+        .map_err(|e| ContextError::InvalidListener(e.to_string()))?;
+    Ok(listener)
+}
+
+impl Drop for ContextSockets {
+    fn drop(&mut self) {
+        for socket in &self.sockets {
+            let _ = std::fs::remove_file(&socket.path);
+        }
+    }
+}
+
+/// Tagged socket errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ContextError {
+    #[error("Invalid tag {0:?}, expected up to 64 letters, digits, '.', '_' or '-'")]
+    InvalidTag(String),
+    #[error("Failed to create a tagged socket: {0}")]
+    Bind(String),
+    #[error("Security context socket isn't listening: {0}")]
+    InvalidListener(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagged_sockets() {
+        let dir = std::env::temp_dir();
+        let mut sockets = ContextSockets::new();
+        assert!(matches!(
+            sockets.socket_in("../x", dir.clone()),
+            Err(ContextError::InvalidTag(_))
+        ));
+
+        let tag = format!("test-{}", std::process::id());
+        let path = sockets.socket_in(&tag, dir.clone()).unwrap();
+        assert_eq!(sockets.socket_in(&tag, dir).unwrap(), path);
+        assert!(sockets.accept().is_empty());
+
+        let _client = UnixStream::connect(&path).unwrap();
+        let accepted = sockets.accept();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].0, tag);
+
+        drop(sockets);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_security_context_listener() {
+        let path = std::env::temp_dir().join(format!("wayoa-test-sc-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let (close_read, close_write) = rustix::pipe::pipe().unwrap();
+        let context = SecurityContext {
+            sandbox_engine: Some("org.flatpak".to_string()),
+            app_id: Some("org.example.App".to_string()),
+            instance_id: None,
+        };
+        assert_eq!(context.label(), "org.flatpak/org.example.App");
+
+        let mut sockets = ContextSockets::new();
+        let listener = security_listener(OwnedFd::from(listener)).unwrap();
+        sockets.add_listener(context.clone(), listener, close_read);
+        let _client = UnixStream::connect(&path).unwrap();
+        let accepted = sockets.accept_secure();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].0, context);
+
+        // Hanging up the close FD stops accepting connections
+        drop(close_write);
+        assert!(sockets.accept_secure().is_empty());
+        assert!(sockets.listeners.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
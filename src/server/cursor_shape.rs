@@ -0,0 +1,180 @@
+//! wp_cursor_shape_manager_v1 objects
+//!
+//! Cursor shape devices let clients ask for a named cursor instead of
+//! attaching a cursor surface to their wl_pointer. Requests from clients
+//! without pointer focus are ignored, like wl_pointer.set_cursor. On macOS
+//! the shape is shown as the matching NSCursor.
+
+use log::debug;
+use wayland_protocols::wp::cursor_shape::v1::server::{
+    wp_cursor_shape_device_v1, wp_cursor_shape_manager_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::input::CursorShape;
+
+impl GlobalDispatch<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_cursor_shape_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+        request: wp_cursor_shape_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wp_cursor_shape_manager_v1::Request::GetPointer {
+                cursor_shape_device,
+                ..
+            } => {
+                data_init.init(cursor_shape_device, ());
+            }
+            wp_cursor_shape_manager_v1::Request::GetTabletToolV2 {
+                cursor_shape_device,
+                ..
+            } => {
+                // Tablet tools move the system cursor, so their shape is
+                // the pointer's
+                data_init.init(cursor_shape_device, ());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wp_cursor_shape_device_v1::WpCursorShapeDeviceV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &wp_cursor_shape_device_v1::WpCursorShapeDeviceV1,
+        request: wp_cursor_shape_device_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_cursor_shape_device_v1::Request::SetShape { shape, .. } = request {
+            let Some(shape) = cursor_shape(shape) else {
+                resource.post_error(
+                    wp_cursor_shape_device_v1::Error::InvalidShape,
+                    "unknown cursor shape",
+                );
+                return;
+            };
+            if !state.has_pointer_focus(client) {
+                debug!("Ignoring cursor shape {:?} without pointer focus", shape);
+                return;
+            }
+            state.set_cursor_shape(shape);
+        }
+    }
+}
+
+impl ServerState {
+    /// Show a named cursor
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        let pointer = self.compositor.seat.pointer_mut();
+        if pointer.cursor_shape() == Some(shape) {
+            return;
+        }
+        debug!("Cursor shape {:?}", shape);
+        pointer.set_cursor_shape(shape);
+
+        #[cfg(target_os = "macos")]
+        crate::backend::cocoa::app::set_cursor(shape);
+    }
+}
+
+/// Convert a protocol cursor shape
+fn cursor_shape(shape: WEnum<wp_cursor_shape_device_v1::Shape>) -> Option<CursorShape> {
+    use wp_cursor_shape_device_v1::Shape;
+
+    let WEnum::Value(shape) = shape else {
+        return None;
+    };
+    Some(match shape {
+        Shape::Default => CursorShape::Default,
+        Shape::ContextMenu => CursorShape::ContextMenu,
+        Shape::Help => CursorShape::Help,
+        Shape::Pointer => CursorShape::Pointer,
+        Shape::Progress => CursorShape::Progress,
+        Shape::Wait => CursorShape::Wait,
+        Shape::Cell => CursorShape::Cell,
+        Shape::Crosshair => CursorShape::Crosshair,
+        Shape::Text => CursorShape::Text,
+        Shape::VerticalText => CursorShape::VerticalText,
+        Shape::Alias => CursorShape::Alias,
+        Shape::Copy => CursorShape::Copy,
+        Shape::Move => CursorShape::Move,
+        Shape::NoDrop => CursorShape::NoDrop,
+        Shape::NotAllowed => CursorShape::NotAllowed,
+        Shape::Grab => CursorShape::Grab,
+        Shape::Grabbing => CursorShape::Grabbing,
+        Shape::EResize => CursorShape::EResize,
+        Shape::NResize => CursorShape::NResize,
+        Shape::NeResize => CursorShape::NeResize,
+        Shape::NwResize => CursorShape::NwResize,
+        Shape::SResize => CursorShape::SResize,
+        Shape::SeResize => CursorShape::SeResize,
+        Shape::SwResize => CursorShape::SwResize,
+        Shape::WResize => CursorShape::WResize,
+        Shape::EwResize => CursorShape::EwResize,
+        Shape::NsResize => CursorShape::NsResize,
+        Shape::NeswResize => CursorShape::NeswResize,
+        Shape::NwseResize => CursorShape::NwseResize,
+        Shape::ColResize => CursorShape::ColResize,
+        Shape::RowResize => CursorShape::RowResize,
+        Shape::AllScroll => CursorShape::AllScroll,
+        Shape::ZoomIn => CursorShape::ZoomIn,
+        Shape::ZoomOut => CursorShape::ZoomOut,
+        Shape::DndAsk => CursorShape::DndAsk,
+        Shape::AllResize => CursorShape::AllResize,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wp_cursor_shape_device_v1::Shape;
+
+    #[test]
+    fn test_cursor_shape() {
+        assert_eq!(
+            cursor_shape(WEnum::Value(Shape::Text)),
+            Some(CursorShape::Text)
+        );
+        assert_eq!(
+            cursor_shape(WEnum::Value(Shape::NwseResize)),
+            Some(CursorShape::NwseResize)
+        );
+        assert_eq!(cursor_shape(WEnum::Unknown(99)), None);
+
+        let mut state = ServerState::new();
+        state.set_cursor_shape(CursorShape::Grab);
+        let pointer = state.compositor.seat.pointer();
+        assert_eq!(pointer.cursor_shape(), Some(CursorShape::Grab));
+    }
+}
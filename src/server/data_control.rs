@@ -0,0 +1,417 @@
+//! zwlr_data_control_manager_v1 objects
+//!
+//! Clipboard managers read and set the selection and the primary selection
+//! without focus. Their sources are [`DataDeviceHandler`] sources like
+//! those of wl_data_device and the primary selection protocol, so all three
+//! share one clipboard. Every data control device is sent a new offer
+//! whenever either selection changes. The manager is a privileged global.
+//!
+//! ext_data_control_v1 is the same protocol under another name. Its objects
+//! are handled in [`super::ext_data_control`], sharing the devices and
+//! sources kept here.
+//!
+//! [`DataDeviceHandler`]: crate::protocol::DataDeviceHandler
+
+use std::collections::{HashMap, HashSet};
+use std::os::fd::{AsFd, BorrowedFd};
+
+use log::debug;
+use wayland_protocols::ext::data_control::v1::server::{
+    ext_data_control_device_v1, ext_data_control_offer_v1, ext_data_control_source_v1,
+};
+use wayland_protocols_wlr::data_control::v1::server::{
+    zwlr_data_control_device_v1, zwlr_data_control_manager_v1, zwlr_data_control_offer_v1,
+    zwlr_data_control_source_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::protocol::data_device::{DataOfferId, DataSourceId};
+
+/// A data control device of either protocol
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataControlDevice {
+    Wlr(zwlr_data_control_device_v1::ZwlrDataControlDeviceV1),
+    Ext(ext_data_control_device_v1::ExtDataControlDeviceV1),
+}
+
+impl DataControlDevice {
+    /// Check if the device is told about the primary selection
+    fn has_primary_selection(&self) -> bool {
+        match self {
+            DataControlDevice::Wlr(device) => device.version() >= 2,
+            DataControlDevice::Ext(_) => true,
+        }
+    }
+
+    /// Introduce an offer of a selection with its MIME types, or clear the
+    /// selection without one
+    ///
+    /// Returns false if the offer object couldn't be created.
+    fn offer(
+        &self,
+        offer: Option<DataOfferId>,
+        mime_types: &[String],
+        selection: Selection,
+    ) -> bool {
+        match self {
+            DataControlDevice::Wlr(device) => {
+                let resource = offer.and_then(|id| {
+                    create_offer::<zwlr_data_control_offer_v1::ZwlrDataControlOfferV1>(device, id)
+                });
+                if let Some(resource) = &resource {
+                    device.data_offer(resource);
+                    for mime_type in mime_types {
+                        resource.offer(mime_type.clone());
+                    }
+                }
+                match selection {
+                    Selection::Clipboard => device.selection(resource.as_ref()),
+                    Selection::Primary => device.primary_selection(resource.as_ref()),
+                }
+                offer.is_none() || resource.is_some()
+            }
+            DataControlDevice::Ext(device) => {
+                let resource = offer.and_then(|id| {
+                    create_offer::<ext_data_control_offer_v1::ExtDataControlOfferV1>(device, id)
+                });
+                if let Some(resource) = &resource {
+                    device.data_offer(resource);
+                    for mime_type in mime_types {
+                        resource.offer(mime_type.clone());
+                    }
+                }
+                match selection {
+                    Selection::Clipboard => device.selection(resource.as_ref()),
+                    Selection::Primary => device.primary_selection(resource.as_ref()),
+                }
+                offer.is_none() || resource.is_some()
+            }
+        }
+    }
+}
+
+/// Create an offer object for the client of a data control device
+fn create_offer<I>(device: &impl Resource, offer: DataOfferId) -> Option<I>
+where
+    I: Resource + 'static,
+    ServerState: Dispatch<I, DataOfferId>,
+{
+    let client = device.client()?;
+    let dh = device.handle().upgrade().map(DisplayHandle::from)?;
+    client
+        .create_resource::<I, _, ServerState>(&dh, device.version(), offer)
+        .ok()
+}
+
+/// A data control source of either protocol
+#[derive(Debug)]
+pub enum DataControlSource {
+    Wlr(zwlr_data_control_source_v1::ZwlrDataControlSourceV1),
+    Ext(ext_data_control_source_v1::ExtDataControlSourceV1),
+}
+
+/// Data control objects of all clients
+#[derive(Debug, Default)]
+pub struct DataControlResources {
+    /// Devices, one or more per client
+    devices: Vec<DataControlDevice>,
+    /// Sources by ID
+    sources: HashMap<DataSourceId, DataControlSource>,
+    /// Sources that were set as a selection, and can't be changed or set
+    /// again
+    used: HashSet<DataSourceId>,
+}
+
+impl DataControlResources {
+    /// Ask a data control source to write its data
+    ///
+    /// Returns false if the source isn't a data control source.
+    pub fn send(&self, source: DataSourceId, mime_type: String, fd: BorrowedFd<'_>) -> bool {
+        match self.sources.get(&source) {
+            Some(DataControlSource::Wlr(source)) => source.send(mime_type, fd),
+            Some(DataControlSource::Ext(source)) => source.send(mime_type, fd),
+            None => return false,
+        }
+        true
+    }
+
+    /// Tell a data control source it's no longer a selection
+    pub fn cancel(&self, source: DataSourceId) {
+        match self.sources.get(&source) {
+            Some(DataControlSource::Wlr(source)) => source.cancelled(),
+            Some(DataControlSource::Ext(source)) => source.cancelled(),
+            None => {}
+        }
+    }
+
+    /// Check if a source was set as a selection
+    pub fn is_used(&self, source: DataSourceId) -> bool {
+        self.used.contains(&source)
+    }
+}
+
+/// Selection a data control offer is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+impl GlobalDispatch<zwlr_data_control_manager_v1::ZwlrDataControlManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_data_control_manager_v1::ZwlrDataControlManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwlr_data_control_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwlr_data_control_manager_v1::ZwlrDataControlManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+        request: zwlr_data_control_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_data_control_manager_v1::Request::CreateDataSource { id } => {
+                let source_id = state.data_device.create_data_source();
+                let source = data_init.init(id, source_id);
+                state.add_data_control_source(source_id, DataControlSource::Wlr(source));
+            }
+            zwlr_data_control_manager_v1::Request::GetDataDevice { id, seat: _ } => {
+                let device = data_init.init(id, ());
+                state.add_data_control_device(DataControlDevice::Wlr(device));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwlr_data_control_device_v1::ZwlrDataControlDeviceV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+        request: zwlr_data_control_device_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (source, selection) = match request {
+            zwlr_data_control_device_v1::Request::SetSelection { source } => {
+                (source, Selection::Clipboard)
+            }
+            zwlr_data_control_device_v1::Request::SetPrimarySelection { source } => {
+                (source, Selection::Primary)
+            }
+            _ => return,
+        };
+        let source = source.and_then(|source| source.data::<DataSourceId>().copied());
+        if !state.set_data_control_selection(source, selection) {
+            resource.post_error(
+                zwlr_data_control_device_v1::Error::UsedSource,
+                "The source was already used",
+            );
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+        _data: &(),
+    ) {
+        state.remove_data_control_device(&DataControlDevice::Wlr(resource.clone()));
+    }
+}
+
+impl Dispatch<zwlr_data_control_source_v1::ZwlrDataControlSourceV1, DataSourceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+        request: zwlr_data_control_source_v1::Request,
+        data: &DataSourceId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_data_control_source_v1::Request::Offer { mime_type } = request {
+            if state.resources.data_controls.is_used(*data) {
+                resource.post_error(
+                    zwlr_data_control_source_v1::Error::InvalidOffer,
+                    "The source was already set as a selection",
+                );
+                return;
+            }
+            if let Some(source) = state.data_device.get_source_mut(*data) {
+                source.offer(mime_type);
+            }
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+        data: &DataSourceId,
+    ) {
+        state.remove_data_control_source(*data);
+    }
+}
+
+impl Dispatch<zwlr_data_control_offer_v1::ZwlrDataControlOfferV1, DataOfferId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+        request: zwlr_data_control_offer_v1::Request,
+        data: &DataOfferId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Request::Receive { mime_type, fd } = request {
+            state.receive_data_control_offer(*data, mime_type, fd.as_fd());
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+        data: &DataOfferId,
+    ) {
+        state.data_device.destroy_offer(*data);
+    }
+}
+
+impl ServerState {
+    /// Track a new data control source
+    pub(super) fn add_data_control_source(&mut self, id: DataSourceId, source: DataControlSource) {
+        self.resources.data_controls.sources.insert(id, source);
+    }
+
+    /// Forget a destroyed data control source, clearing the selections it
+    /// was set as
+    pub(super) fn remove_data_control_source(&mut self, id: DataSourceId) {
+        let selected = self.data_device.selection().map(|source| source.id) == Some(id);
+        let primary = self.data_device.primary_selection().map(|source| source.id) == Some(id);
+        self.data_device.destroy_source(id);
+        let controls = &mut self.resources.data_controls;
+        controls.sources.remove(&id);
+        controls.used.remove(&id);
+        if selected {
+            self.reoffer_selection();
+        }
+        if primary {
+            self.reoffer_primary_selection();
+        }
+    }
+
+    /// Track a new data control device, sending it the current selections
+    pub(super) fn add_data_control_device(&mut self, device: DataControlDevice) {
+        debug!("Creating data control device");
+        self.offer_data_control(&device, Selection::Clipboard);
+        if device.has_primary_selection() {
+            self.offer_data_control(&device, Selection::Primary);
+        }
+        self.resources.data_controls.devices.push(device);
+    }
+
+    /// Forget a destroyed data control device
+    pub(super) fn remove_data_control_device(&mut self, device: &DataControlDevice) {
+        self.resources
+            .data_controls
+            .devices
+            .retain(|other| other != device);
+    }
+
+    /// Set a selection on behalf of a clipboard manager
+    ///
+    /// Returns false if the source was already set as a selection.
+    pub(super) fn set_data_control_selection(
+        &mut self,
+        source: Option<DataSourceId>,
+        selection: Selection,
+    ) -> bool {
+        if let Some(id) = source {
+            if !self.resources.data_controls.used.insert(id) {
+                return false;
+            }
+        }
+        debug!("Data control sets {:?} to {:?}", selection, source);
+        match selection {
+            Selection::Clipboard => self.replace_selection(source),
+            Selection::Primary => self.replace_primary_selection(source),
+        }
+        true
+    }
+
+    /// Ask the source of a data control offer to write its data
+    pub(super) fn receive_data_control_offer(
+        &self,
+        offer: DataOfferId,
+        mime_type: String,
+        fd: BorrowedFd<'_>,
+    ) {
+        let source = self.data_device.get_offer(offer).map(|o| o.source_id);
+        if !source.is_some_and(|source| self.send_source(source, mime_type, fd)) {
+            debug!("Data control offer {:?} has no source", offer);
+        }
+    }
+
+    /// Send every data control device an offer of the changed clipboard
+    /// selection
+    pub(super) fn broadcast_data_control_selection(&mut self) {
+        for device in self.resources.data_controls.devices.clone() {
+            self.offer_data_control(&device, Selection::Clipboard);
+        }
+    }
+
+    /// Send every data control device an offer of the changed primary
+    /// selection
+    pub(super) fn broadcast_data_control_primary_selection(&mut self) {
+        for device in self.resources.data_controls.devices.clone() {
+            if device.has_primary_selection() {
+                self.offer_data_control(&device, Selection::Primary);
+            }
+        }
+    }
+
+    /// Send a new offer of a selection to a data control device
+    fn offer_data_control(&mut self, device: &DataControlDevice, selection: Selection) {
+        let source = match selection {
+            Selection::Clipboard => self.data_device.selection(),
+            Selection::Primary => self.data_device.primary_selection(),
+        };
+        let offer = source
+            .map(|source| source.id)
+            .and_then(|id| self.data_device.create_offer(id));
+        let mime_types = offer
+            .and_then(|id| self.data_device.get_offer(id))
+            .map(|offer| offer.mime_types.clone())
+            .unwrap_or_default();
+        if !device.offer(offer, &mime_types, selection) {
+            if let Some(offer) = offer {
+                self.data_device.destroy_offer(offer);
+            }
+        }
+    }
+}
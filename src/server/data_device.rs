@@ -0,0 +1,356 @@
+//! wl_data_device_manager objects
+//!
+//! Sources and offers are tracked by the [`DataDeviceHandler`] in
+//! [`crate::protocol::data_device`]. The clipboard selection is offered to
+//! the client with keyboard focus: whenever keyboard focus moves to another
+//! client, when the selection changes and when a client creates a device
+//! while it has focus. Only a client handling a key or button press may set
+//! the selection. Drag and drop isn't supported, drags are cancelled right
+//! away.
+//!
+//! [`DataDeviceHandler`]: crate::protocol::DataDeviceHandler
+
+use std::os::fd::{AsFd, BorrowedFd};
+
+use log::debug;
+use wayland_server::backend::ClientId;
+use wayland_server::protocol::{
+    wl_data_device, wl_data_device_manager, wl_data_offer, wl_data_source,
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::protocol::data_device::{DataOfferId, DataSourceId, DndAction, DndActions};
+
+/// Data devices of all clients
+#[derive(Debug, Default)]
+pub struct DataDeviceResources {
+    /// Devices, one or more per client
+    devices: Vec<wl_data_device::WlDataDevice>,
+    /// Client the selection was last offered to
+    focus: Option<ClientId>,
+}
+
+impl GlobalDispatch<wl_data_device_manager::WlDataDeviceManager, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_data_device_manager::WlDataDeviceManager>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wl_data_device_manager");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wl_data_device_manager::WlDataDeviceManager, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &wl_data_device_manager::WlDataDeviceManager,
+        request: wl_data_device_manager::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_data_device_manager::Request::CreateDataSource { id } => {
+                let source_id = state.data_device.create_data_source();
+                let source = data_init.init(id, source_id);
+                state.resources.data_sources.insert(source_id, source);
+            }
+            wl_data_device_manager::Request::GetDataDevice { id, seat: _ } => {
+                debug!("Creating data device");
+                let device = data_init.init(id, ());
+                state.resources.data_devices.devices.push(device);
+                if state.has_keyboard_focus(client) {
+                    state.offer_selection(client);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_data_device::WlDataDevice, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &wl_data_device::WlDataDevice,
+        request: wl_data_device::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_data_device::Request::SetSelection { source, serial } => {
+                if !state.input_from_client(client, serial) {
+                    debug!("Ignoring selection with stale serial {}", serial);
+                    if let Some(source) = source {
+                        source.cancelled();
+                    }
+                    return;
+                }
+
+                let source_id = source.and_then(|source| source.data::<DataSourceId>().copied());
+                state.replace_selection(source_id);
+            }
+            wl_data_device::Request::StartDrag { source, .. } => {
+                debug!("Cancelling drag, drag and drop isn't supported");
+                if let Some(source) = source {
+                    source.cancelled();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &wl_data_device::WlDataDevice,
+        _data: &(),
+    ) {
+        state
+            .resources
+            .data_devices
+            .devices
+            .retain(|device| device != resource);
+    }
+}
+
+impl Dispatch<wl_data_source::WlDataSource, DataSourceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wl_data_source::WlDataSource,
+        request: wl_data_source::Request,
+        data: &DataSourceId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let Some(source) = state.data_device.get_source_mut(*data) else {
+            return;
+        };
+        match request {
+            wl_data_source::Request::Offer { mime_type } => source.offer(mime_type),
+            wl_data_source::Request::SetActions { dnd_actions } => {
+                let actions = u32::from(dnd_actions);
+                source.set_actions(DndActions::from_bits_truncate(actions));
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wl_data_source::WlDataSource,
+        data: &DataSourceId,
+    ) {
+        let selected = state.data_device.selection().map(|source| source.id) == Some(*data);
+        state.data_device.destroy_source(*data);
+        state.resources.data_sources.remove(data);
+        if selected {
+            state.reoffer_selection();
+        }
+    }
+}
+
+impl Dispatch<wl_data_offer::WlDataOffer, DataOfferId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wl_data_offer::WlDataOffer,
+        request: wl_data_offer::Request,
+        data: &DataOfferId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_data_offer::Request::Receive { mime_type, fd } => {
+                let source = state.data_device.get_offer(*data).map(|o| o.source_id);
+                if !source.is_some_and(|source| state.send_source(source, mime_type, fd.as_fd())) {
+                    debug!("Data offer {:?} has no source", data);
+                }
+            }
+            wl_data_offer::Request::Accept { serial, mime_type } => {
+                if let Some(offer) = state.data_device.get_offer_mut(*data) {
+                    offer.accept(serial, mime_type);
+                }
+            }
+            wl_data_offer::Request::SetActions {
+                dnd_actions,
+                preferred_action,
+            } => {
+                let preferred = match u32::from(preferred_action) {
+                    1 => DndAction::Copy,
+                    2 => DndAction::Move,
+                    4 => DndAction::Ask,
+                    _ => DndAction::None,
+                };
+                if let Some(offer) = state.data_device.get_offer_mut(*data) {
+                    let actions = DndActions::from_bits_truncate(dnd_actions.into());
+                    offer.set_actions(actions, preferred);
+                }
+            }
+            wl_data_offer::Request::Finish => {
+                if let Some(offer) = state.data_device.get_offer(*data) {
+                    offer.finish();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wl_data_offer::WlDataOffer,
+        data: &DataOfferId,
+    ) {
+        state.data_device.destroy_offer(*data);
+    }
+}
+
+impl ServerState {
+    /// Offer the selection when keyboard focus moves to another client
+    pub(super) fn update_selection_focus(&mut self) {
+        let client = self.keyboard_focus_client();
+        let id = client.as_ref().map(|client| client.id());
+        if id == self.resources.data_devices.focus {
+            return;
+        }
+        self.resources.data_devices.focus = id;
+        if let Some(client) = client {
+            self.offer_selection(&client);
+        }
+    }
+
+    /// Set the clipboard selection, cancelling the source it replaces
+    pub(super) fn replace_selection(&mut self, source_id: Option<DataSourceId>) {
+        let old = self.data_device.selection().map(|source| source.id);
+        self.data_device.set_selection(source_id, 0);
+        if let Some(old) = old.filter(|&old| Some(old) != source_id) {
+            self.cancel_source(old);
+        }
+        self.reoffer_selection();
+    }
+
+    /// Offer the changed selection to the client with keyboard focus and to
+    /// clipboard managers
+    pub(super) fn reoffer_selection(&mut self) {
+        self.resources.data_devices.focus = None;
+        self.update_selection_focus();
+        self.broadcast_data_control_selection();
+    }
+
+    /// Ask the client owning a source to write its data to a pipe
+    ///
+    /// Sources can come from wl_data_device, the primary selection and data
+    /// control. Returns false if the source has no object.
+    pub(super) fn send_source(
+        &self,
+        source: DataSourceId,
+        mime_type: String,
+        fd: BorrowedFd<'_>,
+    ) -> bool {
+        let resources = &self.resources;
+        if let Some(resource) = resources.data_sources.get(&source) {
+            resource.send(mime_type, fd);
+        } else if let Some(resource) = resources.primary_selection.source(source) {
+            resource.send(mime_type, fd);
+        } else {
+            return resources.data_controls.send(source, mime_type, fd);
+        }
+        true
+    }
+
+    /// Tell the client owning a source that it's no longer a selection
+    pub(super) fn cancel_source(&self, source: DataSourceId) {
+        let resources = &self.resources;
+        if let Some(resource) = resources.data_sources.get(&source) {
+            resource.cancelled();
+        } else if let Some(resource) = resources.primary_selection.source(source) {
+            resource.cancelled();
+        } else {
+            resources.data_controls.cancel(source);
+        }
+    }
+
+    /// Get the client owning the surface with keyboard focus
+    fn keyboard_focus_client(&self) -> Option<Client> {
+        self.compositor
+            .seat
+            .keyboard()
+            .focus()
+            .and_then(|focus| self.resources.surfaces.get(&focus))
+            .and_then(|surface| surface.client())
+    }
+
+    /// Check if a client owns the surface with keyboard focus
+    fn has_keyboard_focus(&self, client: &Client) -> bool {
+        self.keyboard_focus_client()
+            .is_some_and(|owner| owner.id() == client.id())
+    }
+
+    /// Check if a serial is that of a recent key or button press sent to a
+    /// client
+    fn input_from_client(&self, client: &Client, serial: u32) -> bool {
+        self.compositor
+            .seat
+            .input_surface(serial)
+            .and_then(|surface| self.resources.surfaces.get(&surface))
+            .and_then(|surface| surface.client())
+            .is_some_and(|owner| owner.id() == client.id())
+    }
+
+    /// Send a new offer of the selection to a client's devices
+    fn offer_selection(&mut self, client: &Client) {
+        let devices: Vec<_> = self
+            .resources
+            .data_devices
+            .devices
+            .iter()
+            .filter(|device| device.client().is_some_and(|c| c.id() == client.id()))
+            .cloned()
+            .collect();
+        let source_id = self.data_device.selection().map(|source| source.id);
+        for device in devices {
+            let Some(offer_id) = source_id.and_then(|id| self.data_device.create_offer(id)) else {
+                device.selection(None);
+                continue;
+            };
+            let Some(dh) = device.handle().upgrade().map(DisplayHandle::from) else {
+                self.data_device.destroy_offer(offer_id);
+                continue;
+            };
+            let offer = client.create_resource::<wl_data_offer::WlDataOffer, _, Self>(
+                &dh,
+                device.version(),
+                offer_id,
+            );
+            let Ok(offer) = offer else {
+                self.data_device.destroy_offer(offer_id);
+                continue;
+            };
+            device.data_offer(&offer);
+            for mime_type in self
+                .data_device
+                .get_offer(offer_id)
+                .map(|offer| offer.mime_types.clone())
+                .unwrap_or_default()
+            {
+                offer.offer(mime_type);
+            }
+            device.selection(Some(&offer));
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! zxdg_decoration_manager_v1 objects
+//!
+//! Toplevels that negotiate decorations get the native titlebar
+//! (server-side) or a window without visible titlebar that they decorate
+//! themselves (client-side). Every mode change is announced with a
+//! decoration configure followed by an xdg_surface configure.
+
+use log::debug;
+use wayland_protocols::xdg::decoration::zv1::server::{
+    zxdg_decoration_manager_v1, zxdg_toplevel_decoration_v1,
+};
+use wayland_protocols::xdg::shell::server::xdg_toplevel;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
+
+use super::client::GlobalData;
+use super::globals::{configure_toplevel, ToplevelData};
+use super::ServerState;
+use crate::compositor::DecorationMode;
+
+impl GlobalDispatch<zxdg_decoration_manager_v1::ZxdgDecorationManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zxdg_decoration_manager_v1::ZxdgDecorationManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zxdg_decoration_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zxdg_decoration_manager_v1::ZxdgDecorationManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &zxdg_decoration_manager_v1::ZxdgDecorationManagerV1,
+        request: zxdg_decoration_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zxdg_decoration_manager_v1::Request::GetToplevelDecoration { id, toplevel } = request
+        {
+            let Some(data) = toplevel.data::<ToplevelData>() else {
+                return;
+            };
+            if state.resources.decorations.contains_key(&data.window_id) {
+                data_init.post_error(
+                    id,
+                    zxdg_toplevel_decoration_v1::Error::AlreadyConstructed,
+                    "toplevel already has a decoration object",
+                );
+                return;
+            }
+            if state
+                .compositor
+                .surfaces
+                .get(data.surface_id)
+                .is_some_and(|surface| surface.buffer.is_some())
+            {
+                data_init.post_error(
+                    id,
+                    zxdg_toplevel_decoration_v1::Error::UnconfiguredBuffer,
+                    "toplevel already has a buffer",
+                );
+                return;
+            }
+
+            let decoration = data_init.init(id, toplevel.clone());
+            state
+                .resources
+                .decorations
+                .insert(data.window_id, decoration.clone());
+            state.set_decoration_mode(client, &toplevel, &decoration, None);
+        }
+    }
+}
+
+impl Dispatch<zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1, xdg_toplevel::XdgToplevel>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1,
+        request: zxdg_toplevel_decoration_v1::Request,
+        toplevel: &xdg_toplevel::XdgToplevel,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zxdg_toplevel_decoration_v1::Request::SetMode { mode } => {
+                let requested = match mode {
+                    WEnum::Value(zxdg_toplevel_decoration_v1::Mode::ClientSide) => {
+                        DecorationMode::ClientSide
+                    }
+                    WEnum::Value(zxdg_toplevel_decoration_v1::Mode::ServerSide) => {
+                        DecorationMode::ServerSide
+                    }
+                    _ => {
+                        resource.post_error(
+                            zxdg_toplevel_decoration_v1::Error::InvalidMode,
+                            "unknown decoration mode",
+                        );
+                        return;
+                    }
+                };
+                state.set_decoration_mode(client, toplevel, resource, Some(requested));
+            }
+            zxdg_toplevel_decoration_v1::Request::UnsetMode => {
+                state.set_decoration_mode(client, toplevel, resource, None);
+            }
+            zxdg_toplevel_decoration_v1::Request::Destroy => {
+                debug!("zxdg_toplevel_decoration_v1 destroy");
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1,
+        toplevel: &xdg_toplevel::XdgToplevel,
+    ) {
+        let Some(data) = toplevel.data::<ToplevelData>() else {
+            return;
+        };
+        if state
+            .resources
+            .decorations
+            .get(&data.window_id)
+            .is_some_and(|decoration| decoration.id() == resource.id())
+        {
+            state.resources.decorations.remove(&data.window_id);
+        }
+    }
+}
+
+impl ServerState {
+    /// Decide a toplevel's decoration mode, apply it to the native window
+    /// and configure the client
+    fn set_decoration_mode(
+        &mut self,
+        client: &Client,
+        toplevel: &xdg_toplevel::XdgToplevel,
+        decoration: &zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1,
+        requested: Option<DecorationMode>,
+    ) {
+        let Some(data) = toplevel.data::<ToplevelData>() else {
+            return;
+        };
+        let app_id = self
+            .compositor
+            .windows
+            .get(data.window_id)
+            .and_then(|window| window.app_id.clone());
+        let quirks = self.client_quirks(client, app_id.as_deref());
+        let mode = DecorationMode::negotiate(requested, quirks.server_side_decorations);
+        debug!("Toplevel {:?} decoration mode {:?}", data.window_id, mode);
+
+        if let Some(window) = self.compositor.windows.get_mut(data.window_id) {
+            window.decoration = mode;
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.native_window(data.window_id) {
+            native_window.set_decorated(mode == DecorationMode::ServerSide);
+        }
+
+        decoration.configure(match mode {
+            DecorationMode::ClientSide => zxdg_toplevel_decoration_v1::Mode::ClientSide,
+            DecorationMode::ServerSide => zxdg_toplevel_decoration_v1::Mode::ServerSide,
+        });
+        configure_toplevel(self, toplevel);
+    }
+}
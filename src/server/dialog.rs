@@ -0,0 +1,167 @@
+//! xdg_wm_dialog_v1 objects
+//!
+//! A client marks a toplevel with a parent as a dialog of that parent, and
+//! can mark the dialog modal. A modal dialog blocks input to its parent:
+//! activating the parent activates the dialog instead, keyboard focus
+//! meant for the parent goes to the dialog, and button presses on the
+//! parent only bring the dialog forward. On macOS a modal dialog is shown
+//! as a sheet of its parent's native window, other transient windows as
+//! child windows, which move with their parent and stay above it.
+
+use log::debug;
+use wayland_protocols::xdg::dialog::v1::server::{xdg_dialog_v1, xdg_wm_dialog_v1};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::globals::ToplevelData;
+use super::ServerState;
+use crate::compositor::{SurfaceId, WindowId};
+
+impl GlobalDispatch<xdg_wm_dialog_v1::XdgWmDialogV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<xdg_wm_dialog_v1::XdgWmDialogV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound xdg_wm_dialog_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<xdg_wm_dialog_v1::XdgWmDialogV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &xdg_wm_dialog_v1::XdgWmDialogV1,
+        request: xdg_wm_dialog_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let xdg_wm_dialog_v1::Request::GetXdgDialog { id, toplevel } = request {
+            let Some(window_id) = toplevel.data::<ToplevelData>().map(|data| data.window_id) else {
+                return;
+            };
+            if !state.resources.dialogs.insert(window_id) {
+                resource.post_error(
+                    xdg_wm_dialog_v1::Error::AlreadyUsed,
+                    "toplevel already has a dialog object",
+                );
+                return;
+            }
+            data_init.init(id, window_id);
+        }
+    }
+}
+
+impl Dispatch<xdg_dialog_v1::XdgDialogV1, WindowId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &xdg_dialog_v1::XdgDialogV1,
+        request: xdg_dialog_v1::Request,
+        window_id: &WindowId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_dialog_v1::Request::SetModal => state.set_window_modal(*window_id, true),
+            xdg_dialog_v1::Request::UnsetModal => state.set_window_modal(*window_id, false),
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &xdg_dialog_v1::XdgDialogV1,
+        window_id: &WindowId,
+    ) {
+        state.resources.dialogs.remove(window_id);
+        state.set_window_modal(*window_id, false);
+    }
+}
+
+impl ServerState {
+    /// Mark a window as a modal dialog of its parent or not
+    ///
+    /// A dialog made modal while its parent has focus takes the focus.
+    pub fn set_window_modal(&mut self, window_id: WindowId, modal: bool) {
+        let Some(window) = self.compositor.windows.get_mut(window_id) else {
+            return;
+        };
+        if window.modal == modal {
+            return;
+        }
+        debug!("Window {:?} modal: {}", window_id, modal);
+        window.modal = modal;
+        let parent = window.parent;
+
+        #[cfg(target_os = "macos")]
+        self.sync_dialog_windows();
+
+        let focused = self.compositor.windows.focused().map(|window| window.id);
+        if modal && parent.is_some() && focused == parent {
+            self.activate_window(window_id);
+        }
+    }
+
+    /// The modal dialog blocking input to a surface's window, if any
+    pub fn blocking_dialog(&self, surface: SurfaceId) -> Option<WindowId> {
+        let root = self.compositor.surfaces.root(surface);
+        let window = self.compositor.windows.window_for_surface(root)?;
+        self.compositor.windows.modal_dialog(window)
+    }
+
+    /// Attach native windows to the native windows of their parents
+    #[cfg(target_os = "macos")]
+    pub(super) fn sync_dialog_windows(&self) {
+        for (id, native) in self.backend.native_windows() {
+            let Some(window) = self.compositor.windows.get(id) else {
+                continue;
+            };
+            let parent = window
+                .parent
+                .and_then(|parent| self.backend.native_window(parent));
+            native.set_parent(parent, window.modal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modal_dialog_takes_focus() {
+        let mut state = ServerState::new();
+        let parent_surface = state.compositor.surfaces.create_surface();
+        let dialog_surface = state.compositor.surfaces.create_surface();
+        let parent = state.compositor.windows.create_window(parent_surface);
+        let dialog = state.compositor.windows.create_window(dialog_surface);
+        state.compositor.windows.set_parent(dialog, Some(parent));
+        assert!(state.activate_window(parent));
+
+        state.set_window_modal(dialog, true);
+        let focused = state.compositor.windows.focused().map(|window| window.id);
+        assert_eq!(focused, Some(dialog));
+        assert_eq!(state.blocking_dialog(parent_surface), Some(dialog));
+
+        // Activating the parent brings the dialog forward instead
+        state.compositor.windows.set_focused(None);
+        assert!(state.activate_window(parent));
+        let focused = state.compositor.windows.focused().map(|window| window.id);
+        assert_eq!(focused, Some(dialog));
+
+        state.set_window_modal(dialog, false);
+        assert_eq!(state.blocking_dialog(parent_surface), None);
+    }
+}
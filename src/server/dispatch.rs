@@ -2,16 +2,40 @@
 //!
 //! Implements the Dispatch trait for each Wayland protocol object.
 
+use std::sync::Mutex;
+
 use log::{debug, warn};
+use wayland_server::backend::protocol::ProtocolError;
 use wayland_server::protocol::{
-    wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_output, wl_pointer, wl_region, wl_seat,
-    wl_shm, wl_shm_pool, wl_surface,
+    wl_buffer, wl_callback, wl_compositor, wl_region, wl_shm, wl_shm_pool, wl_subcompositor,
+    wl_subsurface, wl_surface,
 };
 use wayland_server::{Client, DataInit, Dispatch, Resource};
 
-use crate::compositor::{SurfaceId, SurfaceRole};
-
-use super::ServerState;
+use crate::compositor::state::ClientId;
+use crate::compositor::surface::DamageRect;
+use crate::compositor::{Placement, Region, SubsurfaceError, SurfaceId};
+
+use super::{client_info, ServerState, SinglePixelBuffer};
+
+/// wl_display.error.no_memory, wl_display is not exposed by wayland-server
+const ERROR_NO_MEMORY: u32 = 2;
+
+/// Disconnect a client that went over a resource limit
+///
+/// The error is posted on wl_display (object 1): the new object was never
+/// created, so an error on it would go nowhere.
+fn kill_no_memory(client: &Client, dhandle: &wayland_server::DisplayHandle, message: String) {
+    client.kill(
+        dhandle,
+        ProtocolError {
+            code: ERROR_NO_MEMORY,
+            object_id: 1,
+            object_interface: "wl_display".into(),
+            message,
+        },
+    );
+}
 
 // ============================================================================
 // wl_compositor
@@ -20,22 +44,37 @@ use super::ServerState;
 impl Dispatch<wl_compositor::WlCompositor, ()> for ServerState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         _resource: &wl_compositor::WlCompositor,
         request: wl_compositor::Request,
         _data: &(),
-        _dhandle: &wayland_server::DisplayHandle,
+        dhandle: &wayland_server::DisplayHandle,
         data_init: &mut DataInit<'_, Self>,
     ) {
+        let client_id = client_info(client).map(|info| info.id);
         match request {
             wl_compositor::Request::CreateSurface { id } => {
+                if let Some(Err(e)) = client_id.map(|c| state.leaks.check_surface(c)) {
+                    warn!("Refusing wl_surface for client {:?}: {}", client_id, e);
+                    kill_no_memory(client, dhandle, e.to_string());
+                    return;
+                }
                 let surface_id = state.compositor.surfaces.create_surface();
                 debug!("Created wl_surface {:?}", surface_id);
-                data_init.init(id, surface_id);
+                let surface = data_init.init(id, surface_id);
+                state.resources.surfaces.insert(surface_id, surface);
+                if let Some(client_id) = client_id {
+                    state.leaks.surface_created(client_id, surface_id);
+                }
             }
             wl_compositor::Request::CreateRegion { id } => {
+                if let Some(Err(e)) = client_id.map(|c| state.leaks.region_created(c)) {
+                    warn!("Refusing wl_region for client {:?}: {}", client_id, e);
+                    kill_no_memory(client, dhandle, e.to_string());
+                    return;
+                }
                 debug!("Created wl_region");
-                data_init.init(id, ());
+                data_init.init(id, RegionData::new(client_id));
             }
             _ => {}
         }
@@ -56,6 +95,11 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
         _dhandle: &wayland_server::DisplayHandle,
         data_init: &mut DataInit<'_, Self>,
     ) {
+        #[cfg(feature = "strict")]
+        if !state.strict_surface_request(*surface_id, &request) {
+            return;
+        }
+
         let Some(surface) = state.compositor.surfaces.get_mut(*surface_id) else {
             warn!("Surface {:?} not found", surface_id);
             return;
@@ -66,7 +110,11 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
                 debug!("Surface {:?} attach buffer at ({}, {})", surface_id, x, y);
                 if let Some(wl_buffer) = buffer {
                     // Get the buffer data from the wl_buffer's user data
-                    if let Some(shm_buffer_id) =
+                    if let Some(single_pixel) = wl_buffer.data::<SinglePixelBuffer>() {
+                        surface.attach(Some(crate::compositor::surface::BufferInfo::single_pixel(
+                            single_pixel.0,
+                        )));
+                    } else if let Some(shm_buffer_id) =
                         wl_buffer.data::<crate::protocol::shm::ShmBufferId>()
                     {
                         // Look up the buffer info
@@ -78,13 +126,15 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
                                 format: shm_buffer.format.to_wayland(),
                                 offset: shm_buffer.offset,
                                 shm_buffer_id: Some(shm_buffer_id.0),
+                                solid: None,
+                                snapshot: None,
                             }));
                         } else {
                             debug!("Buffer {:?} not found in shm handler", shm_buffer_id);
                             surface.attach(None);
                         }
                     } else {
-                        debug!("Buffer has no ShmBufferId user data");
+                        debug!("Buffer has no pixel data");
                         surface.attach(None);
                     }
                 } else {
@@ -113,18 +163,21 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
                     "Surface {:?} damage_buffer ({}, {}, {}, {})",
                     surface_id, x, y, width, height
                 );
-                surface.damage(x, y, width, height);
+                surface.damage_buffer(x, y, width, height);
             }
             wl_surface::Request::Frame { callback } => {
                 debug!("Surface {:?} frame callback", surface_id);
                 let cb: wl_callback::WlCallback = data_init.init(callback, ());
                 surface.frame(cb.id().protocol_id());
+                state.resources.presentation.request_frame(*surface_id, cb);
             }
             wl_surface::Request::SetOpaqueRegion { region: _ } => {
                 debug!("Surface {:?} set opaque region", surface_id);
             }
-            wl_surface::Request::SetInputRegion { region: _ } => {
+            wl_surface::Request::SetInputRegion { region } => {
                 debug!("Surface {:?} set input region", surface_id);
+                let region = region.as_ref().and_then(|r| r.data::<RegionData>());
+                surface.pending.input_region = Some(region.map(RegionData::get));
             }
             wl_surface::Request::Commit => {
                 debug!("Surface {:?} commit", surface_id);
@@ -133,65 +186,34 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
                 let _frame_callbacks: Vec<u32> =
                     surface.pending.frame_callbacks.drain(..).collect();
 
-                // Commit the surface state
-                surface.commit();
+                // Commit the surface state, or cache it until the parent
+                // commits if this is a synchronized subsurface
+                state.check_target_time(*surface_id);
+                let applied = state.compositor.surfaces.commit(*surface_id);
+                state.buffers_applied(&applied);
+                state.leaks.surface_committed(*surface_id);
+                state.check_viewports(&applied);
+                state.commit_presentation_feedback(*surface_id);
+                state.commit_presentation_hints(*surface_id);
+                state.commit_toplevel_icon(*surface_id);
+                state.commit_toplevel_size(*surface_id);
+                state.commit_layer_surface(*surface_id);
+                if state.compositor.surfaces.has_commit_queue(*surface_id) {
+                    state.schedule_commit_release();
+                }
 
-                // Check if this surface is a toplevel and needs a native window
-                #[cfg(target_os = "macos")]
-                {
-                    let surface = state.compositor.surfaces.get(*surface_id).unwrap();
-                    if surface.role == SurfaceRole::XdgToplevel {
-                        // Find the window for this surface
-                        if let Some(window_id) =
-                            state.compositor.windows.window_for_surface(*surface_id)
-                        {
-                            // Get buffer info for window creation/update
-                            let buffer_info = surface.buffer.clone();
-                            let (width, height) = buffer_info
-                                .as_ref()
-                                .map(|b| (b.width.max(640), b.height.max(480)))
-                                .unwrap_or((640, 480));
-
-                            // Create native window if it doesn't exist
-                            if !state.native_windows.contains_key(&window_id) {
-                                if let Some(mtm) = state.mtm {
-                                    match crate::backend::cocoa::window::WayoaWindow::new(
-                                        mtm,
-                                        window_id,
-                                        width,
-                                        height,
-                                        "Wayland Window",
-                                    ) {
-                                        Ok(window) => {
-                                            window.show();
-                                            state.native_windows.insert(window_id, window);
-                                            debug!("Created native window for {:?}", window_id);
-                                        }
-                                        Err(e) => {
-                                            warn!("Failed to create native window: {}", e);
-                                        }
-                                    }
-                                }
-                            }
-
-                            // Update the window content with buffer data
-                            if let Some(ref buf) = buffer_info {
-                                if let Some(shm_buffer_id) = buf.shm_buffer_id {
-                                    let buffer_id =
-                                        crate::protocol::shm::ShmBufferId(shm_buffer_id);
-                                    if let Ok(data) = state.shm.read_buffer_data(buffer_id) {
-                                        if let Some(window) = state.native_windows.get(&window_id) {
-                                            window.update_buffer(
-                                                &data, buf.width, buf.height, buf.stride,
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                        }
+                // Subsurfaces and popups are drawn into their toplevel's window
+                let root = state.compositor.surfaces.root(*surface_id);
+                if !applied.is_empty() && root != *surface_id {
+                    if let Some(window_id) = state.compositor.windows.window_for_surface(root) {
+                        state.schedule_present(window_id);
                     }
                 }
 
+                // Toplevels get a native window on their first commit
+                #[cfg(target_os = "macos")]
+                state.update_native_window(*surface_id);
+
                 // Fire frame callbacks
                 // In a full implementation, this would be done after rendering
                 // For now, we'll just mark them as done
@@ -214,7 +236,16 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
             }
             wl_surface::Request::Destroy => {
                 debug!("Surface {:?} destroy", surface_id);
+                state.buffers_surface_destroyed(*surface_id);
                 state.compositor.surfaces.remove(*surface_id);
+                state.resources.surfaces.remove(surface_id);
+                state.resources.viewports.remove(surface_id);
+                state.resources.fractional_scales.remove(surface_id);
+                state.discard_presentation_feedback(*surface_id);
+                state.drop_pointer_constraint(*surface_id);
+                state.idle_inhibit.remove_surface(*surface_id);
+                #[cfg(feature = "strict")]
+                state.strict.remove_surface(*surface_id);
             }
             _ => {}
         }
@@ -227,7 +258,131 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
         data: &SurfaceId,
     ) {
         debug!("Surface {:?} destroyed", data);
+        state.buffers_surface_destroyed(*data);
         state.compositor.surfaces.remove(*data);
+        state.resources.surfaces.remove(data);
+        state.resources.viewports.remove(data);
+        state.resources.fractional_scales.remove(data);
+        state.resources.pending_icons.remove(data);
+        state.discard_presentation_feedback(*data);
+        state.drop_pointer_constraint(*data);
+        state.idle_inhibit.remove_surface(*data);
+        #[cfg(feature = "strict")]
+        state.strict.remove_surface(*data);
+        state.leaks.surface_destroyed(*data);
+    }
+}
+
+// ============================================================================
+// wl_subcompositor
+// ============================================================================
+
+impl Dispatch<wl_subcompositor::WlSubcompositor, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wl_subcompositor::WlSubcompositor,
+        request: wl_subcompositor::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_subcompositor::Request::GetSubsurface {
+                id,
+                surface,
+                parent,
+            } => {
+                let (Some(&surface_id), Some(&parent_id)) =
+                    (surface.data::<SurfaceId>(), parent.data::<SurfaceId>())
+                else {
+                    return;
+                };
+                match state
+                    .compositor
+                    .surfaces
+                    .create_subsurface(surface_id, parent_id)
+                {
+                    Ok(()) => {
+                        debug!(
+                            "Surface {:?} is a subsurface of {:?}",
+                            surface_id, parent_id
+                        );
+                        data_init.init(id, surface_id);
+                    }
+                    Err(e) => {
+                        let code = match e {
+                            SubsurfaceError::BadParent => wl_subcompositor::Error::BadParent,
+                            _ => wl_subcompositor::Error::BadSurface,
+                        };
+                        resource.post_error(code, e.to_string());
+                    }
+                }
+            }
+            wl_subcompositor::Request::Destroy => {}
+            _ => {}
+        }
+    }
+}
+
+// ============================================================================
+// wl_subsurface
+// ============================================================================
+
+impl Dispatch<wl_subsurface::WlSubsurface, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wl_subsurface::WlSubsurface,
+        request: wl_subsurface::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let surfaces = &mut state.compositor.surfaces;
+        let placement = match request {
+            wl_subsurface::Request::SetPosition { x, y } => {
+                surfaces.set_subsurface_position(*surface_id, x, y);
+                None
+            }
+            wl_subsurface::Request::PlaceAbove { sibling } => {
+                sibling.data::<SurfaceId>().map(|&s| Placement::Above(s))
+            }
+            wl_subsurface::Request::PlaceBelow { sibling } => {
+                sibling.data::<SurfaceId>().map(|&s| Placement::Below(s))
+            }
+            wl_subsurface::Request::SetSync => {
+                surfaces.set_subsurface_sync(*surface_id, true);
+                None
+            }
+            wl_subsurface::Request::SetDesync => {
+                // Cached state is applied with the next commit
+                surfaces.set_subsurface_sync(*surface_id, false);
+                None
+            }
+            wl_subsurface::Request::Destroy => None,
+            _ => None,
+        };
+
+        if let Some(placement) = placement {
+            if let Err(e) = surfaces.place_subsurface(*surface_id, placement) {
+                resource.post_error(wl_subsurface::Error::BadSurface, e.to_string());
+            }
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &wl_subsurface::WlSubsurface,
+        data: &SurfaceId,
+    ) {
+        debug!("Subsurface {:?} destroyed", data);
+        let root = state.compositor.surfaces.root(*data);
+        state.compositor.surfaces.destroy_subsurface(*data);
+        if let Some(window_id) = state.compositor.windows.window_for_surface(root) {
+            state.schedule_present(window_id);
+        }
     }
 }
 
@@ -235,13 +390,37 @@ impl Dispatch<wl_surface::WlSurface, SurfaceId> for ServerState {
 // wl_region
 // ============================================================================
 
-impl Dispatch<wl_region::WlRegion, ()> for ServerState {
+/// Region data, with the client it's accounted to
+///
+/// Regions change until they're destroyed, so the rectangles live behind a
+/// lock, and surfaces copy them when they're set.
+#[derive(Debug)]
+pub struct RegionData {
+    client: Option<ClientId>,
+    region: Mutex<Region>,
+}
+
+impl RegionData {
+    fn new(client: Option<ClientId>) -> Self {
+        Self {
+            client,
+            region: Mutex::new(Region::default()),
+        }
+    }
+
+    /// Get a copy of the region
+    pub fn get(&self) -> Region {
+        self.region.lock().unwrap().clone()
+    }
+}
+
+impl Dispatch<wl_region::WlRegion, RegionData> for ServerState {
     fn request(
         _state: &mut Self,
         _client: &Client,
         _resource: &wl_region::WlRegion,
         request: wl_region::Request,
-        _data: &(),
+        data: &RegionData,
         _dhandle: &wayland_server::DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
@@ -253,6 +432,12 @@ impl Dispatch<wl_region::WlRegion, ()> for ServerState {
                 height,
             } => {
                 debug!("Region add ({}, {}, {}, {})", x, y, width, height);
+                data.region.lock().unwrap().add(DamageRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
             }
             wl_region::Request::Subtract {
                 x,
@@ -261,6 +446,12 @@ impl Dispatch<wl_region::WlRegion, ()> for ServerState {
                 height,
             } => {
                 debug!("Region subtract ({}, {}, {}, {})", x, y, width, height);
+                data.region.lock().unwrap().subtract(DamageRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
             }
             wl_region::Request::Destroy => {
                 debug!("Region destroy");
@@ -268,6 +459,17 @@ impl Dispatch<wl_region::WlRegion, ()> for ServerState {
             _ => {}
         }
     }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &wl_region::WlRegion,
+        data: &RegionData,
+    ) {
+        if let Some(client) = data.client {
+            state.leaks.region_destroyed(client);
+        }
+    }
 }
 
 // ============================================================================
@@ -347,7 +549,8 @@ impl Dispatch<wl_shm_pool::WlShmPool, crate::protocol::shm::ShmPoolId> for Serve
                     format.into(),
                 ) {
                     Ok(buffer_id) => {
-                        data_init.init(id, buffer_id);
+                        let buffer = data_init.init(id, buffer_id);
+                        state.resources.buffers.add(buffer_id, buffer);
                     }
                     Err(e) => {
                         warn!("Failed to create buffer: {}", e);
@@ -384,122 +587,7 @@ impl Dispatch<wl_buffer::WlBuffer, crate::protocol::shm::ShmBufferId> for Server
         if let wl_buffer::Request::Destroy = request {
             debug!("Destroying buffer {:?}", buffer_id);
             state.shm.destroy_buffer(*buffer_id);
-        }
-    }
-}
-
-// ============================================================================
-// wl_seat
-// ============================================================================
-
-/// Seat user data - tracks capabilities
-pub struct SeatData {
-    pub capabilities: wl_seat::Capability,
-}
-
-impl Dispatch<wl_seat::WlSeat, SeatData> for ServerState {
-    fn request(
-        _state: &mut Self,
-        _client: &Client,
-        _resource: &wl_seat::WlSeat,
-        request: wl_seat::Request,
-        _data: &SeatData,
-        _dhandle: &wayland_server::DisplayHandle,
-        data_init: &mut DataInit<'_, Self>,
-    ) {
-        match request {
-            wl_seat::Request::GetPointer { id } => {
-                debug!("Creating pointer");
-                data_init.init(id, ());
-            }
-            wl_seat::Request::GetKeyboard { id } => {
-                debug!("Creating keyboard");
-                data_init.init(id, ());
-            }
-            wl_seat::Request::GetTouch { id: _ } => {
-                debug!("Creating touch");
-                // Touch not implemented, but we'll create the object
-            }
-            wl_seat::Request::Release => {
-                debug!("Seat release");
-            }
-            _ => {}
-        }
-    }
-}
-
-// ============================================================================
-// wl_pointer
-// ============================================================================
-
-impl Dispatch<wl_pointer::WlPointer, ()> for ServerState {
-    fn request(
-        _state: &mut Self,
-        _client: &Client,
-        _resource: &wl_pointer::WlPointer,
-        request: wl_pointer::Request,
-        _data: &(),
-        _dhandle: &wayland_server::DisplayHandle,
-        _data_init: &mut DataInit<'_, Self>,
-    ) {
-        match request {
-            wl_pointer::Request::SetCursor {
-                serial: _,
-                surface: _,
-                hotspot_x,
-                hotspot_y,
-            } => {
-                debug!("Set cursor at ({}, {})", hotspot_x, hotspot_y);
-            }
-            wl_pointer::Request::Release => {
-                debug!("Pointer release");
-            }
-            _ => {}
-        }
-    }
-}
-
-// ============================================================================
-// wl_keyboard
-// ============================================================================
-
-impl Dispatch<wl_keyboard::WlKeyboard, ()> for ServerState {
-    fn request(
-        _state: &mut Self,
-        _client: &Client,
-        _resource: &wl_keyboard::WlKeyboard,
-        request: wl_keyboard::Request,
-        _data: &(),
-        _dhandle: &wayland_server::DisplayHandle,
-        _data_init: &mut DataInit<'_, Self>,
-    ) {
-        if let wl_keyboard::Request::Release = request {
-            debug!("Keyboard release");
-        }
-    }
-}
-
-// ============================================================================
-// wl_output
-// ============================================================================
-
-/// Output user data
-pub struct OutputData {
-    pub output_id: crate::compositor::OutputId,
-}
-
-impl Dispatch<wl_output::WlOutput, OutputData> for ServerState {
-    fn request(
-        _state: &mut Self,
-        _client: &Client,
-        _resource: &wl_output::WlOutput,
-        request: wl_output::Request,
-        _data: &OutputData,
-        _dhandle: &wayland_server::DisplayHandle,
-        _data_init: &mut DataInit<'_, Self>,
-    ) {
-        if let wl_output::Request::Release = request {
-            debug!("Output release");
+            state.resources.buffers.remove(*buffer_id);
         }
     }
 }
@@ -0,0 +1,162 @@
+//! ext_data_control_manager_v1 objects
+//!
+//! The standardized data control protocol. It only differs from
+//! zwlr_data_control_manager_v1 in name, so its devices and sources are
+//! kept and offered the selections by [`super::data_control`].
+
+use std::os::fd::AsFd;
+
+use log::debug;
+use wayland_protocols::ext::data_control::v1::server::{
+    ext_data_control_device_v1, ext_data_control_manager_v1, ext_data_control_offer_v1,
+    ext_data_control_source_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::data_control::{DataControlDevice, DataControlSource, Selection};
+use super::ServerState;
+use crate::protocol::data_device::{DataOfferId, DataSourceId};
+
+impl GlobalDispatch<ext_data_control_manager_v1::ExtDataControlManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ext_data_control_manager_v1::ExtDataControlManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound ext_data_control_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<ext_data_control_manager_v1::ExtDataControlManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ext_data_control_manager_v1::ExtDataControlManagerV1,
+        request: ext_data_control_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            ext_data_control_manager_v1::Request::CreateDataSource { id } => {
+                let source_id = state.data_device.create_data_source();
+                let source = data_init.init(id, source_id);
+                state.add_data_control_source(source_id, DataControlSource::Ext(source));
+            }
+            ext_data_control_manager_v1::Request::GetDataDevice { id, seat: _ } => {
+                let device = data_init.init(id, ());
+                state.add_data_control_device(DataControlDevice::Ext(device));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_data_control_device_v1::ExtDataControlDeviceV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ext_data_control_device_v1::ExtDataControlDeviceV1,
+        request: ext_data_control_device_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (source, selection) = match request {
+            ext_data_control_device_v1::Request::SetSelection { source } => {
+                (source, Selection::Clipboard)
+            }
+            ext_data_control_device_v1::Request::SetPrimarySelection { source } => {
+                (source, Selection::Primary)
+            }
+            _ => return,
+        };
+        let source = source.and_then(|source| source.data::<DataSourceId>().copied());
+        if !state.set_data_control_selection(source, selection) {
+            resource.post_error(
+                ext_data_control_device_v1::Error::UsedSource,
+                "The source was already used",
+            );
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &ext_data_control_device_v1::ExtDataControlDeviceV1,
+        _data: &(),
+    ) {
+        state.remove_data_control_device(&DataControlDevice::Ext(resource.clone()));
+    }
+}
+
+impl Dispatch<ext_data_control_source_v1::ExtDataControlSourceV1, DataSourceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ext_data_control_source_v1::ExtDataControlSourceV1,
+        request: ext_data_control_source_v1::Request,
+        data: &DataSourceId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_data_control_source_v1::Request::Offer { mime_type } = request {
+            if state.resources.data_controls.is_used(*data) {
+                resource.post_error(
+                    ext_data_control_source_v1::Error::InvalidOffer,
+                    "The source was already set as a selection",
+                );
+                return;
+            }
+            if let Some(source) = state.data_device.get_source_mut(*data) {
+                source.offer(mime_type);
+            }
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &ext_data_control_source_v1::ExtDataControlSourceV1,
+        data: &DataSourceId,
+    ) {
+        state.remove_data_control_source(*data);
+    }
+}
+
+impl Dispatch<ext_data_control_offer_v1::ExtDataControlOfferV1, DataOfferId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ext_data_control_offer_v1::ExtDataControlOfferV1,
+        request: ext_data_control_offer_v1::Request,
+        data: &DataOfferId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_data_control_offer_v1::Request::Receive { mime_type, fd } = request {
+            state.receive_data_control_offer(*data, mime_type, fd.as_fd());
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &ext_data_control_offer_v1::ExtDataControlOfferV1,
+        data: &DataOfferId,
+    ) {
+        state.data_device.destroy_offer(*data);
+    }
+}
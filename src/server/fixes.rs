@@ -0,0 +1,53 @@
+//! wl_fixes objects
+//!
+//! wl_fixes lets clients destroy a wl_registry, which the core protocol has
+//! no request for, so that a client binding globals through short-lived
+//! registries doesn't leak one for each of them.
+//!
+//! The registry is destroyed through the backend, which sends
+//! wl_display.delete_id for it and stops announcing globals on it.
+
+use log::debug;
+use wayland_server::protocol::wl_fixes;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+
+impl GlobalDispatch<wl_fixes::WlFixes, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_fixes::WlFixes>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wl_fixes");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wl_fixes::WlFixes, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_fixes::WlFixes,
+        request: wl_fixes::Request,
+        _data: &(),
+        dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_fixes::Request::DestroyRegistry { registry } = request {
+            debug!("Destroying registry {}", registry.id().protocol_id());
+            // Fails only if the registry is already gone
+            let _ = dhandle
+                .backend_handle()
+                .destroy_object::<Self>(&registry.id());
+        }
+    }
+}
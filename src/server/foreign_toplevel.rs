@@ -0,0 +1,331 @@
+//! zwlr_foreign_toplevel_manager_v1 objects
+//!
+//! Lists every toplevel to taskbars and docks written for wlroots
+//! compositors. Each manager gets a handle per window, announced after the
+//! dispatch it was bound in or the window was created in. Title, app_id,
+//! state and parent are compared with what each handle was last sent after
+//! every dispatch, and only the changes are sent, followed by `done`.
+//!
+//! Requests on a handle act on the window as its own client would: the
+//! native window is maximized, minimized or made fullscreen, and the client
+//! is sent a configure. `close` asks the client to close the window.
+//! Windows don't track which outputs they are on, so no `output_enter` is
+//! sent, and the rectangle a taskbar minimizes to is ignored.
+
+use log::debug;
+use wayland_protocols_wlr::foreign_toplevel::v1::server::{
+    zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::{Window, WindowId};
+
+/// What a handle was told about its window
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Description {
+    title: Option<String>,
+    app_id: Option<String>,
+    /// States as an array of native-endian u32
+    states: Vec<u8>,
+    parent: Option<WindowId>,
+}
+
+impl Description {
+    /// Describe a window as it is now
+    fn of(window: &Window) -> Self {
+        use zwlr_foreign_toplevel_handle_v1::State;
+        let flags = [
+            (window.maximized, State::Maximized),
+            (window.state.minimized, State::Minimized),
+            (window.state.activated, State::Activated),
+            (window.fullscreen, State::Fullscreen),
+        ];
+        let mut states = Vec::new();
+        for (_, flag) in flags.into_iter().filter(|(set, _)| *set) {
+            states.extend_from_slice(&(flag as u32).to_ne_bytes());
+        }
+        Self {
+            title: window.title.clone(),
+            app_id: window.app_id.clone(),
+            states,
+            parent: window.parent,
+        }
+    }
+}
+
+/// A handle given to a manager, with what it was last sent
+#[derive(Debug)]
+struct ToplevelHandle {
+    manager: zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+    window: WindowId,
+    handle: zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+    /// None until the first `done`
+    sent: Option<Description>,
+}
+
+/// Foreign toplevel managers and the handles given to them
+#[derive(Debug, Default)]
+pub struct ForeignToplevels {
+    /// Managers that haven't stopped
+    managers: Vec<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1>,
+    /// Handles of windows that are still open
+    handles: Vec<ToplevelHandle>,
+}
+
+impl GlobalDispatch<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwlr_foreign_toplevel_manager_v1");
+        let manager = data_init.init(resource, ());
+        state.resources.foreign_toplevels.managers.push(manager);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+        request: zwlr_foreign_toplevel_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Request::Stop = request {
+            debug!("Foreign toplevel manager stopped");
+            state
+                .resources
+                .foreign_toplevels
+                .managers
+                .retain(|manager| manager != resource);
+            resource.finished();
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+        _data: &(),
+    ) {
+        state
+            .resources
+            .foreign_toplevels
+            .managers
+            .retain(|manager| manager != resource);
+    }
+}
+
+impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, WindowId>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        request: zwlr_foreign_toplevel_handle_v1::Request,
+        data: &WindowId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let window_id = *data;
+        if state.compositor.windows.get(window_id).is_none() {
+            return;
+        }
+        match request {
+            zwlr_foreign_toplevel_handle_v1::Request::SetMaximized => {
+                state.set_window_maximized(window_id, true);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMaximized => {
+                state.set_window_maximized(window_id, false);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::SetMinimized => {
+                state.set_window_minimized(window_id, true);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMinimized => {
+                state.set_window_minimized(window_id, false);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::SetFullscreen { output: _ } => {
+                state.set_window_fullscreen(window_id, true, None);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetFullscreen => {
+                state.set_window_fullscreen(window_id, false, None);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::Activate { seat: _ } => {
+                state.activate_window(window_id);
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::Close => {
+                state.request_window_close(window_id);
+                return;
+            }
+            zwlr_foreign_toplevel_handle_v1::Request::SetRectangle { width, height, .. } => {
+                if width < 0 || height < 0 {
+                    resource.post_error(
+                        zwlr_foreign_toplevel_handle_v1::Error::InvalidRectangle,
+                        "The rectangle has a negative size",
+                    );
+                }
+                return;
+            }
+            _ => return,
+        }
+        if let Some(toplevel) = state.resources.toplevels.get(&window_id).cloned() {
+            super::globals::configure_toplevel(state, &toplevel);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        _data: &WindowId,
+    ) {
+        state
+            .resources
+            .foreign_toplevels
+            .handles
+            .retain(|handle| handle.handle != *resource);
+    }
+}
+
+impl ServerState {
+    /// Announce new windows to managers, send what changed about the
+    /// others and close the handles of windows that are gone
+    pub(super) fn update_foreign_toplevels(&mut self) {
+        let windows = &self.compositor.windows;
+        let foreign = &mut self.resources.foreign_toplevels;
+        if foreign.managers.is_empty() && foreign.handles.is_empty() {
+            return;
+        }
+
+        foreign.handles.retain(|handle| {
+            let open = windows.get(handle.window).is_some();
+            if !open {
+                handle.handle.closed();
+            }
+            open
+        });
+
+        let mut ids: Vec<WindowId> = windows
+            .iter()
+            .filter(|(_, window)| !window.layer_surface)
+            .map(|(&id, _)| id)
+            .collect();
+        ids.sort_by_key(|id| id.0);
+        for manager in &foreign.managers {
+            let (Some(client), Some(dh)) = (
+                manager.client(),
+                manager.handle().upgrade().map(DisplayHandle::from),
+            ) else {
+                continue;
+            };
+            for &window in &ids {
+                let announced = foreign
+                    .handles
+                    .iter()
+                    .any(|handle| handle.manager == *manager && handle.window == window);
+                if announced {
+                    continue;
+                }
+                let Ok(handle) = client
+                    .create_resource::<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, _, Self>(
+                        &dh,
+                        manager.version(),
+                        window,
+                    )
+                else {
+                    continue;
+                };
+                manager.toplevel(&handle);
+                foreign.handles.push(ToplevelHandle {
+                    manager: manager.clone(),
+                    window,
+                    handle,
+                    sent: None,
+                });
+            }
+        }
+
+        for i in 0..foreign.handles.len() {
+            let Some(window) = windows.get(foreign.handles[i].window) else {
+                continue;
+            };
+            let mut now = Description::of(window);
+            // The parent is sent once the manager has a handle for it
+            let parent = now.parent.and_then(|parent| {
+                foreign.handles.iter().find(|other| {
+                    other.manager == foreign.handles[i].manager && other.window == parent
+                })
+            });
+            let parent = parent.map(|parent| parent.handle.clone());
+            if parent.is_none() {
+                now.parent = None;
+            }
+
+            let entry = &mut foreign.handles[i];
+            if entry.sent.as_ref() == Some(&now) {
+                continue;
+            }
+            let first = entry.sent.is_none();
+            let sent = entry.sent.take().unwrap_or_default();
+            let handle = &entry.handle;
+            if let Some(title) = now.title.as_ref().filter(|_| sent.title != now.title) {
+                handle.title(title.clone());
+            }
+            if let Some(app_id) = now.app_id.as_ref().filter(|_| sent.app_id != now.app_id) {
+                handle.app_id(app_id.clone());
+            }
+            if first || sent.states != now.states {
+                handle.state(now.states.clone());
+            }
+            if sent.parent != now.parent && handle.version() >= 3 {
+                handle.parent(parent.as_ref());
+            }
+            handle.done();
+            entry.sent = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::SurfaceId;
+
+    #[test]
+    fn test_description() {
+        let mut state = ServerState::new();
+        let id = state.compositor.windows.create_window(SurfaceId(1));
+        let states = |state: &ServerState| {
+            Description::of(state.compositor.windows.get(id).unwrap())
+                .states
+                .chunks(4)
+                .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert!(states(&state).is_empty());
+
+        state.set_window_maximized(id, true);
+        state.set_window_minimized(id, true);
+        assert_eq!(states(&state), vec![0, 1]);
+
+        state.set_window_minimized(id, false);
+        state.set_window_fullscreen(id, true, None);
+        assert_eq!(states(&state), vec![0, 3]);
+    }
+}
@@ -0,0 +1,201 @@
+//! wp_fractional_scale_manager_v1 objects
+//!
+//! Each wp_fractional_scale tells its surface the scale to render at, in
+//! 120ths. The scale follows the backing scale factor of the screen the
+//! surface's window is on and is sent again whenever it changes, e.g. when
+//! the window is dragged to another display.
+
+use log::debug;
+use wayland_protocols::wp::fractional_scale::v1::server::{
+    wp_fractional_scale_manager_v1, wp_fractional_scale_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+
+/// Denominator of preferred scales on the wire
+const SCALE_DENOMINATOR: f64 = 120.0;
+
+/// A surface's wp_fractional_scale and the scale last sent on it
+#[derive(Debug)]
+pub struct SurfaceScale {
+    /// The wp_fractional_scale object
+    pub resource: wp_fractional_scale_v1::WpFractionalScaleV1,
+    /// Preferred scale last sent, in 120ths
+    pub preferred: Option<u32>,
+}
+
+impl GlobalDispatch<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_fractional_scale_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        request: wp_fractional_scale_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_fractional_scale_manager_v1::Request::GetFractionalScale { id, surface } = request
+        {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if state.resources.fractional_scales.contains_key(&surface_id) {
+                resource.post_error(
+                    wp_fractional_scale_manager_v1::Error::FractionalScaleExists,
+                    "surface already has a fractional scale",
+                );
+                return;
+            }
+            let fractional_scale = data_init.init(id, surface_id);
+            state.resources.fractional_scales.insert(
+                surface_id,
+                SurfaceScale {
+                    resource: fractional_scale,
+                    preferred: None,
+                },
+            );
+            state.update_fractional_scales();
+        }
+    }
+}
+
+impl Dispatch<wp_fractional_scale_v1::WpFractionalScaleV1, SurfaceId> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wp_fractional_scale_v1::WpFractionalScaleV1,
+        request: wp_fractional_scale_v1::Request,
+        _data: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_fractional_scale_v1::Request::Destroy = request {
+            debug!("wp_fractional_scale destroy");
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &wp_fractional_scale_v1::WpFractionalScaleV1,
+        data: &SurfaceId,
+    ) {
+        if state
+            .resources
+            .fractional_scales
+            .get(data)
+            .is_some_and(|scale| scale.resource.id() == resource.id())
+        {
+            state.resources.fractional_scales.remove(data);
+        }
+    }
+}
+
+impl ServerState {
+    /// The scale a surface should render at
+    ///
+    /// This is the backing scale factor of its window's screen, or of the
+    /// headless output the window is fullscreen on. Surfaces without a
+    /// window yet get the primary output's scale.
+    pub fn surface_scale(&self, surface: SurfaceId) -> f64 {
+        let outputs = &self.compositor.outputs;
+        let root = self.compositor.surfaces.root(surface);
+        let window = self.compositor.windows.get_by_surface(root);
+
+        if let Some(output) = window
+            .and_then(|window| window.output)
+            .and_then(|id| outputs.get(id))
+        {
+            return output.fractional_scale;
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(native) = window.and_then(|window| self.backend.native_window(window.id)) {
+            return native.backing_scale();
+        }
+        window
+            .and_then(|window| outputs.output_at(window.geometry.x, window.geometry.y))
+            .or_else(|| outputs.primary())
+            .map(|output| output.fractional_scale)
+            .unwrap_or(1.0)
+    }
+
+    /// Send the preferred scale of surfaces whose scale changed
+    pub fn update_fractional_scales(&mut self) {
+        let preferred: Vec<_> = self
+            .resources
+            .fractional_scales
+            .keys()
+            .map(|&id| (id, preferred_scale(self.surface_scale(id))))
+            .collect();
+        for (id, scale) in preferred {
+            let Some(entry) = self.resources.fractional_scales.get_mut(&id) else {
+                continue;
+            };
+            if entry.preferred != Some(scale) {
+                debug!("Surface {:?} preferred scale {}/120", id, scale);
+                entry.resource.preferred_scale(scale);
+                entry.preferred = Some(scale);
+            }
+        }
+    }
+}
+
+/// Convert a scale factor to 120ths
+fn preferred_scale(scale: f64) -> u32 {
+    (scale * SCALE_DENOMINATOR).round().max(1.0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surface_scale() {
+        let mut state = ServerState::new();
+        let root = state.compositor.surfaces.create_surface();
+        let child = state.compositor.surfaces.create_surface();
+        state.compositor.surfaces.get_mut(child).unwrap().parent = Some(root);
+        assert_eq!(state.surface_scale(child), 1.0);
+
+        let output = state.compositor.outputs.create_headless(
+            "virtual-0".to_string(),
+            2560,
+            1440,
+            2,
+            60_000,
+        );
+        state
+            .compositor
+            .outputs
+            .get_mut(output)
+            .unwrap()
+            .fractional_scale = 1.5;
+        let window = state.compositor.windows.create_window(root);
+        state.compositor.windows.get_mut(window).unwrap().output = Some(output);
+        assert_eq!(preferred_scale(state.surface_scale(child)), 180);
+        assert_eq!(preferred_scale(0.0), 1);
+    }
+}
@@ -2,51 +2,89 @@
 //!
 //! Implements GlobalDispatch for advertising globals to clients.
 
+use std::sync::Mutex;
+
 use log::debug;
 use wayland_protocols::xdg::shell::server::xdg_wm_base;
-use wayland_server::protocol::{wl_compositor, wl_output, wl_seat, wl_shm};
-use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+use wayland_server::protocol::{wl_compositor, wl_output, wl_seat, wl_shm, wl_subcompositor};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
 
-use super::dispatch::{OutputData, SeatData};
+use super::client::{client_info, GlobalData};
+use super::output::OutputData;
+use super::seat::{wayland_capabilities, SeatData};
 use super::ServerState;
+use crate::session::JournalEvent;
 
 // ============================================================================
 // wl_compositor global
 // ============================================================================
 
-impl GlobalDispatch<wl_compositor::WlCompositor, ()> for ServerState {
+impl GlobalDispatch<wl_compositor::WlCompositor, GlobalData> for ServerState {
     fn bind(
         _state: &mut Self,
         _handle: &wayland_server::DisplayHandle,
         _client: &Client,
         resource: New<wl_compositor::WlCompositor>,
-        _global_data: &(),
+        _global_data: &GlobalData,
         data_init: &mut DataInit<'_, Self>,
     ) {
         debug!("Client bound wl_compositor");
         data_init.init(resource, ());
     }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
 }
 
 // ============================================================================
-// wl_shm global
+// wl_subcompositor global
 // ============================================================================
 
-impl GlobalDispatch<wl_shm::WlShm, ()> for ServerState {
+impl GlobalDispatch<wl_subcompositor::WlSubcompositor, GlobalData> for ServerState {
     fn bind(
         _state: &mut Self,
         _handle: &wayland_server::DisplayHandle,
         _client: &Client,
+        resource: New<wl_subcompositor::WlSubcompositor>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wl_subcompositor");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+// ============================================================================
+// wl_shm global
+// ============================================================================
+
+impl GlobalDispatch<wl_shm::WlShm, GlobalData> for ServerState {
+    fn bind(
+        state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
         resource: New<wl_shm::WlShm>,
-        _global_data: &(),
+        _global_data: &GlobalData,
         data_init: &mut DataInit<'_, Self>,
     ) {
         debug!("Client bound wl_shm");
         let shm = data_init.init(resource, ());
 
         // Send supported formats
-        shm.format(wl_shm::Format::Argb8888);
-        shm.format(wl_shm::Format::Xrgb8888);
+        for format in state.shm.formats() {
+            if let Ok(format) = wl_shm::Format::try_from(format.to_wayland()) {
+                shm.format(format);
+            }
+        }
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
     }
 }
 
@@ -54,28 +92,32 @@ impl GlobalDispatch<wl_shm::WlShm, ()> for ServerState {
 // wl_seat global
 // ============================================================================
 
-impl GlobalDispatch<wl_seat::WlSeat, ()> for ServerState {
+impl GlobalDispatch<wl_seat::WlSeat, GlobalData> for ServerState {
     fn bind(
-        _state: &mut Self,
+        state: &mut Self,
         _handle: &wayland_server::DisplayHandle,
         _client: &Client,
         resource: New<wl_seat::WlSeat>,
-        _global_data: &(),
+        _global_data: &GlobalData,
         data_init: &mut DataInit<'_, Self>,
     ) {
         debug!("Client bound wl_seat");
 
-        let capabilities = wl_seat::Capability::Pointer | wl_seat::Capability::Keyboard;
-
-        let seat = data_init.init(resource, SeatData { capabilities });
+        let seat = data_init.init(resource, SeatData::default());
 
         // Send capabilities
-        seat.capabilities(capabilities);
+        seat.capabilities(wayland_capabilities(state.compositor.seat.capabilities()));
 
         // Send name if version >= 2
         if seat.version() >= 2 {
-            seat.name("seat0".to_string());
+            seat.name(state.compositor.seat.name().to_string());
         }
+
+        state.resources.seats.add_seat(seat);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
     }
 }
 
@@ -83,84 +125,38 @@ impl GlobalDispatch<wl_seat::WlSeat, ()> for ServerState {
 // wl_output global
 // ============================================================================
 
-impl GlobalDispatch<wl_output::WlOutput, ()> for ServerState {
+impl GlobalDispatch<wl_output::WlOutput, GlobalData> for ServerState {
     fn bind(
         state: &mut Self,
         _handle: &wayland_server::DisplayHandle,
         _client: &Client,
         resource: New<wl_output::WlOutput>,
-        _global_data: &(),
+        _global_data: &GlobalData,
         data_init: &mut DataInit<'_, Self>,
     ) {
         debug!("Client bound wl_output");
 
-        // Create a default output if we don't have one
-        let output_id = if state.compositor.outputs.is_empty() {
-            state.compositor.outputs.create_output(
-                "default".to_string(),
-                "Wayoa".to_string(),
-                "Virtual Display".to_string(),
-            )
-        } else {
-            state
-                .compositor
-                .outputs
-                .iter()
-                .next()
-                .map(|(id, _)| *id)
-                .unwrap()
-        };
-
-        let output = data_init.init(resource, OutputData { output_id });
-
-        // Get output info
-        if let Some(out) = state.compositor.outputs.get(output_id) {
-            // Send geometry
-            output.geometry(
-                0, // x
-                0, // y
-                out.physical_width as i32,
-                out.physical_height as i32,
-                wl_output::Subpixel::Unknown,
-                out.make.clone(),
-                out.model.clone(),
-                wl_output::Transform::Normal,
-            );
-
-            // Send mode
-            if let Some(mode) = out.current_mode() {
-                output.mode(
-                    wl_output::Mode::Current | wl_output::Mode::Preferred,
-                    mode.width as i32,
-                    mode.height as i32,
-                    mode.refresh as i32,
-                );
-            } else {
-                // Default mode
-                output.mode(
-                    wl_output::Mode::Current | wl_output::Mode::Preferred,
-                    1920,
-                    1080,
-                    60000,
+        // Create a default output if we don't have one. Headless outputs
+        // have globals of their own.
+        let output_id = match state.compositor.outputs.primary().filter(|o| !o.headless) {
+            Some(output) => output.id,
+            None => {
+                let id = state.compositor.outputs.create_output(
+                    "default".to_string(),
+                    "Wayoa".to_string(),
+                    "Virtual Display".to_string(),
                 );
+                state.compositor.outputs.set_primary(id);
+                id
             }
+        };
 
-            // Send scale if version >= 2
-            if output.version() >= 2 {
-                output.scale(out.scale);
-            }
-
-            // Send name if version >= 4
-            if output.version() >= 4 {
-                output.name(out.name.clone());
-                output.description(format!("{} {}", out.make, out.model));
-            }
+        let output = data_init.init(resource, OutputData { output_id });
+        state.bind_output(output_id, output);
+    }
 
-            // Send done if version >= 2
-            if output.version() >= 2 {
-                output.done();
-            }
-        }
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
     }
 }
 
@@ -168,25 +164,30 @@ impl GlobalDispatch<wl_output::WlOutput, ()> for ServerState {
 // xdg_wm_base global
 // ============================================================================
 
-impl GlobalDispatch<xdg_wm_base::XdgWmBase, ()> for ServerState {
+impl GlobalDispatch<xdg_wm_base::XdgWmBase, GlobalData> for ServerState {
     fn bind(
-        _state: &mut Self,
+        state: &mut Self,
         _handle: &wayland_server::DisplayHandle,
         _client: &Client,
         resource: New<xdg_wm_base::XdgWmBase>,
-        _global_data: &(),
+        _global_data: &GlobalData,
         data_init: &mut DataInit<'_, Self>,
     ) {
         debug!("Client bound xdg_wm_base");
-        data_init.init(resource, ());
+        let wm_base = data_init.init(resource, ());
+        state.add_wm_base(wm_base);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
     }
 }
 
 impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ServerState {
     fn request(
-        _state: &mut Self,
-        _client: &Client,
-        _resource: &xdg_wm_base::XdgWmBase,
+        state: &mut Self,
+        client: &Client,
+        resource: &xdg_wm_base::XdgWmBase,
         request: xdg_wm_base::Request,
         _data: &(),
         _dhandle: &wayland_server::DisplayHandle,
@@ -205,11 +206,13 @@ impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ServerState {
                     XdgSurfaceData {
                         surface_id,
                         configured: false,
+                        wm_base: resource.clone(),
                     },
                 );
             }
             xdg_wm_base::Request::Pong { serial } => {
                 debug!("Received pong for serial {}", serial);
+                state.pong(client.id(), serial);
             }
             xdg_wm_base::Request::Destroy => {
                 debug!("xdg_wm_base destroy");
@@ -217,6 +220,15 @@ impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ServerState {
             _ => {}
         }
     }
+
+    fn destroyed(
+        state: &mut Self,
+        client: wayland_server::backend::ClientId,
+        resource: &xdg_wm_base::XdgWmBase,
+        _data: &(),
+    ) {
+        state.remove_wm_base(client, resource);
+    }
 }
 
 // ============================================================================
@@ -225,33 +237,47 @@ impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ServerState {
 
 use wayland_protocols::xdg::shell::server::xdg_positioner;
 
+use crate::compositor::SurfaceId;
+use crate::protocol::shell::{Anchor, Gravity, PopupGeometry, XdgPositioner};
+use crate::protocol::XdgShellHandler;
+
 /// Positioner data for popup placement
+///
+/// Positioners are mutable until used, so the state lives behind a lock.
 #[derive(Debug, Default)]
-pub struct PositionerData {
-    pub width: i32,
-    pub height: i32,
-    pub anchor_rect: (i32, i32, i32, i32),
-    pub anchor: u32,
-    pub gravity: u32,
-    pub constraint_adjustment: u32,
-    pub offset: (i32, i32),
+pub struct PositionerData(Mutex<XdgPositioner>);
+
+impl PositionerData {
+    /// Get a copy of the positioner state
+    pub fn get(&self) -> XdgPositioner {
+        *self.0.lock().unwrap()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut XdgPositioner)) {
+        f(&mut self.0.lock().unwrap())
+    }
 }
 
 impl Dispatch<xdg_positioner::XdgPositioner, PositionerData> for ServerState {
     fn request(
         _state: &mut Self,
         _client: &Client,
-        _resource: &xdg_positioner::XdgPositioner,
+        resource: &xdg_positioner::XdgPositioner,
         request: xdg_positioner::Request,
-        _data: &PositionerData,
+        data: &PositionerData,
         _dhandle: &wayland_server::DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
-        // Note: In a real implementation, we'd need interior mutability for data
-        // For now, just log the requests
+        let invalid_input = |message: &str| {
+            resource.post_error(xdg_positioner::Error::InvalidInput, message.to_string())
+        };
         match request {
             xdg_positioner::Request::SetSize { width, height } => {
                 debug!("Positioner set size {}x{}", width, height);
+                if width <= 0 || height <= 0 {
+                    return invalid_input("size must be positive");
+                }
+                data.update(|p| p.set_size(width, height));
             }
             xdg_positioner::Request::SetAnchorRect {
                 x,
@@ -263,12 +289,34 @@ impl Dispatch<xdg_positioner::XdgPositioner, PositionerData> for ServerState {
                     "Positioner set anchor rect ({}, {}, {}, {})",
                     x, y, width, height
                 );
+                if width < 0 || height < 0 {
+                    return invalid_input("anchor rect size must not be negative");
+                }
+                data.update(|p| p.set_anchor_rect(x, y, width, height));
             }
             xdg_positioner::Request::SetAnchor { anchor } => {
                 debug!("Positioner set anchor {:?}", anchor);
+                match anchor
+                    .into_result()
+                    .ok()
+                    .map(u32::from)
+                    .and_then(Anchor::from_wayland)
+                {
+                    Some(anchor) => data.update(|p| p.set_anchor(anchor)),
+                    None => invalid_input("invalid anchor"),
+                }
             }
             xdg_positioner::Request::SetGravity { gravity } => {
                 debug!("Positioner set gravity {:?}", gravity);
+                match gravity
+                    .into_result()
+                    .ok()
+                    .map(u32::from)
+                    .and_then(Gravity::from_wayland)
+                {
+                    Some(gravity) => data.update(|p| p.set_gravity(gravity)),
+                    None => invalid_input("invalid gravity"),
+                }
             }
             xdg_positioner::Request::SetConstraintAdjustment {
                 constraint_adjustment,
@@ -277,9 +325,15 @@ impl Dispatch<xdg_positioner::XdgPositioner, PositionerData> for ServerState {
                     "Positioner set constraint adjustment {:?}",
                     constraint_adjustment
                 );
+                let bits = match constraint_adjustment {
+                    WEnum::Value(adjustment) => adjustment.bits(),
+                    WEnum::Unknown(bits) => bits,
+                };
+                data.update(|p| p.set_constraint_adjustment(bits));
             }
             xdg_positioner::Request::SetOffset { x, y } => {
                 debug!("Positioner set offset ({}, {})", x, y);
+                data.update(|p| p.set_offset(x, y));
             }
             xdg_positioner::Request::Destroy => {
                 debug!("Positioner destroy");
@@ -299,12 +353,33 @@ use wayland_protocols::xdg::shell::server::xdg_surface;
 pub struct XdgSurfaceData {
     pub surface_id: crate::compositor::SurfaceId,
     pub configured: bool,
+    /// The xdg_wm_base it was created from, which positioner errors are
+    /// posted on
+    pub wm_base: xdg_wm_base::XdgWmBase,
+}
+
+/// Send an xdg_surface configure with a new serial, after its role's
+/// configure
+#[cfg_attr(not(feature = "strict"), allow(unused_variables))]
+fn configure_xdg_surface(
+    state: &mut ServerState,
+    xdg_surface: &xdg_surface::XdgSurface,
+    surface_id: crate::compositor::SurfaceId,
+) -> u32 {
+    let serial = state.compositor.next_serial();
+    xdg_surface.configure(serial);
+    #[cfg(feature = "strict")]
+    {
+        state.strict.add_xdg_surface(surface_id, xdg_surface);
+        state.strict.configured(surface_id, serial);
+    }
+    serial
 }
 
 impl Dispatch<xdg_surface::XdgSurface, XdgSurfaceData> for ServerState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         resource: &xdg_surface::XdgSurface,
         request: xdg_surface::Request,
         data: &XdgSurfaceData,
@@ -322,46 +397,80 @@ impl Dispatch<xdg_surface::XdgSurface, XdgSurfaceData> for ServerState {
 
                 // Create window
                 let window_id = state.compositor.windows.create_window(data.surface_id);
+                let quirks = state.client_quirks(client, None);
+                state
+                    .scheduler
+                    .set_frames_in_flight(window_id, quirks.frames_in_flight);
+                if let Some(window) = state.compositor.windows.get_mut(window_id) {
+                    window.color_filter = quirks.color_filter;
+                }
+                state.journal.record(JournalEvent::WindowCreated {
+                    window: window_id.0,
+                    client: client_info(client).map(|info| info.id.0),
+                });
 
                 let toplevel = data_init.init(
                     id,
                     ToplevelData {
                         surface_id: data.surface_id,
                         window_id,
+                        xdg_surface: resource.clone(),
                     },
                 );
 
                 // Send initial configure
                 toplevel.configure(640, 480, vec![]);
+                state.resources.toplevels.insert(window_id, toplevel);
 
                 // Send xdg_surface configure
-                let serial = state.compositor.next_serial();
-                resource.configure(serial);
+                configure_xdg_surface(state, resource, data.surface_id);
             }
             xdg_surface::Request::GetPopup {
                 id,
-                parent: _,
-                positioner: _,
+                parent,
+                positioner,
             } => {
                 debug!("Creating xdg_popup for surface {:?}", data.surface_id);
+                let parent = parent
+                    .as_ref()
+                    .and_then(|parent| parent.data::<XdgSurfaceData>())
+                    .map(|parent| parent.surface_id);
+                let Some(positioner) = positioner.data::<PositionerData>().map(|p| p.get()) else {
+                    return;
+                };
+                if !positioner.is_complete() {
+                    data.wm_base.post_error(
+                        xdg_wm_base::Error::InvalidPositioner,
+                        "positioner has no size",
+                    );
+                    return;
+                }
 
-                // Set surface role
+                // Set surface role and draw the popup as a child of its parent
                 if let Some(surface) = state.compositor.surfaces.get_mut(data.surface_id) {
                     let _ = surface.set_role(crate::compositor::SurfaceRole::XdgPopup);
+                    surface.parent = parent;
+                }
+                if let Some(parent) = parent.and_then(|p| state.compositor.surfaces.get_mut(p)) {
+                    parent.children.push(data.surface_id);
                 }
+                let bounds = parent.and_then(|parent| popup_bounds(state, parent));
+                let geometry = XdgShellHandler
+                    .place_popup(&mut state.compositor, data.surface_id, &positioner, bounds)
+                    .unwrap_or_default();
 
                 let popup = data_init.init(
                     id,
                     PopupData {
                         surface_id: data.surface_id,
+                        parent,
+                        xdg_surface: resource.clone(),
                     },
                 );
 
                 // Send configure
-                popup.configure(0, 0, 200, 200);
-
-                let serial = state.compositor.next_serial();
-                resource.configure(serial);
+                popup.configure(geometry.x, geometry.y, geometry.width, geometry.height);
+                configure_xdg_surface(state, resource, data.surface_id);
             }
             xdg_surface::Request::SetWindowGeometry {
                 x,
@@ -373,6 +482,16 @@ impl Dispatch<xdg_surface::XdgSurface, XdgSurfaceData> for ServerState {
             }
             xdg_surface::Request::AckConfigure { serial } => {
                 debug!("Ack configure {}", serial);
+                if let Some(window) = state
+                    .compositor
+                    .windows
+                    .window_for_surface(data.surface_id)
+                    .and_then(|window_id| state.compositor.windows.get_mut(window_id))
+                {
+                    window.ack_configure(serial);
+                }
+                #[cfg(feature = "strict")]
+                state.strict_ack_configure(resource, data.surface_id, serial);
             }
             xdg_surface::Request::Destroy => {
                 debug!("xdg_surface destroy");
@@ -386,42 +505,241 @@ impl Dispatch<xdg_surface::XdgSurface, XdgSurfaceData> for ServerState {
 // xdg_toplevel
 // ============================================================================
 
+use wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1;
 use wayland_protocols::xdg::shell::server::xdg_toplevel;
 
 /// Toplevel window data
 pub struct ToplevelData {
     pub surface_id: crate::compositor::SurfaceId,
     pub window_id: crate::compositor::WindowId,
+    /// The xdg_surface, for configure events
+    pub xdg_surface: xdg_surface::XdgSurface,
+}
+
+/// Send a configure sequence for a toplevel's current state
+///
+/// The size is left to the client, unless a resize configure is still
+/// waiting for its ack. That size is sent again, under the new serial the
+/// resize now waits for, since the client may only ack the latest
+/// configure.
+pub(super) fn configure_toplevel(state: &mut ServerState, toplevel: &xdg_toplevel::XdgToplevel) {
+    let window_id = toplevel.data::<ToplevelData>().map(|data| data.window_id);
+    let pending = window_id
+        .and_then(|id| state.compositor.windows.get(id))
+        .and_then(|window| window.resize)
+        .filter(|resize| !resize.acked);
+    let Some(pending) = pending else {
+        send_toplevel_configure(state, toplevel, (0, 0), false);
+        return;
+    };
+    let serial = send_toplevel_configure(state, toplevel, pending.size, pending.resizing);
+    if let (Some(serial), Some(window)) = (
+        serial,
+        window_id.and_then(|id| state.compositor.windows.get_mut(id)),
+    ) {
+        window.resize_configured(serial, pending.size, pending.resizing);
+    }
+}
+
+/// Send a configure sequence for a toplevel's state with a size, returning
+/// the serial
+///
+/// `resizing` adds the resizing state, while the user drags an edge of the
+/// window.
+fn send_toplevel_configure(
+    state: &mut ServerState,
+    toplevel: &xdg_toplevel::XdgToplevel,
+    (width, height): (u32, u32),
+    resizing: bool,
+) -> Option<u32> {
+    let data = toplevel.data::<ToplevelData>()?;
+    let mut states = Vec::new();
+    if let Some(window) = state.compositor.windows.get(data.window_id) {
+        let flags = [
+            (window.maximized, xdg_toplevel::State::Maximized),
+            (window.fullscreen, xdg_toplevel::State::Fullscreen),
+            (window.state.activated, xdg_toplevel::State::Activated),
+            (resizing, xdg_toplevel::State::Resizing),
+        ];
+        for (_, flag) in flags.into_iter().filter(|(set, _)| *set) {
+            states.extend_from_slice(&(flag as u32).to_ne_bytes());
+        }
+    }
+    toplevel.configure(width as i32, height as i32, states);
+    Some(configure_xdg_surface(
+        state,
+        &data.xdg_surface,
+        data.surface_id,
+    ))
+}
+
+impl ServerState {
+    /// Tell a toplevel the content size the user resized its native window
+    /// to
+    ///
+    /// `resizing` is set during a live resize. See [`Window::wants_resize`]
+    /// for when the configure is held back.
+    ///
+    /// [`Window::wants_resize`]: crate::compositor::Window::wants_resize
+    pub fn native_window_resized(
+        &mut self,
+        window_id: crate::compositor::WindowId,
+        size: (u32, u32),
+        resizing: bool,
+    ) {
+        let Some(toplevel) = self.resources.toplevels.get(&window_id).cloned() else {
+            return;
+        };
+        let wanted = self
+            .compositor
+            .windows
+            .get_mut(window_id)
+            .is_some_and(|window| window.wants_resize(size, resizing));
+        if !wanted {
+            return;
+        }
+        let Some(serial) = send_toplevel_configure(self, &toplevel, size, resizing) else {
+            return;
+        };
+        debug!(
+            "Configured {:?} to {}x{}{}",
+            window_id,
+            size.0,
+            size.1,
+            if resizing { " while resizing" } else { "" }
+        );
+        if let Some(window) = self.compositor.windows.get_mut(window_id) {
+            window.resize_configured(serial, size, resizing);
+        }
+    }
+
+    /// Follow a commit of a toplevel resized by the user
+    ///
+    /// A client that settles on another size than the one a resize ended
+    /// with gets its native window resized to match.
+    pub(super) fn commit_toplevel_size(&mut self, surface_id: SurfaceId) {
+        let Some(window_id) = self.compositor.windows.window_for_surface(surface_id) else {
+            return;
+        };
+        let Some((width, height)) = self
+            .compositor
+            .surfaces
+            .get(surface_id)
+            .and_then(|surface| surface.size())
+        else {
+            return;
+        };
+        let size = (width.max(0) as u32, height.max(0) as u32);
+        let Some(settled) = self
+            .compositor
+            .windows
+            .get_mut(window_id)
+            .and_then(|window| window.resize_committed(size))
+        else {
+            return;
+        };
+        debug!("{:?} settled on {}x{}", window_id, settled.0, settled.1);
+        #[cfg(target_os = "macos")]
+        if let Some(native) = self.backend.native_window(window_id) {
+            native.set_size(settled.0, settled.1);
+        }
+    }
+
+    /// Ask the client of a window to close it, as its close button does
+    ///
+    /// The window stays until the client destroys it. See
+    /// [`Window::close_expired`] for when the native window is closed
+    /// anyway.
+    ///
+    /// [`Window::close_expired`]: crate::compositor::Window::close_expired
+    pub fn request_window_close(&mut self, window_id: crate::compositor::WindowId) {
+        debug!("Asking toplevel {:?} to close", window_id);
+        if let Some(toplevel) = self.resources.toplevels.get(&window_id) {
+            toplevel.close();
+        } else if !self.xwayland.request_close(window_id) {
+            return;
+        }
+        if let Some(window) = self.compositor.windows.get_mut(window_id) {
+            window.request_close(std::time::Instant::now());
+        }
+    }
 }
 
 impl Dispatch<xdg_toplevel::XdgToplevel, ToplevelData> for ServerState {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         _resource: &xdg_toplevel::XdgToplevel,
         request: xdg_toplevel::Request,
         data: &ToplevelData,
-        _dhandle: &wayland_server::DisplayHandle,
+        dhandle: &wayland_server::DisplayHandle,
         _data_init: &mut DataInit<'_, Self>,
     ) {
         match request {
-            xdg_toplevel::Request::SetParent { parent: _ } => {
-                debug!("Toplevel {:?} set parent", data.window_id);
+            xdg_toplevel::Request::SetParent { parent } => {
+                let parent = parent
+                    .as_ref()
+                    .and_then(|parent| parent.data::<ToplevelData>())
+                    .map(|parent| parent.window_id);
+                debug!("Toplevel {:?} set parent {:?}", data.window_id, parent);
+                if !state.compositor.windows.set_parent(data.window_id, parent) {
+                    debug!("Ignoring parent of toplevel {:?}", data.window_id);
+                }
+                #[cfg(target_os = "macos")]
+                state.sync_dialog_windows();
             }
             xdg_toplevel::Request::SetTitle { title } => {
                 debug!("Toplevel {:?} set title: {}", data.window_id, title);
-                if let Some(window) = state.compositor.windows.get_mut(data.window_id) {
-                    window.title = Some(title.clone());
-                }
+                state
+                    .compositor
+                    .windows
+                    .set_title(data.window_id, title.clone());
                 #[cfg(target_os = "macos")]
-                if let Some(native_window) = state.native_windows.get(&data.window_id) {
+                if let Some(native_window) = state.backend.native_window(data.window_id) {
                     native_window.set_title(&title);
                 }
             }
             xdg_toplevel::Request::SetAppId { app_id } => {
                 debug!("Toplevel {:?} set app_id: {}", data.window_id, app_id);
+
+                // Link the app_id to the process we spawned, if any, and pick
+                // up the placement it had in the last session
+                let mut placement = None;
+                if let Ok(credentials) = client.get_credentials(dhandle) {
+                    let pid = credentials.pid as u32;
+                    if state
+                        .session
+                        .spawner
+                        .associate_app_id(pid, &app_id, data.window_id)
+                    {
+                        placement = state.session.take_placement(&app_id);
+                    }
+                }
+
+                let quirks = state.client_quirks(client, Some(&app_id));
+                if !quirks.is_empty() {
+                    debug!("Quirks for app_id {}: {:?}", app_id, quirks);
+                }
+                state
+                    .scheduler
+                    .set_frames_in_flight(data.window_id, quirks.frames_in_flight);
+                if quirks.snapshot_buffers {
+                    state.resources.buffers.snapshot_client(client.id());
+                }
+
+                state.compositor.windows.set_app_id(data.window_id, app_id);
                 if let Some(window) = state.compositor.windows.get_mut(data.window_id) {
-                    window.app_id = Some(app_id);
+                    if placement.is_some() {
+                        window.placement = placement;
+                    }
+                    window.color_filter = quirks.color_filter;
+                }
+
+                #[cfg(target_os = "macos")]
+                if let (Some(placement), Some(native)) =
+                    (placement, state.backend.native_window(data.window_id))
+                {
+                    native.set_placement(placement);
                 }
             }
             xdg_toplevel::Request::ShowWindowMenu {
@@ -459,64 +777,68 @@ impl Dispatch<xdg_toplevel::XdgToplevel, ToplevelData> for ServerState {
             }
             xdg_toplevel::Request::SetMaximized => {
                 debug!("Toplevel {:?} set maximized", data.window_id);
-                if let Some(window) = state.compositor.windows.get_mut(data.window_id) {
-                    window.maximized = true;
-                }
-                #[cfg(target_os = "macos")]
-                if let Some(native_window) = state.native_windows.get(&data.window_id) {
-                    native_window.set_maximized(true);
-                }
+                state.set_window_maximized(data.window_id, true);
             }
             xdg_toplevel::Request::UnsetMaximized => {
                 debug!("Toplevel {:?} unset maximized", data.window_id);
-                if let Some(window) = state.compositor.windows.get_mut(data.window_id) {
-                    window.maximized = false;
-                }
-                #[cfg(target_os = "macos")]
-                if let Some(native_window) = state.native_windows.get(&data.window_id) {
-                    native_window.set_maximized(false);
-                }
+                state.set_window_maximized(data.window_id, false);
             }
-            xdg_toplevel::Request::SetFullscreen { output: _ } => {
+            xdg_toplevel::Request::SetFullscreen { output } => {
                 debug!("Toplevel {:?} set fullscreen", data.window_id);
-                if let Some(window) = state.compositor.windows.get_mut(data.window_id) {
-                    window.fullscreen = true;
-                }
-                #[cfg(target_os = "macos")]
-                if let Some(native_window) = state.native_windows.get(&data.window_id) {
-                    native_window.set_fullscreen(true);
-                }
+                let headless = output
+                    .as_ref()
+                    .and_then(|output| output.data::<OutputData>())
+                    .map(|data| data.output_id)
+                    .filter(|&id| {
+                        state
+                            .compositor
+                            .outputs
+                            .get(id)
+                            .is_some_and(|output| output.headless)
+                    });
+                state.set_window_fullscreen(data.window_id, true, headless);
             }
             xdg_toplevel::Request::UnsetFullscreen => {
                 debug!("Toplevel {:?} unset fullscreen", data.window_id);
-                if let Some(window) = state.compositor.windows.get_mut(data.window_id) {
-                    window.fullscreen = false;
-                }
-                #[cfg(target_os = "macos")]
-                if let Some(native_window) = state.native_windows.get(&data.window_id) {
-                    native_window.set_fullscreen(false);
-                }
+                state.set_window_fullscreen(data.window_id, false, None);
             }
             xdg_toplevel::Request::SetMinimized => {
                 debug!("Toplevel {:?} set minimized", data.window_id);
-                #[cfg(target_os = "macos")]
-                if let Some(native_window) = state.native_windows.get(&data.window_id) {
-                    native_window.minimize();
-                }
+                state.set_window_minimized(data.window_id, true);
             }
             xdg_toplevel::Request::Destroy => {
                 debug!("Toplevel {:?} destroy", data.window_id);
+                state.resources.toplevels.remove(&data.window_id);
+
+                if let Some(decoration) = state.resources.decorations.remove(&data.window_id) {
+                    decoration.post_error(
+                        zxdg_toplevel_decoration_v1::Error::Orphaned,
+                        "xdg_toplevel destroyed before its decoration",
+                    );
+                }
 
                 // Remove native window
                 #[cfg(target_os = "macos")]
                 {
-                    if let Some(native_window) = state.native_windows.remove(&data.window_id) {
+                    if let Some(native_window) = state.backend.remove_native_window(data.window_id)
+                    {
                         native_window.close();
                     }
                 }
 
                 // Remove window from compositor
-                state.compositor.windows.remove(data.window_id);
+                state.scheduler.remove_window(data.window_id);
+                if let Some(window) = state.compositor.windows.remove(data.window_id) {
+                    state.journal.record(JournalEvent::WindowClosed {
+                        window: window.id.0,
+                        title: window.title,
+                        app_id: window.app_id,
+                    });
+                }
+
+                // Children of the window move to its parent
+                #[cfg(target_os = "macos")]
+                state.sync_dialog_windows();
             }
             _ => {}
         }
@@ -532,13 +854,85 @@ use wayland_protocols::xdg::shell::server::xdg_popup;
 /// Popup data
 pub struct PopupData {
     pub surface_id: crate::compositor::SurfaceId,
+    /// Surface the popup is placed relative to
+    pub parent: Option<crate::compositor::SurfaceId>,
+    /// The xdg_surface, for configure events
+    pub xdg_surface: xdg_surface::XdgSurface,
+}
+
+/// Get the area a popup may cover, in its parent's surface coordinates
+///
+/// Popups are drawn into their toplevel's window, so they are kept inside
+/// it, and out of the menu bar, the Dock and exclusive zones where the
+/// window extends under them.
+fn popup_bounds(state: &ServerState, parent: SurfaceId) -> Option<PopupGeometry> {
+    let surfaces = &state.compositor.surfaces;
+    let root = surfaces.root(parent);
+    let window = state.compositor.windows.get_by_surface(root)?;
+    let (width, height) = surfaces.get(root)?.size()?;
+    let (mut x, mut y) = (window.geometry.x, window.geometry.y);
+    #[cfg(target_os = "macos")]
+    if let Some(native) = state.backend.native_window(window.id) {
+        (x, y) = native.content_origin();
+    }
+
+    // The window's part of the work area, in global coordinates
+    let (mut left, mut top, mut right, mut bottom) = (x, y, x + width, y + height);
+    if let Some(area) = state
+        .compositor
+        .outputs
+        .output_at(x, y)
+        .map(|o| o.work_area())
+    {
+        let clipped = (
+            left.max(area.x),
+            top.max(area.y),
+            right.min(area.x + area.width),
+            bottom.min(area.y + area.height),
+        );
+        if clipped.2 > clipped.0 && clipped.3 > clipped.1 {
+            (left, top, right, bottom) = clipped;
+        }
+    }
+
+    // Popups in between are placed relative to their own parents
+    let mut surface = parent;
+    while let Some(current) = surfaces.get(surface).filter(|_| surface != root) {
+        x += current.position.0;
+        y += current.position.1;
+        match current.parent {
+            Some(next) => surface = next,
+            None => break,
+        }
+    }
+
+    Some(PopupGeometry {
+        x: left - x,
+        y: top - y,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Get the surface a popup is placed relative to
+///
+/// Popups created without a parent get one from the layer surface they're
+/// assigned to.
+fn popup_parent(state: &ServerState, data: &PopupData) -> Option<SurfaceId> {
+    data.parent.or_else(|| {
+        state
+            .compositor
+            .surfaces
+            .get(data.surface_id)
+            .and_then(|surface| surface.parent)
+    })
 }
 
 impl Dispatch<xdg_popup::XdgPopup, PopupData> for ServerState {
     fn request(
-        _state: &mut Self,
+        state: &mut Self,
         _client: &Client,
-        _resource: &xdg_popup::XdgPopup,
+        resource: &xdg_popup::XdgPopup,
         request: xdg_popup::Request,
         data: &PopupData,
         _dhandle: &wayland_server::DisplayHandle,
@@ -548,14 +942,52 @@ impl Dispatch<xdg_popup::XdgPopup, PopupData> for ServerState {
             xdg_popup::Request::Grab { seat: _, serial: _ } => {
                 debug!("Popup {:?} grab", data.surface_id);
             }
-            xdg_popup::Request::Reposition {
-                positioner: _,
-                token: _,
-            } => {
-                debug!("Popup {:?} reposition", data.surface_id);
+            xdg_popup::Request::Reposition { positioner, token } => {
+                debug!("Popup {:?} reposition (token {})", data.surface_id, token);
+                let Some(positioner) = positioner.data::<PositionerData>().map(|p| p.get()) else {
+                    return;
+                };
+                let bounds =
+                    popup_parent(state, data).and_then(|parent| popup_bounds(state, parent));
+                let geometry = match XdgShellHandler.place_popup(
+                    &mut state.compositor,
+                    data.surface_id,
+                    &positioner,
+                    bounds,
+                ) {
+                    Ok(geometry) => geometry,
+                    Err(e) => {
+                        if let Some(xdg_surface) = data.xdg_surface.data::<XdgSurfaceData>() {
+                            xdg_surface
+                                .wm_base
+                                .post_error(xdg_wm_base::Error::InvalidPositioner, e.to_string());
+                        }
+                        return;
+                    }
+                };
+
+                resource.repositioned(token);
+                resource.configure(geometry.x, geometry.y, geometry.width, geometry.height);
+                configure_xdg_surface(state, &data.xdg_surface, data.surface_id);
+
+                // The popup is drawn into its toplevel's window, which moves it
+                let root = state.compositor.surfaces.root(data.surface_id);
+                if let Some(window_id) = state.compositor.windows.window_for_surface(root) {
+                    state.schedule_present(window_id);
+                }
             }
             xdg_popup::Request::Destroy => {
                 debug!("Popup {:?} destroy", data.surface_id);
+                // The window is drawn again without the popup
+                let root = state.compositor.surfaces.root(data.surface_id);
+                if let Some(parent) = popup_parent(state, data)
+                    .and_then(|parent| state.compositor.surfaces.get_mut(parent))
+                {
+                    parent.children.retain(|&child| child != data.surface_id);
+                }
+                if let Some(window_id) = state.compositor.windows.window_for_surface(root) {
+                    state.schedule_present(window_id);
+                }
             }
             _ => {}
         }
@@ -0,0 +1,130 @@
+//! zwp_idle_inhibit_manager_v1 objects
+//!
+//! Inhibitors are tracked by the [`IdleInhibitHandler`]. After every
+//! dispatch the compositor checks whether any of them is active, on a
+//! visible surface of an app that isn't ignored, and holds a display sleep
+//! assertion for as long as one is.
+//!
+//! [`IdleInhibitHandler`]: crate::protocol::IdleInhibitHandler
+
+use log::debug;
+use wayland_protocols::wp::idle_inhibit::zv1::server::{
+    zwp_idle_inhibit_manager_v1, zwp_idle_inhibitor_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::protocol::idle_inhibit::IdleInhibitorId;
+use crate::util::power;
+
+/// Name of the assertion, as shown by `pmset -g assertions`
+const ASSERTION_NAME: &str = "Wayland client inhibiting idle";
+
+impl GlobalDispatch<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_idle_inhibit_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_idle_inhibit_manager_v1::ZwpIdleInhibitManagerV1,
+        request: zwp_idle_inhibit_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_idle_inhibit_manager_v1::Request::CreateInhibitor { id, surface } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            let inhibitor_id = state.idle_inhibit.create_inhibitor(surface_id);
+            data_init.init(id, inhibitor_id);
+        }
+    }
+}
+
+impl Dispatch<zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1, IdleInhibitorId> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+        _request: zwp_idle_inhibitor_v1::Request,
+        _data: &IdleInhibitorId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &zwp_idle_inhibitor_v1::ZwpIdleInhibitorV1,
+        data: &IdleInhibitorId,
+    ) {
+        state.idle_inhibit.destroy_inhibitor(*data);
+    }
+}
+
+impl ServerState {
+    /// Hold the display sleep assertion while an inhibitor is active
+    pub(super) fn update_idle_inhibit(&mut self) {
+        let inhibited = self.idle_inhibit.is_inhibited(&self.compositor);
+        power::hold(&mut self.backend.sleep_assertion, inhibited, ASSERTION_NAME);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::surface::BufferInfo;
+
+    #[test]
+    fn test_assertion_follows_visibility() {
+        let mut state = ServerState::new();
+        let surface_id = state.compositor.surfaces.create_surface();
+        let id = state.idle_inhibit.create_inhibitor(surface_id);
+        state.update_idle_inhibit();
+        assert!(state.backend.sleep_assertion.is_none());
+
+        state
+            .compositor
+            .surfaces
+            .get_mut(surface_id)
+            .unwrap()
+            .buffer = Some(BufferInfo {
+            width: 1,
+            height: 1,
+            stride: 4,
+            format: 0,
+            offset: 0,
+            shm_buffer_id: None,
+            solid: None,
+            snapshot: None,
+        });
+        state.update_idle_inhibit();
+        assert!(state.backend.sleep_assertion.is_some());
+
+        state.idle_inhibit.destroy_inhibitor(id);
+        state.update_idle_inhibit();
+        assert!(state.backend.sleep_assertion.is_none());
+    }
+}
@@ -0,0 +1,126 @@
+//! ext_idle_notifier_v1 objects
+//!
+//! Notifications are tracked by the [`IdleTracker`], which is told about
+//! every input the seat routes. After every dispatch the tracker is
+//! updated with the system's idle time and whether an idle inhibitor is
+//! active, and the notifications whose state changed are sent.
+//!
+//! [`IdleTracker`]: crate::input::IdleTracker
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use wayland_protocols::ext::idle_notify::v1::server::{
+    ext_idle_notification_v1, ext_idle_notifier_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::input::idle::{self, IdleEvent, IdleNotificationId};
+
+/// Notification objects by ID
+pub type IdleNotifications =
+    HashMap<IdleNotificationId, ext_idle_notification_v1::ExtIdleNotificationV1>;
+
+impl GlobalDispatch<ext_idle_notifier_v1::ExtIdleNotifierV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<ext_idle_notifier_v1::ExtIdleNotifierV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound ext_idle_notifier_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<ext_idle_notifier_v1::ExtIdleNotifierV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &ext_idle_notifier_v1::ExtIdleNotifierV1,
+        request: ext_idle_notifier_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (id, timeout, respects_inhibitors) = match request {
+            ext_idle_notifier_v1::Request::GetIdleNotification { id, timeout, .. } => {
+                (id, timeout, true)
+            }
+            ext_idle_notifier_v1::Request::GetInputIdleNotification { id, timeout, .. } => {
+                (id, timeout, false)
+            }
+            _ => return,
+        };
+        let timeout = Duration::from_millis(timeout as u64);
+        let notification_id = state.idle.add(timeout, respects_inhibitors);
+        let notification = data_init.init(id, notification_id);
+        state
+            .resources
+            .idle_notifications
+            .insert(notification_id, notification);
+    }
+}
+
+impl Dispatch<ext_idle_notification_v1::ExtIdleNotificationV1, IdleNotificationId> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        _request: ext_idle_notification_v1::Request,
+        _data: &IdleNotificationId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &ext_idle_notification_v1::ExtIdleNotificationV1,
+        data: &IdleNotificationId,
+    ) {
+        state.idle.remove(*data);
+        state.resources.idle_notifications.remove(data);
+    }
+}
+
+impl ServerState {
+    /// Record user input, resuming idle notifications
+    pub(super) fn input_activity(&mut self) {
+        for id in self.idle.input(Instant::now()) {
+            if let Some(notification) = self.resources.idle_notifications.get(&id) {
+                notification.resumed();
+            }
+        }
+    }
+
+    /// Send the notifications whose state changed since the last update
+    pub(super) fn update_idle_notifications(&mut self) {
+        if self.idle.is_empty() {
+            return;
+        }
+        let inhibited = self.idle_inhibit.is_inhibited(&self.compositor);
+        let events = self
+            .idle
+            .update(Instant::now(), idle::system_idle_time(), inhibited);
+        for (id, event) in events {
+            let Some(notification) = self.resources.idle_notifications.get(&id) else {
+                continue;
+            };
+            match event {
+                IdleEvent::Idled => notification.idled(),
+                IdleEvent::Resumed => notification.resumed(),
+            }
+        }
+    }
+}
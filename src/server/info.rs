@@ -0,0 +1,103 @@
+//! wayoa_info_v1 objects
+//!
+//! Tells a client it's running under wayoa, which version, and what the
+//! host platform offers. Capabilities are hints for toolkits with fast
+//! paths for macOS; clients can't rely on them for correctness.
+
+use log::debug;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::extensions::info::server::wayoa_info_v1;
+
+/// Get the name and version of the host operating system
+fn platform() -> (&'static str, String) {
+    #[cfg(target_os = "macos")]
+    {
+        let version = objc2_foundation::NSProcessInfo::processInfo().operatingSystemVersion();
+        let version = format!(
+            "{}.{}.{}",
+            version.majorVersion, version.minorVersion, version.patchVersion
+        );
+        (std::env::consts::OS, version)
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        (std::env::consts::OS, String::new())
+    }
+}
+
+impl ServerState {
+    /// Capabilities of the platform advertised to clients
+    ///
+    /// Only what the compositor actually uses is advertised: windows are
+    /// shown from shm buffers, not composited with Metal or IOSurfaces.
+    fn platform_capabilities(&self) -> Vec<&'static str> {
+        let mut capabilities = Vec::new();
+        if self
+            .compositor
+            .outputs
+            .iter()
+            .any(|(_, output)| output.fractional_scale > 1.0)
+        {
+            capabilities.push("retina");
+        }
+        capabilities
+    }
+}
+
+impl GlobalDispatch<wayoa_info_v1::WayoaInfoV1, GlobalData> for ServerState {
+    fn bind(
+        state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wayoa_info_v1::WayoaInfoV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wayoa_info_v1");
+        let info = data_init.init(resource, ());
+        info.compositor("wayoa".to_string(), env!("CARGO_PKG_VERSION").to_string());
+        let (name, version) = platform();
+        info.platform(name.to_string(), version);
+        for capability in state.platform_capabilities() {
+            info.capability(capability.to_string());
+        }
+        info.done();
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wayoa_info_v1::WayoaInfoV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wayoa_info_v1::WayoaInfoV1,
+        _request: wayoa_info_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retina_capability() {
+        let mut state = ServerState::new();
+        assert!(!state.platform_capabilities().contains(&"retina"));
+
+        state
+            .compositor
+            .outputs
+            .create_headless("virtual-0".to_string(), 2560, 1440, 2, 60_000);
+        assert!(state.platform_capabilities().contains(&"retina"));
+    }
+}
@@ -0,0 +1,353 @@
+//! zwp_input_method_manager_v2 objects
+//!
+//! The first input method created for the seat is used; later ones are
+//! told they are unavailable. It follows the text input from
+//! [`crate::protocol::text_input`] that has focus and is enabled, and the
+//! text it commits goes to that text input. While it is active its
+//! keyboard grab gets the keys the focused client would otherwise get.
+//! Popup surfaces take the input popup role and are told where the text
+//! cursor is, but are not shown yet.
+
+use log::debug;
+use wayland_protocols::wp::text_input::zv3::server::zwp_text_input_v3;
+use wayland_protocols_misc::zwp_input_method_v2::server::{
+    zwp_input_method_keyboard_grab_v2, zwp_input_method_manager_v2, zwp_input_method_v2,
+    zwp_input_popup_surface_v2,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::protocol::wl_keyboard;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::keymap::SharedKeymap;
+use super::ServerState;
+use crate::compositor::{SurfaceId, SurfaceRole};
+use crate::protocol::input_method::{InputMethod, InputMethodEvent};
+use crate::protocol::text_input::{ChangeCause, Preedit};
+
+/// Protocol objects of the seat's input method
+#[derive(Debug, Default)]
+pub struct InputMethodResources {
+    /// The input method in use
+    pub method: Option<zwp_input_method_v2::ZwpInputMethodV2>,
+    /// Its keyboard grab
+    pub grab: Option<zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2>,
+    /// Its popup surfaces
+    pub popups: Vec<zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2>,
+}
+
+impl InputMethodResources {
+    /// Check if an object is the input method in use
+    fn is_current(&self, resource: &zwp_input_method_v2::ZwpInputMethodV2) -> bool {
+        self.method.as_ref() == Some(resource)
+    }
+}
+
+impl GlobalDispatch<zwp_input_method_manager_v2::ZwpInputMethodManagerV2, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_input_method_manager_v2::ZwpInputMethodManagerV2>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_input_method_manager_v2");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_input_method_manager_v2::ZwpInputMethodManagerV2, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_input_method_manager_v2::ZwpInputMethodManagerV2,
+        request: zwp_input_method_manager_v2::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_input_method_manager_v2::Request::GetInputMethod {
+            seat: _,
+            input_method,
+        } = request
+        {
+            let method = data_init.init(input_method, ());
+            if state.resources.input_method.method.is_some() {
+                debug!("Seat already has an input method");
+                method.unavailable();
+                return;
+            }
+            debug!("Creating input method");
+            state.input_method = InputMethod::new();
+            state.resources.input_method.method = Some(method);
+            state.update_input_method();
+        }
+    }
+}
+
+impl Dispatch<zwp_input_method_v2::ZwpInputMethodV2, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwp_input_method_v2::ZwpInputMethodV2,
+        request: zwp_input_method_v2::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Child objects of an unavailable input method stay inert
+        let current = state.resources.input_method.is_current(resource);
+        let pending = &mut state.input_method.pending;
+        match request {
+            zwp_input_method_v2::Request::CommitString { text } if current => {
+                pending.commit = Some(text);
+            }
+            zwp_input_method_v2::Request::SetPreeditString {
+                text,
+                cursor_begin,
+                cursor_end,
+            } if current => {
+                pending.preedit = Some(Preedit {
+                    text,
+                    cursor_begin,
+                    cursor_end,
+                });
+            }
+            zwp_input_method_v2::Request::DeleteSurroundingText {
+                before_length,
+                after_length,
+            } if current => {
+                pending.delete = Some((before_length, after_length));
+            }
+            zwp_input_method_v2::Request::Commit { serial } if current => {
+                if serial != state.input_method.done_count {
+                    debug!(
+                        "Input method commit for done {} of {}",
+                        serial, state.input_method.done_count
+                    );
+                }
+                if let Some(text) = state.input_method.commit() {
+                    state.text_input_method_text(text.preedit, text.commit, text.delete);
+                }
+            }
+            zwp_input_method_v2::Request::GetInputPopupSurface { id, surface } => {
+                let surface_id = surface.data::<SurfaceId>().copied();
+                let popup = data_init.init(id, ());
+                if let Some(surface) =
+                    surface_id.and_then(|id| state.compositor.surfaces.get_mut(id))
+                {
+                    let _ = surface.set_role(SurfaceRole::InputPopup);
+                }
+                if current {
+                    debug!("Creating input popup surface");
+                    state.resources.input_method.popups.push(popup);
+                    state.send_text_input_rectangle();
+                }
+            }
+            zwp_input_method_v2::Request::GrabKeyboard { keyboard } => {
+                let grab = data_init.init(keyboard, ());
+                if current && state.resources.input_method.grab.is_none() {
+                    debug!("Input method grabbed the keyboard");
+                    state.start_keyboard_grab(&grab);
+                    state.resources.input_method.grab = Some(grab);
+                }
+            }
+            zwp_input_method_v2::Request::Destroy => {
+                debug!("Input method destroy");
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_input_method_v2::ZwpInputMethodV2,
+        _data: &(),
+    ) {
+        if !state.resources.input_method.is_current(resource) {
+            return;
+        }
+        // Drop text still being composed
+        if state.input_method.is_active() {
+            state.text_input_method_text(None, None, None);
+        }
+        state.input_method = InputMethod::new();
+        state.resources.input_method = InputMethodResources::default();
+    }
+}
+
+impl Dispatch<zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2,
+        _request: zwp_input_method_keyboard_grab_v2::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Only release, handled by destroyed
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2,
+        _data: &(),
+    ) {
+        let resources = &mut state.resources.input_method;
+        if resources.grab.as_ref() == Some(resource) {
+            debug!("Input method released the keyboard");
+            resources.grab = None;
+        }
+    }
+}
+
+impl Dispatch<zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+        _request: zwp_input_popup_surface_v2::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Only destroy, handled by destroyed
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_input_popup_surface_v2::ZwpInputPopupSurfaceV2,
+        _data: &(),
+    ) {
+        state
+            .resources
+            .input_method
+            .popups
+            .retain(|popup| popup != resource);
+    }
+}
+
+impl ServerState {
+    /// Tell the input method about the focused and enabled text input
+    ///
+    /// Called whenever text input focus or state changes.
+    pub fn update_input_method(&mut self) {
+        let Some(method) = self.resources.input_method.method.as_ref() else {
+            return;
+        };
+        let active = self
+            .text_input
+            .active()
+            .map(|(id, input)| (id, &input.current));
+        for event in self.input_method.update(active) {
+            send_input_method_event(method, event);
+        }
+        self.send_text_input_rectangle();
+    }
+
+    /// Send a key to the input method's keyboard grab
+    ///
+    /// Returns true if the input method is active and has a grab, in which
+    /// case the key must not reach the focused client.
+    pub(super) fn input_method_key(&mut self, time: u32, key: u32, pressed: bool) -> bool {
+        let Some(grab) = self
+            .resources
+            .input_method
+            .grab
+            .as_ref()
+            .filter(|_| self.input_method.is_active())
+        else {
+            return false;
+        };
+        let state = match pressed {
+            true => wl_keyboard::KeyState::Pressed,
+            false => wl_keyboard::KeyState::Released,
+        };
+        grab.key(self.compositor.next_serial(), time, key, state);
+        true
+    }
+
+    /// Send the keymap, repeat rate and modifiers to a new keyboard grab
+    fn start_keyboard_grab(
+        &mut self,
+        grab: &zwp_input_method_keyboard_grab_v2::ZwpInputMethodKeyboardGrabV2,
+    ) {
+        if let Some((fd, size)) = self.shared_keymap().map(SharedKeymap::file) {
+            grab.keymap(wl_keyboard::KeymapFormat::XkbV1, fd, size);
+        }
+        let keyboard = self.compositor.seat.keyboard();
+        let (rate, delay) = keyboard.repeat_info();
+        grab.repeat_info(rate as i32, delay as i32);
+        let modifiers = keyboard.modifiers();
+        grab.modifiers(
+            self.compositor.next_serial(),
+            modifiers.depressed,
+            modifiers.latched,
+            modifiers.locked,
+            modifiers.group,
+        );
+    }
+
+    /// Tell input popups where the text cursor is
+    fn send_text_input_rectangle(&self) {
+        let Some(rect) = self
+            .text_input
+            .active()
+            .and_then(|(_, input)| input.current.cursor_rectangle)
+        else {
+            return;
+        };
+        for popup in &self.resources.input_method.popups {
+            popup.text_input_rectangle(rect.x, rect.y, rect.width, rect.height);
+        }
+    }
+}
+
+/// Send an event to the input method
+fn send_input_method_event(
+    method: &zwp_input_method_v2::ZwpInputMethodV2,
+    event: InputMethodEvent,
+) {
+    match event {
+        InputMethodEvent::Activate => method.activate(),
+        InputMethodEvent::Deactivate => method.deactivate(),
+        InputMethodEvent::SurroundingText(surrounding) => method.surrounding_text(
+            surrounding.text,
+            surrounding.cursor as u32,
+            surrounding.anchor as u32,
+        ),
+        InputMethodEvent::TextChangeCause(cause) => method.text_change_cause(match cause {
+            ChangeCause::InputMethod => zwp_text_input_v3::ChangeCause::InputMethod,
+            ChangeCause::Other => zwp_text_input_v3::ChangeCause::Other,
+        }),
+        InputMethodEvent::ContentType { hint, purpose } => method.content_type(
+            zwp_text_input_v3::ContentHint::from_bits_truncate(hint.bits()),
+            zwp_text_input_v3::ContentPurpose::try_from(purpose as u32)
+                .unwrap_or(zwp_text_input_v3::ContentPurpose::Normal),
+        ),
+        InputMethodEvent::Done => method.done(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_without_input_method() {
+        // Without an input method, keys go to the focused client
+        let mut state = ServerState::new();
+        assert!(!state.input_method_key(0, 30, true));
+    }
+}
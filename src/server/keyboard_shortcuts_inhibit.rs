@@ -0,0 +1,197 @@
+//! zwp_keyboard_shortcuts_inhibit_manager_v1 objects
+//!
+//! Inhibiting surfaces are tracked by the seat. While one of them has
+//! keyboard focus, key presses skip the compositor's keybindings and, on
+//! macOS, the menu's key equivalents, going straight to the client. The
+//! inhibitor of the focused surface is told it is active after every
+//! dispatch. Shortcuts handled by macOS itself, like Cmd+Tab, can't be
+//! suspended.
+
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocols::wp::keyboard_shortcuts_inhibit::zv1::server::{
+    zwp_keyboard_shortcuts_inhibit_manager_v1, zwp_keyboard_shortcuts_inhibitor_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+
+/// Inhibitor objects and the one last told it is active
+#[derive(Debug, Default)]
+pub struct ShortcutInhibitors {
+    /// Inhibitors by surface
+    inhibitors:
+        HashMap<SurfaceId, zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1>,
+    /// Surface whose inhibitor is active
+    active: Option<SurfaceId>,
+}
+
+impl
+    GlobalDispatch<
+        zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+        GlobalData,
+    > for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<
+            zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+        >,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_keyboard_shortcuts_inhibit_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1, ()>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwp_keyboard_shortcuts_inhibit_manager_v1::ZwpKeyboardShortcutsInhibitManagerV1,
+        request: zwp_keyboard_shortcuts_inhibit_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_keyboard_shortcuts_inhibit_manager_v1::Request::InhibitShortcuts {
+            id,
+            surface,
+            seat: _,
+        } = request
+        {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if !state.compositor.seat.inhibit_shortcuts(surface_id) {
+                resource.post_error(
+                    zwp_keyboard_shortcuts_inhibit_manager_v1::Error::AlreadyInhibited,
+                    "The surface already inhibits shortcuts",
+                );
+                return;
+            }
+            debug!("Surface {:?} inhibits shortcuts", surface_id);
+            let inhibitor = data_init.init(id, surface_id);
+            state
+                .resources
+                .shortcut_inhibitors
+                .inhibitors
+                .insert(surface_id, inhibitor);
+        }
+    }
+}
+
+impl Dispatch<zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1, SurfaceId>
+    for ServerState
+{
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1,
+        _request: zwp_keyboard_shortcuts_inhibitor_v1::Request,
+        _data: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &zwp_keyboard_shortcuts_inhibitor_v1::ZwpKeyboardShortcutsInhibitorV1,
+        data: &SurfaceId,
+    ) {
+        state.compositor.seat.restore_shortcuts(*data);
+        state.resources.shortcut_inhibitors.inhibitors.remove(data);
+        if state.resources.shortcut_inhibitors.active == Some(*data) {
+            state.resources.shortcut_inhibitors.active = None;
+        }
+    }
+}
+
+impl ServerState {
+    /// Offer a key press to the compositor's keybindings
+    ///
+    /// An open launcher takes every key. Otherwise the launcher,
+    /// focus-previous, preview and zoom bindings and those of the active
+    /// profile are tried in turn, unless the focused surface inhibits
+    /// shortcuts. Returns true if a binding took the key, in which
+    /// case it must not reach clients.
+    pub fn binding_key(&mut self, key: u32, modifiers: u32) -> bool {
+        if self.launcher.is_open() {
+            return self.launcher_key(key, modifiers);
+        }
+        if self.compositor.seat.shortcuts_inhibited() {
+            return false;
+        }
+        self.launcher_key(key, modifiers)
+            || self.focus_previous_key(key, modifiers)
+            || self.preview_key(key, modifiers)
+            || self.zoom_key(key, modifiers)
+            || self.profile_key(key, modifiers)
+    }
+
+    /// Tell inhibitors whether they are active as keyboard focus moves
+    pub(super) fn update_shortcut_inhibitors(&mut self) {
+        let seat = &self.compositor.seat;
+        let active = seat
+            .keyboard_focus()
+            .filter(|&focus| seat.has_shortcut_inhibitor(focus));
+        if active == self.resources.shortcut_inhibitors.active {
+            return;
+        }
+        let inhibitors = &self.resources.shortcut_inhibitors.inhibitors;
+        if let Some(inhibitor) = self
+            .resources
+            .shortcut_inhibitors
+            .active
+            .and_then(|s| inhibitors.get(&s))
+        {
+            inhibitor.inactive();
+        }
+        if let Some(inhibitor) = active.and_then(|s| inhibitors.get(&s)) {
+            inhibitor.active();
+        }
+        self.resources.shortcut_inhibitors.active = active;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::keybinding::modifiers;
+
+    #[test]
+    fn test_inhibited_bindings() {
+        let mut state = ServerState::new();
+        let surface = SurfaceId(1);
+        state.compositor.seat.inhibit_shortcuts(surface);
+        let open =
+            |state: &mut ServerState| state.binding_key(57, modifiers::CTRL | modifiers::ALT);
+
+        state.compositor.seat.focus_surface(Some(surface), 0.0, 0.0);
+        assert!(!open(&mut state));
+        assert!(!state.launcher.is_open());
+
+        state.compositor.seat.focus_surface(None, 0.0, 0.0);
+        assert!(open(&mut state));
+        assert!(state.launcher.is_open());
+
+        // The open launcher still takes keys after an inhibitor gets focus
+        state.compositor.seat.focus_surface(Some(surface), 0.0, 0.0);
+        assert!(state.binding_key(1, 0));
+        assert!(!state.launcher.is_open());
+    }
+}
@@ -0,0 +1,224 @@
+//! Keymap delivery
+//!
+//! The seat's keymap is written once per version to anonymous shared
+//! memory that clients can only read: a sealed memfd on Linux, and on
+//! macOS a POSIX shared memory object that is unlinked right away and
+//! reopened read-only. Every wl_keyboard and keyboard grab is sent the
+//! same file, each getting its own duplicate of the descriptor as the
+//! event is queued, so the keymap isn't copied again for every client.
+
+use std::io;
+use std::os::fd::{AsFd, OwnedFd};
+
+use log::{debug, warn};
+use wayland_server::protocol::wl_keyboard;
+
+use super::ServerState;
+
+/// A keymap in shared memory, NUL-terminated
+#[derive(Debug)]
+pub struct SharedKeymap {
+    /// Keymap version the file was written for
+    version: u64,
+    fd: OwnedFd,
+    /// Size including the NUL terminator
+    size: u32,
+}
+
+impl SharedKeymap {
+    /// Write a keymap to a new read-only shared memory file
+    pub fn new(keymap: &str, version: u64) -> io::Result<Self> {
+        let mut contents = Vec::with_capacity(keymap.len() + 1);
+        contents.extend_from_slice(keymap.as_bytes());
+        contents.push(0);
+        let size = u32::try_from(contents.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "keymap too large"))?;
+        Ok(Self {
+            version,
+            fd: sealed_file(&contents)?,
+            size,
+        })
+    }
+
+    /// Send the keymap to a keyboard
+    pub fn send(&self, keyboard: &wl_keyboard::WlKeyboard) {
+        keyboard.keymap(wl_keyboard::KeymapFormat::XkbV1, self.fd.as_fd(), self.size);
+    }
+
+    /// The descriptor and size, for keymap events of other interfaces
+    pub fn file(&self) -> (std::os::fd::BorrowedFd<'_>, u32) {
+        (self.fd.as_fd(), self.size)
+    }
+}
+
+/// Create a memfd holding `contents` that can't be written or resized
+#[cfg(target_os = "linux")]
+fn sealed_file(contents: &[u8]) -> io::Result<OwnedFd> {
+    use std::io::Write;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    // SAFETY: memfd_create returns a new descriptor we take ownership of
+    let fd = unsafe {
+        libc::memfd_create(
+            c"wayoa-keymap".as_ptr(),
+            libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut file = std::fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+    file.write_all(contents)?;
+    let seals = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+    // SAFETY: plain fcntl on a descriptor we own
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file.into())
+}
+
+/// Create a shared memory object holding `contents`, open read-only
+///
+/// macOS has no memfd or seals, but a descriptor opened read-only can't
+/// be used to write or resize the object.
+#[cfg(not(target_os = "linux"))]
+fn sealed_file(contents: &[u8]) -> io::Result<OwnedFd> {
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    // Names are limited to 31 bytes on macOS
+    let name = CString::new(format!(
+        "/wayoa-km-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // SAFETY: shm_open returns a new descriptor we take ownership of, and
+    // the object is unlinked before returning on every path
+    let fd = unsafe {
+        libc::shm_open(
+            name.as_ptr(),
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+            0o400 as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let writable = unsafe { OwnedFd::from_raw_fd(fd) };
+    let readable = unsafe { libc::shm_open(name.as_ptr(), libc::O_RDONLY) };
+    unsafe { libc::shm_unlink(name.as_ptr()) };
+    if readable < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let readable = unsafe { OwnedFd::from_raw_fd(readable) };
+
+    // Shared memory objects can't be written to, only mapped
+    let len = contents.len();
+    if unsafe { libc::ftruncate(writable.as_raw_fd(), len as libc::off_t) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the mapping is `len` bytes long and unmapped after the copy
+    let map = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            writable.as_raw_fd(),
+            0,
+        )
+    };
+    if map == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(contents.as_ptr(), map.cast::<u8>(), len);
+        libc::munmap(map, len);
+    }
+    Ok(readable)
+}
+
+impl ServerState {
+    /// The shared file of the seat's current keymap, written on first use
+    /// after the keymap changes
+    pub fn shared_keymap(&mut self) -> Option<&SharedKeymap> {
+        let keyboard = self.compositor.seat.keyboard();
+        let keymap = keyboard.keymap()?;
+        let version = keyboard.keymap_version();
+        let cached = &mut self.resources.keymap;
+        if cached.as_ref().map(|shared| shared.version) != Some(version) {
+            match SharedKeymap::new(keymap, version) {
+                Ok(shared) => {
+                    debug!("Keymap version {} shared, {} bytes", version, shared.size);
+                    *cached = Some(shared);
+                }
+                Err(e) => {
+                    warn!("Failed to share the keymap: {}", e);
+                    return None;
+                }
+            }
+        }
+        cached.as_ref()
+    }
+
+    /// Send the current keymap to a keyboard, if the seat has one
+    pub fn send_keymap(&mut self, keyboard: &wl_keyboard::WlKeyboard) {
+        if let Some(shared) = self.shared_keymap() {
+            shared.send(keyboard);
+        }
+    }
+
+    /// Change the seat's keymap and send it to every keyboard
+    pub fn set_keymap(&mut self, keymap: String) {
+        self.compositor.seat.keyboard_mut().set_keymap(keymap);
+        let keyboards = self.resources.seats.keyboards().to_vec();
+        for keyboard in &keyboards {
+            self.send_keymap(keyboard);
+        }
+        if let Some(grab) = self.resources.input_method.grab.clone() {
+            if let Some((fd, size)) = self.shared_keymap().map(SharedKeymap::file) {
+                grab.keymap(wl_keyboard::KeymapFormat::XkbV1, fd, size);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::FileExt;
+
+    #[test]
+    fn test_shared_keymap() {
+        let shared = SharedKeymap::new("xkb_keymap {};", 1).unwrap();
+        assert_eq!(shared.size, 15);
+        let mut file = std::fs::File::from(shared.fd.try_clone().unwrap());
+        let mut contents = vec![0; 15];
+        file.read_exact_at(&mut contents, 0).unwrap();
+        assert_eq!(contents, b"xkb_keymap {};\0");
+
+        // Clients can't change it
+        assert!(std::io::Write::write_all(&mut file, b"x").is_err());
+    }
+
+    #[test]
+    fn test_keymap_reused_until_changed() {
+        let mut state = ServerState::new();
+        assert!(state.shared_keymap().is_none());
+
+        state.set_keymap("xkb_keymap {};".to_string());
+        let first = state.shared_keymap().unwrap().version;
+        let fd = state.shared_keymap().unwrap().fd.as_raw_fd();
+        assert_eq!(state.shared_keymap().unwrap().fd.as_raw_fd(), fd);
+
+        state.set_keymap("xkb_keymap { };".to_string());
+        let shared = state.shared_keymap().unwrap();
+        assert_ne!(shared.version, first);
+        assert_eq!(shared.size, 16);
+    }
+}
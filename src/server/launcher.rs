@@ -0,0 +1,131 @@
+//! Launcher overlay input
+//!
+//! Key presses go through the launcher before any client sees them: the
+//! configured keybinding opens it, and while it is open it consumes every
+//! key. Another keybinding switches straight back to the window focused
+//! before the current one, without the overlay.
+
+use std::time::Instant;
+
+use log::{debug, warn};
+
+use super::ServerState;
+use crate::compositor::{CompositorAction, LauncherOutcome, LauncherTarget, WindowId};
+
+impl ServerState {
+    /// Show the launcher with the current windows and configured apps
+    pub fn open_launcher(&mut self) {
+        self.launcher
+            .open(&self.compositor.windows, &self.config.launcher.apps);
+    }
+
+    /// Activate the most recently focused window other than the focused
+    /// one, returning it
+    pub fn focus_previous_window(&mut self) -> Option<WindowId> {
+        let previous = self.compositor.windows.previous()?;
+        self.activate_window(previous).then_some(previous)
+    }
+
+    /// Focus the previous window if a key press is its binding
+    ///
+    /// Returns true if the key was taken, in which case it must not reach
+    /// clients.
+    pub fn focus_previous_key(&mut self, key: u32, modifiers: u32) -> bool {
+        let bound = self
+            .focus_previous_binding
+            .is_some_and(|binding| binding.matches(key, modifiers));
+        if bound {
+            self.perform_action(CompositorAction::FocusPrevious);
+        }
+        bound
+    }
+
+    /// Offer a key press to the launcher
+    ///
+    /// `modifiers` is the XKB modifier mask. Returns true if the launcher
+    /// took the key, in which case it must not reach clients.
+    pub fn launcher_key(&mut self, key: u32, modifiers: u32) -> bool {
+        if !self.launcher.is_open() {
+            let bound = self
+                .launcher_binding
+                .is_some_and(|binding| binding.matches(key, modifiers));
+            if bound {
+                self.open_launcher();
+            }
+            return bound;
+        }
+
+        match self.launcher.handle_key(key) {
+            LauncherOutcome::Pending | LauncherOutcome::Dismissed => {}
+            LauncherOutcome::Chosen(LauncherTarget::Window(window_id)) => {
+                self.activate_window(window_id);
+            }
+            LauncherOutcome::Chosen(LauncherTarget::Launch(command)) => {
+                // The launched app may take focus with the token
+                let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+                let token = self.activation.issue(true, Instant::now());
+                let env = [
+                    ("XDG_ACTIVATION_TOKEN", token.as_str()),
+                    ("DESKTOP_STARTUP_ID", token.as_str()),
+                ];
+                match self.session.spawner.spawn_with_env(&command, &env) {
+                    Ok(pid) => debug!("Launched {:?} (pid {})", command, pid),
+                    Err(e) => warn!("Failed to launch {:?}: {}", command, e),
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::SurfaceId;
+    use crate::input::keybinding::{modifiers, KeyBinding};
+
+    #[test]
+    fn test_launcher_keys() {
+        let mut state = ServerState::new();
+        let first = state.compositor.windows.create_window(SurfaceId(1));
+        let second = state.compositor.windows.create_window(SurfaceId(2));
+        state.compositor.windows.get_mut(second).unwrap().title = Some("Mail".to_string());
+        state.compositor.windows.set_focused(Some(first));
+
+        // Unbound keys pass through
+        assert!(!state.launcher_key(50, 0));
+        assert!(!state.launcher.is_open());
+
+        // ctrl+alt+space opens it, then "m" and Return pick the window
+        assert!(state.launcher_key(57, modifiers::CTRL | modifiers::ALT));
+        assert!(state.launcher.is_open());
+        assert!(state.launcher_key(50, 0));
+        assert!(state.launcher_key(28, 0));
+        assert!(!state.launcher.is_open());
+        assert_eq!(
+            state.compositor.windows.focused().map(|w| w.id),
+            Some(second)
+        );
+    }
+
+    #[test]
+    fn test_focus_previous() {
+        let mut state = ServerState::new();
+        state.focus_previous_binding = KeyBinding::parse("alt+tab").ok();
+        let first = state.compositor.windows.create_window(SurfaceId(1));
+        let second = state.compositor.windows.create_window(SurfaceId(2));
+        state.activate_window(first);
+        assert_eq!(state.focus_previous_window(), Some(second));
+
+        // Pressed again it goes back
+        assert!(state.focus_previous_key(15, modifiers::ALT));
+        assert_eq!(
+            state.compositor.windows.focused().map(|w| w.id),
+            Some(first)
+        );
+
+        // Closed windows leave the history
+        state.compositor.windows.remove(second);
+        assert_eq!(state.focus_previous_window(), None);
+    }
+}
@@ -0,0 +1,397 @@
+//! zwlr_layer_shell_v1 objects
+//!
+//! Layer surfaces are modeled by the [`LayerShellHandler`]. A mapped layer
+//! surface gets a compositor window outside the toplevel list, shown in a
+//! borderless NSWindow at its layer's window level. Surfaces are laid out
+//! against the whole output, and exclusive zones are taken out of the
+//! output's work area, which is where maximized windows and popups go.
+//!
+//! [`LayerShellHandler`]: crate::protocol::LayerShellHandler
+
+use log::debug;
+use wayland_protocols::xdg::shell::server::xdg_popup;
+use wayland_protocols_wlr::layer_shell::v1::server::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::globals::PopupData;
+use super::{OutputData, ServerState};
+use crate::compositor::{OutputId, SurfaceId, SurfaceRole, WindowGeometry};
+use crate::protocol::layer_shell::{Anchor, KeyboardInteractivity, Layer, LayerSurfaceId};
+
+impl GlobalDispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwlr_layer_shell_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_layer_shell_v1::ZwlrLayerShellV1,
+        request: zwlr_layer_shell_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_layer_shell_v1::Error;
+
+        if let zwlr_layer_shell_v1::Request::GetLayerSurface {
+            id,
+            surface,
+            output,
+            layer,
+            namespace,
+        } = request
+        {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            let output = output
+                .as_ref()
+                .and_then(|output| output.data::<OutputData>())
+                .map(|data| data.output_id);
+            let layer = Layer::from_protocol(layer.into());
+
+            // The object is created even when the request fails, so that
+            // the error can be sent
+            let layer_id = state.layer_shell.get_layer_surface(
+                surface_id,
+                output,
+                layer.unwrap_or_default(),
+                namespace,
+            );
+            let layer_surface = data_init.init(id, layer_id);
+            state
+                .resources
+                .layer_surfaces
+                .insert(layer_id, layer_surface);
+
+            let Some(wl_surface) = state.compositor.surfaces.get_mut(surface_id) else {
+                return;
+            };
+            if wl_surface.buffer.is_some() {
+                resource.post_error(
+                    Error::AlreadyConstructed,
+                    "The surface already has a buffer",
+                );
+            } else if wl_surface.set_role(SurfaceRole::LayerSurface).is_err() {
+                resource.post_error(Error::Role, "The surface already has another role");
+            } else if layer.is_none() {
+                resource.post_error(Error::InvalidLayer, "Unknown layer");
+            } else {
+                debug!("Surface {:?} is layer surface {:?}", surface_id, layer_id);
+            }
+        }
+    }
+}
+
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, LayerSurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        request: zwlr_layer_surface_v1::Request,
+        data: &LayerSurfaceId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_layer_surface_v1::{Error, Request};
+
+        let Some(layer) = state.layer_shell.get_mut(*data) else {
+            return;
+        };
+        match request {
+            Request::SetSize { width, height } => layer.set_size(width, height),
+            Request::SetAnchor { anchor } => {
+                layer.set_anchor(Anchor::from_bits_truncate(anchor.into()));
+            }
+            Request::SetExclusiveZone { zone } => layer.set_exclusive_zone(zone),
+            Request::SetMargin {
+                top,
+                right,
+                bottom,
+                left,
+            } => layer.set_margin(top, right, bottom, left),
+            Request::SetKeyboardInteractivity {
+                keyboard_interactivity,
+            } => match KeyboardInteractivity::from_protocol(keyboard_interactivity.into()) {
+                Some(mode) => layer.set_keyboard_interactivity(mode),
+                None => resource.post_error(
+                    Error::InvalidKeyboardInteractivity,
+                    "Unknown keyboard interactivity",
+                ),
+            },
+            Request::GetPopup { popup } => {
+                let parent = layer.surface_id;
+                state.set_layer_popup_parent(&popup, parent);
+            }
+            Request::AckConfigure { serial } => {
+                debug!("Layer surface {:?} ack configure {}", data, serial);
+                if serial == layer.configure_serial {
+                    layer.configured = true;
+                }
+            }
+            Request::SetLayer { layer: value } => match Layer::from_protocol(value.into()) {
+                Some(value) => {
+                    layer.set_layer(value);
+                    #[cfg(target_os = "macos")]
+                    {
+                        let surface_id = layer.surface_id;
+                        if let Some(native) = state
+                            .compositor
+                            .windows
+                            .window_for_surface(surface_id)
+                            .and_then(|window_id| state.backend.native_window(window_id))
+                        {
+                            native.set_layer(value);
+                        }
+                    }
+                }
+                None => {
+                    resource.post_error(zwlr_layer_shell_v1::Error::InvalidLayer, "Unknown layer")
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        data: &LayerSurfaceId,
+    ) {
+        state.resources.layer_surfaces.remove(data);
+        if let Some(surface_id) = state.layer_shell.get(*data).map(|layer| layer.surface_id) {
+            state.unmap_layer_surface(surface_id);
+        }
+        state.layer_shell.destroy(*data);
+        state.update_exclusive_zones();
+    }
+}
+
+impl ServerState {
+    /// Configure, map or unmap a layer surface after its wl_surface commits
+    ///
+    /// The first commit must come without a buffer, and gets the initial
+    /// configure. Committing a null buffer unmaps the surface, after which
+    /// it starts over.
+    pub(super) fn commit_layer_surface(&mut self, surface_id: SurfaceId) {
+        use zwlr_layer_surface_v1::Error;
+
+        let Some(layer) = self.layer_shell.get_by_surface(surface_id) else {
+            return;
+        };
+        let (id, valid_size, configured) = (layer.id, layer.has_valid_size(), layer.configured);
+        let (configure_serial, configured_size) = (layer.configure_serial, layer.configured_size);
+        let Some(resource) = self.resources.layer_surfaces.get(&id).cloned() else {
+            return;
+        };
+        let has_buffer = self
+            .compositor
+            .surfaces
+            .get(surface_id)
+            .is_some_and(|surface| surface.buffer.is_some());
+        if !valid_size {
+            resource.post_error(
+                Error::InvalidSize,
+                "A size of 0 needs anchors to both edges",
+            );
+            return;
+        }
+        if !configured {
+            if has_buffer {
+                resource.post_error(
+                    Error::InvalidSurfaceState,
+                    "A buffer was attached before the first configure",
+                );
+            } else if configure_serial == 0 {
+                self.configure_layer_surface(&resource);
+            }
+            return;
+        }
+        if !has_buffer {
+            self.unmap_layer_surface(surface_id);
+            if let Some(layer) = self.layer_shell.get_mut(id) {
+                layer.configured = false;
+                layer.configure_serial = 0;
+            }
+            self.update_exclusive_zones();
+            return;
+        }
+
+        // Anchors, margins or the output may have changed the size
+        let Some((output, x, y, width, height)) = self.layer_surface_layout(&resource) else {
+            return;
+        };
+        if (width, height) != configured_size {
+            self.configure_layer_surface(&resource);
+        }
+        self.map_layer_surface(&resource, output, x, y);
+        self.update_exclusive_zones();
+    }
+
+    /// Lay a layer surface out on its output
+    ///
+    /// Returns the output and the surface's position and size in output
+    /// coordinates. Surfaces without an output go on the primary output.
+    fn layer_surface_layout(
+        &self,
+        resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+    ) -> Option<(OutputId, i32, i32, u32, u32)> {
+        let layer = self.layer_shell.get(*resource.data::<LayerSurfaceId>()?)?;
+        let outputs = &self.compositor.outputs;
+        let output = match layer.output {
+            Some(id) => outputs.get(id),
+            None => outputs.primary(),
+        }?;
+        let (width, height) = output.logical_size();
+        let (x, y, width, height) =
+            layer.calculate_geometry(width.max(0) as u32, height.max(0) as u32);
+        Some((output.id, x, y, width, height))
+    }
+
+    /// Send a configure with the size the layout gives a layer surface
+    fn configure_layer_surface(&mut self, resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1) {
+        let (width, height) = self
+            .layer_surface_layout(resource)
+            .map_or((0, 0), |(_, _, _, width, height)| (width, height));
+        let serial = self.compositor.next_serial();
+        let Some(layer) = resource
+            .data::<LayerSurfaceId>()
+            .and_then(|&id| self.layer_shell.get_mut(id))
+        else {
+            return;
+        };
+        layer.configure_serial = serial;
+        layer.configured_size = (width, height);
+        resource.configure(serial, width, height);
+    }
+
+    /// Show a layer surface's buffer at its place on its output
+    ///
+    /// The surface is placed by its configured size, but drawn at the size
+    /// of its buffer.
+    fn map_layer_surface(
+        &mut self,
+        resource: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        output: OutputId,
+        x: i32,
+        y: i32,
+    ) {
+        let Some(layer) = resource
+            .data::<LayerSurfaceId>()
+            .and_then(|&id| self.layer_shell.get(id))
+        else {
+            return;
+        };
+        let (surface_id, keyboard) = (layer.surface_id, layer.keyboard_interactivity);
+        #[cfg(target_os = "macos")]
+        let level = layer.layer;
+        let Some(output) = self.compositor.outputs.get(output) else {
+            return;
+        };
+        let (width, height) = self
+            .compositor
+            .surfaces
+            .get(surface_id)
+            .and_then(|surface| surface.size())
+            .unwrap_or_default();
+        let geometry = WindowGeometry {
+            x: output.x + x,
+            y: output.y + y,
+            width: width.max(0) as u32,
+            height: height.max(0) as u32,
+        };
+        #[cfg(target_os = "macos")]
+        let headless = output.headless;
+
+        let mapped = self.compositor.windows.window_for_surface(surface_id);
+        let window_id = mapped.unwrap_or_else(|| {
+            debug!("Mapping layer surface {:?}", surface_id);
+            self.compositor.windows.create_layer_window(surface_id)
+        });
+        if let Some(window) = self.compositor.windows.get_mut(window_id) {
+            window.geometry = geometry;
+        }
+
+        #[cfg(target_os = "macos")]
+        match self.backend.native_window(window_id) {
+            Some(native) => native.set_frame_global(geometry),
+            None if !headless => {
+                if let Some(mtm) = self.backend.mtm {
+                    let native = crate::backend::cocoa::window::WayoaWindow::new_layer(
+                        mtm, window_id, geometry, level,
+                    );
+                    native.show_layer();
+                    self.backend.insert_native_window(window_id, native);
+                }
+            }
+            None => {}
+        }
+
+        if mapped.is_none() && keyboard == KeyboardInteractivity::Exclusive {
+            self.set_keyboard_focus(Some(surface_id));
+        }
+        self.schedule_present(window_id);
+    }
+
+    /// Take a layer surface's window away, if it's mapped
+    fn unmap_layer_surface(&mut self, surface_id: SurfaceId) {
+        let Some(window_id) = self.compositor.windows.window_for_surface(surface_id) else {
+            return;
+        };
+        debug!("Unmapping layer surface {:?}", surface_id);
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.remove_native_window(window_id) {
+            native_window.close();
+        }
+        self.scheduler.remove_window(window_id);
+        self.compositor.windows.remove(window_id);
+
+        if self.compositor.seat.keyboard().focus() == Some(surface_id) {
+            let focused = self.compositor.windows.focused().map(|w| w.surface_id);
+            self.set_keyboard_focus(focused);
+        }
+    }
+
+    /// Make an xdg_popup a child of a layer surface
+    fn set_layer_popup_parent(&mut self, popup: &xdg_popup::XdgPopup, parent: SurfaceId) {
+        let Some(popup) = popup.data::<PopupData>().map(|data| data.surface_id) else {
+            return;
+        };
+        let surfaces = &mut self.compositor.surfaces;
+        if let Some(surface) = surfaces.get_mut(popup) {
+            surface.parent = Some(parent);
+        }
+        if let Some(surface) = surfaces.get_mut(parent) {
+            surface.children.push(popup);
+        }
+    }
+
+    /// Reserve the exclusive zones of layer surfaces on each output
+    fn update_exclusive_zones(&mut self) {
+        let ids: Vec<OutputId> = self.compositor.outputs.iter().map(|(&id, _)| id).collect();
+        for id in ids {
+            let insets = self.layer_shell.exclusive_insets(id);
+            if let Some(output) = self.compositor.outputs.get_mut(id) {
+                output.exclusive = insets;
+            }
+        }
+    }
+}
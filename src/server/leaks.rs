@@ -0,0 +1,328 @@
+//! Surface and region leak detection
+//!
+//! Tracks the surfaces and regions each client creates. Clients can be
+//! capped at a number of surfaces and regions, and a periodic check reports
+//! clients that pile up thousands of surfaces they never commit or regions
+//! they never destroy. Role-less surfaces left behind by disconnected
+//! clients can optionally be collected by the same check.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::compositor::state::ClientId;
+use crate::compositor::SurfaceId;
+use crate::config::ResourceLimitsConfig;
+
+/// Why a surface or region was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum LimitExceeded {
+    #[error("too many surfaces (limit {0})")]
+    Surfaces(usize),
+    #[error("too many regions (limit {0})")]
+    Regions(usize),
+}
+
+/// Resource counts of a client, as reported over IPC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ClientResources {
+    /// Compositor-side client ID
+    pub client: u64,
+    /// Live surfaces
+    pub surfaces: usize,
+    /// Live surfaces that were never committed
+    pub uncommitted: usize,
+    /// Live regions
+    pub regions: usize,
+    /// The client crossed the leak threshold
+    pub leaking: bool,
+}
+
+/// A surface and the client that created it
+#[derive(Debug, Clone, Copy)]
+struct TrackedSurface {
+    client: ClientId,
+    committed: bool,
+}
+
+/// Per-client surface and region accounting
+#[derive(Debug)]
+pub struct LeakDetector {
+    /// Maximum number of surfaces per client
+    max_surfaces: Option<usize>,
+    /// Maximum number of regions per client
+    max_regions: Option<usize>,
+    /// Count at which a client is reported as leaking
+    threshold: usize,
+    /// Time between checks
+    interval: Duration,
+    /// Collect surfaces of disconnected clients
+    collect_orphans: bool,
+    /// Live surfaces
+    surfaces: HashMap<SurfaceId, TrackedSurface>,
+    /// Live regions per client
+    regions: HashMap<ClientId, usize>,
+    /// Clients already reported as leaking
+    flagged: HashSet<ClientId>,
+    /// When the last check ran
+    last_check: Option<Instant>,
+    /// Orphaned surfaces collected since startup
+    collected_total: u64,
+}
+
+impl LeakDetector {
+    /// Create a detector from the configuration
+    pub fn new(config: &ResourceLimitsConfig) -> Self {
+        Self {
+            max_surfaces: config.max_surfaces_per_client,
+            max_regions: config.max_regions_per_client,
+            threshold: config.leak_threshold.max(1),
+            interval: Duration::from_secs(config.check_interval_secs),
+            collect_orphans: config.collect_orphans,
+            surfaces: HashMap::new(),
+            regions: HashMap::new(),
+            flagged: HashSet::new(),
+            last_check: None,
+            collected_total: 0,
+        }
+    }
+
+    /// Check if a client may create another surface
+    pub fn check_surface(&self, client: ClientId) -> Result<(), LimitExceeded> {
+        match self.max_surfaces {
+            Some(max) if self.surface_count(client) >= max => Err(LimitExceeded::Surfaces(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a new surface
+    pub fn surface_created(&mut self, client: ClientId, surface: SurfaceId) {
+        self.surfaces.insert(
+            surface,
+            TrackedSurface {
+                client,
+                committed: false,
+            },
+        );
+    }
+
+    /// Record a commit of a surface
+    pub fn surface_committed(&mut self, surface: SurfaceId) {
+        if let Some(tracked) = self.surfaces.get_mut(&surface) {
+            tracked.committed = true;
+        }
+    }
+
+    /// Forget a destroyed surface
+    pub fn surface_destroyed(&mut self, surface: SurfaceId) {
+        self.surfaces.remove(&surface);
+    }
+
+    /// Record a new region, unless the client is at its limit
+    pub fn region_created(&mut self, client: ClientId) -> Result<(), LimitExceeded> {
+        let count = self.regions.entry(client).or_default();
+        match self.max_regions {
+            Some(max) if *count >= max => Err(LimitExceeded::Regions(max)),
+            _ => {
+                *count += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Forget a destroyed region
+    pub fn region_destroyed(&mut self, client: ClientId) {
+        if let Some(count) = self.regions.get_mut(&client) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.regions.remove(&client);
+            }
+        }
+    }
+
+    fn surface_count(&self, client: ClientId) -> usize {
+        self.surfaces
+            .values()
+            .filter(|s| s.client == client)
+            .count()
+    }
+
+    /// Resource counts of every client with live surfaces or regions
+    pub fn clients(&self) -> Vec<ClientResources> {
+        let empty = |client: ClientId| ClientResources {
+            client: client.0,
+            surfaces: 0,
+            uncommitted: 0,
+            regions: 0,
+            leaking: false,
+        };
+        let mut counts: HashMap<ClientId, ClientResources> = HashMap::new();
+        for tracked in self.surfaces.values() {
+            let resources = counts
+                .entry(tracked.client)
+                .or_insert_with(|| empty(tracked.client));
+            resources.surfaces += 1;
+            resources.uncommitted += usize::from(!tracked.committed);
+        }
+        for (&client, &regions) in &self.regions {
+            counts
+                .entry(client)
+                .or_insert_with(|| empty(client))
+                .regions = regions;
+        }
+
+        let mut clients: Vec<ClientResources> = counts
+            .into_values()
+            .map(|mut r| {
+                r.leaking = r.uncommitted >= self.threshold || r.regions >= self.threshold;
+                r
+            })
+            .collect();
+        clients.sort_by_key(|r| r.client);
+        clients
+    }
+
+    /// Look for leaking clients, at most once per check interval
+    ///
+    /// Returns None if the interval hasn't passed yet, otherwise the leaking
+    /// clients. Each client is logged once when it starts leaking.
+    pub fn check(&mut self, now: Instant) -> Option<Vec<ClientResources>> {
+        if self
+            .last_check
+            .is_some_and(|at| now.duration_since(at) < self.interval)
+        {
+            return None;
+        }
+        self.last_check = Some(now);
+
+        let leaking: Vec<ClientResources> =
+            self.clients().into_iter().filter(|r| r.leaking).collect();
+        let current: HashSet<ClientId> = leaking.iter().map(|r| ClientId(r.client)).collect();
+        for resources in &leaking {
+            if !self.flagged.contains(&ClientId(resources.client)) {
+                warn!(
+                    "Client {} may be leaking: {} uncommitted surfaces ({} total), {} regions",
+                    resources.client, resources.uncommitted, resources.surfaces, resources.regions
+                );
+            }
+        }
+        self.flagged = current;
+        Some(leaking)
+    }
+
+    /// Check if orphaned surfaces should be collected
+    pub fn collects_orphans(&self) -> bool {
+        self.collect_orphans
+    }
+
+    /// Live surfaces and the clients that created them
+    pub fn surfaces(&self) -> impl Iterator<Item = (SurfaceId, ClientId)> + '_ {
+        self.surfaces.iter().map(|(id, s)| (*id, s.client))
+    }
+
+    /// Record that orphaned surfaces were collected
+    pub fn collected(&mut self, surfaces: &[SurfaceId]) {
+        for surface in surfaces {
+            self.surfaces.remove(surface);
+        }
+        if !surfaces.is_empty() {
+            info!("Collected {} orphaned surface(s)", surfaces.len());
+            self.collected_total += surfaces.len() as u64;
+        }
+    }
+
+    /// Number of orphaned surfaces collected since startup
+    pub fn collected_total(&self) -> u64 {
+        self.collected_total
+    }
+
+    /// Maximum number of surfaces per client
+    pub fn max_surfaces(&self) -> Option<usize> {
+        self.max_surfaces
+    }
+
+    /// Maximum number of regions per client
+    pub fn max_regions(&self) -> Option<usize> {
+        self.max_regions
+    }
+
+    /// Count at which a client is reported as leaking
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(config: ResourceLimitsConfig) -> LeakDetector {
+        LeakDetector::new(&config)
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut leaks = detector(ResourceLimitsConfig {
+            max_surfaces_per_client: Some(2),
+            max_regions_per_client: Some(1),
+            ..Default::default()
+        });
+        let (a, b) = (ClientId(1), ClientId(2));
+
+        for id in 0..2 {
+            leaks.check_surface(a).unwrap();
+            leaks.surface_created(a, SurfaceId(id));
+        }
+        assert_eq!(leaks.check_surface(a), Err(LimitExceeded::Surfaces(2)));
+        assert_eq!(leaks.check_surface(b), Ok(()));
+        leaks.surface_destroyed(SurfaceId(0));
+        assert_eq!(leaks.check_surface(a), Ok(()));
+
+        leaks.region_created(a).unwrap();
+        assert_eq!(leaks.region_created(a), Err(LimitExceeded::Regions(1)));
+        leaks.region_destroyed(a);
+        assert_eq!(leaks.region_created(a), Ok(()));
+    }
+
+    #[test]
+    fn test_leak_check() {
+        let mut leaks = detector(ResourceLimitsConfig {
+            leak_threshold: 3,
+            check_interval_secs: 10,
+            ..Default::default()
+        });
+        let client = ClientId(7);
+        for id in 0..3 {
+            leaks.surface_created(client, SurfaceId(id));
+        }
+
+        let now = Instant::now();
+        let leaking = leaks.check(now).unwrap();
+        assert_eq!(leaking.len(), 1);
+        assert_eq!(leaking[0].uncommitted, 3);
+
+        // Checks are rate limited
+        assert!(leaks.check(now + Duration::from_secs(1)).is_none());
+
+        // Committed surfaces don't count as leaked
+        leaks.surface_committed(SurfaceId(0));
+        let leaking = leaks.check(now + Duration::from_secs(10)).unwrap();
+        assert!(leaking.is_empty());
+        assert_eq!(leaks.clients()[0].surfaces, 3);
+    }
+
+    #[test]
+    fn test_collected() {
+        let mut leaks = detector(ResourceLimitsConfig::default());
+        leaks.surface_created(ClientId(1), SurfaceId(1));
+        leaks.surface_created(ClientId(1), SurfaceId(2));
+        leaks.collected(&[SurfaceId(1)]);
+        assert_eq!(leaks.collected_total(), 1);
+        assert_eq!(
+            leaks.surfaces().collect::<Vec<_>>(),
+            vec![(SurfaceId(2), ClientId(1))]
+        );
+    }
+}
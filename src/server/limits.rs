@@ -0,0 +1,246 @@
+//! Client connection limits
+//!
+//! Protects the compositor from scripts that spawn clients in a loop by
+//! capping the number of concurrent clients and the rate of new
+//! connections. Rejected connections are closed right after accept and
+//! reported in the log and over IPC.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::config::ClientLimitsConfig;
+
+/// Window over which the connection rate is measured
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Number of rejected connections kept for IPC
+const REJECTED_HISTORY: usize = 32;
+
+/// Minimum time between rejection warnings in the log
+const WARN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why a connection was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum Rejection {
+    #[error("too many clients (limit {0})")]
+    TooManyClients(usize),
+    #[error("too many connections per second (limit {0})")]
+    RateLimited(u32),
+}
+
+/// A refused connection, as reported over IPC
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedConnection {
+    /// Why the connection was refused
+    pub reason: String,
+    /// When it was refused (seconds since the Unix epoch)
+    pub time: u64,
+}
+
+/// Counts a connected client until dropped
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    connected: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.connected.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Connection limiter for the Wayland listening socket
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    /// Maximum number of concurrent clients
+    max_clients: Option<usize>,
+    /// Maximum number of new connections per second
+    max_rate: Option<u32>,
+    /// Accept times within the rate window
+    recent: VecDeque<Instant>,
+    /// Number of connected clients, shared with their guards
+    connected: Arc<AtomicUsize>,
+    /// Most recent rejections, oldest first
+    rejected: VecDeque<RejectedConnection>,
+    /// Rejections since startup
+    rejected_total: u64,
+    /// Rejections not yet reported in the log
+    unreported: u64,
+    /// When a rejection was last reported in the log
+    last_warning: Option<Instant>,
+}
+
+impl ConnectionLimiter {
+    /// Create a limiter from the configuration
+    pub fn new(config: &ClientLimitsConfig) -> Self {
+        Self {
+            max_clients: config.max_clients,
+            max_rate: config.max_connections_per_sec,
+            recent: VecDeque::new(),
+            connected: Arc::new(AtomicUsize::new(0)),
+            rejected: VecDeque::new(),
+            rejected_total: 0,
+            unreported: 0,
+            last_warning: None,
+        }
+    }
+
+    /// Decide whether to accept a new connection
+    ///
+    /// An accepted connection is counted until the returned guard is dropped,
+    /// which should happen when the client goes away.
+    pub fn admit(&mut self, now: Instant) -> Result<ConnectionGuard, Rejection> {
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= RATE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+
+        let result = match (self.max_clients, self.max_rate) {
+            (Some(max), _) if self.connected() >= max => Err(Rejection::TooManyClients(max)),
+            (_, Some(rate)) if self.recent.len() >= rate as usize => {
+                Err(Rejection::RateLimited(rate))
+            }
+            _ => Ok(()),
+        };
+
+        match result {
+            Ok(()) => {
+                self.recent.push_back(now);
+                self.connected.fetch_add(1, Ordering::Relaxed);
+                Ok(ConnectionGuard {
+                    connected: self.connected.clone(),
+                })
+            }
+            Err(rejection) => {
+                self.reject(rejection, now);
+                Err(rejection)
+            }
+        }
+    }
+
+    /// Record and report a rejection, without flooding the log
+    fn reject(&mut self, rejection: Rejection, now: Instant) {
+        self.rejected_total += 1;
+        self.unreported += 1;
+        if self.rejected.len() == REJECTED_HISTORY {
+            self.rejected.pop_front();
+        }
+        self.rejected.push_back(RejectedConnection {
+            reason: rejection.to_string(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        });
+
+        if self
+            .last_warning
+            .is_none_or(|at| now.duration_since(at) >= WARN_INTERVAL)
+        {
+            warn!(
+                "Rejected {} client connection(s): {}",
+                self.unreported, rejection
+            );
+            self.unreported = 0;
+            self.last_warning = Some(now);
+        }
+    }
+
+    /// Number of connected clients
+    pub fn connected(&self) -> usize {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Maximum number of concurrent clients
+    pub fn max_clients(&self) -> Option<usize> {
+        self.max_clients
+    }
+
+    /// Maximum number of new connections per second
+    pub fn max_rate(&self) -> Option<u32> {
+        self.max_rate
+    }
+
+    /// Rejections since startup
+    pub fn rejected_total(&self) -> u64 {
+        self.rejected_total
+    }
+
+    /// Most recent rejections, oldest first
+    pub fn rejected(&self) -> impl Iterator<Item = &RejectedConnection> {
+        self.rejected.iter()
+    }
+}
+
+impl Default for ConnectionLimiter {
+    fn default() -> Self {
+        Self::new(&ClientLimitsConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_clients() {
+        let mut limiter = ConnectionLimiter::new(&ClientLimitsConfig {
+            max_clients: Some(2),
+            max_connections_per_sec: None,
+        });
+        let now = Instant::now();
+        let first = limiter.admit(now).unwrap();
+        let _second = limiter.admit(now).unwrap();
+        assert_eq!(
+            limiter.admit(now).unwrap_err(),
+            Rejection::TooManyClients(2)
+        );
+
+        // A disconnect frees a slot
+        drop(first);
+        assert_eq!(limiter.connected(), 1);
+        assert!(limiter.admit(now).is_ok());
+        assert_eq!(limiter.rejected_total(), 1);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut limiter = ConnectionLimiter::new(&ClientLimitsConfig {
+            max_clients: None,
+            max_connections_per_sec: Some(3),
+        });
+        let start = Instant::now();
+        let guards: Vec<_> = (0..3).map(|_| limiter.admit(start).unwrap()).collect();
+        assert_eq!(
+            limiter
+                .admit(start + Duration::from_millis(500))
+                .unwrap_err(),
+            Rejection::RateLimited(3)
+        );
+        assert!(limiter.admit(start + Duration::from_secs(1)).is_ok());
+        assert_eq!(guards.len(), 3);
+    }
+
+    #[test]
+    fn test_rejection_history_is_bounded() {
+        let mut limiter = ConnectionLimiter::new(&ClientLimitsConfig {
+            max_clients: Some(0),
+            max_connections_per_sec: None,
+        });
+        let now = Instant::now();
+        for _ in 0..REJECTED_HISTORY + 5 {
+            assert!(limiter.admit(now).is_err());
+        }
+        assert_eq!(limiter.rejected().count(), REJECTED_HISTORY);
+        assert_eq!(limiter.rejected_total(), REJECTED_HISTORY as u64 + 5);
+        assert_eq!(limiter.connected(), 0);
+    }
+}
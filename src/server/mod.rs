@@ -2,23 +2,170 @@
 //!
 //! This module sets up the Wayland display server, registers globals,
 //! and dispatches protocol events to the compositor.
+//!
+//! [`ServerState`] is layered so that each part has one owner:
+//!
+//! - [`ProtocolResources`]: the Wayland objects of all clients, touched only
+//!   by dispatch code and the code sending events
+//! - the compositor core: [`CompositorState`] and the protocol handlers,
+//!   which don't know about Wayland objects or the platform
+//! - [`BackendState`]: native windows and uploads, touched only by the
+//!   presentation code
 
+mod activation;
+mod alpha_modifier;
+mod bindings;
+mod buffers;
+mod client;
+mod commit_queue;
+mod contexts;
+mod cursor_shape;
+mod data_control;
+mod data_device;
+mod decoration;
+mod dialog;
 mod dispatch;
+mod ext_data_control;
+mod fixes;
+mod foreign_toplevel;
+mod fractional_scale;
 mod globals;
+mod idle_inhibit;
+mod idle_notify;
+mod info;
+mod input_method;
+mod keyboard_shortcuts_inhibit;
+mod keymap;
+mod launcher;
+mod layer_shell;
+mod leaks;
+mod limits;
+mod output;
+mod output_management;
+mod output_power;
+mod permissions;
+mod ping;
+mod pointer_constraints;
+mod pointer_gestures;
+mod pointer_warp;
+mod presentation;
+mod presentation_hints;
+mod preview;
+mod primary_selection;
+mod relative_pointer;
+mod resources;
+mod screencopy;
+mod seat;
+mod security_context;
+mod services;
+mod single_pixel_buffer;
+mod socket;
+#[cfg(feature = "strict")]
+mod strict;
+mod tablet;
+mod text_input;
+mod toplevel_icon;
+mod toplevel_list;
+mod touch;
+mod viewporter;
+mod virtual_keyboard;
+mod xwayland;
+mod zoom;
 
+use std::collections::HashMap;
 use std::os::unix::io::AsFd;
+use std::os::unix::net::UnixStream;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use calloop::generic::Generic;
 use calloop::{Interest, LoopHandle, Mode, PostAction};
-use log::{debug, error, info};
-use wayland_server::{Display, ListeningSocket};
+use log::{debug, error, info, warn};
+use wayland_server::{Display, ListeningSocket, Resource};
 
-use crate::compositor::CompositorState;
-use crate::protocol::WlShmHandler;
+use crate::backend::BackendState;
+use crate::compositor::surface::BufferInfo;
+use crate::compositor::{
+    CompositorAction, CompositorState, Launcher, OutputId, Quirks, SurfaceRole, WindowId,
+};
+use crate::config::{Config, RemoteConfig, XwaylandConfig};
+use crate::input::seat::SeatCapabilities;
+use crate::input::{BindingProfile, HotCorners, IdleTracker, KeyBinding, Tablet, TouchEmulator};
+use crate::ipc::IpcServer;
+use crate::protocol::shm::ShmBufferId;
+#[cfg(target_os = "macos")]
+use crate::protocol::shm::ShmFormat;
+use crate::protocol::{
+    ActivationHandler, DataDeviceHandler, IdleInhibitHandler, InputMethod, LayerShellHandler,
+    ScreencopyHandler, TextInputHandler, WlShmHandler,
+};
+use crate::remote::RemoteServer;
+#[cfg(target_os = "macos")]
+use crate::renderer::scene::FULL_CROP;
+#[cfg(target_os = "macos")]
+use crate::renderer::zoom::magnify;
+#[cfg(target_os = "macos")]
+use crate::renderer::{apply_color_filter, DiagnosticOverlay, Unresponsive};
+#[cfg(target_os = "macos")]
+use crate::renderer::{place_tree, Layer, Pixels, Upload, UploadJob};
+use crate::renderer::{Capture, CaptureError, FrameScheduler, PresentFeedback, Scene};
+use crate::session::{Journal, JournalEvent, SessionManager};
+use crate::util::clock;
+use crate::watchdog::WatchdogHandle;
+use crate::xwayland::Xwayland;
 
-pub use dispatch::*;
+pub use bindings::BindingsError;
+pub use buffers::BufferReleases;
+pub use client::{client_info, ClientInfo, ClientState, GlobalData, GLOBALS};
+pub use contexts::{ContextError, ContextSockets, SecurityContext, PRIVILEGED_GLOBALS};
+pub use data_control::DataControlResources;
+pub use data_device::DataDeviceResources;
+pub use foreign_toplevel::ForeignToplevels;
+pub use fractional_scale::SurfaceScale;
 pub use globals::*;
+pub use input_method::InputMethodResources;
+pub use keyboard_shortcuts_inhibit::ShortcutInhibitors;
+pub use keymap::SharedKeymap;
+pub use leaks::{ClientResources, LeakDetector, LimitExceeded};
+pub use limits::{ConnectionLimiter, RejectedConnection, Rejection};
+pub use output::{send_output_state, OutputData, OutputResources};
+pub use output_management::{HeadChanges, ModeChoice, OutputHeads};
+pub use output_power::OutputPowers;
+pub use ping::PingResources;
+pub use pointer_constraints::{ConstraintResource, PointerConstraint, PointerConstraints};
+pub use presentation::PresentationQueue;
+pub use presentation_hints::PresentationHints;
+pub use preview::PreviewError;
+pub use primary_selection::PrimarySelectionResources;
+pub use resources::ProtocolResources;
+pub use seat::{wayland_capabilities, SeatData, SeatResources};
+pub use single_pixel_buffer::SinglePixelBuffer;
+pub use socket::{SocketChoice, SocketError};
+#[cfg(feature = "strict")]
+pub use strict::{StrictValidator, Violation};
+pub use tablet::TabletResources;
+pub use toplevel_list::ToplevelLists;
+pub use virtual_keyboard::VirtualKeyboards;
+pub use xwayland::XwaylandWindows;
+pub use zoom::{ZoomChange, ZoomError};
+
+/// How often the state summary for stall reports is refreshed
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Read the pixel data of a committed buffer
+///
+/// Single-pixel buffers have no storage and yield their one pixel, and
+/// snapshotted buffers yield the copy made at commit.
+fn read_buffer(shm: &mut WlShmHandler, buffer: &BufferInfo) -> Option<Vec<u8>> {
+    if let Some(pixel) = buffer.solid_pixel() {
+        return Some(pixel.to_vec());
+    }
+    if let Some(snapshot) = &buffer.snapshot {
+        return Some(snapshot.data.to_vec());
+    }
+    shm.read_buffer_data(ShmBufferId(buffer.shm_buffer_id?))
+        .ok()
+}
 
 /// The Wayland server state
 ///
@@ -31,42 +178,833 @@ pub struct WaylandServer {
     socket: ListeningSocket,
     /// Socket name for WAYLAND_DISPLAY
     socket_name: String,
+    /// IPC control socket
+    ipc: Option<IpcServer>,
+    /// Remote viewer server, when enabled
+    remote: Option<RemoteServer>,
+    /// Xwayland, when enabled and running
+    xwayland: Option<Xwayland>,
+    /// When the state summary was last published to the watchdog
+    status_published: Option<Instant>,
 }
 
 /// State passed to Wayland dispatch handlers
+///
+/// `resources` holds the Wayland objects of clients and `backend` the
+/// platform adapter, whose native windows are only reached through its
+/// methods. The other fields make up the compositor core: the
+/// configuration, the compositor model and a handler per subsystem.
 pub struct ServerState {
+    /// Compositor configuration
+    pub config: Config,
     /// Compositor state (surfaces, windows, outputs, seat)
     pub compositor: CompositorState,
+    /// Wayland objects of all clients
+    pub resources: ProtocolResources,
+    /// Native windows and presentation
+    pub backend: BackendState,
     /// SHM handler
     pub shm: WlShmHandler,
-    /// Main thread marker (for creating native windows)
-    #[cfg(target_os = "macos")]
-    pub mtm: Option<objc2_foundation::MainThreadMarker>,
-    /// Native windows
-    #[cfg(target_os = "macos")]
-    pub native_windows: std::collections::HashMap<
-        crate::compositor::WindowId,
-        crate::backend::cocoa::window::WayoaWindow,
-    >,
+    /// Hot corner detection
+    pub hot_corners: HotCorners,
+    /// Trackpad contacts as touch points, in touchscreen emulation mode
+    pub touch_emulation: Option<TouchEmulator>,
+    /// Graphics tablet tools and where they are
+    pub tablet: Tablet,
+    /// Clipboard selection and data sources
+    pub data_device: DataDeviceHandler,
+    /// xdg_activation_v1 token requests and tokens
+    pub activation: ActivationHandler,
+    /// wlr-screencopy frames
+    pub screencopy: ScreencopyHandler,
+    /// wlr-layer-shell surfaces
+    pub layer_shell: LayerShellHandler,
+    /// Windows of X11 clients
+    pub xwayland: XwaylandWindows,
+    /// Idle inhibitors
+    pub idle_inhibit: IdleInhibitHandler,
+    /// User idle time and idle notifications
+    pub idle: IdleTracker,
+    /// Text input state
+    pub text_input: TextInputHandler,
+    /// Input method state
+    pub input_method: InputMethod,
+    /// Spawned clients and session restore
+    pub session: SessionManager,
+    /// Session journal
+    pub journal: Journal,
+    /// Client connection limits
+    pub client_limits: ConnectionLimiter,
+    /// Per-client surface and region accounting
+    pub leaks: LeakDetector,
+    /// Progress reporting for the main loop watchdog
+    pub watchdog: WatchdogHandle,
+    /// Frame timing of each output
+    pub scheduler: FrameScheduler,
+    /// Window switcher and launcher overlay
+    pub launcher: Launcher,
+    /// Key that opens the launcher
+    pub launcher_binding: Option<KeyBinding>,
+    /// Key that focuses the previously focused window
+    pub focus_previous_binding: Option<KeyBinding>,
+    /// Key that previews the focused window
+    pub preview_binding: Option<KeyBinding>,
+    /// Keys that zoom the focused window
+    pub zoom_bindings: Vec<(KeyBinding, CompositorAction)>,
+    /// Active keybinding profile
+    pub binding_profile: Option<BindingProfile>,
+    /// Sockets of tagged clients, created for `wayoa proxy --tag`
+    pub context_sockets: ContextSockets,
+    /// Set when the compositor was asked to quit, e.g. by `--replace`
+    pub quit_requested: bool,
+    /// Request traces for strict validation
+    #[cfg(feature = "strict")]
+    pub strict: StrictValidator,
 }
 
 impl ServerState {
     /// Create a new server state
     pub fn new() -> Self {
-        Self {
-            compositor: CompositorState::new(),
+        Self::with_config(Config::default())
+    }
+
+    /// Create a new server state with the given configuration
+    pub fn with_config(config: Config) -> Self {
+        let mut idle_inhibit = IdleInhibitHandler::new();
+        for app_id in &config.idle_inhibit.ignore {
+            idle_inhibit.ignore(app_id);
+        }
+
+        let mut compositor = CompositorState::new();
+        compositor.outputs.configure(&config.default_output);
+        compositor.seat.clicks_mut().configure(&config.input);
+        compositor
+            .seat
+            .keyboard_mut()
+            .sticky_mut()
+            .configure(&config.input);
+        if config.touch_emulation.enabled {
+            let capabilities = compositor.seat.capabilities();
+            compositor.seat.set_capabilities(SeatCapabilities {
+                touch: true,
+                ..capabilities
+            });
+        }
+        let client_limits = ConnectionLimiter::new(&config.clients);
+        let leaks = LeakDetector::new(&config.resources);
+        let profile = config.bindings.profile.clone();
+
+        let mut state = Self {
+            hot_corners: HotCorners::new(&config.hot_corners),
+            touch_emulation: TouchEmulator::new(&config.touch_emulation),
+            tablet: Tablet::new(),
+            config,
+            compositor,
+            resources: ProtocolResources::default(),
+            backend: BackendState::default(),
             shm: WlShmHandler::new(),
-            #[cfg(target_os = "macos")]
-            mtm: None,
-            #[cfg(target_os = "macos")]
-            native_windows: std::collections::HashMap::new(),
+            data_device: DataDeviceHandler::new(),
+            activation: ActivationHandler::new(),
+            screencopy: ScreencopyHandler::new(),
+            layer_shell: LayerShellHandler::new(),
+            xwayland: XwaylandWindows::default(),
+            idle_inhibit,
+            idle: IdleTracker::new(Instant::now()),
+            text_input: TextInputHandler::new(),
+            input_method: InputMethod::new(),
+            session: SessionManager::new(),
+            journal: Journal::new(),
+            client_limits,
+            leaks,
+            watchdog: WatchdogHandle::new(),
+            scheduler: FrameScheduler::new(),
+            launcher: Launcher::new(),
+            launcher_binding: None,
+            focus_previous_binding: None,
+            preview_binding: None,
+            zoom_bindings: Vec::new(),
+            binding_profile: None,
+            context_sockets: ContextSockets::new(),
+            quit_requested: false,
+            #[cfg(feature = "strict")]
+            strict: StrictValidator::default(),
+        };
+        state.parse_key_bindings();
+        if let Err(e) = state.use_binding_profile(profile.as_deref()) {
+            warn!("{}", e);
         }
+        state
     }
 
     /// Set the main thread marker (must be called from main thread)
     #[cfg(target_os = "macos")]
     pub fn set_main_thread_marker(&mut self, mtm: objc2_foundation::MainThreadMarker) {
-        self.mtm = Some(mtm);
+        self.backend.mtm = Some(mtm);
+    }
+
+    /// Get the quirks of a client, including those matched by app_id
+    pub fn client_quirks(&self, client: &wayland_server::Client, app_id: Option<&str>) -> Quirks {
+        let mut quirks = client_info(client)
+            .map(|info| info.quirks.clone())
+            .unwrap_or_default();
+        for rule in self
+            .config
+            .quirks
+            .iter()
+            .filter(|rule| rule.matches(None, app_id))
+        {
+            quirks.merge(rule);
+        }
+        quirks
+    }
+
+    /// Ask for the user's attention on behalf of a window
+    ///
+    /// Used when a client may not take focus itself. The window is marked
+    /// as needing attention until it is focused, and the Dock icon bounces.
+    /// Returns false if the window doesn't exist or is already focused.
+    pub fn request_attention(&mut self, window_id: WindowId) -> bool {
+        if !self.compositor.windows.request_attention(window_id) {
+            return false;
+        }
+        info!("Window {:?} needs attention", window_id);
+
+        #[cfg(target_os = "macos")]
+        crate::backend::cocoa::app::request_user_attention();
+        true
+    }
+
+    /// Bring a window to the front and give it keyboard focus
+    ///
+    /// A minimized window is restored first, and a window on another Space
+    /// is brought to the user. A window blocked by a modal dialog activates
+    /// the dialog instead. Returns false if the window doesn't exist.
+    pub fn activate_window(&mut self, window_id: WindowId) -> bool {
+        let window_id = self
+            .compositor
+            .windows
+            .modal_dialog(window_id)
+            .unwrap_or(window_id);
+        if !self.compositor.windows.activate(window_id) {
+            return false;
+        }
+        info!("Activating window {:?}", window_id);
+
+        #[cfg(target_os = "macos")]
+        if let Some(native) = self.backend.native_window(window_id) {
+            native.activate();
+        }
+
+        let surface = self
+            .compositor
+            .windows
+            .get(window_id)
+            .map(|window| window.surface_id);
+        self.set_keyboard_focus(surface);
+        true
+    }
+
+    /// Put a window on top of the others without focusing it
+    ///
+    /// Returns false if the window doesn't exist.
+    pub fn raise_window(&mut self, window_id: WindowId) -> bool {
+        if !self.compositor.windows.raise(window_id) {
+            return false;
+        }
+        debug!("Raising window {:?}", window_id);
+
+        #[cfg(target_os = "macos")]
+        if let Some(native) = self.backend.native_window(window_id) {
+            native.order_front();
+        }
+        true
+    }
+
+    /// Put a window below the others, keeping its focus
+    ///
+    /// Returns false if the window doesn't exist.
+    pub fn lower_window(&mut self, window_id: WindowId) -> bool {
+        if !self.compositor.windows.lower(window_id) {
+            return false;
+        }
+        debug!("Lowering window {:?}", window_id);
+
+        #[cfg(target_os = "macos")]
+        if let Some(native) = self.backend.native_window(window_id) {
+            native.order_back();
+        }
+        true
+    }
+
+    /// Maximize a window or restore it from maximized
+    pub fn set_window_maximized(&mut self, window_id: WindowId, maximized: bool) {
+        if let Some(window) = self.compositor.windows.get_mut(window_id) {
+            window.maximized = maximized;
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.native_window(window_id) {
+            native_window.set_maximized(maximized);
+        }
+    }
+
+    /// Make a window fullscreen or restore it
+    ///
+    /// A window fullscreen on a headless output is only drawn offscreen, its
+    /// native window stays as it is.
+    pub fn set_window_fullscreen(
+        &mut self,
+        window_id: WindowId,
+        fullscreen: bool,
+        headless: Option<OutputId>,
+    ) {
+        let headless = headless.filter(|_| fullscreen);
+        if let Some(window) = self.compositor.windows.get_mut(window_id) {
+            window.fullscreen = fullscreen;
+            window.output = headless;
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.native_window(window_id) {
+            if headless.is_none() {
+                native_window.set_fullscreen(fullscreen);
+            }
+        }
+    }
+
+    /// Minimize a window or restore it from minimized
+    pub fn set_window_minimized(&mut self, window_id: WindowId, minimized: bool) {
+        if let Some(window) = self.compositor.windows.get_mut(window_id) {
+            window.set_minimized(minimized);
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.native_window(window_id) {
+            if minimized {
+                native_window.minimize();
+            } else {
+                native_window.restore();
+            }
+        }
+    }
+
+    /// Run the periodic leak check, collecting orphaned surfaces if enabled
+    ///
+    /// A surface is orphaned when its wl_surface is gone (the client
+    /// disconnected or destroyed it) but the surface itself is still around
+    /// without a role.
+    pub fn check_leaks(&mut self, now: Instant) {
+        if self.leaks.check(now).is_none() || !self.leaks.collects_orphans() {
+            return;
+        }
+
+        let orphans: Vec<_> = self
+            .leaks
+            .surfaces()
+            .map(|(id, _)| id)
+            .filter(|id| {
+                !self
+                    .resources
+                    .surfaces
+                    .get(id)
+                    .is_some_and(|resource| resource.is_alive())
+            })
+            .filter(|id| {
+                self.compositor
+                    .surfaces
+                    .get(*id)
+                    .is_none_or(|surface| surface.role == SurfaceRole::None)
+            })
+            .collect();
+        for id in &orphans {
+            self.compositor.surfaces.remove(*id);
+            self.resources.surfaces.remove(id);
+            self.idle_inhibit.remove_surface(*id);
+        }
+        self.leaks.collected(&orphans);
+    }
+
+    /// Save the running spawned clients and their window placements
+    pub fn save_session(&mut self) {
+        if !self.config.session.restore {
+            return;
+        }
+
+        let snapshot = self.session.snapshot(|window_id| {
+            let window = self.compositor.windows.get(window_id)?;
+
+            #[cfg(target_os = "macos")]
+            if let Some(native) = self.backend.native_window(window.id) {
+                return Some(native.placement());
+            }
+
+            window.placement
+        });
+
+        if let Err(e) = self.session.save(&snapshot) {
+            error!("Failed to save session: {}", e);
+        }
+    }
+
+    /// Describe a window's committed content for upload
+    ///
+    /// The content is drawn in the pixels of the root surface's buffer. A
+    /// window whose subsurfaces or popups have content, or whose root
+    /// surface is cropped or scaled by its viewport, is uploaded as the
+    /// layers of its surface tree, clipped to its root surface.
+    #[cfg(target_os = "macos")]
+    fn upload_job(&mut self, window_id: WindowId) -> Option<UploadJob> {
+        let root = self.compositor.windows.get(window_id)?.surface_id;
+        let scale = self.compositor.surfaces.get(root)?.scale;
+        let placements = place_tree(&self.compositor.surfaces, root, (0, 0), scale);
+        let root_placement = *placements.iter().find(|p| p.surface == root)?;
+        let (width, height) = (root_placement.width, root_placement.height);
+
+        if let [_] = placements.as_slice() {
+            let (pixels, buffer_width, buffer_height) = self.surface_pixels(root)?;
+            let as_is = root_placement.crop == FULL_CROP
+                && (buffer_width, buffer_height) == (width, height);
+            if matches!(pixels, Pixels::Solid(_)) || as_is {
+                return Some(UploadJob {
+                    window: window_id,
+                    pixels,
+                    width,
+                    height,
+                });
+            }
+        }
+
+        let layers = placements
+            .into_iter()
+            .filter_map(|placement| {
+                let (pixels, width, height) = self.surface_pixels(placement.surface)?;
+                Some(Layer {
+                    pixels,
+                    width,
+                    height,
+                    placement,
+                })
+            })
+            .collect();
+        Some(UploadJob {
+            window: window_id,
+            pixels: Pixels::Layers(layers),
+            width,
+            height,
+        })
+    }
+
+    /// Get the committed pixels of a surface and their size
+    #[cfg(target_os = "macos")]
+    fn surface_pixels(
+        &mut self,
+        surface_id: crate::compositor::SurfaceId,
+    ) -> Option<(Pixels, u32, u32)> {
+        let surface = self.compositor.surfaces.get(surface_id)?;
+        let buffer = surface.buffer.clone()?;
+
+        // A single-pixel buffer is stretched over the surface
+        if let Some(pixel) = buffer.solid_pixel() {
+            return Some((Pixels::Solid(pixel), 1, 1));
+        }
+
+        let (memory, range) = match buffer.snapshot {
+            Some(snapshot) => {
+                let len = snapshot.data.len();
+                let memory: crate::renderer::upload::SharedMemory = snapshot.data;
+                (memory, 0..len)
+            }
+            None => {
+                let shm_buffer_id = buffer.shm_buffer_id?;
+                self.shm.buffer_memory(ShmBufferId(shm_buffer_id)).ok()?
+            }
+        };
+        let pixels = Pixels::Shm {
+            memory,
+            range,
+            format: crate::protocol::shm::ShmFormat::from_wayland(buffer.format),
+            stride: buffer.stride,
+        };
+        Some((pixels, buffer.width, buffer.height))
+    }
+
+    /// Show uploaded content in its native window, magnified if zoomed,
+    /// color filtered by a window rule and dimmed if the client is
+    /// unresponsive, with the diagnostic overlay on top if enabled
+    ///
+    /// The toplevel surface's alpha multiplier becomes the window's opacity.
+    #[cfg(target_os = "macos")]
+    fn show_upload(&mut self, upload: &Upload) {
+        let Some(native) = self.backend.native_window(upload.window) else {
+            return;
+        };
+        let window = self.compositor.windows.get(upload.window);
+        let surface = window.and_then(|window| self.compositor.surfaces.get(window.surface_id));
+        let opacity = surface.map_or(1.0, |surface| surface.opacity());
+        native.set_opacity(opacity);
+        let zoom = window
+            .map(|window| window.zoom)
+            .filter(|zoom| zoom.is_zoomed());
+        let effect = window
+            .and_then(|window| window.unresponsive_since)
+            .map(|since| Unresponsive::new(since.elapsed()));
+        let filter = window.and_then(|window| window.color_filter);
+        let overlay = self
+            .backend
+            .diagnostics
+            .then(|| DiagnosticOverlay::for_window(&self.compositor, upload.window));
+        let (width, height) = (upload.width, upload.height);
+        match (zoom, effect, filter) {
+            (None, None, None) if overlay.is_none() => {
+                native.update_buffer(&upload.data, width, height, upload.stride)
+            }
+            (zoom, effect, filter) => {
+                let (mut data, stride) = match zoom {
+                    Some(zoom) => (
+                        magnify(&upload.data, width, height, upload.stride, &zoom),
+                        width * 4,
+                    ),
+                    None => (upload.data.clone(), upload.stride),
+                };
+                if let Some(filter) = filter {
+                    // XRGB content leaves the alpha byte undefined
+                    let opaque = surface
+                        .and_then(|surface| surface.buffer.as_ref())
+                        .is_some_and(|buffer| ShmFormat::from_wayland(buffer.format).is_opaque());
+                    apply_color_filter(filter, &mut data, width, height, stride, opaque);
+                }
+                if let Some(effect) = effect {
+                    effect.apply(&mut data, width, height, stride);
+                }
+                if let Some(overlay) = overlay {
+                    // The content is the toplevel's buffer, in its pixels
+                    let surface_width = surface.and_then(|surface| surface.size()).map(|s| s.0);
+                    let scale = width as f32 / surface_width.unwrap_or(width as i32).max(1) as f32;
+                    overlay.paint(&mut data, width, height, stride, scale);
+                }
+                native.update_buffer(&data, width, height, stride);
+            }
+        }
+        self.watchdog.presented();
+    }
+
+    /// Draw diagnostic overlays over window content or stop
+    ///
+    /// Windows are drawn again right away.
+    pub fn set_diagnostics(&mut self, enabled: bool) {
+        info!("Diagnostic overlays {}", if enabled { "on" } else { "off" });
+        self.backend.diagnostics = enabled;
+
+        #[cfg(target_os = "macos")]
+        {
+            let windows: Vec<WindowId> = self.backend.native_window_ids().collect();
+            for window_id in windows {
+                self.present_window(window_id);
+            }
+        }
+    }
+
+    /// Show the committed buffer of a window in its native window
+    ///
+    /// The content is uploaded on the calling thread, rather than by the
+    /// upload workers like newly committed content.
+    #[cfg(target_os = "macos")]
+    pub fn present_window(&mut self, window_id: WindowId) {
+        match self.upload_job(window_id).and_then(UploadJob::run) {
+            Some(upload) => self.show_upload(&upload),
+            None => debug!("Can't present content of window {:?}", window_id),
+        }
+    }
+
+    /// Queue a window's committed content for the next frame
+    ///
+    /// The content starts uploading on the worker threads right away.
+    /// Windows are paced by the primary output, the only one native windows
+    /// are tracked on. If the window has room for more frames in flight, its
+    /// frame callbacks are answered now instead of at the present.
+    pub fn schedule_present(&mut self, window_id: WindowId) {
+        self.watchdog.committed();
+        self.backend.pending_presents.insert(window_id);
+        if self.scheduler.window_committed(window_id, Instant::now()) {
+            self.send_frame_callbacks(&[window_id], clock::now());
+        }
+        #[cfg(target_os = "macos")]
+        if let Some(job) = self.upload_job(window_id) {
+            self.backend.uploads.submit(job);
+        }
+        if let Some(output) = self.compositor.outputs.primary() {
+            if self.scheduler.refresh(output.id).is_none() {
+                self.scheduler.sync_output(output);
+            }
+            self.scheduler.schedule(output.id, Instant::now());
+        }
+    }
+
+    /// Create the native window of a toplevel surface if it has none yet,
+    /// and present its committed content
+    ///
+    /// Toplevels are xdg_toplevels and the surfaces of X11 toplevels.
+    #[cfg(target_os = "macos")]
+    pub fn update_native_window(&mut self, surface_id: crate::compositor::SurfaceId) {
+        let Some(surface) = self.compositor.surfaces.get(surface_id) else {
+            return;
+        };
+        let xwayland = surface.role == SurfaceRole::Xwayland;
+        if surface.role != SurfaceRole::XdgToplevel && !xwayland {
+            return;
+        }
+        let Some(window_id) = self.compositor.windows.window_for_surface(surface_id) else {
+            return;
+        };
+        // Get buffer info for window creation/update
+        let buffer_info = surface.buffer.clone();
+        let (width, height) = buffer_info
+            .as_ref()
+            .map(|b| (b.width.max(640), b.height.max(480)))
+            .unwrap_or((640, 480));
+
+        // An unresponsive client asked to close the window lost it
+        let closed = !self.backend.has_native_window(window_id)
+            && self
+                .compositor
+                .windows
+                .get_mut(window_id)
+                .is_some_and(|window| window.close_expired(Instant::now()));
+        if closed {
+            return;
+        }
+
+        // Create native window if it doesn't exist
+        if !self.backend.has_native_window(window_id) {
+            if let Some(mtm) = self.backend.mtm {
+                let title = self
+                    .compositor
+                    .windows
+                    .get(window_id)
+                    .and_then(|w| w.title.clone())
+                    .unwrap_or_else(|| "Wayland Window".to_string());
+                match crate::backend::cocoa::window::WayoaWindow::new(
+                    mtm, window_id, width, height, &title,
+                ) {
+                    Ok(window) => {
+                        if let Some(placement) = self
+                            .compositor
+                            .windows
+                            .get(window_id)
+                            .and_then(|w| w.placement)
+                        {
+                            window.set_placement(placement);
+                        }
+                        let decoration = self
+                            .compositor
+                            .windows
+                            .get(window_id)
+                            .map(|w| w.decoration)
+                            .unwrap_or_default();
+                        // X11 clients expect the window manager to decorate
+                        window.set_decorated(
+                            xwayland || decoration == crate::compositor::DecorationMode::ServerSide,
+                        );
+                        window.set_icon(
+                            self.compositor
+                                .windows
+                                .get(window_id)
+                                .and_then(|w| w.icon.as_ref()),
+                        );
+                        window.show();
+                        self.backend.insert_native_window(window_id, window);
+                        debug!("Created native window for {:?}", window_id);
+                        self.sync_dialog_windows();
+
+                        // The new window was made key
+                        self.set_keyboard_focus(Some(surface_id));
+                    }
+                    Err(e) => {
+                        warn!("Failed to create native window: {}", e);
+                    }
+                }
+            }
+        }
+
+        // Update the window content with buffer data
+        if buffer_info.is_some() {
+            self.schedule_present(window_id);
+        }
+    }
+
+    /// Present the queued windows once their output reaches its deadline
+    ///
+    /// Returns the timing of each presented frame. Without an output to
+    /// pace against, queued windows are presented right away.
+    pub fn compose_due(&mut self, now: Instant) -> Vec<PresentFeedback> {
+        let due = self.scheduler.due(now);
+        let paced = self.compositor.outputs.primary().is_some();
+        if due.is_empty() && (paced || self.backend.pending_presents.is_empty()) {
+            return Vec::new();
+        }
+
+        let windows: Vec<WindowId> = std::mem::take(&mut self.backend.pending_presents)
+            .into_iter()
+            .collect();
+        // Wait for the uploads still in flight before presenting
+        #[cfg(target_os = "macos")]
+        for upload in self.backend.uploads.finish() {
+            self.show_upload(&upload);
+        }
+
+        let presented_at = Instant::now();
+        let feedback: Vec<PresentFeedback> = due
+            .into_iter()
+            .filter_map(|output| self.scheduler.presented(output, presented_at))
+            .collect();
+        for frame in feedback.iter().filter(|frame| frame.late) {
+            debug!(
+                "Frame {} on output {:?} was late",
+                frame.sequence, frame.output
+            );
+        }
+        // Windows are paced by the primary output
+        let primary = self.compositor.outputs.primary().map(|output| output.id);
+        let frame = feedback
+            .iter()
+            .find(|frame| Some(frame.output) == primary)
+            .copied();
+        let shown = frame.map(|frame| frame.time).unwrap_or(presented_at);
+        for &window in &windows {
+            self.scheduler.window_presented(window, shown);
+        }
+        self.send_presentation_feedback(&windows, frame.as_ref(), presented_at);
+
+        // Queued commits are latched for the refresh after this frame
+        let next_refresh = match frame {
+            Some(frame) => clock::at(frame.time + frame.refresh),
+            None if paced => return feedback,
+            None => Duration::MAX,
+        };
+        self.release_queued_commits(next_refresh);
+        if self.compositor.surfaces.has_timed_commits() {
+            self.schedule_commit_release();
+        }
+        feedback
+    }
+
+    /// Present every window again, e.g. after the watchdog saw a stall
+    pub fn restart_rendering(&mut self) {
+        warn!("Re-presenting all windows");
+        #[cfg(target_os = "macos")]
+        {
+            let windows: Vec<WindowId> = self.backend.native_window_ids().collect();
+            for window_id in windows {
+                self.present_window(window_id);
+            }
+        }
+        self.watchdog.presented();
+    }
+
+    /// One-line summary of the compositor state for diagnostics
+    pub fn status_summary(&self) -> String {
+        format!(
+            "{} surfaces, {} windows, keyboard focus {:?}, pointer focus {:?}, \
+             {} spawned clients, {} idle inhibitors",
+            self.compositor.surfaces.len(),
+            self.compositor.windows.len(),
+            self.compositor.seat.keyboard().focus(),
+            self.compositor.seat.pointer().focus(),
+            self.session.spawner.len(),
+            self.idle_inhibit.len(),
+        )
+    }
+
+    /// Render a single window into an offscreen image
+    ///
+    /// Only the window's own surface tree is drawn, so overlapping windows
+    /// never show up. The cursor is drawn only if requested.
+    pub fn capture_window(
+        &mut self,
+        window: WindowId,
+        include_cursor: bool,
+    ) -> Result<Capture, CaptureError> {
+        let shm = &mut self.shm;
+        let scene = Scene::for_window(&self.compositor, window, include_cursor, |buffer| {
+            read_buffer(shm, buffer)
+        })?;
+        Ok(scene.render())
+    }
+
+    /// Render the Wayland windows of an output into an offscreen image
+    ///
+    /// Windows on a screen are placed where their native windows are on
+    /// it; windows of other macOS apps aren't included.
+    pub fn capture_output(
+        &mut self,
+        output: OutputId,
+        include_cursor: bool,
+    ) -> Result<Capture, CaptureError> {
+        let positions = self.window_positions(output);
+        let shm = &mut self.shm;
+        let scene = Scene::for_output(
+            &self.compositor,
+            output,
+            &positions,
+            include_cursor,
+            |buffer| read_buffer(shm, buffer),
+        )?;
+        Ok(scene.render())
+    }
+
+    /// Get the content origins of the native windows on a screen, relative
+    /// to it
+    #[cfg(target_os = "macos")]
+    fn window_positions(&self, output: OutputId) -> HashMap<WindowId, (i32, i32)> {
+        let Some(output) = self.compositor.outputs.get(output).filter(|o| !o.headless) else {
+            return HashMap::new();
+        };
+        self.compositor
+            .windows
+            .on_output(output.id)
+            .into_iter()
+            .filter_map(|window| {
+                let native = self.backend.native_window(window.id)?;
+                let (x, y) = native.content_origin();
+                Some((window.id, (x - output.x, y - output.y)))
+            })
+            .collect()
+    }
+
+    /// Get the content origins of the native windows on a screen, none here
+    #[cfg(not(target_os = "macos"))]
+    fn window_positions(&self, _output: OutputId) -> HashMap<WindowId, (i32, i32)> {
+        HashMap::new()
+    }
+
+    /// Perform a compositor action
+    pub fn perform_action(&mut self, action: CompositorAction) {
+        info!("Performing compositor action: {}", action.name());
+        match action {
+            CompositorAction::ShowOverview => {
+                #[cfg(target_os = "macos")]
+                crate::backend::cocoa::app::show_mission_control();
+            }
+            CompositorAction::LockSession => {
+                #[cfg(target_os = "macos")]
+                crate::backend::cocoa::app::lock_session();
+            }
+            CompositorAction::ShowLauncher => self.open_launcher(),
+            CompositorAction::PreviewWindow => {
+                if let Err(e) = self.preview_focused_window() {
+                    warn!("Can't preview window: {}", e);
+                }
+            }
+            CompositorAction::ZoomIn => self.zoom_focused_window(ZoomChange::In),
+            CompositorAction::ZoomOut => self.zoom_focused_window(ZoomChange::Out),
+            CompositorAction::ZoomReset => self.zoom_focused_window(ZoomChange::Reset),
+            CompositorAction::FocusPrevious => {
+                if self.focus_previous_window().is_none() {
+                    debug!("No previous window to focus");
+                }
+            }
+            CompositorAction::ToggleDiagnostics => self.set_diagnostics(!self.backend.diagnostics),
+            CompositorAction::ToggleTouchEmulation => {
+                self.set_touch_emulation(self.touch_emulation.is_none())
+            }
+        }
     }
 }
 
@@ -77,26 +1015,55 @@ impl Default for ServerState {
 }
 
 impl WaylandServer {
-    /// Create a new Wayland server
+    /// Create a new Wayland server on the first free display
     pub fn new() -> anyhow::Result<Self> {
+        Self::with_replace(false)
+    }
+
+    /// Create a new Wayland server, optionally taking over the display of a
+    /// running wayoa instance
+    pub fn with_replace(replace: bool) -> anyhow::Result<Self> {
         info!("Creating Wayland display server");
 
         // Create the Wayland display
         let display: Display<ServerState> = Display::new()?;
 
         // Create a listening socket
-        let socket = ListeningSocket::bind_auto("wayland", 0..33)?;
-        let socket_name = socket
-            .socket_name()
-            .and_then(|n| n.to_str().map(String::from))
-            .unwrap_or_else(|| "wayland-0".to_string());
+        let dir = socket::runtime_dir()?;
+        let bound = if replace {
+            let preferred = std::env::var("WAYLAND_DISPLAY").ok();
+            match socket::bind_replacing(&dir, preferred.as_deref()) {
+                Ok(bound) => Ok(bound),
+                Err(e) => {
+                    warn!("{}, picking a free display instead", e);
+                    socket::bind_auto(&dir)
+                }
+            }
+        } else {
+            socket::bind_auto(&dir)
+        };
+        let (socket, choice) = bound?;
+        let socket_name = choice.name.clone();
 
-        info!("Wayland socket: {}", socket_name);
+        info!("Wayland socket: {}", choice.describe());
+
+        // The compositor works without IPC, so a bind failure is not fatal
+        let ipc = match IpcServer::bind(&IpcServer::default_path(&socket_name)) {
+            Ok(ipc) => Some(ipc),
+            Err(e) => {
+                warn!("Failed to create IPC socket: {}", e);
+                None
+            }
+        };
 
         Ok(Self {
             display,
             socket,
             socket_name,
+            ipc,
+            remote: None,
+            xwayland: None,
+            status_published: None,
         })
     }
 
@@ -105,6 +1072,56 @@ impl WaylandServer {
         &self.socket_name
     }
 
+    /// Get the IPC socket path (for WAYOA_SOCKET)
+    pub fn ipc_socket_path(&self) -> Option<&std::path::Path> {
+        self.ipc.as_ref().map(|ipc| ipc.path())
+    }
+
+    /// Start serving remote viewers if enabled in the configuration
+    ///
+    /// Call after the virtual outputs exist, since only headless outputs
+    /// are streamed.
+    pub fn start_remote(&mut self, config: &RemoteConfig) {
+        if !config.enabled {
+            return;
+        }
+        // Like IPC, remote access is optional
+        match RemoteServer::bind(config) {
+            Ok(remote) => self.remote = Some(remote),
+            Err(e) => warn!("Failed to start remote access: {}", e),
+        }
+    }
+
+    /// Start Xwayland if enabled in the configuration
+    pub fn start_xwayland(&mut self, config: &XwaylandConfig, state: &mut ServerState) {
+        if !config.enabled {
+            return;
+        }
+        // Xwayland connects through a socket pair rather than the
+        // listening socket, so that its client is known
+        let (stream, xwayland_stream) = match UnixStream::pair() {
+            Ok(pair) => pair,
+            Err(e) => {
+                warn!("Failed to create the Xwayland socket: {}", e);
+                return;
+            }
+        };
+        let mut dh = self.display.handle();
+        let client = match client::insert_client(&mut dh, stream, None, None, state) {
+            Ok(Some(client)) => client,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to insert the Xwayland client: {}", e);
+                return;
+            }
+        };
+        // Like remote access, X11 clients are optional
+        match Xwayland::spawn(config, xwayland_stream, client.id()) {
+            Ok(xwayland) => self.xwayland = Some(xwayland),
+            Err(e) => warn!("Failed to start Xwayland: {}", e),
+        }
+    }
+
     /// Get a handle to the display for registering globals
     pub fn display_handle(&self) -> wayland_server::DisplayHandle {
         self.display.handle()
@@ -114,25 +1131,18 @@ impl WaylandServer {
     pub fn register_globals(&mut self) {
         let dh = self.display.handle();
 
-        // Register wl_compositor (version 6)
-        dh.create_global::<ServerState, wayland_server::protocol::wl_compositor::WlCompositor, _>(
-            6,
-            (),
-        );
-
-        // Register wl_shm (version 1)
-        dh.create_global::<ServerState, wayland_server::protocol::wl_shm::WlShm, _>(1, ());
-
-        // Register wl_seat (version 9)
-        dh.create_global::<ServerState, wayland_server::protocol::wl_seat::WlSeat, _>(9, ());
-
-        // Register wl_output (version 4)
-        dh.create_global::<ServerState, wayland_server::protocol::wl_output::WlOutput, _>(4, ());
-
-        // Register xdg_wm_base (version 6)
-        dh.create_global::<ServerState, wayland_protocols::xdg::shell::server::xdg_wm_base::XdgWmBase, _>(6, ());
+        for &(interface, version) in GLOBALS {
+            client::create_global(&dh, interface, version, None);
+        }
 
-        info!("Registered Wayland globals: wl_compositor, wl_shm, wl_seat, wl_output, xdg_wm_base");
+        info!(
+            "Registered Wayland globals: {}",
+            GLOBALS
+                .iter()
+                .map(|(interface, _)| *interface)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
     }
 
     /// Insert the Wayland event sources into a calloop event loop
@@ -156,10 +1166,14 @@ impl WaylandServer {
                     if let Some(stream) = socket.accept()? {
                         debug!("New Wayland client connected");
                         let mut state_guard = state.lock().unwrap();
-                        if let Err(e) = display_handle.insert_client(stream, Arc::new(())) {
+                        if let Err(e) = client::insert_client(
+                            &mut display_handle,
+                            stream,
+                            None,
+                            None,
+                            &mut state_guard,
+                        ) {
                             error!("Failed to insert client: {}", e);
-                        } else {
-                            state_guard.compositor.add_client();
                         }
                     }
                     Ok(PostAction::Continue)
@@ -185,6 +1199,11 @@ impl WaylandServer {
             },
         )?;
 
+        // Answer IPC commands
+        if let Some(ipc) = self.ipc {
+            ipc.insert_into_loop(handle)?;
+        }
+
         Ok(())
     }
 
@@ -193,17 +1212,87 @@ impl WaylandServer {
         // Accept any new connections
         while let Some(stream) = self.socket.accept()? {
             debug!("New Wayland client connected");
-            if let Err(e) = self.display.handle().insert_client(stream, Arc::new(())) {
+            let mut dh = self.display.handle();
+            if let Err(e) = client::insert_client(&mut dh, stream, None, None, state) {
+                error!("Failed to insert client: {}", e);
+            }
+        }
+        for (tag, stream) in state.context_sockets.accept() {
+            debug!("New Wayland client tagged {} connected", tag);
+            let mut dh = self.display.handle();
+            if let Err(e) = client::insert_client(&mut dh, stream, Some(tag), None, state) {
+                error!("Failed to insert client: {}", e);
+            }
+        }
+        for (context, stream) in state.context_sockets.accept_secure() {
+            debug!(
+                "New Wayland client of security context {} connected",
+                context.label()
+            );
+            let mut dh = self.display.handle();
+            if let Err(e) = client::insert_client(&mut dh, stream, None, Some(context), state) {
                 error!("Failed to insert client: {}", e);
-            } else {
-                state.compositor.add_client();
+            }
+        }
+
+        // Answer IPC commands
+        if let Some(ipc) = &mut self.ipc {
+            ipc.dispatch(state);
+        }
+
+        // Stream to remote viewers and apply their input
+        if let Some(remote) = &mut self.remote {
+            remote.dispatch(state, Instant::now());
+        }
+
+        // Forget spawned clients that have exited, journaling crashes
+        for record in state.session.spawner.reap() {
+            if let Some(status) = record.status.filter(|status| !status.success()) {
+                warn!("Spawned client {:?} exited: {}", record.command, status);
+                state.journal.record(JournalEvent::ClientCrashed {
+                    pid: record.pid,
+                    command: record.command,
+                    status: status.to_string(),
+                });
             }
         }
 
         // Dispatch to clients
         self.display.dispatch_clients(state)?;
+        // Manage the windows of X11 clients
+        if let Some(xwayland) = &mut self.xwayland {
+            if !xwayland.dispatch(&self.display.handle(), state) {
+                self.xwayland = None;
+            }
+        }
+        // Windows may have moved to a screen with another scale
+        state.update_fractional_scales();
+        // Inhibitors follow surfaces being mapped, minimized and destroyed
+        state.update_idle_inhibit();
+        state.update_idle_notifications();
+        state.update_shortcut_inhibitors();
+        state.update_foreign_toplevels();
+        state.update_toplevel_lists();
+        state.update_output_heads();
+        state.update_refresh_policy();
+        // Ping clients and dim the windows of those that don't answer
+        state.update_responsiveness();
         self.display.flush_clients()?;
 
+        // Report progress to the watchdog
+        state.watchdog.dispatched();
+        if self
+            .status_published
+            .is_none_or(|at| at.elapsed() >= STATUS_INTERVAL)
+        {
+            state.watchdog.set_status(state.status_summary());
+            self.status_published = Some(Instant::now());
+        }
+        if state.watchdog.take_restart_request() {
+            state.restart_rendering();
+        }
+        state.check_leaks(Instant::now());
+
         Ok(())
     }
 }
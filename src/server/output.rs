@@ -0,0 +1,299 @@
+//! wl_output objects
+//!
+//! Tracks the wl_output resources bound by each client, so that output
+//! changes are sent to every bound resource instead of only at bind time.
+//! Resources stop being tracked when released or when their client
+//! disconnects. Headless outputs get a wl_output global each, next to the
+//! global of the screen output, which is removed while the output is
+//! disabled.
+
+use std::collections::HashMap;
+
+use log::{debug, info};
+use wayland_server::backend::{ClientId, GlobalId, ObjectId};
+use wayland_server::protocol::wl_output;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::{client_info, ServerState};
+use crate::compositor::{Output, OutputId};
+use crate::config::VirtualOutputConfig;
+
+/// Output user data
+pub struct OutputData {
+    pub output_id: OutputId,
+}
+
+/// Bound wl_output resources by output
+#[derive(Debug, Default)]
+pub struct OutputResources {
+    outputs: HashMap<OutputId, Vec<wl_output::WlOutput>>,
+    /// wl_output globals of enabled headless outputs
+    globals: HashMap<OutputId, GlobalId>,
+}
+
+impl OutputResources {
+    /// Track a bound output resource
+    pub fn add(&mut self, output_id: OutputId, resource: wl_output::WlOutput) {
+        self.outputs.entry(output_id).or_default().push(resource);
+    }
+
+    /// Stop tracking a released or destroyed resource
+    pub fn remove(&mut self, id: &ObjectId) {
+        for resources in self.outputs.values_mut() {
+            resources.retain(|r| r.id() != *id);
+        }
+        self.outputs.retain(|_, resources| !resources.is_empty());
+    }
+
+    /// Stop tracking all resources of an output that went away
+    pub fn remove_output(&mut self, output_id: OutputId) -> Vec<wl_output::WlOutput> {
+        self.outputs.remove(&output_id).unwrap_or_default()
+    }
+
+    /// Get the resources bound to an output
+    pub fn resources(&self, output_id: OutputId) -> &[wl_output::WlOutput] {
+        self.outputs
+            .get(&output_id)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Count the output resources a client holds
+    pub fn count_for_client(&self, client: &ClientId) -> usize {
+        self.outputs
+            .values()
+            .flatten()
+            .filter(|r| r.client().is_some_and(|c| c.id() == *client))
+            .count()
+    }
+
+    /// Count all tracked resources
+    pub fn len(&self) -> usize {
+        self.outputs.values().map(Vec::len).sum()
+    }
+
+    /// Check if no resources are tracked
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+/// Send the full output description, followed by done
+///
+/// `scale` overrides the output scale, for clients with a scale quirk.
+pub fn send_output_state(resource: &wl_output::WlOutput, output: &Output, scale: Option<i32>) {
+    resource.geometry(
+        output.x,
+        output.y,
+        output.physical_width as i32,
+        output.physical_height as i32,
+        wl_output::Subpixel::try_from(output.subpixel.to_wayland())
+            .unwrap_or(wl_output::Subpixel::Unknown),
+        output.make.clone(),
+        output.model.clone(),
+        wl_output::Transform::try_from(output.transform.to_wayland())
+            .unwrap_or(wl_output::Transform::Normal),
+    );
+
+    for (index, mode) in output.modes.iter().enumerate() {
+        let mut flags = wl_output::Mode::empty();
+        if output.current_mode == Some(index) {
+            flags |= wl_output::Mode::Current;
+        }
+        if mode.preferred {
+            flags |= wl_output::Mode::Preferred;
+        }
+        resource.mode(
+            flags,
+            mode.width as i32,
+            mode.height as i32,
+            mode.refresh as i32,
+        );
+    }
+
+    if resource.version() >= 2 {
+        resource.scale(scale.unwrap_or(output.scale));
+    }
+
+    if resource.version() >= 4 {
+        resource.name(output.name.clone());
+        resource.description(format!("{} {}", output.make, output.model));
+    }
+
+    if resource.version() >= 2 {
+        resource.done();
+    }
+}
+
+/// Scale override for the client owning a resource
+fn scale_quirk(resource: &wl_output::WlOutput) -> Option<i32> {
+    let client = resource.client()?;
+    client_info(&client)?.quirks.output_scale
+}
+
+impl ServerState {
+    /// Send an output's description to a newly bound resource and track it
+    pub fn bind_output(&mut self, output_id: OutputId, resource: wl_output::WlOutput) {
+        if let Some(output) = self.compositor.outputs.get(output_id) {
+            send_output_state(&resource, output, scale_quirk(&resource));
+        }
+        self.resources.outputs.add(output_id, resource);
+    }
+
+    /// Change an output and send the new description to every bound resource
+    pub fn update_output(&mut self, output_id: OutputId, update: impl FnOnce(&mut Output)) {
+        let Some(output) = self.compositor.outputs.get_mut(output_id) else {
+            return;
+        };
+        update(output);
+        self.scheduler.sync_output(output);
+
+        let resources = self.resources.outputs.resources(output_id);
+        debug!(
+            "Output {:?} changed, notifying {} resources",
+            output_id,
+            resources.len()
+        );
+        for resource in resources {
+            send_output_state(resource, output, scale_quirk(resource));
+        }
+    }
+}
+
+impl ServerState {
+    /// Enable or disable a headless output, adding or removing its global
+    ///
+    /// Screen outputs can't be disabled. Returns false if the output
+    /// can't be changed.
+    pub fn set_output_enabled(
+        &mut self,
+        dh: &DisplayHandle,
+        output_id: OutputId,
+        enabled: bool,
+    ) -> bool {
+        let Some(output) = self.compositor.outputs.get_mut(output_id) else {
+            return false;
+        };
+        if output.enabled == enabled {
+            return true;
+        }
+        if !output.headless {
+            return false;
+        }
+        output.enabled = enabled;
+        if enabled {
+            let global =
+                dh.create_global::<ServerState, wl_output::WlOutput, OutputId>(4, output_id);
+            self.resources.outputs.globals.insert(output_id, global);
+        } else if let Some(global) = self.resources.outputs.globals.remove(&output_id) {
+            dh.remove_global::<ServerState>(global);
+            self.resources.output_powers.fail(output_id);
+        }
+        info!(
+            "Output {} {}",
+            output.name,
+            if enabled { "enabled" } else { "disabled" }
+        );
+        true
+    }
+
+    /// Add the configured headless outputs and advertise them
+    pub fn add_virtual_outputs(&mut self, dh: &DisplayHandle) {
+        let configs: Vec<VirtualOutputConfig> = self.config.virtual_outputs.clone();
+        for (index, config) in configs.iter().enumerate() {
+            let name = config
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("virtual-{}", index));
+            let id = self.compositor.outputs.create_headless(
+                name.clone(),
+                config.width,
+                config.height,
+                config.scale,
+                config.refresh_mhz,
+            );
+            if let Some(output) = self.compositor.outputs.get(id) {
+                self.scheduler.sync_output(output);
+            }
+            let global = dh.create_global::<ServerState, wl_output::WlOutput, OutputId>(4, id);
+            self.resources.outputs.globals.insert(id, global);
+            info!(
+                "Added headless output {} ({}x{} at scale {})",
+                name, config.width, config.height, config.scale
+            );
+        }
+    }
+}
+
+impl GlobalDispatch<wl_output::WlOutput, OutputId> for ServerState {
+    fn bind(
+        state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wl_output::WlOutput>,
+        output_id: &OutputId,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound headless wl_output {:?}", output_id);
+        let output = data_init.init(
+            resource,
+            OutputData {
+                output_id: *output_id,
+            },
+        );
+        state.bind_output(*output_id, output);
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, OutputData> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        resource: &wl_output::WlOutput,
+        request: wl_output::Request,
+        _data: &OutputData,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_output::Request::Release = request {
+            // Release is a destructor, tracking stops in destroyed()
+            debug!("Output {:?} released", resource.id());
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &wl_output::WlOutput,
+        _data: &OutputData,
+    ) {
+        state.resources.outputs.remove(&resource.id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_output_without_resources() {
+        let mut state = ServerState::new();
+        let id = state.compositor.outputs.create_output(
+            "test".to_string(),
+            "Wayoa".to_string(),
+            "Test".to_string(),
+        );
+        state.update_output(id, |output| output.scale = 2);
+        assert_eq!(state.compositor.outputs.get(id).unwrap().scale, 2);
+        assert!(state.resources.outputs.resources(id).is_empty());
+    }
+
+    #[test]
+    fn test_output_resources_empty() {
+        let mut resources = OutputResources::default();
+        let id = OutputId(1);
+        assert!(resources.is_empty());
+        assert!(resources.remove_output(id).is_empty());
+        assert_eq!(resources.len(), 0);
+    }
+}
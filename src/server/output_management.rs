@@ -0,0 +1,790 @@
+//! zwlr_output_manager_v1 objects
+//!
+//! Describes every output to tools like kanshi and wlr-randr, and applies
+//! the configurations they send. Each manager gets a head per output, with
+//! a mode object per mode. After every dispatch each head is compared with
+//! what it was last sent and only the changes are sent, and managers whose
+//! heads changed get `done` with the serial of the current configuration.
+//!
+//! A configuration can move and rescale any output, and enable or disable
+//! headless outputs, whose wl_output global goes away while disabled.
+//! macOS decides the mode, transform and adaptive sync of a screen, so a
+//! configuration changing them fails; headless outputs can switch to any
+//! mode, including custom ones. Applied changes go through
+//! [`ServerState::update_output`], so bound wl_output objects see them too.
+
+use std::sync::Mutex;
+
+use log::{debug, info};
+use wayland_protocols_wlr::output_management::v1::server::{
+    zwlr_output_configuration_head_v1, zwlr_output_configuration_v1, zwlr_output_head_v1,
+    zwlr_output_manager_v1, zwlr_output_mode_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::output::OutputTransform;
+use crate::compositor::{Output, OutputId, OutputMode};
+
+/// Largest scale a configuration may set
+const MAX_SCALE: f64 = 8.0;
+
+/// Highest version of zwlr_output_mode_v1
+const MODE_VERSION: u32 = 3;
+
+/// What a head was told about its output
+#[derive(Debug, Clone, PartialEq)]
+struct HeadState {
+    description: String,
+    /// Width, height, refresh and whether the mode is preferred
+    modes: Vec<(i32, i32, i32, bool)>,
+    current_mode: Option<usize>,
+    enabled: bool,
+    position: (i32, i32),
+    transform: OutputTransform,
+    scale: f64,
+    adaptive_sync: bool,
+}
+
+impl HeadState {
+    /// Describe an output as it is now
+    fn of(output: &Output, adaptive_sync: bool) -> Self {
+        Self {
+            description: format!("{} {}", output.make, output.model),
+            modes: output
+                .modes
+                .iter()
+                .map(|mode| {
+                    (
+                        mode.width as i32,
+                        mode.height as i32,
+                        mode.refresh as i32,
+                        mode.preferred,
+                    )
+                })
+                .collect(),
+            current_mode: output.current_mode,
+            enabled: output.enabled,
+            position: (output.x, output.y),
+            transform: output.transform,
+            scale: output.fractional_scale,
+            adaptive_sync,
+        }
+    }
+}
+
+/// A head given to a manager, with what it was last sent
+#[derive(Debug)]
+struct Head {
+    manager: zwlr_output_manager_v1::ZwlrOutputManagerV1,
+    output: OutputId,
+    head: zwlr_output_head_v1::ZwlrOutputHeadV1,
+    /// Mode objects, in the order of the output's modes
+    modes: Vec<zwlr_output_mode_v1::ZwlrOutputModeV1>,
+    /// None until the first `done`
+    sent: Option<HeadState>,
+}
+
+impl Head {
+    /// Tell the client the head and its modes are gone
+    fn finish(&self) {
+        for mode in &self.modes {
+            mode.finished();
+        }
+        self.head.finished();
+    }
+}
+
+/// Output managers and the heads given to them
+#[derive(Debug, Default)]
+pub struct OutputHeads {
+    /// Managers that haven't stopped
+    managers: Vec<zwlr_output_manager_v1::ZwlrOutputManagerV1>,
+    /// Heads of outputs that still exist
+    heads: Vec<Head>,
+    /// Serial of the current configuration
+    serial: u32,
+    /// The configuration the serial was taken for
+    current: Vec<(OutputId, HeadState)>,
+}
+
+/// A mode a configuration picks for a head
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeChoice {
+    /// One of the output's modes, by index
+    Listed(usize),
+    /// A mode of any size, refresh in mHz or zero for any
+    Custom {
+        width: i32,
+        height: i32,
+        refresh: i32,
+    },
+}
+
+/// Changes a configuration makes to an enabled head
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeadChanges {
+    pub mode: Option<ModeChoice>,
+    pub position: Option<(i32, i32)>,
+    pub transform: Option<OutputTransform>,
+    pub scale: Option<f64>,
+    pub adaptive_sync: Option<bool>,
+}
+
+/// A configuration being built by a client
+#[derive(Debug)]
+struct Configuration {
+    /// Serial of the configuration it is based on
+    serial: u32,
+    /// Heads by output, with their changes or None to disable them
+    heads: Vec<(
+        OutputId,
+        Option<zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1>,
+    )>,
+    /// Applied or tested, no request is allowed after that
+    used: bool,
+}
+
+/// zwlr_output_configuration_v1 user data
+pub struct ConfigurationData(Mutex<Configuration>);
+
+/// zwlr_output_configuration_head_v1 user data
+pub struct ConfigurationHeadData(Mutex<HeadChanges>);
+
+impl GlobalDispatch<zwlr_output_manager_v1::ZwlrOutputManagerV1, GlobalData> for ServerState {
+    fn bind(
+        state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_output_manager_v1::ZwlrOutputManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwlr_output_manager_v1");
+        let manager = data_init.init(resource, ());
+        state.resources.output_heads.managers.push(manager);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwlr_output_manager_v1::ZwlrOutputManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_output_manager_v1::ZwlrOutputManagerV1,
+        request: zwlr_output_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwlr_output_manager_v1::Request::CreateConfiguration { id, serial } => {
+                data_init.init(
+                    id,
+                    ConfigurationData(Mutex::new(Configuration {
+                        serial,
+                        heads: Vec::new(),
+                        used: false,
+                    })),
+                );
+            }
+            zwlr_output_manager_v1::Request::Stop => {
+                debug!("Output manager stopped");
+                state
+                    .resources
+                    .output_heads
+                    .managers
+                    .retain(|manager| manager != resource);
+                resource.finished();
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwlr_output_manager_v1::ZwlrOutputManagerV1,
+        _data: &(),
+    ) {
+        state
+            .resources
+            .output_heads
+            .managers
+            .retain(|manager| manager != resource);
+    }
+}
+
+impl Dispatch<zwlr_output_head_v1::ZwlrOutputHeadV1, OutputId> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_output_head_v1::ZwlrOutputHeadV1,
+        _request: zwlr_output_head_v1::Request,
+        _data: &OutputId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Release is a destructor, tracking stops in destroyed()
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwlr_output_head_v1::ZwlrOutputHeadV1,
+        _data: &OutputId,
+    ) {
+        state
+            .resources
+            .output_heads
+            .heads
+            .retain(|head| head.head != *resource);
+    }
+}
+
+impl Dispatch<zwlr_output_mode_v1::ZwlrOutputModeV1, (OutputId, usize)> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_output_mode_v1::ZwlrOutputModeV1,
+        _request: zwlr_output_mode_v1::Request,
+        _data: &(OutputId, usize),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl Dispatch<zwlr_output_configuration_v1::ZwlrOutputConfigurationV1, ConfigurationData>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_output_configuration_v1::ZwlrOutputConfigurationV1,
+        request: zwlr_output_configuration_v1::Request,
+        data: &ConfigurationData,
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_output_configuration_v1::{Error, Request};
+
+        let mut configuration = data.0.lock().unwrap();
+        if configuration.used && !matches!(request, Request::Destroy) {
+            resource.post_error(
+                Error::AlreadyUsed,
+                "The configuration was already applied or tested",
+            );
+            return;
+        }
+        let (head, enable) = match request {
+            Request::EnableHead { id, head } => (head, Some(id)),
+            Request::DisableHead { head } => (head, None),
+            Request::Apply | Request::Test => {
+                configuration.used = true;
+                if let Some(output) = state.compositor.outputs.iter().find_map(|(&id, _)| {
+                    (!configuration.heads.iter().any(|(head, _)| *head == id)).then_some(id)
+                }) {
+                    resource.post_error(
+                        Error::UnconfiguredHead,
+                        format!("Output {:?} isn't configured", output),
+                    );
+                    return;
+                }
+                if configuration.serial != state.resources.output_heads.serial {
+                    debug!("Output configuration is outdated");
+                    resource.cancelled();
+                    return;
+                }
+                let heads: Vec<(OutputId, Option<HeadChanges>)> = configuration
+                    .heads
+                    .iter()
+                    .map(|(output, head)| {
+                        let changes = head.as_ref().and_then(|head| {
+                            head.data::<ConfigurationHeadData>()
+                                .map(|data| data.0.lock().unwrap().clone())
+                        });
+                        (*output, head.as_ref().map(|_| changes.unwrap_or_default()))
+                    })
+                    .collect();
+                let result = match request {
+                    Request::Apply => state.apply_output_configuration(dhandle, &heads),
+                    _ => state.check_output_configuration(&heads),
+                };
+                match result {
+                    Ok(()) => resource.succeeded(),
+                    Err(reason) => {
+                        info!("Output configuration failed: {}", reason);
+                        resource.failed();
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        let Some(&output) = head.data::<OutputId>() else {
+            return;
+        };
+        if configuration.heads.iter().any(|(id, _)| *id == output) {
+            resource.post_error(
+                Error::AlreadyConfiguredHead,
+                "The head was already configured",
+            );
+            return;
+        }
+        let head = enable.map(|id| {
+            data_init.init(
+                id,
+                ConfigurationHeadData(Mutex::new(HeadChanges::default())),
+            )
+        });
+        configuration.heads.push((output, head));
+    }
+}
+
+impl
+    Dispatch<
+        zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1,
+        ConfigurationHeadData,
+    > for ServerState
+{
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1,
+        request: zwlr_output_configuration_head_v1::Request,
+        data: &ConfigurationHeadData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_output_configuration_head_v1::{Error, Request};
+
+        let mut changes = data.0.lock().unwrap();
+        let already_set = match request {
+            Request::SetMode { mode } => {
+                let Some(&(_, index)) = mode.data::<(OutputId, usize)>() else {
+                    resource.post_error(Error::InvalidMode, "The mode is gone");
+                    return;
+                };
+                changes.mode.replace(ModeChoice::Listed(index)).is_some()
+            }
+            Request::SetCustomMode {
+                width,
+                height,
+                refresh,
+            } => {
+                if width <= 0 || height <= 0 || refresh < 0 {
+                    resource.post_error(Error::InvalidCustomMode, "The mode is invalid");
+                    return;
+                }
+                let mode = ModeChoice::Custom {
+                    width,
+                    height,
+                    refresh,
+                };
+                changes.mode.replace(mode).is_some()
+            }
+            Request::SetPosition { x, y } => changes.position.replace((x, y)).is_some(),
+            Request::SetTransform { transform } => {
+                let transform = match transform {
+                    WEnum::Value(transform) => OutputTransform::from_wayland(transform as u32),
+                    WEnum::Unknown(_) => None,
+                };
+                let Some(transform) = transform else {
+                    resource.post_error(Error::InvalidTransform, "Unknown transform");
+                    return;
+                };
+                changes.transform.replace(transform).is_some()
+            }
+            Request::SetScale { scale } => {
+                if scale <= 0.0 {
+                    resource.post_error(Error::InvalidScale, "The scale isn't positive");
+                    return;
+                }
+                changes.scale.replace(scale).is_some()
+            }
+            Request::SetAdaptiveSync { state } => {
+                let enabled = match state {
+                    WEnum::Value(zwlr_output_head_v1::AdaptiveSyncState::Enabled) => true,
+                    WEnum::Value(zwlr_output_head_v1::AdaptiveSyncState::Disabled) => false,
+                    _ => {
+                        resource.post_error(
+                            Error::InvalidAdaptiveSyncState,
+                            "Unknown adaptive sync state",
+                        );
+                        return;
+                    }
+                };
+                changes.adaptive_sync.replace(enabled).is_some()
+            }
+            _ => return,
+        };
+        if already_set {
+            resource.post_error(Error::AlreadySet, "The property was already set");
+        }
+    }
+}
+
+impl ServerState {
+    /// Check that a configuration can be applied
+    ///
+    /// `heads` has every output, with its changes or None to disable it.
+    pub fn check_output_configuration(
+        &self,
+        heads: &[(OutputId, Option<HeadChanges>)],
+    ) -> Result<(), String> {
+        if heads.iter().all(|(_, changes)| changes.is_none()) {
+            return Err("No output would be enabled".to_string());
+        }
+        for (id, changes) in heads {
+            let Some(output) = self.compositor.outputs.get(*id) else {
+                return Err(format!("Output {:?} is gone", id));
+            };
+            let Some(changes) = changes else {
+                if !output.headless {
+                    return Err(format!("Screen {} can't be disabled", output.name));
+                }
+                continue;
+            };
+            match changes.mode {
+                Some(ModeChoice::Listed(index)) if index >= output.modes.len() => {
+                    return Err(format!("Output {} has no mode {}", output.name, index));
+                }
+                Some(ModeChoice::Listed(index))
+                    if !output.headless && output.current_mode != Some(index) =>
+                {
+                    return Err(format!("Screen {} can't change modes", output.name));
+                }
+                Some(ModeChoice::Custom { .. }) if !output.headless => {
+                    return Err(format!("Screen {} can't change modes", output.name));
+                }
+                _ => {}
+            }
+            if changes.transform.is_some_and(|t| t != output.transform) {
+                return Err(format!("Output {} can't be transformed", output.name));
+            }
+            if changes.scale.is_some_and(|scale| scale > MAX_SCALE) {
+                return Err(format!("Scale of output {} is too large", output.name));
+            }
+            if changes
+                .adaptive_sync
+                .is_some_and(|enabled| enabled != self.scheduler.adaptive_sync(*id))
+            {
+                return Err(format!("Output {} can't change adaptive sync", output.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a configuration after checking it
+    pub fn apply_output_configuration(
+        &mut self,
+        dh: &DisplayHandle,
+        heads: &[(OutputId, Option<HeadChanges>)],
+    ) -> Result<(), String> {
+        self.check_output_configuration(heads)?;
+        for (id, changes) in heads {
+            self.set_output_enabled(dh, *id, changes.is_some());
+            let Some(changes) = changes else {
+                continue;
+            };
+            self.update_output(*id, |output| {
+                match changes.mode {
+                    Some(ModeChoice::Listed(index)) => {
+                        for (i, mode) in output.modes.iter_mut().enumerate() {
+                            mode.current = i == index;
+                        }
+                        output.current_mode = Some(index);
+                    }
+                    Some(ModeChoice::Custom {
+                        width,
+                        height,
+                        refresh,
+                    }) => {
+                        let refresh = match refresh {
+                            0 => output.current_mode().map_or(60_000, |mode| mode.refresh),
+                            refresh => refresh as u32,
+                        };
+                        output.modes.clear();
+                        output.current_mode = None;
+                        output.add_mode(OutputMode {
+                            width: width as u32,
+                            height: height as u32,
+                            refresh,
+                            current: true,
+                            preferred: true,
+                        });
+                    }
+                    None => {}
+                }
+                if let Some((x, y)) = changes.position {
+                    output.x = x;
+                    output.y = y;
+                }
+                if let Some(scale) = changes.scale {
+                    output.fractional_scale = scale;
+                    output.scale = scale.ceil() as i32;
+                }
+            });
+            if let Some(output) = self.compositor.outputs.get(*id) {
+                info!(
+                    "Output {} configured: {}x{} at {},{} scale {}",
+                    output.name,
+                    output.width(),
+                    output.height(),
+                    output.x,
+                    output.y,
+                    output.fractional_scale
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Announce new outputs to managers, send what changed about the others
+    /// and finish the heads of outputs that are gone
+    pub(super) fn update_output_heads(&mut self) {
+        let outputs = &self.compositor.outputs;
+        let scheduler = &self.scheduler;
+        let heads = &mut self.resources.output_heads;
+
+        let mut current: Vec<(OutputId, HeadState)> = outputs
+            .iter()
+            .map(|(&id, output)| (id, HeadState::of(output, scheduler.adaptive_sync(id))))
+            .collect();
+        current.sort_by_key(|(id, _)| id.0);
+        if current != heads.current {
+            heads.serial = heads.serial.wrapping_add(1);
+            heads.current = current;
+        }
+        if heads.managers.is_empty() && heads.heads.is_empty() {
+            return;
+        }
+
+        let mut changed: Vec<zwlr_output_manager_v1::ZwlrOutputManagerV1> = Vec::new();
+        heads.heads.retain(|head| {
+            let exists = outputs.get(head.output).is_some();
+            if !exists {
+                head.finish();
+                changed.push(head.manager.clone());
+            }
+            exists
+        });
+
+        for manager in &heads.managers {
+            let (Some(client), Some(dh)) = (
+                manager.client(),
+                manager.handle().upgrade().map(DisplayHandle::from),
+            ) else {
+                continue;
+            };
+            for (id, _) in &heads.current {
+                let announced = heads
+                    .heads
+                    .iter()
+                    .any(|head| head.manager == *manager && head.output == *id);
+                if announced {
+                    continue;
+                }
+                let Ok(head) = client
+                    .create_resource::<zwlr_output_head_v1::ZwlrOutputHeadV1, _, Self>(
+                        &dh,
+                        manager.version(),
+                        *id,
+                    )
+                else {
+                    continue;
+                };
+                manager.head(&head);
+                heads.heads.push(Head {
+                    manager: manager.clone(),
+                    output: *id,
+                    head,
+                    modes: Vec::new(),
+                    sent: None,
+                });
+            }
+        }
+
+        for head in &mut heads.heads {
+            let Some(output) = outputs.get(head.output) else {
+                continue;
+            };
+            let Some((_, now)) = heads.current.iter().find(|(id, _)| *id == head.output) else {
+                continue;
+            };
+            if head.sent.as_ref() == Some(now) {
+                continue;
+            }
+            send_head(head, output, now);
+            head.sent = Some(now.clone());
+            if !changed.contains(&head.manager) {
+                changed.push(head.manager.clone());
+            }
+        }
+
+        for manager in changed {
+            if heads.managers.contains(&manager) {
+                manager.done(heads.serial);
+            }
+        }
+    }
+}
+
+/// Send a head what changed about its output
+fn send_head(head: &mut Head, output: &Output, now: &HeadState) {
+    let first = head.sent.is_none();
+    let sent = head.sent.take();
+    let resource = &head.head;
+    if first {
+        resource.name(output.name.clone());
+        resource.physical_size(output.physical_width as i32, output.physical_height as i32);
+        if resource.version() >= 2 {
+            resource.make(output.make.clone());
+            resource.model(output.model.clone());
+            resource.serial_number(output.serial.clone());
+        }
+    }
+    if sent.as_ref().map(|sent| &sent.description) != Some(&now.description) {
+        resource.description(now.description.clone());
+    }
+
+    let modes_changed = sent.as_ref().map(|sent| &sent.modes) != Some(&now.modes);
+    if modes_changed {
+        for mode in head.modes.drain(..) {
+            mode.finished();
+        }
+        let (Some(client), Some(dh)) = (
+            resource.client(),
+            resource.handle().upgrade().map(DisplayHandle::from),
+        ) else {
+            return;
+        };
+        for (index, &(width, height, refresh, preferred)) in now.modes.iter().enumerate() {
+            let Ok(mode) = client
+                .create_resource::<zwlr_output_mode_v1::ZwlrOutputModeV1, _, ServerState>(
+                    &dh,
+                    resource.version().min(MODE_VERSION),
+                    (head.output, index),
+                )
+            else {
+                continue;
+            };
+            resource.mode(&mode);
+            mode.size(width, height);
+            if refresh > 0 {
+                mode.refresh(refresh);
+            }
+            if preferred {
+                mode.preferred();
+            }
+            head.modes.push(mode);
+        }
+    }
+
+    let sent = sent.filter(|sent| sent.enabled && now.enabled);
+    if sent.is_none() || !now.enabled {
+        resource.enabled(now.enabled as i32);
+    }
+    if !now.enabled {
+        return;
+    }
+    if let Some(mode) = now.current_mode.and_then(|index| head.modes.get(index)) {
+        if modes_changed || sent.as_ref().map(|sent| sent.current_mode) != Some(now.current_mode) {
+            resource.current_mode(mode);
+        }
+    }
+    if sent.as_ref().map(|sent| sent.position) != Some(now.position) {
+        resource.position(now.position.0, now.position.1);
+    }
+    if sent.as_ref().map(|sent| sent.transform) != Some(now.transform) {
+        if let Ok(transform) = now.transform.to_wayland().try_into() {
+            resource.transform(transform);
+        }
+    }
+    if sent.as_ref().map(|sent| sent.scale) != Some(now.scale) {
+        resource.scale(now.scale);
+    }
+    if resource.version() >= 4
+        && sent.as_ref().map(|sent| sent.adaptive_sync) != Some(now.adaptive_sync)
+    {
+        resource.adaptive_sync(match now.adaptive_sync {
+            true => zwlr_output_head_v1::AdaptiveSyncState::Enabled,
+            false => zwlr_output_head_v1::AdaptiveSyncState::Disabled,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayland_server::Display;
+
+    #[test]
+    fn test_configuration() {
+        let mut state = ServerState::new();
+        let display = Display::<ServerState>::new().unwrap();
+        let dh = display.handle();
+        let screen = state.compositor.outputs.create_output(
+            "screen".to_string(),
+            "Apple".to_string(),
+            "Built-in".to_string(),
+        );
+        let headless = state.compositor.outputs.create_headless(
+            "virtual-0".to_string(),
+            1920,
+            1080,
+            1,
+            60_000,
+        );
+
+        // The screen can't be disabled or change modes
+        let moved = HeadChanges {
+            position: Some((-1920, 0)),
+            scale: Some(1.5),
+            ..Default::default()
+        };
+        assert!(state
+            .check_output_configuration(&[(screen, None), (headless, Some(moved.clone()))])
+            .is_err());
+        let custom = HeadChanges {
+            mode: Some(ModeChoice::Custom {
+                width: 1280,
+                height: 720,
+                refresh: 0,
+            }),
+            ..Default::default()
+        };
+        assert!(state
+            .check_output_configuration(&[(screen, Some(custom.clone())), (headless, None)])
+            .is_err());
+
+        state
+            .apply_output_configuration(&dh, &[(screen, Some(moved)), (headless, Some(custom))])
+            .unwrap();
+        let output = state.compositor.outputs.get(screen).unwrap();
+        assert_eq!(
+            (output.x, output.scale, output.fractional_scale),
+            (-1920, 2, 1.5)
+        );
+        let output = state.compositor.outputs.get(headless).unwrap();
+        assert_eq!((output.width(), output.height()), (1280, 720));
+        assert_eq!(output.current_mode().unwrap().refresh, 60_000);
+
+        state
+            .apply_output_configuration(
+                &dh,
+                &[(screen, Some(HeadChanges::default())), (headless, None)],
+            )
+            .unwrap();
+        assert!(!state.compositor.outputs.get(headless).unwrap().enabled);
+    }
+}
@@ -0,0 +1,239 @@
+//! zwlr_output_power_manager_v1 objects
+//!
+//! Lets clients such as idle daemons turn outputs off and on. Headless
+//! outputs change at once. Screens are backed by display sleep: macOS
+//! sleeps and wakes all displays together, so turning one screen off turns
+//! off all of them, and their mode changes once the displays actually went
+//! to sleep or woke up. Only one client at a time controls an output's
+//! power; others get a failed event.
+
+use log::{debug, warn};
+use wayland_protocols_wlr::output_power_management::v1::server::{
+    zwlr_output_power_manager_v1, zwlr_output_power_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
+
+use super::client::GlobalData;
+use super::{OutputData, ServerState};
+use crate::compositor::OutputId;
+use crate::util::power;
+
+/// zwlr_output_power_v1 objects by output
+#[derive(Debug, Default)]
+pub struct OutputPowers {
+    powers: Vec<(OutputId, zwlr_output_power_v1::ZwlrOutputPowerV1)>,
+}
+
+impl OutputPowers {
+    /// Check if a client other than `client` controls an output's power
+    fn controlled_by_other(&self, output_id: OutputId, client: &ClientId) -> bool {
+        self.powers.iter().any(|(id, power)| {
+            *id == output_id && power.client().is_some_and(|c| c.id() != *client)
+        })
+    }
+
+    /// Invalidate the objects of an output that went away
+    pub fn fail(&mut self, output_id: OutputId) {
+        self.powers.retain(|(id, power)| {
+            if *id == output_id {
+                power.failed();
+            }
+            *id != output_id
+        });
+    }
+}
+
+fn wayland_mode(powered: bool) -> zwlr_output_power_v1::Mode {
+    if powered {
+        zwlr_output_power_v1::Mode::On
+    } else {
+        zwlr_output_power_v1::Mode::Off
+    }
+}
+
+impl GlobalDispatch<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwlr_output_power_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1,
+        request: zwlr_output_power_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_output_power_manager_v1::Request::GetOutputPower { id, output } = request {
+            let output = output
+                .data::<OutputData>()
+                .and_then(|data| state.compositor.outputs.get(data.output_id))
+                .filter(|output| output.enabled)
+                .map(|output| (output.id, output.powered))
+                .filter(|(id, _)| {
+                    !state
+                        .resources
+                        .output_powers
+                        .controlled_by_other(*id, &client.id())
+                });
+            let Some((output_id, powered)) = output else {
+                debug!("Output power control refused");
+                data_init.init(id, None).failed();
+                return;
+            };
+            let power = data_init.init(id, Some(output_id));
+            power.mode(wayland_mode(powered));
+            state
+                .resources
+                .output_powers
+                .powers
+                .push((output_id, power));
+        }
+    }
+}
+
+impl Dispatch<zwlr_output_power_v1::ZwlrOutputPowerV1, Option<OutputId>> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_output_power_v1::ZwlrOutputPowerV1,
+        request: zwlr_output_power_v1::Request,
+        data: &Option<OutputId>,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwlr_output_power_v1::Request::SetMode { mode } = request {
+            let WEnum::Value(mode) = mode else {
+                resource.post_error(
+                    zwlr_output_power_v1::Error::InvalidMode,
+                    "unknown power mode",
+                );
+                return;
+            };
+            if let Some(output_id) = *data {
+                state.set_output_power(output_id, mode == zwlr_output_power_v1::Mode::On);
+            }
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwlr_output_power_v1::ZwlrOutputPowerV1,
+        _data: &Option<OutputId>,
+    ) {
+        state
+            .resources
+            .output_powers
+            .powers
+            .retain(|(_, power)| power.id() != resource.id());
+    }
+}
+
+impl ServerState {
+    /// Turn an output on or off
+    ///
+    /// Headless outputs change at once; screens change when the displays
+    /// follow, see [`Self::set_displays_powered`].
+    pub fn set_output_power(&mut self, output_id: OutputId, powered: bool) {
+        let Some(output) = self.compositor.outputs.get_mut(output_id) else {
+            return;
+        };
+        debug!(
+            "Output {} power {}",
+            output.name,
+            if powered { "on" } else { "off" }
+        );
+        if output.headless {
+            output.powered = powered;
+            self.send_power_mode(output_id, powered);
+        } else if output.powered != powered {
+            if let Err(e) = power::set_displays_awake(powered) {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    /// Follow the displays going to sleep or waking up
+    pub fn set_displays_powered(&mut self, powered: bool) {
+        let changed: Vec<OutputId> = self
+            .compositor
+            .outputs
+            .iter()
+            .filter(|(_, output)| !output.headless && output.powered != powered)
+            .map(|(&id, _)| id)
+            .collect();
+        for output_id in changed {
+            if let Some(output) = self.compositor.outputs.get_mut(output_id) {
+                output.powered = powered;
+            }
+            self.send_power_mode(output_id, powered);
+        }
+    }
+
+    fn send_power_mode(&self, output_id: OutputId, powered: bool) {
+        for (_, power) in self
+            .resources
+            .output_powers
+            .powers
+            .iter()
+            .filter(|(id, _)| *id == output_id)
+        {
+            power.mode(wayland_mode(powered));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_power() {
+        let mut state = ServerState::new();
+        let screen = state.compositor.outputs.create_output(
+            "default".to_string(),
+            String::new(),
+            String::new(),
+        );
+        let headless = state.compositor.outputs.create_headless(
+            "virtual-0".to_string(),
+            1920,
+            1080,
+            1,
+            60_000,
+        );
+        let powered = |state: &ServerState, id| state.compositor.outputs.get(id).unwrap().powered;
+
+        // Headless outputs change at once, screens when the displays do
+        state.set_output_power(headless, false);
+        state.set_output_power(screen, false);
+        assert!(!powered(&state, headless));
+        assert!(powered(&state, screen));
+
+        state.set_displays_powered(false);
+        assert!(!powered(&state, screen));
+        assert!(!powered(&state, headless));
+        state.set_displays_powered(true);
+        assert!(powered(&state, screen));
+        assert!(!powered(&state, headless));
+    }
+}
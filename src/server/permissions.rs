@@ -0,0 +1,58 @@
+//! wayoa_permissions_v1 objects
+//!
+//! Tells a client which globals are withheld from it. Only clients of a
+//! tagged socket or a security context are restricted, and they are denied
+//! the privileged globals.
+
+use log::debug;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New};
+
+use super::client::{client_info, GlobalData};
+use super::contexts::PRIVILEGED_GLOBALS;
+use super::ServerState;
+use crate::extensions::permissions::server::wayoa_permissions_v1;
+
+/// Get the globals withheld from a client
+fn denied_globals(client: &Client) -> &'static [&'static str] {
+    let tagged = client_info(client).is_some_and(|info| info.context.is_some());
+    if tagged {
+        PRIVILEGED_GLOBALS
+    } else {
+        &[]
+    }
+}
+
+impl GlobalDispatch<wayoa_permissions_v1::WayoaPermissionsV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        client: &Client,
+        resource: New<wayoa_permissions_v1::WayoaPermissionsV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wayoa_permissions_v1");
+        let permissions = data_init.init(resource, ());
+        for interface in denied_globals(client) {
+            permissions.denied(interface.to_string());
+        }
+        permissions.done();
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wayoa_permissions_v1::WayoaPermissionsV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wayoa_permissions_v1::WayoaPermissionsV1,
+        _request: wayoa_permissions_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
@@ -0,0 +1,174 @@
+//! Unresponsive clients
+//!
+//! Clients are pinged through their xdg_wm_base by the [`PingTracker`].
+//! After every dispatch the windows of clients that missed a ping are
+//! marked unresponsive, which dims them with a spinner when they are
+//! composed, and unmarked as soon as the pong arrives. Native windows are
+//! presented again for every frame of the effect's animation.
+//!
+//! [`PingTracker`]: crate::protocol::ping::PingTracker
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use wayland_protocols::xdg::shell::server::xdg_wm_base;
+use wayland_server::backend::ClientId;
+use wayland_server::Resource;
+
+use super::ServerState;
+use crate::compositor::WindowId;
+use crate::protocol::ping::PingTracker;
+use crate::renderer::Unresponsive;
+
+/// Ping state and xdg_wm_base objects of all clients
+#[derive(Debug, Default)]
+pub struct PingResources {
+    /// Pings sent and answered
+    tracker: PingTracker<ClientId>,
+    /// The xdg_wm_base each client is pinged through
+    wm_bases: HashMap<ClientId, xdg_wm_base::XdgWmBase>,
+    /// Animation frame last presented, by unresponsive window
+    frames: HashMap<WindowId, u64>,
+}
+
+impl ServerState {
+    /// Start pinging a client through a newly bound xdg_wm_base
+    pub(super) fn add_wm_base(&mut self, wm_base: xdg_wm_base::XdgWmBase) {
+        let Some(client) = wm_base.client().map(|client| client.id()) else {
+            return;
+        };
+        self.resources
+            .pings
+            .tracker
+            .add(client.clone(), Instant::now());
+        self.resources.pings.wm_bases.insert(client, wm_base);
+    }
+
+    /// Stop pinging a client through a destroyed xdg_wm_base
+    pub(super) fn remove_wm_base(&mut self, client: ClientId, wm_base: &xdg_wm_base::XdgWmBase) {
+        if self.resources.pings.wm_bases.get(&client) == Some(wm_base) {
+            self.resources.pings.wm_bases.remove(&client);
+            self.resources.pings.tracker.remove(&client);
+        }
+    }
+
+    /// Handle a pong
+    ///
+    /// The client's windows are restored by the next update, right after
+    /// this dispatch.
+    pub(super) fn pong(&mut self, client: ClientId, serial: u32) {
+        self.resources
+            .pings
+            .tracker
+            .pong(&client, serial, Instant::now());
+    }
+
+    /// Send the pings that are due and mark the windows of unresponsive
+    /// clients
+    pub(super) fn update_responsiveness(&mut self) {
+        let now = Instant::now();
+        let compositor = &self.compositor;
+        for (client, serial) in self
+            .resources
+            .pings
+            .tracker
+            .due(now, || compositor.next_serial())
+        {
+            if let Some(wm_base) = self.resources.pings.wm_bases.get(&client) {
+                wm_base.ping(serial);
+            }
+        }
+        self.resources.pings.tracker.check(now);
+
+        let changed: Vec<_> = self
+            .compositor
+            .windows
+            .iter()
+            .filter_map(|(&id, window)| {
+                let since = self
+                    .resources
+                    .surfaces
+                    .get(&window.surface_id)
+                    .and_then(|surface| surface.client())
+                    .and_then(|client| {
+                        self.resources
+                            .pings
+                            .tracker
+                            .unresponsive_since(&client.id())
+                    });
+                (since != window.unresponsive_since).then_some((id, since))
+            })
+            .collect();
+        for (id, since) in changed {
+            if let Some(window) = self.compositor.windows.get_mut(id) {
+                window.unresponsive_since = since;
+            }
+        }
+        self.expire_close_requests(now);
+
+        // Present the frames of the animation, and the content as it was
+        // once the client is back
+        let mut present = Vec::new();
+        for (&id, window) in self.compositor.windows.iter() {
+            match window.unresponsive_since {
+                Some(since) => {
+                    let frame = Unresponsive::new(now.duration_since(since)).frame();
+                    if self.resources.pings.frames.insert(id, frame) != Some(frame) {
+                        present.push(id);
+                    }
+                }
+                None if self.resources.pings.frames.remove(&id).is_some() => present.push(id),
+                None => {}
+            }
+        }
+        self.resources
+            .pings
+            .frames
+            .retain(|id, _| self.compositor.windows.get(*id).is_some());
+        #[cfg(target_os = "macos")]
+        for id in present {
+            self.present_window(id);
+        }
+        #[cfg(not(target_os = "macos"))]
+        drop(present);
+    }
+
+    /// Close the native windows of unresponsive clients that were asked to
+    /// close them long enough ago
+    ///
+    /// The windows themselves stay until their clients destroy them, and
+    /// get a native window again if their clients come back.
+    fn expire_close_requests(&mut self, now: Instant) {
+        let expired: Vec<_> = self
+            .compositor
+            .windows
+            .iter_mut()
+            .filter_map(|(&id, window)| window.close_expired(now).then_some(id))
+            .collect();
+        #[cfg(target_os = "macos")]
+        for id in expired {
+            if let Some(native) = self.backend.remove_native_window(id) {
+                log::debug!("Closing {:?} of an unresponsive client", id);
+                native.close();
+            }
+        }
+        #[cfg(not(target_os = "macos"))]
+        drop(expired);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_without_clients_stay_responsive() {
+        let mut state = ServerState::new();
+        let surface_id = state.compositor.surfaces.create_surface();
+        let window_id = state.compositor.windows.create_window(surface_id);
+        state.update_responsiveness();
+        let window = state.compositor.windows.get(window_id).unwrap();
+        assert_eq!(window.unresponsive_since, None);
+        assert!(state.resources.pings.frames.is_empty());
+    }
+}
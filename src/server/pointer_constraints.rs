@@ -0,0 +1,426 @@
+//! zwp_pointer_constraints_v1 objects
+//!
+//! A client can lock the pointer in place on one of its surfaces, e.g. for
+//! mouse look in a game, or confine it to the surface, e.g. for a remote
+//! desktop viewer. A constraint is active while its surface has pointer
+//! focus and its window has keyboard focus. On macOS a lock detaches the
+//! cursor from the mouse with CGAssociateMouseAndMouseCursorPosition, so
+//! only relative motion reaches the client, and a confined cursor is warped
+//! back into the window when it leaves. Constraint regions are not
+//! supported; constraints cover the whole surface.
+
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocols::wp::pointer_constraints::zv1::server::{
+    zwp_confined_pointer_v1, zwp_locked_pointer_v1, zwp_pointer_constraints_v1,
+};
+use wayland_server::backend::{ClientId, ObjectId};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::{SurfaceId, WindowId};
+
+/// The protocol object of a constraint
+#[derive(Debug)]
+pub enum ConstraintResource {
+    /// zwp_locked_pointer_v1
+    Lock(zwp_locked_pointer_v1::ZwpLockedPointerV1),
+    /// zwp_confined_pointer_v1
+    Confine(zwp_confined_pointer_v1::ZwpConfinedPointerV1),
+}
+
+impl ConstraintResource {
+    /// Get the protocol object id
+    fn id(&self) -> ObjectId {
+        match self {
+            ConstraintResource::Lock(resource) => resource.id(),
+            ConstraintResource::Confine(resource) => resource.id(),
+        }
+    }
+}
+
+/// A pointer constraint on a surface
+#[derive(Debug)]
+pub struct PointerConstraint {
+    /// The locked or confined pointer
+    pub resource: ConstraintResource,
+    /// Whether the constraint comes back after it was deactivated
+    pub persistent: bool,
+    /// Whether the constraint is in effect
+    pub active: bool,
+    /// Where a lock leaves the cursor when it ends, in surface coordinates
+    pub hint: Option<(f64, f64)>,
+}
+
+/// Pointer constraints by surface
+#[derive(Debug, Default)]
+pub struct PointerConstraints {
+    constraints: HashMap<SurfaceId, PointerConstraint>,
+}
+
+impl PointerConstraints {
+    /// Get the constraint on a surface
+    pub fn get(&self, surface: SurfaceId) -> Option<&PointerConstraint> {
+        self.constraints.get(&surface)
+    }
+
+    /// Get the constraint in effect, if any
+    pub fn active(&self) -> Option<(SurfaceId, &PointerConstraint)> {
+        self.constraints
+            .iter()
+            .find(|(_, constraint)| constraint.active)
+            .map(|(&surface, constraint)| (surface, constraint))
+    }
+
+    /// Check if the pointer is locked in place
+    pub fn is_locked(&self) -> bool {
+        self.active().is_some_and(|(_, constraint)| {
+            matches!(constraint.resource, ConstraintResource::Lock(_))
+        })
+    }
+
+    /// Get a surface's constraint if it is the given protocol object
+    fn get_mut_for(&mut self, surface: SurfaceId, id: &ObjectId) -> Option<&mut PointerConstraint> {
+        self.constraints
+            .get_mut(&surface)
+            .filter(|constraint| constraint.resource.id() == *id)
+    }
+}
+
+/// Check if a constraint on `surface` should be in effect
+///
+/// `root` is the surface's toplevel, which has keyboard focus while its
+/// window is focused.
+fn should_activate(
+    surface: SurfaceId,
+    root: SurfaceId,
+    pointer_focus: Option<SurfaceId>,
+    keyboard_focus: Option<SurfaceId>,
+) -> bool {
+    pointer_focus == Some(surface) && keyboard_focus == Some(root)
+}
+
+impl GlobalDispatch<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_pointer_constraints_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_pointer_constraints_v1::ZwpPointerConstraintsV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwp_pointer_constraints_v1::ZwpPointerConstraintsV1,
+        request: zwp_pointer_constraints_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (surface, lifetime) = match &request {
+            zwp_pointer_constraints_v1::Request::LockPointer {
+                surface, lifetime, ..
+            }
+            | zwp_pointer_constraints_v1::Request::ConfinePointer {
+                surface, lifetime, ..
+            } => (surface, *lifetime),
+            _ => return,
+        };
+        let Some(&surface_id) = surface.data::<SurfaceId>() else {
+            return;
+        };
+        if state
+            .resources
+            .pointer_constraints
+            .get(surface_id)
+            .is_some()
+        {
+            resource.post_error(
+                zwp_pointer_constraints_v1::Error::AlreadyConstrained,
+                "surface already has a pointer constraint",
+            );
+            return;
+        }
+
+        let constraint = match request {
+            zwp_pointer_constraints_v1::Request::LockPointer { id, .. } => {
+                debug!("Surface {:?} lock pointer", surface_id);
+                ConstraintResource::Lock(data_init.init(id, surface_id))
+            }
+            zwp_pointer_constraints_v1::Request::ConfinePointer { id, .. } => {
+                debug!("Surface {:?} confine pointer", surface_id);
+                ConstraintResource::Confine(data_init.init(id, surface_id))
+            }
+            _ => return,
+        };
+        let persistent = lifetime == WEnum::Value(zwp_pointer_constraints_v1::Lifetime::Persistent);
+        state.resources.pointer_constraints.constraints.insert(
+            surface_id,
+            PointerConstraint {
+                resource: constraint,
+                persistent,
+                active: false,
+                hint: None,
+            },
+        );
+        state.update_pointer_constraints();
+    }
+}
+
+impl Dispatch<zwp_locked_pointer_v1::ZwpLockedPointerV1, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwp_locked_pointer_v1::ZwpLockedPointerV1,
+        request: zwp_locked_pointer_v1::Request,
+        data: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_locked_pointer_v1::Request::SetCursorPositionHint {
+                surface_x,
+                surface_y,
+            } => {
+                if let Some(constraint) = state
+                    .resources
+                    .pointer_constraints
+                    .get_mut_for(*data, &resource.id())
+                {
+                    constraint.hint = Some((surface_x, surface_y));
+                }
+            }
+            zwp_locked_pointer_v1::Request::SetRegion { .. } => {
+                debug!("Ignoring lock region of surface {:?}", data);
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_locked_pointer_v1::ZwpLockedPointerV1,
+        data: &SurfaceId,
+    ) {
+        state.remove_pointer_constraint(*data, &resource.id());
+    }
+}
+
+impl Dispatch<zwp_confined_pointer_v1::ZwpConfinedPointerV1, SurfaceId> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_confined_pointer_v1::ZwpConfinedPointerV1,
+        request: zwp_confined_pointer_v1::Request,
+        data: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_confined_pointer_v1::Request::SetRegion { .. } = request {
+            debug!("Ignoring confine region of surface {:?}", data);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_confined_pointer_v1::ZwpConfinedPointerV1,
+        data: &SurfaceId,
+    ) {
+        state.remove_pointer_constraint(*data, &resource.id());
+    }
+}
+
+impl ServerState {
+    /// Activate the constraint of the focused surface and deactivate others
+    ///
+    /// Called when pointer or keyboard focus changes. One-shot constraints
+    /// are gone once deactivated.
+    pub fn update_pointer_constraints(&mut self) {
+        let pointer_focus = self.compositor.seat.pointer().focus();
+        let keyboard_focus = self.compositor.seat.keyboard().focus();
+        let surfaces: Vec<SurfaceId> = self
+            .resources
+            .pointer_constraints
+            .constraints
+            .keys()
+            .copied()
+            .collect();
+
+        // Deactivate first so the backend ends up in the new state
+        let mut activate = None;
+        for surface in surfaces {
+            let root = self.compositor.surfaces.root(surface);
+            let wanted = should_activate(surface, root, pointer_focus, keyboard_focus);
+            let Some(constraint) = self.resources.pointer_constraints.constraints.get(&surface)
+            else {
+                continue;
+            };
+            if wanted && !constraint.active {
+                activate = Some(surface);
+            } else if !wanted && constraint.active {
+                self.deactivate_pointer_constraint(surface);
+            }
+        }
+
+        let Some(surface) = activate else {
+            return;
+        };
+        let Some(constraint) = self
+            .resources
+            .pointer_constraints
+            .constraints
+            .get_mut(&surface)
+        else {
+            return;
+        };
+        constraint.active = true;
+        match &constraint.resource {
+            ConstraintResource::Lock(resource) => {
+                debug!("Pointer locked to surface {:?}", surface);
+                resource.locked();
+                #[cfg(target_os = "macos")]
+                crate::backend::cocoa::input::set_cursor_locked(true);
+            }
+            ConstraintResource::Confine(resource) => {
+                debug!("Pointer confined to surface {:?}", surface);
+                resource.confined();
+            }
+        }
+    }
+
+    /// Get the window the pointer is confined to, if any
+    pub fn confined_window(&self) -> Option<WindowId> {
+        let (surface, constraint) = self.resources.pointer_constraints.active()?;
+        if !matches!(constraint.resource, ConstraintResource::Confine(_)) {
+            return None;
+        }
+        let root = self.compositor.surfaces.root(surface);
+        self.compositor.windows.window_for_surface(root)
+    }
+
+    /// End a constraint, dropping it unless it is persistent
+    fn deactivate_pointer_constraint(&mut self, surface: SurfaceId) {
+        let Some(constraint) = self
+            .resources
+            .pointer_constraints
+            .constraints
+            .get_mut(&surface)
+        else {
+            return;
+        };
+        constraint.active = false;
+        match &constraint.resource {
+            ConstraintResource::Lock(resource) => {
+                debug!("Pointer unlocked from surface {:?}", surface);
+                resource.unlocked();
+                #[cfg(target_os = "macos")]
+                {
+                    let hint = constraint.hint;
+                    self.release_cursor_lock(surface, hint);
+                }
+            }
+            ConstraintResource::Confine(resource) => {
+                debug!("Pointer unconfined from surface {:?}", surface);
+                resource.unconfined();
+            }
+        }
+        if self
+            .resources
+            .pointer_constraints
+            .get(surface)
+            .is_some_and(|constraint| !constraint.persistent)
+        {
+            self.resources
+                .pointer_constraints
+                .constraints
+                .remove(&surface);
+        }
+    }
+
+    /// Reattach the cursor to the mouse, moving it to the lock's hint
+    #[cfg(target_os = "macos")]
+    fn release_cursor_lock(&self, surface: SurfaceId, hint: Option<(f64, f64)>) {
+        use crate::backend::cocoa::input;
+
+        let root = self.compositor.surfaces.root(surface);
+        let origin = self
+            .compositor
+            .windows
+            .window_for_surface(root)
+            .and_then(|window| self.backend.native_window(window))
+            .map(|native| native.content_origin());
+        if let (Some((x, y)), Some((hint_x, hint_y))) = (origin, hint) {
+            input::warp_cursor(x as f64 + hint_x, y as f64 + hint_y);
+        }
+        input::set_cursor_locked(false);
+    }
+
+    /// Forget a destroyed constraint
+    fn remove_pointer_constraint(&mut self, surface: SurfaceId, id: &ObjectId) {
+        if self
+            .resources
+            .pointer_constraints
+            .get_mut_for(surface, id)
+            .is_some()
+        {
+            self.drop_pointer_constraint(surface);
+        }
+    }
+
+    /// Forget the constraint of a surface, e.g. when it is destroyed
+    ///
+    /// The client no longer cares about the constraint, so it isn't told
+    /// when an active one ends.
+    pub fn drop_pointer_constraint(&mut self, surface: SurfaceId) {
+        let Some(constraint) = self
+            .resources
+            .pointer_constraints
+            .constraints
+            .remove(&surface)
+        else {
+            return;
+        };
+        #[cfg(target_os = "macos")]
+        if constraint.active && matches!(constraint.resource, ConstraintResource::Lock(_)) {
+            self.release_cursor_lock(surface, constraint.hint);
+        }
+        #[cfg(not(target_os = "macos"))]
+        let _ = constraint;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_activate() {
+        let (root, child) = (SurfaceId(1), SurfaceId(2));
+        assert!(should_activate(child, root, Some(child), Some(root)));
+        assert!(should_activate(root, root, Some(root), Some(root)));
+        // Needs both pointer focus and a focused window
+        assert!(!should_activate(child, root, Some(root), Some(root)));
+        assert!(!should_activate(child, root, Some(child), None));
+
+        let state = ServerState::new();
+        assert!(!state.resources.pointer_constraints.is_locked());
+        assert_eq!(state.confined_window(), None);
+    }
+}
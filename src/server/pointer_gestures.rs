@@ -0,0 +1,329 @@
+//! zwp_pointer_gestures_v1 objects
+//!
+//! Touchpad gestures go to the client of the surface they began on, through
+//! the gesture objects it created for its pointers. Swipes and pinches come
+//! from AppKit's swipe, magnify and rotate events, and holds from fingers
+//! resting on the trackpad before they scroll.
+
+use log::debug;
+use wayland_protocols::wp::pointer_gestures::zv1::server::{
+    zwp_pointer_gesture_hold_v1, zwp_pointer_gesture_pinch_v1, zwp_pointer_gesture_swipe_v1,
+    zwp_pointer_gestures_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::protocol::seat::{GestureEvent, GestureKind};
+
+/// A gesture object of a pointer
+#[derive(Debug, Clone, PartialEq)]
+pub enum GestureResource {
+    Swipe(zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1),
+    Pinch(zwp_pointer_gesture_pinch_v1::ZwpPointerGesturePinchV1),
+    Hold(zwp_pointer_gesture_hold_v1::ZwpPointerGestureHoldV1),
+}
+
+impl GestureResource {
+    /// Get the protocol object ID
+    pub fn id(&self) -> wayland_server::backend::ObjectId {
+        match self {
+            GestureResource::Swipe(r) => r.id(),
+            GestureResource::Pinch(r) => r.id(),
+            GestureResource::Hold(r) => r.id(),
+        }
+    }
+
+    /// Kind of gesture the object reports
+    pub fn kind(&self) -> GestureKind {
+        match self {
+            GestureResource::Swipe(_) => GestureKind::Swipe,
+            GestureResource::Pinch(_) => GestureKind::Pinch,
+            GestureResource::Hold(_) => GestureKind::Hold,
+        }
+    }
+}
+
+/// Get the kind of gesture an event belongs to
+fn event_kind(event: &GestureEvent) -> GestureKind {
+    match event {
+        GestureEvent::Begin { kind, .. } | GestureEvent::End { kind, .. } => *kind,
+        GestureEvent::SwipeUpdate { .. } => GestureKind::Swipe,
+        GestureEvent::PinchUpdate { .. } => GestureKind::Pinch,
+    }
+}
+
+impl GlobalDispatch<zwp_pointer_gestures_v1::ZwpPointerGesturesV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_pointer_gestures_v1::ZwpPointerGesturesV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_pointer_gestures_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_pointer_gestures_v1::ZwpPointerGesturesV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+        request: zwp_pointer_gestures_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (gesture, pointer) = match request {
+            zwp_pointer_gestures_v1::Request::GetSwipeGesture { id, pointer } => {
+                (GestureResource::Swipe(data_init.init(id, ())), pointer)
+            }
+            zwp_pointer_gestures_v1::Request::GetPinchGesture { id, pointer } => {
+                (GestureResource::Pinch(data_init.init(id, ())), pointer)
+            }
+            zwp_pointer_gestures_v1::Request::GetHoldGesture { id, pointer } => {
+                (GestureResource::Hold(data_init.init(id, ())), pointer)
+            }
+            zwp_pointer_gestures_v1::Request::Release => {
+                debug!("Pointer gestures release");
+                return;
+            }
+            _ => return,
+        };
+        let kind = gesture.kind();
+        if state.resources.seats.add_gesture(gesture, pointer) {
+            debug!("Creating {:?} gesture", kind);
+        } else {
+            debug!("Creating inert {:?} gesture", kind);
+        }
+    }
+}
+
+impl Dispatch<zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1,
+        _request: zwp_pointer_gesture_swipe_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Only destroy, handled by destroyed
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1,
+        _data: &(),
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+impl Dispatch<zwp_pointer_gesture_pinch_v1::ZwpPointerGesturePinchV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_pointer_gesture_pinch_v1::ZwpPointerGesturePinchV1,
+        _request: zwp_pointer_gesture_pinch_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Only destroy, handled by destroyed
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_pointer_gesture_pinch_v1::ZwpPointerGesturePinchV1,
+        _data: &(),
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+impl Dispatch<zwp_pointer_gesture_hold_v1::ZwpPointerGestureHoldV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_pointer_gesture_hold_v1::ZwpPointerGestureHoldV1,
+        _request: zwp_pointer_gesture_hold_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // Only destroy, handled by destroyed
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_pointer_gesture_hold_v1::ZwpPointerGestureHoldV1,
+        _data: &(),
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+impl ServerState {
+    /// Start a touchpad gesture on the surface with pointer focus
+    pub fn gesture_begin(&mut self, kind: GestureKind, time: u32, fingers: u32) {
+        self.input_activity();
+        let events = self
+            .compositor
+            .seat
+            .pointer_mut()
+            .gesture_begin(kind, time, fingers);
+        self.send_gesture_events(events);
+    }
+
+    /// Send the finger motion of a swipe in progress
+    pub fn gesture_swipe_update(&mut self, time: u32, dx: f64, dy: f64) {
+        let event = self
+            .compositor
+            .seat
+            .pointer_mut()
+            .gesture_swipe_update(time, dx, dy);
+        self.send_gesture_events(event.into_iter().collect());
+    }
+
+    /// Send the change in scale and rotation of a pinch in progress
+    pub fn gesture_pinch_update(
+        &mut self,
+        time: u32,
+        dx: f64,
+        dy: f64,
+        magnification: f64,
+        rotation: f64,
+    ) {
+        let event = self.compositor.seat.pointer_mut().gesture_pinch_update(
+            time,
+            dx,
+            dy,
+            magnification,
+            rotation,
+        );
+        self.send_gesture_events(event.into_iter().collect());
+    }
+
+    /// End a touchpad gesture in progress
+    pub fn gesture_end(&mut self, kind: GestureKind, time: u32, cancelled: bool) {
+        let events = self
+            .compositor
+            .seat
+            .pointer_mut()
+            .gesture_end(kind, time, cancelled);
+        self.send_gesture_events(events);
+    }
+
+    /// Send gesture events to the gesture objects of each surface's client
+    fn send_gesture_events(&mut self, events: Vec<(SurfaceId, GestureEvent)>) {
+        for (target, event) in events {
+            let Some(wl_surface) = self.resources.surfaces.get(&target) else {
+                continue;
+            };
+            let serial = match event {
+                GestureEvent::Begin { .. } | GestureEvent::End { .. } => {
+                    self.compositor.next_serial()
+                }
+                _ => 0,
+            };
+            let kind = event_kind(&event);
+            for gesture in self
+                .resources
+                .seats
+                .gestures
+                .iter()
+                .map(|(gesture, _)| gesture)
+                .filter(|g| g.kind() == kind && g.id().same_client_as(&wl_surface.id()))
+            {
+                send_gesture_event(gesture, &event, serial, wl_surface);
+            }
+        }
+    }
+}
+
+/// Send an event to a gesture object of its kind
+fn send_gesture_event(
+    gesture: &GestureResource,
+    event: &GestureEvent,
+    serial: u32,
+    surface: &wayland_server::protocol::wl_surface::WlSurface,
+) {
+    match (gesture, *event) {
+        (GestureResource::Swipe(swipe), GestureEvent::Begin { time, fingers, .. }) => {
+            swipe.begin(serial, time, surface, fingers)
+        }
+        (GestureResource::Swipe(swipe), GestureEvent::SwipeUpdate { time, dx, dy }) => {
+            swipe.update(time, dx, dy)
+        }
+        (
+            GestureResource::Swipe(swipe),
+            GestureEvent::End {
+                time, cancelled, ..
+            },
+        ) => swipe.end(serial, time, cancelled as i32),
+        (GestureResource::Pinch(pinch), GestureEvent::Begin { time, fingers, .. }) => {
+            pinch.begin(serial, time, surface, fingers)
+        }
+        (
+            GestureResource::Pinch(pinch),
+            GestureEvent::PinchUpdate {
+                time,
+                dx,
+                dy,
+                scale,
+                rotation,
+            },
+        ) => pinch.update(time, dx, dy, scale, rotation),
+        (
+            GestureResource::Pinch(pinch),
+            GestureEvent::End {
+                time, cancelled, ..
+            },
+        ) => pinch.end(serial, time, cancelled as i32),
+        (GestureResource::Hold(hold), GestureEvent::Begin { time, fingers, .. }) => {
+            hold.begin(serial, time, surface, fingers)
+        }
+        (
+            GestureResource::Hold(hold),
+            GestureEvent::End {
+                time, cancelled, ..
+            },
+        ) => hold.end(serial, time, cancelled as i32),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_kind() {
+        let end = GestureEvent::End {
+            kind: GestureKind::Hold,
+            time: 1,
+            cancelled: false,
+        };
+        assert_eq!(event_kind(&end), GestureKind::Hold);
+        let update = GestureEvent::SwipeUpdate {
+            time: 1,
+            dx: 0.0,
+            dy: 0.0,
+        };
+        assert_eq!(event_kind(&update), GestureKind::Swipe);
+    }
+}
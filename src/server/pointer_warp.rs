@@ -0,0 +1,137 @@
+//! wp_pointer_warp_v1 objects
+//!
+//! Clients can move the pointer to a position on one of their surfaces,
+//! e.g. to center it on a dialog's default button. The request is only
+//! honored for the client with pointer focus, with the serial of the
+//! latest pointer enter, and for positions on the surface. On macOS the
+//! cursor is moved with CGWarpMouseCursorPosition.
+
+use log::debug;
+use wayland_protocols::wp::pointer_warp::v1::server::wp_pointer_warp_v1;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::util::clock;
+
+impl GlobalDispatch<wp_pointer_warp_v1::WpPointerWarpV1, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wp_pointer_warp_v1::WpPointerWarpV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_pointer_warp_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_pointer_warp_v1::WpPointerWarpV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &wp_pointer_warp_v1::WpPointerWarpV1,
+        request: wp_pointer_warp_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_pointer_warp_v1::Request::WarpPointer {
+            surface,
+            pointer: _,
+            x,
+            y,
+            serial,
+        } = request
+        {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if state.compositor.seat.pointer_enter_serial() != Some(serial)
+                || !state.has_pointer_focus(client)
+            {
+                debug!("Ignoring pointer warp with stale serial {}", serial);
+                return;
+            }
+            if !state.warp_pointer(surface_id, x, y) {
+                debug!(
+                    "Ignoring pointer warp to ({}, {}) off {:?}",
+                    x, y, surface_id
+                );
+            }
+        }
+    }
+}
+
+impl ServerState {
+    /// Move the pointer to a position on a surface
+    ///
+    /// Returns false if the position isn't on the surface or the pointer is
+    /// locked.
+    pub fn warp_pointer(&mut self, surface_id: SurfaceId, x: f64, y: f64) -> bool {
+        let Some((width, height)) = self
+            .compositor
+            .surfaces
+            .get(surface_id)
+            .and_then(|surface| surface.size())
+        else {
+            return false;
+        };
+        if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+            return false;
+        }
+        if self.resources.pointer_constraints.is_locked() {
+            return false;
+        }
+
+        #[cfg(target_os = "macos")]
+        self.warp_cursor_to(surface_id, x, y);
+
+        // The cursor doesn't generate an event when warped, so the client
+        // is told where it is now. Unlike user motion, a warp doesn't count
+        // as activity.
+        let events = self.compositor.seat.pointer_mut().handle_motion(
+            clock::now_msec(),
+            Some(surface_id),
+            |_| (x, y),
+        );
+        self.send_pointer_events(events);
+        self.update_pointer_constraints();
+        true
+    }
+
+    /// Move the cursor to a position on a surface's native window
+    #[cfg(target_os = "macos")]
+    fn warp_cursor_to(&self, surface_id: SurfaceId, x: f64, y: f64) {
+        let root = self.compositor.surfaces.root(surface_id);
+        let Some(native) = self
+            .compositor
+            .windows
+            .window_for_surface(root)
+            .and_then(|window| self.backend.native_window(window))
+        else {
+            return;
+        };
+        // Subsurfaces are offset from the root surface
+        let (offset_x, offset_y) = self
+            .compositor
+            .surfaces
+            .stacking_order(root, (0, 0))
+            .into_iter()
+            .find(|(surface, _)| *surface == surface_id)
+            .map(|(_, offset)| offset)
+            .unwrap_or((0, 0));
+        let (origin_x, origin_y) = native.content_origin();
+        crate::backend::cocoa::input::warp_cursor(
+            (origin_x + offset_x) as f64 + x,
+            (origin_y + offset_y) as f64 + y,
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! wp_presentation objects and frame callbacks
+//!
+//! A wp_presentation_feedback reports when the content of one surface commit
+//! reached the screen. Feedback requested before a commit is queued with
+//! that commit and answered when its window is next presented, with the
+//! refresh time, period and counter the frame scheduler recorded for the
+//! output. Frames shown by an output under the adaptive refresh policy are
+//! reported with a refresh period of zero, as the rate isn't constant.
+//! Windows are shown through AppKit, which doesn't report when a frame
+//! was scanned out, so the times are estimated from the scheduler's
+//! refresh timeline and the feedback carries no vsync or hardware clock
+//! flags.
+//! Content replaced by a newer commit before it was presented, or whose
+//! surface went away, is reported as discarded.
+//!
+//! wl_surface frame callbacks take the same path and are done when their
+//! window is presented, with the same refresh time in milliseconds. Both
+//! are on the protocol clock in [`crate::util::clock`], like input event
+//! times, so clients can compare all three.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use wayland_protocols::wp::presentation_time::server::{wp_presentation, wp_presentation_feedback};
+use wayland_server::protocol::wl_callback;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::{SurfaceId, WindowId};
+use crate::renderer::PresentFeedback;
+use crate::util::clock;
+
+/// Feedback objects and frame callbacks waiting for their content to be
+/// presented
+#[derive(Debug, Default)]
+pub struct PresentationQueue {
+    /// Requested since the surface last committed
+    pending: HashMap<SurfaceId, Vec<wp_presentation_feedback::WpPresentationFeedback>>,
+    /// For committed content not yet presented
+    queued: HashMap<SurfaceId, Vec<wp_presentation_feedback::WpPresentationFeedback>>,
+    /// Frame callbacks requested since the surface last committed
+    pending_frames: HashMap<SurfaceId, Vec<wl_callback::WlCallback>>,
+    /// Frame callbacks of committed content not yet presented
+    queued_frames: HashMap<SurfaceId, Vec<wl_callback::WlCallback>>,
+}
+
+impl PresentationQueue {
+    /// Number of feedback objects and frame callbacks waiting, requested or
+    /// committed
+    pub fn len(&self) -> usize {
+        let feedback = self.pending.values().chain(self.queued.values());
+        let frames = self
+            .pending_frames
+            .values()
+            .chain(self.queued_frames.values());
+        feedback.map(Vec::len).sum::<usize>() + frames.map(Vec::len).sum::<usize>()
+    }
+
+    /// Check if nothing is waiting
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a frame callback, to be queued with the surface's next commit
+    pub fn request_frame(&mut self, surface: SurfaceId, callback: wl_callback::WlCallback) {
+        self.pending_frames
+            .entry(surface)
+            .or_default()
+            .push(callback);
+    }
+}
+
+impl GlobalDispatch<wp_presentation::WpPresentation, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_presentation::WpPresentation>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_presentation");
+        let presentation = data_init.init(resource, ());
+        presentation.clock_id(clock::CLOCK_ID);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_presentation::WpPresentation, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wp_presentation::WpPresentation,
+        request: wp_presentation::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_presentation::Request::Feedback { surface, callback } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            let feedback = data_init.init(callback, ());
+            state
+                .resources
+                .presentation
+                .pending
+                .entry(surface_id)
+                .or_default()
+                .push(feedback);
+        }
+    }
+}
+
+impl Dispatch<wp_presentation_feedback::WpPresentationFeedback, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wp_presentation_feedback::WpPresentationFeedback,
+        _request: wp_presentation_feedback::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        // wp_presentation_feedback has no requests
+    }
+}
+
+impl ServerState {
+    /// Queue the feedback and frame callbacks requested for a surface with
+    /// its new commit
+    ///
+    /// Feedback still queued from an earlier commit is discarded: that
+    /// content is replaced before it was ever shown. Its frame callbacks
+    /// stay queued, as they only ask for a good time to draw.
+    pub fn commit_presentation_feedback(&mut self, surface: SurfaceId) {
+        if let Some(frames) = self.resources.presentation.pending_frames.remove(&surface) {
+            self.resources
+                .presentation
+                .queued_frames
+                .entry(surface)
+                .or_default()
+                .extend(frames);
+        }
+
+        let pending = self
+            .resources
+            .presentation
+            .pending
+            .remove(&surface)
+            .unwrap_or_default();
+        let replaced = if pending.is_empty() {
+            self.resources.presentation.queued.remove(&surface)
+        } else {
+            self.resources.presentation.queued.insert(surface, pending)
+        };
+        for feedback in replaced.into_iter().flatten() {
+            feedback.discarded();
+        }
+    }
+
+    /// Discard all feedback of a destroyed surface
+    ///
+    /// Its frame callbacks are dropped without being done.
+    pub fn discard_presentation_feedback(&mut self, surface: SurfaceId) {
+        self.resources.presentation.pending_frames.remove(&surface);
+        self.resources.presentation.queued_frames.remove(&surface);
+        let pending = self.resources.presentation.pending.remove(&surface);
+        let queued = self.resources.presentation.queued.remove(&surface);
+        for feedback in pending.into_iter().chain(queued).flatten() {
+            feedback.discarded();
+        }
+    }
+
+    /// Answer the queued feedback and frame callbacks of the windows in a
+    /// presented frame
+    ///
+    /// `frame` is the scheduler's record of the present, None if it wasn't
+    /// paced by an output. Feedback of surfaces without a window can never
+    /// be presented and is discarded, while their frame callbacks are done
+    /// so clients such as animated cursors keep drawing.
+    pub fn send_presentation_feedback(
+        &mut self,
+        windows: &[WindowId],
+        frame: Option<&PresentFeedback>,
+        presented_at: Instant,
+    ) {
+        // Paced frames became visible at the refresh they were shown at
+        let shown = frame.map(|frame| frame.time).unwrap_or(presented_at);
+        let time = clock::at(shown);
+        self.send_frame_callbacks(windows, time);
+
+        let mut presented = Vec::new();
+        let mut orphaned = Vec::new();
+        for &surface in self.resources.presentation.queued.keys() {
+            let root = self.compositor.surfaces.root(surface);
+            match self.compositor.windows.window_for_surface(root) {
+                Some(window) if windows.contains(&window) => presented.push(surface),
+                Some(_) => {}
+                None => orphaned.push(surface),
+            }
+        }
+        for surface in orphaned {
+            self.discard_presentation_feedback(surface);
+        }
+        if presented.is_empty() {
+            return;
+        }
+
+        // The times are the scheduler's estimate of the refresh the window
+        // was shown at, not a hardware timestamp, so no flags are set
+        let (refresh, sequence) = match frame {
+            Some(frame) if frame.adaptive => (0, frame.sequence),
+            Some(frame) => (frame.refresh.as_nanos() as u32, frame.sequence),
+            None => (0, 0),
+        };
+        let outputs = frame
+            .map(|frame| self.resources.outputs.resources(frame.output))
+            .unwrap_or_default();
+
+        for surface in presented {
+            let Some(feedbacks) = self.resources.presentation.queued.remove(&surface) else {
+                continue;
+            };
+            debug!(
+                "Surface {:?} presented at {:?} (sequence {})",
+                surface, time, sequence
+            );
+            for feedback in feedbacks {
+                for output in outputs
+                    .iter()
+                    .filter(|output| output.id().same_client_as(&feedback.id()))
+                {
+                    feedback.sync_output(output);
+                }
+                feedback.presented(
+                    (time.as_secs() >> 32) as u32,
+                    time.as_secs() as u32,
+                    time.subsec_nanos(),
+                    refresh,
+                    (sequence >> 32) as u32,
+                    sequence as u32,
+                    wp_presentation_feedback::Kind::empty(),
+                );
+            }
+        }
+    }
+
+    /// Send done on the queued frame callbacks of presented windows and of
+    /// surfaces without a window
+    pub(super) fn send_frame_callbacks(&mut self, windows: &[WindowId], time: Duration) {
+        let compositor = &self.compositor;
+        let due: Vec<SurfaceId> = self
+            .resources
+            .presentation
+            .queued_frames
+            .keys()
+            .copied()
+            .filter(|&surface| {
+                let root = compositor.surfaces.root(surface);
+                compositor
+                    .windows
+                    .window_for_surface(root)
+                    .is_none_or(|window| windows.contains(&window))
+            })
+            .collect();
+        for surface in due {
+            for callback in self
+                .resources
+                .presentation
+                .queued_frames
+                .remove(&surface)
+                .into_iter()
+                .flatten()
+            {
+                callback.done(clock::msec(time));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presentation_queue() {
+        let mut state = ServerState::new();
+        assert!(state.resources.presentation.is_empty());
+        // Committing or destroying a surface without feedback is a no-op
+        state.commit_presentation_feedback(SurfaceId(1));
+        state.discard_presentation_feedback(SurfaceId(1));
+        assert_eq!(state.resources.presentation.len(), 0);
+    }
+}
@@ -0,0 +1,370 @@
+//! wp_content_type_manager_v1 and wp_tearing_control_manager_v1 objects
+//!
+//! Both let a client describe how its surface wants to be presented. The
+//! hints are double-buffered: they are kept by surface from the surface's
+//! next commit, and handed to the frame scheduler for the surface's window
+//! after every dispatch, since clients may set them before the surface
+//! becomes a toplevel. A content type also sets how many frames
+//! the window may have in flight.
+//!
+//! On a primary output that refreshes adaptively, a fullscreen window of a
+//! game or of a client accepting tearing switches the output to the
+//! adaptive refresh policy, so its frames are shown as soon as they are
+//! ready. Their Metal layers also present without waiting for the display,
+//! see [`crate::renderer::LayerOptions`].
+//!
+//! Photos are static, so while every visible window shows photos macOS may
+//! put wayoa in App Nap. Any other visible window keeps it awake, since
+//! timers and frame callbacks would be throttled.
+
+use std::collections::HashMap;
+
+use log::{debug, info};
+use wayland_protocols::wp::content_type::v1::server::{
+    wp_content_type_manager_v1, wp_content_type_v1,
+};
+use wayland_protocols::wp::tearing_control::v1::server::{
+    wp_tearing_control_manager_v1, wp_tearing_control_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::renderer::{ContentType, RefreshPolicy};
+
+/// Presentation hints by surface
+#[derive(Debug, Default)]
+pub struct PresentationHints {
+    /// Content types, of surfaces with a wp_content_type_v1
+    content_types: HashMap<SurfaceId, ContentType>,
+    /// Whether tearing is accepted, of surfaces with a wp_tearing_control_v1
+    tearing: HashMap<SurfaceId, bool>,
+    /// Content types set since the surface's last commit
+    pending_content_types: HashMap<SurfaceId, ContentType>,
+    /// Tearing hints set since the surface's last commit
+    pending_tearing: HashMap<SurfaceId, bool>,
+}
+
+impl GlobalDispatch<wp_content_type_manager_v1::WpContentTypeManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_content_type_manager_v1::WpContentTypeManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_content_type_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_content_type_manager_v1::WpContentTypeManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_content_type_manager_v1::WpContentTypeManagerV1,
+        request: wp_content_type_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_content_type_manager_v1::Request::GetSurfaceContentType { id, surface } = request
+        {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            let hints = &mut state.resources.presentation_hints;
+            if hints.content_types.contains_key(&surface_id) {
+                resource.post_error(
+                    wp_content_type_manager_v1::Error::AlreadyConstructed,
+                    "surface already has a content type object",
+                );
+                return;
+            }
+            hints.content_types.insert(surface_id, ContentType::None);
+            data_init.init(id, surface_id);
+        }
+    }
+}
+
+impl Dispatch<wp_content_type_v1::WpContentTypeV1, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wp_content_type_v1::WpContentTypeV1,
+        request: wp_content_type_v1::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_content_type_v1::Request::SetContentType { content_type } = request {
+            let content = match content_type {
+                WEnum::Value(wp_content_type_v1::Type::Photo) => ContentType::Photo,
+                WEnum::Value(wp_content_type_v1::Type::Video) => ContentType::Video,
+                WEnum::Value(wp_content_type_v1::Type::Game) => ContentType::Game,
+                _ => ContentType::None,
+            };
+            debug!("Surface {:?} content type {:?}", surface_id, content);
+            state
+                .resources
+                .presentation_hints
+                .pending_content_types
+                .insert(*surface_id, content);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wp_content_type_v1::WpContentTypeV1,
+        surface_id: &SurfaceId,
+    ) {
+        let hints = &mut state.resources.presentation_hints;
+        hints.content_types.remove(surface_id);
+        hints.pending_content_types.remove(surface_id);
+        if let Some(window) = state.compositor.windows.window_for_surface(*surface_id) {
+            state.scheduler.set_content_type(window, ContentType::None);
+        }
+    }
+}
+
+impl GlobalDispatch<wp_tearing_control_manager_v1::WpTearingControlManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_tearing_control_manager_v1::WpTearingControlManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_tearing_control_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_tearing_control_manager_v1::WpTearingControlManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_tearing_control_manager_v1::WpTearingControlManagerV1,
+        request: wp_tearing_control_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_tearing_control_manager_v1::Request::GetTearingControl { id, surface } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            let hints = &mut state.resources.presentation_hints;
+            if hints.tearing.contains_key(&surface_id) {
+                resource.post_error(
+                    wp_tearing_control_manager_v1::Error::TearingControlExists,
+                    "surface already has a tearing control object",
+                );
+                return;
+            }
+            hints.tearing.insert(surface_id, false);
+            data_init.init(id, surface_id);
+        }
+    }
+}
+
+impl Dispatch<wp_tearing_control_v1::WpTearingControlV1, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wp_tearing_control_v1::WpTearingControlV1,
+        request: wp_tearing_control_v1::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_tearing_control_v1::Request::SetPresentationHint { hint } = request {
+            let tearing = hint == WEnum::Value(wp_tearing_control_v1::PresentationHint::Async);
+            debug!("Surface {:?} accepts tearing: {}", surface_id, tearing);
+            state
+                .resources
+                .presentation_hints
+                .pending_tearing
+                .insert(*surface_id, tearing);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &wp_tearing_control_v1::WpTearingControlV1,
+        surface_id: &SurfaceId,
+    ) {
+        let hints = &mut state.resources.presentation_hints;
+        hints.tearing.remove(surface_id);
+        hints.pending_tearing.remove(surface_id);
+        if let Some(window) = state.compositor.windows.window_for_surface(*surface_id) {
+            state.scheduler.set_tearing(window, false);
+        }
+    }
+}
+
+impl ServerState {
+    /// Apply the hints set since a surface's last commit
+    pub fn commit_presentation_hints(&mut self, surface: SurfaceId) {
+        let hints = &mut self.resources.presentation_hints;
+        if let Some(content) = hints.pending_content_types.remove(&surface) {
+            // Only while the hint object is alive
+            if let Some(current) = hints.content_types.get_mut(&surface) {
+                *current = content;
+            }
+        }
+        if let Some(tearing) = hints.pending_tearing.remove(&surface) {
+            if let Some(current) = hints.tearing.get_mut(&surface) {
+                *current = tearing;
+            }
+        }
+    }
+
+    /// Check if macOS may throttle wayoa with App Nap: no visible window
+    /// shows anything but photos
+    pub fn allows_app_nap(&self) -> bool {
+        let content_types = &self.resources.presentation_hints.content_types;
+        self.compositor
+            .windows
+            .iter()
+            .filter(|(_, window)| !window.state.minimized)
+            .all(|(_, window)| content_types.get(&window.surface_id) == Some(&ContentType::Photo))
+    }
+
+    /// Hand the hints to the scheduler and pick the primary output's refresh
+    /// policy
+    pub(super) fn update_refresh_policy(&mut self) {
+        let windows = &self.compositor.windows;
+        let hints = &self.resources.presentation_hints;
+        for (&surface, &content) in &hints.content_types {
+            if let Some(window) = windows.window_for_surface(surface) {
+                self.scheduler.set_content_type(window, content);
+            }
+        }
+        for (&surface, &tearing) in &hints.tearing {
+            if let Some(window) = windows.window_for_surface(surface) {
+                self.scheduler.set_tearing(window, tearing);
+            }
+        }
+
+        let Some(output) = self.compositor.outputs.primary().map(|output| output.id) else {
+            return;
+        };
+        if !self.scheduler.adaptive_sync(output) {
+            return;
+        }
+        // Windows fullscreen on a headless output aren't on the screen
+        let adaptive = windows.iter().any(|(&id, window)| {
+            window.fullscreen && window.output.is_none() && self.scheduler.wants_adaptive(id)
+        });
+        let policy = match adaptive {
+            true => RefreshPolicy::Adaptive,
+            false => RefreshPolicy::Fixed,
+        };
+        if self.scheduler.set_policy(output, policy) {
+            info!("Output {:?} refresh policy: {:?}", output, policy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::OutputMode;
+
+    #[test]
+    fn test_adaptive_for_fullscreen_games() {
+        let mut state = ServerState::new();
+        let output = state.compositor.outputs.create_output(
+            "screen".to_string(),
+            "Apple".to_string(),
+            "Built-in".to_string(),
+        );
+        state.update_output(output, |output| {
+            output.modes.clear();
+            output.current_mode = None;
+            output.add_mode(OutputMode {
+                width: 3024,
+                height: 1964,
+                refresh: 120_000,
+                current: true,
+                preferred: true,
+            });
+            output.min_refresh = Some(24_000);
+        });
+
+        let surface = SurfaceId(1);
+        let window = state.compositor.windows.create_window(surface);
+        state
+            .resources
+            .presentation_hints
+            .content_types
+            .insert(surface, ContentType::Game);
+        state.update_refresh_policy();
+        assert_eq!(state.scheduler.policy(output), Some(RefreshPolicy::Fixed));
+
+        state.set_window_fullscreen(window, true, None);
+        state.update_refresh_policy();
+        assert_eq!(
+            state.scheduler.policy(output),
+            Some(RefreshPolicy::Adaptive)
+        );
+
+        state.set_window_fullscreen(window, false, None);
+        state.update_refresh_policy();
+        assert_eq!(state.scheduler.policy(output), Some(RefreshPolicy::Fixed));
+    }
+
+    #[test]
+    fn test_app_nap_only_for_photos() {
+        let mut state = ServerState::new();
+        assert!(state.allows_app_nap());
+
+        let (photo, video) = (SurfaceId(1), SurfaceId(2));
+        state.compositor.windows.create_window(photo);
+        let window = state.compositor.windows.create_window(video);
+        let content_types = &mut state.resources.presentation_hints.content_types;
+        content_types.insert(photo, ContentType::Photo);
+        content_types.insert(video, ContentType::Video);
+        assert!(!state.allows_app_nap());
+
+        // A minimized video isn't seen
+        state.set_window_minimized(window, true);
+        assert!(state.allows_app_nap());
+    }
+
+    #[test]
+    fn test_tearing_applied_on_commit() {
+        let mut state = ServerState::new();
+        let surface = SurfaceId(1);
+        let window = state.compositor.windows.create_window(surface);
+        let hints = &mut state.resources.presentation_hints;
+        hints.tearing.insert(surface, false);
+        hints.pending_tearing.insert(surface, true);
+        state.update_refresh_policy();
+        assert!(state.scheduler.layer_options(window).display_sync);
+
+        // The async hint takes effect with the commit
+        state.commit_presentation_hints(surface);
+        state.update_refresh_policy();
+        assert!(!state.scheduler.layer_options(window).display_sync);
+    }
+}
@@ -0,0 +1,101 @@
+//! QuickLook previews of window content
+//!
+//! Previewing exports the focused window's current frame as a PNG in a
+//! temporary directory and opens it in QuickLook, from where it can be
+//! shared or copied without going through screenshot tooling. Each window
+//! has one preview file, overwritten by the next preview.
+
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use super::ServerState;
+use crate::compositor::WindowId;
+use crate::renderer::CaptureError;
+
+/// Preview errors
+#[derive(Debug, thiserror::Error)]
+pub enum PreviewError {
+    #[error("No focused window")]
+    NoFocusedWindow,
+    #[error(transparent)]
+    Capture(#[from] CaptureError),
+    #[error("Failed to write {0}: {1}")]
+    Write(PathBuf, std::io::Error),
+}
+
+/// Directory previews are written to
+fn preview_dir() -> PathBuf {
+    std::env::temp_dir().join("wayoa-previews")
+}
+
+impl ServerState {
+    /// Write a window's current frame as a PNG in `dir`
+    ///
+    /// Returns the path of the file.
+    pub fn export_preview(
+        &mut self,
+        window: WindowId,
+        dir: &Path,
+    ) -> Result<PathBuf, PreviewError> {
+        let png = self.capture_window(window, false)?.to_png()?;
+        let path = dir.join(format!("window-{}.png", window.0));
+        std::fs::create_dir_all(dir)
+            .and_then(|()| std::fs::write(&path, png))
+            .map_err(|e| PreviewError::Write(path.clone(), e))?;
+        Ok(path)
+    }
+
+    /// Export the focused window's frame and open it in QuickLook
+    pub fn preview_focused_window(&mut self) -> Result<PathBuf, PreviewError> {
+        let window = self
+            .compositor
+            .windows
+            .focused()
+            .map(|window| window.id)
+            .ok_or(PreviewError::NoFocusedWindow)?;
+        let path = self.export_preview(window, &preview_dir())?;
+        info!("Previewing window {:?} from {}", window, path.display());
+        #[cfg(target_os = "macos")]
+        crate::backend::cocoa::app::quick_look(&path);
+        Ok(path)
+    }
+
+    /// Preview the focused window if a key press is the preview binding
+    ///
+    /// Returns true if the key was taken, in which case it must not reach
+    /// clients.
+    pub fn preview_key(&mut self, key: u32, modifiers: u32) -> bool {
+        let bound = self
+            .preview_binding
+            .is_some_and(|binding| binding.matches(key, modifiers));
+        if bound {
+            self.perform_action(crate::compositor::CompositorAction::PreviewWindow);
+        }
+        bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::SurfaceId;
+
+    #[test]
+    fn test_export_preview() {
+        let mut state = ServerState::new();
+        assert!(matches!(
+            state.preview_focused_window(),
+            Err(PreviewError::NoFocusedWindow)
+        ));
+
+        // A window without content has nothing to export
+        let window = state.compositor.windows.create_window(SurfaceId(1));
+        let dir = std::env::temp_dir().join(format!("wayoa-preview-test-{}", std::process::id()));
+        assert!(matches!(
+            state.export_preview(window, &dir),
+            Err(PreviewError::Capture(CaptureError::NoBuffer(_)))
+        ));
+        assert!(!state.preview_key(25, 0));
+    }
+}
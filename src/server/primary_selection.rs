@@ -0,0 +1,300 @@
+//! zwp_primary_selection_device_manager_v1 objects
+//!
+//! Sources and offers are tracked by the [`DataDeviceHandler`] in
+//! [`crate::protocol::data_device`]. The primary selection is offered to
+//! the client with pointer focus, since it is pasted with the middle
+//! button: whenever pointer focus moves to another client, when the
+//! selection changes and when a client creates a device while it has focus.
+//!
+//! [`DataDeviceHandler`]: crate::protocol::DataDeviceHandler
+
+use std::collections::HashMap;
+use std::os::fd::AsFd;
+
+use log::debug;
+use wayland_protocols::wp::primary_selection::zv1::server::{
+    zwp_primary_selection_device_manager_v1, zwp_primary_selection_device_v1,
+    zwp_primary_selection_offer_v1, zwp_primary_selection_source_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::protocol::data_device::{DataOfferId, DataSourceId};
+
+/// Primary selection objects of all clients
+#[derive(Debug, Default)]
+pub struct PrimarySelectionResources {
+    /// Devices, one or more per client
+    devices: Vec<zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1>,
+    /// Sources by ID
+    sources: HashMap<DataSourceId, zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1>,
+    /// Client the selection was last offered to
+    focus: Option<ClientId>,
+}
+
+impl PrimarySelectionResources {
+    /// Get the object of a primary selection source
+    pub fn source(
+        &self,
+        id: DataSourceId,
+    ) -> Option<&zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1> {
+        self.sources.get(&id)
+    }
+}
+
+impl
+    GlobalDispatch<
+        zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+        GlobalData,
+    > for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_primary_selection_device_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1, ()>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+        request: zwp_primary_selection_device_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            zwp_primary_selection_device_manager_v1::Request::CreateSource { id } => {
+                let source_id = state.data_device.create_data_source();
+                let source = data_init.init(id, source_id);
+                state
+                    .resources
+                    .primary_selection
+                    .sources
+                    .insert(source_id, source);
+            }
+            zwp_primary_selection_device_manager_v1::Request::GetDevice { id, seat: _ } => {
+                debug!("Creating primary selection device");
+                let device = data_init.init(id, ());
+                state.resources.primary_selection.devices.push(device);
+                if state.has_pointer_focus(client) {
+                    state.offer_primary_selection(client);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+        request: zwp_primary_selection_device_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_primary_selection_device_v1::Request::SetSelection { source, serial } = request {
+            // Only the client the user is interacting with may select
+            let keyboard_focus = state
+                .compositor
+                .seat
+                .keyboard()
+                .focus()
+                .and_then(|focus| state.resources.surfaces.get(&focus))
+                .and_then(|surface| surface.client());
+            if !state.has_pointer_focus(client) && keyboard_focus.as_ref() != Some(client) {
+                debug!("Ignoring primary selection from a client without focus");
+                return;
+            }
+
+            let source_id = source.and_then(|source| source.data::<DataSourceId>().copied());
+            debug!("Primary selection set with serial {}", serial);
+            state.replace_primary_selection(source_id);
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+        _data: &(),
+    ) {
+        state
+            .resources
+            .primary_selection
+            .devices
+            .retain(|device| device != resource);
+    }
+}
+
+impl Dispatch<zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1, DataSourceId>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+        request: zwp_primary_selection_source_v1::Request,
+        data: &DataSourceId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_primary_selection_source_v1::Request::Offer { mime_type } = request {
+            if let Some(source) = state.data_device.get_source_mut(*data) {
+                source.offer(mime_type);
+            }
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+        data: &DataSourceId,
+    ) {
+        let selected = state
+            .data_device
+            .primary_selection()
+            .map(|source| source.id)
+            == Some(*data);
+        state.data_device.destroy_source(*data);
+        state.resources.primary_selection.sources.remove(data);
+        if selected {
+            state.reoffer_primary_selection();
+        }
+    }
+}
+
+impl Dispatch<zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1, DataOfferId>
+    for ServerState
+{
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+        request: zwp_primary_selection_offer_v1::Request,
+        data: &DataOfferId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_primary_selection_offer_v1::Request::Receive { mime_type, fd } = request {
+            let source = state.data_device.get_offer(*data).map(|o| o.source_id);
+            if !source.is_some_and(|source| state.send_source(source, mime_type, fd.as_fd())) {
+                debug!("Primary selection offer {:?} has no source", data);
+            }
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+        data: &DataOfferId,
+    ) {
+        state.data_device.destroy_offer(*data);
+    }
+}
+
+impl ServerState {
+    /// Offer the primary selection when pointer focus moves to another
+    /// client
+    pub(super) fn update_primary_selection_focus(&mut self) {
+        let client = self.pointer_focus_client();
+        let id = client.as_ref().map(|client| client.id());
+        if id == self.resources.primary_selection.focus {
+            return;
+        }
+        self.resources.primary_selection.focus = id;
+        if let Some(client) = client {
+            self.offer_primary_selection(&client);
+        }
+    }
+
+    /// Set the primary selection, cancelling the source it replaces
+    pub(super) fn replace_primary_selection(&mut self, source_id: Option<DataSourceId>) {
+        if let Some(old) = self.data_device.set_primary_selection(source_id, 0) {
+            self.cancel_source(old);
+        }
+        self.reoffer_primary_selection();
+    }
+
+    /// Offer the changed primary selection to the client with pointer focus
+    /// and to clipboard managers
+    pub(super) fn reoffer_primary_selection(&mut self) {
+        self.resources.primary_selection.focus = None;
+        self.update_primary_selection_focus();
+        self.broadcast_data_control_primary_selection();
+    }
+
+    /// Get the client owning the surface with pointer focus
+    fn pointer_focus_client(&self) -> Option<Client> {
+        self.compositor
+            .seat
+            .pointer()
+            .focus()
+            .and_then(|focus| self.resources.surfaces.get(&focus))
+            .and_then(|surface| surface.client())
+    }
+
+    /// Send a new offer of the primary selection to a client's devices
+    fn offer_primary_selection(&mut self, client: &Client) {
+        let devices: Vec<_> = self
+            .resources
+            .primary_selection
+            .devices
+            .iter()
+            .filter(|device| device.client().is_some_and(|c| c.id() == client.id()))
+            .cloned()
+            .collect();
+        let source_id = self.data_device.primary_selection().map(|source| source.id);
+        for device in devices {
+            let Some(offer_id) = source_id.and_then(|id| self.data_device.create_offer(id)) else {
+                device.selection(None);
+                continue;
+            };
+            let Some(dh) = device.handle().upgrade().map(DisplayHandle::from) else {
+                self.data_device.destroy_offer(offer_id);
+                continue;
+            };
+            let offer = client
+                .create_resource::<zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1, _, Self>(
+                    &dh,
+                    device.version(),
+                    offer_id,
+                );
+            let Ok(offer) = offer else {
+                self.data_device.destroy_offer(offer_id);
+                continue;
+            };
+            device.data_offer(&offer);
+            for mime_type in self
+                .data_device
+                .get_offer(offer_id)
+                .map(|offer| offer.mime_types.clone())
+                .unwrap_or_default()
+            {
+                offer.offer(mime_type);
+            }
+            device.selection(Some(&offer));
+        }
+    }
+}
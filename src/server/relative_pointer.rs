@@ -0,0 +1,88 @@
+//! zwp_relative_pointer_manager_v1 objects
+//!
+//! Relative pointers report how far the pointer moved rather than where it
+//! is, and keep reporting when the cursor is held at a screen edge. Games
+//! and 3D viewports use them for mouse look. Motion goes to the relative
+//! pointers of the client with pointer focus.
+
+use log::debug;
+use wayland_protocols::wp::relative_pointer::zv1::server::{
+    zwp_relative_pointer_manager_v1, zwp_relative_pointer_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+
+impl GlobalDispatch<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_relative_pointer_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_relative_pointer_manager_v1::ZwpRelativePointerManagerV1,
+        request: zwp_relative_pointer_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_relative_pointer_manager_v1::Request::GetRelativePointer { id, pointer } =
+            request
+        {
+            let relative_pointer = data_init.init(id, ());
+            if state
+                .resources
+                .seats
+                .add_relative_pointer(relative_pointer, pointer)
+            {
+                debug!("Creating relative pointer");
+            } else {
+                debug!("Creating inert relative pointer");
+            }
+        }
+    }
+}
+
+impl Dispatch<zwp_relative_pointer_v1::ZwpRelativePointerV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        request: zwp_relative_pointer_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_relative_pointer_v1::Request::Destroy = request {
+            debug!("Relative pointer destroy");
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        _data: &(),
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
@@ -0,0 +1,105 @@
+//! Wayland objects of all clients
+//!
+//! The protocol layer's bookkeeping: the objects events are sent through,
+//! keyed by the compositor objects they stand for. The compositor core
+//! never sees these. Dispatch code turns requests into calls on
+//! [`CompositorState`] and the handlers in [`crate::protocol`], and sends
+//! the resulting events through the objects kept here.
+//!
+//! [`CompositorState`]: crate::compositor::CompositorState
+
+use std::collections::{HashMap, HashSet};
+
+use wayland_protocols::wp::text_input::zv3::server::zwp_text_input_v3;
+use wayland_protocols::wp::viewporter::server::wp_viewport;
+use wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1;
+use wayland_protocols::xdg::shell::server::xdg_toplevel;
+use wayland_protocols_wlr::layer_shell::v1::server::zwlr_layer_surface_v1;
+use wayland_server::backend::GlobalId;
+use wayland_server::protocol::{wl_data_source, wl_surface};
+
+use super::idle_notify::IdleNotifications;
+use super::{
+    BufferReleases, DataControlResources, DataDeviceResources, ForeignToplevels,
+    InputMethodResources, OutputHeads, OutputPowers, OutputResources, PingResources,
+    PointerConstraints, PresentationHints, PresentationQueue, PrimarySelectionResources,
+    SeatResources, SharedKeymap, ShortcutInhibitors, SurfaceScale, TabletResources, ToplevelLists,
+    VirtualKeyboards,
+};
+use crate::compositor::{SurfaceId, WindowIcon, WindowId};
+use crate::protocol::data_device::DataSourceId;
+use crate::protocol::layer_shell::LayerSurfaceId;
+use crate::protocol::text_input::TextInputId;
+
+/// Wayland objects of all clients
+#[derive(Debug, Default)]
+pub struct ProtocolResources {
+    /// wl_surface objects by surface ID
+    pub surfaces: HashMap<SurfaceId, wl_surface::WlSurface>,
+    /// Bound seats and input devices
+    pub seats: SeatResources,
+    /// The seat's keymap in shared memory
+    pub keymap: Option<SharedKeymap>,
+    /// Bound outputs
+    pub outputs: OutputResources,
+    /// Output managers and their heads
+    pub output_heads: OutputHeads,
+    /// wp_viewport objects by surface
+    pub viewports: HashMap<SurfaceId, wp_viewport::WpViewport>,
+    /// wp_fractional_scale objects by surface
+    pub fractional_scales: HashMap<SurfaceId, SurfaceScale>,
+    /// wp_presentation_feedback objects waiting for a present
+    pub presentation: PresentationQueue,
+    /// Content types and tearing hints by surface
+    pub presentation_hints: PresentationHints,
+    /// shm buffers to release, and snapshots of committed buffers
+    pub buffers: BufferReleases,
+    /// Surfaces with a wp_fifo_v1 object
+    pub fifos: HashSet<SurfaceId>,
+    /// Surfaces with a wp_commit_timer_v1 object
+    pub commit_timers: HashSet<SurfaceId>,
+    /// Surfaces with a wp_alpha_modifier_surface_v1 object
+    pub alpha_modifiers: HashSet<SurfaceId>,
+    /// Pointer locks and confinements
+    pub pointer_constraints: PointerConstraints,
+    /// wl_data_source objects by source ID
+    pub data_sources: HashMap<DataSourceId, wl_data_source::WlDataSource>,
+    /// Data devices and the client the selection was offered to
+    pub data_devices: DataDeviceResources,
+    /// Data control devices and sources of clipboard managers
+    pub data_controls: DataControlResources,
+    /// Primary selection devices, sources and focus
+    pub primary_selection: PrimarySelectionResources,
+    /// xdg_toplevel objects by window
+    pub toplevels: HashMap<WindowId, xdg_toplevel::XdgToplevel>,
+    /// Foreign toplevel managers and their handles
+    pub foreign_toplevels: ForeignToplevels,
+    /// Toplevel lists and their handles
+    pub toplevel_lists: ToplevelLists,
+    /// Keymaps replaced by virtual keyboards
+    pub virtual_keyboards: VirtualKeyboards,
+    /// Windows with an xdg_dialog_v1 object
+    pub dialogs: HashSet<WindowId>,
+    /// Toplevel icons set since the toplevel's last commit, by surface
+    pub pending_icons: HashMap<SurfaceId, Option<WindowIcon>>,
+    /// zxdg_toplevel_decoration_v1 objects by window
+    pub decorations: HashMap<WindowId, zxdg_toplevel_decoration_v1::ZxdgToplevelDecorationV1>,
+    /// zwp_keyboard_shortcuts_inhibitor_v1 objects
+    pub shortcut_inhibitors: ShortcutInhibitors,
+    /// ext_idle_notification_v1 objects
+    pub idle_notifications: IdleNotifications,
+    /// Client pings, and windows dimmed while their client doesn't answer
+    pub pings: PingResources,
+    /// zwp_text_input_v3 objects by text input
+    pub text_inputs: HashMap<TextInputId, zwp_text_input_v3::ZwpTextInputV3>,
+    /// Tablet seats, their tablet and tool objects
+    pub tablets: TabletResources,
+    /// The seat's zwp_input_method_v2 object, its keyboard grab and popups
+    pub input_method: InputMethodResources,
+    /// zwlr_output_power_v1 objects by output
+    pub output_powers: OutputPowers,
+    /// zwlr_layer_surface_v1 objects by layer surface
+    pub layer_surfaces: HashMap<LayerSurfaceId, zwlr_layer_surface_v1::ZwlrLayerSurfaceV1>,
+    /// Version-capped copies of globals, created for client quirks
+    pub capped_globals: HashMap<(&'static str, u32), GlobalId>,
+}
@@ -0,0 +1,280 @@
+//! zwlr_screencopy_manager_v1 objects
+//!
+//! Frames are tracked by the [`ScreencopyHandler`]. Headless outputs are
+//! rendered offscreen the same way remote viewers see them, and the
+//! requested part is copied into the client's wl_shm buffer as ARGB8888.
+//! Screens are composited by the window server, so capturing them fails.
+//! Copies happen as soon as they're requested: copy_with_damage doesn't
+//! wait for the output to change, and reports the whole frame as damaged.
+//!
+//! [`ScreencopyHandler`]: crate::protocol::ScreencopyHandler
+
+use log::{debug, warn};
+use wayland_protocols_wlr::screencopy::v1::server::{
+    zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1,
+};
+use wayland_server::protocol::{wl_buffer, wl_shm};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::{OutputData, ServerState};
+use crate::protocol::screencopy::{CaptureRegion, FrameFlags, ScreencopyFrameId};
+use crate::protocol::shm::{ShmBufferId, ShmFormat};
+use crate::renderer::Capture;
+use crate::util::clock;
+
+impl GlobalDispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwlr_screencopy_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_screencopy_manager_v1::Request;
+
+        let (frame, overlay_cursor, output, region) = match request {
+            Request::CaptureOutput {
+                frame,
+                overlay_cursor,
+                output,
+            } => (frame, overlay_cursor, output, None),
+            Request::CaptureOutputRegion {
+                frame,
+                overlay_cursor,
+                output,
+                x,
+                y,
+                width,
+                height,
+            } => (frame, overlay_cursor, output, Some((x, y, width, height))),
+            _ => return,
+        };
+        let Some(output_id) = output.data::<OutputData>().map(|data| data.output_id) else {
+            return;
+        };
+        let id = state
+            .screencopy
+            .capture_output(output_id, overlay_cursor != 0);
+        let frame = data_init.init(frame, id);
+        state.describe_screencopy_frame(&frame, id, region);
+    }
+}
+
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ScreencopyFrameId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        data: &ScreencopyFrameId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        use zwlr_screencopy_frame_v1::Request;
+
+        let (buffer, with_damage) = match request {
+            Request::Copy { buffer } => (buffer, false),
+            Request::CopyWithDamage { buffer } => (buffer, true),
+            _ => return,
+        };
+        state.copy_screencopy_frame(resource, *data, &buffer, with_damage);
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        _resource: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        data: &ScreencopyFrameId,
+    ) {
+        state.screencopy.destroy(*data);
+    }
+}
+
+impl ServerState {
+    /// Tell a client the buffer a frame needs, or that it can't be captured
+    ///
+    /// Regions are in the output's logical coordinates and are clipped to
+    /// the output.
+    fn describe_screencopy_frame(
+        &mut self,
+        frame: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        id: ScreencopyFrameId,
+        region: Option<(i32, i32, i32, i32)>,
+    ) {
+        let Some(screencopy) = self.screencopy.get_mut(id) else {
+            return;
+        };
+        let Some(output) = self
+            .compositor
+            .outputs
+            .get(screencopy.output)
+            .filter(|output| output.headless && output.enabled)
+        else {
+            debug!("Can't capture output {:?}", screencopy.output);
+            screencopy.fail();
+            frame.failed();
+            return;
+        };
+
+        let (width, height) = (output.width() as i32, output.height() as i32);
+        let scale = output.scale.max(1);
+        let (x, y, w, h) = region.unwrap_or((0, 0, width / scale, height / scale));
+        let x0 = x.saturating_mul(scale).clamp(0, width);
+        let y0 = y.saturating_mul(scale).clamp(0, height);
+        let x1 = x.saturating_add(w).saturating_mul(scale).clamp(0, width);
+        let y1 = y.saturating_add(h).saturating_mul(scale).clamp(0, height);
+        if x0 >= x1 || y0 >= y1 {
+            screencopy.fail();
+            frame.failed();
+            return;
+        }
+        screencopy.set_region(x0, y0, x1 - x0, y1 - y0);
+
+        let (width, height) = ((x1 - x0) as u32, (y1 - y0) as u32);
+        let format = ShmFormat::Argb8888;
+        let stride = width * format.bytes_per_pixel();
+        screencopy.set_buffer_info(format.to_wayland(), width, height, stride);
+        frame.buffer(wl_shm::Format::Argb8888, width, height, stride);
+        if frame.version() >= 3 {
+            frame.buffer_done();
+        }
+    }
+
+    /// Render a frame's output and copy it into a client buffer
+    fn copy_screencopy_frame(
+        &mut self,
+        frame: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        id: ScreencopyFrameId,
+        buffer: &wl_buffer::WlBuffer,
+        with_damage: bool,
+    ) {
+        use zwlr_screencopy_frame_v1::Error;
+
+        let Some(screencopy) = self.screencopy.get_mut(id) else {
+            return;
+        };
+        let (Some(info), Some(region)) = (screencopy.buffer_info.clone(), screencopy.region) else {
+            // The frame already failed
+            return;
+        };
+        if screencopy.buffer.is_some() {
+            frame.post_error(Error::AlreadyUsed, "The frame was already copied");
+            return;
+        }
+        let Some(&buffer_id) = buffer.data::<ShmBufferId>() else {
+            frame.post_error(Error::InvalidBuffer, "Only wl_shm buffers can be copied to");
+            return;
+        };
+        let matches = self.shm.get_buffer(buffer_id).is_some_and(|shm| {
+            shm.format.to_wayland() == info.format
+                && shm.width == info.width
+                && shm.height == info.height
+                && shm.stride >= info.stride
+        });
+        if !matches {
+            frame.post_error(
+                Error::InvalidBuffer,
+                "The buffer doesn't match the advertised parameters",
+            );
+            return;
+        }
+        screencopy.copy(buffer_id);
+        let (output, overlay_cursor) = (screencopy.output, screencopy.overlay_cursor);
+
+        let copied = self
+            .capture_output(output, overlay_cursor)
+            .map_err(|e| e.to_string())
+            .and_then(|capture| {
+                let stride = self.shm.get_buffer(buffer_id).map_or(0, |shm| shm.stride);
+                let pixels = crop(&capture, region, stride);
+                self.shm
+                    .write_buffer_data(buffer_id, &pixels)
+                    .map_err(|e| e.to_string())
+            });
+        let Some(screencopy) = self.screencopy.get_mut(id) else {
+            return;
+        };
+        if let Err(e) = copied {
+            warn!("Failed to copy screencopy frame: {}", e);
+            screencopy.fail();
+            frame.failed();
+            return;
+        }
+
+        let time = clock::now();
+        let done = screencopy.done(
+            FrameFlags::empty(),
+            time.as_secs() as u32,
+            time.subsec_nanos(),
+        );
+        frame.flags(zwlr_screencopy_frame_v1::Flags::from_bits_truncate(
+            done.flags.bits(),
+        ));
+        if with_damage {
+            frame.damage(0, 0, info.width, info.height);
+        }
+        let secs = time.as_secs();
+        frame.ready((secs >> 32) as u32, secs as u32, done.tv_nsec);
+    }
+}
+
+/// Copy a region of a capture into rows of the given stride
+fn crop(capture: &Capture, region: CaptureRegion, stride: u32) -> Vec<u8> {
+    let stride = stride as usize;
+    let row_len = region.width as usize * 4;
+    let mut pixels = vec![0; stride * region.height as usize];
+    for (row, dst) in pixels.chunks_exact_mut(stride).enumerate() {
+        let y = region.y as usize + row;
+        let start = y * capture.stride() as usize + region.x as usize * 4;
+        dst[..row_len].copy_from_slice(&capture.data[start..start + row_len]);
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop() {
+        let mut capture = Capture::new(4, 3);
+        for (i, pixel) in capture.data.chunks_exact_mut(4).enumerate() {
+            pixel.fill(i as u8);
+        }
+        let region = CaptureRegion {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 2,
+        };
+        // Rows are padded to the buffer's stride
+        let pixels = crop(&capture, region, 12);
+        assert_eq!(pixels.len(), 24);
+        assert_eq!(&pixels[..8], &[5, 5, 5, 5, 6, 6, 6, 6]);
+        assert_eq!(&pixels[8..12], &[0; 4]);
+        assert_eq!(&pixels[12..20], &[9, 9, 9, 9, 10, 10, 10, 10]);
+    }
+}
@@ -0,0 +1,721 @@
+//! wl_seat and input device objects
+//!
+//! Tracks bound seats and the input device objects created from them, so
+//! capability changes reach already-bound seats. Device objects requested
+//! for a capability the seat lacks are created inert: they are valid
+//! protocol objects but never receive events. Live objects of a capability
+//! that goes away become inert the same way.
+
+use std::time::Instant;
+
+use log::debug;
+use wayland_protocols::wp::relative_pointer::zv1::server::zwp_relative_pointer_v1;
+use wayland_server::backend::{ClientId, ObjectId};
+use wayland_server::protocol::{wl_keyboard, wl_pointer, wl_seat, wl_touch};
+use wayland_server::{Client, DataInit, Dispatch, Resource};
+
+use super::pointer_gestures::GestureResource;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::input::keyboard::{keys_to_array, ModifierState};
+use crate::input::seat::SeatCapabilities;
+use crate::protocol::seat::{AxisType, ButtonState, KeyboardEvent, PointerEvent, RelativeMotion};
+
+/// Seat user data
+#[derive(Debug, Default)]
+pub struct SeatData {}
+
+/// Convert seat capabilities to the protocol bitfield
+pub fn wayland_capabilities(capabilities: SeatCapabilities) -> wl_seat::Capability {
+    wl_seat::Capability::from_bits_truncate(capabilities.to_wayland())
+}
+
+/// Live seat and input device resources
+#[derive(Debug, Default)]
+pub struct SeatResources {
+    /// Bound wl_seat objects
+    seats: Vec<wl_seat::WlSeat>,
+    /// Live (non-inert) pointers
+    pointers: Vec<wl_pointer::WlPointer>,
+    /// Live (non-inert) keyboards
+    keyboards: Vec<wl_keyboard::WlKeyboard>,
+    /// Live (non-inert) touch objects
+    touches: Vec<wl_touch::WlTouch>,
+    /// Relative pointers of live pointers, with their pointer
+    relative_pointers: Vec<(
+        zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        wl_pointer::WlPointer,
+    )>,
+    /// Gesture objects of live pointers, with their pointer
+    pub(super) gestures: Vec<(GestureResource, wl_pointer::WlPointer)>,
+}
+
+impl SeatResources {
+    /// Track a bound seat
+    pub fn add_seat(&mut self, seat: wl_seat::WlSeat) {
+        self.seats.push(seat);
+    }
+
+    /// Stop tracking a destroyed object
+    pub fn remove(&mut self, id: &ObjectId) {
+        self.seats.retain(|r| r.id() != *id);
+        self.pointers.retain(|r| r.id() != *id);
+        self.keyboards.retain(|r| r.id() != *id);
+        self.touches.retain(|r| r.id() != *id);
+        self.relative_pointers
+            .retain(|(r, pointer)| r.id() != *id && pointer.id() != *id);
+        self.gestures
+            .retain(|(r, pointer)| r.id() != *id && pointer.id() != *id);
+    }
+
+    /// Track a relative pointer, unless its pointer is inert
+    pub fn add_relative_pointer(
+        &mut self,
+        relative_pointer: zwp_relative_pointer_v1::ZwpRelativePointerV1,
+        pointer: wl_pointer::WlPointer,
+    ) -> bool {
+        if !self.pointers.contains(&pointer) {
+            return false;
+        }
+        self.relative_pointers.push((relative_pointer, pointer));
+        true
+    }
+
+    /// Track a gesture object, unless its pointer is inert
+    pub fn add_gesture(
+        &mut self,
+        gesture: GestureResource,
+        pointer: wl_pointer::WlPointer,
+    ) -> bool {
+        if !self.pointers.contains(&pointer) {
+            return false;
+        }
+        self.gestures.push((gesture, pointer));
+        true
+    }
+
+    /// Get the live pointers
+    pub fn pointers(&self) -> &[wl_pointer::WlPointer] {
+        &self.pointers
+    }
+
+    /// Get the live keyboards
+    pub fn keyboards(&self) -> &[wl_keyboard::WlKeyboard] {
+        &self.keyboards
+    }
+
+    /// Get the live touch objects
+    pub fn touches(&self) -> &[wl_touch::WlTouch] {
+        &self.touches
+    }
+
+    /// Get the number of bound seats
+    pub fn seat_count(&self) -> usize {
+        self.seats.len()
+    }
+
+    /// Broadcast new capabilities to all bound seats
+    ///
+    /// Device objects of removed capabilities become inert.
+    pub fn set_capabilities(&mut self, capabilities: SeatCapabilities) {
+        let flags = wayland_capabilities(capabilities);
+        for seat in &self.seats {
+            seat.capabilities(flags);
+        }
+        if !capabilities.pointer {
+            self.pointers.clear();
+            self.relative_pointers.clear();
+            self.gestures.clear();
+        }
+        if !capabilities.keyboard {
+            self.keyboards.clear();
+        }
+        if !capabilities.touch {
+            self.touches.clear();
+        }
+    }
+}
+
+impl ServerState {
+    /// Change the seat capabilities and notify bound seats
+    pub fn set_seat_capabilities(&mut self, capabilities: SeatCapabilities) {
+        debug!("Seat capabilities changed: {:?}", capabilities);
+        self.compositor.seat.set_capabilities(capabilities);
+        self.resources.seats.set_capabilities(capabilities);
+    }
+
+    /// Move keyboard focus to a surface
+    ///
+    /// The old surface gets leave, the new one gets enter carrying the keys
+    /// still held down, followed by the current modifiers. Focus meant for a
+    /// window blocked by a modal dialog goes to the dialog.
+    pub fn set_keyboard_focus(&mut self, surface: Option<SurfaceId>) {
+        let surface = surface.map(|surface| {
+            self.blocking_dialog(surface)
+                .and_then(|dialog| self.compositor.windows.get(dialog))
+                .map_or(surface, |dialog| dialog.surface_id)
+        });
+        let change = self.compositor.seat.keyboard_mut().set_focus(surface);
+        for event in change.events() {
+            let target = match &event {
+                KeyboardEvent::Leave { surface } | KeyboardEvent::Enter { surface, .. } => *surface,
+                _ => match change.new_focus {
+                    Some(surface) => surface,
+                    None => continue,
+                },
+            };
+            let Some(wl_surface) = self.resources.surfaces.get(&target) else {
+                continue;
+            };
+
+            let serial = self.compositor.next_serial();
+            for keyboard in self
+                .resources
+                .seats
+                .keyboards()
+                .iter()
+                .filter(|k| k.id().same_client_as(&wl_surface.id()))
+            {
+                match &event {
+                    KeyboardEvent::Leave { .. } => keyboard.leave(serial, wl_surface),
+                    KeyboardEvent::Enter { pressed_keys, .. } => {
+                        keyboard.enter(serial, wl_surface, keys_to_array(pressed_keys))
+                    }
+                    KeyboardEvent::Modifiers {
+                        depressed,
+                        latched,
+                        locked,
+                        group,
+                    } => keyboard.modifiers(serial, *depressed, *latched, *locked, *group),
+                    KeyboardEvent::Key { .. } => {}
+                }
+            }
+        }
+        self.update_text_input_focus();
+        self.update_pointer_constraints();
+        self.update_selection_focus();
+    }
+}
+
+impl ServerState {
+    /// Check if a client owns the surface with pointer focus
+    pub fn has_pointer_focus(&self, client: &Client) -> bool {
+        self.compositor
+            .seat
+            .pointer()
+            .focus()
+            .and_then(|focus| self.resources.surfaces.get(&focus))
+            .and_then(|surface| surface.client())
+            .is_some_and(|owner| owner.id() == client.id())
+    }
+
+    /// Route pointer motion to clients, honoring implicit grabs
+    ///
+    /// A locked pointer doesn't move, so motion is dropped while a lock is
+    /// in effect.
+    pub fn pointer_motion(
+        &mut self,
+        time: u32,
+        under: Option<SurfaceId>,
+        locate: impl Fn(SurfaceId) -> (f64, f64),
+    ) {
+        self.input_activity();
+        if self.resources.pointer_constraints.is_locked() {
+            return;
+        }
+        let events = self
+            .compositor
+            .seat
+            .pointer_mut()
+            .handle_motion(time, under, locate);
+        self.send_pointer_events(events);
+        self.update_pointer_constraints();
+    }
+
+    /// Route a pointer button to clients, starting or ending implicit grabs
+    ///
+    /// Returns the click count of a press (2 for a double-click) using the
+    /// seat's click thresholds, or 0 for a release. A press on a window
+    /// blocked by a modal dialog isn't sent, it activates the dialog.
+    pub fn pointer_button(
+        &mut self,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        under: Option<SurfaceId>,
+        locate: impl Fn(SurfaceId) -> (f64, f64),
+    ) -> u32 {
+        self.input_activity();
+        if state == ButtonState::Pressed {
+            if let Some(dialog) = under.and_then(|surface| self.blocking_dialog(surface)) {
+                self.activate_window(dialog);
+                return 0;
+            }
+        }
+        let click_count = match state {
+            ButtonState::Pressed => {
+                let position = self.compositor.seat.pointer().position();
+                self.compositor
+                    .seat
+                    .clicks_mut()
+                    .press(button, position, Instant::now())
+            }
+            ButtonState::Released => 0,
+        };
+
+        let serial = self.compositor.next_serial();
+        let events = self
+            .compositor
+            .seat
+            .pointer_mut()
+            .handle_button(time, serial, button, state, under, locate);
+        // The button event carries the serial its implicit grab started with
+        self.send_pointer_events_with(events, Some(serial));
+        self.update_pointer_constraints();
+        click_count
+    }
+
+    /// Send the axis events of one scroll to the client with pointer focus
+    pub fn pointer_axis(&mut self, events: Vec<PointerEvent>) {
+        self.input_activity();
+        let events = self.compositor.seat.pointer().handle_axis(events);
+        self.send_pointer_events(events);
+    }
+
+    /// Send relative motion to the client with pointer focus
+    ///
+    /// Pointers of version 5 and up get a frame after it.
+    pub fn pointer_relative_motion(&mut self, motion: RelativeMotion) {
+        self.input_activity();
+        let Some(wl_surface) = self
+            .compositor
+            .seat
+            .pointer()
+            .focus()
+            .and_then(|focus| self.resources.surfaces.get(&focus))
+        else {
+            return;
+        };
+        for (relative_pointer, pointer) in self
+            .resources
+            .seats
+            .relative_pointers
+            .iter()
+            .filter(|(r, _)| r.id().same_client_as(&wl_surface.id()))
+        {
+            relative_pointer.relative_motion(
+                (motion.utime >> 32) as u32,
+                motion.utime as u32,
+                motion.dx,
+                motion.dy,
+                motion.dx_unaccel,
+                motion.dy_unaccel,
+            );
+            if pointer.version() >= 5 {
+                pointer.frame();
+            }
+        }
+    }
+
+    /// Send a key press or release to the client with keyboard focus
+    ///
+    /// Repeated presses of a key that is already down are dropped. Keys go
+    /// to the input method instead while it is active and grabs the
+    /// keyboard.
+    ///
+    /// Sticky modifiers that change with the key are sent after it.
+    pub fn keyboard_key(&mut self, time: u32, key: u32, pressed: bool) {
+        self.restore_hardware_keymap();
+        self.seat_key(time, key, pressed);
+    }
+
+    /// Handle a key of the seat's keyboard, typed on hardware or injected
+    /// by a virtual keyboard
+    pub(super) fn seat_key(&mut self, time: u32, key: u32, pressed: bool) {
+        self.input_activity();
+        let keyboard = self.compositor.seat.keyboard_mut();
+        let changed = match pressed {
+            true => keyboard.key_press(key),
+            false => keyboard.key_release(key),
+        };
+        if !changed {
+            return;
+        }
+        self.send_key(time, key, pressed);
+        if self.compositor.seat.keyboard_mut().sticky_key(key, pressed) {
+            self.send_modifiers();
+        }
+    }
+
+    /// Update the modifiers reported by the system and send them to the
+    /// client with keyboard focus if they changed
+    pub fn keyboard_modifiers(&mut self, reported: ModifierState) {
+        if self
+            .compositor
+            .seat
+            .keyboard_mut()
+            .set_reported_modifiers(reported)
+        {
+            self.send_modifiers();
+        }
+    }
+
+    /// Follow a key that the system handles itself, with the modifiers it
+    /// reports after the key, so sticky modifiers see it too
+    pub fn keyboard_system_key(&mut self, key: u32, pressed: bool, reported: ModifierState) {
+        let keyboard = self.compositor.seat.keyboard_mut();
+        let before = keyboard.modifiers();
+        keyboard.set_reported_modifiers(reported);
+        keyboard.sticky_key(key, pressed);
+        if keyboard.modifiers() != before {
+            self.send_modifiers();
+        }
+    }
+
+    /// Send the current modifiers to the client with keyboard focus, or to
+    /// the input method while it grabs the keyboard
+    fn send_modifiers(&mut self) {
+        let modifiers = self.compositor.seat.keyboard().modifiers();
+        let serial = self.compositor.next_serial();
+        if let Some(grab) = self
+            .resources
+            .input_method
+            .grab
+            .as_ref()
+            .filter(|_| self.input_method.is_active())
+        {
+            grab.modifiers(
+                serial,
+                modifiers.depressed,
+                modifiers.latched,
+                modifiers.locked,
+                modifiers.group,
+            );
+            return;
+        }
+        let Some(wl_surface) = self
+            .compositor
+            .seat
+            .keyboard()
+            .focus()
+            .and_then(|focus| self.resources.surfaces.get(&focus))
+        else {
+            return;
+        };
+        for keyboard in self
+            .resources
+            .seats
+            .keyboards()
+            .iter()
+            .filter(|k| k.id().same_client_as(&wl_surface.id()))
+        {
+            keyboard.modifiers(
+                serial,
+                modifiers.depressed,
+                modifiers.latched,
+                modifiers.locked,
+                modifiers.group,
+            );
+        }
+    }
+
+    /// Send a key to the input method or the client with keyboard focus
+    fn send_key(&mut self, time: u32, key: u32, pressed: bool) {
+        if self.input_method_key(time, key, pressed) {
+            return;
+        }
+        let Some(wl_surface) = self
+            .compositor
+            .seat
+            .keyboard()
+            .focus()
+            .and_then(|focus| self.resources.surfaces.get(&focus))
+        else {
+            return;
+        };
+
+        let serial = self.compositor.next_serial();
+        if let Some(focus) = self.compositor.seat.keyboard_focus().filter(|_| pressed) {
+            self.compositor.seat.record_input(serial, focus);
+        }
+        let state = match pressed {
+            true => wl_keyboard::KeyState::Pressed,
+            false => wl_keyboard::KeyState::Released,
+        };
+        for keyboard in self
+            .resources
+            .seats
+            .keyboards()
+            .iter()
+            .filter(|k| k.id().same_client_as(&wl_surface.id()))
+        {
+            keyboard.key(serial, time, key, state);
+        }
+    }
+
+    /// Send routed pointer events to the pointers of each surface's client
+    pub(super) fn send_pointer_events(&mut self, events: Vec<(SurfaceId, PointerEvent)>) {
+        self.send_pointer_events_with(events, None);
+    }
+
+    /// Send routed pointer events, giving button events a serial already
+    /// taken instead of a new one
+    fn send_pointer_events_with(
+        &mut self,
+        events: Vec<(SurfaceId, PointerEvent)>,
+        button_serial: Option<u32>,
+    ) {
+        for (target, event) in events {
+            let Some(wl_surface) = self.resources.surfaces.get(&target) else {
+                continue;
+            };
+            let serial = match (&event, button_serial) {
+                (PointerEvent::Button { .. }, Some(serial)) => serial,
+                (
+                    PointerEvent::Enter { .. }
+                    | PointerEvent::Leave { .. }
+                    | PointerEvent::Button { .. },
+                    _,
+                ) => self.compositor.next_serial(),
+                _ => 0,
+            };
+            if let PointerEvent::Button {
+                state: ButtonState::Pressed,
+                ..
+            } = event
+            {
+                self.compositor.seat.record_input(serial, target);
+            }
+            if let PointerEvent::Enter { .. } = event {
+                self.compositor.seat.record_pointer_enter(serial);
+            }
+
+            for pointer in self
+                .resources
+                .seats
+                .pointers()
+                .iter()
+                .filter(|p| p.id().same_client_as(&wl_surface.id()))
+            {
+                match event {
+                    PointerEvent::Enter { x, y, .. } => pointer.enter(serial, wl_surface, x, y),
+                    PointerEvent::Leave { .. } => pointer.leave(serial, wl_surface),
+                    PointerEvent::Motion { time, x, y } => pointer.motion(time, x, y),
+                    PointerEvent::Button {
+                        time,
+                        button,
+                        state,
+                    } => {
+                        let state = match state {
+                            ButtonState::Pressed => wl_pointer::ButtonState::Pressed,
+                            ButtonState::Released => wl_pointer::ButtonState::Released,
+                        };
+                        pointer.button(serial, time, button, state);
+                    }
+                    PointerEvent::Axis { time, axis, value } => {
+                        let axis = match axis {
+                            AxisType::VerticalScroll => wl_pointer::Axis::VerticalScroll,
+                            AxisType::HorizontalScroll => wl_pointer::Axis::HorizontalScroll,
+                        };
+                        pointer.axis(time, axis, value);
+                    }
+                    PointerEvent::Frame => {
+                        if pointer.version() >= 5 {
+                            pointer.frame();
+                        }
+                    }
+                }
+            }
+        }
+        self.update_primary_selection_focus();
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, SeatData> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &wl_seat::WlSeat,
+        request: wl_seat::Request,
+        _data: &SeatData,
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let capabilities = state.compositor.seat.capabilities();
+        match request {
+            wl_seat::Request::GetPointer { id } => {
+                let pointer = data_init.init(id, ());
+                if capabilities.pointer {
+                    debug!("Creating pointer");
+                    state.resources.seats.pointers.push(pointer);
+                } else {
+                    debug!("Creating inert pointer");
+                }
+            }
+            wl_seat::Request::GetKeyboard { id } => {
+                let keyboard = data_init.init(id, ());
+                if capabilities.keyboard {
+                    debug!("Creating keyboard");
+                    state.send_keymap(&keyboard);
+                    state.resources.seats.keyboards.push(keyboard);
+                } else {
+                    debug!("Creating inert keyboard");
+                }
+            }
+            wl_seat::Request::GetTouch { id } => {
+                let touch = data_init.init(id, ());
+                if capabilities.touch {
+                    debug!("Creating touch");
+                    state.resources.seats.touches.push(touch);
+                } else {
+                    debug!("Creating inert touch");
+                }
+            }
+            wl_seat::Request::Release => {
+                // Device objects created from the seat stay valid
+                debug!("Seat release");
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &wl_seat::WlSeat,
+        _data: &SeatData,
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &wl_pointer::WlPointer,
+        request: wl_pointer::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            wl_pointer::Request::SetCursor {
+                serial: _,
+                surface,
+                hotspot_x,
+                hotspot_y,
+            } => {
+                debug!("Set cursor at ({}, {})", hotspot_x, hotspot_y);
+                if state.has_pointer_focus(client) {
+                    let surface = surface.and_then(|s| s.data::<SurfaceId>().copied());
+                    state
+                        .compositor
+                        .seat
+                        .pointer_mut()
+                        .set_cursor(surface, hotspot_x, hotspot_y);
+                }
+            }
+            wl_pointer::Request::Release => {
+                debug!("Pointer release");
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &wl_pointer::WlPointer,
+        _data: &(),
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_keyboard::WlKeyboard,
+        request: wl_keyboard::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_keyboard::Request::Release = request {
+            debug!("Keyboard release");
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &wl_keyboard::WlKeyboard,
+        _data: &(),
+    ) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+impl Dispatch<wl_touch::WlTouch, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_touch::WlTouch,
+        request: wl_touch::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_touch::Request::Release = request {
+            debug!("Touch release");
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: &wl_touch::WlTouch, _data: &()) {
+        state.resources.seats.remove(&resource.id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wayland_capabilities() {
+        let capabilities = SeatCapabilities {
+            keyboard: true,
+            pointer: true,
+            touch: false,
+        };
+        assert_eq!(
+            wayland_capabilities(capabilities),
+            wl_seat::Capability::Pointer | wl_seat::Capability::Keyboard
+        );
+        assert!(wayland_capabilities(SeatCapabilities::default()).is_empty());
+    }
+
+    #[test]
+    fn test_set_seat_capabilities() {
+        let mut state = ServerState::new();
+        let capabilities = SeatCapabilities {
+            keyboard: true,
+            pointer: false,
+            touch: true,
+        };
+        state.set_seat_capabilities(capabilities);
+        assert!(state.compositor.seat.capabilities().touch);
+        assert!(!state.compositor.seat.capabilities().pointer);
+        assert_eq!(state.resources.seats.seat_count(), 0);
+    }
+
+    #[test]
+    fn test_set_keyboard_focus_without_resources() {
+        let mut state = ServerState::new();
+        state.compositor.seat.keyboard_mut().key_press(42);
+        state.set_keyboard_focus(Some(SurfaceId(1)));
+        assert_eq!(state.compositor.seat.keyboard_focus(), Some(SurfaceId(1)));
+        assert_eq!(state.compositor.seat.keyboard().pressed_keys(), &[42]);
+    }
+}
@@ -0,0 +1,141 @@
+//! wp_security_context_manager_v1 objects
+//!
+//! A sandbox engine creates a security context with the listening socket it
+//! hands to the sandbox, sets the metadata of the sandboxed application and
+//! commits it. From then on the compositor accepts connections on that
+//! socket through [`ContextSockets`], and its clients carry the metadata.
+//! Clients of a security context or a tagged socket can't create nested
+//! ones, since the manager is a privileged global.
+//!
+//! [`ContextSockets`]: super::ContextSockets
+
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixListener;
+use std::sync::Mutex;
+
+use log::debug;
+use wayland_protocols::wp::security_context::v1::server::{
+    wp_security_context_manager_v1, wp_security_context_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::{client_info, GlobalData};
+use super::contexts::{security_listener, SecurityContext};
+use super::ServerState;
+
+/// A security context being set up
+#[derive(Debug)]
+struct PendingContext {
+    context: SecurityContext,
+    /// The listening socket and close FD, taken on commit, after which no
+    /// request but destroy is allowed
+    sockets: Option<(UnixListener, OwnedFd)>,
+}
+
+/// wp_security_context_v1 user data
+#[derive(Debug)]
+pub struct SecurityContextData(Mutex<PendingContext>);
+
+impl GlobalDispatch<wp_security_context_manager_v1::WpSecurityContextManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<wp_security_context_manager_v1::WpSecurityContextManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_security_context_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_security_context_manager_v1::WpSecurityContextManagerV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        client: &Client,
+        resource: &wp_security_context_manager_v1::WpSecurityContextManagerV1,
+        request: wp_security_context_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_security_context_manager_v1::Request::CreateListener {
+            id,
+            listen_fd,
+            close_fd,
+        } = request
+        {
+            use wp_security_context_manager_v1::Error;
+
+            let nested = client_info(client).is_some_and(|info| info.context.is_some());
+            let listener = security_listener(listen_fd);
+            let error = listener.as_ref().err().map(ToString::to_string);
+            let sockets = listener.ok().filter(|_| !nested).map(|l| (l, close_fd));
+            let pending = PendingContext {
+                context: SecurityContext::default(),
+                sockets,
+            };
+            data_init.init(id, SecurityContextData(Mutex::new(pending)));
+            if nested {
+                resource.post_error(Error::Nested, "Nested security contexts are forbidden");
+            } else if let Some(e) = error {
+                resource.post_error(Error::InvalidListenFd, e);
+            }
+        }
+    }
+}
+
+impl Dispatch<wp_security_context_v1::WpSecurityContextV1, SecurityContextData> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_security_context_v1::WpSecurityContextV1,
+        request: wp_security_context_v1::Request,
+        data: &SecurityContextData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        use wp_security_context_v1::{Error, Request};
+
+        let mut pending = data.0.lock().unwrap();
+        if let Request::Destroy = request {
+            return;
+        }
+        if pending.sockets.is_none() {
+            resource.post_error(
+                Error::AlreadyUsed,
+                "The security context was already committed",
+            );
+            return;
+        }
+        let (field, value) = match request {
+            Request::SetSandboxEngine { name } => (&mut pending.context.sandbox_engine, name),
+            Request::SetAppId { app_id } => (&mut pending.context.app_id, app_id),
+            Request::SetInstanceId { instance_id } => {
+                (&mut pending.context.instance_id, instance_id)
+            }
+            Request::Commit => {
+                if let Some((listener, close_fd)) = pending.sockets.take() {
+                    let context = pending.context.clone();
+                    state
+                        .context_sockets
+                        .add_listener(context, listener, close_fd);
+                }
+                return;
+            }
+            _ => return,
+        };
+        if field.is_some() {
+            resource.post_error(Error::AlreadySet, "The metadata was already set");
+            return;
+        }
+        *field = Some(value);
+    }
+}
@@ -0,0 +1,74 @@
+//! Selection text for the macOS Services menu
+//!
+//! AppKit asks for the selected text when a service such as Look Up or
+//! Translate is picked, not when the selection changes. Only then is the
+//! client owning the selection asked to write it as text into a pipe, and
+//! the compositor waits a short while for it to finish.
+
+use std::os::fd::AsFd;
+use std::time::Duration;
+
+use log::debug;
+use wayland_server::DisplayHandle;
+
+use super::ServerState;
+use crate::protocol::data_device::{read_transfer, TransferError};
+
+/// How long the selection owner gets to write its text
+const TRANSFER_TIMEOUT: Duration = Duration::from_millis(500);
+
+impl ServerState {
+    /// Check if the selection can be read as text, without reading it
+    pub fn has_selection_text(&self) -> bool {
+        self.data_device.selection_text_type().is_some()
+    }
+
+    /// Read the text of the selection from the client owning it
+    pub fn read_selection_text(&self, dh: &mut DisplayHandle) -> Result<String, TransferError> {
+        let source = self
+            .data_device
+            .selection()
+            .ok_or(TransferError::NoSelection)?;
+        let mime_type = self
+            .data_device
+            .selection_text_type()
+            .ok_or(TransferError::NoText)?;
+
+        debug!("Reading selection {:?} as {}", source.id, mime_type);
+        let (read, write) = rustix::pipe::pipe().map_err(std::io::Error::from)?;
+        if !self.send_source(source.id, mime_type.to_string(), write.as_fd()) {
+            return Err(TransferError::NoSelection);
+        }
+        // Only the client may hold the write end, or the read never ends
+        drop(write);
+        dh.flush_clients()?;
+
+        let data = read_transfer(read, TRANSFER_TIMEOUT)?;
+        String::from_utf8(data).map_err(|_| TransferError::NotUtf8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_without_source_object() {
+        let mut state = ServerState::new();
+        assert!(!state.has_selection_text());
+
+        let source = state.data_device.create_data_source();
+        state
+            .data_device
+            .get_source_mut(source)
+            .unwrap()
+            .offer("text/plain".to_string());
+        state.data_device.set_selection(Some(source), 1);
+        assert!(state.has_selection_text());
+
+        // No wl_data_source to ask for the text
+        let display = wayland_server::Display::<ServerState>::new().unwrap();
+        let result = state.read_selection_text(&mut display.handle());
+        assert!(matches!(result, Err(TransferError::NoSelection)));
+    }
+}
@@ -0,0 +1,100 @@
+//! wp_single_pixel_buffer_manager_v1 objects
+//!
+//! Single-pixel buffers are wl_buffers of one color, usually stretched over
+//! a surface with wp_viewport for backgrounds and letterboxing. They have no
+//! pixel storage: the color is kept with the buffer and filled in when the
+//! window's content is composed for upload.
+
+use log::debug;
+use wayland_protocols::wp::single_pixel_buffer::v1::server::wp_single_pixel_buffer_manager_v1;
+use wayland_server::protocol::wl_buffer;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New};
+
+use super::client::GlobalData;
+use super::ServerState;
+
+/// Color of a single-pixel wl_buffer, premultiplied RGBA from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SinglePixelBuffer(pub [f32; 4]);
+
+impl SinglePixelBuffer {
+    /// Convert the 32-bit channel values of the protocol request
+    pub fn from_u32(r: u32, g: u32, b: u32, a: u32) -> Self {
+        Self([r, g, b, a].map(|c| (c as f64 / u32::MAX as f64) as f32))
+    }
+}
+
+impl GlobalDispatch<wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_single_pixel_buffer_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wp_single_pixel_buffer_manager_v1::WpSinglePixelBufferManagerV1,
+        request: wp_single_pixel_buffer_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_single_pixel_buffer_manager_v1::Request::CreateU32RgbaBuffer { id, r, g, b, a } =
+            request
+        {
+            let buffer = SinglePixelBuffer::from_u32(r, g, b, a);
+            debug!("Creating single-pixel buffer {:?}", buffer.0);
+            data_init.init(id, buffer);
+        }
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, SinglePixelBuffer> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &wl_buffer::WlBuffer,
+        request: wl_buffer::Request,
+        _data: &SinglePixelBuffer,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_buffer::Request::Destroy = request {
+            debug!("Destroying single-pixel buffer");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::surface::BufferInfo;
+
+    #[test]
+    fn test_single_pixel_color() {
+        let buffer = SinglePixelBuffer::from_u32(u32::MAX, 0, u32::MAX / 2, u32::MAX);
+        assert_eq!(buffer.0[0], 1.0);
+        assert_eq!(buffer.0[1], 0.0);
+        assert!((buffer.0[2] - 0.5).abs() < 1e-6);
+
+        let info = BufferInfo::single_pixel(buffer.0);
+        assert_eq!((info.width, info.height), (1, 1));
+        // ARGB8888 is stored as BGRA
+        assert_eq!(info.solid_pixel(), Some([128, 0, 255, 255]));
+    }
+}
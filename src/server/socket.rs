@@ -0,0 +1,193 @@
+//! Wayland listening socket selection
+//!
+//! Picks a free `wayland-N` display, cleaning up sockets left behind by
+//! crashed compositors, and explains the choice in the log. With
+//! `--replace`, an already running wayoa instance is asked over IPC to quit
+//! so its display name can be taken over.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+use wayland_server::{BindError, ListeningSocket};
+
+use crate::ipc::{self, IpcServer};
+
+/// Display numbers tried when picking a socket name
+const DISPLAY_RANGE: Range<usize> = 0..33;
+
+/// How long to wait for a replaced instance to release its socket
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How the socket name was chosen
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SocketChoice {
+    /// Chosen socket name
+    pub name: String,
+    /// Names skipped because another compositor holds them
+    pub skipped: Vec<String>,
+    /// A stale socket or lock from a crashed instance was cleaned up
+    pub stale: bool,
+    /// The name was taken over from a previous wayoa instance
+    pub replaced: bool,
+}
+
+impl SocketChoice {
+    /// Describe the choice for the log
+    pub fn describe(&self) -> String {
+        if self.replaced {
+            return format!("{} (took over from the previous wayoa instance)", self.name);
+        }
+
+        let mut reasons = Vec::new();
+        if !self.skipped.is_empty() {
+            reasons.push(format!(
+                "{} in use by another compositor",
+                self.skipped.join(", ")
+            ));
+        }
+        if self.stale {
+            reasons.push("removed a stale socket left by a crashed instance".to_string());
+        }
+        match reasons.is_empty() {
+            true => self.name.clone(),
+            false => format!("{} ({})", self.name, reasons.join("; ")),
+        }
+    }
+}
+
+/// Socket selection errors
+#[derive(Debug, thiserror::Error)]
+pub enum SocketError {
+    #[error("XDG_RUNTIME_DIR is not set to an absolute path")]
+    RuntimeDirNotSet,
+    #[error("All display names wayland-{}..wayland-{} are in use", DISPLAY_RANGE.start, DISPLAY_RANGE.end - 1)]
+    AllInUse,
+    #[error("No running wayoa instance to replace")]
+    NoInstanceToReplace,
+    #[error("{0} was not released within {1:?}")]
+    ReplaceTimeout(String, Duration),
+    #[error("Failed to bind {0}: {1}")]
+    Bind(String, String),
+}
+
+/// Get the directory Wayland sockets live in
+pub fn runtime_dir() -> Result<PathBuf, SocketError> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .ok_or(SocketError::RuntimeDirNotSet)
+}
+
+/// Bind a socket name, noting whether a stale socket was cleaned up
+///
+/// wayland-server removes a leftover socket once it holds the lock, which
+/// can only happen if the compositor that created it is gone.
+fn bind_name(dir: &Path, name: &str) -> Result<(ListeningSocket, bool), BindError> {
+    let path = dir.join(name);
+    let leftover = path.exists() || path.with_extension("lock").exists();
+    let socket = ListeningSocket::bind_absolute(path)?;
+    Ok((socket, leftover))
+}
+
+/// Bind the first free display name
+pub fn bind_auto(dir: &Path) -> Result<(ListeningSocket, SocketChoice), SocketError> {
+    let mut choice = SocketChoice::default();
+    for n in DISPLAY_RANGE {
+        let name = format!("wayland-{}", n);
+        match bind_name(dir, &name) {
+            Ok((socket, stale)) => {
+                choice.name = name;
+                choice.stale = stale;
+                return Ok((socket, choice));
+            }
+            Err(BindError::AlreadyInUse) => {
+                debug!("{} is in use", name);
+                choice.skipped.push(name);
+            }
+            Err(e) => return Err(SocketError::Bind(name, format!("{:?}", e))),
+        }
+    }
+    Err(SocketError::AllInUse)
+}
+
+/// Ask a running wayoa instance to quit and take over its display name
+///
+/// `preferred` is tried first, then every display with a wayoa IPC socket.
+pub fn bind_replacing(
+    dir: &Path,
+    preferred: Option<&str>,
+) -> Result<(ListeningSocket, SocketChoice), SocketError> {
+    let candidates = preferred
+        .map(str::to_string)
+        .into_iter()
+        .chain(DISPLAY_RANGE.map(|n| format!("wayland-{}", n)));
+
+    for name in candidates {
+        let ipc_path = IpcServer::default_path(&name);
+        if !ipc_path.exists() {
+            continue;
+        }
+        match ipc::request(&ipc_path, "quit") {
+            Ok(response) if response.ok => {}
+            _ => continue,
+        }
+        info!("Asked the wayoa instance on {} to quit", name);
+
+        let deadline = Instant::now() + REPLACE_TIMEOUT;
+        loop {
+            match bind_name(dir, &name) {
+                Ok((socket, _)) => {
+                    let choice = SocketChoice {
+                        name,
+                        replaced: true,
+                        ..Default::default()
+                    };
+                    return Ok((socket, choice));
+                }
+                Err(BindError::AlreadyInUse) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(BindError::AlreadyInUse) => {
+                    return Err(SocketError::ReplaceTimeout(name, REPLACE_TIMEOUT));
+                }
+                Err(e) => return Err(SocketError::Bind(name, format!("{:?}", e))),
+            }
+        }
+    }
+    Err(SocketError::NoInstanceToReplace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_auto_skips_names_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_first, choice) = bind_auto(dir.path()).unwrap();
+        assert_eq!(choice.name, "wayland-0");
+        assert_eq!(choice.describe(), "wayland-0");
+
+        let (_second, choice) = bind_auto(dir.path()).unwrap();
+        assert_eq!(choice.name, "wayland-1");
+        assert_eq!(choice.skipped, vec!["wayland-0".to_string()]);
+        assert_eq!(
+            choice.describe(),
+            "wayland-1 (wayland-0 in use by another compositor)"
+        );
+    }
+
+    #[test]
+    fn test_bind_auto_cleans_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("wayland-0"), b"").unwrap();
+        std::fs::write(dir.path().join("wayland-0.lock"), b"").unwrap();
+
+        let (_socket, choice) = bind_auto(dir.path()).unwrap();
+        assert_eq!(choice.name, "wayland-0");
+        assert!(choice.stale);
+        assert!(choice.describe().contains("stale socket"));
+    }
+}
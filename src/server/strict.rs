@@ -0,0 +1,259 @@
+//! Strict protocol validation
+//!
+//! Built with the `strict` feature, wayoa rejects requests that release
+//! builds tolerate: damage with a negative size, buffer scales below one,
+//! unknown buffer transforms, acks of configure serials that were never
+//! sent, and buffers committed before the first configure was acked. Each
+//! violation posts a protocol error and is logged with the requests that
+//! led up to it, which helps when bringing up a client toolkit.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+
+use log::warn;
+use wayland_protocols::xdg::shell::server::xdg_surface;
+use wayland_server::protocol::wl_surface;
+use wayland_server::{Resource, WEnum};
+
+use super::ServerState;
+use crate::compositor::SurfaceId;
+
+/// Requests remembered per surface
+const TRACE_LENGTH: usize = 32;
+
+/// Violations kept for inspection
+const MAX_VIOLATIONS: usize = 100;
+
+/// A rejected request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Surface the request was made on
+    pub surface: SurfaceId,
+    /// What was wrong with it
+    pub message: String,
+    /// The surface's requests up to and including the rejected one
+    pub trace: Vec<String>,
+}
+
+/// Request traces and configure bookkeeping for strict validation
+#[derive(Debug, Default)]
+pub struct StrictValidator {
+    /// Recent requests by surface
+    traces: HashMap<SurfaceId, VecDeque<String>>,
+    /// Configured xdg_surfaces by their wl_surface
+    xdg_surfaces: HashMap<SurfaceId, xdg_surface::XdgSurface>,
+    /// Configure serials sent and not acked yet
+    configures: HashMap<SurfaceId, VecDeque<u32>>,
+    /// xdg_surfaces that acked a configure
+    acked: HashSet<SurfaceId>,
+    /// Recent violations, oldest first
+    pub violations: VecDeque<Violation>,
+}
+
+impl StrictValidator {
+    /// Remember a request made on a surface or its role objects
+    pub fn trace(&mut self, surface: SurfaceId, request: &impl Debug) {
+        let trace = self.traces.entry(surface).or_default();
+        if trace.len() == TRACE_LENGTH {
+            trace.pop_front();
+        }
+        trace.push_back(format!("{:?}", request));
+    }
+
+    /// Track an xdg_surface, once it is first configured
+    pub fn add_xdg_surface(&mut self, surface: SurfaceId, resource: &xdg_surface::XdgSurface) {
+        self.xdg_surfaces
+            .entry(surface)
+            .or_insert_with(|| resource.clone());
+    }
+
+    /// Record a configure sent to an xdg_surface
+    pub fn configured(&mut self, surface: SurfaceId, serial: u32) {
+        self.configures
+            .entry(surface)
+            .or_default()
+            .push_back(serial);
+    }
+
+    /// Record an ack, returning false if the serial was never sent
+    ///
+    /// Acking a configure also drops the ones sent before it.
+    pub fn ack(&mut self, surface: SurfaceId, serial: u32) -> bool {
+        let Some(sent) = self.configures.get_mut(&surface) else {
+            return false;
+        };
+        let Some(position) = sent.iter().position(|&s| s == serial) else {
+            return false;
+        };
+        sent.drain(..=position);
+        self.acked.insert(surface);
+        true
+    }
+
+    /// Forget a destroyed surface
+    pub fn remove_surface(&mut self, surface: SurfaceId) {
+        self.traces.remove(&surface);
+        self.xdg_surfaces.remove(&surface);
+        self.configures.remove(&surface);
+        self.acked.remove(&surface);
+    }
+
+    /// Record a violation with the surface's trace
+    fn violation(&mut self, surface: SurfaceId, message: String) -> &Violation {
+        if self.violations.len() == MAX_VIOLATIONS {
+            self.violations.pop_front();
+        }
+        let trace = self
+            .traces
+            .get(&surface)
+            .map(|trace| trace.iter().cloned().collect())
+            .unwrap_or_default();
+        self.violations.push_back(Violation {
+            surface,
+            message,
+            trace,
+        });
+        self.violations.back().unwrap()
+    }
+}
+
+impl ServerState {
+    /// Validate a wl_surface request
+    ///
+    /// Returns false if the request was rejected with a protocol error.
+    pub(super) fn strict_surface_request(
+        &mut self,
+        surface_id: SurfaceId,
+        request: &wl_surface::Request,
+    ) -> bool {
+        self.strict.trace(surface_id, request);
+        let Some(resource) = self.resources.surfaces.get(&surface_id).cloned() else {
+            return true;
+        };
+
+        match request {
+            wl_surface::Request::Damage { width, height, .. }
+            | wl_surface::Request::DamageBuffer { width, height, .. }
+                if *width < 0 || *height < 0 =>
+            {
+                let message = format!("damage with negative size {}x{}", width, height);
+                self.reject(
+                    &resource,
+                    wl_surface::Error::InvalidSize,
+                    surface_id,
+                    message,
+                )
+            }
+            wl_surface::Request::SetBufferScale { scale } if *scale <= 0 => {
+                let message = format!("buffer scale {} is not positive", scale);
+                self.reject(
+                    &resource,
+                    wl_surface::Error::InvalidScale,
+                    surface_id,
+                    message,
+                )
+            }
+            wl_surface::Request::SetBufferTransform {
+                transform: WEnum::Unknown(transform),
+            } => {
+                let message = format!("unknown buffer transform {}", transform);
+                self.reject(
+                    &resource,
+                    wl_surface::Error::InvalidTransform,
+                    surface_id,
+                    message,
+                )
+            }
+            wl_surface::Request::Commit => {
+                let attached = self
+                    .compositor
+                    .surfaces
+                    .get(surface_id)
+                    .is_some_and(|surface| surface.pending.buffer.is_some());
+                match self.strict.xdg_surfaces.get(&surface_id).cloned() {
+                    Some(xdg_surface) if attached && !self.strict.acked.contains(&surface_id) => {
+                        let message = "buffer committed before the first configure was acked";
+                        self.reject(
+                            &xdg_surface,
+                            xdg_surface::Error::UnconfiguredBuffer,
+                            surface_id,
+                            message.to_string(),
+                        )
+                    }
+                    _ => true,
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Validate an xdg_surface ack_configure
+    ///
+    /// Returns false if the serial was never sent and the request was
+    /// rejected with a protocol error.
+    pub(super) fn strict_ack_configure(
+        &mut self,
+        resource: &xdg_surface::XdgSurface,
+        surface_id: SurfaceId,
+        serial: u32,
+    ) -> bool {
+        self.strict
+            .trace(surface_id, &xdg_surface::Request::AckConfigure { serial });
+        if self.strict.ack(surface_id, serial) {
+            return true;
+        }
+        let message = format!("ack of unknown configure serial {}", serial);
+        self.reject(
+            resource,
+            xdg_surface::Error::InvalidSerial,
+            surface_id,
+            message,
+        )
+    }
+
+    /// Post a protocol error for a rejected request and log its trace
+    fn reject(
+        &mut self,
+        resource: &impl Resource,
+        code: impl Into<u32>,
+        surface: SurfaceId,
+        message: String,
+    ) -> bool {
+        let violation = self.strict.violation(surface, message);
+        warn!(
+            "Strict: {} on {} of surface {:?}, after:\n  {}",
+            violation.message,
+            resource.id(),
+            surface,
+            violation.trace.join("\n  ")
+        );
+        resource.post_error(code.into(), violation.message.clone());
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_configure() {
+        let mut validator = StrictValidator::default();
+        let surface = SurfaceId(1);
+        assert!(!validator.ack(surface, 1));
+
+        validator.configured(surface, 5);
+        validator.configured(surface, 7);
+        assert!(!validator.ack(surface, 6));
+        // Acking the latest drops the earlier one
+        assert!(validator.ack(surface, 7));
+        assert!(!validator.ack(surface, 5));
+        assert!(validator.acked.contains(&surface));
+
+        validator.trace(surface, &"commit");
+        let violation = validator.violation(surface, "bad".to_string());
+        assert_eq!(violation.trace, vec!["\"commit\"".to_string()]);
+        validator.remove_surface(surface);
+        assert!(validator.traces.is_empty() && validator.acked.is_empty());
+    }
+}
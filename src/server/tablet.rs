@@ -0,0 +1,331 @@
+//! zwp_tablet_manager_v2 objects
+//!
+//! Every tablet seat gets one tablet, standing for the tablets attached to
+//! the Mac, and a tool object for each tool that has come into proximity so
+//! far. New tools are announced to existing tablet seats as they appear.
+//! Tools never go away, and tablet pads aren't supported. Events come from
+//! the [`Tablet`] model, which the Cocoa backend feeds with NSEvent tablet
+//! proximity and point events.
+//!
+//! [`Tablet`]: crate::input::Tablet
+
+use log::debug;
+use wayland_protocols::wp::tablet::zv2::server::{
+    zwp_tablet_manager_v2, zwp_tablet_seat_v2, zwp_tablet_tool_v2, zwp_tablet_v2,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::input::{TabletEvent, TabletSample, TabletTool, TabletToolId, TabletToolKind};
+
+/// Name of the tablet advertised to clients
+const TABLET_NAME: &str = "Wayoa Tablet";
+
+/// Tablet seats with their tablet, and the tool objects
+#[derive(Debug, Default)]
+pub struct TabletResources {
+    seats: Vec<(
+        zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        zwp_tablet_v2::ZwpTabletV2,
+    )>,
+    tools: Vec<(TabletToolId, zwp_tablet_tool_v2::ZwpTabletToolV2)>,
+}
+
+impl TabletResources {
+    /// Stop tracking a destroyed object
+    fn remove(&mut self, id: &wayland_server::backend::ObjectId) {
+        self.seats
+            .retain(|(seat, tablet)| seat.id() != *id && Resource::id(tablet) != *id);
+        self.tools.retain(|(_, tool)| tool.id() != *id);
+    }
+
+    /// Create a tool object for a tablet seat and describe the tool
+    fn announce_tool(
+        &mut self,
+        dh: &DisplayHandle,
+        seat: &zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        tool: &TabletTool,
+    ) {
+        let Some(client) = seat.client() else {
+            return;
+        };
+        let Ok(resource) = client
+            .create_resource::<zwp_tablet_tool_v2::ZwpTabletToolV2, _, ServerState>(
+                dh,
+                seat.version(),
+                tool.id,
+            )
+        else {
+            return;
+        };
+        seat.tool_added(&resource);
+
+        use zwp_tablet_tool_v2::{Capability, Type};
+        resource._type(match tool.kind {
+            TabletToolKind::Pen => Type::Pen,
+            TabletToolKind::Eraser => Type::Eraser,
+            TabletToolKind::Mouse => Type::Mouse,
+        });
+        if tool.serial != 0 {
+            resource.hardware_serial((tool.serial >> 32) as u32, tool.serial as u32);
+        }
+        if tool.hardware_id != 0 {
+            resource.hardware_id_wacom((tool.hardware_id >> 32) as u32, tool.hardware_id as u32);
+        }
+        if tool.kind != TabletToolKind::Mouse {
+            resource.capability(Capability::Pressure);
+            resource.capability(Capability::Tilt);
+            resource.capability(Capability::Rotation);
+        }
+        resource.done();
+        self.tools.push((tool.id, resource));
+    }
+}
+
+impl GlobalDispatch<zwp_tablet_manager_v2::ZwpTabletManagerV2, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_tablet_manager_v2::ZwpTabletManagerV2>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_tablet_manager_v2");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_tablet_manager_v2::ZwpTabletManagerV2, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        _resource: &zwp_tablet_manager_v2::ZwpTabletManagerV2,
+        request: zwp_tablet_manager_v2::Request,
+        _data: &(),
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_tablet_manager_v2::Request::GetTabletSeat { tablet_seat, .. } = request {
+            debug!("Creating tablet seat");
+            let seat = data_init.init(tablet_seat, ());
+            let Ok(tablet) = client.create_resource::<zwp_tablet_v2::ZwpTabletV2, _, Self>(
+                dhandle,
+                seat.version(),
+                (),
+            ) else {
+                return;
+            };
+            seat.tablet_added(&tablet);
+            tablet.name(TABLET_NAME.to_string());
+            tablet.done();
+
+            let resources = &mut state.resources.tablets;
+            for tool in state.tablet.tools() {
+                resources.announce_tool(dhandle, &seat, tool);
+            }
+            resources.seats.push((seat, tablet));
+        }
+    }
+}
+
+impl Dispatch<zwp_tablet_seat_v2::ZwpTabletSeatV2, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        _request: zwp_tablet_seat_v2::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_tablet_seat_v2::ZwpTabletSeatV2,
+        _data: &(),
+    ) {
+        state.resources.tablets.remove(&resource.id());
+    }
+}
+
+impl Dispatch<zwp_tablet_v2::ZwpTabletV2, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_tablet_v2::ZwpTabletV2,
+        _request: zwp_tablet_v2::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_tablet_v2::ZwpTabletV2,
+        _data: &(),
+    ) {
+        state.resources.tablets.remove(&Resource::id(resource));
+    }
+}
+
+impl Dispatch<zwp_tablet_tool_v2::ZwpTabletToolV2, TabletToolId> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_tablet_tool_v2::ZwpTabletToolV2,
+        request: zwp_tablet_tool_v2::Request,
+        _data: &TabletToolId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_tablet_tool_v2::Request::SetCursor { .. } = request {
+            // The tool moves the system cursor, which shows its own image
+            debug!("Ignoring tablet tool cursor surface");
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &zwp_tablet_tool_v2::ZwpTabletToolV2,
+        _data: &TabletToolId,
+    ) {
+        state.resources.tablets.remove(&resource.id());
+    }
+}
+
+impl ServerState {
+    /// A tool came into proximity, announcing it to tablet seats if it's
+    /// new
+    pub fn tablet_tool_added(&mut self, tool: TabletTool) {
+        if !self.tablet.add_tool(tool) {
+            return;
+        }
+        debug!("New tablet tool {:?}", tool);
+        let seats: Vec<_> = self
+            .resources
+            .tablets
+            .seats
+            .iter()
+            .map(|(seat, _)| seat.clone())
+            .collect();
+        for seat in seats {
+            if let Some(dh) = seat.handle().upgrade().map(DisplayHandle::from) {
+                self.resources.tablets.announce_tool(&dh, &seat, &tool);
+            }
+        }
+    }
+
+    /// Route a sample of a tool to the surface under it, or the surface its
+    /// tip is down on
+    pub fn tablet_tool_motion(
+        &mut self,
+        time: u32,
+        tool: TabletToolId,
+        sample: TabletSample,
+        under: Option<SurfaceId>,
+        locate: impl Fn(SurfaceId) -> (f64, f64),
+    ) {
+        self.input_activity();
+        let events = self.tablet.update(tool, sample, under, locate);
+        self.send_tablet_events(time, events);
+    }
+
+    /// A tool left proximity of the tablet
+    pub fn tablet_tool_proximity_out(&mut self, time: u32, tool: TabletToolId) {
+        let events = self.tablet.proximity_out(tool);
+        self.send_tablet_events(time, events);
+    }
+
+    /// Send tablet events to the tool objects of each surface's client
+    fn send_tablet_events(&mut self, time: u32, events: Vec<(SurfaceId, TabletEvent)>) {
+        for (target, event) in events {
+            let Some(wl_surface) = self.resources.surfaces.get(&target).cloned() else {
+                continue;
+            };
+            let serial = match event {
+                TabletEvent::ProximityIn { .. }
+                | TabletEvent::Down { .. }
+                | TabletEvent::Button { .. } => self.compositor.next_serial(),
+                _ => 0,
+            };
+            if let TabletEvent::Down { .. } = event {
+                self.compositor.seat.record_input(serial, target);
+            }
+
+            let resources = &self.resources.tablets;
+            let tablet = resources
+                .seats
+                .iter()
+                .find(|(_, tablet)| Resource::id(tablet).same_client_as(&wl_surface.id()))
+                .map(|(_, tablet)| tablet);
+            for (_, resource) in resources.tools.iter().filter(|(id, resource)| {
+                *id == event.tool() && resource.id().same_client_as(&wl_surface.id())
+            }) {
+                use zwp_tablet_tool_v2::ButtonState;
+                match event {
+                    TabletEvent::ProximityIn { .. } => {
+                        if let Some(tablet) = tablet {
+                            resource.proximity_in(serial, tablet, &wl_surface);
+                        }
+                    }
+                    TabletEvent::ProximityOut { .. } => resource.proximity_out(),
+                    TabletEvent::Down { .. } => resource.down(serial),
+                    TabletEvent::Up { .. } => resource.up(),
+                    TabletEvent::Motion { x, y, .. } => resource.motion(x, y),
+                    TabletEvent::Pressure { pressure, .. } => resource.pressure(pressure),
+                    TabletEvent::Tilt { x, y, .. } => resource.tilt(x, y),
+                    TabletEvent::Rotation { degrees, .. } => resource.rotation(degrees),
+                    TabletEvent::Button {
+                        button, pressed, ..
+                    } => resource.button(
+                        serial,
+                        button,
+                        if pressed {
+                            ButtonState::Pressed
+                        } else {
+                            ButtonState::Released
+                        },
+                    ),
+                    TabletEvent::Frame { .. } => resource.frame(time),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tablet_tool_added() {
+        let mut state = ServerState::new();
+        let tool = TabletTool {
+            id: TabletToolId(1),
+            kind: TabletToolKind::Eraser,
+            serial: 0,
+            hardware_id: 0,
+        };
+        state.tablet_tool_added(tool);
+        state.tablet_tool_added(tool);
+        assert_eq!(state.tablet.tools(), [tool]);
+
+        // Without a surface under it the tool has no focus
+        let sample = TabletSample::default();
+        state.tablet_tool_motion(0, tool.id, sample, None, |_| (0.0, 0.0));
+        assert_eq!(state.tablet.focus(tool.id), None);
+    }
+}
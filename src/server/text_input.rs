@@ -0,0 +1,211 @@
+//! zwp_text_input_manager_v3 objects
+//!
+//! Requests update the text input state in [`crate::protocol::text_input`].
+//! Text inputs of the client with keyboard focus are entered on the focused
+//! surface, and text from an input method goes to the enabled one. The
+//! input method follows the changes through `update_input_method`.
+
+use log::debug;
+use wayland_protocols::wp::text_input::zv3::server::{
+    zwp_text_input_manager_v3, zwp_text_input_v3,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource, WEnum};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::protocol::text_input::{
+    ChangeCause, ContentHint, ContentPurpose, CursorRectangle, Preedit, SurroundingText,
+    TextInputEvent, TextInputId,
+};
+
+impl GlobalDispatch<zwp_text_input_manager_v3::ZwpTextInputManagerV3, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_text_input_manager_v3::ZwpTextInputManagerV3>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_text_input_manager_v3");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_text_input_manager_v3::ZwpTextInputManagerV3, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+        request: zwp_text_input_manager_v3::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_text_input_manager_v3::Request::GetTextInput { id, seat: _ } = request {
+            let text_input_id = state.text_input.create();
+            let resource = data_init.init(id, text_input_id);
+            state.resources.text_inputs.insert(text_input_id, resource);
+            // Enter right away if the client already has keyboard focus
+            state.update_text_input_focus();
+        }
+    }
+}
+
+impl Dispatch<zwp_text_input_v3::ZwpTextInputV3, TextInputId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_text_input_v3::ZwpTextInputV3,
+        request: zwp_text_input_v3::Request,
+        data: &TextInputId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let Some(input) = state.text_input.get_mut(*data) else {
+            return;
+        };
+        // Requests without focus are ignored until the next enter
+        if input.focus.is_none() && !matches!(request, zwp_text_input_v3::Request::Destroy) {
+            return;
+        }
+        match request {
+            zwp_text_input_v3::Request::Enable => input.enable(),
+            zwp_text_input_v3::Request::Disable => input.disable(),
+            zwp_text_input_v3::Request::SetSurroundingText {
+                text,
+                cursor,
+                anchor,
+            } => {
+                input.pending.surrounding = Some(SurroundingText {
+                    text,
+                    cursor,
+                    anchor,
+                });
+            }
+            zwp_text_input_v3::Request::SetTextChangeCause { cause } => {
+                input.pending.change_cause = match cause {
+                    WEnum::Value(zwp_text_input_v3::ChangeCause::Other) => ChangeCause::Other,
+                    _ => ChangeCause::InputMethod,
+                };
+            }
+            zwp_text_input_v3::Request::SetContentType { hint, purpose } => {
+                input.pending.hint = match hint {
+                    WEnum::Value(hint) => ContentHint::from_bits_truncate(hint.bits()),
+                    WEnum::Unknown(bits) => ContentHint::from_bits_truncate(bits),
+                };
+                input.pending.purpose = match purpose {
+                    WEnum::Value(purpose) => {
+                        ContentPurpose::from_u32(purpose.into()).unwrap_or_default()
+                    }
+                    WEnum::Unknown(_) => ContentPurpose::Normal,
+                };
+            }
+            zwp_text_input_v3::Request::SetCursorRectangle {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                input.pending.cursor_rectangle = Some(CursorRectangle {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+            zwp_text_input_v3::Request::Commit => {
+                input.commit();
+                debug!("Text input {:?} committed {:?}", data, input.current);
+                state.update_input_method();
+            }
+            zwp_text_input_v3::Request::Destroy => {
+                debug!("Text input {:?} destroy", data);
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwp_text_input_v3::ZwpTextInputV3,
+        data: &TextInputId,
+    ) {
+        state.text_input.destroy(*data);
+        state.resources.text_inputs.remove(data);
+        state.update_input_method();
+    }
+}
+
+impl ServerState {
+    /// Enter the text inputs of the client with keyboard focus, and leave
+    /// the others
+    pub fn update_text_input_focus(&mut self) {
+        let focus = self.compositor.seat.keyboard().focus();
+        let wl_surface = focus.and_then(|surface| self.resources.surfaces.get(&surface));
+        let focused: Vec<TextInputId> = self
+            .resources
+            .text_inputs
+            .iter()
+            .filter(|(_, r)| wl_surface.is_some_and(|s| r.id().same_client_as(&s.id())))
+            .map(|(id, _)| *id)
+            .collect();
+        let events = self.text_input.set_focus(focus, &focused);
+        self.send_text_input_events(events);
+        self.update_input_method();
+    }
+
+    /// Send text from an input method to the enabled text input
+    ///
+    /// `delete` is the number of bytes to delete before and after the
+    /// cursor, before the commit string is inserted.
+    pub fn text_input_method_text(
+        &mut self,
+        preedit: Option<Preedit>,
+        commit: Option<String>,
+        delete: Option<(u32, u32)>,
+    ) {
+        let events = self.text_input.input_method_text(preedit, commit, delete);
+        self.send_text_input_events(events);
+    }
+
+    /// Send events to text input objects
+    fn send_text_input_events(&self, events: Vec<(TextInputId, TextInputEvent)>) {
+        for (id, event) in events {
+            let Some(resource) = self.resources.text_inputs.get(&id) else {
+                continue;
+            };
+            match event {
+                TextInputEvent::Enter(surface) | TextInputEvent::Leave(surface) => {
+                    let Some(wl_surface) = self.resources.surfaces.get(&surface) else {
+                        continue;
+                    };
+                    if matches!(event, TextInputEvent::Enter(_)) {
+                        resource.enter(wl_surface);
+                    } else {
+                        resource.leave(wl_surface);
+                    }
+                }
+                TextInputEvent::Preedit(Some(preedit)) => {
+                    resource.preedit_string(
+                        Some(preedit.text),
+                        preedit.cursor_begin,
+                        preedit.cursor_end,
+                    );
+                }
+                TextInputEvent::Preedit(None) => resource.preedit_string(None, 0, 0),
+                TextInputEvent::CommitString(text) => resource.commit_string(Some(text)),
+                TextInputEvent::DeleteSurroundingText { before, after } => {
+                    resource.delete_surrounding_text(before, after);
+                }
+                TextInputEvent::Done(serial) => resource.done(serial),
+            }
+        }
+    }
+}
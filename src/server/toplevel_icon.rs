@@ -0,0 +1,213 @@
+//! xdg_toplevel_icon_manager_v1 objects
+//!
+//! A client builds an icon from square shm buffers and an icon theme name,
+//! then sets it on a toplevel, after which the icon can't change. The icon
+//! is double-buffered toplevel state, applied with the toplevel's next
+//! commit. Buffers are read when they're added, since their contents must
+//! not change afterwards. On macOS a window's icon is its miniaturized
+//! image and the Dock tile shown for it.
+
+use std::sync::Mutex;
+
+use log::debug;
+use wayland_protocols::xdg::toplevel_icon::v1::server::{
+    xdg_toplevel_icon_manager_v1, xdg_toplevel_icon_v1,
+};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::globals::ToplevelData;
+use super::ServerState;
+use crate::compositor::icon::ICON_SIZE;
+use crate::compositor::{IconImage, SurfaceId, WindowIcon, WindowId};
+use crate::protocol::shm::ShmBufferId;
+use crate::renderer::convert::to_argb8888;
+
+/// An icon being built, which can't change once set on a toplevel
+#[derive(Debug, Default)]
+pub struct IconData(Mutex<(WindowIcon, bool)>);
+
+impl IconData {
+    /// Mark the icon as set on a toplevel, and get it
+    fn assign(&self) -> WindowIcon {
+        let mut data = self.0.lock().unwrap();
+        data.1 = true;
+        data.0.clone()
+    }
+}
+
+impl GlobalDispatch<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound xdg_toplevel_icon_manager_v1");
+        let manager = data_init.init(resource, ());
+        manager.icon_size(ICON_SIZE as i32);
+        manager.done();
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        _resource: &xdg_toplevel_icon_manager_v1::XdgToplevelIconManagerV1,
+        request: xdg_toplevel_icon_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            xdg_toplevel_icon_manager_v1::Request::CreateIcon { id } => {
+                data_init.init(id, IconData::default());
+            }
+            xdg_toplevel_icon_manager_v1::Request::SetIcon { toplevel, icon } => {
+                let Some(surface_id) = toplevel.data::<ToplevelData>().map(|d| d.surface_id) else {
+                    return;
+                };
+                let icon = icon
+                    .as_ref()
+                    .and_then(|icon| icon.data::<IconData>())
+                    .map(IconData::assign)
+                    .filter(|icon| !icon.is_empty());
+                state.resources.pending_icons.insert(surface_id, icon);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<xdg_toplevel_icon_v1::XdgToplevelIconV1, IconData> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &xdg_toplevel_icon_v1::XdgToplevelIconV1,
+        request: xdg_toplevel_icon_v1::Request,
+        data: &IconData,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let mut data = data.0.lock().unwrap();
+        let (icon, assigned) = &mut *data;
+        if *assigned && !matches!(request, xdg_toplevel_icon_v1::Request::Destroy) {
+            resource.post_error(
+                xdg_toplevel_icon_v1::Error::Immutable,
+                "the icon was already set on a toplevel",
+            );
+            return;
+        }
+        match request {
+            xdg_toplevel_icon_v1::Request::SetName { icon_name } => {
+                debug!("Icon name {}", icon_name);
+                icon.name = Some(icon_name);
+            }
+            xdg_toplevel_icon_v1::Request::AddBuffer { buffer, scale } => {
+                let shm_buffer = buffer
+                    .data::<ShmBufferId>()
+                    .and_then(|&id| state.shm.get_buffer(id).map(|b| (id, b.clone())));
+                let Some((id, shm_buffer)) = shm_buffer.filter(|(_, b)| b.width == b.height) else {
+                    resource.post_error(
+                        xdg_toplevel_icon_v1::Error::InvalidBuffer,
+                        "icon buffers must be square shm buffers",
+                    );
+                    return;
+                };
+                let pixels = state.shm.read_buffer_data(id).ok().and_then(|data| {
+                    to_argb8888(
+                        shm_buffer.format,
+                        &data,
+                        shm_buffer.width,
+                        shm_buffer.height,
+                        shm_buffer.stride,
+                    )
+                });
+                let Some(pixels) = pixels else {
+                    debug!("Icon buffer {:?} can't be read", id);
+                    return;
+                };
+                debug!(
+                    "Icon buffer {}x{} scale {}",
+                    shm_buffer.width, shm_buffer.width, scale
+                );
+                let size = shm_buffer.width;
+                icon.images
+                    .retain(|image| image.size != size || image.scale != scale);
+                icon.images.push(IconImage {
+                    size,
+                    scale,
+                    pixels,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ServerState {
+    /// Apply the icon set on a toplevel surface since its last commit
+    pub fn commit_toplevel_icon(&mut self, surface: SurfaceId) {
+        let Some(icon) = self.resources.pending_icons.remove(&surface) else {
+            return;
+        };
+        if let Some(window_id) = self.compositor.windows.window_for_surface(surface) {
+            self.set_window_icon(window_id, icon);
+        }
+    }
+
+    /// Set a window's icon, or reset it to the default one
+    pub fn set_window_icon(&mut self, window_id: WindowId, icon: Option<WindowIcon>) {
+        let Some(window) = self.compositor.windows.get_mut(window_id) else {
+            return;
+        };
+        debug!("Window {:?} icon set: {}", window_id, icon.is_some());
+        window.icon = icon;
+
+        #[cfg(target_os = "macos")]
+        if let Some(native) = self.backend.native_window(window_id) {
+            native.set_icon(window.icon.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toplevel_icon_applied_on_commit() {
+        let mut state = ServerState::new();
+        let surface = state.compositor.surfaces.create_surface();
+        let window = state.compositor.windows.create_window(surface);
+        let icon = WindowIcon {
+            name: Some("editor".to_string()),
+            images: Vec::new(),
+        };
+        state
+            .resources
+            .pending_icons
+            .insert(surface, Some(icon.clone()));
+        assert_eq!(state.compositor.windows.get(window).unwrap().icon, None);
+
+        state.commit_toplevel_icon(surface);
+        assert_eq!(
+            state.compositor.windows.get(window).unwrap().icon,
+            Some(icon)
+        );
+
+        // A null icon resets it
+        state.resources.pending_icons.insert(surface, None);
+        state.commit_toplevel_icon(surface);
+        assert_eq!(state.compositor.windows.get(window).unwrap().icon, None);
+    }
+}
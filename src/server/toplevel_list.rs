@@ -0,0 +1,241 @@
+//! ext_foreign_toplevel_list_v1 objects
+//!
+//! Lists every toplevel by an identifier that is never reused, with its
+//! title and app_id. A new list is sent the windows open when it is bound;
+//! after that, the [`WindowChange`]s the window manager records are taken
+//! after every dispatch and turned into new handles, updated titles and
+//! app_ids, and `closed` events.
+
+use log::debug;
+use wayland_protocols::ext::foreign_toplevel_list::v1::server::{
+    ext_foreign_toplevel_handle_v1, ext_foreign_toplevel_list_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::{Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::{Window, WindowChange, WindowId, WindowManager};
+
+/// Toplevel lists and the handles sent through them
+#[derive(Debug, Default)]
+pub struct ToplevelLists {
+    /// Lists that haven't stopped
+    lists: Vec<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1>,
+    /// Handles by window, until the window closes
+    handles: Vec<(
+        WindowId,
+        ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    )>,
+}
+
+/// Identifier of a window, unique for the compositor's lifetime
+fn identifier(window: WindowId) -> String {
+    format!("wayoa-window-{}", window.0)
+}
+
+/// Send a window's title and app_id, then `done`
+fn send_details(
+    handle: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    window: &Window,
+) {
+    if let Some(title) = &window.title {
+        handle.title(title.clone());
+    }
+    if let Some(app_id) = &window.app_id {
+        handle.app_id(app_id.clone());
+    }
+    handle.done();
+}
+
+impl ToplevelLists {
+    /// Send a list a handle for a window
+    fn announce(
+        &mut self,
+        dh: &DisplayHandle,
+        list: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        window: &Window,
+    ) {
+        let Some(client) = list.client() else {
+            return;
+        };
+        let Ok(handle) = client
+            .create_resource::<ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, _, ServerState>(
+                dh,
+                list.version(),
+                window.id,
+            )
+        else {
+            return;
+        };
+        list.toplevel(&handle);
+        handle.identifier(identifier(window.id));
+        send_details(&handle, window);
+        self.handles.push((window.id, handle));
+    }
+
+    /// Turn window changes into events
+    fn apply(&mut self, windows: &WindowManager, changes: Vec<WindowChange>) {
+        for change in changes {
+            match change {
+                WindowChange::Opened(id) => {
+                    let Some(window) = windows.get(id) else {
+                        continue;
+                    };
+                    for list in self.lists.clone() {
+                        if let Some(dh) = list.handle().upgrade().map(DisplayHandle::from) {
+                            self.announce(&dh, &list, window);
+                        }
+                    }
+                }
+                WindowChange::Renamed(id) => {
+                    let Some(window) = windows.get(id) else {
+                        continue;
+                    };
+                    for (_, handle) in self.handles.iter().filter(|(w, _)| *w == id) {
+                        send_details(handle, window);
+                    }
+                }
+                WindowChange::Closed(id) => self.handles.retain(|(window, handle)| {
+                    if *window == id {
+                        handle.closed();
+                    }
+                    *window != id
+                }),
+            }
+        }
+    }
+}
+
+impl GlobalDispatch<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        state: &mut Self,
+        handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound ext_foreign_toplevel_list_v1");
+        let list = data_init.init(resource, ());
+        let mut windows: Vec<&Window> = state
+            .compositor
+            .windows
+            .iter()
+            .map(|(_, window)| window)
+            .collect();
+        windows.sort_by_key(|window| window.id.0);
+        for window in windows {
+            state
+                .resources
+                .toplevel_lists
+                .announce(handle, &list, window);
+        }
+        state.resources.toplevel_lists.lists.push(list);
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        request: ext_foreign_toplevel_list_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Request::Stop = request {
+            debug!("Toplevel list stopped");
+            state
+                .resources
+                .toplevel_lists
+                .lists
+                .retain(|list| list != resource);
+            resource.finished();
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        _data: &(),
+    ) {
+        state
+            .resources
+            .toplevel_lists
+            .lists
+            .retain(|list| list != resource);
+    }
+}
+
+impl Dispatch<ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, WindowId>
+    for ServerState
+{
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        _request: ext_foreign_toplevel_handle_v1::Request,
+        _data: &WindowId,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        resource: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        _data: &WindowId,
+    ) {
+        state
+            .resources
+            .toplevel_lists
+            .handles
+            .retain(|(_, handle)| handle != resource);
+    }
+}
+
+impl ServerState {
+    /// Send toplevel lists the windows opened, renamed and closed since the
+    /// last dispatch
+    pub(super) fn update_toplevel_lists(&mut self) {
+        let changes = self.compositor.windows.take_changes();
+        if changes.is_empty() {
+            return;
+        }
+        self.resources
+            .toplevel_lists
+            .apply(&self.compositor.windows, changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::SurfaceId;
+
+    #[test]
+    fn test_identifiers_are_unique() {
+        let mut windows = WindowManager::new();
+        let first = windows.create_window(SurfaceId(1));
+        windows.remove(first);
+        let second = windows.create_window(SurfaceId(1));
+        assert_ne!(identifier(first), identifier(second));
+
+        // Changes are dropped while no list is bound
+        let mut lists = ToplevelLists::default();
+        let changes = windows.take_changes();
+        assert_eq!(changes.len(), 3);
+        lists.apply(&windows, changes);
+        assert!(lists.handles.is_empty());
+    }
+}
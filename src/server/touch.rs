@@ -0,0 +1,134 @@
+//! Touch points from the trackpad
+//!
+//! In touchscreen emulation mode the Cocoa backend reports the fingers on
+//! the trackpad, which the [`TouchEmulator`] turns into touch events for the
+//! wl_touch objects of the touched surface's client.
+//!
+//! [`TouchEmulator`]: crate::input::TouchEmulator
+
+use log::info;
+use wayland_server::Resource;
+
+use super::ServerState;
+use crate::compositor::SurfaceId;
+use crate::input::seat::SeatCapabilities;
+use crate::input::{Contact, TouchEmulator};
+use crate::protocol::seat::TouchEvent;
+
+impl ServerState {
+    /// Send the fingers now on the trackpad as touch points on the focused
+    /// surface
+    ///
+    /// Does nothing unless touchscreen emulation is enabled.
+    pub fn touch_contacts(&mut self, time: u32, contacts: &[Contact]) {
+        let Some(emulator) = &mut self.touch_emulation else {
+            return;
+        };
+        let focus = self.compositor.seat.keyboard_focus();
+        let surfaces = &self.compositor.surfaces;
+        let events = emulator.update(contacts, focus, |surface| {
+            let (width, height) = surfaces.get(surface)?.size()?;
+            Some((width as f64, height as f64))
+        });
+        if !events.is_empty() {
+            self.input_activity();
+        }
+        self.send_touch_events(time, events);
+    }
+
+    /// Turn touchscreen emulation on or off
+    ///
+    /// The seat gains or loses the touch capability with it, and touch
+    /// points still down are cancelled.
+    pub fn set_touch_emulation(&mut self, enabled: bool) {
+        if self.touch_emulation.is_some() == enabled {
+            return;
+        }
+        info!("Touch emulation {}", if enabled { "on" } else { "off" });
+        self.config.touch_emulation.enabled = enabled;
+        match self.touch_emulation.take() {
+            Some(mut emulator) => {
+                let events = emulator.cancel();
+                self.send_touch_events(0, events);
+            }
+            None => self.touch_emulation = TouchEmulator::new(&self.config.touch_emulation),
+        }
+        let capabilities = self.compositor.seat.capabilities();
+        self.set_seat_capabilities(SeatCapabilities {
+            touch: enabled,
+            ..capabilities
+        });
+    }
+
+    /// Send touch events to the touch objects of each surface's client
+    fn send_touch_events(&mut self, time: u32, events: Vec<(SurfaceId, TouchEvent)>) {
+        for (target, event) in events {
+            let Some(wl_surface) = self.resources.surfaces.get(&target) else {
+                continue;
+            };
+            let serial = match event {
+                TouchEvent::Down { .. } | TouchEvent::Up { .. } => self.compositor.next_serial(),
+                _ => 0,
+            };
+            if let TouchEvent::Down { .. } = event {
+                self.compositor.seat.record_input(serial, target);
+            }
+
+            for touch in self
+                .resources
+                .seats
+                .touches()
+                .iter()
+                .filter(|t| t.id().same_client_as(&wl_surface.id()))
+            {
+                match event {
+                    TouchEvent::Down { id, x, y, .. } => {
+                        touch.down(serial, time, wl_surface, id, x, y)
+                    }
+                    TouchEvent::Motion { id, x, y } => touch.motion(time, id, x, y),
+                    TouchEvent::Up { id } => touch.up(serial, time, id),
+                    TouchEvent::Frame => touch.frame(),
+                    TouchEvent::Cancel => touch.cancel(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, TouchEmulationConfig};
+
+    #[test]
+    fn test_touch_emulation_mode() {
+        let state = ServerState::new();
+        assert!(state.touch_emulation.is_none());
+        assert!(!state.compositor.seat.capabilities().touch);
+
+        let config = Config {
+            touch_emulation: TouchEmulationConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut state = ServerState::with_config(config);
+        assert!(state.compositor.seat.capabilities().touch);
+        let finger = Contact {
+            identity: 1,
+            x: 0.5,
+            y: 0.5,
+        };
+        // Nothing has focus to touch
+        state.touch_contacts(0, &[finger]);
+        assert_eq!(state.touch_emulation.as_ref().unwrap().target(), None);
+
+        state.set_touch_emulation(false);
+        assert!(state.touch_emulation.is_none());
+        assert!(!state.compositor.seat.capabilities().touch);
+        state.set_touch_emulation(true);
+        assert!(state.touch_emulation.is_some());
+        assert!(state.compositor.seat.capabilities().touch);
+    }
+}
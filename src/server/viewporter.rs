@@ -0,0 +1,163 @@
+//! wp_viewporter objects
+//!
+//! A wp_viewport crops a surface's buffer to a source rectangle and scales
+//! it to a destination size. Both are double-buffered surface state; the
+//! combination is checked against the buffer once the surface commits.
+
+use log::debug;
+use wayland_protocols::wp::viewporter::server::{wp_viewport, wp_viewporter};
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::compositor::{SurfaceId, ViewportError, ViewportSource};
+
+impl GlobalDispatch<wp_viewporter::WpViewporter, GlobalData> for ServerState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<wp_viewporter::WpViewporter>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound wp_viewporter");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<wp_viewporter::WpViewporter, ()> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_viewporter::WpViewporter,
+        request: wp_viewporter::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wp_viewporter::Request::GetViewport { id, surface } = request {
+            let Some(&surface_id) = surface.data::<SurfaceId>() else {
+                return;
+            };
+            if state.resources.viewports.contains_key(&surface_id) {
+                resource.post_error(
+                    wp_viewporter::Error::ViewportExists,
+                    "surface already has a viewport",
+                );
+                return;
+            }
+            let viewport = data_init.init(id, surface_id);
+            state.resources.viewports.insert(surface_id, viewport);
+        }
+    }
+}
+
+impl Dispatch<wp_viewport::WpViewport, SurfaceId> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &wp_viewport::WpViewport,
+        request: wp_viewport::Request,
+        surface_id: &SurfaceId,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let Some(surface) = state.compositor.surfaces.get_mut(*surface_id) else {
+            if !matches!(request, wp_viewport::Request::Destroy) {
+                resource.post_error(wp_viewport::Error::NoSurface, "surface was destroyed");
+            }
+            return;
+        };
+
+        match request {
+            wp_viewport::Request::SetSource {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                // All -1 unsets the source rectangle
+                if [x, y, width, height].iter().all(|&v| v == -1.0) {
+                    surface.update_viewport(|viewport| viewport.source = None);
+                } else if x < 0.0 || y < 0.0 || width <= 0.0 || height <= 0.0 {
+                    resource.post_error(
+                        wp_viewport::Error::BadValue,
+                        format!("invalid source ({}, {}, {}, {})", x, y, width, height),
+                    );
+                } else {
+                    surface.update_viewport(|viewport| {
+                        viewport.source = Some(ViewportSource {
+                            x,
+                            y,
+                            width,
+                            height,
+                        })
+                    });
+                }
+            }
+            wp_viewport::Request::SetDestination { width, height } => {
+                if width == -1 && height == -1 {
+                    surface.update_viewport(|viewport| viewport.destination = None);
+                } else if width <= 0 || height <= 0 {
+                    resource.post_error(
+                        wp_viewport::Error::BadValue,
+                        format!("invalid destination {}x{}", width, height),
+                    );
+                } else {
+                    surface
+                        .update_viewport(|viewport| viewport.destination = Some((width, height)));
+                }
+            }
+            wp_viewport::Request::Destroy => {
+                // The viewport goes away with the next commit
+                surface.update_viewport(|viewport| *viewport = Default::default());
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: wayland_server::backend::ClientId,
+        resource: &wp_viewport::WpViewport,
+        data: &SurfaceId,
+    ) {
+        if state
+            .resources
+            .viewports
+            .get(data)
+            .is_some_and(|viewport| viewport.id() == resource.id())
+        {
+            state.resources.viewports.remove(data);
+        }
+    }
+}
+
+impl ServerState {
+    /// Check the viewports of committed surfaces against their buffers
+    pub fn check_viewports(&self, committed: &[SurfaceId]) {
+        for id in committed {
+            let (Some(viewport), Some(surface)) = (
+                self.resources.viewports.get(id),
+                self.compositor.surfaces.get(*id),
+            ) else {
+                continue;
+            };
+            if surface.buffer.is_none() {
+                continue;
+            }
+            if let Err(e) = surface.buffer_geometry().check_viewport() {
+                let code = match e {
+                    ViewportError::BadSize => wp_viewport::Error::BadSize,
+                    ViewportError::OutOfBuffer => wp_viewport::Error::OutOfBuffer,
+                };
+                viewport.post_error(code, e.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! zwp_virtual_keyboard_manager_v1 objects
+//!
+//! On-screen keyboards and automation tools send keys as if typed on the
+//! seat's keyboard. Injected keys and modifiers go through the same
+//! keyboard state as hardware keys, so focus, key tracking, sticky
+//! modifiers and the input method's grab all see them. A virtual keyboard
+//! must set a keymap before sending keys; while it types with a keymap
+//! other than the seat's, its keymap is the seat's, and the replaced one
+//! comes back with the next hardware key. Keys a virtual keyboard still
+//! holds when it's destroyed are released.
+
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::FileExt;
+use std::sync::Mutex;
+
+use log::{debug, warn};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::server::{
+    zwp_virtual_keyboard_manager_v1, zwp_virtual_keyboard_v1,
+};
+use wayland_server::backend::ClientId;
+use wayland_server::protocol::wl_keyboard;
+use wayland_server::{Client, DataInit, Dispatch, GlobalDispatch, New, Resource};
+
+use super::client::GlobalData;
+use super::ServerState;
+use crate::input::keyboard::ModifierState;
+
+/// Largest keymap read from a virtual keyboard
+const MAX_KEYMAP_SIZE: u32 = 1 << 20;
+
+/// Keymap swaps made for virtual keyboards
+#[derive(Debug, Default)]
+pub struct VirtualKeyboards {
+    /// The seat's keymap from before a virtual keyboard replaced it
+    replaced_keymap: Option<String>,
+}
+
+/// A virtual keyboard's keymap and the keys it holds
+#[derive(Debug, Default)]
+pub struct VirtualKeyboardData(Mutex<VirtualKeyboard>);
+
+#[derive(Debug, Default)]
+struct VirtualKeyboard {
+    keymap: Option<String>,
+    pressed: Vec<u32>,
+}
+
+impl GlobalDispatch<zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1, GlobalData>
+    for ServerState
+{
+    fn bind(
+        _state: &mut Self,
+        _handle: &wayland_server::DisplayHandle,
+        _client: &Client,
+        resource: New<zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1>,
+        _global_data: &GlobalData,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        debug!("Client bound zwp_virtual_keyboard_manager_v1");
+        data_init.init(resource, ());
+    }
+
+    fn can_view(client: Client, global_data: &GlobalData) -> bool {
+        global_data.can_view(&client)
+    }
+}
+
+impl Dispatch<zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1, ()> for ServerState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _resource: &zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1,
+        request: zwp_virtual_keyboard_manager_v1::Request,
+        _data: &(),
+        _dhandle: &wayland_server::DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let zwp_virtual_keyboard_manager_v1::Request::CreateVirtualKeyboard { seat: _, id } =
+            request
+        {
+            debug!("Creating virtual keyboard");
+            data_init.init(id, VirtualKeyboardData::default());
+        }
+    }
+}
+
+impl Dispatch<zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1, VirtualKeyboardData> for ServerState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        resource: &zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+        request: zwp_virtual_keyboard_v1::Request,
+        data: &VirtualKeyboardData,
+        _dhandle: &wayland_server::DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let mut keyboard = data.0.lock().unwrap();
+        match request {
+            zwp_virtual_keyboard_v1::Request::Keymap { format, fd, size } => {
+                if format != wl_keyboard::KeymapFormat::XkbV1 as u32 {
+                    debug!("Ignoring virtual keyboard keymap of format {}", format);
+                    return;
+                }
+                match read_keymap(fd, size) {
+                    Ok(keymap) => keyboard.keymap = Some(keymap),
+                    Err(e) => warn!("Can't read virtual keyboard keymap: {}", e),
+                }
+            }
+            zwp_virtual_keyboard_v1::Request::Key {
+                time,
+                key,
+                state: key_state,
+            } => {
+                let Some(keymap) = keyboard.keymap.clone() else {
+                    resource.post_error(
+                        zwp_virtual_keyboard_v1::Error::NoKeymap,
+                        "no keymap was set",
+                    );
+                    return;
+                };
+                let pressed = key_state == wl_keyboard::KeyState::Pressed as u32;
+                keyboard.pressed.retain(|&held| held != key);
+                if pressed {
+                    keyboard.pressed.push(key);
+                }
+                drop(keyboard);
+                state.use_virtual_keymap(keymap);
+                state.seat_key(time, key, pressed);
+            }
+            zwp_virtual_keyboard_v1::Request::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+            } => {
+                let Some(keymap) = keyboard.keymap.clone() else {
+                    resource.post_error(
+                        zwp_virtual_keyboard_v1::Error::NoKeymap,
+                        "no keymap was set",
+                    );
+                    return;
+                };
+                drop(keyboard);
+                state.use_virtual_keymap(keymap);
+                state.keyboard_modifiers(ModifierState {
+                    depressed: mods_depressed,
+                    latched: mods_latched,
+                    locked: mods_locked,
+                    group,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        _resource: &zwp_virtual_keyboard_v1::ZwpVirtualKeyboardV1,
+        data: &VirtualKeyboardData,
+    ) {
+        let pressed = std::mem::take(&mut data.0.lock().unwrap().pressed);
+        for key in pressed {
+            debug!("Releasing key {} of a destroyed virtual keyboard", key);
+            state.seat_key(0, key, false);
+        }
+    }
+}
+
+/// Read a keymap sent as a file, without its NUL terminator
+fn read_keymap(fd: OwnedFd, size: u32) -> std::io::Result<String> {
+    if size > MAX_KEYMAP_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "keymap too large",
+        ));
+    }
+    let mut contents = vec![0; size as usize];
+    std::fs::File::from(fd).read_exact_at(&mut contents, 0)?;
+    let end = contents
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(contents.len());
+    contents.truncate(end);
+    String::from_utf8(contents).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+impl ServerState {
+    /// Make a virtual keyboard's keymap the seat's, remembering the keymap
+    /// it replaces
+    fn use_virtual_keymap(&mut self, keymap: String) {
+        let current = self.compositor.seat.keyboard().keymap();
+        if current == Some(keymap.as_str()) {
+            return;
+        }
+        debug!("Switching to a virtual keyboard's keymap");
+        let replaced = current.map(str::to_string);
+        let saved = &mut self.resources.virtual_keyboards.replaced_keymap;
+        if saved.is_none() {
+            *saved = replaced;
+        }
+        self.set_keymap(keymap);
+    }
+
+    /// Bring back the keymap a virtual keyboard replaced, before a
+    /// hardware key
+    pub(super) fn restore_hardware_keymap(&mut self) {
+        if let Some(keymap) = self.resources.virtual_keyboards.replaced_keymap.take() {
+            debug!("Switching back to the hardware keymap");
+            self.set_keymap(keymap);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virtual_keymap_is_restored() {
+        let mut state = ServerState::new();
+        state.set_keymap("hardware".to_string());
+
+        state.use_virtual_keymap("virtual".to_string());
+        assert_eq!(state.compositor.seat.keyboard().keymap(), Some("virtual"));
+        state.use_virtual_keymap("other".to_string());
+        state.seat_key(0, 30, true);
+        assert_eq!(state.compositor.seat.keyboard().pressed_keys(), [30]);
+
+        // A hardware key brings the seat's own keymap back
+        state.keyboard_key(0, 30, false);
+        assert_eq!(state.compositor.seat.keyboard().keymap(), Some("hardware"));
+        assert!(state.compositor.seat.keyboard().pressed_keys().is_empty());
+    }
+
+    #[test]
+    fn test_read_keymap() {
+        let mut file = tempfile::tempfile().unwrap();
+        std::io::Write::write_all(&mut file, b"xkb_keymap {};\0").unwrap();
+        let keymap = read_keymap(file.into(), 15).unwrap();
+        assert_eq!(keymap, "xkb_keymap {};");
+    }
+}
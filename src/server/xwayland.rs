@@ -0,0 +1,140 @@
+//! Windows of X11 clients
+//!
+//! The window manager in [`crate::xwayland`] maps X11 toplevels to the
+//! wl_surfaces Xwayland draws them into. The compositor treats those
+//! surfaces like xdg_toplevels, except that closing them has to go through
+//! the X connection, so close requests are queued for the window manager.
+
+use std::collections::HashSet;
+
+use log::debug;
+
+use super::ServerState;
+use crate::compositor::{SurfaceId, SurfaceRole, WindowId};
+use crate::session::JournalEvent;
+
+/// Windows mapped for X11 clients
+#[derive(Debug, Default)]
+pub struct XwaylandWindows {
+    /// Windows of X11 toplevels
+    windows: HashSet<WindowId>,
+    /// Windows the user asked to close, oldest first
+    close_requests: Vec<WindowId>,
+}
+
+impl XwaylandWindows {
+    /// Check if a window belongs to an X11 client
+    pub fn contains(&self, window_id: WindowId) -> bool {
+        self.windows.contains(&window_id)
+    }
+
+    /// Ask the X11 client owning a window to close it
+    ///
+    /// Returns false if the window doesn't belong to an X11 client.
+    pub fn request_close(&mut self, window_id: WindowId) -> bool {
+        if !self.contains(window_id) {
+            return false;
+        }
+        self.close_requests.push(window_id);
+        true
+    }
+
+    /// Take the close requests since they were last taken
+    pub fn take_close_requests(&mut self) -> Vec<WindowId> {
+        std::mem::take(&mut self.close_requests)
+    }
+}
+
+impl ServerState {
+    /// Create a window for the surface of an X11 toplevel
+    ///
+    /// The native window is created right away, or on the surface's first
+    /// commit. Returns None if the surface already has a role.
+    pub fn map_xwayland_surface(&mut self, surface_id: SurfaceId) -> Option<WindowId> {
+        if self
+            .compositor
+            .windows
+            .window_for_surface(surface_id)
+            .is_some()
+        {
+            return None;
+        }
+        let surface = self.compositor.surfaces.get_mut(surface_id)?;
+        if surface.set_role(SurfaceRole::Xwayland).is_err() {
+            debug!("X11 surface {:?} already has a role", surface_id);
+            return None;
+        }
+        let window_id = self.compositor.windows.create_window(surface_id);
+        self.xwayland.windows.insert(window_id);
+        self.journal.record(JournalEvent::WindowCreated {
+            window: window_id.0,
+            client: None,
+        });
+        // Xwayland may have committed the surface before the window was
+        // mapped
+        #[cfg(target_os = "macos")]
+        self.update_native_window(surface_id);
+        Some(window_id)
+    }
+
+    /// Remove the window of an X11 toplevel that was unmapped
+    pub fn unmap_xwayland_window(&mut self, window_id: WindowId) {
+        if !self.xwayland.windows.remove(&window_id) {
+            return;
+        }
+        self.xwayland.close_requests.retain(|id| *id != window_id);
+
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.remove_native_window(window_id) {
+            native_window.close();
+        }
+
+        self.scheduler.remove_window(window_id);
+        if let Some(window) = self.compositor.windows.remove(window_id) {
+            self.journal.record(JournalEvent::WindowClosed {
+                window: window.id.0,
+                title: window.title,
+                app_id: window.app_id,
+            });
+        }
+
+        #[cfg(target_os = "macos")]
+        self.sync_dialog_windows();
+    }
+
+    /// Set the title of an X11 toplevel's window
+    pub fn set_xwayland_title(&mut self, window_id: WindowId, title: String) {
+        #[cfg(target_os = "macos")]
+        if let Some(native_window) = self.backend.native_window(window_id) {
+            native_window.set_title(&title);
+        }
+        self.compositor.windows.set_title(window_id, title);
+    }
+
+    /// Set the app_id of an X11 toplevel's window, from its WM_CLASS
+    pub fn set_xwayland_app_id(&mut self, window_id: WindowId, app_id: String) {
+        self.compositor.windows.set_app_id(window_id, app_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_and_close() {
+        let mut state = ServerState::new();
+        let surface_id = state.compositor.surfaces.create_surface();
+        let window_id = state.map_xwayland_surface(surface_id).unwrap();
+        assert!(state.xwayland.contains(window_id));
+        // The surface can't be mapped twice
+        assert!(state.map_xwayland_surface(surface_id).is_none());
+
+        assert!(state.xwayland.request_close(window_id));
+        assert_eq!(state.xwayland.take_close_requests(), vec![window_id]);
+
+        state.unmap_xwayland_window(window_id);
+        assert!(!state.xwayland.request_close(window_id));
+        assert!(state.compositor.windows.get(window_id).is_none());
+    }
+}
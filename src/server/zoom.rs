@@ -0,0 +1,180 @@
+//! Window zoom actions
+//!
+//! Zooming in and out changes a window's [`Zoom`], which the presented
+//! content and pointer mapping follow. Zooming into an unzoomed window
+//! centers on the pointer if it is over the window.
+
+use log::{info, warn};
+
+use super::ServerState;
+use crate::compositor::{CompositorAction, WindowId, Zoom};
+use crate::config::ZoomConfig;
+use crate::input::KeyBinding;
+
+/// A change to a window's zoom
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoomChange {
+    /// Magnify by one step
+    In,
+    /// Shrink by one step
+    Out,
+    /// Show the content at its size
+    Reset,
+    /// Set the magnification
+    To(f64),
+}
+
+impl ZoomChange {
+    /// Parse `in`, `out`, `reset` or a factor
+    pub fn parse(change: &str) -> Option<Self> {
+        match change {
+            "in" => Some(ZoomChange::In),
+            "out" => Some(ZoomChange::Out),
+            "reset" => Some(ZoomChange::Reset),
+            factor => factor
+                .parse()
+                .ok()
+                .filter(|factor: &f64| factor.is_finite() && *factor > 0.0)
+                .map(ZoomChange::To),
+        }
+    }
+
+    /// Get the magnification after the change
+    fn apply(self, factor: f64) -> f64 {
+        match self {
+            ZoomChange::In => factor * Zoom::STEP,
+            ZoomChange::Out => factor / Zoom::STEP,
+            ZoomChange::Reset => 1.0,
+            ZoomChange::To(factor) => factor,
+        }
+    }
+}
+
+/// Parse the configured zoom keybindings
+pub(super) fn zoom_bindings(config: &ZoomConfig) -> Vec<(KeyBinding, CompositorAction)> {
+    [
+        (&config.zoom_in, CompositorAction::ZoomIn),
+        (&config.zoom_out, CompositorAction::ZoomOut),
+        (&config.reset, CompositorAction::ZoomReset),
+    ]
+    .into_iter()
+    .filter_map(
+        |(binding, action)| match KeyBinding::parse(binding.as_deref()?) {
+            Ok(binding) => Some((binding, action)),
+            Err(e) => {
+                warn!("Invalid {} keybinding: {}", action.name(), e);
+                None
+            }
+        },
+    )
+    .collect()
+}
+
+/// Zoom errors
+#[derive(Debug, thiserror::Error)]
+pub enum ZoomError {
+    #[error("No focused window")]
+    NoFocusedWindow,
+    #[error("No window {0}")]
+    NoSuchWindow(u64),
+}
+
+impl ServerState {
+    /// Zoom a window, the focused one if none is given
+    ///
+    /// Returns the window and its new magnification.
+    pub fn zoom_window(
+        &mut self,
+        window: Option<WindowId>,
+        change: ZoomChange,
+    ) -> Result<(WindowId, f64), ZoomError> {
+        let window_id = match window {
+            Some(id) => id,
+            None => self
+                .compositor
+                .windows
+                .focused()
+                .map(|window| window.id)
+                .ok_or(ZoomError::NoFocusedWindow)?,
+        };
+        let pointer = self.compositor.seat.pointer();
+        let (focus, (px, py)) = (pointer.focus(), pointer.position());
+        let window = self
+            .compositor
+            .windows
+            .get_mut(window_id)
+            .ok_or(ZoomError::NoSuchWindow(window_id.0))?;
+
+        if !window.zoom.is_zoomed() && focus == Some(window.surface_id) {
+            let size = self
+                .compositor
+                .surfaces
+                .get(window.surface_id)
+                .and_then(|surface| surface.size());
+            if let Some((width, height)) = size.filter(|&(w, h)| w > 0 && h > 0) {
+                window
+                    .zoom
+                    .set_center(px / width as f64, py / height as f64);
+            }
+        }
+        window.zoom.set_factor(change.apply(window.zoom.factor()));
+        let factor = window.zoom.factor();
+        info!("Window {:?} zoomed to {:.2}x", window_id, factor);
+
+        #[cfg(target_os = "macos")]
+        self.present_window(window_id);
+        Ok((window_id, factor))
+    }
+
+    /// Zoom the focused window, logging why not if it can't be
+    pub(super) fn zoom_focused_window(&mut self, change: ZoomChange) {
+        if let Err(e) = self.zoom_window(None, change) {
+            warn!("Can't zoom window: {}", e);
+        }
+    }
+
+    /// Zoom the focused window if a key press is a zoom binding
+    ///
+    /// Returns true if the key was taken, in which case it must not reach
+    /// clients.
+    pub fn zoom_key(&mut self, key: u32, modifiers: u32) -> bool {
+        let action = self
+            .zoom_bindings
+            .iter()
+            .find(|(binding, _)| binding.matches(key, modifiers))
+            .map(|&(_, action)| action);
+        if let Some(action) = action {
+            self.perform_action(action);
+        }
+        action.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zoom_window() {
+        let mut state = ServerState::new();
+        assert!(matches!(
+            state.zoom_window(None, ZoomChange::In),
+            Err(ZoomError::NoFocusedWindow)
+        ));
+
+        let surface = state.compositor.surfaces.create_surface();
+        let window = state.compositor.windows.create_window(surface);
+        state.compositor.windows.set_focused(Some(window));
+        assert_eq!(state.zoom_window(None, ZoomChange::To(2.0)).unwrap().1, 2.0);
+        assert_eq!(
+            state.zoom_window(Some(window), ZoomChange::In).unwrap().1,
+            2.5
+        );
+        assert_eq!(state.zoom_window(None, ZoomChange::Reset).unwrap().1, 1.0);
+        assert_eq!(state.zoom_window(None, ZoomChange::Out).unwrap().1, 1.0);
+
+        assert_eq!(ZoomChange::parse("1.5"), Some(ZoomChange::To(1.5)));
+        assert_eq!(ZoomChange::parse("-2"), None);
+        assert!(!state.zoom_key(25, 0));
+    }
+}
@@ -0,0 +1,255 @@
+//! Session journal
+//!
+//! A timestamped record of what happened during a session: clients
+//! connecting and disconnecting, windows opening and closing, and spawned
+//! clients that crashed. Recent entries are kept in memory for
+//! `wayoactl journal`, and every entry is appended as a JSON line to
+//! `journal.jsonl` in the state directory, so "the window disappeared at
+//! 14:32" can be matched to what the compositor saw.
+//!
+//! The journal file is rotated to `journal.jsonl.old` at startup once it
+//! grows past a megabyte.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{state_dir, SessionError};
+
+/// Entries kept in memory
+const MAX_ENTRIES: usize = 1000;
+
+/// Size at which the journal file is rotated at startup
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// Something that happened during the session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum JournalEvent {
+    /// The compositor started
+    Started { pid: u32 },
+    /// A Wayland client connected
+    ClientConnected {
+        client: u64,
+        pid: Option<u32>,
+        executable: Option<String>,
+        /// Tag of the socket it connected through
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        context: Option<String>,
+    },
+    /// A Wayland client disconnected
+    ClientDisconnected {
+        client: u64,
+        executable: Option<String>,
+        reason: String,
+    },
+    /// A client created a toplevel window
+    WindowCreated { window: u64, client: Option<u64> },
+    /// A toplevel window was destroyed
+    WindowClosed {
+        window: u64,
+        title: Option<String>,
+        app_id: Option<String>,
+    },
+    /// A client spawned by wayoa exited abnormally
+    ClientCrashed {
+        pid: u32,
+        command: Vec<String>,
+        status: String,
+    },
+}
+
+/// A journal entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Wall-clock time in UTC (RFC 3339)
+    pub time: String,
+    /// Seconds since the compositor started
+    pub uptime: f64,
+    /// What happened
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+/// Journal state shared between clones
+#[derive(Debug)]
+struct Inner {
+    /// Recent entries, oldest first
+    entries: VecDeque<JournalEntry>,
+    /// Journal file entries are appended to
+    file: Option<File>,
+}
+
+/// The session journal
+///
+/// Cheap to clone; clones share the same journal, so client data can
+/// record disconnects without access to the server state.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    /// When the compositor started
+    started: Instant,
+    /// Wall-clock start time
+    started_at: SystemTime,
+    /// Entries and the journal file
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Journal {
+    /// Create an in-memory journal
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            inner: Arc::new(Mutex::new(Inner {
+                entries: VecDeque::new(),
+                file: None,
+            })),
+        }
+    }
+
+    /// Get the default journal file path
+    pub fn default_path() -> Option<PathBuf> {
+        Some(state_dir()?.join("journal.jsonl"))
+    }
+
+    /// Also append entries to a file, rotating it if it grew too large
+    pub fn open(&self, path: &Path) -> Result<(), SessionError> {
+        let io_error =
+            |path: &Path, e: std::io::Error| SessionError::Io(path.to_path_buf(), e.to_string());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+        }
+        if std::fs::metadata(path).is_ok_and(|meta| meta.len() > MAX_FILE_SIZE) {
+            let old = path.with_extension("jsonl.old");
+            std::fs::rename(path, &old).map_err(|e| io_error(&old, e))?;
+        }
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| io_error(path, e))?;
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.file = Some(file);
+        }
+        Ok(())
+    }
+
+    /// Record an event
+    pub fn record(&self, event: JournalEvent) {
+        let entry = JournalEntry {
+            time: format_utc(SystemTime::now()),
+            uptime: self.uptime().as_secs_f64(),
+            event,
+        };
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if let Some(file) = &mut inner.file {
+            let written = serde_json::to_string(&entry)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line));
+            if let Err(e) = written {
+                // Keep journaling in memory rather than warn on every entry
+                warn!("Failed to write journal, no longer persisting it: {}", e);
+                inner.file = None;
+            }
+        }
+        if inner.entries.len() == MAX_ENTRIES {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(entry);
+    }
+
+    /// Recent entries, oldest first
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.inner
+            .lock()
+            .map(|inner| inner.entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Time since the compositor started
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// When the compositor started, in UTC (RFC 3339)
+    pub fn started_at(&self) -> String {
+        format_utc(self.started_at)
+    }
+}
+
+/// Format a time as RFC 3339 in UTC, to the second
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, day_secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_217_120);
+        assert_eq!(format_utc(time), "2024-02-29T14:32:00Z");
+    }
+
+    #[test]
+    fn test_record_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wayoa/journal.jsonl");
+        let journal = Journal::new();
+        journal.open(&path).unwrap();
+
+        journal.clone().record(JournalEvent::WindowClosed {
+            window: 3,
+            title: Some("Mail".to_string()),
+            app_id: None,
+        });
+        let entries = journal.entries();
+        assert_eq!(entries.len(), 1);
+
+        let line = std::fs::read_to_string(&path).unwrap();
+        let persisted: JournalEntry = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(persisted, entries[0]);
+        assert!(line.contains(r#""event":"window-closed""#));
+    }
+}
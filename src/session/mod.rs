@@ -0,0 +1,316 @@
+//! Session management
+//!
+//! Wayoa remembers which clients it launched and where their windows were
+//! when it shuts down. On the next start it can re-launch them and put each
+//! window back in place once a toplevel with the matching app_id appears.
+//!
+//! The snapshot is stored as TOML in the state directory:
+//! - `$XDG_STATE_HOME/wayoa/session.toml`
+//! - `$HOME/.local/state/wayoa/session.toml`
+//!
+//! The session journal is kept next to it.
+
+pub mod journal;
+pub mod spawn;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::compositor::{WindowGeometry, WindowId};
+use crate::config::SessionConfig;
+
+pub use journal::{Journal, JournalEntry, JournalEvent};
+pub use spawn::{SpawnRecord, Spawner};
+
+/// Get wayoa's state directory
+pub fn state_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+        })?;
+    Some(base.join("wayoa"))
+}
+
+/// A client that was running when the session was saved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedClient {
+    /// Command line used to launch the client
+    pub command: Vec<String>,
+    /// Application ID of the client's toplevel
+    #[serde(default)]
+    pub app_id: Option<String>,
+    /// Screen placement of the client's window
+    #[serde(default)]
+    pub placement: Option<WindowGeometry>,
+}
+
+/// Saved session state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    /// Clients to re-launch
+    #[serde(default)]
+    pub clients: Vec<SavedClient>,
+}
+
+impl SessionSnapshot {
+    /// Parse a snapshot from a TOML string
+    pub fn from_toml(source: &str) -> Result<Self, SessionError> {
+        toml::from_str(source).map_err(|e| SessionError::Parse(e.to_string()))
+    }
+
+    /// Serialize the snapshot to a TOML string
+    pub fn to_toml(&self) -> Result<String, SessionError> {
+        toml::to_string(self).map_err(|e| SessionError::Serialize(e.to_string()))
+    }
+
+    /// Load a snapshot from a file
+    pub fn load_from(path: &Path) -> Result<Self, SessionError> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| SessionError::Io(path.to_path_buf(), e.to_string()))?;
+        Self::from_toml(&source)
+    }
+
+    /// Save the snapshot to a file, creating parent directories as needed
+    pub fn save_to(&self, path: &Path) -> Result<(), SessionError> {
+        let source = self.to_toml()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| SessionError::Io(parent.to_path_buf(), e.to_string()))?;
+        }
+        std::fs::write(path, source)
+            .map_err(|e| SessionError::Io(path.to_path_buf(), e.to_string()))
+    }
+}
+
+/// Session manager
+///
+/// Owns the spawner and the placements waiting for restored windows.
+#[derive(Debug, Default)]
+pub struct SessionManager {
+    /// Spawned client processes
+    pub spawner: Spawner,
+    /// Saved placements by app_id, waiting for a matching toplevel
+    pending: HashMap<String, Vec<WindowGeometry>>,
+}
+
+impl SessionManager {
+    /// Create a new session manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the default snapshot file path
+    pub fn default_path() -> Option<PathBuf> {
+        Some(state_dir()?.join("session.toml"))
+    }
+
+    /// Re-launch the last session and autostart clients
+    ///
+    /// Returns the number of clients launched.
+    pub fn startup(&mut self, config: &SessionConfig) -> usize {
+        let snapshot = if config.restore {
+            Self::default_path()
+                .filter(|path| path.exists())
+                .and_then(|path| match SessionSnapshot::load_from(&path) {
+                    Ok(snapshot) => Some(snapshot),
+                    Err(e) => {
+                        warn!("Failed to load session: {}", e);
+                        None
+                    }
+                })
+                .unwrap_or_default()
+        } else {
+            SessionSnapshot::default()
+        };
+
+        let mut launched = 0;
+        for command in self.plan(config, &snapshot) {
+            match self.spawner.spawn(&command) {
+                Ok(_) => launched += 1,
+                Err(e) => warn!("Failed to launch {:?}: {}", command, e),
+            }
+        }
+        info!("Session startup launched {} clients", launched);
+        launched
+    }
+
+    /// Work out which commands to launch at startup
+    ///
+    /// Restored clients come first, followed by autostart entries that are
+    /// not already part of the restored session. Placements of restored
+    /// clients are queued for their app_ids.
+    pub fn plan(&mut self, config: &SessionConfig, snapshot: &SessionSnapshot) -> Vec<Vec<String>> {
+        let mut commands: Vec<Vec<String>> = Vec::new();
+
+        for client in &snapshot.clients {
+            if client.command.is_empty() {
+                continue;
+            }
+            if let (Some(app_id), Some(placement)) = (&client.app_id, client.placement) {
+                self.pending
+                    .entry(app_id.clone())
+                    .or_default()
+                    .push(placement);
+            }
+            commands.push(client.command.clone());
+        }
+
+        for entry in &config.autostart {
+            let command: Vec<String> = entry.split_whitespace().map(String::from).collect();
+            if !command.is_empty() && !commands.contains(&command) {
+                commands.push(command);
+            }
+        }
+
+        commands
+    }
+
+    /// Take the saved placement for a newly mapped toplevel
+    pub fn take_placement(&mut self, app_id: &str) -> Option<WindowGeometry> {
+        let placements = self.pending.get_mut(app_id)?;
+        let placement = (!placements.is_empty()).then(|| placements.remove(0));
+        if placements.is_empty() {
+            self.pending.remove(app_id);
+        }
+        if placement.is_some() {
+            debug!("Restoring placement for {}", app_id);
+        }
+        placement
+    }
+
+    /// Build a snapshot of the running spawned clients
+    ///
+    /// `placement` looks up the current placement of a window. Each client
+    /// is saved with the placement of its own toplevel, so clients sharing
+    /// an app_id keep their own places.
+    pub fn snapshot(
+        &self,
+        placement: impl Fn(WindowId) -> Option<WindowGeometry>,
+    ) -> SessionSnapshot {
+        let clients = self
+            .spawner
+            .records()
+            .map(|record| SavedClient {
+                command: record.command.clone(),
+                app_id: record.app_id.clone(),
+                placement: record.window.and_then(&placement),
+            })
+            .collect();
+        SessionSnapshot { clients }
+    }
+
+    /// Save a snapshot to the default location
+    pub fn save(&self, snapshot: &SessionSnapshot) -> Result<(), SessionError> {
+        let path = Self::default_path().ok_or(SessionError::NoStateDir)?;
+        snapshot.save_to(&path)?;
+        info!(
+            "Saved session with {} clients to {}",
+            snapshot.clients.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Session errors
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SessionError {
+    #[error("No state directory available")]
+    NoStateDir,
+    #[error("Failed to access {0}: {1}")]
+    Io(PathBuf, String),
+    #[error("Invalid session file: {0}")]
+    Parse(String),
+    #[error("Failed to serialize session: {0}")]
+    Serialize(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry(x: i32) -> WindowGeometry {
+        WindowGeometry {
+            x,
+            y: 100,
+            width: 800,
+            height: 600,
+        }
+    }
+
+    fn snapshot() -> SessionSnapshot {
+        SessionSnapshot {
+            clients: vec![
+                SavedClient {
+                    command: vec!["foot".to_string()],
+                    app_id: Some("foot".to_string()),
+                    placement: Some(geometry(10)),
+                },
+                SavedClient {
+                    command: vec!["foot".to_string()],
+                    app_id: Some("foot".to_string()),
+                    placement: Some(geometry(20)),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let snapshot = snapshot();
+        let source = snapshot.to_toml().unwrap();
+        assert_eq!(SessionSnapshot::from_toml(&source).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_plan_dedupes_autostart() {
+        let mut session = SessionManager::new();
+        let config = SessionConfig {
+            restore: true,
+            autostart: vec![
+                "foot".to_string(),
+                "weston-terminal --maximized".to_string(),
+            ],
+        };
+        let commands = session.plan(&config, &snapshot());
+        assert_eq!(
+            commands,
+            vec![
+                vec!["foot".to_string()],
+                vec!["foot".to_string()],
+                vec!["weston-terminal".to_string(), "--maximized".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_snapshot_per_window() {
+        let mut session = SessionManager::new();
+        for window in [WindowId(1), WindowId(2)] {
+            let pid = session.spawner.spawn(&["true".to_string()]).unwrap();
+            session.spawner.associate_app_id(pid, "foot", window);
+        }
+
+        let snapshot = session.snapshot(|window| Some(geometry(window.0 as i32 * 10)));
+        let placements: Vec<_> = snapshot
+            .clients
+            .iter()
+            .map(|client| client.placement.map(|g| g.x))
+            .collect();
+        assert_eq!(placements, vec![Some(10), Some(20)]);
+    }
+
+    #[test]
+    fn test_take_placement() {
+        let mut session = SessionManager::new();
+        session.plan(&SessionConfig::default(), &snapshot());
+        assert_eq!(session.take_placement("foot").map(|g| g.x), Some(10));
+        assert_eq!(session.take_placement("foot").map(|g| g.x), Some(20));
+        assert_eq!(session.take_placement("foot"), None);
+        assert_eq!(session.take_placement("other"), None);
+    }
+}
@@ -0,0 +1,187 @@
+//! Client process spawning
+//!
+//! Keeps a record of every client process wayoa launched, so the session
+//! can be restored on the next start.
+
+use std::process::{Child, Command, ExitStatus};
+
+use log::{debug, info};
+
+use crate::compositor::WindowId;
+
+/// A client process launched by wayoa
+#[derive(Debug)]
+pub struct SpawnRecord {
+    /// Process ID
+    pub pid: u32,
+    /// Command line (program followed by arguments)
+    pub command: Vec<String>,
+    /// Application ID of the first toplevel the process mapped
+    pub app_id: Option<String>,
+    /// That toplevel, whose placement is saved with the session
+    pub window: Option<WindowId>,
+    /// Exit status, once reaped
+    pub status: Option<ExitStatus>,
+    /// Child process handle
+    child: Child,
+}
+
+/// Launches client processes and tracks them while they run
+#[derive(Debug, Default)]
+pub struct Spawner {
+    /// Running processes
+    records: Vec<SpawnRecord>,
+    /// WAYLAND_DISPLAY passed to children
+    socket_name: Option<String>,
+    /// DISPLAY passed to children, once Xwayland is ready
+    x11_display: Option<String>,
+}
+
+impl Spawner {
+    /// Create a new spawner
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the WAYLAND_DISPLAY value passed to spawned clients
+    pub fn set_socket_name(&mut self, socket_name: String) {
+        self.socket_name = Some(socket_name);
+    }
+
+    /// Set the DISPLAY value passed to spawned clients
+    pub fn set_x11_display(&mut self, display: String) {
+        self.x11_display = Some(display);
+    }
+
+    /// Spawn a client process
+    pub fn spawn(&mut self, command: &[String]) -> std::io::Result<u32> {
+        self.spawn_with_env(command, &[])
+    }
+
+    /// Spawn a client process with extra environment variables
+    pub fn spawn_with_env(
+        &mut self,
+        command: &[String],
+        env: &[(&str, &str)],
+    ) -> std::io::Result<u32> {
+        let (program, args) = command.split_first().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty command")
+        })?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        if let Some(socket_name) = &self.socket_name {
+            cmd.env("WAYLAND_DISPLAY", socket_name);
+        }
+        if let Some(display) = &self.x11_display {
+            cmd.env("DISPLAY", display);
+        }
+        cmd.envs(env.iter().copied());
+
+        let child = cmd.spawn()?;
+        let pid = child.id();
+        info!("Spawned {:?} (pid {})", command, pid);
+
+        self.records.push(SpawnRecord {
+            pid,
+            command: command.to_vec(),
+            app_id: None,
+            window: None,
+            status: None,
+            child,
+        });
+        Ok(pid)
+    }
+
+    /// Associate a spawned process with a toplevel and its app_id
+    ///
+    /// Only the first toplevel is recorded. Returns true if the pid belongs
+    /// to a spawned process.
+    pub fn associate_app_id(&mut self, pid: u32, app_id: &str, window: WindowId) -> bool {
+        let Some(record) = self.records.iter_mut().find(|r| r.pid == pid) else {
+            return false;
+        };
+        if record.app_id.is_none() {
+            debug!("Spawned pid {} maps app_id {} in {:?}", pid, app_id, window);
+            record.app_id = Some(app_id.to_string());
+            record.window = Some(window);
+        }
+        true
+    }
+
+    /// Remove records of processes that have exited
+    pub fn reap(&mut self) -> Vec<SpawnRecord> {
+        let mut exited = Vec::new();
+        let mut i = 0;
+        while i < self.records.len() {
+            if let Ok(Some(status)) = self.records[i].child.try_wait() {
+                let mut record = self.records.swap_remove(i);
+                debug!("Spawned process {} exited: {}", record.pid, status);
+                record.status = Some(status);
+                exited.push(record);
+            } else {
+                i += 1;
+            }
+        }
+        exited
+    }
+
+    /// Check if a command is already running
+    pub fn is_running(&self, command: &[String]) -> bool {
+        self.records.iter().any(|r| r.command == command)
+    }
+
+    /// Get all running processes
+    pub fn records(&self) -> impl Iterator<Item = &SpawnRecord> {
+        self.records.iter()
+    }
+
+    /// Get the number of running processes
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Check if empty
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_and_reap() {
+        let mut spawner = Spawner::new();
+        let pid = spawner.spawn(&["true".to_string()]).unwrap();
+        assert!(spawner.associate_app_id(pid, "org.example.True", WindowId(1)));
+        assert!(spawner.is_running(&["true".to_string()]));
+
+        // Wait for the process to exit
+        let mut exited = Vec::new();
+        for _ in 0..100 {
+            exited.extend(spawner.reap());
+            if spawner.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(exited.len(), 1);
+        assert_eq!(exited[0].app_id.as_deref(), Some("org.example.True"));
+        assert_eq!(exited[0].window, Some(WindowId(1)));
+        assert!(exited[0].status.is_some_and(|status| status.success()));
+    }
+
+    #[test]
+    fn test_spawn_empty_command() {
+        let mut spawner = Spawner::new();
+        assert!(spawner.spawn(&[]).is_err());
+    }
+
+    #[test]
+    fn test_associate_unknown_pid() {
+        let mut spawner = Spawner::new();
+        assert!(!spawner.associate_app_id(1, "app", WindowId(1)));
+    }
+}
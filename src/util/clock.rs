@@ -0,0 +1,142 @@
+//! Protocol clock
+//!
+//! Every timestamp sent to clients is taken on this clock, so they can be
+//! compared with each other: wl_pointer and wl_keyboard event times in
+//! milliseconds, and wp_presentation feedback in nanoseconds. On macOS it
+//! is mach_absolute_time, the clock NSEvent timestamps are taken on, which
+//! POSIX exposes as CLOCK_UPTIME_RAW. Elsewhere it is CLOCK_MONOTONIC.
+//!
+//! Instants recorded by the compositor, such as the refresh a frame was
+//! shown at, are converted with [`at`]. `Instant` is taken on the same
+//! clock, so one offset converts every instant, and converted times stay
+//! monotonic and comparable across sleep and wake.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// POSIX clock id of the clock, advertised by wp_presentation
+#[cfg(target_os = "macos")]
+pub const CLOCK_ID: u32 = 8; // CLOCK_UPTIME_RAW
+
+/// POSIX clock id of the clock, advertised by wp_presentation
+#[cfg(not(target_os = "macos"))]
+pub const CLOCK_ID: u32 = rustix::time::ClockId::Monotonic as u32;
+
+#[cfg(target_os = "macos")]
+mod mach {
+    use super::OnceLock;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct TimebaseInfo {
+        numer: u32,
+        denom: u32,
+    }
+
+    extern "C" {
+        fn mach_absolute_time() -> u64;
+        fn mach_timebase_info(info: *mut TimebaseInfo) -> i32;
+    }
+
+    /// Nanoseconds since boot, not counting sleep
+    pub fn now_nsec() -> u64 {
+        static TIMEBASE: OnceLock<(u64, u64)> = OnceLock::new();
+        let &(numer, denom) = TIMEBASE.get_or_init(|| {
+            let mut info = TimebaseInfo::default();
+            // SAFETY: info is a valid mach_timebase_info_data_t to fill in
+            match unsafe { mach_timebase_info(&mut info) } {
+                0 if info.denom != 0 => (info.numer as u64, info.denom as u64),
+                _ => (1, 1),
+            }
+        });
+        // SAFETY: mach_absolute_time has no preconditions
+        let ticks = unsafe { mach_absolute_time() };
+        (ticks as u128 * numer as u128 / denom as u128) as u64
+    }
+}
+
+/// Current time on the clock
+pub fn now() -> Duration {
+    #[cfg(target_os = "macos")]
+    {
+        Duration::from_nanos(mach::now_nsec())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let now = rustix::time::clock_gettime(rustix::time::ClockId::Monotonic);
+        Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+    }
+}
+
+/// Current time in milliseconds, for input events
+pub fn now_msec() -> u32 {
+    msec(now())
+}
+
+/// A time in milliseconds, as input events carry it
+///
+/// The value wraps around after about 49 days, like it does for every
+/// Wayland compositor.
+pub fn msec(time: Duration) -> u32 {
+    time.as_millis() as u32
+}
+
+/// Convert an instant to the clock
+pub fn at(time: Instant) -> Duration {
+    static EPOCH: OnceLock<(Instant, Duration)> = OnceLock::new();
+    let &(instant, clock_now) = EPOCH.get_or_init(|| (Instant::now(), now()));
+    convert(time, instant, clock_now)
+}
+
+/// Convert an instant to the clock, given the clock's reading at `now`
+fn convert(time: Instant, now: Instant, clock_now: Duration) -> Duration {
+    match time.checked_duration_since(now) {
+        Some(ahead) => clock_now + ahead,
+        None => clock_now.saturating_sub(now.duration_since(time)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let now = Instant::now();
+        let clock_now = Duration::from_secs(100);
+        let refresh = Duration::from_millis(16);
+
+        // Refreshes still ahead, e.g. a frame composed early, and past ones
+        assert_eq!(convert(now + refresh, now, clock_now), clock_now + refresh);
+        assert_eq!(convert(now - refresh, now, clock_now), clock_now - refresh);
+        assert_eq!(convert(now, now, Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_monotonic() {
+        let first = now();
+        assert!(first > Duration::ZERO);
+        assert!(now() >= first);
+        assert!(at(Instant::now()) >= first);
+        assert_eq!(msec(Duration::from_millis(u32::MAX as u64 + 5)), 4);
+    }
+
+    #[test]
+    fn test_consistent_across_suspend() {
+        // Instants converted before and after the thread is suspended keep
+        // their order and distance, and agree with fresh clock readings,
+        // as happens when the system sleeps and wakes
+        let before = Instant::now();
+        let converted = at(before);
+        std::thread::sleep(Duration::from_millis(20));
+        let after = Instant::now();
+        let reading = now();
+
+        assert_eq!(at(after) - converted, after - before);
+        assert!(at(after) >= converted + Duration::from_millis(20));
+        let skew = reading.abs_diff(at(after));
+        assert!(skew < Duration::from_millis(5), "clock skew {:?}", skew);
+        // Input times are the same clock in milliseconds
+        assert!(now_msec().wrapping_sub(msec(at(after))) < 5);
+    }
+}
@@ -0,0 +1,8 @@
+//! Utilities shared across the compositor
+//!
+//! This module provides helpers that don't belong to any one subsystem,
+//! such as the clock protocol timestamps are taken on and display sleep
+//! assertions.
+
+pub mod clock;
+pub mod power;
@@ -0,0 +1,213 @@
+//! Display sleep assertions
+//!
+//! While a [`SleepAssertion`] is held, macOS doesn't put the display to
+//! sleep for user inactivity. It is an IOKit power assertion of type
+//! PreventUserIdleDisplaySleep, listed by `pmset -g assertions` under the
+//! name it was taken with, and released when dropped. Elsewhere assertions
+//! do nothing.
+//!
+//! The displays can also be put to sleep and woken up directly, for output
+//! power management. macOS sleeps and wakes all displays together.
+
+use log::{debug, warn};
+
+#[cfg(target_os = "macos")]
+mod iokit {
+    use std::ffi::{c_char, c_void, CString};
+
+    type CFStringRef = *const c_void;
+
+    /// kCFStringEncodingUTF8
+    const UTF8: u32 = 0x0800_0100;
+    /// kIOPMAssertionLevelOn
+    const LEVEL_ON: u32 = 255;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(
+            alloc: *const c_void,
+            c_str: *const c_char,
+            encoding: u32,
+        ) -> CFStringRef;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(id: u32) -> i32;
+        fn IOPMAssertionDeclareUserActivity(name: CFStringRef, user_type: u32, id: *mut u32)
+            -> i32;
+    }
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGMainDisplayID() -> u32;
+        fn CGDisplayIsAsleep(display: u32) -> u32;
+    }
+
+    /// Create a CFString, released by the caller
+    fn cf_string(s: &str) -> CFStringRef {
+        let s = CString::new(s).unwrap_or_default();
+        // SAFETY: s is a valid NUL-terminated UTF-8 string
+        unsafe { CFStringCreateWithCString(std::ptr::null(), s.as_ptr(), UTF8) }
+    }
+
+    /// Take a PreventUserIdleDisplaySleep assertion
+    pub fn create(name: &str) -> Result<u32, i32> {
+        let assertion_type = cf_string("PreventUserIdleDisplaySleep");
+        let name = cf_string(name);
+        let mut id = 0;
+        // SAFETY: both strings are valid CFStrings, released right after
+        let result =
+            unsafe { IOPMAssertionCreateWithName(assertion_type, LEVEL_ON, name, &mut id) };
+        unsafe {
+            CFRelease(assertion_type);
+            CFRelease(name);
+        }
+        match result {
+            0 => Ok(id),
+            error => Err(error),
+        }
+    }
+
+    /// Release an assertion
+    pub fn release(id: u32) {
+        // SAFETY: id was returned by IOPMAssertionCreateWithName
+        unsafe { IOPMAssertionRelease(id) };
+    }
+
+    /// Declare local user activity, which wakes the displays
+    pub fn declare_user_activity(name: &str) -> Result<(), i32> {
+        let name = cf_string(name);
+        let mut id = 0;
+        // SAFETY: name is a valid CFString, released right after;
+        // 0 is kIOPMUserActiveLocal
+        let result = unsafe { IOPMAssertionDeclareUserActivity(name, 0, &mut id) };
+        unsafe { CFRelease(name) };
+        match result {
+            0 => {
+                release(id);
+                Ok(())
+            }
+            error => Err(error),
+        }
+    }
+
+    /// Check if the main display is asleep
+    pub fn displays_asleep() -> bool {
+        // SAFETY: both functions only read the display configuration
+        unsafe { CGDisplayIsAsleep(CGMainDisplayID()) != 0 }
+    }
+}
+
+/// A held display sleep assertion
+#[derive(Debug)]
+pub struct SleepAssertion {
+    #[cfg(target_os = "macos")]
+    id: u32,
+}
+
+impl SleepAssertion {
+    /// Prevent display sleep, naming the reason
+    pub fn take(name: &str) -> Result<Self, PowerError> {
+        debug!("Preventing display sleep: {}", name);
+        #[cfg(target_os = "macos")]
+        {
+            iokit::create(name)
+                .map(|id| Self { id })
+                .map_err(PowerError::Assertion)
+        }
+        #[cfg(not(target_os = "macos"))]
+        {
+            Ok(Self {})
+        }
+    }
+}
+
+impl Drop for SleepAssertion {
+    fn drop(&mut self) {
+        debug!("Allowing display sleep");
+        #[cfg(target_os = "macos")]
+        iokit::release(self.id);
+    }
+}
+
+/// Take an assertion while `wanted`, releasing it otherwise
+///
+/// Failures are logged, and retried on the next call.
+pub fn hold(assertion: &mut Option<SleepAssertion>, wanted: bool, name: &str) {
+    match (wanted, assertion.is_some()) {
+        (true, false) => match SleepAssertion::take(name) {
+            Ok(taken) => *assertion = Some(taken),
+            Err(e) => warn!("{}", e),
+        },
+        (false, true) => *assertion = None,
+        _ => {}
+    }
+}
+
+/// Put the displays to sleep, or wake them up
+pub fn set_displays_awake(awake: bool) -> Result<(), PowerError> {
+    debug!("{} the displays", if awake { "Waking" } else { "Sleeping" });
+    #[cfg(target_os = "macos")]
+    {
+        if awake {
+            iokit::declare_user_activity("Wayoa output power").map_err(PowerError::Wake)
+        } else {
+            std::process::Command::new("pmset")
+                .arg("displaysleepnow")
+                .spawn()
+                .map_err(PowerError::Sleep)
+                .map(drop)
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Ok(())
+    }
+}
+
+/// Check if the displays are asleep
+pub fn displays_asleep() -> bool {
+    #[cfg(target_os = "macos")]
+    {
+        iokit::displays_asleep()
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        false
+    }
+}
+
+/// Power assertion errors
+#[derive(Debug, thiserror::Error)]
+pub enum PowerError {
+    #[error("Failed to prevent display sleep: IOKit error {0:#x}")]
+    Assertion(i32),
+    #[error("Failed to wake the displays: IOKit error {0:#x}")]
+    Wake(i32),
+    #[error("Failed to sleep the displays: {0}")]
+    Sleep(std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold() {
+        let mut assertion = None;
+        hold(&mut assertion, true, "test");
+        assert!(assertion.is_some());
+        hold(&mut assertion, true, "test");
+        assert!(assertion.is_some());
+        hold(&mut assertion, false, "test");
+        assert!(assertion.is_none());
+    }
+}
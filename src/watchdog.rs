@@ -0,0 +1,294 @@
+//! Main loop watchdog
+//!
+//! A background thread that notices when the main loop stops servicing
+//! Wayland clients, or stops presenting the frames clients commit. A stall
+//! is logged once with the last known compositor state and, on macOS, a
+//! sample of every thread's backtrace. If configured, the main loop is asked
+//! to re-present all windows once it recovers.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+use crate::config::WatchdogConfig;
+
+/// A detected stall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stall {
+    /// The main loop hasn't dispatched clients for this long
+    MainLoop(Duration),
+    /// Committed content hasn't been presented for this long
+    Rendering(Duration),
+}
+
+impl std::fmt::Display for Stall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stall::MainLoop(d) => write!(f, "main loop stalled for {:.1}s", d.as_secs_f64()),
+            Stall::Rendering(d) => write!(f, "no frame presented for {:.1}s", d.as_secs_f64()),
+        }
+    }
+}
+
+/// Progress markers shared between the main loop and the watchdog
+#[derive(Debug)]
+struct Shared {
+    /// Reference point for the timestamps below
+    start: Instant,
+    /// Last client dispatch (milliseconds since start)
+    dispatched: AtomicU64,
+    /// Last commit that needs presenting (milliseconds since start)
+    committed: AtomicU64,
+    /// Last presented frame (milliseconds since start)
+    presented: AtomicU64,
+    /// Set by the watchdog to have the main loop re-present its windows
+    restart: AtomicBool,
+    /// Stops the watchdog thread
+    stop: AtomicBool,
+    /// Compositor state summary, published by the main loop
+    status: Mutex<String>,
+}
+
+/// Handle the main loop uses to report progress
+#[derive(Debug, Clone)]
+pub struct WatchdogHandle {
+    shared: Arc<Shared>,
+}
+
+impl WatchdogHandle {
+    /// Create a handle that is not watched yet
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                start: Instant::now(),
+                dispatched: AtomicU64::new(0),
+                committed: AtomicU64::new(0),
+                presented: AtomicU64::new(0),
+                restart: AtomicBool::new(false),
+                stop: AtomicBool::new(false),
+                status: Mutex::new(String::new()),
+            }),
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.shared.start.elapsed().as_millis() as u64
+    }
+
+    /// Record that the main loop serviced the Wayland fds
+    pub fn dispatched(&self) {
+        self.shared
+            .dispatched
+            .store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    /// Record a commit with new content to present
+    pub fn committed(&self) {
+        self.shared
+            .committed
+            .store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    /// Record that a frame was presented
+    pub fn presented(&self) {
+        self.shared
+            .presented
+            .store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    /// Publish a summary of the compositor state for stall reports
+    pub fn set_status(&self, status: String) {
+        if let Ok(mut current) = self.shared.status.lock() {
+            *current = status;
+        }
+    }
+
+    /// Check if the watchdog asked for windows to be re-presented
+    pub fn take_restart_request(&self) -> bool {
+        self.shared.restart.swap(false, Ordering::Relaxed)
+    }
+
+    /// Check for a stall at `now_ms` milliseconds since the handle was created
+    fn check(&self, now_ms: u64, timeout: Duration) -> Option<Stall> {
+        let timeout = timeout.as_millis() as u64;
+        let dispatched = self.shared.dispatched.load(Ordering::Relaxed);
+        let committed = self.shared.committed.load(Ordering::Relaxed);
+        let presented = self.shared.presented.load(Ordering::Relaxed);
+
+        let since_dispatch = now_ms.saturating_sub(dispatched);
+        if since_dispatch > timeout {
+            return Some(Stall::MainLoop(Duration::from_millis(since_dispatch)));
+        }
+        let since_commit = now_ms.saturating_sub(committed);
+        if committed > presented && since_commit > timeout {
+            return Some(Stall::Rendering(Duration::from_millis(since_commit)));
+        }
+        None
+    }
+}
+
+impl Default for WatchdogHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The watchdog thread
+#[derive(Debug)]
+pub struct Watchdog {
+    handle: WatchdogHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start watching the given handle
+    ///
+    /// Returns None if the watchdog is disabled or the thread can't start.
+    pub fn spawn(handle: WatchdogHandle, config: &WatchdogConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        // Don't report the time before the main loop first runs
+        handle.dispatched();
+
+        let timeout = Duration::from_secs(config.timeout_secs.max(1));
+        let restart = config.restart_renderer;
+        let watched = handle.clone();
+        let thread = std::thread::Builder::new()
+            .name("wayoa-watchdog".to_string())
+            .spawn(move || watch(watched, timeout, restart));
+
+        match thread {
+            Ok(thread) => {
+                info!("Watchdog started with a {}s timeout", timeout.as_secs());
+                Some(Self {
+                    handle,
+                    thread: Some(thread),
+                })
+            }
+            Err(e) => {
+                warn!("Failed to start watchdog: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.handle.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Watchdog thread body
+fn watch(handle: WatchdogHandle, timeout: Duration, restart: bool) {
+    let interval = timeout / 4;
+    let mut stalled = false;
+
+    while !handle.shared.stop.load(Ordering::Relaxed) {
+        std::thread::park_timeout(interval);
+
+        match handle.check(handle.now_ms(), timeout) {
+            Some(stall) if !stalled => {
+                stalled = true;
+                report(&handle, stall);
+                if restart {
+                    handle.shared.restart.store(true, Ordering::Relaxed);
+                }
+            }
+            None if stalled => {
+                stalled = false;
+                info!("Watchdog: main loop recovered");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Log a stall with diagnostics
+fn report(handle: &WatchdogHandle, stall: Stall) {
+    error!("Watchdog: {}", stall);
+    if let Ok(status) = handle.shared.status.lock() {
+        if !status.is_empty() {
+            error!("Watchdog: last known state: {}", status);
+        }
+    }
+
+    // sample(1) records the backtraces of all threads of a process
+    #[cfg(target_os = "macos")]
+    {
+        let path = std::env::temp_dir().join(format!("wayoa-stall-{}.txt", std::process::id()));
+        let sampled = std::process::Command::new("sample")
+            .arg(std::process::id().to_string())
+            .arg("1")
+            .arg("-mayDie")
+            .arg("-file")
+            .arg(&path)
+            .output();
+        match sampled {
+            Ok(output) if output.status.success() => {
+                error!("Watchdog: thread backtraces written to {}", path.display());
+            }
+            Ok(output) => warn!("Watchdog: sample failed: {}", output.status),
+            Err(e) => warn!("Watchdog: failed to run sample: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn test_main_loop_stall() {
+        let handle = WatchdogHandle::new();
+        assert_eq!(handle.check(5_000, TIMEOUT), None);
+        assert_eq!(
+            handle.check(12_000, TIMEOUT),
+            Some(Stall::MainLoop(Duration::from_secs(12)))
+        );
+    }
+
+    #[test]
+    fn test_rendering_stall() {
+        let handle = WatchdogHandle::new();
+        handle.shared.committed.store(1_000, Ordering::Relaxed);
+        handle.shared.dispatched.store(20_000, Ordering::Relaxed);
+        assert_eq!(
+            handle.check(20_000, TIMEOUT),
+            Some(Stall::Rendering(Duration::from_secs(19)))
+        );
+
+        // Presenting the commit clears the stall
+        handle.shared.presented.store(1_500, Ordering::Relaxed);
+        assert_eq!(handle.check(20_000, TIMEOUT), None);
+    }
+
+    #[test]
+    fn test_disabled_and_restart_request() {
+        let config = WatchdogConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(Watchdog::spawn(WatchdogHandle::new(), &config).is_none());
+
+        let handle = WatchdogHandle::new();
+        assert!(!handle.take_restart_request());
+        handle.shared.restart.store(true, Ordering::Relaxed);
+        assert!(handle.take_restart_request());
+        assert!(!handle.take_restart_request());
+
+        let watchdog = Watchdog::spawn(handle, &WatchdogConfig::default());
+        assert!(watchdog.is_some());
+    }
+}
@@ -0,0 +1,170 @@
+//! X11 clients through Xwayland
+//!
+//! With `[xwayland] enabled = true`, wayoa runs Xwayland rootless as a
+//! Wayland client of its own, connected through a socket pair instead of
+//! the listening socket. Xwayland is given a second connection with `-wm`,
+//! over which [`Xwm`] manages the X11 windows, and reports its display
+//! number through `-displayfd` once it's ready for X11 clients.
+//!
+//! Xwayland is started once and not restarted if it exits.
+
+pub mod x11;
+mod xwm;
+
+use std::io::{ErrorKind, Read};
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+
+use log::{info, warn};
+use wayland_server::backend::ClientId;
+use wayland_server::DisplayHandle;
+
+use crate::config::XwaylandConfig;
+use crate::server::ServerState;
+
+pub use xwm::Xwm;
+
+/// A running Xwayland
+#[derive(Debug)]
+pub struct Xwayland {
+    child: Child,
+    /// Socket Xwayland writes its display number to once ready
+    display_fd: Option<UnixStream>,
+    /// Partial display number
+    display_buf: Vec<u8>,
+    /// Window manager, while connected
+    xwm: Option<Xwm>,
+}
+
+impl Xwayland {
+    /// Start Xwayland as the Wayland client `client`, connected through
+    /// `wayland`
+    pub fn spawn(
+        config: &XwaylandConfig,
+        wayland: UnixStream,
+        client: ClientId,
+    ) -> std::io::Result<Self> {
+        let (wm, xwayland_wm) = UnixStream::pair()?;
+        let (display_fd, xwayland_display_fd) = UnixStream::pair()?;
+        display_fd.set_nonblocking(true)?;
+
+        let fds = [
+            wayland.as_raw_fd(),
+            xwayland_wm.as_raw_fd(),
+            xwayland_display_fd.as_raw_fd(),
+        ];
+        let mut cmd = Command::new(&config.path);
+        cmd.args(["-rootless", "-displayfd"])
+            .arg(fds[2].to_string())
+            .arg("-wm")
+            .arg(fds[1].to_string())
+            .env("WAYLAND_SOCKET", fds[0].to_string())
+            .env_remove("WAYLAND_DISPLAY");
+        // SAFETY: only fcntl is called between fork and exec, which is
+        // async-signal-safe, on descriptors the parent keeps open until the
+        // child was spawned
+        unsafe {
+            cmd.pre_exec(move || {
+                // Let the descriptors survive exec
+                for fd in fds {
+                    let fd = BorrowedFd::borrow_raw(fd);
+                    rustix::io::fcntl_setfd(fd, rustix::io::FdFlags::empty())?;
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn()?;
+        info!("Started Xwayland (pid {})", child.id());
+
+        Ok(Self {
+            child,
+            display_fd: Some(display_fd),
+            display_buf: Vec::new(),
+            xwm: Some(Xwm::new(wm, client)?),
+        })
+    }
+
+    /// Manage X11 windows and watch for Xwayland becoming ready
+    ///
+    /// Returns false once Xwayland exited.
+    pub fn dispatch(&mut self, dh: &DisplayHandle, state: &mut ServerState) -> bool {
+        if let Some(display) = self.read_display() {
+            info!("Xwayland ready, DISPLAY={}", display);
+            state.session.spawner.set_x11_display(display);
+        }
+
+        if let Some(xwm) = &mut self.xwm {
+            if let Err(e) = xwm.dispatch(dh, state) {
+                warn!("Lost the Xwayland window manager connection: {}", e);
+                xwm.unmap_all(state);
+                self.xwm = None;
+            }
+        }
+
+        match self.child.try_wait() {
+            Ok(None) => true,
+            Ok(Some(status)) => {
+                warn!("Xwayland exited: {}", status);
+                if let Some(xwm) = &mut self.xwm {
+                    xwm.unmap_all(state);
+                }
+                false
+            }
+            Err(e) => {
+                warn!("Failed to check on Xwayland: {}", e);
+                true
+            }
+        }
+    }
+
+    /// Read the display number, returning the DISPLAY value once complete
+    fn read_display(&mut self) -> Option<String> {
+        let stream = self.display_fd.as_mut()?;
+        let mut buf = [0u8; 16];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => self.display_buf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+                Err(e) => {
+                    warn!("Failed to read the Xwayland display: {}", e);
+                    break;
+                }
+            }
+            if self.display_buf.contains(&b'\n') {
+                break;
+            }
+        }
+        // Xwayland writes the number and a newline, or closes the socket
+        // if it fails to start
+        self.display_fd = None;
+        let display = parse_display(&self.display_buf);
+        if display.is_none() {
+            warn!("Xwayland didn't report a display");
+        }
+        display
+    }
+}
+
+/// Parse the display number Xwayland wrote to its `-displayfd`
+fn parse_display(buf: &[u8]) -> Option<String> {
+    let line = std::str::from_utf8(buf).ok()?.lines().next()?;
+    let number: u32 = line.trim().parse().ok()?;
+    Some(format!(":{}", number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_display() {
+        assert_eq!(parse_display(b"1\n").as_deref(), Some(":1"));
+        assert_eq!(parse_display(b"12\n").as_deref(), Some(":12"));
+        assert_eq!(parse_display(b""), None);
+        assert_eq!(parse_display(b"x\n"), None);
+    }
+}
@@ -0,0 +1,541 @@
+//! Minimal X11 client connection
+//!
+//! Only what the window manager needs is encoded: the connection setup, a
+//! handful of requests and the events a window manager selects. Messages
+//! are little-endian. Requests are written without waiting for replies;
+//! replies are matched to their request by sequence number.
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+
+/// Predefined atoms
+pub const ATOM_STRING: u32 = 31;
+pub const ATOM_WINDOW: u32 = 33;
+pub const ATOM_WM_NAME: u32 = 39;
+pub const ATOM_WM_CLASS: u32 = 67;
+
+/// ChangeWindowAttributes value mask bit of the event mask
+pub const CW_EVENT_MASK: u32 = 1 << 11;
+
+/// Event mask bits
+pub const EVENT_MASK_PROPERTY_CHANGE: u32 = 1 << 22;
+pub const EVENT_MASK_SUBSTRUCTURE_NOTIFY: u32 = 1 << 19;
+pub const EVENT_MASK_SUBSTRUCTURE_REDIRECT: u32 = 1 << 20;
+
+/// ConfigureWindow value mask bits, in the order of their values
+pub const CONFIG_X: u16 = 1 << 0;
+pub const CONFIG_Y: u16 = 1 << 1;
+pub const CONFIG_WIDTH: u16 = 1 << 2;
+pub const CONFIG_HEIGHT: u16 = 1 << 3;
+pub const CONFIG_BORDER_WIDTH: u16 = 1 << 4;
+pub const CONFIG_SIBLING: u16 = 1 << 5;
+pub const CONFIG_STACK_MODE: u16 = 1 << 6;
+
+/// Bytes read from the server at a time
+const READ_CHUNK: usize = 4096;
+
+/// Server information from the connection setup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setup {
+    /// Root window of the first screen
+    pub root: u32,
+    /// Base of the IDs this client may allocate
+    pub resource_id_base: u32,
+    /// Bits of the IDs this client may allocate
+    pub resource_id_mask: u32,
+}
+
+/// Events the window manager handles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    CreateNotify {
+        window: u32,
+        override_redirect: bool,
+    },
+    DestroyNotify {
+        window: u32,
+    },
+    UnmapNotify {
+        window: u32,
+    },
+    MapRequest {
+        window: u32,
+    },
+    ConfigureRequest {
+        window: u32,
+        value_mask: u16,
+        /// Values of the bits set in `value_mask`, in bit order
+        values: Vec<u32>,
+    },
+    PropertyNotify {
+        window: u32,
+        atom: u32,
+    },
+    ClientMessage {
+        window: u32,
+        message_type: u32,
+        data: [u32; 5],
+    },
+    /// Any other event, by code
+    Other(u8),
+}
+
+/// A message from the X server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// The connection was set up
+    Setup(Setup),
+    /// Reply to the request with the given sequence number
+    Reply { sequence: u16, data: Vec<u8> },
+    /// An event
+    Event(Event),
+    /// A request failed
+    Error { code: u8, sequence: u16 },
+}
+
+/// Connection to an X server
+#[derive(Debug)]
+pub struct Connection {
+    stream: UnixStream,
+    /// Data received but not yet parsed
+    input: Vec<u8>,
+    /// Requests not yet written
+    output: Vec<u8>,
+    /// Sequence number of the last request
+    sequence: u16,
+    /// Set once the setup reply arrived
+    set_up: bool,
+}
+
+impl Connection {
+    /// Start setting up a connection over a stream
+    ///
+    /// The setup reply is returned by [`Connection::read`] as
+    /// [`Message::Setup`]. No authorization is sent.
+    pub fn new(stream: UnixStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        let mut output = Vec::new();
+        // Byte order, pad, protocol 11.0, empty authorization name and data
+        output.extend_from_slice(&[b'l', 0]);
+        push_u16(&mut output, 11);
+        push_u16(&mut output, 0);
+        push_u16(&mut output, 0);
+        push_u16(&mut output, 0);
+        push_u16(&mut output, 0);
+        Ok(Self {
+            stream,
+            input: Vec::new(),
+            output,
+            sequence: 0,
+            set_up: false,
+        })
+    }
+
+    /// Queue a request, returning its sequence number
+    pub fn send(&mut self, request: Vec<u8>) -> u16 {
+        self.output.extend_from_slice(&request);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sequence
+    }
+
+    /// Write queued requests, as far as the socket takes them
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read and parse the messages the server sent
+    ///
+    /// Fails once the server closed the connection.
+    pub fn read(&mut self) -> std::io::Result<Vec<Message>> {
+        let mut buf = [0u8; READ_CHUNK];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut messages = Vec::new();
+        if !self.set_up {
+            let Some(length) = setup_length(&self.input) else {
+                return Ok(messages);
+            };
+            let setup = parse_setup(&self.input[..length]).ok_or(ErrorKind::InvalidData)?;
+            self.input.drain(..length);
+            self.set_up = true;
+            messages.push(Message::Setup(setup));
+        }
+        while let Some(length) = message_length(&self.input) {
+            messages.push(parse_message(&self.input[..length]));
+            self.input.drain(..length);
+        }
+        Ok(messages)
+    }
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+/// Round up to a multiple of four
+fn pad(length: usize) -> usize {
+    (length + 3) & !3
+}
+
+/// Start a request, leaving its length to [`finish`]
+fn request(opcode: u8, data: u8) -> Vec<u8> {
+    vec![opcode, data, 0, 0]
+}
+
+/// Pad a request and fill in its length in four byte units
+fn finish(mut request: Vec<u8>) -> Vec<u8> {
+    request.resize(pad(request.len()), 0);
+    let units = (request.len() / 4) as u16;
+    request[2..4].copy_from_slice(&units.to_le_bytes());
+    request
+}
+
+/// ChangeWindowAttributes
+pub fn change_window_attributes(window: u32, value_mask: u32, values: &[u32]) -> Vec<u8> {
+    let mut req = request(2, 0);
+    push_u32(&mut req, window);
+    push_u32(&mut req, value_mask);
+    for value in values {
+        push_u32(&mut req, *value);
+    }
+    finish(req)
+}
+
+/// MapWindow
+pub fn map_window(window: u32) -> Vec<u8> {
+    let mut req = request(8, 0);
+    push_u32(&mut req, window);
+    finish(req)
+}
+
+/// ConfigureWindow, with the values of the bits set in `value_mask`
+pub fn configure_window(window: u32, value_mask: u16, values: &[u32]) -> Vec<u8> {
+    let mut req = request(12, 0);
+    push_u32(&mut req, window);
+    push_u16(&mut req, value_mask);
+    push_u16(&mut req, 0);
+    for value in values {
+        push_u32(&mut req, *value);
+    }
+    finish(req)
+}
+
+/// InternAtom, creating the atom if it doesn't exist
+pub fn intern_atom(name: &str) -> Vec<u8> {
+    let mut req = request(16, 0);
+    push_u16(&mut req, name.len() as u16);
+    push_u16(&mut req, 0);
+    req.extend_from_slice(name.as_bytes());
+    finish(req)
+}
+
+/// ChangeProperty, replacing the value with 32 bit items
+pub fn change_property(window: u32, property: u32, property_type: u32, data: &[u32]) -> Vec<u8> {
+    let mut req = request(18, 0);
+    push_u32(&mut req, window);
+    push_u32(&mut req, property);
+    push_u32(&mut req, property_type);
+    req.extend_from_slice(&[32, 0, 0, 0]);
+    push_u32(&mut req, data.len() as u32);
+    for value in data {
+        push_u32(&mut req, *value);
+    }
+    finish(req)
+}
+
+/// GetProperty of any type, up to 4 KiB of it
+pub fn get_property(window: u32, property: u32) -> Vec<u8> {
+    let mut req = request(20, 0);
+    push_u32(&mut req, window);
+    push_u32(&mut req, property);
+    push_u32(&mut req, 0);
+    push_u32(&mut req, 0);
+    push_u32(&mut req, 1024);
+    finish(req)
+}
+
+/// SendEvent of a 32 bit ClientMessage, with no event mask
+pub fn send_client_message(window: u32, message_type: u32, data: [u32; 5]) -> Vec<u8> {
+    let mut req = request(25, 0);
+    push_u32(&mut req, window);
+    push_u32(&mut req, 0);
+    let mut event = vec![33, 32, 0, 0];
+    push_u32(&mut event, window);
+    push_u32(&mut event, message_type);
+    for value in data {
+        push_u32(&mut event, value);
+    }
+    req.extend_from_slice(&event);
+    finish(req)
+}
+
+/// SetInputFocus, reverting to the parent window
+pub fn set_input_focus(window: u32) -> Vec<u8> {
+    let mut req = request(42, 2);
+    push_u32(&mut req, window);
+    push_u32(&mut req, 0);
+    finish(req)
+}
+
+/// KillClient, closing the connection of the client owning a resource
+pub fn kill_client(resource: u32) -> Vec<u8> {
+    let mut req = request(113, 0);
+    push_u32(&mut req, resource);
+    finish(req)
+}
+
+/// Get the atom from an InternAtom reply
+pub fn intern_atom_reply(reply: &[u8]) -> Option<u32> {
+    (reply.len() >= 12).then(|| u32_at(reply, 8))
+}
+
+/// Get the value of a GetProperty reply
+///
+/// Returns the type and the raw bytes, empty if the property doesn't exist.
+pub fn get_property_reply(reply: &[u8]) -> Option<(u32, Vec<u8>)> {
+    if reply.len() < 32 {
+        return None;
+    }
+    let format = usize::from(reply[1]);
+    let property_type = u32_at(reply, 8);
+    let length = u32_at(reply, 16) as usize * format / 8;
+    let value = reply.get(32..32 + length)?;
+    Some((property_type, value.to_vec()))
+}
+
+/// Length of the setup reply, once all of it arrived
+fn setup_length(input: &[u8]) -> Option<usize> {
+    if input.len() < 8 {
+        return None;
+    }
+    let length = 8 + 4 * usize::from(u16_at(input, 6));
+    (input.len() >= length).then_some(length)
+}
+
+/// Parse a successful setup reply
+fn parse_setup(reply: &[u8]) -> Option<Setup> {
+    if reply.first() != Some(&1) || reply.len() < 40 {
+        return None;
+    }
+    let vendor_length = usize::from(u16_at(reply, 24));
+    let formats = usize::from(reply[29]);
+    let screen = 40 + pad(vendor_length) + 8 * formats;
+    if reply[28] == 0 || reply.len() < screen + 4 {
+        return None;
+    }
+    Some(Setup {
+        root: u32_at(reply, screen),
+        resource_id_base: u32_at(reply, 12),
+        resource_id_mask: u32_at(reply, 16),
+    })
+}
+
+/// Length of the next message, once all of it arrived
+fn message_length(input: &[u8]) -> Option<usize> {
+    if input.len() < 32 {
+        return None;
+    }
+    let length = if input[0] == 1 {
+        32 + 4 * u32_at(input, 4) as usize
+    } else {
+        32
+    };
+    (input.len() >= length).then_some(length)
+}
+
+/// Parse a complete error, reply or event
+fn parse_message(buf: &[u8]) -> Message {
+    let sequence = u16_at(buf, 2);
+    match buf[0] {
+        0 => Message::Error {
+            code: buf[1],
+            sequence,
+        },
+        1 => Message::Reply {
+            sequence,
+            data: buf.to_vec(),
+        },
+        // The top bit marks events sent with SendEvent
+        code => Message::Event(parse_event(code & 0x7f, buf)),
+    }
+}
+
+fn parse_event(code: u8, buf: &[u8]) -> Event {
+    match code {
+        16 => Event::CreateNotify {
+            window: u32_at(buf, 8),
+            override_redirect: buf[22] != 0,
+        },
+        17 => Event::DestroyNotify {
+            window: u32_at(buf, 8),
+        },
+        18 => Event::UnmapNotify {
+            window: u32_at(buf, 8),
+        },
+        20 => Event::MapRequest {
+            window: u32_at(buf, 8),
+        },
+        23 => {
+            let value_mask = u16_at(buf, 26);
+            let fields = [
+                (CONFIG_X, i32::from(u16_at(buf, 16) as i16) as u32),
+                (CONFIG_Y, i32::from(u16_at(buf, 18) as i16) as u32),
+                (CONFIG_WIDTH, u32::from(u16_at(buf, 20))),
+                (CONFIG_HEIGHT, u32::from(u16_at(buf, 22))),
+                (CONFIG_BORDER_WIDTH, u32::from(u16_at(buf, 24))),
+                (CONFIG_SIBLING, u32_at(buf, 12)),
+                (CONFIG_STACK_MODE, u32::from(buf[1])),
+            ];
+            Event::ConfigureRequest {
+                window: u32_at(buf, 8),
+                value_mask,
+                values: fields
+                    .iter()
+                    .filter(|(bit, _)| value_mask & bit != 0)
+                    .map(|(_, value)| *value)
+                    .collect(),
+            }
+        }
+        28 => Event::PropertyNotify {
+            window: u32_at(buf, 4),
+            atom: u32_at(buf, 8),
+        },
+        33 => Event::ClientMessage {
+            window: u32_at(buf, 4),
+            message_type: u32_at(buf, 8),
+            data: std::array::from_fn(|i| u32_at(buf, 12 + 4 * i)),
+        },
+        code => Event::Other(code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_length() {
+        // Four bytes of header and name length, then the padded name
+        let req = intern_atom("WL_SURFACE_ID");
+        assert_eq!(req.len(), 8 + 16);
+        assert_eq!(u16_at(&req, 2), 6);
+        assert_eq!(u16_at(&req, 4), 13);
+        assert_eq!(&req[8..21], b"WL_SURFACE_ID");
+    }
+
+    #[test]
+    fn test_parse_setup() {
+        let mut reply = vec![1, 0, 11, 0, 0, 0, 0, 0];
+        push_u32(&mut reply, 0); // release
+        push_u32(&mut reply, 0x0020_0000); // resource ID base
+        push_u32(&mut reply, 0x001f_ffff); // resource ID mask
+        push_u32(&mut reply, 0); // motion buffer
+        push_u16(&mut reply, 5); // vendor length
+        push_u16(&mut reply, 0xffff); // maximum request length
+        reply.extend_from_slice(&[1, 1, 0, 0, 32, 32, 8, 255, 0, 0, 0, 0]);
+        reply.extend_from_slice(b"Xorg\0\0\0\0");
+        reply.extend_from_slice(&[24, 32, 32, 0, 0, 0, 0, 0]);
+        push_u32(&mut reply, 0x3ab); // root
+        reply.resize(reply.len() + 36, 0);
+        let units = ((reply.len() - 8) / 4) as u16;
+        reply[6..8].copy_from_slice(&units.to_le_bytes());
+
+        assert_eq!(setup_length(&reply[..20]), None);
+        assert_eq!(setup_length(&reply), Some(reply.len()));
+        let setup = parse_setup(&reply).unwrap();
+        assert_eq!(setup.root, 0x3ab);
+        assert_eq!(setup.resource_id_base, 0x0020_0000);
+
+        // A failed setup
+        reply[0] = 0;
+        assert_eq!(parse_setup(&reply), None);
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        // ClientMessage sent with SendEvent
+        let mut event = vec![33 | 0x80, 32, 7, 0];
+        push_u32(&mut event, 0x0040_0001);
+        push_u32(&mut event, 300);
+        for value in [42, 0, 0, 0, 0] {
+            push_u32(&mut event, value);
+        }
+        assert_eq!(message_length(&event), Some(32));
+        assert_eq!(
+            parse_message(&event),
+            Message::Event(Event::ClientMessage {
+                window: 0x0040_0001,
+                message_type: 300,
+                data: [42, 0, 0, 0, 0],
+            })
+        );
+
+        // Reply with one extra unit, not complete yet
+        let mut reply = vec![1, 0, 9, 0];
+        push_u32(&mut reply, 1);
+        reply.resize(32, 0);
+        assert_eq!(message_length(&reply), None);
+        reply.resize(36, 0);
+        assert_eq!(message_length(&reply), Some(36));
+        assert!(matches!(
+            parse_message(&reply),
+            Message::Reply { sequence: 9, .. }
+        ));
+    }
+
+    #[test]
+    fn test_configure_request_values() {
+        let mut event = vec![23, 0, 0, 0];
+        push_u32(&mut event, 1); // parent
+        push_u32(&mut event, 2); // window
+        push_u32(&mut event, 0); // sibling
+        push_u16(&mut event, (-10i16) as u16);
+        push_u16(&mut event, 20);
+        push_u16(&mut event, 800);
+        push_u16(&mut event, 600);
+        push_u16(&mut event, 0);
+        push_u16(&mut event, CONFIG_X | CONFIG_WIDTH | CONFIG_HEIGHT);
+        event.resize(32, 0);
+
+        let Message::Event(Event::ConfigureRequest { values, .. }) = parse_message(&event) else {
+            panic!("not a ConfigureRequest");
+        };
+        assert_eq!(values, vec![-10i32 as u32, 800, 600]);
+    }
+}
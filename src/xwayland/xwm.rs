@@ -0,0 +1,443 @@
+//! X11 window manager
+//!
+//! Xwayland draws every X11 window into a wl_surface of its own and tells
+//! the window manager which one with a WL_SURFACE_ID client message. The
+//! window manager redirects the root window's substructure so that it
+//! decides when windows are mapped and how they are configured, then gives
+//! each toplevel surface a window in the [`WindowManager`].
+//!
+//! Configure requests are granted as asked. Override-redirect windows
+//! (menus, tooltips) are not shown, since they aren't attached to a
+//! toplevel.
+//!
+//! [`WindowManager`]: crate::compositor::WindowManager
+
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+
+use log::{debug, info, warn};
+use wayland_server::backend::ClientId;
+use wayland_server::protocol::wl_surface::WlSurface;
+use wayland_server::{DisplayHandle, Resource};
+
+use super::x11::{self, Connection, Event, Message};
+use crate::compositor::{SurfaceId, WindowId};
+use crate::server::ServerState;
+
+/// Atoms interned on connection, in the order of [`Atom`]
+const ATOM_NAMES: [&str; 7] = [
+    "WL_SURFACE_ID",
+    "WM_PROTOCOLS",
+    "WM_DELETE_WINDOW",
+    "WM_STATE",
+    "_NET_WM_NAME",
+    "UTF8_STRING",
+    "_NET_ACTIVE_WINDOW",
+];
+
+/// Index of an atom in [`ATOM_NAMES`]
+#[derive(Debug, Clone, Copy)]
+enum Atom {
+    WlSurfaceId,
+    WmProtocols,
+    WmDeleteWindow,
+    WmState,
+    NetWmName,
+    Utf8String,
+    NetActiveWindow,
+}
+
+/// WM_STATE values
+const WM_STATE_WITHDRAWN: u32 = 0;
+const WM_STATE_NORMAL: u32 = 1;
+
+/// What a reply answers
+#[derive(Debug, Clone, Copy)]
+enum Pending {
+    Atom(usize),
+    Property { window: u32, property: u32 },
+}
+
+/// An X11 window the window manager knows about
+#[derive(Debug, Default)]
+struct XWindow {
+    /// Not managed by the window manager
+    override_redirect: bool,
+    /// Protocol ID of the wl_surface, from WL_SURFACE_ID
+    surface: Option<u32>,
+    /// Window of the surface, once mapped
+    window_id: Option<WindowId>,
+    /// _NET_WM_NAME, preferred to `wm_name`
+    net_wm_name: Option<String>,
+    /// WM_NAME
+    wm_name: Option<String>,
+    /// Class from WM_CLASS
+    class: Option<String>,
+    /// WM_DELETE_WINDOW is in WM_PROTOCOLS
+    deletable: bool,
+}
+
+impl XWindow {
+    fn title(&self) -> Option<&String> {
+        self.net_wm_name.as_ref().or(self.wm_name.as_ref())
+    }
+}
+
+/// Window manager connection to Xwayland
+#[derive(Debug)]
+pub struct Xwm {
+    connection: Connection,
+    /// Xwayland's Wayland client
+    client: ClientId,
+    /// Root window, once set up
+    root: Option<u32>,
+    /// Interned atoms, 0 until their reply arrives
+    atoms: [u32; ATOM_NAMES.len()],
+    /// Requests waiting for their reply, by sequence number
+    pending: HashMap<u16, Pending>,
+    /// Windows by X11 ID
+    windows: HashMap<u32, XWindow>,
+    /// Window with X11 input focus
+    focus: Option<u32>,
+}
+
+impl Xwm {
+    /// Start managing windows over the connection Xwayland was given with
+    /// `-wm`
+    pub fn new(stream: UnixStream, client: ClientId) -> std::io::Result<Self> {
+        let mut connection = Connection::new(stream)?;
+        connection.flush()?;
+        Ok(Self {
+            connection,
+            client,
+            root: None,
+            atoms: [0; ATOM_NAMES.len()],
+            pending: HashMap::new(),
+            windows: HashMap::new(),
+            focus: None,
+        })
+    }
+
+    /// Handle the messages Xwayland sent and the compositor's requests
+    ///
+    /// Fails once the connection is closed.
+    pub fn dispatch(&mut self, dh: &DisplayHandle, state: &mut ServerState) -> std::io::Result<()> {
+        for message in self.connection.read()? {
+            match message {
+                Message::Setup(setup) => self.set_up(setup.root),
+                Message::Reply { sequence, data } => self.handle_reply(sequence, &data, state),
+                Message::Event(event) => self.handle_event(event, state),
+                Message::Error { code, sequence } => {
+                    self.pending.remove(&sequence);
+                    debug!("X11 request {} failed with error {}", sequence, code);
+                }
+            }
+        }
+        self.map_surfaces(dh, state);
+        self.update_focus(state);
+        for window_id in state.xwayland.take_close_requests() {
+            self.close(window_id);
+        }
+        self.connection.flush()
+    }
+
+    /// Remove the windows of all X11 toplevels, once Xwayland is gone
+    pub fn unmap_all(&mut self, state: &mut ServerState) {
+        for window in self.windows.values() {
+            if let Some(window_id) = window.window_id {
+                state.unmap_xwayland_window(window_id);
+            }
+        }
+        self.windows.clear();
+    }
+
+    fn atom(&self, atom: Atom) -> u32 {
+        self.atoms[atom as usize]
+    }
+
+    /// Intern the atoms and become the window manager
+    fn set_up(&mut self, root: u32) {
+        info!("Connected to Xwayland as window manager");
+        self.root = Some(root);
+        for (index, name) in ATOM_NAMES.iter().enumerate() {
+            let sequence = self.connection.send(x11::intern_atom(name));
+            self.pending.insert(sequence, Pending::Atom(index));
+        }
+        self.connection.send(x11::change_window_attributes(
+            root,
+            x11::CW_EVENT_MASK,
+            &[x11::EVENT_MASK_SUBSTRUCTURE_REDIRECT | x11::EVENT_MASK_SUBSTRUCTURE_NOTIFY],
+        ));
+    }
+
+    fn handle_reply(&mut self, sequence: u16, data: &[u8], state: &mut ServerState) {
+        match self.pending.remove(&sequence) {
+            Some(Pending::Atom(index)) => {
+                if let Some(atom) = x11::intern_atom_reply(data) {
+                    self.atoms[index] = atom;
+                }
+            }
+            Some(Pending::Property { window, property }) => {
+                if let Some((property_type, value)) = x11::get_property_reply(data) {
+                    self.property_changed(window, property, property_type, &value, state);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn handle_event(&mut self, event: Event, state: &mut ServerState) {
+        match event {
+            Event::CreateNotify {
+                window,
+                override_redirect,
+            } => {
+                self.windows.insert(
+                    window,
+                    XWindow {
+                        override_redirect,
+                        ..XWindow::default()
+                    },
+                );
+                if !override_redirect {
+                    self.connection.send(x11::change_window_attributes(
+                        window,
+                        x11::CW_EVENT_MASK,
+                        &[x11::EVENT_MASK_PROPERTY_CHANGE],
+                    ));
+                    for property in [
+                        x11::ATOM_WM_NAME,
+                        x11::ATOM_WM_CLASS,
+                        self.atom(Atom::NetWmName),
+                        self.atom(Atom::WmProtocols),
+                    ] {
+                        self.query_property(window, property);
+                    }
+                }
+            }
+            Event::MapRequest { window } => {
+                debug!("Mapping X11 window {:#x}", window);
+                self.set_wm_state(window, WM_STATE_NORMAL);
+                self.connection.send(x11::map_window(window));
+            }
+            Event::ConfigureRequest {
+                window,
+                value_mask,
+                values,
+            } => {
+                self.connection
+                    .send(x11::configure_window(window, value_mask, &values));
+            }
+            Event::UnmapNotify { window } => {
+                self.unmap(window, state);
+                if self.windows.contains_key(&window) {
+                    self.set_wm_state(window, WM_STATE_WITHDRAWN);
+                }
+            }
+            Event::DestroyNotify { window } => {
+                self.unmap(window, state);
+                self.windows.remove(&window);
+            }
+            Event::PropertyNotify { window, atom } => {
+                self.query_property(window, atom);
+            }
+            Event::ClientMessage {
+                window,
+                message_type,
+                data,
+            } => {
+                if message_type != 0 && message_type == self.atom(Atom::WlSurfaceId) {
+                    debug!("X11 window {:#x} has surface {}", window, data[0]);
+                    if let Some(x_window) = self.windows.get_mut(&window) {
+                        x_window.surface = Some(data[0]);
+                    }
+                }
+            }
+            Event::Other(_) => {}
+        }
+    }
+
+    /// Ask for a property of a window, if it's one the window manager uses
+    fn query_property(&mut self, window: u32, property: u32) {
+        let known = [
+            x11::ATOM_WM_NAME,
+            x11::ATOM_WM_CLASS,
+            self.atom(Atom::NetWmName),
+            self.atom(Atom::WmProtocols),
+        ];
+        if property == 0 || !known.contains(&property) {
+            return;
+        }
+        let sequence = self.connection.send(x11::get_property(window, property));
+        self.pending
+            .insert(sequence, Pending::Property { window, property });
+    }
+
+    fn property_changed(
+        &mut self,
+        window: u32,
+        property: u32,
+        property_type: u32,
+        value: &[u8],
+        state: &mut ServerState,
+    ) {
+        let net_wm_name = self.atom(Atom::NetWmName);
+        let utf8_string = self.atom(Atom::Utf8String);
+        let wm_protocols = self.atom(Atom::WmProtocols);
+        let wm_delete_window = self.atom(Atom::WmDeleteWindow);
+        let Some(x_window) = self.windows.get_mut(&window) else {
+            return;
+        };
+        let text = || {
+            (property_type == x11::ATOM_STRING || property_type == utf8_string)
+                .then(|| String::from_utf8_lossy(value).into_owned())
+        };
+        if property == x11::ATOM_WM_NAME {
+            x_window.wm_name = text();
+        } else if property == net_wm_name {
+            x_window.net_wm_name = text();
+        } else if property == x11::ATOM_WM_CLASS {
+            // Instance and class name, each terminated by a NUL
+            x_window.class = value
+                .split(|byte| *byte == 0)
+                .nth(1)
+                .filter(|class| !class.is_empty())
+                .map(|class| String::from_utf8_lossy(class).into_owned());
+        } else if property == wm_protocols {
+            x_window.deletable = value.chunks_exact(4).any(|atom| {
+                u32::from_le_bytes([atom[0], atom[1], atom[2], atom[3]]) == wm_delete_window
+            });
+            return;
+        }
+
+        if let Some(window_id) = x_window.window_id {
+            if let Some(title) = x_window.title().cloned() {
+                state.set_xwayland_title(window_id, title);
+            }
+            if let Some(class) = x_window.class.clone() {
+                state.set_xwayland_app_id(window_id, class);
+            }
+        }
+    }
+
+    fn set_wm_state(&mut self, window: u32, wm_state: u32) {
+        let atom = self.atom(Atom::WmState);
+        if atom != 0 {
+            self.connection
+                .send(x11::change_property(window, atom, atom, &[wm_state, 0]));
+        }
+    }
+
+    /// Give windows whose surface Xwayland created a window in the
+    /// compositor
+    ///
+    /// The WL_SURFACE_ID message can arrive before Xwayland's request
+    /// creating the surface was dispatched, so windows are retried until
+    /// the surface exists.
+    fn map_surfaces(&mut self, dh: &DisplayHandle, state: &mut ServerState) {
+        let handle = dh.backend_handle();
+        for x_window in self.windows.values_mut() {
+            if x_window.override_redirect || x_window.window_id.is_some() {
+                continue;
+            }
+            let Some(protocol_id) = x_window.surface else {
+                continue;
+            };
+            let surface_id = handle
+                .object_for_protocol_id(self.client.clone(), WlSurface::interface(), protocol_id)
+                .ok()
+                .and_then(|object| WlSurface::from_id(dh, object).ok())
+                .and_then(|surface| surface.data::<SurfaceId>().copied());
+            let Some(surface_id) = surface_id else {
+                continue;
+            };
+            // A surface that can't be mapped isn't retried
+            x_window.surface = None;
+            let Some(window_id) = state.map_xwayland_surface(surface_id) else {
+                continue;
+            };
+            x_window.window_id = Some(window_id);
+            if let Some(title) = x_window.title().cloned() {
+                state.set_xwayland_title(window_id, title);
+            }
+            if let Some(class) = x_window.class.clone() {
+                state.set_xwayland_app_id(window_id, class);
+            }
+        }
+    }
+
+    /// Give X11 input focus to the window whose surface has keyboard focus
+    fn update_focus(&mut self, state: &ServerState) {
+        let Some(focus) = state.compositor.seat.keyboard().focus() else {
+            return;
+        };
+        let Some(window_id) = state.compositor.windows.window_for_surface(focus) else {
+            return;
+        };
+        let Some(window) = self
+            .windows
+            .iter()
+            .find(|(_, x_window)| x_window.window_id == Some(window_id))
+            .map(|(window, _)| *window)
+        else {
+            return;
+        };
+        if self.focus == Some(window) {
+            return;
+        }
+        self.focus = Some(window);
+        self.connection.send(x11::set_input_focus(window));
+        let net_active_window = self.atom(Atom::NetActiveWindow);
+        if let (Some(root), true) = (self.root, net_active_window != 0) {
+            self.connection.send(x11::change_property(
+                root,
+                net_active_window,
+                x11::ATOM_WINDOW,
+                &[window],
+            ));
+        }
+    }
+
+    /// Ask the client owning a window to close it, or disconnect the client
+    /// if it doesn't support being asked
+    fn close(&mut self, window_id: WindowId) {
+        let Some((&window, x_window)) = self
+            .windows
+            .iter()
+            .find(|(_, x_window)| x_window.window_id == Some(window_id))
+        else {
+            return;
+        };
+        if x_window.deletable {
+            debug!("Asking X11 window {:#x} to close", window);
+            let data = [self.atom(Atom::WmDeleteWindow), 0, 0, 0, 0];
+            self.connection.send(x11::send_client_message(
+                window,
+                self.atom(Atom::WmProtocols),
+                data,
+            ));
+        } else {
+            warn!(
+                "X11 window {:#x} can't be asked to close, killing its client",
+                window
+            );
+            self.connection.send(x11::kill_client(window));
+        }
+    }
+
+    /// Remove the compositor window of an X11 window
+    fn unmap(&mut self, window: u32, state: &mut ServerState) {
+        if self.focus == Some(window) {
+            self.focus = None;
+        }
+        let Some(x_window) = self.windows.get_mut(&window) else {
+            return;
+        };
+        // Xwayland creates a new surface when the window is mapped again
+        x_window.surface = None;
+        if let Some(window_id) = x_window.window_id.take() {
+            debug!("Unmapping X11 window {:#x}", window);
+            state.unmap_xwayland_window(window_id);
+        }
+    }
+}